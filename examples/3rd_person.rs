@@ -50,7 +50,6 @@ use rg3d::{
     },
     utils::{
         mesh_to_static_geometry,
-        translate_event,
     },
     scene::{
         base::BaseBuilder,
@@ -613,7 +612,7 @@ fn main() {
         .with_title("Example - 3rd Person")
         .with_resizable(true);
 
-    let mut engine = GameEngine::new(window_builder, &event_loop).unwrap();
+    let mut engine = GameEngine::new(window_builder, &event_loop, true).unwrap();
 
     // Prepare resource manager - it must be notified where to search textures. When engine
     // loads model resource it automatically tries to load textures it uses. But since most
@@ -752,12 +751,10 @@ fn main() {
                     _ => ()
                 }
 
-                // It is very important to "feed" user interface (UI) with events coming
-                // from main window, otherwise UI won't respond to mouse, keyboard, or any
-                // other event.
-                if let Some(os_event) = translate_event(&event) {
-                    engine.user_interface.process_os_event(&os_event);
-                }
+                // It is very important to "feed" engine with events coming from main
+                // window, otherwise UI won't respond to mouse, keyboard, or any other
+                // event.
+                engine.process_window_event(&event);
             }
             Event::DeviceEvent { event, .. } => {
                 if let Some(game_scene) = game_scene.as_mut() {
@@ -44,7 +44,6 @@ use rg3d::{
     window::Fullscreen,
     monitor::VideoMode,
     animation::Animation,
-    utils::translate_event,
     gui::{
         stack_panel::StackPanelBuilder,
         grid::{GridBuilder, Column, Row},
@@ -327,7 +326,7 @@ fn main() {
         .with_title("Example - Model")
         .with_resizable(true);
 
-    let mut engine = GameEngine::new(window_builder, &event_loop).unwrap();
+    let mut engine = GameEngine::new(window_builder, &event_loop, true).unwrap();
 
     // Prepare resource manager - it must be notified where to search textures. When engine
     // loads model resource it automatically tries to load textures it uses. But since most
@@ -472,12 +471,10 @@ fn main() {
                     _ => ()
                 }
 
-                // It is very important to "feed" user interface (UI) with events coming
-                // from main window, otherwise UI won't respond to mouse, keyboard, or any
-                // other event.
-                if let Some(os_event) = translate_event(&event) {
-                    engine.user_interface.process_os_event(&os_event);
-                }
+                // It is very important to "feed" engine with events coming from main
+                // window, otherwise UI won't respond to mouse, keyboard, or any other
+                // event.
+                engine.process_window_event(&event);
             }
             Event::DeviceEvent { event, .. } => {
                 if let DeviceEvent::Key(key) = event {
@@ -0,0 +1,125 @@
+//! Animation level of detail - reduced sampling rate for distant characters.
+//!
+//! Sampling of animation (search of key frames and interpolation for every track) is done
+//! each frame and for large crowds it becomes noticeable. Far characters occupy few pixels
+//! and nobody will notice if their animations are sampled ten times per second instead of
+//! every frame. Animation with LOD is sampled with rate which depends on distance from
+//! its node to nearest enabled camera: every frame closer than `full_rate_distance`, with
+//! `min_rate` at `min_rate_distance` and farther, and linearly in between. Animations of
+//! invisible nodes are sampled with `min_rate` too.
+//! LOD is applied by `AnimationContainer::update_animations_with_lod` which is used by
+//! `Scene::update`.
+//!
+//! Poses between samples are interpolated, so motion stays smooth: on each sample
+//! animation samples pose one sampling interval ahead and blends towards it. Time of
+//! animation and signals are still advanced every frame, so throttled animation stays in
+//! sync with gameplay.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::{Animation, lod::AnimationLod},
+//!     scene::node::Node,
+//!     core::pool::Handle,
+//! };
+//!
+//! fn make_crowd_friendly(animation: &mut Animation, character_root: Handle<Node>) {
+//!     animation.set_lod(Some(AnimationLod::new(character_root, 15.0, 60.0, 10.0)));
+//! }
+//! ```
+
+use crate::{
+    core::{
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+    scene::node::Node,
+};
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AnimationLod {
+    /// Node which distance to camera defines sampling rate, usually root of character.
+    pub node: Handle<Node>,
+    /// Distance within which animation is sampled every frame.
+    pub full_rate_distance: f32,
+    /// Distance from which animation is sampled with minimal rate.
+    pub min_rate_distance: f32,
+    /// Minimal sampling rate in samples per second.
+    pub min_rate: f32,
+}
+
+impl Default for AnimationLod {
+    fn default() -> Self {
+        Self {
+            node: Handle::NONE,
+            full_rate_distance: 15.0,
+            min_rate_distance: 60.0,
+            min_rate: 10.0,
+        }
+    }
+}
+
+impl AnimationLod {
+    pub fn new(node: Handle<Node>, full_rate_distance: f32, min_rate_distance: f32, min_rate: f32) -> Self {
+        Self {
+            node,
+            full_rate_distance,
+            min_rate_distance: min_rate_distance.max(full_rate_distance),
+            min_rate: min_rate.max(0.1),
+        }
+    }
+
+    /// Returns sampling rate at given distance for node with given visibility, `None` means
+    /// that animation must be sampled every frame. `frame_rate` is current rate of updates,
+    /// it is used as rate of closest range.
+    pub fn rate(&self, distance: f32, visible: bool, frame_rate: f32) -> Option<f32> {
+        if !visible || distance >= self.min_rate_distance {
+            return Some(self.min_rate);
+        }
+        if distance <= self.full_rate_distance {
+            return None;
+        }
+        let t = (distance - self.full_rate_distance) / (self.min_rate_distance - self.full_rate_distance);
+        let rate = frame_rate + (self.min_rate - frame_rate) * t;
+        if rate >= frame_rate {
+            None
+        } else {
+            Some(rate.max(self.min_rate))
+        }
+    }
+}
+
+impl Visit for AnimationLod {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.full_rate_distance.visit("FullRateDistance", visitor)?;
+        self.min_rate_distance.visit("MinRateDistance", visitor)?;
+        self.min_rate.visit("MinRate", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::pool::Handle,
+        animation::lod::AnimationLod,
+    };
+
+    #[test]
+    fn test_animation_lod_rate() {
+        let lod = AnimationLod::new(Handle::NONE, 10.0, 50.0, 10.0);
+        assert_eq!(lod.rate(5.0, true, 60.0), None);
+        assert_eq!(lod.rate(5.0, false, 60.0), Some(10.0));
+        assert_eq!(lod.rate(100.0, true, 60.0), Some(10.0));
+        let rate = lod.rate(30.0, true, 60.0).unwrap();
+        assert!((rate - 35.0).abs() < 1.0e-4);
+    }
+}
@@ -1,4 +1,10 @@
+pub mod lod;
 pub mod machine;
+pub mod property;
+pub mod spline;
+pub mod spring_bone;
+pub mod timeline;
+pub mod tween;
 
 use crate::{
     core::{
@@ -27,7 +33,11 @@ use crate::{
         graph::Graph
     },
     resource::model::Model,
-    utils::log::Log
+    utils::log::Log,
+    animation::{
+        property::{NodeProperty, PropertyTrack, PropertyValue},
+        lod::AnimationLod,
+    },
 };
 use std::{
     sync::{
@@ -36,6 +46,7 @@ use std::{
     },
     collections::{
         HashMap,
+        HashSet,
         VecDeque
     }
 };
@@ -139,6 +150,11 @@ impl Track {
         self.node
     }
 
+    /// Returns amount of memory occupied by key frames of track.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of_val(self.frames.as_slice())
+    }
+
     pub fn add_key_frame(&mut self, key_frame: KeyFrame) {
         if key_frame.time > self.max_time {
             self.frames.push(key_frame);
@@ -285,6 +301,41 @@ impl Visit for AnimationSignal {
     }
 }
 
+/// Returns true if playback from `from` to `to` crosses given time. Looped playback wraps
+/// around, so time can be crossed on the other side of the wrap, reverse playback crosses
+/// times in opposite direction.
+fn is_signal_crossed(time: f32, from: f32, to: f32, length: f32, looped: bool) -> bool {
+    let crossed = |time: f32| if to >= from {
+        from < time && time <= to
+    } else {
+        to <= time && time < from
+    };
+    if looped && length > 0.0 {
+        (to - from).abs() >= length || crossed(time) || crossed(time + length) || crossed(time - length)
+    } else {
+        crossed(time)
+    }
+}
+
+/// Samples enabled tracks at given time into given pose.
+fn sample_pose(tracks: &[Track], property_tracks: &[PropertyTrack], time: f32, pose: &mut AnimationPose) {
+    pose.reset();
+    for track in tracks.iter() {
+        if track.is_enabled() {
+            if let Some(local_pose) = track.get_local_pose(time) {
+                pose.add_local_pose(local_pose);
+            }
+        }
+    }
+    for track in property_tracks.iter() {
+        if track.is_enabled() {
+            if let Some(value) = track.value_at(time) {
+                pose.properties.insert((track.node(), track.property()), value);
+            }
+        }
+    }
+}
+
 pub struct Animation {
     // TODO: Extract into separate struct AnimationTimeline
     tracks: Vec<Track>,
@@ -297,7 +348,23 @@ pub struct Animation {
     pub(in crate) resource: Option<Arc<Mutex<Model>>>,
     pose: AnimationPose,
     signals: Vec<AnimationSignal>,
-    events: VecDeque<AnimationEvent>
+    events: VecDeque<AnimationEvent>,
+    property_tracks: Vec<PropertyTrack>,
+    lod: Option<AnimationLod>,
+    /// Sampling rate chosen by LOD for current frame, `None` - sample every frame.
+    lod_rate: Option<f32>,
+    lod_sampler: LodSampler,
+}
+
+/// Poses of animation sampled with reduced rate, see `animation::lod`. Displayed pose is
+/// interpolated from `from` to `to` during sampling interval.
+#[derive(Default)]
+struct LodSampler {
+    from: AnimationPose,
+    to: AnimationPose,
+    interval: f32,
+    elapsed: f32,
+    primed: bool,
 }
 
 /// Snapshot of scene node local transform state.
@@ -335,19 +402,82 @@ impl LocalPose {
         self.rotation = self.rotation.nlerp(&other.rotation, weight);
         // TODO: Implement scale blending
     }
+
+    /// Returns pose between this pose (`t` = 0) and other pose (`t` = 1).
+    fn interpolate(&self, other: &LocalPose, t: f32) -> LocalPose {
+        LocalPose {
+            node: self.node,
+            position: self.position.lerp(&other.position, t),
+            scale: self.scale.lerp(&other.scale, t),
+            rotation: self.rotation.slerp(&other.rotation, t),
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct AnimationPose {
-    local_poses: HashMap<Handle<Node>, LocalPose>
+    local_poses: HashMap<Handle<Node>, LocalPose>,
+    /// Values of property tracks, see `animation::property`.
+    properties: HashMap<(Handle<Node>, NodeProperty), PropertyValue>,
 }
 
 impl AnimationPose {
+    /// Captures current local transforms of given nodes. Use it to remember pose which
+    /// is not produced by animations (ragdoll, scripted or procedural pose) to blend from
+    /// it into animation later, see [`PoseTransition`].
+    pub fn capture<I>(graph: &Graph, nodes: I) -> Self where I: IntoIterator<Item=Handle<Node>> {
+        let mut pose = AnimationPose::default();
+        for node in nodes {
+            let transform = graph[node].local_transform();
+            pose.add_local_pose(LocalPose {
+                node,
+                position: transform.position(),
+                scale: transform.scale(),
+                rotation: transform.rotation(),
+            });
+        }
+        pose
+    }
+
+    /// Captures current local transforms of given node and all its descendants, for
+    /// example of whole skeleton.
+    pub fn capture_hierarchy(graph: &Graph, root: Handle<Node>) -> Self {
+        Self::capture(graph, graph.traverse_handle_iter(root))
+    }
+
+    /// Returns true if pose has local pose of given node.
+    pub fn contains(&self, node: Handle<Node>) -> bool {
+        self.local_poses.contains_key(&node)
+    }
+
+    /// Replaces each local pose by interpolation between local pose of same node in `from`
+    /// pose (`weight` = 0) and this pose (`weight` = 1). Nodes which are only in `from` pose
+    /// are not added, they are not animated by this pose anyway. Property values are
+    /// interpolated the same way.
+    pub fn blend_from(&mut self, from: &AnimationPose, weight: f32) {
+        for (handle, current_pose) in self.local_poses.iter_mut() {
+            if let Some(from_pose) = from.local_poses.get(handle) {
+                *current_pose = from_pose.interpolate(current_pose, weight);
+            }
+        }
+        for (key, value) in self.properties.iter_mut() {
+            if let Some(from_value) = from.properties.get(key) {
+                *value = from_value.interpolate(value, weight);
+            }
+        }
+    }
+
+    /// Returns value of property of node in this pose, if pose has it.
+    pub fn property(&self, node: Handle<Node>, property: NodeProperty) -> Option<PropertyValue> {
+        self.properties.get(&(node, property)).cloned()
+    }
+
     pub fn clone_into(&self, dest: &mut AnimationPose) {
         dest.reset();
         for (handle, local_pose) in self.local_poses.iter() {
             dest.local_poses.insert(*handle, local_pose.clone());
         }
+        dest.properties.extend(self.properties.iter().map(|(key, value)| (*key, *value)));
     }
 
     pub fn blend_with(&mut self, other: &AnimationPose, weight: f32) {
@@ -360,6 +490,13 @@ impl AnimationPose {
                 self.add_local_pose(other_pose.weighted_clone(weight));
             }
         }
+        for (key, other_value) in other.properties.iter() {
+            if let Some(value) = self.properties.get_mut(key) {
+                value.add_weighted(other_value, weight);
+            } else {
+                self.properties.insert(*key, other_value.weighted(weight));
+            }
+        }
     }
 
     fn add_local_pose(&mut self, local_pose: LocalPose) {
@@ -368,19 +505,94 @@ impl AnimationPose {
 
     pub fn reset(&mut self) {
         self.local_poses.clear();
+        self.properties.clear();
     }
 
     pub fn apply(&self, graph: &mut Graph) {
         for (node, local_pose) in self.local_poses.iter() {
             if node.is_none() {
                 Log::writeln("Invalid node handle found for animation pose, most likely it means that animation retargetting failed!".to_owned());
-            } else {
+            } else if graph[*node].is_globally_enabled() {
                 graph[*node].local_transform_mut()
                     .set_position(local_pose.position)
                     .set_rotation(local_pose.rotation)
                     .set_scale(local_pose.scale);
             }
         }
+        for (&(node, property), value) in self.properties.iter() {
+            if graph.is_valid_handle(node) && graph[node].is_globally_enabled() {
+                property.write(&mut graph[node], *value);
+            }
+        }
+    }
+}
+
+/// Smooth transition from static pose (see [`AnimationPose::capture`]) into animation, for
+/// example when character gets up after ragdoll or scripted pose ends. Each frame pose of
+/// animation (or output pose of machine) is blended with captured pose and result is
+/// applied to graph instead of pose of animation. Weight of animation follows smooth step
+/// curve, so motion does not start or stop abruptly.
+///
+/// ```no_run
+/// use rg3d::{
+///     animation::{Animation, AnimationPose, PoseTransition},
+///     scene::{Scene, node::Node},
+///     core::pool::Handle,
+/// };
+///
+/// fn get_up(scene: &Scene, skeleton: Handle<Node>) -> PoseTransition {
+///     PoseTransition::new(AnimationPose::capture_hierarchy(&scene.graph, skeleton), 0.4)
+/// }
+///
+/// fn update(scene: &mut Scene, transition: &mut PoseTransition, animation: Handle<Animation>, dt: f32) {
+///     transition.update(dt);
+///     transition.blend(scene.animations.get(animation).get_pose()).apply(&mut scene.graph);
+/// }
+/// ```
+pub struct PoseTransition {
+    from: AnimationPose,
+    duration: f32,
+    elapsed: f32,
+    output: AnimationPose,
+}
+
+impl PoseTransition {
+    /// Creates transition from given pose, animation fully replaces it in `duration`
+    /// seconds.
+    pub fn new(from: AnimationPose, duration: f32) -> Self {
+        Self {
+            from,
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+            output: Default::default(),
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+    }
+
+    /// Returns weight of animation in [0; 1] range.
+    pub fn weight(&self) -> f32 {
+        if self.duration > 0.0 {
+            let t = self.elapsed / self.duration;
+            t * t * (3.0 - 2.0 * t)
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns true when captured pose has no effect anymore and pose of animation can be
+    /// applied directly.
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Blends captured pose with given pose of animation using current weight.
+    pub fn blend(&mut self, target: &AnimationPose) -> &AnimationPose {
+        target.clone_into(&mut self.output);
+        self.output.blend_from(&self.from, self.weight());
+        &self.output
     }
 }
 
@@ -396,7 +608,11 @@ impl Clone for Animation {
             resource: self.resource.clone(),
             pose: Default::default(),
             signals: self.signals.clone(),
-            events: Default::default()
+            events: Default::default(),
+            property_tracks: self.property_tracks.clone(),
+            lod: self.lod,
+            lod_rate: None,
+            lod_sampler: Default::default(),
         }
     }
 }
@@ -416,12 +632,72 @@ impl Animation {
         &self.tracks
     }
 
-    pub fn set_time_position(&mut self, time: f32) -> &mut Self {
+    /// Adds track which animates property of node other than transform, see
+    /// `animation::property` module docs.
+    pub fn add_property_track(&mut self, track: PropertyTrack) {
+        self.length = self.length.max(track.max_time());
+        self.property_tracks.push(track);
+    }
+
+    pub fn property_tracks(&self) -> &[PropertyTrack] {
+        &self.property_tracks
+    }
+
+    pub fn property_tracks_mut(&mut self) -> &mut [PropertyTrack] {
+        &mut self.property_tracks
+    }
+
+    pub fn retain_property_tracks<F>(&mut self, filter: F)
+        where F: FnMut(&PropertyTrack) -> bool {
+        self.property_tracks.retain(filter)
+    }
+
+    /// Removes property tracks of nodes which are not in given map and remaps nodes of
+    /// rest of tracks. Node of LOD is remapped too, if it is in the map.
+    pub(in crate) fn remap_property_tracks(&mut self, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) {
+        self.property_tracks.retain(|track| old_new_map.contains_key(&track.node()));
+        for track in self.property_tracks.iter_mut() {
+            track.set_node(old_new_map[&track.node()]);
+        }
+        if let Some(lod) = self.lod.as_mut() {
+            if let Some(&node) = old_new_map.get(&lod.node) {
+                lod.node = node;
+            }
+        }
+    }
+
+    /// Sets level of detail of animation, `None` means that animation is sampled every
+    /// frame. See `animation::lod` module docs.
+    pub fn set_lod(&mut self, lod: Option<AnimationLod>) -> &mut Self {
+        self.lod = lod;
+        self
+    }
+
+    pub fn lod(&self) -> Option<&AnimationLod> {
+        self.lod.as_ref()
+    }
+
+    pub fn lod_mut(&mut self) -> Option<&mut AnimationLod> {
+        self.lod.as_mut()
+    }
+
+    /// Returns amount of memory occupied by key frames of all tracks.
+    pub fn memory_usage(&self) -> usize {
+        self.tracks.iter().map(|track| track.memory_usage()).sum()
+    }
+
+    fn wrap_time(&self, time: f32) -> f32 {
         if self.looped {
-            self.time_position = wrapf(time, 0.0, self.length);
+            wrapf(time, 0.0, self.length)
         } else {
-            self.time_position = clampf(time, 0.0, self.length);
+            clampf(time, 0.0, self.length)
         }
+    }
+
+    pub fn set_time_position(&mut self, time: f32) -> &mut Self {
+        self.time_position = self.wrap_time(time);
+        // Jump in time, poses sampled ahead are no longer valid.
+        self.lod_sampler.primed = false;
         self
     }
 
@@ -430,13 +706,19 @@ impl Animation {
     }
 
     fn tick(&mut self, dt: f32) {
-        self.update_pose();
+        match self.lod_rate {
+            Some(rate) => self.update_pose_throttled(rate, dt),
+            None => {
+                self.lod_sampler.primed = false;
+                self.update_pose();
+            }
+        }
 
         let current_time_position = self.get_time_position();
         let new_time_position = current_time_position + dt * self.get_speed();
 
         for signal in self.signals.iter_mut() {
-            if current_time_position < signal.time && new_time_position >= signal.time {
+            if is_signal_crossed(signal.time, current_time_position, new_time_position, self.length, self.looped) {
                 // TODO: Make this configurable.
                 if self.events.len() < 32 {
                     self.events.push_back(AnimationEvent { signal_id: signal.id });
@@ -444,7 +726,31 @@ impl Animation {
             }
         }
 
-        self.set_time_position(new_time_position);
+        self.time_position = self.wrap_time(new_time_position);
+    }
+
+    /// Samples pose with given rate and interpolates between samples, see `animation::lod`.
+    fn update_pose_throttled(&mut self, rate: f32, dt: f32) {
+        if !self.lod_sampler.primed || self.lod_sampler.elapsed >= self.lod_sampler.interval {
+            let interval = 1.0 / rate;
+            let time_ahead = self.wrap_time(self.time_position + interval * self.speed);
+            let sampler = &mut self.lod_sampler;
+            if sampler.primed {
+                // Pose which was sampled ahead is the pose displayed right now.
+                std::mem::swap(&mut sampler.from, &mut sampler.to);
+            } else {
+                sample_pose(&self.tracks, &self.property_tracks, self.time_position, &mut sampler.from);
+            }
+            sample_pose(&self.tracks, &self.property_tracks, time_ahead, &mut sampler.to);
+            sampler.interval = interval;
+            sampler.elapsed = 0.0;
+            sampler.primed = true;
+        }
+
+        let sampler = &mut self.lod_sampler;
+        sampler.to.clone_into(&mut self.pose);
+        self.pose.blend_from(&sampler.from, sampler.elapsed / sampler.interval);
+        sampler.elapsed += dt;
     }
 
     pub fn pop_event(&mut self) -> Option<AnimationEvent> {
@@ -468,8 +774,39 @@ impl Animation {
         self.looped
     }
 
+    /// Returns length of animation in seconds, it is time of last key frame of all tracks.
+    pub fn get_length(&self) -> f32 {
+        self.length
+    }
+
+    /// Returns time position in [0; 1] range, where 1 is end of animation. Animation
+    /// without key frames is always at 0.
+    pub fn get_normalized_time_position(&self) -> f32 {
+        if self.length > 0.0 {
+            self.time_position / self.length
+        } else {
+            0.0
+        }
+    }
+
+    /// Sets time position in [0; 1] range, see [`get_normalized_time_position`].
+    ///
+    /// [`get_normalized_time_position`]: Animation::get_normalized_time_position
+    pub fn set_normalized_time_position(&mut self, time: f32) -> &mut Self {
+        self.set_time_position(time * self.length)
+    }
+
+    /// Returns true if non-looped animation has reached its end in direction of playback:
+    /// its length when playing forward or zero when playing backwards. Looped animation
+    /// never ends.
     pub fn has_ended(&self) -> bool {
-        !self.looped && (self.time_position - self.length).abs() <= std::f32::EPSILON
+        if self.looped {
+            false
+        } else if self.speed < 0.0 {
+            self.time_position <= 0.0
+        } else {
+            self.time_position >= self.length
+        }
     }
 
     pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
@@ -504,6 +841,14 @@ impl Animation {
         self
     }
 
+    /// Adds signal at normalized time in [0; 1] range, for example signal at 0.5 is emitted
+    /// in the middle of animation. Time is converted using current length of animation, so
+    /// add tracks first.
+    pub fn add_signal_at_normalized_time(&mut self, id: u64, time: f32) -> &mut Self {
+        let time = clampf(time, 0.0, 1.0) * self.length;
+        self.add_signal(AnimationSignal::new(id, time))
+    }
+
     /// Enables or disables animation tracks for nodes in hierarchy starting from given root.
     /// Could be useful to enable or disable animation for skeleton parts, i.e. you don't want
     /// legs to be animated and you know that legs starts from torso bone, then you could do
@@ -585,15 +930,71 @@ impl Animation {
         }
     }
 
-    fn update_pose(&mut self) {
-        self.pose.reset();
-        for track in self.tracks.iter() {
-            if track.is_enabled() {
-                if let Some(local_pose) = track.get_local_pose(self.time_position) {
-                    self.pose.add_local_pose(local_pose);
+    /// Binds tracks to nodes again after model resource of animation was reloaded. Key frames
+    /// are copied from resource by names of nodes, like in `resolve`, and tracks are added
+    /// for nodes which appeared in resource and exist in animated instance. Tracks which
+    /// could not be matched are kept as is and logged.
+    pub(in crate) fn rebind(&mut self, graph: &Graph) {
+        let resource = match self.resource.clone() {
+            Some(resource) => resource,
+            None => return,
+        };
+        let resource = resource.lock().unwrap();
+        let resource_graph = &resource.get_scene().graph;
+        let ref_animation = match resource.get_scene().animations.pool.at(0) {
+            Some(ref_animation) => ref_animation,
+            None => {
+                Log::writeln(format!("Unable to rebind animation: resource {:?} has no animations!", resource.path));
+                return;
+            }
+        };
+        let ref_track_name = |ref_track: &Track| resource_graph[ref_track.get_node()].name();
+
+        let mut matched = HashSet::new();
+        let mut root = Handle::NONE;
+        for track in self.tracks.iter_mut() {
+            if !graph.is_valid_handle(track.node) {
+                Log::writeln(format!("Unable to rebind animation track of {:?}: node was removed!", track.node));
+                continue;
+            }
+            root = graph.find_model_root(track.node);
+            let name = graph[track.node].name();
+            match ref_animation.get_tracks().iter().position(|ref_track| ref_track_name(ref_track) == name) {
+                Some(index) => {
+                    track.set_key_frames(ref_animation.get_tracks()[index].get_key_frames());
+                    matched.insert(index);
                 }
+                None => Log::writeln(format!("Unable to rebind animation track of node {}: there is no such track in {:?}!",
+                                             name, resource.path)),
             }
         }
+
+        if root.is_some() {
+            for (index, ref_track) in ref_animation.get_tracks().iter().enumerate() {
+                if matched.contains(&index) {
+                    continue;
+                }
+                let node = graph.find_by_name(root, ref_track_name(ref_track));
+                if node.is_some() {
+                    let mut track = Track::new();
+                    track.set_node(node);
+                    track.set_key_frames(ref_track.get_key_frames());
+                    self.tracks.push(track);
+                } else {
+                    Log::writeln(format!("Unable to bind new animation track of node {}: no such node in instance {}!",
+                                         ref_track_name(ref_track), graph[root].name()));
+                }
+            }
+        }
+
+        self.length = self.tracks.iter().map(|track| track.max_time)
+            .chain(self.property_tracks.iter().map(|track| track.max_time()))
+            .fold(0.0, f32::max);
+        self.set_time_position(self.time_position);
+    }
+
+    pub(in crate) fn update_pose(&mut self) {
+        sample_pose(&self.tracks, &self.property_tracks, self.time_position, &mut self.pose);
     }
 
     pub fn get_pose(&self) -> &AnimationPose {
@@ -613,7 +1014,11 @@ impl Default for Animation {
             resource: Default::default(),
             pose: Default::default(),
             signals: Default::default(),
-            events: Default::default()
+            events: Default::default(),
+            property_tracks: Default::default(),
+            lod: None,
+            lod_rate: None,
+            lod_sampler: Default::default(),
         }
     }
 }
@@ -630,6 +1035,8 @@ impl Visit for Animation {
         self.looped.visit("Looped", visitor)?;
         self.enabled.visit("Enabled", visitor)?;
         self.signals.visit("Signals", visitor)?;
+        self.property_tracks.visit("PropertyTracks", visitor)?;
+        self.lod.visit("Lod", visitor)?;
 
         visitor.leave_region()
     }
@@ -697,11 +1104,21 @@ impl AnimationContainer {
         self.pool.borrow_mut(handle)
     }
 
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Animation>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
     #[inline]
     pub fn retain<P>(&mut self, pred: P) where P: FnMut(&Animation) -> bool {
         self.pool.retain(pred)
     }
 
+    /// Returns total amount of slots in pool and amount of alive animations.
+    pub fn pool_usage(&self) -> (usize, usize) {
+        (self.pool.get_capacity(), self.pool.alive_count())
+    }
+
     pub fn resolve(&mut self, graph: &Graph) {
         Log::writeln("Resolving animations...".to_owned());
         for animation in self.pool.iter_mut() {
@@ -710,8 +1127,46 @@ impl AnimationContainer {
         Log::writeln("Animations resolved successfully!".to_owned());
     }
 
+    /// Binds tracks of animations to nodes again after their model resources were reloaded,
+    /// see `Scene::sync_instances`.
+    pub(in crate) fn rebind(&mut self, graph: &Graph) {
+        for animation in self.pool.iter_mut() {
+            animation.rebind(graph)
+        }
+    }
+
+    /// Updates animations sampling every animation each frame, LOD of animations is ignored.
     pub fn update_animations(&mut self, dt: f32) {
         for animation in self.pool.iter_mut().filter(|anim| anim.enabled) {
+            animation.lod_rate = None;
+            animation.tick(dt);
+        }
+    }
+
+    /// Updates animations, sampling rate of animations with LOD is chosen by distance from
+    /// their nodes to nearest enabled camera of given graph. See `animation::lod` module docs.
+    pub fn update_animations_with_lod(&mut self, graph: &Graph, dt: f32) {
+        let camera_positions = graph.linear_iter()
+            .filter_map(|node| match node {
+                Node::Camera(camera) if camera.is_enabled() && camera.is_globally_enabled() => Some(camera.global_position()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let frame_rate = if dt > 0.0 { 1.0 / dt } else { std::f32::MAX };
+
+        for animation in self.pool.iter_mut().filter(|anim| anim.enabled) {
+            animation.lod_rate = match animation.lod {
+                Some(lod) if graph.is_valid_handle(lod.node) && !camera_positions.is_empty() => {
+                    let node = &graph[lod.node];
+                    let position = node.global_position();
+                    let distance = camera_positions.iter()
+                        .map(|camera_position| (*camera_position - position).len())
+                        .fold(std::f32::MAX, f32::min);
+                    lod.rate(distance, node.global_visibility(), frame_rate)
+                }
+                _ => None,
+            };
             animation.tick(dt);
         }
     }
@@ -0,0 +1,337 @@
+//! Property tracks - animation of node properties other than transform.
+//!
+//! Regular tracks drive local transforms only. Property track binds a key-framed value to
+//! a property of a node - intensity or color of light, size or color of sprite, field of
+//! view of camera or spawn rate of particle system - so cutscenes can animate more than
+//! movement. Property tracks are added to an animation and evaluated together with its
+//! transform tracks, their values are part of animation pose and are blended by machine
+//! like transforms.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::{
+//!         Animation,
+//!         property::{NodeProperty, PropertyKeyFrame, PropertyTrack, PropertyValue},
+//!     },
+//!     scene::node::Node,
+//!     core::pool::Handle,
+//! };
+//!
+//! fn flicker(animation: &mut Animation, lamp: Handle<Node>) {
+//!     let mut track = PropertyTrack::new(lamp, NodeProperty::LightIntensity);
+//!     track.add_key_frame(PropertyKeyFrame::new(0.0, PropertyValue::Float(1.0)));
+//!     track.add_key_frame(PropertyKeyFrame::new(0.1, PropertyValue::Float(0.2)));
+//!     track.add_key_frame(PropertyKeyFrame::new(0.3, PropertyValue::Float(1.0)));
+//!     animation.add_property_track(track);
+//! }
+//! ```
+//!
+//! Unlike transform tracks, key frames of property tracks are saved, because they are not
+//! taken from model resources.
+
+use crate::{
+    animation::tween::lerp_color,
+    core::{
+        color::Color,
+        pool::Handle,
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor,
+        },
+    },
+    scene::node::Node,
+};
+
+/// Animatable property of node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum NodeProperty {
+    LightIntensity = 0,
+    LightColor = 1,
+    SpriteSize = 2,
+    SpriteColor = 3,
+    /// Field of view of camera in radians.
+    CameraFov = 4,
+    /// Amount of particles spawned per second, value is rounded.
+    ParticleSpawnRate = 5,
+}
+
+impl Default for NodeProperty {
+    fn default() -> Self {
+        NodeProperty::LightIntensity
+    }
+}
+
+impl NodeProperty {
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(NodeProperty::LightIntensity),
+            1 => Ok(NodeProperty::LightColor),
+            2 => Ok(NodeProperty::SpriteSize),
+            3 => Ok(NodeProperty::SpriteColor),
+            4 => Ok(NodeProperty::CameraFov),
+            5 => Ok(NodeProperty::ParticleSpawnRate),
+            _ => Err(format!("Invalid node property {}", id))
+        }
+    }
+
+    /// Reads current value of property from node. Returns None if node does not have such
+    /// property.
+    pub fn read(self, node: &Node) -> Option<PropertyValue> {
+        match (self, node) {
+            (NodeProperty::LightIntensity, Node::Light(light)) => Some(PropertyValue::Float(light.intensity())),
+            (NodeProperty::LightColor, Node::Light(light)) => Some(PropertyValue::Color(light.color())),
+            (NodeProperty::SpriteSize, Node::Sprite(sprite)) => Some(PropertyValue::Float(sprite.size())),
+            (NodeProperty::SpriteColor, Node::Sprite(sprite)) => Some(PropertyValue::Color(sprite.color())),
+            (NodeProperty::CameraFov, Node::Camera(camera)) => Some(PropertyValue::Float(camera.fov())),
+            (NodeProperty::ParticleSpawnRate, Node::ParticleSystem(particle_system)) =>
+                Some(PropertyValue::Float(particle_system.spawn_rate() as f32)),
+            _ => None
+        }
+    }
+
+    /// Writes value into property of node. Nothing happens if node does not have such
+    /// property or value has wrong kind.
+    pub fn write(self, node: &mut Node, value: PropertyValue) {
+        match (self, node, value) {
+            (NodeProperty::LightIntensity, Node::Light(light), PropertyValue::Float(value)) => light.set_intensity(value),
+            (NodeProperty::LightColor, Node::Light(light), PropertyValue::Color(value)) => light.set_color(value),
+            (NodeProperty::SpriteSize, Node::Sprite(sprite), PropertyValue::Float(value)) => sprite.set_size(value),
+            (NodeProperty::SpriteColor, Node::Sprite(sprite), PropertyValue::Color(value)) => sprite.set_color(value),
+            (NodeProperty::CameraFov, Node::Camera(camera), PropertyValue::Float(value)) => {
+                camera.set_fov(value);
+            }
+            (NodeProperty::ParticleSpawnRate, Node::ParticleSystem(particle_system), PropertyValue::Float(value)) => {
+                particle_system.set_spawn_rate(value.max(0.0).round() as u32);
+            }
+            _ => ()
+        }
+    }
+}
+
+impl Visit for NodeProperty {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id = *self as u32;
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = Self::from_id(id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Value of animatable property.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Float(f32),
+    Color(Color),
+}
+
+impl Default for PropertyValue {
+    fn default() -> Self {
+        PropertyValue::Float(0.0)
+    }
+}
+
+fn scale_color(color: Color, k: f32) -> Color {
+    let scale = |c: u8| (f32::from(c) * k).max(0.0).min(255.0) as u8;
+    Color::from_rgba(scale(color.r), scale(color.g), scale(color.b), scale(color.a))
+}
+
+impl PropertyValue {
+    /// Returns value between this value (`t` = 0) and other value (`t` = 1). Values of
+    /// different kinds are not interpolated, other value is returned.
+    pub fn interpolate(&self, other: &PropertyValue, t: f32) -> PropertyValue {
+        match (self, other) {
+            (PropertyValue::Float(a), PropertyValue::Float(b)) => PropertyValue::Float(a + (b - a) * t),
+            (PropertyValue::Color(a), PropertyValue::Color(b)) => PropertyValue::Color(lerp_color(*a, *b, t)),
+            _ => *other
+        }
+    }
+
+    pub(in crate) fn weighted(&self, weight: f32) -> PropertyValue {
+        match self {
+            PropertyValue::Float(value) => PropertyValue::Float(value * weight),
+            PropertyValue::Color(color) => PropertyValue::Color(scale_color(*color, weight)),
+        }
+    }
+
+    /// Adds weighted other value, it is how poses are blended, see `AnimationPose::blend_with`.
+    pub(in crate) fn add_weighted(&mut self, other: &PropertyValue, weight: f32) {
+        match (self, other) {
+            (PropertyValue::Float(a), PropertyValue::Float(b)) => *a += b * weight,
+            (PropertyValue::Color(a), PropertyValue::Color(b)) => {
+                let add = |a: u8, b: u8| (f32::from(a) + f32::from(b) * weight).max(0.0).min(255.0) as u8;
+                *a = Color::from_rgba(add(a.r, b.r), add(a.g, b.g), add(a.b, b.b), add(a.a, b.a));
+            }
+            _ => ()
+        }
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            PropertyValue::Float(_) => 0,
+            PropertyValue::Color(_) => 1,
+        }
+    }
+}
+
+impl Visit for PropertyValue {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = match id {
+                0 => PropertyValue::Float(0.0),
+                1 => PropertyValue::Color(Color::WHITE),
+                _ => return Err(format!("Invalid property value kind {}", id).into()),
+            };
+        }
+
+        match self {
+            PropertyValue::Float(value) => value.visit("Value", visitor)?,
+            PropertyValue::Color(color) => color.visit("Value", visitor)?,
+        }
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PropertyKeyFrame {
+    pub time: f32,
+    pub value: PropertyValue,
+}
+
+impl PropertyKeyFrame {
+    pub fn new(time: f32, value: PropertyValue) -> Self {
+        Self {
+            time,
+            value,
+        }
+    }
+}
+
+impl Visit for PropertyKeyFrame {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.time.visit("Time", visitor)?;
+        self.value.visit("Value", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct PropertyTrack {
+    node: Handle<Node>,
+    property: NodeProperty,
+    frames: Vec<PropertyKeyFrame>,
+    enabled: bool,
+}
+
+impl PropertyTrack {
+    pub fn new(node: Handle<Node>, property: NodeProperty) -> Self {
+        Self {
+            node,
+            property,
+            frames: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    pub fn set_node(&mut self, node: Handle<Node>) {
+        self.node = node;
+    }
+
+    pub fn node(&self) -> Handle<Node> {
+        self.node
+    }
+
+    pub fn property(&self) -> NodeProperty {
+        self.property
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Adds key frame, key frames are kept sorted by time.
+    pub fn add_key_frame(&mut self, key_frame: PropertyKeyFrame) {
+        let index = self.frames.iter()
+            .position(|other| other.time > key_frame.time)
+            .unwrap_or_else(|| self.frames.len());
+        self.frames.insert(index, key_frame);
+    }
+
+    pub fn key_frames(&self) -> &[PropertyKeyFrame] {
+        &self.frames
+    }
+
+    /// Returns time of last key frame.
+    pub fn max_time(&self) -> f32 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+
+    /// Returns value of property at given time, value is linearly interpolated between
+    /// key frames and clamped to first and last key frames.
+    pub fn value_at(&self, time: f32) -> Option<PropertyValue> {
+        let right_index = self.frames.iter().position(|frame| frame.time >= time);
+        match right_index {
+            Some(0) => self.frames.first().map(|frame| frame.value),
+            Some(index) => {
+                let left = &self.frames[index - 1];
+                let right = &self.frames[index];
+                let span = right.time - left.time;
+                let t = if span > 0.0 { (time - left.time) / span } else { 1.0 };
+                Some(left.value.interpolate(&right.value, t))
+            }
+            None => self.frames.last().map(|frame| frame.value),
+        }
+    }
+}
+
+impl Visit for PropertyTrack {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.property.visit("Property", visitor)?;
+        self.frames.visit("Frames", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::property::{NodeProperty, PropertyKeyFrame, PropertyTrack, PropertyValue},
+        core::{color::Color, pool::Handle},
+    };
+
+    #[test]
+    fn test_property_track_interpolation() {
+        let mut track = PropertyTrack::new(Handle::NONE, NodeProperty::LightIntensity);
+        track.add_key_frame(PropertyKeyFrame::new(1.0, PropertyValue::Float(3.0)));
+        track.add_key_frame(PropertyKeyFrame::new(0.0, PropertyValue::Float(1.0)));
+        assert_eq!(track.max_time(), 1.0);
+        assert_eq!(track.value_at(-1.0), Some(PropertyValue::Float(1.0)));
+        assert_eq!(track.value_at(0.5), Some(PropertyValue::Float(2.0)));
+        assert_eq!(track.value_at(2.0), Some(PropertyValue::Float(3.0)));
+
+        let black = PropertyValue::Color(Color::from_rgba(0, 0, 0, 255));
+        let white = PropertyValue::Color(Color::from_rgba(200, 200, 200, 255));
+        assert_eq!(black.interpolate(&white, 0.5), PropertyValue::Color(Color::from_rgba(100, 100, 100, 255)));
+    }
+}
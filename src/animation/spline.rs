@@ -0,0 +1,491 @@
+//! Spline following - moves nodes along smooth 3D paths.
+//!
+//! Spline is a Catmull-Rom curve which goes exactly through its control points. Spline
+//! follower moves a node along a spline with constant speed (curve is parametrized by
+//! arc length) and optionally turns node along direction of motion and banks it on
+//! turns. Followers are stored in scene and updated in `Scene::update` after animations,
+//! so they are suitable for camera rails, trams, patrolling drones, etc.
+//!
+//! Control points are defined in coordinate system of parent of node, so spline moves
+//! together with parent.
+
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            quat::Quat,
+        },
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor,
+        },
+        pool::{
+            Pool,
+            Handle,
+            PoolIterator,
+            PoolIteratorMut,
+            PoolPairIterator,
+        },
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Amount of linear segments per span used to approximate arc length.
+const SAMPLES_PER_SPAN: usize = 16;
+
+#[derive(Clone)]
+pub struct Spline {
+    points: Vec<Vec3>,
+    closed: bool,
+    /// Arc length of curve at each sample, first one is always zero.
+    lengths: Vec<f32>,
+}
+
+impl Default for Spline {
+    fn default() -> Self {
+        Self::new(Vec::new(), false)
+    }
+}
+
+impl Spline {
+    /// Creates new spline which goes through given points. Closed spline connects last
+    /// point with first one.
+    pub fn new(points: Vec<Vec3>, closed: bool) -> Self {
+        let mut spline = Self {
+            points,
+            closed,
+            lengths: Vec::new(),
+        };
+        spline.rebuild();
+        spline
+    }
+
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    pub fn set_points(&mut self, points: Vec<Vec3>) {
+        self.points = points;
+        self.rebuild();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn set_closed(&mut self, closed: bool) {
+        self.closed = closed;
+        self.rebuild();
+    }
+
+    /// Returns total arc length of spline.
+    pub fn length(&self) -> f32 {
+        self.lengths.last().cloned().unwrap_or(0.0)
+    }
+
+    fn span_count(&self) -> usize {
+        match self.points.len() {
+            0 | 1 => 0,
+            n if self.closed => n,
+            n => n - 1,
+        }
+    }
+
+    fn point(&self, index: isize) -> Vec3 {
+        let count = self.points.len() as isize;
+        let index = if self.closed {
+            ((index % count) + count) % count
+        } else {
+            index.max(0).min(count - 1)
+        };
+        self.points[index as usize]
+    }
+
+    /// Evaluates point on span `span` with local parameter `t` in [0; 1] range.
+    fn evaluate(&self, span: usize, t: f32) -> Vec3 {
+        let i = span as isize;
+        let p0 = self.point(i - 1);
+        let p1 = self.point(i);
+        let p2 = self.point(i + 1);
+        let p3 = self.point(i + 2);
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+
+        (p1.scale(2.0) +
+            (p2 - p0).scale(t) +
+            (p0.scale(2.0) - p1.scale(5.0) + p2.scale(4.0) - p3).scale(t2) +
+            (p1.scale(3.0) - p0 - p2.scale(3.0) + p3).scale(t3)).scale(0.5)
+    }
+
+    fn rebuild(&mut self) {
+        self.lengths.clear();
+        let spans = self.span_count();
+        if spans == 0 {
+            return;
+        }
+        self.lengths.push(0.0);
+        let mut length = 0.0;
+        let mut prev = self.evaluate(0, 0.0);
+        for span in 0..spans {
+            for k in 1..=SAMPLES_PER_SPAN {
+                let p = self.evaluate(span, k as f32 / SAMPLES_PER_SPAN as f32);
+                length += (p - prev).len();
+                self.lengths.push(length);
+                prev = p;
+            }
+        }
+    }
+
+    /// Converts arc length to span index and local parameter of span.
+    fn locate(&self, distance: f32) -> (usize, f32) {
+        let distance = distance.max(0.0).min(self.length());
+        let sample = match self.lengths.binary_search_by(|l| l.partial_cmp(&distance).unwrap()) {
+            Ok(index) => index,
+            Err(index) => index,
+        }.max(1).min(self.lengths.len() - 1);
+        let l0 = self.lengths[sample - 1];
+        let l1 = self.lengths[sample];
+        let k = if l1 > l0 { (distance - l0) / (l1 - l0) } else { 0.0 };
+        let global = (sample - 1) as f32 + k;
+        let span = ((global as usize) / SAMPLES_PER_SPAN).min(self.span_count() - 1);
+        let t = (global - (span * SAMPLES_PER_SPAN) as f32) / SAMPLES_PER_SPAN as f32;
+        (span, t)
+    }
+
+    /// Returns point at given distance along spline. Distance is clamped to length of
+    /// spline.
+    pub fn position_at(&self, distance: f32) -> Vec3 {
+        match self.points.len() {
+            0 => Vec3::ZERO,
+            1 => self.points[0],
+            _ => {
+                let (span, t) = self.locate(distance);
+                self.evaluate(span, t)
+            }
+        }
+    }
+
+    /// Returns normalized direction of spline at given distance, or None if spline is
+    /// degenerate.
+    pub fn tangent_at(&self, distance: f32) -> Option<Vec3> {
+        if self.points.len() < 2 {
+            return None;
+        }
+        let (span, t) = self.locate(distance);
+        let delta = 1.0 / (4 * SAMPLES_PER_SPAN) as f32;
+        let (a, b) = if t + delta <= 1.0 { (t, t + delta) } else { (t - delta, t) };
+        (self.evaluate(span, b) - self.evaluate(span, a)).normalized()
+    }
+}
+
+impl Visit for Spline {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.points.visit("Points", visitor)?;
+        self.closed.visit("Closed", visitor)?;
+
+        if visitor.is_reading() {
+            self.rebuild();
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Moves node along spline, see module docs.
+#[derive(Clone)]
+pub struct SplineFollower {
+    node: Handle<Node>,
+    spline: Spline,
+    speed: f32,
+    distance: f32,
+    looped: bool,
+    orient_to_tangent: bool,
+    banking: f32,
+    max_bank_angle: f32,
+    enabled: bool,
+}
+
+impl Default for SplineFollower {
+    fn default() -> Self {
+        Self::new(Handle::NONE, Spline::default())
+    }
+}
+
+impl SplineFollower {
+    pub fn new(node: Handle<Node>, spline: Spline) -> Self {
+        Self {
+            node,
+            spline,
+            speed: 1.0,
+            distance: 0.0,
+            looped: true,
+            orient_to_tangent: true,
+            banking: 0.0,
+            max_bank_angle: std::f32::consts::FRAC_PI_4,
+            enabled: true,
+        }
+    }
+
+    pub fn node(&self) -> Handle<Node> {
+        self.node
+    }
+
+    pub fn set_node(&mut self, node: Handle<Node>) {
+        self.node = node;
+    }
+
+    pub fn spline(&self) -> &Spline {
+        &self.spline
+    }
+
+    pub fn spline_mut(&mut self) -> &mut Spline {
+        &mut self.spline
+    }
+
+    /// Sets speed in units per second, negative speed moves node backwards.
+    pub fn set_speed(&mut self, speed: f32) -> &mut Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Sets current distance along spline.
+    pub fn set_distance(&mut self, distance: f32) -> &mut Self {
+        self.distance = distance;
+        self
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Looped follower starts over when it reaches end of spline, otherwise it stops at
+    /// the end.
+    pub fn set_looped(&mut self, looped: bool) -> &mut Self {
+        self.looped = looped;
+        self
+    }
+
+    pub fn is_looped(&self) -> bool {
+        self.looped
+    }
+
+    /// Sets whether node should look along direction of motion (local Z axis of node
+    /// points along spline).
+    pub fn set_orient_to_tangent(&mut self, orient: bool) -> &mut Self {
+        self.orient_to_tangent = orient;
+        self
+    }
+
+    pub fn is_orient_to_tangent(&self) -> bool {
+        self.orient_to_tangent
+    }
+
+    /// Sets how much node rolls into turns, zero disables banking. Bank angle is
+    /// proportional to horizontal curvature of spline and speed. Has effect only if
+    /// node is oriented to tangent.
+    pub fn set_banking(&mut self, banking: f32) -> &mut Self {
+        self.banking = banking.max(0.0);
+        self
+    }
+
+    pub fn banking(&self) -> f32 {
+        self.banking
+    }
+
+    /// Sets maximum bank angle in radians.
+    pub fn set_max_bank_angle(&mut self, angle: f32) -> &mut Self {
+        self.max_bank_angle = angle.abs();
+        self
+    }
+
+    pub fn max_bank_angle(&self) -> f32 {
+        self.max_bank_angle
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns true if non-looped follower reached end (or start when moving backwards)
+    /// of spline.
+    pub fn is_finished(&self) -> bool {
+        !self.looped && ((self.speed >= 0.0 && self.distance >= self.spline.length()) ||
+            (self.speed < 0.0 && self.distance <= 0.0))
+    }
+
+    fn bank_angle(&self, tangent: Vec3) -> f32 {
+        let delta = 0.1;
+        let ahead = if self.looped {
+            self.distance + delta
+        } else {
+            (self.distance + delta).min(self.spline.length())
+        };
+        let next = match self.spline.tangent_at(self.wrap(ahead)) {
+            Some(next) => next,
+            None => return 0.0,
+        };
+        // Signed change of heading in horizontal plane per unit of length.
+        let turn = (tangent.z * next.x - tangent.x * next.z) / delta;
+        let angle = -turn * self.speed.abs() * self.banking;
+        angle.max(-self.max_bank_angle).min(self.max_bank_angle)
+    }
+
+    fn wrap(&self, distance: f32) -> f32 {
+        let length = self.spline.length();
+        if self.looped && length > 0.0 {
+            ((distance % length) + length) % length
+        } else {
+            distance.max(0.0).min(length)
+        }
+    }
+
+    fn update(&mut self, graph: &mut Graph, dt: f32) {
+        if self.spline.points().is_empty() {
+            return;
+        }
+
+        self.distance = self.wrap(self.distance + self.speed * dt);
+
+        let position = self.spline.position_at(self.distance);
+        let rotation = if self.orient_to_tangent {
+            self.spline.tangent_at(self.distance).map(|tangent| {
+                let tangent = if self.speed < 0.0 { tangent.scale(-1.0) } else { tangent };
+                let yaw = tangent.x.atan2(tangent.z);
+                let pitch = -tangent.y.max(-1.0).min(1.0).asin();
+                let roll = if self.banking > 0.0 { self.bank_angle(tangent) } else { 0.0 };
+                Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), yaw) *
+                    Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), pitch) *
+                    Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), roll)
+            })
+        } else {
+            None
+        };
+
+        let transform = graph[self.node].local_transform_mut();
+        transform.set_position(position);
+        if let Some(rotation) = rotation {
+            transform.set_rotation(rotation);
+        }
+    }
+}
+
+impl Visit for SplineFollower {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.spline.visit("Spline", visitor)?;
+        self.speed.visit("Speed", visitor)?;
+        self.distance.visit("Distance", visitor)?;
+        self.looped.visit("Looped", visitor)?;
+        self.orient_to_tangent.visit("OrientToTangent", visitor)?;
+        self.banking.visit("Banking", visitor)?;
+        self.max_bank_angle.visit("MaxBankAngle", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct SplineFollowerContainer {
+    pool: Pool<SplineFollower>
+}
+
+impl Default for SplineFollowerContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SplineFollowerContainer {
+    pub(in crate) fn new() -> Self {
+        Self {
+            pool: Pool::new()
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> PoolIterator<SplineFollower> {
+        self.pool.iter()
+    }
+
+    #[inline]
+    pub fn pair_iter(&self) -> PoolPairIterator<SplineFollower> {
+        self.pool.pair_iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<SplineFollower> {
+        self.pool.iter_mut()
+    }
+
+    #[inline]
+    pub fn add(&mut self, follower: SplineFollower) -> Handle<SplineFollower> {
+        self.pool.spawn(follower)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, handle: Handle<SplineFollower>) {
+        self.pool.free(handle);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.pool.clear()
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<SplineFollower>) -> &SplineFollower {
+        self.pool.borrow(handle)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<SplineFollower>) -> &mut SplineFollower {
+        self.pool.borrow_mut(handle)
+    }
+
+    #[inline]
+    pub fn retain<P>(&mut self, pred: P) where P: FnMut(&SplineFollower) -> bool {
+        self.pool.retain(pred)
+    }
+
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        for follower in self.pool.iter_mut().filter(|f| f.enabled) {
+            if graph.is_valid_handle(follower.node) && graph[follower.node].is_globally_enabled() {
+                follower.update(graph, dt);
+            }
+        }
+    }
+
+    pub fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone()
+        }
+    }
+}
+
+impl Visit for SplineFollowerContainer {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
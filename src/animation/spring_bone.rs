@@ -0,0 +1,529 @@
+//! Spring bones - secondary motion of bones (hair, tails, antennas, cloth strips).
+//!
+//! Spring bone chain is a list of bones where each next bone is a child of previous one.
+//! After animations are applied, tail of every bone of chain (position of next bone) is
+//! simulated as a damped spring which is pulled towards its animated position, so chain
+//! lags behind fast motion, swings and settles down. Bones are then rotated towards
+//! simulated positions, lengths of bones are kept. Last bone of chain is a tip - it is
+//! moved by its parent, but not rotated.
+//!
+//! Chain can collide with spheres attached to other nodes (usually to bones of the same
+//! skeleton, like head or shoulders), so hair does not go through the body.
+//!
+//! Chains are stored in scene and updated in `Scene::update` after animations, spline
+//! followers and tweens.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::spring_bone::{SpringBoneChain, SpringBoneCollider},
+//!     scene::{Scene, node::Node},
+//!     core::{math::vec3::Vec3, pool::Handle},
+//! };
+//!
+//! fn add_tail(scene: &mut Scene, tail_bones: Vec<Handle<Node>>, pelvis: Handle<Node>) {
+//!     scene.spring_bones.add(SpringBoneChain::new(tail_bones)
+//!         .with_stiffness(20.0)
+//!         .with_damping(0.3)
+//!         .with_gravity(Vec3::new(0.0, -2.0, 0.0))
+//!         .with_colliders(vec![SpringBoneCollider {
+//!             node: pelvis,
+//!             offset: Vec3::ZERO,
+//!             radius: 0.2,
+//!         }]));
+//! }
+//! ```
+//!
+//! Simulated state is not saved, chain starts from animated pose after load.
+
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            mat4::Mat4,
+            quat::Quat,
+        },
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor,
+        },
+        pool::{
+            Pool,
+            Handle,
+            PoolIterator,
+            PoolIteratorMut,
+        },
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Sphere which pushes bones of chain away.
+#[derive(Clone, Debug)]
+pub struct SpringBoneCollider {
+    /// Node to which sphere is attached.
+    pub node: Handle<Node>,
+    /// Center of sphere in local coordinates of node.
+    pub offset: Vec3,
+    pub radius: f32,
+}
+
+impl Default for SpringBoneCollider {
+    fn default() -> Self {
+        Self {
+            node: Handle::NONE,
+            offset: Vec3::ZERO,
+            radius: 0.1,
+        }
+    }
+}
+
+impl Visit for SpringBoneCollider {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.offset.visit("Offset", visitor)?;
+        self.radius.visit("Radius", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Simulated state of one bone of chain.
+#[derive(Clone)]
+struct Joint {
+    tail: Vec3,
+    prev_tail: Vec3,
+    /// Local transform of bone set by animation, it is rest pose of spring.
+    animated: Mat4,
+    /// Local transform written by spring. If bone still has it on next update, then
+    /// animation did not touch the bone and `animated` is still actual.
+    written: Mat4,
+}
+
+/// Keeps distance between head and tail of bone.
+fn constrain_length(head: Vec3, tail: Vec3, length: f32) -> Vec3 {
+    match (tail - head).normalized() {
+        Some(direction) => head + direction.scale(length),
+        None => tail,
+    }
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct SpringBoneChain {
+    bones: Vec<Handle<Node>>,
+    stiffness: f32,
+    damping: f32,
+    gravity: Vec3,
+    radius: f32,
+    colliders: Vec<SpringBoneCollider>,
+    enabled: bool,
+    joints: Vec<Joint>,
+}
+
+impl Default for SpringBoneChain {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl SpringBoneChain {
+    /// Creates new chain from given bones, ordered from root to tip. Each bone must be
+    /// a child of previous one, chain must have at least two bones.
+    pub fn new(bones: Vec<Handle<Node>>) -> Self {
+        Self {
+            bones,
+            stiffness: 10.0,
+            damping: 0.2,
+            gravity: Vec3::ZERO,
+            radius: 0.02,
+            colliders: Vec::new(),
+            enabled: true,
+            joints: Vec::new(),
+        }
+    }
+
+    /// Sets how fast bones return to animated pose.
+    pub fn with_stiffness(mut self, stiffness: f32) -> Self {
+        self.set_stiffness(stiffness);
+        self
+    }
+
+    /// Sets fraction of velocity which is lost each update, in [0; 1] range.
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.set_damping(damping);
+        self
+    }
+
+    /// Sets acceleration in world space applied to tails of bones.
+    pub fn with_gravity(mut self, gravity: Vec3) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Sets radius of bones which is used for collisions.
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.set_radius(radius);
+        self
+    }
+
+    pub fn with_colliders(mut self, colliders: Vec<SpringBoneCollider>) -> Self {
+        self.colliders = colliders;
+        self
+    }
+
+    pub fn bones(&self) -> &[Handle<Node>] {
+        &self.bones
+    }
+
+    pub fn set_stiffness(&mut self, stiffness: f32) {
+        self.stiffness = stiffness.max(0.0);
+    }
+
+    pub fn stiffness(&self) -> f32 {
+        self.stiffness
+    }
+
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping = damping.max(0.0).min(1.0);
+    }
+
+    pub fn damping(&self) -> f32 {
+        self.damping
+    }
+
+    pub fn set_gravity(&mut self, gravity: Vec3) {
+        self.gravity = gravity;
+    }
+
+    pub fn gravity(&self) -> Vec3 {
+        self.gravity
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.max(0.0);
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn colliders(&self) -> &[SpringBoneCollider] {
+        &self.colliders
+    }
+
+    pub fn colliders_mut(&mut self) -> &mut Vec<SpringBoneCollider> {
+        &mut self.colliders
+    }
+
+    /// Disabled chain does not touch bones, they stay in animated pose.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.joints.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Forgets simulated state, chain starts again from animated pose on next update.
+    /// Call it after teleporting of character, otherwise bones will swing wildly.
+    pub fn reset(&mut self) {
+        self.joints.clear();
+    }
+
+    /// Replaces handles of bones and colliders with handles returned by given function.
+    pub(in crate) fn remap_nodes<F>(&mut self, mut map: F) where F: FnMut(Handle<Node>) -> Handle<Node> {
+        for bone in self.bones.iter_mut() {
+            *bone = map(*bone);
+        }
+        for collider in self.colliders.iter_mut() {
+            collider.node = map(collider.node);
+        }
+        self.joints.clear();
+    }
+
+    fn is_valid(&self, graph: &Graph) -> bool {
+        self.bones.len() >= 2
+            && self.bones.iter().all(|&bone| graph.is_valid_handle(bone))
+            && self.bones.windows(2).all(|pair| graph[pair[1]].parent() == pair[0])
+    }
+
+    /// Returns local transform of bone set by animation.
+    fn animated_local(&self, graph: &Graph, index: usize) -> Mat4 {
+        let current = graph[self.bones[index]].local_transform().matrix();
+        match self.joints.get(index) {
+            Some(joint) if joint.written.f == current.f => joint.animated,
+            _ => current,
+        }
+    }
+
+    fn update(&mut self, graph: &mut Graph, dt: f32) {
+        if !self.enabled || !self.is_valid(graph) {
+            self.joints.clear();
+            return;
+        }
+
+        let root = &graph[self.bones[0]];
+        if !root.is_globally_enabled() {
+            return;
+        }
+        let mut parent_global = if root.parent().is_some() {
+            graph[root.parent()].global_transform()
+        } else {
+            Mat4::IDENTITY
+        };
+
+        let colliders = self.colliders
+            .iter()
+            .filter(|collider| graph.is_valid_handle(collider.node))
+            .map(|collider| {
+                let center = graph[collider.node].global_transform().transform_vector(collider.offset);
+                (center, collider.radius + self.radius)
+            })
+            .collect::<Vec<_>>();
+
+        let initialize = self.joints.len() != self.bones.len() - 1;
+        if initialize {
+            self.joints.clear();
+        }
+
+        for i in 0..self.bones.len() - 1 {
+            let animated = self.animated_local(graph, i);
+            let global = parent_global * animated;
+            let head = global.position();
+            let target = global.transform_vector(self.animated_local(graph, i + 1).position());
+            let length = (target - head).len();
+
+            if initialize {
+                self.joints.push(Joint {
+                    tail: target,
+                    prev_tail: target,
+                    animated,
+                    written: animated,
+                });
+            }
+
+            let joint = &mut self.joints[i];
+            joint.animated = animated;
+
+            let velocity = (joint.tail - joint.prev_tail).scale(1.0 - self.damping);
+            let pull = (target - joint.tail).scale((self.stiffness * dt).min(1.0));
+            let mut tail = constrain_length(head, joint.tail + velocity + pull + self.gravity.scale(dt * dt), length);
+            for &(center, radius) in colliders.iter() {
+                let offset = tail - center;
+                if offset.len() < radius {
+                    if let Some(direction) = offset.normalized() {
+                        tail = constrain_length(head, center + direction.scale(radius), length);
+                    }
+                }
+            }
+            joint.prev_tail = joint.tail;
+            joint.tail = tail;
+
+            // Rotate bone around its head so it points to simulated tail.
+            let mut new_global = global;
+            if let (Some(from), Some(to)) = ((target - head).normalized(), (tail - head).normalized()) {
+                if let Some(axis) = from.cross(&to).normalized() {
+                    let angle = from.dot(&to).max(-1.0).min(1.0).acos();
+                    new_global = Mat4::translate(head)
+                        * Mat4::from_quat(Quat::from_axis_angle(axis, angle))
+                        * Mat4::translate(head.scale(-1.0))
+                        * global;
+                }
+            }
+
+            let transform = graph[self.bones[i]].local_transform_mut();
+            transform.set_matrix(parent_global.inverse().unwrap_or(Mat4::IDENTITY) * new_global);
+            joint.written = transform.matrix();
+            parent_global = parent_global * joint.written;
+        }
+    }
+
+    fn shift_origin(&mut self, offset: Vec3) {
+        for joint in self.joints.iter_mut() {
+            joint.tail = joint.tail - offset;
+            joint.prev_tail = joint.prev_tail - offset;
+        }
+    }
+}
+
+impl Visit for SpringBoneChain {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.bones.visit("Bones", visitor)?;
+        self.stiffness.visit("Stiffness", visitor)?;
+        self.damping.visit("Damping", visitor)?;
+        self.gravity.visit("Gravity", visitor)?;
+        self.radius.visit("Radius", visitor)?;
+        self.colliders.visit("Colliders", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+
+        if visitor.is_reading() {
+            self.joints.clear();
+        }
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Clone)]
+pub struct SpringBoneContainer {
+    pool: Pool<SpringBoneChain>,
+}
+
+impl Default for SpringBoneContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpringBoneContainer {
+    pub(in crate) fn new() -> Self {
+        Self {
+            pool: Pool::new(),
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> PoolIterator<SpringBoneChain> {
+        self.pool.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<SpringBoneChain> {
+        self.pool.iter_mut()
+    }
+
+    #[inline]
+    pub fn add(&mut self, chain: SpringBoneChain) -> Handle<SpringBoneChain> {
+        self.pool.spawn(chain)
+    }
+
+    /// Removes chain, bones keep their last pose until animation changes them.
+    #[inline]
+    pub fn remove(&mut self, handle: Handle<SpringBoneChain>) {
+        self.pool.free(handle);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.pool.clear()
+    }
+
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<SpringBoneChain>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<SpringBoneChain>) -> &SpringBoneChain {
+        self.pool.borrow(handle)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<SpringBoneChain>) -> &mut SpringBoneChain {
+        self.pool.borrow_mut(handle)
+    }
+
+    #[inline]
+    pub fn retain<P>(&mut self, pred: P) where P: FnMut(&SpringBoneChain) -> bool {
+        self.pool.retain(pred)
+    }
+
+    /// Resets every chain, see [`SpringBoneChain::reset`].
+    pub fn reset(&mut self) {
+        for chain in self.pool.iter_mut() {
+            chain.reset();
+        }
+    }
+
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        if self.pool.alive_count() == 0 {
+            return;
+        }
+
+        // Chains need global transforms of animated pose.
+        graph.update_hierachical_data();
+
+        for chain in self.pool.iter_mut() {
+            chain.update(graph, dt);
+        }
+    }
+
+    pub(in crate) fn shift_origin(&mut self, offset: Vec3) {
+        for chain in self.pool.iter_mut() {
+            chain.shift_origin(offset);
+        }
+    }
+}
+
+impl Visit for SpringBoneContainer {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::spring_bone::{SpringBoneChain, SpringBoneCollider, SpringBoneContainer},
+        core::math::vec3::Vec3,
+        scene::{
+            base::Base,
+            graph::Graph,
+            node::Node,
+        },
+    };
+
+    #[test]
+    fn test_spring_bone_gravity_and_collision() {
+        let mut graph = Graph::new();
+        let root = graph.add_node(Node::Base(Base::default()));
+        let middle = graph.add_node(Node::Base(Base::default()));
+        let tip = graph.add_node(Node::Base(Base::default()));
+        graph.link_nodes(middle, root);
+        graph.link_nodes(tip, middle);
+        graph[middle].local_transform_mut().set_position(Vec3::new(1.0, 0.0, 0.0));
+        graph[tip].local_transform_mut().set_position(Vec3::new(1.0, 0.0, 0.0));
+
+        let mut container = SpringBoneContainer::default();
+        let chain = container.add(SpringBoneChain::new(vec![root, middle, tip])
+            .with_stiffness(0.0)
+            .with_gravity(Vec3::new(0.0, -10.0, 0.0)));
+
+        for _ in 0..300 {
+            container.update(&mut graph, 1.0 / 60.0);
+        }
+        graph.update_hierachical_data();
+
+        // Chain hangs down and keeps its length.
+        assert!(graph[tip].global_position().y < -1.0);
+        assert!((graph[middle].global_position().len() - 1.0).abs() < 1.0e-3);
+
+        // Sphere below root pushes chain away.
+        let center = Vec3::new(0.3, -2.0, 0.0);
+        let distance_before = (graph[middle].global_position() - center).len();
+        container.get_mut(chain).colliders_mut().push(SpringBoneCollider {
+            node: root,
+            offset: center,
+            radius: 1.5,
+        });
+        container.update(&mut graph, 1.0 / 60.0);
+        graph.update_hierachical_data();
+        assert!((graph[middle].global_position() - center).len() > distance_before + 0.05);
+    }
+}
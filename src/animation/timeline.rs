@@ -0,0 +1,620 @@
+//! Timelines - sequencing of animations, camera cuts, sounds and signals for cutscenes.
+//!
+//! Timeline puts multiple animations (clips), camera cuts, sound cues and signals on a
+//! shared time axis. While timeline is playing, each clip which covers current time drives
+//! its animation: time position of animation is set from time of timeline and its pose is
+//! applied to graph. Camera cut enables its camera and disables cameras of other cuts of
+//! the timeline. Sound cues are played by sound bank of scene (see `scene::sound_bank`) and
+//! signals are published to event bus as `TimelineSignalMessage` when playback crosses them.
+//!
+//! Timeline can be scrubbed with `seek` - animations and camera are evaluated at new time
+//! on next update, but sound cues and signals between old and new time are skipped, so
+//! scrubbing in editor or skipping a cutscene does not play everything at once.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::{
+//!         Animation,
+//!         timeline::{AnimationClip, CameraCut, Timeline, TimelineSignal},
+//!     },
+//!     core::pool::Handle,
+//!     scene::{node::Node, Scene},
+//! };
+//!
+//! fn create_cutscene(scene: &mut Scene, walk: Handle<Animation>, wide: Handle<Node>, close: Handle<Node>) {
+//!     // Animation is driven by timeline, container must not tick it.
+//!     scene.animations.get_mut(walk).set_enabled(false);
+//!
+//!     let mut timeline = Timeline::new();
+//!     timeline.add_clip(AnimationClip::new(walk, 0.0, 4.0));
+//!     timeline.add_camera_cut(CameraCut::new(0.0, wide));
+//!     timeline.add_camera_cut(CameraCut::new(2.5, close));
+//!     timeline.add_sound_cue(1.0, "door_open", Handle::NONE);
+//!     timeline.add_signal(TimelineSignal::new(4.0, 1));
+//!     timeline.play();
+//!     scene.timelines.add(timeline);
+//! }
+//! ```
+//!
+//! Timelines are updated by scene right after animations, animations used by clips should
+//! be disabled, otherwise they are ticked by animation container too. Timeline does not
+//! restore state of nodes when it stops: animated nodes keep last applied pose and camera
+//! of last cut stays enabled.
+
+use std::collections::HashMap;
+use crate::{
+    animation::{
+        Animation,
+        AnimationContainer,
+    },
+    core::{
+        pool::{
+            Handle,
+            Pool,
+            PoolIterator,
+            PoolIteratorMut,
+        },
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor,
+        },
+    },
+    scene::{
+        event::{
+            EventBus,
+            SoundCueMessage,
+            TimelineSignalMessage,
+        },
+        graph::Graph,
+        node::Node,
+    },
+};
+
+/// Placement of animation on timeline.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    pub animation: Handle<Animation>,
+    /// Time on timeline when clip starts.
+    pub start: f32,
+    /// Length of clip on timeline.
+    pub duration: f32,
+    /// Time of animation at the start of clip.
+    pub offset: f32,
+    /// Multiplier of animation time relative to timeline time.
+    pub speed: f32,
+}
+
+impl Default for AnimationClip {
+    fn default() -> Self {
+        Self {
+            animation: Handle::NONE,
+            start: 0.0,
+            duration: 0.0,
+            offset: 0.0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl AnimationClip {
+    pub fn new(animation: Handle<Animation>, start: f32, duration: f32) -> Self {
+        Self {
+            animation,
+            start,
+            duration: duration.max(0.0),
+            ..Default::default()
+        }
+    }
+
+    pub fn end(&self) -> f32 {
+        self.start + self.duration
+    }
+
+    pub fn contains(&self, time: f32) -> bool {
+        time >= self.start && time <= self.end()
+    }
+
+    /// Returns time of animation at given time of timeline.
+    pub fn animation_time(&self, time: f32) -> f32 {
+        self.offset + (time - self.start) * self.speed
+    }
+}
+
+impl Visit for AnimationClip {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.animation.visit("Animation", visitor)?;
+        self.start.visit("Start", visitor)?;
+        self.duration.visit("Duration", visitor)?;
+        self.offset.visit("Offset", visitor)?;
+        self.speed.visit("Speed", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Switch to given camera at given time.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CameraCut {
+    pub time: f32,
+    pub camera: Handle<Node>,
+}
+
+impl CameraCut {
+    pub fn new(time: f32, camera: Handle<Node>) -> Self {
+        Self {
+            time,
+            camera,
+        }
+    }
+}
+
+impl Visit for CameraCut {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.time.visit("Time", visitor)?;
+        self.camera.visit("Camera", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Sound event of sound bank which is played at given time, at position of given node or
+/// as non-spatial sound if node is `Handle::NONE`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SoundCue {
+    pub time: f32,
+    pub event: String,
+    pub node: Handle<Node>,
+}
+
+impl Visit for SoundCue {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.time.visit("Time", visitor)?;
+        self.event.visit("Event", visitor)?;
+        self.node.visit("Node", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Signal for game code, published as `TimelineSignalMessage` when playback crosses it.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TimelineSignal {
+    pub time: f32,
+    pub id: u64,
+}
+
+impl TimelineSignal {
+    pub fn new(time: f32, id: u64) -> Self {
+        Self {
+            time,
+            id,
+        }
+    }
+}
+
+impl Visit for TimelineSignal {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.time.visit("Time", visitor)?;
+        self.id.visit("Id", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Returns true if time is in [from; to) range, or in [from; to] if `inclusive` is set.
+fn crossed(time: f32, from: f32, to: f32, inclusive: bool) -> bool {
+    time >= from && (time < to || (inclusive && time <= to))
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    clips: Vec<AnimationClip>,
+    cuts: Vec<CameraCut>,
+    sound_cues: Vec<SoundCue>,
+    signals: Vec<TimelineSignal>,
+    time: f32,
+    speed: f32,
+    looped: bool,
+    playing: bool,
+    /// Time was changed by seek, timeline must be evaluated without firing events.
+    dirty: bool,
+    active_cut: Option<usize>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            clips: Default::default(),
+            cuts: Default::default(),
+            sound_cues: Default::default(),
+            signals: Default::default(),
+            time: 0.0,
+            speed: 1.0,
+            looped: false,
+            playing: false,
+            dirty: true,
+            active_cut: None,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_clip(&mut self, clip: AnimationClip) {
+        self.clips.push(clip);
+    }
+
+    pub fn clips(&self) -> &[AnimationClip] {
+        &self.clips
+    }
+
+    pub fn clips_mut(&mut self) -> &mut Vec<AnimationClip> {
+        self.dirty = true;
+        &mut self.clips
+    }
+
+    /// Adds camera cut, cuts are kept sorted by time.
+    pub fn add_camera_cut(&mut self, cut: CameraCut) {
+        let index = self.cuts.iter()
+            .position(|other| other.time > cut.time)
+            .unwrap_or_else(|| self.cuts.len());
+        self.cuts.insert(index, cut);
+        self.dirty = true;
+    }
+
+    pub fn camera_cuts(&self) -> &[CameraCut] {
+        &self.cuts
+    }
+
+    pub fn remove_camera_cut(&mut self, index: usize) -> CameraCut {
+        self.dirty = true;
+        self.cuts.remove(index)
+    }
+
+    pub fn add_sound_cue(&mut self, time: f32, event: &str, node: Handle<Node>) {
+        self.sound_cues.push(SoundCue {
+            time,
+            event: event.to_owned(),
+            node,
+        });
+    }
+
+    pub fn sound_cues(&self) -> &[SoundCue] {
+        &self.sound_cues
+    }
+
+    pub fn sound_cues_mut(&mut self) -> &mut Vec<SoundCue> {
+        &mut self.sound_cues
+    }
+
+    pub fn add_signal(&mut self, signal: TimelineSignal) {
+        self.signals.push(signal);
+    }
+
+    pub fn signals(&self) -> &[TimelineSignal] {
+        &self.signals
+    }
+
+    pub fn signals_mut(&mut self) -> &mut Vec<TimelineSignal> {
+        &mut self.signals
+    }
+
+    /// Returns length of timeline - time of its last clip end, cut, sound cue or signal.
+    pub fn length(&self) -> f32 {
+        self.clips.iter().map(|clip| clip.end())
+            .chain(self.cuts.iter().map(|cut| cut.time))
+            .chain(self.sound_cues.iter().map(|cue| cue.time))
+            .chain(self.signals.iter().map(|signal| signal.time))
+            .fold(0.0, f32::max)
+    }
+
+    /// Starts or resumes playback, finished timeline is played from the beginning.
+    pub fn play(&mut self) {
+        if !self.looped && self.time >= self.length() {
+            self.seek(0.0);
+        }
+        self.playing = true;
+    }
+
+    /// Pauses playback, current state of nodes is kept.
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Pauses playback and rewinds to the beginning.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.seek(0.0);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Returns true if timeline has reached its end and is not looped.
+    pub fn is_finished(&self) -> bool {
+        !self.looped && !self.playing && self.time >= self.length()
+    }
+
+    /// Moves playback to given time. Animations and camera are evaluated at new time on
+    /// next update, sound cues and signals which are skipped are not fired.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.max(0.0).min(self.length());
+        self.dirty = true;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Sets playback speed, negative values are clamped to zero.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_loop(&mut self, looped: bool) {
+        self.looped = looped;
+    }
+
+    pub fn is_loop(&self) -> bool {
+        self.looped
+    }
+
+    /// Remaps nodes of camera cuts and sound cues, nodes which are not in map are set to
+    /// `Handle::NONE`.
+    pub(in crate) fn remap_nodes(&mut self, old_new_mapping: &HashMap<Handle<Node>, Handle<Node>>) {
+        for cut in self.cuts.iter_mut() {
+            cut.camera = old_new_mapping.get(&cut.camera).cloned().unwrap_or(Handle::NONE);
+        }
+        for cue in self.sound_cues.iter_mut() {
+            cue.node = old_new_mapping.get(&cue.node).cloned().unwrap_or(Handle::NONE);
+        }
+    }
+
+    pub(in crate) fn remap_animations(&mut self, old_new_mapping: &HashMap<Handle<Animation>, Handle<Animation>>) {
+        for clip in self.clips.iter_mut() {
+            clip.animation = old_new_mapping.get(&clip.animation).cloned().unwrap_or(Handle::NONE);
+        }
+    }
+
+    fn fire(&self, self_handle: Handle<Timeline>, events: &mut EventBus, from: f32, to: f32, inclusive: bool) {
+        for cue in self.sound_cues.iter().filter(|cue| crossed(cue.time, from, to, inclusive)) {
+            events.publish(SoundCueMessage {
+                event: cue.event.clone(),
+                node: cue.node,
+            });
+        }
+        for signal in self.signals.iter().filter(|signal| crossed(signal.time, from, to, inclusive)) {
+            events.publish(TimelineSignalMessage {
+                timeline: self_handle,
+                signal_id: signal.id,
+            });
+        }
+    }
+
+    fn evaluate(&mut self, graph: &mut Graph, animations: &mut AnimationContainer) {
+        for clip in self.clips.iter().filter(|clip| clip.contains(self.time)) {
+            if animations.is_valid_handle(clip.animation) {
+                let animation = animations.get_mut(clip.animation);
+                animation.set_time_position(clip.animation_time(self.time));
+                animation.update_pose();
+                animation.get_pose().apply(graph);
+            }
+        }
+
+        let time = self.time;
+        let active_cut = self.cuts.iter().rposition(|cut| cut.time <= time);
+        if active_cut != self.active_cut || self.dirty {
+            for (index, cut) in self.cuts.iter().enumerate() {
+                if graph.is_valid_handle(cut.camera) {
+                    if let Node::Camera(camera) = &mut graph[cut.camera] {
+                        camera.set_enabled(Some(index) == active_cut);
+                    }
+                }
+            }
+            self.active_cut = active_cut;
+        }
+
+        self.dirty = false;
+    }
+
+    fn update(&mut self, self_handle: Handle<Timeline>, graph: &mut Graph, animations: &mut AnimationContainer, events: &mut EventBus, dt: f32) {
+        if self.playing {
+            let length = self.length();
+            let from = self.time;
+            let to = from + dt * self.speed;
+            if to < length {
+                self.fire(self_handle, events, from, to, false);
+                self.time = to;
+            } else if self.looped && length > 0.0 {
+                self.fire(self_handle, events, from, length, true);
+                self.time = to % length;
+                self.fire(self_handle, events, 0.0, self.time, false);
+            } else {
+                self.fire(self_handle, events, from, length, true);
+                self.time = length;
+                self.playing = false;
+            }
+            self.evaluate(graph, animations);
+        } else if self.dirty {
+            self.evaluate(graph, animations);
+        }
+    }
+}
+
+impl Visit for Timeline {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.clips.visit("Clips", visitor)?;
+        self.cuts.visit("Cuts", visitor)?;
+        self.sound_cues.visit("SoundCues", visitor)?;
+        self.signals.visit("Signals", visitor)?;
+        self.time.visit("Time", visitor)?;
+        self.speed.visit("Speed", visitor)?;
+        self.looped.visit("Looped", visitor)?;
+        self.playing.visit("Playing", visitor)?;
+
+        if visitor.is_reading() {
+            self.dirty = true;
+        }
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Clone)]
+pub struct TimelineContainer {
+    pool: Pool<Timeline>,
+}
+
+impl Default for TimelineContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimelineContainer {
+    pub(in crate) fn new() -> Self {
+        Self {
+            pool: Pool::new(),
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> PoolIterator<Timeline> {
+        self.pool.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<Timeline> {
+        self.pool.iter_mut()
+    }
+
+    #[inline]
+    pub fn add(&mut self, timeline: Timeline) -> Handle<Timeline> {
+        self.pool.spawn(timeline)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, handle: Handle<Timeline>) {
+        self.pool.free(handle);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.pool.clear()
+    }
+
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Timeline>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<Timeline>) -> &Timeline {
+        self.pool.borrow(handle)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<Timeline>) -> &mut Timeline {
+        self.pool.borrow_mut(handle)
+    }
+
+    #[inline]
+    pub fn retain<P>(&mut self, pred: P) where P: FnMut(&Timeline) -> bool {
+        self.pool.retain(pred)
+    }
+
+    pub fn update(&mut self, graph: &mut Graph, animations: &mut AnimationContainer, events: &mut EventBus, dt: f32) {
+        for (handle, timeline) in self.pool.pair_iter_mut() {
+            timeline.update(handle, graph, animations, events, dt);
+        }
+    }
+}
+
+impl Visit for TimelineContainer {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        animation::{
+            AnimationContainer,
+            timeline::{CameraCut, Timeline, TimelineContainer, TimelineSignal},
+        },
+        core::pool::Handle,
+        scene::{
+            base::BaseBuilder,
+            camera::CameraBuilder,
+            event::{EventBus, SoundCueMessage, TimelineSignalMessage},
+            graph::Graph,
+            node::Node,
+        },
+    };
+
+    #[test]
+    fn test_timeline_events_and_seek() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(Node::Camera(CameraBuilder::new(BaseBuilder::new()).build()));
+        let b = graph.add_node(Node::Camera(CameraBuilder::new(BaseBuilder::new()).build()));
+        let mut animations = AnimationContainer::new();
+        let mut events = EventBus::new();
+
+        let mut timeline = Timeline::new();
+        timeline.add_camera_cut(CameraCut::new(1.0, b));
+        timeline.add_camera_cut(CameraCut::new(0.0, a));
+        timeline.add_signal(TimelineSignal::new(0.5, 1));
+        timeline.add_signal(TimelineSignal::new(2.0, 2));
+        timeline.add_sound_cue(1.5, "boom", Handle::NONE);
+        timeline.play();
+        assert_eq!(timeline.length(), 2.0);
+
+        let mut timelines = TimelineContainer::new();
+        let handle = timelines.add(timeline);
+
+        let is_enabled = |graph: &Graph, camera| match &graph[camera] {
+            Node::Camera(camera) => camera.is_enabled(),
+            _ => false,
+        };
+
+        timelines.update(&mut graph, &mut animations, &mut events, 0.75);
+        assert_eq!(events.drain::<TimelineSignalMessage>(), vec![TimelineSignalMessage { timeline: handle, signal_id: 1 }]);
+        assert!(is_enabled(&graph, a) && !is_enabled(&graph, b));
+
+        // Scrubbing does not fire skipped events.
+        timelines.get_mut(handle).seek(1.75);
+        timelines.update(&mut graph, &mut animations, &mut events, 0.0);
+        assert!(events.is_empty());
+        assert!(!is_enabled(&graph, a) && is_enabled(&graph, b));
+
+        timelines.update(&mut graph, &mut animations, &mut events, 1.0);
+        assert_eq!(events.drain::<TimelineSignalMessage>(), vec![TimelineSignalMessage { timeline: handle, signal_id: 2 }]);
+        assert!(events.read::<SoundCueMessage>().is_empty());
+        assert!(timelines.get(handle).is_finished());
+    }
+}
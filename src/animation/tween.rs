@@ -0,0 +1,588 @@
+//! Tweens - lightweight property animations.
+//!
+//! Tween smoothly changes one property of a node (position, scale, rotation, color of
+//! sprite, intensity of light, etc.) from its current value to target value during
+//! given time using easing function. Unlike full animations, tweens do not need any
+//! animation assets, so they are handy for doors, elevators, pop-up effects and other
+//! small bits of polish.
+//!
+//! Start value is taken from node when tween actually starts (after delay), so several
+//! tweens can be chained on same property using delays. When tween is finished, its
+//! callback (if any) is called and an event is pushed to the container, then tween is
+//! removed.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     animation::tween::{Tween, TweenTarget, Easing},
+//!     scene::{Scene, node::Node},
+//!     core::{math::vec3::Vec3, pool::Handle},
+//! };
+//!
+//! fn open_door(scene: &mut Scene, door: Handle<Node>) {
+//!     scene.tweens.add(Tween::new(door, TweenTarget::Position(Vec3::new(0.0, 3.0, 0.0)), 1.5)
+//!         .with_easing(Easing::CubicInOut)
+//!         .with_callback(|_graph, _door| println!("Door is open!")));
+//! }
+//! ```
+//!
+//! Callbacks are neither saved nor cloned with scene, tween without callback is saved
+//! and continues after load.
+
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+};
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            quat::Quat,
+        },
+        color::Color,
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor,
+        },
+        pool::{
+            Pool,
+            Handle,
+            PoolIterator,
+            PoolIteratorMut,
+        },
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Easing function defines how fast tweened value changes over time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Easing {
+    Linear = 0,
+    QuadIn = 1,
+    QuadOut = 2,
+    QuadInOut = 3,
+    CubicIn = 4,
+    CubicOut = 5,
+    CubicInOut = 6,
+    SineInOut = 7,
+    /// Overshoots target a bit and comes back.
+    BackOut = 8,
+    /// Oscillates around target with decaying amplitude.
+    ElasticOut = 9,
+    /// Bounces off target like a dropped ball.
+    BounceOut = 10,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(Easing::Linear),
+            1 => Ok(Easing::QuadIn),
+            2 => Ok(Easing::QuadOut),
+            3 => Ok(Easing::QuadInOut),
+            4 => Ok(Easing::CubicIn),
+            5 => Ok(Easing::CubicOut),
+            6 => Ok(Easing::CubicInOut),
+            7 => Ok(Easing::SineInOut),
+            8 => Ok(Easing::BackOut),
+            9 => Ok(Easing::ElasticOut),
+            10 => Ok(Easing::BounceOut),
+            _ => Err(format!("Invalid easing {}", id))
+        }
+    }
+
+    /// Maps normalized time in [0; 1] range to interpolation factor. Result is 0 at 0
+    /// and 1 at 1, but may go beyond [0; 1] range in between for some functions.
+    pub fn ease(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2.0 - t),
+            Easing::QuadInOut => if t < 0.5 {
+                2.0 * t * t
+            } else {
+                -1.0 + (4.0 - 2.0 * t) * t
+            },
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => {
+                let k = t - 1.0;
+                k * k * k + 1.0
+            }
+            Easing::CubicInOut => if t < 0.5 {
+                4.0 * t * t * t
+            } else {
+                let k = 2.0 * t - 2.0;
+                0.5 * k * k * k + 1.0
+            },
+            Easing::SineInOut => 0.5 * (1.0 - (PI * t).cos()),
+            Easing::BackOut => {
+                let s = 1.70158;
+                let k = t - 1.0;
+                k * k * ((s + 1.0) * k + s) + 1.0
+            }
+            Easing::ElasticOut => if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                2.0f32.powf(-10.0 * t) * ((t - 0.075) * (2.0 * PI) / 0.3).sin() + 1.0
+            },
+            Easing::BounceOut => {
+                let n = 7.5625;
+                let d = 2.75;
+                if t < 1.0 / d {
+                    n * t * t
+                } else if t < 2.0 / d {
+                    let k = t - 1.5 / d;
+                    n * k * k + 0.75
+                } else if t < 2.5 / d {
+                    let k = t - 2.25 / d;
+                    n * k * k + 0.9375
+                } else {
+                    let k = t - 2.625 / d;
+                    n * k * k + 0.984375
+                }
+            }
+        }
+    }
+}
+
+impl Visit for Easing {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id = *self as u32;
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = Self::from_id(id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Property of node and its target value.
+#[derive(Copy, Clone, Debug)]
+pub enum TweenTarget {
+    /// Local position of node.
+    Position(Vec3),
+    /// Local scale of node.
+    Scale(Vec3),
+    /// Local rotation of node.
+    Rotation(Quat),
+    /// Color of sprite.
+    SpriteColor(Color),
+    /// Size of sprite.
+    SpriteSize(f32),
+    /// Color of light.
+    LightColor(Color),
+    /// Intensity of light.
+    LightIntensity(f32),
+}
+
+impl Default for TweenTarget {
+    fn default() -> Self {
+        TweenTarget::Position(Vec3::ZERO)
+    }
+}
+
+pub(in crate) fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let lerp = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t).max(0.0).min(255.0) as u8;
+    Color::from_rgba(lerp(a.r, b.r), lerp(a.g, b.g), lerp(a.b, b.b), lerp(a.a, b.a))
+}
+
+impl TweenTarget {
+    fn new(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(TweenTarget::Position(Default::default())),
+            1 => Ok(TweenTarget::Scale(Default::default())),
+            2 => Ok(TweenTarget::Rotation(Quat::IDENTITY)),
+            3 => Ok(TweenTarget::SpriteColor(Default::default())),
+            4 => Ok(TweenTarget::SpriteSize(Default::default())),
+            5 => Ok(TweenTarget::LightColor(Default::default())),
+            6 => Ok(TweenTarget::LightIntensity(Default::default())),
+            _ => Err(format!("Invalid tween target kind {}", id))
+        }
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            TweenTarget::Position(_) => 0,
+            TweenTarget::Scale(_) => 1,
+            TweenTarget::Rotation(_) => 2,
+            TweenTarget::SpriteColor(_) => 3,
+            TweenTarget::SpriteSize(_) => 4,
+            TweenTarget::LightColor(_) => 5,
+            TweenTarget::LightIntensity(_) => 6,
+        }
+    }
+
+    /// Reads current value of same property from node. Returns None if node does not
+    /// have such property.
+    fn read(&self, node: &Node) -> Option<TweenTarget> {
+        let transform = node.local_transform();
+        match (self, node) {
+            (TweenTarget::Position(_), _) => Some(TweenTarget::Position(transform.position())),
+            (TweenTarget::Scale(_), _) => Some(TweenTarget::Scale(transform.scale())),
+            (TweenTarget::Rotation(_), _) => Some(TweenTarget::Rotation(transform.rotation())),
+            (TweenTarget::SpriteColor(_), Node::Sprite(sprite)) => Some(TweenTarget::SpriteColor(sprite.color())),
+            (TweenTarget::SpriteSize(_), Node::Sprite(sprite)) => Some(TweenTarget::SpriteSize(sprite.size())),
+            (TweenTarget::LightColor(_), Node::Light(light)) => Some(TweenTarget::LightColor(light.color())),
+            (TweenTarget::LightIntensity(_), Node::Light(light)) => Some(TweenTarget::LightIntensity(light.intensity())),
+            _ => None
+        }
+    }
+
+    /// Writes value interpolated between `start` and `self` into node.
+    fn apply(&self, start: &TweenTarget, t: f32, node: &mut Node) {
+        match (start, self) {
+            (TweenTarget::Position(a), TweenTarget::Position(b)) => {
+                node.local_transform_mut().set_position(*a + (*b - *a).scale(t));
+            }
+            (TweenTarget::Scale(a), TweenTarget::Scale(b)) => {
+                node.local_transform_mut().set_scale(*a + (*b - *a).scale(t));
+            }
+            (TweenTarget::Rotation(a), TweenTarget::Rotation(b)) => {
+                node.local_transform_mut().set_rotation(a.nlerp(b, t));
+            }
+            (TweenTarget::SpriteColor(a), TweenTarget::SpriteColor(b)) => {
+                if let Node::Sprite(sprite) = node {
+                    sprite.set_color(lerp_color(*a, *b, t));
+                }
+            }
+            (TweenTarget::SpriteSize(a), TweenTarget::SpriteSize(b)) => {
+                if let Node::Sprite(sprite) = node {
+                    sprite.set_size(a + (b - a) * t);
+                }
+            }
+            (TweenTarget::LightColor(a), TweenTarget::LightColor(b)) => {
+                if let Node::Light(light) = node {
+                    light.set_color(lerp_color(*a, *b, t));
+                }
+            }
+            (TweenTarget::LightIntensity(a), TweenTarget::LightIntensity(b)) => {
+                if let Node::Light(light) = node {
+                    light.set_intensity(a + (b - a) * t);
+                }
+            }
+            _ => ()
+        }
+    }
+}
+
+impl Visit for TweenTarget {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut kind_id = self.id();
+        kind_id.visit("KindId", visitor)?;
+        if visitor.is_reading() {
+            *self = TweenTarget::new(kind_id)?;
+        }
+
+        match self {
+            TweenTarget::Position(v) | TweenTarget::Scale(v) => v.visit("Value", visitor)?,
+            TweenTarget::Rotation(q) => q.visit("Value", visitor)?,
+            TweenTarget::SpriteColor(c) | TweenTarget::LightColor(c) => c.visit("Value", visitor)?,
+            TweenTarget::SpriteSize(f) | TweenTarget::LightIntensity(f) => f.visit("Value", visitor)?,
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Callback which is called when tween is finished, it receives graph and handle of
+/// tweened node.
+pub type TweenCallback = Box<dyn FnMut(&mut Graph, Handle<Node>)>;
+
+/// See module docs.
+pub struct Tween {
+    node: Handle<Node>,
+    target: TweenTarget,
+    start: Option<TweenTarget>,
+    duration: f32,
+    delay: f32,
+    elapsed: f32,
+    easing: Easing,
+    callback: Option<TweenCallback>,
+}
+
+impl Default for Tween {
+    fn default() -> Self {
+        Self::new(Handle::NONE, Default::default(), 0.0)
+    }
+}
+
+/// Callback is not cloned.
+impl Clone for Tween {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node,
+            target: self.target,
+            start: self.start,
+            duration: self.duration,
+            delay: self.delay,
+            elapsed: self.elapsed,
+            easing: self.easing,
+            callback: None,
+        }
+    }
+}
+
+impl Tween {
+    /// Creates new tween which changes property of node to target value in `duration`
+    /// seconds.
+    pub fn new(node: Handle<Node>, target: TweenTarget, duration: f32) -> Self {
+        Self {
+            node,
+            target,
+            start: None,
+            duration: duration.max(0.0),
+            delay: 0.0,
+            elapsed: 0.0,
+            easing: Easing::Linear,
+            callback: None,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Sets time in seconds before tween starts.
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay.max(0.0);
+        self
+    }
+
+    /// Sets callback which will be called when tween is finished.
+    pub fn with_callback<F>(mut self, callback: F) -> Self
+        where F: FnMut(&mut Graph, Handle<Node>) + 'static {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn node(&self) -> Handle<Node> {
+        self.node
+    }
+
+    pub fn set_node(&mut self, node: Handle<Node>) {
+        self.node = node;
+    }
+
+    pub fn target(&self) -> TweenTarget {
+        self.target
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    pub fn easing(&self) -> Easing {
+        self.easing
+    }
+
+    /// Returns progress of tween in [0; 1] range, it does not include easing.
+    pub fn progress(&self) -> f32 {
+        if self.duration > 0.0 {
+            ((self.elapsed - self.delay) / self.duration).max(0.0).min(1.0)
+        } else if self.elapsed >= self.delay {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.delay + self.duration
+    }
+
+    fn update(&mut self, graph: &mut Graph, dt: f32) {
+        self.elapsed += dt;
+
+        if self.elapsed < self.delay {
+            return;
+        }
+
+        let node = &mut graph[self.node];
+        if self.start.is_none() {
+            self.start = self.target.read(node);
+        }
+
+        match self.start {
+            Some(ref start) => self.target.apply(start, self.easing.ease(self.progress()), node),
+            // Node does not have such property, there is nothing to animate.
+            None => self.elapsed = self.delay + self.duration,
+        }
+    }
+}
+
+impl Visit for Tween {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.target.visit("Target", visitor)?;
+        self.duration.visit("Duration", visitor)?;
+        self.delay.visit("Delay", visitor)?;
+        self.elapsed.visit("Elapsed", visitor)?;
+        self.easing.visit("Easing", visitor)?;
+
+        let mut has_start = self.start.is_some();
+        has_start.visit("HasStart", visitor)?;
+        if has_start {
+            let mut start = self.start.unwrap_or_default();
+            start.visit("Start", visitor)?;
+            self.start = Some(start);
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Event which is emitted when tween is finished.
+#[derive(Copy, Clone, Debug)]
+pub struct TweenEvent {
+    /// Handle of finished tween, it is not valid anymore.
+    pub tween: Handle<Tween>,
+    /// Handle of tweened node.
+    pub node: Handle<Node>,
+}
+
+pub struct TweenContainer {
+    pool: Pool<Tween>,
+    events: VecDeque<TweenEvent>,
+}
+
+impl Default for TweenContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TweenContainer {
+    pub(in crate) fn new() -> Self {
+        Self {
+            pool: Pool::new(),
+            events: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> PoolIterator<Tween> {
+        self.pool.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<Tween> {
+        self.pool.iter_mut()
+    }
+
+    #[inline]
+    pub fn add(&mut self, tween: Tween) -> Handle<Tween> {
+        self.pool.spawn(tween)
+    }
+
+    /// Removes tween, property keeps its current value and callback is not called.
+    #[inline]
+    pub fn remove(&mut self, handle: Handle<Tween>) {
+        self.pool.free(handle);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.pool.clear()
+    }
+
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Tween>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<Tween>) -> &Tween {
+        self.pool.borrow(handle)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<Tween>) -> &mut Tween {
+        self.pool.borrow_mut(handle)
+    }
+
+    #[inline]
+    pub fn retain<P>(&mut self, pred: P) where P: FnMut(&Tween) -> bool {
+        self.pool.retain(pred)
+    }
+
+    /// Removes all tweens of given node, properties keep their current values.
+    pub fn stop_all(&mut self, node: Handle<Node>) {
+        self.pool.retain(|tween| tween.node != node)
+    }
+
+    /// Returns next event of finished tween, if any.
+    pub fn pop_event(&mut self) -> Option<TweenEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        let mut finished = Vec::new();
+        for (handle, tween) in self.pool.pair_iter_mut() {
+            if !graph.is_valid_handle(tween.node) {
+                // Node was removed, tween is dropped silently.
+                finished.push((handle, false));
+                continue;
+            }
+            if !graph[tween.node].is_globally_enabled() {
+                // Tweens of disabled nodes are paused.
+                continue;
+            }
+            tween.update(graph, dt);
+            if tween.is_finished() {
+                finished.push((handle, true));
+            }
+        }
+
+        for (handle, completed) in finished {
+            let tween = self.pool.borrow_mut(handle);
+            let node = tween.node;
+            let callback = tween.callback.take();
+            self.pool.free(handle);
+            if completed {
+                if let Some(mut callback) = callback {
+                    callback(graph, node);
+                }
+                // Keep amount of events bounded if user does not read them.
+                if self.events.len() < 64 {
+                    self.events.push_back(TweenEvent { tween: handle, node });
+                }
+            }
+        }
+    }
+
+    /// Clones tweens without callbacks.
+    pub fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            events: Default::default(),
+        }
+    }
+}
+
+impl Visit for TweenContainer {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
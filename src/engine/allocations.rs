@@ -0,0 +1,271 @@
+//! Allocation audit - counts heap allocations made by each phase of engine frame.
+//!
+//! Allocations in the middle of a frame are cheap one by one, but hundreds of them per
+//! frame cost noticeable time and fragment memory. Audit makes them visible: with feature
+//! `alloc_audit` the engine installs counting global allocator and records amount of
+//! allocations made by main thread in each phase of frame (the same phases as watchdog
+//! uses: resources, game logic hooks, scenes, sound, user interface, rendering).
+//! Allocations of other threads (async resource loading, jobs of task pool) are not
+//! counted, so numbers do not depend on timings of workers.
+//!
+//! Feature is meant for tests and profiling builds only, it replaces global allocator of
+//! the whole program and adds a bit of overhead to each allocation. Tests of a game can
+//! catch regressions by running a few warm-up frames and then checking budget:
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::{Engine, allocations::AllocationBudget},
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn check_steady_frame(engine: &mut Engine<(), StubNode>) {
+//!     for _ in 0..10 {
+//!         engine.update(1.0 / 60.0);
+//!         engine.render(1.0 / 60.0).unwrap();
+//!     }
+//!     // Allocations of last finished frame.
+//!     let budget = AllocationBudget::new()
+//!         .with_phase("Scenes", 0)
+//!         .with_total(16);
+//!     if let Err(e) = budget.check(engine.frame_allocations()) {
+//!         panic!("{}", e);
+//!     }
+//! }
+//! ```
+//!
+//! Any code can be checked the same way with `count_allocations`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fmt::{self, Display, Formatter, Write},
+};
+
+thread_local! {
+    static COUNT: Cell<usize> = Cell::new(0);
+    static BYTES: Cell<usize> = Cell::new(0);
+}
+
+fn count(size: usize) {
+    // Thread locals may be already destroyed when thread is finishing, such allocations
+    // are not interesting.
+    let _ = COUNT.try_with(|count| count.set(count.get() + 1));
+    let _ = BYTES.try_with(|bytes| bytes.set(bytes.get() + size));
+}
+
+/// Global allocator which counts allocations of each thread and passes them to system
+/// allocator. Reallocations are counted as allocations, deallocations are not counted.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Amount of allocations and total amount of requested bytes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocationCount {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl AllocationCount {
+    /// Returns amount of allocations made by current thread since start of thread.
+    pub fn current() -> Self {
+        Self {
+            count: COUNT.try_with(|count| count.get()).unwrap_or(0),
+            bytes: BYTES.try_with(|bytes| bytes.get()).unwrap_or(0),
+        }
+    }
+
+    /// Returns amount of allocations made by current thread since given count was taken.
+    pub fn since(earlier: AllocationCount) -> Self {
+        let now = Self::current();
+        Self {
+            count: now.count - earlier.count,
+            bytes: now.bytes - earlier.bytes,
+        }
+    }
+
+    fn add(&mut self, other: AllocationCount) {
+        self.count += other.count;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Calls given closure and returns its result with amount of allocations it made on
+/// current thread.
+pub fn count_allocations<F, R>(func: F) -> (R, AllocationCount) where F: FnOnce() -> R {
+    let start = AllocationCount::current();
+    let result = func();
+    (result, AllocationCount::since(start))
+}
+
+/// Allocations made by phases of one frame, in order of phases.
+#[derive(Clone, Debug, Default)]
+pub struct FrameAllocations {
+    phases: Vec<(&'static str, AllocationCount)>,
+}
+
+impl FrameAllocations {
+    /// Adds allocations to phase, phases which run a few times per frame (fixed steps of
+    /// scenes) are summed.
+    pub(in crate::engine) fn record(&mut self, name: &'static str, allocations: AllocationCount) {
+        match self.phases.iter_mut().find(|(phase, _)| *phase == name) {
+            Some((_, total)) => total.add(allocations),
+            None => self.phases.push((name, allocations)),
+        }
+    }
+
+    pub(in crate::engine) fn clear(&mut self) {
+        self.phases.clear();
+    }
+
+    /// Returns allocations of given phase, zero if phase did not run.
+    pub fn phase(&self, name: &str) -> AllocationCount {
+        self.phases.iter()
+            .find(|(phase, _)| *phase == name)
+            .map_or_else(Default::default, |(_, allocations)| *allocations)
+    }
+
+    pub fn phases(&self) -> &[(&'static str, AllocationCount)] {
+        &self.phases
+    }
+
+    /// Returns allocations of all phases.
+    pub fn total(&self) -> AllocationCount {
+        let mut total = AllocationCount::default();
+        for (_, allocations) in self.phases.iter() {
+            total.add(*allocations);
+        }
+        total
+    }
+}
+
+impl Display for FrameAllocations {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, allocations) in self.phases.iter() {
+            writeln!(f, "{}: {} allocations, {} bytes", name, allocations.count, allocations.bytes)?;
+        }
+        let total = self.total();
+        write!(f, "Total: {} allocations, {} bytes", total.count, total.bytes)
+    }
+}
+
+/// Max amounts of allocations per phase and per frame.
+#[derive(Clone, Debug, Default)]
+pub struct AllocationBudget {
+    phases: Vec<(String, usize)>,
+    total: Option<usize>,
+}
+
+impl AllocationBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets max amount of allocations of given phase.
+    pub fn with_phase(mut self, name: &str, max: usize) -> Self {
+        self.phases.push((name.to_owned(), max));
+        self
+    }
+
+    /// Sets max amount of allocations of whole frame.
+    pub fn with_total(mut self, max: usize) -> Self {
+        self.total = Some(max);
+        self
+    }
+
+    /// Checks allocations of frame against budget, error lists every exceeded limit.
+    pub fn check(&self, frame: &FrameAllocations) -> Result<(), String> {
+        let mut message = String::new();
+        for (name, max) in self.phases.iter() {
+            let count = frame.phase(name).count;
+            if count > *max {
+                writeln!(message, "{} made {} allocations, budget is {}", name, count, max).unwrap();
+            }
+        }
+        if let Some(max) = self.total {
+            let count = frame.total().count;
+            if count > max {
+                writeln!(message, "Frame made {} allocations, budget is {}", count, max).unwrap();
+            }
+        }
+        if message.is_empty() {
+            Ok(())
+        } else {
+            Err(message)
+        }
+    }
+}
+
+/// Allocations of current and last finished frames of engine.
+#[derive(Default)]
+pub(in crate::engine) struct AllocationAudit {
+    current: FrameAllocations,
+    last: FrameAllocations,
+}
+
+impl AllocationAudit {
+    pub fn begin_frame(&mut self) {
+        // Swap instead of clone to keep capacity, audit itself must not allocate each frame.
+        std::mem::swap(&mut self.current, &mut self.last);
+        self.current.clear();
+    }
+
+    pub fn record(&mut self, name: &'static str, start: AllocationCount) {
+        self.current.record(name, AllocationCount::since(start));
+    }
+
+    pub fn last_frame(&self) -> &FrameAllocations {
+        &self.last
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::allocations::{
+        count_allocations,
+        AllocationBudget,
+        AllocationCount,
+        FrameAllocations,
+    };
+
+    #[test]
+    fn test_allocation_budget() {
+        let (_, allocations) = count_allocations(|| Vec::<u32>::with_capacity(16));
+        assert_eq!(allocations, AllocationCount { count: 1, bytes: 64 });
+        let (_, allocations) = count_allocations(|| 2 + 2);
+        assert_eq!(allocations.count, 0);
+
+        let mut frame = FrameAllocations::default();
+        frame.record("Scenes", AllocationCount { count: 2, bytes: 32 });
+        frame.record("Render", AllocationCount { count: 5, bytes: 100 });
+        frame.record("Scenes", AllocationCount { count: 1, bytes: 16 });
+        assert_eq!(frame.phase("Scenes"), AllocationCount { count: 3, bytes: 48 });
+        assert_eq!(frame.total().count, 8);
+
+        assert!(AllocationBudget::new().with_phase("Scenes", 3).with_total(8).check(&frame).is_ok());
+        let error = AllocationBudget::new().with_phase("Render", 4).with_total(7).check(&frame).unwrap_err();
+        assert_eq!(error.lines().count(), 2);
+    }
+}
@@ -0,0 +1,642 @@
+//! Engine configuration file.
+//!
+//! Every game needs settings menu and a file to keep settings between launches. Engine
+//! config holds settings of window, renderer quality, audio volumes and input bindings,
+//! it can be saved to and loaded from a TOML file and applied to running engine by
+//! `Engine::apply_config`. Config is applied as a whole: if any part of it can't be applied
+//! (for example HRIR sphere can't be loaded), engine stays with previous settings.
+//!
+//! File contains only a subset of TOML which is needed for settings: sections, strings,
+//! numbers, booleans and arrays of strings. Missing values have defaults, so old config
+//! files stay valid when new settings are added:
+//!
+//! ```text
+//! [window]
+//! width = 1280
+//! height = 720
+//! fullscreen = false
+//!
+//! [renderer]
+//! use_ssao = true
+//! spot_shadow_map_size = 2048
+//!
+//! [audio]
+//! master_volume = 0.8
+//! music_volume = 0.5
+//!
+//! [input]
+//! jump = ["Space"]
+//! move_forward = ["W", "Up"]
+//! ```
+//!
+//! ```no_run
+//! use rg3d::engine::{Engine, config::EngineConfig};
+//! use rg3d::gui::node::StubNode;
+//!
+//! fn load_settings(engine: &mut Engine<(), StubNode>) {
+//!     let config = EngineConfig::load("settings.toml").unwrap_or_default();
+//!     engine.apply_config(&config).unwrap();
+//! }
+//! ```
+
+use std::{
+    fmt::{Display, Formatter, Write},
+    path::{Path, PathBuf},
+};
+use crate::{
+    renderer::{
+        QualitySettings,
+        ShadowFilter,
+        PoissonKernel,
+    },
+    resource::texture::TextureFilter,
+    event::VirtualKeyCode,
+};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    /// Error in file, line numbers start from one.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            ConfigError::Io(io) => write!(f, "Io error: {}", io),
+            ConfigError::Syntax { line, message } => write!(f, "Config error at line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig {
+    /// Size of client area of window in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Borderless full screen window on current monitor.
+    pub fullscreen: bool,
+    /// Vertical synchronization, it is used only when engine is created and can't be
+    /// changed at runtime.
+    pub vsync: bool,
+    /// Frame rate limit, `None` means no limit.
+    pub frame_rate_limit: Option<u32>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            fullscreen: false,
+            vsync: true,
+            frame_rate_limit: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioConfig {
+    /// Gain of every sound, applied to sound context.
+    pub master_volume: f32,
+    /// Volume of music, engine does not know which sounds are music, game multiplies gains
+    /// of its music sources by this value.
+    pub music_volume: f32,
+    /// Volume of sound effects, used by game the same way as `music_volume`.
+    pub effects_volume: f32,
+    /// Path of HRIR sphere for binaural rendering, `None` for stereo panning. See
+    /// `Engine::set_hrtf`.
+    pub hrir_path: Option<PathBuf>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            music_volume: 1.0,
+            effects_volume: 1.0,
+            hrir_path: None,
+        }
+    }
+}
+
+/// Maps names of game actions to names of keys, names of keys are names of variants of
+/// `VirtualKeyCode`, for example `W`, `Space` or `LShift`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputBindings {
+    bindings: Vec<(String, Vec<String>)>,
+}
+
+impl InputBindings {
+    /// Replaces keys bound to given action.
+    pub fn bind(&mut self, action: &str, keys: &[&str]) {
+        let keys = keys.iter().map(|key| (*key).to_owned()).collect();
+        match self.bindings.iter_mut().find(|(name, _)| name == action) {
+            Some((_, bound)) => *bound = keys,
+            None => self.bindings.push((action.to_owned(), keys)),
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.bindings.retain(|(name, _)| name != action);
+    }
+
+    /// Returns names of keys bound to given action.
+    pub fn keys(&self, action: &str) -> &[String] {
+        self.bindings
+            .iter()
+            .find(|(name, _)| name == action)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    /// Returns true if given key is bound to given action.
+    pub fn is_bound(&self, action: &str, key: VirtualKeyCode) -> bool {
+        let key = format!("{:?}", key);
+        self.keys(action).iter().any(|bound| *bound == key)
+    }
+
+    /// Returns names of actions to which given key is bound.
+    pub fn actions_of(&self, key: VirtualKeyCode) -> impl Iterator<Item=&str> {
+        let key = format!("{:?}", key);
+        self.bindings
+            .iter()
+            .filter(move |(_, keys)| keys.iter().any(|bound| *bound == key))
+            .map(|(action, _)| action.as_str())
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item=&str> {
+        self.bindings.iter().map(|(action, _)| action.as_str())
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Default)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    pub renderer: QualitySettings,
+    pub audio: AudioConfig,
+    pub input: InputBindings,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+fn syntax_error(line: usize, message: String) -> ConfigError {
+    ConfigError::Syntax { line, message }
+}
+
+/// Parses quoted string at beginning of `source`, returns string and rest of source.
+fn parse_string(source: &str, line: usize) -> Result<(String, &str), ConfigError> {
+    let mut result = String::new();
+    let mut chars = source.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((result, &source[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => result.push('\n'),
+                Some((_, 't')) => result.push('\t'),
+                Some((_, c)) => result.push(c),
+                None => break,
+            },
+            _ => result.push(c),
+        }
+    }
+    Err(syntax_error(line, "unterminated string".to_owned()))
+}
+
+fn parse_value(source: &str, line: usize) -> Result<Value, ConfigError> {
+    let source = source.trim();
+    if source.starts_with('"') {
+        let (string, rest) = parse_string(source, line)?;
+        check_trailing(rest, line)?;
+        Ok(Value::String(string))
+    } else if source.starts_with('[') {
+        let mut items = Vec::new();
+        let mut rest = source[1..].trim_start();
+        loop {
+            if rest.starts_with(']') {
+                check_trailing(&rest[1..], line)?;
+                return Ok(Value::Array(items));
+            }
+            if !rest.starts_with('"') {
+                return Err(syntax_error(line, "arrays may contain only strings".to_owned()));
+            }
+            let (item, after) = parse_string(rest, line)?;
+            items.push(item);
+            rest = after.trim_start();
+            if rest.starts_with(',') {
+                rest = rest[1..].trim_start();
+            } else if !rest.starts_with(']') {
+                return Err(syntax_error(line, "expected , or ] in array".to_owned()));
+            }
+        }
+    } else {
+        // Unquoted values may have trailing comment.
+        let source = source.split('#').next().unwrap_or("").trim();
+        match source {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => source.replace('_', "")
+                .parse()
+                .map(Value::Number)
+                .map_err(|_| syntax_error(line, format!("invalid value {}", source))),
+        }
+    }
+}
+
+fn check_trailing(rest: &str, line: usize) -> Result<(), ConfigError> {
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(syntax_error(line, format!("unexpected {}", rest)))
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Names of shadow filters in config file.
+const SHADOW_FILTERS: [(&str, ShadowFilter); 8] = [
+    ("hard", ShadowFilter::Hard),
+    ("hardware_pcf", ShadowFilter::HardwarePcf),
+    ("poisson8", ShadowFilter::Poisson(PoissonKernel::Small)),
+    ("poisson16", ShadowFilter::Poisson(PoissonKernel::Medium)),
+    ("poisson32", ShadowFilter::Poisson(PoissonKernel::Large)),
+    ("esm8", ShadowFilter::Exponential(PoissonKernel::Small)),
+    ("esm16", ShadowFilter::Exponential(PoissonKernel::Medium)),
+    ("esm32", ShadowFilter::Exponential(PoissonKernel::Large)),
+];
+
+fn shadow_filter_name(filter: ShadowFilter) -> &'static str {
+    SHADOW_FILTERS.iter()
+        .find(|(_, known)| *known == filter)
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+/// Reads values of a section into fields, unknown keys are ignored to let newer files be
+/// read by older versions of game.
+struct SectionReader<'a> {
+    values: &'a [(usize, String, Value)],
+}
+
+impl<'a> SectionReader<'a> {
+    fn find(&self, key: &str) -> Option<(usize, &Value)> {
+        self.values
+            .iter()
+            .find(|(_, name, _)| name == key)
+            .map(|(line, _, value)| (*line, value))
+    }
+
+    fn number(&self, key: &str, target: &mut f64) -> Result<(), ConfigError> {
+        match self.find(key) {
+            Some((_, Value::Number(number))) => *target = *number,
+            Some((line, _)) => return Err(syntax_error(line, format!("{} must be a number", key))),
+            None => (),
+        }
+        Ok(())
+    }
+
+    fn f32(&self, key: &str, target: &mut f32) -> Result<(), ConfigError> {
+        let mut value = f64::from(*target);
+        self.number(key, &mut value)?;
+        *target = value as f32;
+        Ok(())
+    }
+
+    fn u32(&self, key: &str, target: &mut u32) -> Result<(), ConfigError> {
+        let mut value = f64::from(*target);
+        self.number(key, &mut value)?;
+        *target = value.max(0.0) as u32;
+        Ok(())
+    }
+
+    fn usize(&self, key: &str, target: &mut usize) -> Result<(), ConfigError> {
+        let mut value = *target as f64;
+        self.number(key, &mut value)?;
+        *target = value.max(0.0) as usize;
+        Ok(())
+    }
+
+    fn bool(&self, key: &str, target: &mut bool) -> Result<(), ConfigError> {
+        match self.find(key) {
+            Some((_, Value::Bool(flag))) => *target = *flag,
+            Some((line, _)) => return Err(syntax_error(line, format!("{} must be true or false", key))),
+            None => (),
+        }
+        Ok(())
+    }
+
+    fn texture_filter(&self, key: &str, target: &mut TextureFilter) -> Result<(), ConfigError> {
+        match self.find(key) {
+            Some((line, Value::String(name))) => {
+                *target = match name.as_str() {
+                    "nearest" => TextureFilter::Nearest,
+                    "bilinear" => TextureFilter::Bilinear,
+                    "trilinear" => TextureFilter::Trilinear,
+                    _ => return Err(syntax_error(line, format!("{} must be nearest, bilinear or trilinear", key))),
+                }
+            }
+            Some((line, _)) => return Err(syntax_error(line, format!("{} must be a string", key))),
+            None => (),
+        }
+        Ok(())
+    }
+
+    fn shadow_filter(&self, key: &str, target: &mut ShadowFilter) -> Result<(), ConfigError> {
+        match self.find(key) {
+            Some((line, Value::String(name))) => {
+                *target = SHADOW_FILTERS.iter()
+                    .find(|(filter_name, _)| filter_name == name)
+                    .map(|(_, filter)| *filter)
+                    .ok_or_else(|| syntax_error(line, format!("{} must be one of: {}", key,
+                        SHADOW_FILTERS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", "))))?;
+            }
+            Some((line, _)) => return Err(syntax_error(line, format!("{} must be a string", key))),
+            None => (),
+        }
+        Ok(())
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.find(key) {
+            Some((_, Value::String(string))) => Ok(Some(string.clone())),
+            Some((line, _)) => Err(syntax_error(line, format!("{} must be a string", key))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Files written before shadow filters were added have only switch of soft shadows, switched
+/// off soft shadows mean hard filter.
+fn read_legacy_soft_shadows(reader: &SectionReader, key: &str, target: &mut ShadowFilter) -> Result<(), ConfigError> {
+    let mut soft = true;
+    reader.bool(key, &mut soft)?;
+    if !soft {
+        *target = ShadowFilter::Hard;
+    }
+    Ok(())
+}
+
+impl EngineConfig {
+    /// Loads config from file, see module docs for format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    /// Parses config from text, missing values are set to defaults.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(source: &str) -> Result<Self, ConfigError> {
+        let mut sections: Vec<(String, Vec<(usize, String, Value)>)> = vec![(String::new(), Vec::new())];
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                let end = line.find(']')
+                    .ok_or_else(|| syntax_error(number, "unterminated section name".to_owned()))?;
+                check_trailing(&line[end + 1..], number)?;
+                sections.push((line[1..end].trim().to_owned(), Vec::new()));
+                continue;
+            }
+            let separator = line.find('=')
+                .ok_or_else(|| syntax_error(number, "expected key = value".to_owned()))?;
+            let key = line[..separator].trim().trim_matches('"').to_owned();
+            let value = parse_value(&line[separator + 1..], number)?;
+            if let Some((_, values)) = sections.last_mut() {
+                values.push((number, key, value));
+            }
+        }
+
+        let mut config = Self::default();
+        for (name, values) in sections.iter() {
+            let reader = SectionReader { values };
+            match name.as_str() {
+                "window" => config.read_window(&reader)?,
+                "renderer" => config.read_renderer(&reader)?,
+                "audio" => config.read_audio(&reader)?,
+                "input" => {
+                    for (line, action, value) in values.iter() {
+                        match value {
+                            Value::Array(keys) => config.input.bindings.push((action.clone(), keys.clone())),
+                            Value::String(key) => config.input.bindings.push((action.clone(), vec![key.clone()])),
+                            _ => return Err(syntax_error(*line, format!("keys of {} must be strings", action))),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(config)
+    }
+
+    fn read_window(&mut self, reader: &SectionReader) -> Result<(), ConfigError> {
+        let window = &mut self.window;
+        reader.u32("width", &mut window.width)?;
+        reader.u32("height", &mut window.height)?;
+        reader.bool("fullscreen", &mut window.fullscreen)?;
+        reader.bool("vsync", &mut window.vsync)?;
+        let mut limit = window.frame_rate_limit.unwrap_or(0);
+        reader.u32("frame_rate_limit", &mut limit)?;
+        window.frame_rate_limit = if limit > 0 { Some(limit) } else { None };
+        Ok(())
+    }
+
+    fn read_renderer(&mut self, reader: &SectionReader) -> Result<(), ConfigError> {
+        let quality = &mut self.renderer;
+        reader.usize("point_shadow_map_size", &mut quality.point_shadow_map_size)?;
+        read_legacy_soft_shadows(reader, "point_soft_shadows", &mut quality.point_shadow_filter)?;
+        reader.shadow_filter("point_shadow_filter", &mut quality.point_shadow_filter)?;
+        reader.bool("point_shadows_enabled", &mut quality.point_shadows_enabled)?;
+        reader.f32("point_shadows_distance", &mut quality.point_shadows_distance)?;
+        reader.usize("spot_shadow_map_size", &mut quality.spot_shadow_map_size)?;
+        read_legacy_soft_shadows(reader, "spot_soft_shadows", &mut quality.spot_shadow_filter)?;
+        reader.shadow_filter("spot_shadow_filter", &mut quality.spot_shadow_filter)?;
+        reader.bool("spot_shadows_enabled", &mut quality.spot_shadows_enabled)?;
+        reader.f32("spot_shadows_distance", &mut quality.spot_shadows_distance)?;
+        reader.f32("shadows_fade_distance", &mut quality.shadows_fade_distance)?;
+        reader.f32("shadows_esm_exponent", &mut quality.shadows_esm_exponent)?;
+        reader.bool("use_ssao", &mut quality.use_ssao)?;
+        reader.f32("ssao_radius", &mut quality.ssao_radius)?;
+        reader.bool("use_ssr", &mut quality.use_ssr)?;
+        reader.f32("ssr_max_distance", &mut quality.ssr_max_distance)?;
+        reader.bool("light_scatter_enabled", &mut quality.light_scatter_enabled)?;
+        // Zero means no limit.
+        let mut draw_distance = quality.draw_distance.unwrap_or(0.0);
+        reader.f32("draw_distance", &mut draw_distance)?;
+        quality.draw_distance = if draw_distance > 0.0 { Some(draw_distance) } else { None };
+        reader.f32("draw_distance_hysteresis", &mut quality.draw_distance_hysteresis)?;
+        reader.bool("use_logarithmic_depth", &mut quality.use_logarithmic_depth)?;
+        reader.texture_filter("texture_filter", &mut quality.texture_filter)?;
+        reader.f32("texture_anisotropy", &mut quality.texture_anisotropy)?;
+        Ok(())
+    }
+
+    fn read_audio(&mut self, reader: &SectionReader) -> Result<(), ConfigError> {
+        let audio = &mut self.audio;
+        reader.f32("master_volume", &mut audio.master_volume)?;
+        reader.f32("music_volume", &mut audio.music_volume)?;
+        reader.f32("effects_volume", &mut audio.effects_volume)?;
+        if let Some(path) = reader.string("hrir_path")? {
+            audio.hrir_path = if path.is_empty() { None } else { Some(PathBuf::from(path)) };
+        }
+        Ok(())
+    }
+
+    /// Writes config in format of config file.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+
+        let window = &self.window;
+        writeln!(out, "[window]").unwrap();
+        writeln!(out, "width = {}", window.width).unwrap();
+        writeln!(out, "height = {}", window.height).unwrap();
+        writeln!(out, "fullscreen = {}", window.fullscreen).unwrap();
+        writeln!(out, "vsync = {}", window.vsync).unwrap();
+        writeln!(out, "frame_rate_limit = {}", window.frame_rate_limit.unwrap_or(0)).unwrap();
+
+        let quality = &self.renderer;
+        writeln!(out, "\n[renderer]").unwrap();
+        writeln!(out, "point_shadow_map_size = {}", quality.point_shadow_map_size).unwrap();
+        writeln!(out, "point_shadow_filter = \"{}\"", shadow_filter_name(quality.point_shadow_filter)).unwrap();
+        writeln!(out, "point_shadows_enabled = {}", quality.point_shadows_enabled).unwrap();
+        writeln!(out, "point_shadows_distance = {}", quality.point_shadows_distance).unwrap();
+        writeln!(out, "spot_shadow_map_size = {}", quality.spot_shadow_map_size).unwrap();
+        writeln!(out, "spot_shadow_filter = \"{}\"", shadow_filter_name(quality.spot_shadow_filter)).unwrap();
+        writeln!(out, "spot_shadows_enabled = {}", quality.spot_shadows_enabled).unwrap();
+        writeln!(out, "spot_shadows_distance = {}", quality.spot_shadows_distance).unwrap();
+        writeln!(out, "shadows_fade_distance = {}", quality.shadows_fade_distance).unwrap();
+        writeln!(out, "shadows_esm_exponent = {}", quality.shadows_esm_exponent).unwrap();
+        writeln!(out, "use_ssao = {}", quality.use_ssao).unwrap();
+        writeln!(out, "ssao_radius = {}", quality.ssao_radius).unwrap();
+        writeln!(out, "use_ssr = {}", quality.use_ssr).unwrap();
+        writeln!(out, "ssr_max_distance = {}", quality.ssr_max_distance).unwrap();
+        writeln!(out, "light_scatter_enabled = {}", quality.light_scatter_enabled).unwrap();
+        writeln!(out, "draw_distance = {}", quality.draw_distance.unwrap_or(0.0)).unwrap();
+        writeln!(out, "draw_distance_hysteresis = {}", quality.draw_distance_hysteresis).unwrap();
+        writeln!(out, "use_logarithmic_depth = {}", quality.use_logarithmic_depth).unwrap();
+        let texture_filter = match quality.texture_filter {
+            TextureFilter::Nearest => "nearest",
+            TextureFilter::Bilinear => "bilinear",
+            TextureFilter::Trilinear => "trilinear",
+        };
+        writeln!(out, "texture_filter = \"{}\"", texture_filter).unwrap();
+        writeln!(out, "texture_anisotropy = {}", quality.texture_anisotropy).unwrap();
+
+        let audio = &self.audio;
+        writeln!(out, "\n[audio]").unwrap();
+        writeln!(out, "master_volume = {}", audio.master_volume).unwrap();
+        writeln!(out, "music_volume = {}", audio.music_volume).unwrap();
+        writeln!(out, "effects_volume = {}", audio.effects_volume).unwrap();
+        out.push_str("hrir_path = ");
+        write_string(&mut out, &audio.hrir_path.as_ref().map_or(String::new(), |path| path.to_string_lossy().into_owned()));
+        out.push('\n');
+
+        writeln!(out, "\n[input]").unwrap();
+        for (action, keys) in self.input.bindings.iter() {
+            write_string(&mut out, action);
+            out.push_str(" = [");
+            for (index, key) in keys.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_string(&mut out, key);
+            }
+            out.push_str("]\n");
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use crate::{
+        engine::config::EngineConfig,
+        renderer::{ShadowFilter, PoissonKernel},
+        resource::texture::TextureFilter,
+        event::VirtualKeyCode,
+    };
+
+    #[test]
+    fn test_config_round_trip() {
+        let mut config = EngineConfig::from_str(r#"
+            # Comment
+            [window]
+            width = 1920
+            fullscreen = true # trailing comment
+
+            [renderer]
+            use_ssr = true
+            draw_distance = 500.0
+            texture_filter = "nearest"
+            point_soft_shadows = false
+            spot_shadow_filter = "esm32"
+
+            [audio]
+            master_volume = 0.5
+            hrir_path = "data/hrir \"sphere\".bin"
+
+            [input]
+            jump = ["Space"]
+            move_forward = ["W", "Up"]
+        "#).unwrap();
+
+        assert_eq!(config.window.width, 1920);
+        assert_eq!(config.window.height, 720);
+        assert!(config.window.fullscreen);
+        assert!(config.renderer.use_ssr);
+        assert_eq!(config.renderer.draw_distance, Some(500.0));
+        assert_eq!(config.renderer.texture_filter, TextureFilter::Nearest);
+        assert_eq!(config.renderer.point_shadow_filter, ShadowFilter::Hard);
+        assert_eq!(config.renderer.spot_shadow_filter, ShadowFilter::Exponential(PoissonKernel::Large));
+        assert_eq!(config.audio.master_volume, 0.5);
+        assert_eq!(config.audio.hrir_path, Some(PathBuf::from("data/hrir \"sphere\".bin")));
+        assert!(config.input.is_bound("move_forward", VirtualKeyCode::Up));
+        assert!(!config.input.is_bound("jump", VirtualKeyCode::W));
+
+        config.input.bind("crouch", &["LControl"]);
+        let loaded = EngineConfig::from_str(&config.to_text()).unwrap();
+        assert_eq!(loaded.window, config.window);
+        assert!(loaded.renderer == config.renderer);
+        assert_eq!(loaded.audio, config.audio);
+        assert_eq!(loaded.input, config.input);
+
+        assert!(EngineConfig::from_str("[window]\nwidth = \"wide\"").is_err());
+        assert!(EngineConfig::from_str("[renderer]\nspot_shadow_filter = \"blurry\"").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+//! Engine-wide counters of work done per update, for graphs in in-game debug overlays.
+//!
+//! Counters of work (nodes, animations, bodies, events) are gathered during
+//! `Engine::update` and describe last finished update: with fixed time step they are
+//! summed over all steps of the update, so they show real amount of work done per frame.
+//! Window events are counted from the end of previous update to the end of last one.
+//! Amounts of resident resources are taken at the moment of `Engine::counters` call.
+//! Counting is cheap, counters are always gathered.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::Engine,
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn overlay_text(engine: &Engine<(), StubNode>) -> String {
+//!     let counters = engine.counters();
+//!     format!("Nodes: {} Animations: {} Bodies: {}", counters.nodes_updated,
+//!             counters.animations_sampled, counters.bodies_simulated)
+//! }
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+use crate::scene::Scene;
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineCounters {
+    /// Amount of scene nodes updated by all scenes.
+    pub nodes_updated: usize,
+    /// Amount of enabled animations which were advanced and sampled.
+    pub animations_sampled: usize,
+    /// Amount of rigid bodies bound to scene nodes.
+    pub bodies_simulated: usize,
+    /// Amount of messages published by scenes (triggers, animation signals, etc).
+    pub scene_events: usize,
+    /// Amount of window events passed to `Engine::process_window_event`.
+    pub window_events: usize,
+    /// Amount of textures in resource manager.
+    pub textures_resident: usize,
+    /// Amount of models in resource manager.
+    pub models_resident: usize,
+    /// Amount of sound buffers in resource manager.
+    pub sound_buffers_resident: usize,
+}
+
+impl EngineCounters {
+    /// Returns total amount of processed events of all kinds.
+    pub fn events_processed(&self) -> usize {
+        self.scene_events + self.window_events
+    }
+
+    /// Adds work done by last update of given scene.
+    pub(in crate::engine) fn count_scene(&mut self, scene: &Scene) {
+        self.nodes_updated += scene.graph.pool_usage().1;
+        self.animations_sampled += scene.animations.iter().filter(|animation| animation.is_enabled()).count();
+        self.bodies_simulated += scene.physics_binder.node_rigid_body_map.values()
+            .filter(|&&body| scene.physics.is_valid_body_handle(body))
+            .count();
+        self.scene_events += scene.events.len();
+    }
+}
+
+impl Display for EngineCounters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Nodes updated: {}", self.nodes_updated)?;
+        writeln!(f, "Animations sampled: {}", self.animations_sampled)?;
+        writeln!(f, "Bodies simulated: {}", self.bodies_simulated)?;
+        writeln!(f, "Events processed: {}", self.events_processed())?;
+        write!(f, "Resident: {} textures, {} models, {} sound buffers",
+               self.textures_resident, self.models_resident, self.sound_buffers_resident)
+    }
+}
@@ -0,0 +1,126 @@
+//! Protection of long sessions from crashes - periodic autosave and emergency save on panic.
+//!
+//! Autosave writes state of engine (the same data as `Engine::visit`) to a file every
+//! `interval` seconds of update time. File is written to a temporary file first and then
+//! renamed, so crash during saving never leaves broken save behind.
+//!
+//! Crash guard catches panic of `Engine::update_with` (including game logic called from
+//! it) and `Engine::render`, writes panic message to log, tries to save current state to
+//! emergency file and aborts the process. State may be inconsistent at that moment, so
+//! emergency save is a last resort - regular autosave is more reliable.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::{Engine, crash::AutosaveSettings},
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn protect(engine: &mut Engine<(), StubNode>) {
+//!     engine.set_autosave(Some(AutosaveSettings {
+//!         interval: 300.0,
+//!         path: "autosave.bin".into(),
+//!     }));
+//!     engine.enable_crash_guard("emergency.bin");
+//! }
+//! ```
+
+use std::{
+    fs,
+    panic,
+    path::{Path, PathBuf},
+    sync::Once,
+};
+use crate::{
+    core::visitor::{Visit, Visitor, VisitResult},
+    utils::log::Log,
+};
+
+/// Settings of periodic autosave.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutosaveSettings {
+    /// Time between saves in seconds.
+    pub interval: f32,
+    /// Path of save file, it is overwritten by each save.
+    pub path: PathBuf,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self {
+            interval: 300.0,
+            path: PathBuf::from("autosave.bin"),
+        }
+    }
+}
+
+/// Tracks time to next autosave.
+#[derive(Default)]
+pub(in crate::engine) struct Autosave {
+    pub settings: Option<AutosaveSettings>,
+    elapsed: f32,
+}
+
+impl Autosave {
+    /// Accumulates time and returns path to save to if save is due.
+    pub fn advance(&mut self, dt: f32) -> Option<PathBuf> {
+        let settings = self.settings.as_ref()?;
+        self.elapsed += dt;
+        if self.elapsed >= settings.interval {
+            self.elapsed = 0.0;
+            Some(settings.path.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+/// Visits given value into new visitor and writes it to given path through temporary file.
+pub(in crate::engine) fn save_atomically<T: Visit>(value: &mut T, name: &str, path: &Path) -> VisitResult {
+    let mut visitor = Visitor::new();
+    value.visit(name, &mut visitor)?;
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    visitor.save_binary(&temp_path)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Installs panic hook which writes panic message to log and flushes it, then calls hook
+/// which was set before. Installed only once.
+pub(in crate::engine) fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            Log::writeln(format!("Panic: {}", info));
+            Log::flush();
+            previous(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::crash::{Autosave, AutosaveSettings};
+
+    #[test]
+    fn test_autosave_interval() {
+        let mut autosave = Autosave::default();
+        assert_eq!(autosave.advance(1000.0), None);
+
+        autosave.settings = Some(AutosaveSettings {
+            interval: 1.0,
+            path: "save.bin".into(),
+        });
+        assert_eq!(autosave.advance(0.6), None);
+        assert_eq!(autosave.advance(0.6), Some("save.bin".into()));
+        assert_eq!(autosave.advance(0.6), None);
+    }
+}
@@ -0,0 +1,588 @@
+//! Console variables (cvars) - named typed values which can be changed at runtime.
+//!
+//! Tuning values like shadow distance or speed of player is much faster when they can be
+//! changed without recompiling the game. Cvar registry holds such values by name, each
+//! value has type (bool, integer, float or string), default value, optional range and
+//! callbacks which are called when value changes. Values are set by typing commands
+//! (`execute`), which is what debug console does, or by loading a file with `name = value`
+//! lines, for example:
+//!
+//! ```text
+//! # Tuning
+//! r_shadow_distance = 30
+//! g_player_speed = 4.5
+//! ```
+//!
+//! Engine registers its own cvars (prefix `r_` for renderer) in `Engine::cvars` and applies
+//! them on next update after change, game registers its own cvars in the same registry and
+//! either reads them every frame or subscribes to changes.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::{Engine, cvar::CvarBuilder},
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn setup(engine: &mut Engine<(), StubNode>) {
+//!     engine.cvars.register(CvarBuilder::new("g_player_speed", 4.0)
+//!         .with_description("Speed of player in units per second")
+//!         .with_range(0.0, 20.0)
+//!         .with_callback(|value| println!("new speed {}", value)))
+//!         .unwrap();
+//!
+//!     engine.cvars.execute("r_shadow_distance 30").unwrap();
+//!     engine.cvars.load("tuning.cfg").unwrap();
+//!
+//!     let speed = engine.cvars.get_float("g_player_speed").unwrap_or(4.0);
+//!     println!("speed is {}", speed);
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Write},
+    path::Path,
+};
+use crate::renderer::QualitySettings;
+
+#[derive(Debug)]
+pub enum CvarError {
+    Io(std::io::Error),
+    /// There is no cvar with given name.
+    UnknownName(String),
+    /// Cvar with given name is already registered.
+    AlreadyRegistered(String),
+    /// Value can't be converted to type of cvar.
+    InvalidValue {
+        name: String,
+        value: String,
+    },
+    /// Error in cvar file, line numbers start from one.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+}
+
+impl Display for CvarError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            CvarError::Io(io) => write!(f, "Io error: {}", io),
+            CvarError::UnknownName(name) => write!(f, "Unknown cvar {}", name),
+            CvarError::AlreadyRegistered(name) => write!(f, "Cvar {} is already registered", name),
+            CvarError::InvalidValue { name, value } => write!(f, "Invalid value {} of cvar {}", value, name),
+            CvarError::Syntax { line, message } => write!(f, "Cvar file error at line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<std::io::Error> for CvarError {
+    fn from(err: std::io::Error) -> Self {
+        CvarError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CvarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Display for CvarValue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            CvarValue::Bool(value) => write!(f, "{}", value),
+            CvarValue::Int(value) => write!(f, "{}", value),
+            CvarValue::Float(value) => write!(f, "{}", value),
+            CvarValue::String(value) => write!(f, "\"{}\"", value),
+        }
+    }
+}
+
+impl From<bool> for CvarValue {
+    fn from(value: bool) -> Self {
+        CvarValue::Bool(value)
+    }
+}
+
+impl From<i64> for CvarValue {
+    fn from(value: i64) -> Self {
+        CvarValue::Int(value)
+    }
+}
+
+impl From<i32> for CvarValue {
+    fn from(value: i32) -> Self {
+        CvarValue::Int(i64::from(value))
+    }
+}
+
+impl From<f64> for CvarValue {
+    fn from(value: f64) -> Self {
+        CvarValue::Float(value)
+    }
+}
+
+impl From<f32> for CvarValue {
+    fn from(value: f32) -> Self {
+        CvarValue::Float(f64::from(value))
+    }
+}
+
+impl From<&str> for CvarValue {
+    fn from(value: &str) -> Self {
+        CvarValue::String(value.to_owned())
+    }
+}
+
+impl CvarValue {
+    /// Converts given value to type of this value. Numbers are converted to each other,
+    /// strings are parsed, so `"1"`, `1`, `1.0` and `true` are valid values of any
+    /// numeric or boolean cvar.
+    fn convert(&self, value: &CvarValue) -> Option<CvarValue> {
+        match (self, value) {
+            (CvarValue::Bool(_), CvarValue::Bool(v)) => Some(CvarValue::Bool(*v)),
+            (CvarValue::Bool(_), CvarValue::Int(v)) => Some(CvarValue::Bool(*v != 0)),
+            (CvarValue::Int(_), CvarValue::Int(v)) => Some(CvarValue::Int(*v)),
+            (CvarValue::Int(_), CvarValue::Float(v)) => Some(CvarValue::Int(v.round() as i64)),
+            (CvarValue::Int(_), CvarValue::Bool(v)) => Some(CvarValue::Int(*v as i64)),
+            (CvarValue::Float(_), CvarValue::Float(v)) => Some(CvarValue::Float(*v)),
+            (CvarValue::Float(_), CvarValue::Int(v)) => Some(CvarValue::Float(*v as f64)),
+            (CvarValue::String(_), CvarValue::String(v)) => Some(CvarValue::String(v.clone())),
+            (CvarValue::String(_), v) => Some(CvarValue::String(v.to_string())),
+            (_, CvarValue::String(v)) => self.parse(v),
+            _ => None,
+        }
+    }
+
+    fn parse(&self, source: &str) -> Option<CvarValue> {
+        let source = source.trim();
+        match self {
+            CvarValue::Bool(_) => match source {
+                "true" | "1" | "on" => Some(CvarValue::Bool(true)),
+                "false" | "0" | "off" => Some(CvarValue::Bool(false)),
+                _ => None,
+            },
+            CvarValue::Int(_) => source.parse::<i64>().ok()
+                .or_else(|| source.parse::<f64>().ok().map(|v| v.round() as i64))
+                .map(CvarValue::Int),
+            CvarValue::Float(_) => source.parse().ok().map(CvarValue::Float),
+            CvarValue::String(_) => Some(CvarValue::String(source.trim_matches('"').to_owned())),
+        }
+    }
+
+    fn clamp(self, min: Option<f64>, max: Option<f64>) -> CvarValue {
+        let min = min.unwrap_or(f64::MIN);
+        let max = max.unwrap_or(f64::MAX);
+        match self {
+            CvarValue::Int(v) => CvarValue::Int((v as f64).max(min).min(max) as i64),
+            CvarValue::Float(v) => CvarValue::Float(v.max(min).min(max)),
+            other => other,
+        }
+    }
+}
+
+type CvarCallback = Box<dyn FnMut(&CvarValue)>;
+
+/// Registered variable.
+pub struct Cvar {
+    name: String,
+    description: String,
+    value: CvarValue,
+    default: CvarValue,
+    min: Option<f64>,
+    max: Option<f64>,
+    callbacks: Vec<CvarCallback>,
+}
+
+impl Cvar {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn value(&self) -> &CvarValue {
+        &self.value
+    }
+
+    pub fn default_value(&self) -> &CvarValue {
+        &self.default
+    }
+
+    /// Returns range of numeric cvar, `None` means no bound.
+    pub fn range(&self) -> (Option<f64>, Option<f64>) {
+        (self.min, self.max)
+    }
+}
+
+pub struct CvarBuilder {
+    name: String,
+    description: String,
+    default: CvarValue,
+    min: Option<f64>,
+    max: Option<f64>,
+    callbacks: Vec<CvarCallback>,
+}
+
+impl CvarBuilder {
+    /// Creates builder of cvar with given name and default value, type of default value
+    /// is type of cvar.
+    pub fn new<V: Into<CvarValue>>(name: &str, default: V) -> Self {
+        Self {
+            name: name.to_owned(),
+            description: String::new(),
+            default: default.into(),
+            min: None,
+            max: None,
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = description.to_owned();
+        self
+    }
+
+    /// Sets range of numeric cvar, values out of range are clamped.
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    /// Adds function which is called with new value each time value changes.
+    pub fn with_callback<F>(mut self, callback: F) -> Self where F: FnMut(&CvarValue) + 'static {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    fn build(self) -> Cvar {
+        let default = self.default.clamp(self.min, self.max);
+        Cvar {
+            name: self.name,
+            description: self.description,
+            value: default.clone(),
+            default,
+            min: self.min,
+            max: self.max,
+            callbacks: self.callbacks,
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Default)]
+pub struct CvarRegistry {
+    cvars: HashMap<String, Cvar>,
+    revision: u64,
+}
+
+impl CvarRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register(&mut self, builder: CvarBuilder) -> Result<(), CvarError> {
+        if self.cvars.contains_key(&builder.name) {
+            return Err(CvarError::AlreadyRegistered(builder.name));
+        }
+        let cvar = builder.build();
+        self.cvars.insert(cvar.name.clone(), cvar);
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Cvar> {
+        self.cvars.remove(name)
+    }
+
+    /// Adds callback to existing cvar.
+    pub fn subscribe<F>(&mut self, name: &str, callback: F) -> Result<(), CvarError>
+        where F: FnMut(&CvarValue) + 'static {
+        let cvar = self.cvars.get_mut(name).ok_or_else(|| CvarError::UnknownName(name.to_owned()))?;
+        cvar.callbacks.push(Box::new(callback));
+        Ok(())
+    }
+
+    /// Sets value of cvar, value is converted to type of cvar and clamped to its range.
+    /// Callbacks are called only if value has actually changed.
+    pub fn set<V: Into<CvarValue>>(&mut self, name: &str, value: V) -> Result<(), CvarError> {
+        let value = value.into();
+        let cvar = self.cvars.get_mut(name).ok_or_else(|| CvarError::UnknownName(name.to_owned()))?;
+        let converted = cvar.value.convert(&value)
+            .ok_or_else(|| CvarError::InvalidValue { name: name.to_owned(), value: value.to_string() })?
+            .clamp(cvar.min, cvar.max);
+        if converted != cvar.value {
+            cvar.value = converted;
+            for callback in cvar.callbacks.iter_mut() {
+                callback(&cvar.value);
+            }
+            self.revision += 1;
+        }
+        Ok(())
+    }
+
+    pub fn reset(&mut self, name: &str) -> Result<(), CvarError> {
+        let default = self.cvars.get(name)
+            .ok_or_else(|| CvarError::UnknownName(name.to_owned()))?
+            .default
+            .clone();
+        self.set(name, default)
+    }
+
+    pub fn reset_all(&mut self) {
+        let names = self.cvars.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            let _ = self.reset(&name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CvarValue> {
+        self.cvars.get(name).map(|cvar| &cvar.value)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            CvarValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            CvarValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns value of float or integer cvar.
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            CvarValue::Float(value) => Some(*value),
+            CvarValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            CvarValue::String(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn cvar(&self, name: &str) -> Option<&Cvar> {
+        self.cvars.get(name)
+    }
+
+    /// Returns cvars sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item=&Cvar> {
+        let mut cvars = self.cvars.values().collect::<Vec<_>>();
+        cvars.sort_by(|a, b| a.name.cmp(&b.name));
+        cvars.into_iter()
+    }
+
+    /// Returns sorted names of cvars which start with given prefix, used for completion in
+    /// console.
+    pub fn complete(&self, prefix: &str) -> Vec<&str> {
+        self.iter()
+            .map(|cvar| cvar.name.as_str())
+            .filter(|name| name.starts_with(prefix))
+            .collect()
+    }
+
+    /// Returns counter which is incremented on every change of any cvar. Systems which
+    /// apply many cvars at once compare it with value seen last time instead of
+    /// subscribing to each cvar.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Executes console command and returns text to print:
+    ///
+    /// - `name` - prints value and description of cvar.
+    /// - `name value` - sets value of cvar.
+    /// - `reset name` - sets default value of cvar.
+    /// - `find prefix` - prints names of cvars which start with prefix.
+    pub fn execute(&mut self, command: &str) -> Result<String, CvarError> {
+        let command = command.trim();
+        let (name, argument) = match command.find(char::is_whitespace) {
+            Some(index) => (&command[..index], command[index..].trim()),
+            None => (command, ""),
+        };
+        match name {
+            "reset" => {
+                self.reset(argument)?;
+                self.describe(argument)
+            }
+            "find" => Ok(self.complete(argument).join("\n")),
+            _ if argument.is_empty() => self.describe(name),
+            _ => {
+                self.set(name, CvarValue::String(argument.to_owned()))?;
+                self.describe(name)
+            }
+        }
+    }
+
+    fn describe(&self, name: &str) -> Result<String, CvarError> {
+        let cvar = self.cvars.get(name).ok_or_else(|| CvarError::UnknownName(name.to_owned()))?;
+        let mut out = format!("{} = {} (default {})", cvar.name, cvar.value, cvar.default);
+        if !cvar.description.is_empty() {
+            write!(out, " - {}", cvar.description).unwrap();
+        }
+        Ok(out)
+    }
+
+    /// Sets cvars from text with `name = value` lines, see module docs. Unknown cvars are
+    /// errors, so typos in tuning files do not go unnoticed.
+    pub fn load_from_str(&mut self, source: &str) -> Result<(), CvarError> {
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let separator = line.find('=').ok_or_else(|| CvarError::Syntax {
+                line: index + 1,
+                message: "expected name = value".to_owned(),
+            })?;
+            let name = line[..separator].trim();
+            let value = line[separator + 1..].trim();
+            self.set(name, CvarValue::String(value.to_owned()))
+                .map_err(|e| CvarError::Syntax { line: index + 1, message: e.to_string() })?;
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CvarError> {
+        self.load_from_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Writes values which differ from defaults in format of cvar file.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for cvar in self.iter().filter(|cvar| cvar.value != cvar.default) {
+            writeln!(out, "{} = {}", cvar.name, cvar.value).unwrap();
+        }
+        out
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CvarError> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+}
+
+/// Registers cvars of renderer with values from given settings.
+pub(in crate::engine) fn register_renderer_cvars(registry: &mut CvarRegistry, settings: &QualitySettings) {
+    let cvars = [
+        CvarBuilder::new("r_shadow_distance", settings.spot_shadows_distance.max(settings.point_shadows_distance))
+            .with_description("Max distance from camera to shadows of point and spot lights")
+            .with_range(0.0, f64::MAX),
+        CvarBuilder::new("r_shadows_fade_distance", settings.shadows_fade_distance)
+            .with_description("Distance over which shadows fade out before max distance")
+            .with_range(0.0, f64::MAX),
+        CvarBuilder::new("r_point_shadows", settings.point_shadows_enabled),
+        CvarBuilder::new("r_spot_shadows", settings.spot_shadows_enabled),
+        CvarBuilder::new("r_ssao", settings.use_ssao),
+        CvarBuilder::new("r_ssao_radius", settings.ssao_radius)
+            .with_range(0.0, f64::MAX),
+        CvarBuilder::new("r_ssr", settings.use_ssr),
+        CvarBuilder::new("r_ssr_max_distance", settings.ssr_max_distance)
+            .with_range(0.0, f64::MAX),
+        CvarBuilder::new("r_light_scatter", settings.light_scatter_enabled),
+        CvarBuilder::new("r_draw_distance", settings.draw_distance.unwrap_or(0.0))
+            .with_description("Max distance from camera to meshes, zero means no limit")
+            .with_range(0.0, f64::MAX),
+    ];
+    for cvar in cvars {
+        // Registry is fresh, names are unique.
+        let _ = registry.register(cvar);
+    }
+}
+
+/// Sets values of renderer cvars from given settings, used when settings are changed
+/// directly, for example by config.
+pub(in crate::engine) fn sync_renderer_cvars(registry: &mut CvarRegistry, settings: &QualitySettings) {
+    let values: [(&str, CvarValue); 10] = [
+        ("r_shadow_distance", settings.spot_shadows_distance.max(settings.point_shadows_distance).into()),
+        ("r_shadows_fade_distance", settings.shadows_fade_distance.into()),
+        ("r_point_shadows", settings.point_shadows_enabled.into()),
+        ("r_spot_shadows", settings.spot_shadows_enabled.into()),
+        ("r_ssao", settings.use_ssao.into()),
+        ("r_ssao_radius", settings.ssao_radius.into()),
+        ("r_ssr", settings.use_ssr.into()),
+        ("r_ssr_max_distance", settings.ssr_max_distance.into()),
+        ("r_light_scatter", settings.light_scatter_enabled.into()),
+        ("r_draw_distance", settings.draw_distance.unwrap_or(0.0).into()),
+    ];
+    for (name, value) in values.iter() {
+        let _ = registry.set(name, value.clone());
+    }
+}
+
+/// Writes values of renderer cvars to given settings.
+pub(in crate::engine) fn apply_renderer_cvars(registry: &CvarRegistry, settings: &mut QualitySettings) {
+    let float = |name: &str, target: &mut f32| {
+        if let Some(value) = registry.get_float(name) {
+            *target = value as f32;
+        }
+    };
+    let flag = |name: &str, target: &mut bool| {
+        if let Some(value) = registry.get_bool(name) {
+            *target = value;
+        }
+    };
+    float("r_shadow_distance", &mut settings.point_shadows_distance);
+    float("r_shadow_distance", &mut settings.spot_shadows_distance);
+    float("r_shadows_fade_distance", &mut settings.shadows_fade_distance);
+    flag("r_point_shadows", &mut settings.point_shadows_enabled);
+    flag("r_spot_shadows", &mut settings.spot_shadows_enabled);
+    flag("r_ssao", &mut settings.use_ssao);
+    float("r_ssao_radius", &mut settings.ssao_radius);
+    flag("r_ssr", &mut settings.use_ssr);
+    float("r_ssr_max_distance", &mut settings.ssr_max_distance);
+    flag("r_light_scatter", &mut settings.light_scatter_enabled);
+    let mut draw_distance = settings.draw_distance.unwrap_or(0.0);
+    float("r_draw_distance", &mut draw_distance);
+    settings.draw_distance = if draw_distance > 0.0 { Some(draw_distance) } else { None };
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, rc::Rc};
+    use crate::engine::cvar::{CvarBuilder, CvarRegistry, CvarValue};
+
+    #[test]
+    fn test_cvar_registry() {
+        let changes = Rc::new(Cell::new(0));
+        let mut registry = CvarRegistry::new();
+        let counter = changes.clone();
+        registry.register(CvarBuilder::new("g_speed", 4.0)
+            .with_range(0.0, 10.0)
+            .with_callback(move |_| counter.set(counter.get() + 1)))
+            .unwrap();
+        registry.register(CvarBuilder::new("g_god_mode", false)).unwrap();
+        assert!(registry.register(CvarBuilder::new("g_speed", 1.0)).is_err());
+
+        registry.execute("g_speed 25").unwrap();
+        assert_eq!(registry.get_float("g_speed"), Some(10.0));
+        registry.set("g_speed", 10).unwrap();
+        assert_eq!(changes.get(), 1);
+
+        registry.load_from_str("# comment\ng_god_mode = on\n").unwrap();
+        assert_eq!(registry.get_bool("g_god_mode"), Some(true));
+        assert!(registry.execute("g_god_mode maybe").is_err());
+        assert!(registry.load_from_str("g_unknown = 1").is_err());
+
+        let text = registry.to_text();
+        registry.reset_all();
+        assert_eq!(registry.get("g_speed"), Some(&CvarValue::Float(4.0)));
+        registry.load_from_str(&text).unwrap();
+        assert_eq!(registry.get_float("g_speed"), Some(10.0));
+        assert_eq!(registry.complete("g_"), vec!["g_god_mode", "g_speed"]);
+    }
+}
@@ -0,0 +1,143 @@
+//! Contains everything needed to run engine in deterministic mode.
+//!
+//! Lockstep multiplayer requires that every peer produces exactly the same simulation from
+//! the same input. Variable frame time is the main source of divergence - physics and
+//! animations accumulate floating point numbers in different order if peers have different
+//! frame rate. In deterministic mode engine splits incoming time delta into steps of fixed
+//! length and updates scenes only by whole steps, so sequence of floating point operations
+//! is the same on every peer. Global random number generator must be seeded with the same
+//! seed on every peer too, see `utils::random`.
+//! Game logic which affects simulation must run by the same steps, use
+//! `Engine::update_with` and put such logic to `UpdateHandler::fixed_update`.
+//!
+//! State hash can be calculated by `Engine::state_hash` or `Scene::state_hash` and exchanged
+//! between peers to detect desync as early as possible. `Scene::physics_state_hash` covers
+//! only bound rigid bodies, it is handy to check that physics state survives save and load.
+
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Settings of deterministic update mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeterminismSettings {
+    /// Length of single simulation step in seconds.
+    pub fixed_step: f32,
+    /// Maximum amount of steps performed per one `Engine::update` call. Prevents "spiral of
+    /// death" when simulation can't keep up with real time.
+    pub max_steps_per_update: u32,
+    /// Seed for global random number generator.
+    pub seed: u64,
+}
+
+impl Default for DeterminismSettings {
+    fn default() -> Self {
+        Self {
+            fixed_step: 1.0 / 60.0,
+            max_steps_per_update: 8,
+            seed: 0,
+        }
+    }
+}
+
+/// Time accumulator which converts variable time deltas to fixed amount of steps.
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    settings: DeterminismSettings,
+    accumulator: f64,
+    step_count: u64,
+}
+
+impl FixedTimestep {
+    /// Creates new accumulator with given settings.
+    pub fn new(settings: DeterminismSettings) -> Self {
+        Self {
+            settings,
+            accumulator: 0.0,
+            step_count: 0,
+        }
+    }
+
+    /// Returns current settings.
+    pub fn settings(&self) -> &DeterminismSettings {
+        &self.settings
+    }
+
+    /// Accumulates given time delta and returns amount of fixed steps that should be performed.
+    /// Excess time which exceeds `max_steps_per_update` is dropped.
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        // Accumulate in double precision so leftover does not drift over long sessions.
+        self.accumulator += f64::from(dt);
+        let step = f64::from(self.settings.fixed_step);
+        let mut steps = 0;
+        while self.accumulator >= step && steps < self.settings.max_steps_per_update {
+            self.accumulator -= step;
+            steps += 1;
+        }
+        if steps == self.settings.max_steps_per_update {
+            self.accumulator = self.accumulator.min(step);
+        }
+        self.step_count += u64::from(steps);
+        steps
+    }
+
+    /// Returns fraction of step which was not simulated yet, in [0; 1] range.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / f64::from(self.settings.fixed_step)).min(1.0) as f32
+    }
+
+    /// Returns total amount of performed steps. Peers can compare state hashes only for the
+    /// same step number.
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+}
+
+/// FNV-1a hasher with fixed output. Unlike `std::collections::hash_map::DefaultHasher` its
+/// algorithm is guaranteed to be the same between builds, so hashes can be sent over network.
+#[derive(Copy, Clone, Debug)]
+pub struct StateHasher {
+    hash: u64,
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self {
+            hash: FNV_OFFSET_BASIS
+        }
+    }
+}
+
+impl StateHasher {
+    /// Creates new hasher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes bit pattern of floating point number. Bit pattern is used instead of value so
+    /// any difference, even in last bit of mantissa, will change the hash.
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits())
+    }
+
+    /// Writes bit patterns of slice of floating point numbers.
+    pub fn write_f32_slice(&mut self, values: &[f32]) {
+        for value in values {
+            self.write_f32(*value)
+        }
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= u64::from(*byte);
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
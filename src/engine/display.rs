@@ -0,0 +1,180 @@
+//! Monitors, display modes and switching between windowed and full screen modes.
+//!
+//! Options menus list monitors and resolutions supported by them and let player pick one.
+//! `Engine::monitors` returns description of every monitor with its display modes,
+//! `Engine::set_window_mode` switches window to chosen mode. Exclusive full screen mode
+//! is not guaranteed to work everywhere (driver may reject mode, monitor may be unplugged
+//! between enumeration and switch), so switching falls back to closest mode with the same
+//! resolution, then to borderless full screen on the same monitor, and returns mode which
+//! was actually applied:
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::{Engine, display::WindowMode},
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn switch_to_best_mode(engine: &mut Engine<(), StubNode>) {
+//!     let monitor = engine.current_monitor().unwrap();
+//!     if let Some(mode) = monitor.modes.iter().max_by_key(|mode| (mode.width * mode.height, mode.refresh_rate)) {
+//!         let applied = engine.set_window_mode(WindowMode::Exclusive { monitor: monitor.index, mode: *mode }).unwrap();
+//!         if applied != (WindowMode::Exclusive { monitor: monitor.index, mode: *mode }) {
+//!             println!("Mode {} is not supported, using {:?}", mode, applied);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Windowing library does not report current display mode of monitor, so refresh rate of
+//! monitor is estimated as highest refresh rate of modes with current resolution of monitor.
+//!
+//! Scenes are rendered at physical resolution of window, user interface is scaled by DPI
+//! scale factor of monitor, so it has the same size on high-DPI displays, see
+//! `Engine::set_ui_scale`.
+
+use std::fmt::{self, Display, Formatter};
+use crate::{
+    monitor::{MonitorHandle, VideoMode},
+    dpi::{PhysicalPosition, PhysicalSize},
+};
+
+/// Resolution, color depth and refresh rate of exclusive full screen mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per pixel.
+    pub bit_depth: u16,
+    /// Refresh rate in Hz.
+    pub refresh_rate: u16,
+}
+
+impl DisplayMode {
+    pub(in crate::engine) fn from_video_mode(mode: &VideoMode) -> Self {
+        Self {
+            width: mode.size().width,
+            height: mode.size().height,
+            bit_depth: mode.bit_depth(),
+            refresh_rate: mode.refresh_rate(),
+        }
+    }
+}
+
+impl Display for DisplayMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} @ {} Hz", self.width, self.height, self.refresh_rate)
+    }
+}
+
+/// Description of connected monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorInfo {
+    /// Index of monitor in list returned by `Engine::monitors`, used to refer to monitor
+    /// in `WindowMode`.
+    pub index: usize,
+    pub name: Option<String>,
+    /// Position of top left corner of monitor on virtual desktop, in pixels.
+    pub position: PhysicalPosition<i32>,
+    /// Current resolution of monitor in pixels.
+    pub size: PhysicalSize<u32>,
+    /// DPI scale factor of monitor, 1.0 is 96 DPI.
+    pub scale_factor: f64,
+    /// Estimated current refresh rate in Hz, see module docs. `None` if monitor reports no
+    /// modes with its current resolution.
+    pub refresh_rate: Option<u16>,
+    /// Supported exclusive full screen modes, sorted by resolution and then by refresh rate.
+    pub modes: Vec<DisplayMode>,
+    /// True if window of engine is (mostly) on this monitor.
+    pub is_current: bool,
+}
+
+impl MonitorInfo {
+    pub(in crate::engine) fn new(index: usize, handle: &MonitorHandle, is_current: bool) -> Self {
+        let mut modes = handle.video_modes()
+            .map(|mode| DisplayMode::from_video_mode(&mode))
+            .collect::<Vec<_>>();
+        modes.sort_by_key(|mode| (mode.width, mode.height, mode.refresh_rate, mode.bit_depth));
+        modes.dedup();
+        let size = handle.size();
+        Self {
+            index,
+            name: handle.name(),
+            position: handle.position(),
+            size,
+            scale_factor: handle.scale_factor(),
+            refresh_rate: estimate_refresh_rate(&modes, size.width, size.height),
+            modes,
+            is_current,
+        }
+    }
+}
+
+/// Mode of main window.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    /// Ordinary window with given size of client area.
+    Windowed {
+        width: u32,
+        height: u32,
+    },
+    /// Borderless window which covers whole monitor, resolution of monitor is not changed.
+    Borderless {
+        monitor: usize,
+    },
+    /// Exclusive full screen with given display mode.
+    Exclusive {
+        monitor: usize,
+        mode: DisplayMode,
+    },
+}
+
+/// Returns highest refresh rate of modes with given resolution.
+pub(in crate::engine) fn estimate_refresh_rate(modes: &[DisplayMode], width: u32, height: u32) -> Option<u16> {
+    modes.iter()
+        .filter(|mode| mode.width == width && mode.height == height)
+        .map(|mode| mode.refresh_rate)
+        .max()
+}
+
+/// Returns index of supported mode which is the best replacement of requested mode: the
+/// same mode, or mode with the same resolution and closest refresh rate (highest color
+/// depth wins ties). `None` if resolution is not supported at all.
+pub(in crate::engine) fn closest_mode(modes: &[DisplayMode], requested: DisplayMode) -> Option<usize> {
+    if let Some(index) = modes.iter().position(|mode| *mode == requested) {
+        return Some(index);
+    }
+    modes.iter()
+        .enumerate()
+        .filter(|(_, mode)| mode.width == requested.width && mode.height == requested.height)
+        .min_by_key(|(_, mode)| {
+            let rate_distance = (i32::from(mode.refresh_rate) - i32::from(requested.refresh_rate)).abs();
+            (rate_distance, std::cmp::Reverse(mode.bit_depth))
+        })
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::display::{DisplayMode, closest_mode, estimate_refresh_rate};
+
+    fn mode(width: u32, height: u32, bit_depth: u16, refresh_rate: u16) -> DisplayMode {
+        DisplayMode { width, height, bit_depth, refresh_rate }
+    }
+
+    #[test]
+    fn test_closest_mode() {
+        let modes = [
+            mode(1280, 720, 32, 60),
+            mode(1920, 1080, 24, 60),
+            mode(1920, 1080, 32, 60),
+            mode(1920, 1080, 32, 144),
+        ];
+        assert_eq!(closest_mode(&modes, mode(1920, 1080, 24, 60)), Some(1));
+        assert_eq!(closest_mode(&modes, mode(1920, 1080, 32, 120)), Some(3));
+        assert_eq!(closest_mode(&modes, mode(1920, 1080, 16, 59)), Some(2));
+        assert_eq!(closest_mode(&modes, mode(2560, 1440, 32, 60)), None);
+
+        assert_eq!(estimate_refresh_rate(&modes, 1920, 1080), Some(144));
+        assert_eq!(estimate_refresh_rate(&modes, 800, 600), None);
+    }
+}
@@ -0,0 +1,256 @@
+//! Asset manifest - sizes and hashes of data files for verification of shipped builds.
+//!
+//! Corrupted or tampered data file usually does not fail with a clear error: a truncated
+//! texture loads as garbage, a broken model panics somewhere deep inside of loader. Manifest
+//! lists size and hash of every data file at the moment of packaging the game, resource
+//! manager checks files against it before loading and refuses to load file which does not
+//! match, with a message that names the file.
+//!
+//! Manifest is generated once by build scripts of the game and shipped together with data:
+//!
+//! ```no_run
+//! use rg3d::engine::manifest::AssetManifest;
+//!
+//! let manifest = AssetManifest::generate("data").unwrap();
+//! manifest.save("data.manifest").unwrap();
+//! ```
+//!
+//! On start game loads manifest and gives it to resource manager, optionally checking all
+//! files at once to report problems before player enters a level:
+//!
+//! ```no_run
+//! use rg3d::engine::{manifest::AssetManifest, resource_manager::ResourceManager};
+//!
+//! fn setup(resource_manager: &mut ResourceManager) {
+//!     let manifest = AssetManifest::load("data.manifest").unwrap();
+//!     for error in manifest.verify_all() {
+//!         eprintln!("{}", error);
+//!     }
+//!     resource_manager.set_manifest(Some(manifest));
+//! }
+//! ```
+//!
+//! Files which are not listed in manifest are loaded without checks, so mods and user
+//! content keep working. Hash is 64-bit FNV-1a, it detects damage and casual editing of
+//! files, but it is not a cryptographic signature. Resources are not checked on hot reload
+//! since files are expected to change while developing.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    hash::Hasher,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+use crate::engine::determinism::StateHasher;
+
+/// Size and hash of a file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub hash: u64,
+}
+
+/// Reason why file does not pass verification.
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(PathBuf, io::Error),
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    HashMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    /// Line of manifest file can't be parsed.
+    Malformed {
+        line: usize,
+        text: String,
+    },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(path, e) => write!(f, "Unable to read {}: {}", path.display(), e),
+            ManifestError::SizeMismatch { path, expected, actual } =>
+                write!(f, "File {} is corrupted: size is {} bytes, expected {} bytes", path.display(), actual, expected),
+            ManifestError::HashMismatch { path, expected, actual } =>
+                write!(f, "File {} is corrupted: hash is {:016x}, expected {:016x}", path.display(), actual, expected),
+            ManifestError::Malformed { line, text } =>
+                write!(f, "Malformed line {} of manifest: {}", line, text),
+        }
+    }
+}
+
+/// Key of file in manifest: path with forward slashes and without leading `./`.
+fn key_of(path: &Path) -> String {
+    let key = path.to_string_lossy().replace('\\', "/");
+    key.trim_start_matches("./").to_owned()
+}
+
+/// Reads whole file and returns its size and hash.
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<ManifestEntry> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = StateHasher::new();
+    let mut size = 0u64;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.write(&buffer[..count]);
+        size += count as u64;
+    }
+    Ok(ManifestEntry { size, hash: hasher.finish() })
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct AssetManifest {
+    entries: HashMap<String, ManifestEntry>,
+}
+
+impl AssetManifest {
+    /// Lists every file in given directory and its subdirectories. Paths of files start with
+    /// given directory, so it must be the same path which is used to request resources, for
+    /// example `data` for `data/models/tree.fbx`.
+    pub fn generate<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut manifest = Self::default();
+        let mut stack = vec![dir.as_ref().to_owned()];
+        while let Some(dir) = stack.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else {
+                    manifest.add_file(&path)?;
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Adds file to manifest or updates its entry.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let entry = hash_file(path.as_ref())?;
+        self.entries.insert(key_of(path.as_ref()), entry);
+        Ok(())
+    }
+
+    /// Returns entry of file, if file is listed.
+    pub fn entry<P: AsRef<Path>>(&self, path: P) -> Option<ManifestEntry> {
+        self.entries.get(&key_of(path.as_ref())).cloned()
+    }
+
+    /// Returns amount of files in manifest.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks file against manifest. Files which are not listed pass, size is checked before
+    /// hash so truncated files are detected without reading them.
+    pub fn verify<P: AsRef<Path>>(&self, path: P) -> Result<(), ManifestError> {
+        let path = path.as_ref();
+        let expected = match self.entry(path) {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+        let size = std::fs::metadata(path)
+            .map_err(|e| ManifestError::Io(path.to_owned(), e))?
+            .len();
+        if size != expected.size {
+            return Err(ManifestError::SizeMismatch { path: path.to_owned(), expected: expected.size, actual: size });
+        }
+        let actual = hash_file(path).map_err(|e| ManifestError::Io(path.to_owned(), e))?;
+        if actual.hash != expected.hash {
+            return Err(ManifestError::HashMismatch { path: path.to_owned(), expected: expected.hash, actual: actual.hash });
+        }
+        Ok(())
+    }
+
+    /// Checks every file of manifest, missing files are reported as I/O errors.
+    pub fn verify_all(&self) -> Vec<ManifestError> {
+        let mut keys = self.entries.keys().collect::<Vec<_>>();
+        keys.sort();
+        keys.into_iter()
+            .filter_map(|key| self.verify(Path::new(key)).err())
+            .collect()
+    }
+
+    /// Writes manifest as text, one file per line: hash, size and path. Lines are sorted by
+    /// path, so manifests of two builds can be compared with any diff tool.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut keys = self.entries.keys().collect::<Vec<_>>();
+        keys.sort();
+        let mut file = io::BufWriter::new(File::create(path)?);
+        for key in keys {
+            let entry = &self.entries[key];
+            writeln!(file, "{:016x} {} {}", entry.hash, entry.size, key)?;
+        }
+        file.flush()
+    }
+
+    /// Reads manifest written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ManifestError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| ManifestError::Io(path.to_owned(), e))?;
+        Self::parse(BufReader::new(file)).map_err(|e| match e {
+            ManifestError::Io(_, e) => ManifestError::Io(path.to_owned(), e),
+            e => e,
+        })
+    }
+
+    fn parse<R: BufRead>(reader: R) -> Result<Self, ManifestError> {
+        let mut manifest = Self::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| ManifestError::Io(PathBuf::new(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Path is the rest of line, it may contain spaces.
+            let mut parts = line.splitn(3, ' ');
+            let hash = parts.next().and_then(|hash| u64::from_str_radix(hash, 16).ok());
+            let size = parts.next().and_then(|size| size.parse::<u64>().ok());
+            match (hash, size, parts.next()) {
+                (Some(hash), Some(size), Some(file)) if !file.is_empty() => {
+                    manifest.entries.insert(file.to_owned(), ManifestEntry { size, hash });
+                }
+                _ => return Err(ManifestError::Malformed { line: index + 1, text: line }),
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use crate::engine::manifest::{AssetManifest, ManifestEntry, ManifestError};
+
+    #[test]
+    fn test_manifest_parse() {
+        let text = "00000000000000ff 12 data/models/tree.fbx\n\n0000000000000001 3 data/my file.png\n";
+        let manifest = AssetManifest::parse(Cursor::new(text)).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest.entry("./data/models/tree.fbx"), Some(ManifestEntry { size: 12, hash: 0xff }));
+        assert_eq!(manifest.entry("data\\my file.png"), Some(ManifestEntry { size: 3, hash: 1 }));
+
+        match AssetManifest::parse(Cursor::new("zz 12 data/a.png")) {
+            Err(ManifestError::Malformed { line: 1, .. }) => (),
+            _ => panic!("malformed line must be reported"),
+        }
+
+        // Unlisted files pass.
+        assert!(manifest.verify("data/unlisted.png").is_ok());
+    }
+}
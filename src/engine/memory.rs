@@ -0,0 +1,134 @@
+//! Approximate memory usage reporting.
+//!
+//! Resources in the engine are shared between scenes, model resources and renderer caches
+//! using `Arc`s and handles, so it is not always obvious what holds memory. Report gathered
+//! by `Engine::memory_usage` shows how much memory is held by each subsystem, shared data
+//! is counted only once. Numbers are estimations: they include payload data only (pixels,
+//! vertices, key frames), not allocator overhead or small bookkeeping structures.
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    sync::{Arc, Mutex},
+};
+use crate::{
+    scene::{
+        Scene,
+        node::Node,
+    },
+    renderer::surface::SurfaceSharedData,
+};
+
+/// Occupancy of a pool. Pools never shrink, so large capacity with small amount of alive
+/// objects means that memory was used once and is still reserved.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolUsage {
+    /// Total amount of slots in pools.
+    pub capacity: usize,
+    /// Amount of alive objects in pools.
+    pub alive: usize,
+}
+
+impl PoolUsage {
+    fn add(&mut self, (capacity, alive): (usize, usize)) {
+        self.capacity += capacity;
+        self.alive += alive;
+    }
+}
+
+/// See module docs. All sizes are in bytes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Vertices and triangles of surfaces of scenes and model resources.
+    pub surface_data: usize,
+    /// Pixels of textures loaded by resource manager.
+    pub textures_cpu: usize,
+    /// Textures uploaded to GPU.
+    pub textures_gpu: usize,
+    /// Geometry of surfaces uploaded to GPU.
+    pub geometry_gpu: usize,
+    /// Key frames of animations of scenes and model resources.
+    pub animations: usize,
+    /// Amount of textures in resource manager.
+    pub texture_count: usize,
+    /// Amount of models in resource manager.
+    pub model_count: usize,
+    /// Occupancy of node pools of all scenes.
+    pub node_pools: PoolUsage,
+    /// Occupancy of animation pools of all scenes.
+    pub animation_pools: PoolUsage,
+}
+
+impl MemoryUsage {
+    /// Returns total amount of RAM in bytes.
+    pub fn total_cpu(&self) -> usize {
+        self.surface_data + self.textures_cpu + self.animations
+    }
+
+    /// Returns total amount of video memory in bytes.
+    pub fn total_gpu(&self) -> usize {
+        self.textures_gpu + self.geometry_gpu
+    }
+}
+
+impl Display for MemoryUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        const MB: f32 = 1024.0 * 1024.0;
+        writeln!(f, "Surface data: {:.2} Mb", self.surface_data as f32 / MB)?;
+        writeln!(f, "Textures (CPU): {:.2} Mb in {} textures", self.textures_cpu as f32 / MB, self.texture_count)?;
+        writeln!(f, "Textures (GPU): {:.2} Mb", self.textures_gpu as f32 / MB)?;
+        writeln!(f, "Geometry (GPU): {:.2} Mb", self.geometry_gpu as f32 / MB)?;
+        writeln!(f, "Animations: {:.2} Mb", self.animations as f32 / MB)?;
+        writeln!(f, "Models: {}", self.model_count)?;
+        writeln!(f, "Nodes: {} alive of {} slots", self.node_pools.alive, self.node_pools.capacity)?;
+        write!(f, "Animations: {} alive of {} slots", self.animation_pools.alive, self.animation_pools.capacity)
+    }
+}
+
+/// Accumulates usage of scenes, shared surface data is counted once.
+#[derive(Default)]
+pub(in crate) struct SceneMemoryCounter {
+    visited_surfaces: HashSet<usize>,
+}
+
+impl SceneMemoryCounter {
+    fn count_surface_data(&mut self, data: &Arc<Mutex<SurfaceSharedData>>, usage: &mut MemoryUsage) {
+        let key = (&**data as *const _) as usize;
+        if self.visited_surfaces.insert(key) {
+            usage.surface_data += data.lock().unwrap().memory_usage();
+        }
+    }
+
+    /// Adds usage of scene, `count_pools` must be false for internal scenes of resources.
+    pub(in crate) fn count_scene(&mut self, scene: &Scene, count_pools: bool, usage: &mut MemoryUsage) {
+        for node in scene.graph.linear_iter() {
+            match node {
+                Node::Mesh(mesh) => {
+                    for surface in mesh.surfaces() {
+                        self.count_surface_data(&surface.get_data(), usage);
+                    }
+                }
+                Node::Scatter(scatter) => {
+                    for surface in scatter.surfaces() {
+                        self.count_surface_data(&surface.get_data(), usage);
+                    }
+                }
+                Node::Crowd(crowd) => {
+                    for surface in crowd.surfaces() {
+                        self.count_surface_data(&surface.get_data(), usage);
+                    }
+                }
+                _ => ()
+            }
+        }
+
+        for animation in scene.animations.iter() {
+            usage.animations += animation.memory_usage();
+        }
+
+        if count_pools {
+            usage.node_pools.add(scene.graph.pool_usage());
+            usage.animation_pools.add(scene.animations.pool_usage());
+        }
+    }
+}
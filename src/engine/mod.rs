@@ -1,53 +1,244 @@
 pub mod resource_manager;
 pub mod error;
+pub mod determinism;
+pub mod memory;
+pub mod config;
+pub mod task;
+pub mod crash;
+pub mod cvar;
+pub mod watchdog;
+pub mod redraw;
+pub mod secondary_window;
+pub mod counters;
+pub mod manifest;
+pub mod display;
+#[cfg(feature = "alloc_audit")]
+pub mod allocations;
 
 use crate::{
     core::{
-        math::vec2::Vec2,
+        math::{
+            vec2::Vec2,
+            ray::Ray,
+        },
+        pool::Handle,
         visitor::{
             Visitor,
             VisitResult,
             Visit,
         },
     },
-    sound::context::Context,
+    sound::{
+        context::{self as sound_context, Context},
+        renderer::{
+            Renderer as SoundRenderer,
+            hrtf::{HrtfRenderer, HrirSphere},
+        },
+    },
     engine::{
         resource_manager::ResourceManager,
         error::EngineError,
+        determinism::{
+            DeterminismSettings,
+            FixedTimestep,
+            StateHasher,
+        },
+        memory::{
+            MemoryUsage,
+            SceneMemoryCounter,
+        },
+        config::EngineConfig,
+        task::TaskPool,
+        crash::{self, Autosave, AutosaveSettings},
+        cvar::{self, CvarRegistry},
+        watchdog::{Watchdog, WatchdogSettings, LongFrameReport},
+        redraw::{RedrawTracker, RenderMode},
+        secondary_window::{self, SecondaryWindow},
+        counters::EngineCounters,
+        display::{self, DisplayMode, MonitorInfo, WindowMode},
+    },
+    gui::{
+        UserInterface,
+        message::OsEvent,
     },
-    gui::UserInterface,
     renderer::{
         Renderer,
         error::RendererError,
+        forget_lost_objects,
     },
+    resource::string_table::Localization,
+    monitor::MonitorHandle,
     window::{
         WindowBuilder,
         Window,
+        WindowId,
+        Fullscreen,
+    },
+    dpi::PhysicalSize,
+    scene::{
+        SceneContainer,
+        Scene,
+        node::Node,
     },
-    scene::SceneContainer,
+    utils::{
+        random,
+        translate_event,
+        log::Log,
+    },
+    event::WindowEvent,
     PossiblyCurrent,
     GlRequest,
     GlProfile,
+    Robustness,
     WindowedContext,
     NotCurrent,
     Api,
-    event_loop::EventLoop,
+    event_loop::{EventLoop, EventLoopWindowTarget},
     gui::Control,
 };
 use std::{
+    panic::{self, AssertUnwindSafe},
+    process,
     sync::{Arc, Mutex},
     time,
     time::Duration,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    ffi::c_void,
 };
 
 pub struct Engine<M: 'static, C: 'static + Control<M, C>> {
-    context: glutin::WindowedContext<PossiblyCurrent>,
+    /// Context of main window, taken out only while it is being made current. `None` if
+    /// engine is embedded into context of host application.
+    context: Option<glutin::WindowedContext<PossiblyCurrent>>,
     pub renderer: Renderer,
     pub user_interface: UserInterface<M, C>,
     pub sound_context: Arc<Mutex<Context>>,
     pub resource_manager: Arc<Mutex<ResourceManager>>,
     pub scenes: SceneContainer,
     pub ui_time: Duration,
+    /// String tables and current locale, see `resource::string_table` module docs.
+    pub localization: Localization,
+    /// Worker threads shared by engine and game code, see `engine::task` module docs.
+    pub task_pool: Arc<TaskPool>,
+    /// Console variables of engine and game, see `engine::cvar` module docs.
+    pub cvars: CvarRegistry,
+    cvars_revision: u64,
+    fixed_timestep: Option<FixedTimestep>,
+    cursor_position: Vec2,
+    hrir_path: Option<PathBuf>,
+    config: EngineConfig,
+    autosave: Autosave,
+    emergency_save_path: Option<PathBuf>,
+    watchdog: Option<Watchdog>,
+    redraw: RedrawTracker,
+    secondary_windows: Vec<SecondaryWindow>,
+    /// Counters being gathered by current update.
+    counters: EngineCounters,
+    /// Counters of last finished update.
+    last_counters: EngineCounters,
+    /// Scale of user interface set by game, on top of DPI scale factor of window.
+    ui_scale: f32,
+    /// Builder of main window, used to re-create window when context is lost.
+    window_builder: Option<WindowBuilder>,
+    /// Set when renderer reports that context is lost, see `recover_context`.
+    context_lost: bool,
+    #[cfg(feature = "alloc_audit")]
+    allocation_audit: allocations::AllocationAudit,
+}
+
+/// Start of a phase of frame, see `Engine::record_time`.
+struct PhaseStart {
+    instant: time::Instant,
+    #[cfg(feature = "alloc_audit")]
+    allocations: allocations::AllocationCount,
+}
+
+impl PhaseStart {
+    fn now() -> Self {
+        Self {
+            instant: time::Instant::now(),
+            #[cfg(feature = "alloc_audit")]
+            allocations: allocations::AllocationCount::current(),
+        }
+    }
+}
+
+/// Game logic hooks called by [`Engine::update_with`], both methods do nothing by default.
+///
+/// Logic which drives simulation (movement, forces, AI decisions) goes to `fixed_update`,
+/// it is called right before each update of scenes. With fixed time step (see
+/// [`Engine::enable_deterministic_mode`]) it is called zero or more times per frame with
+/// length of step, otherwise once per frame with frame time. Logic which only presents
+/// simulation (camera, UI, effects) goes to `frame_update`, it is called once per frame after
+/// every step and interpolation of physics and before update of sound and user interface.
+///
+/// Hooks may add and remove scenes: engine does not keep handles of scenes across hooks, so
+/// removed scene is simply not updated anymore, and a scene removed twice is ignored.
+///
+/// ```no_run
+/// use rg3d::{
+///     engine::{Engine, UpdateHandler},
+///     gui::node::StubNode,
+/// };
+///
+/// struct Game {
+///     ticks: u64,
+/// }
+///
+/// impl UpdateHandler<(), StubNode> for Game {
+///     fn fixed_update(&mut self, _engine: &mut Engine<(), StubNode>, _dt: f32) {
+///         self.ticks += 1;
+///     }
+///
+///     fn frame_update(&mut self, _engine: &mut Engine<(), StubNode>, _dt: f32, alpha: f32) {
+///         println!("tick {}, {} of next tick", self.ticks, alpha);
+///     }
+/// }
+///
+/// fn frame(engine: &mut Engine<(), StubNode>, game: &mut Game, dt: f32) {
+///     engine.update_with(dt, game);
+/// }
+/// ```
+pub trait UpdateHandler<M: 'static, C: 'static + Control<M, C>> {
+    /// Called before each update of scenes with time step of that update.
+    fn fixed_update(&mut self, _engine: &mut Engine<M, C>, _dt: f32) {}
+
+    /// Called once per frame with frame time. `alpha` is fraction of fixed step which is not
+    /// simulated yet (the same value is used to interpolate physics), it is always 1 with
+    /// variable time step.
+    fn frame_update(&mut self, _engine: &mut Engine<M, C>, _dt: f32, _alpha: f32) {}
+}
+
+impl<M: 'static, C: 'static + Control<M, C>> UpdateHandler<M, C> for () {}
+
+/// Creates context of main window and makes it current. Robustness is requested so driver
+/// reports resets of context, see `Engine::recover_context`.
+fn make_main_context(window_builder: WindowBuilder, window_target: &EventLoopWindowTarget<()>, vsync: bool)
+                     -> Result<WindowedContext<PossiblyCurrent>, EngineError> {
+    let context_wrapper: WindowedContext<NotCurrent> = glutin::ContextBuilder::new()
+        .with_vsync(vsync)
+        .with_gl_profile(GlProfile::Core)
+        .with_gl(GlRequest::Specific(Api::OpenGl, (3, 3)))
+        .with_gl_robustness(Robustness::TryRobustLoseContextOnReset)
+        .build_windowed(window_builder, window_target)?;
+
+    match unsafe { context_wrapper.make_current() } {
+        Ok(context) => Ok(context),
+        Err((_, e)) => Err(EngineError::from(e)),
+    }
+}
+
+fn make_sound_renderer(hrir_path: Option<&Path>) -> Result<SoundRenderer, EngineError> {
+    match hrir_path {
+        Some(path) => {
+            let sphere = HrirSphere::from_file(path, sound_context::SAMPLE_RATE)
+                .map_err(|e| EngineError::InternalError(
+                    format!("Unable to load HRIR sphere from {}! Reason: {:?}", path.display(), e)))?;
+            Ok(SoundRenderer::HrtfRenderer(HrtfRenderer::new(sphere)))
+        }
+        None => Ok(SoundRenderer::Default),
+    }
 }
 
 impl<M, C: 'static + Control<M, C>> Engine<M, C> {
@@ -55,6 +246,13 @@ impl<M, C: 'static + Control<M, C>> Engine<M, C> {
     ///
     /// Automatically creates all sub-systems (renderer, sound, ui, etc.).
     ///
+    /// `vsync` enables vertical synchronization, it can't be changed later without re-creating
+    /// engine. Use `Renderer::set_frame_rate_limit` to limit frame rate at runtime.
+    ///
+    /// All built-in shaders are compiled here, which may take a while on first launch. Use
+    /// `renderer::set_program_cache_directory` before creating engine to make subsequent
+    /// launches faster and `renderer::set_shader_compilation_callback` to track progress.
+    ///
     /// # Examples
     ///
     /// ```
@@ -67,72 +265,898 @@ impl<M, C: 'static + Control<M, C>> Engine<M, C> {
     /// let window_builder = WindowBuilder::new()
     ///     .with_title("Test")
     ///     .with_fullscreen(None);
-    /// let mut engine: Engine<(), StubNode> = Engine::new(window_builder, &evt).unwrap();
+    /// let mut engine: Engine<(), StubNode> = Engine::new(window_builder, &evt, true).unwrap();
     /// ```
     #[inline]
-    pub fn new(window_builder: WindowBuilder, events_loop: &EventLoop<()>) -> Result<Engine<M, C>, EngineError> {
-        let context_wrapper: WindowedContext<NotCurrent> = glutin::ContextBuilder::new()
-            .with_vsync(true)
-            .with_gl_profile(GlProfile::Core)
-            .with_gl(GlRequest::Specific(Api::OpenGl, (3, 3)))
-            .build_windowed(window_builder, events_loop)?;
+    pub fn new(window_builder: WindowBuilder, events_loop: &EventLoop<()>, vsync: bool) -> Result<Engine<M, C>, EngineError> {
+        let context = make_main_context(window_builder.clone(), events_loop, vsync)?;
+        let client_size = context.window().inner_size();
+        let renderer = Renderer::new(|symbol| context.get_proc_address(symbol) as *const _, client_size.into(), vsync)?;
+        let mut engine = Self::with_renderer(Some(context), renderer)?;
+        engine.window_builder = Some(window_builder);
+        Ok(engine)
+    }
 
-        let mut context = match unsafe { context_wrapper.make_current() } {
-            Ok(context) => context,
-            Err((_, e)) => return Err(EngineError::from(e)),
-        };
+    /// Creates engine which renders into OpenGL 3.3 core context created by host application,
+    /// for example into a widget of Qt or other UI toolkit, or into a window of application
+    /// which loads game as a plugin. Host owns window and event loop: it must make context
+    /// current before any call of engine, pass window events to `process_window_event`, call
+    /// `Renderer::set_frame_size` when view is resized, and swap buffers after `render`. If
+    /// host renders into its own frame buffer object, pass it to `Renderer::set_target_framebuffer`.
+    ///
+    /// `loader` returns address of OpenGL function by name, it is usually `get_proc_address`
+    /// of context of host. Renderer restores its GL state at the beginning of each frame,
+    /// so host may change state of context between frames, but renderer leaves context in
+    /// its own state after frame.
+    ///
+    /// Embedded engine has no window, `get_window` panics and secondary windows can't be
+    /// created, see `is_embedded`.
+    ///
+    /// ```no_run
+    /// use std::ffi::c_void;
+    /// use rg3d::{engine::Engine, gui::node::StubNode};
+    ///
+    /// fn embed(get_proc_address: fn(&str) -> *const c_void) -> Engine<(), StubNode> {
+    ///     Engine::from_external_context(get_proc_address, (800, 600)).unwrap()
+    /// }
+    /// ```
+    pub fn from_external_context<F>(loader: F, frame_size: (u32, u32)) -> Result<Engine<M, C>, EngineError>
+        where F: FnMut(&str) -> *const c_void {
+        let mut renderer = Renderer::new(loader, frame_size, false)?;
+        renderer.set_external_context();
+        Self::with_renderer(None, renderer)
+    }
 
-        let client_size = context.window().inner_size();
+    fn with_renderer(context: Option<WindowedContext<PossiblyCurrent>>, renderer: Renderer) -> Result<Engine<M, C>, EngineError> {
+        let task_pool = Arc::new(TaskPool::default());
+        let mut cvars = CvarRegistry::new();
+        cvar::register_renderer_cvars(&mut cvars, &renderer.get_quality_settings());
 
         Ok(Engine {
-            renderer: Renderer::new(&mut context, client_size.into())?,
-            resource_manager: Arc::new(Mutex::new(ResourceManager::new())),
+            renderer,
+            resource_manager: Arc::new(Mutex::new(ResourceManager::new(task_pool.clone()))),
             sound_context: Context::new()?,
             scenes: SceneContainer::new(),
             user_interface: UserInterface::new(),
             ui_time: Default::default(),
+            localization: Default::default(),
+            task_pool,
+            cvars_revision: cvars.revision(),
+            cvars,
+            fixed_timestep: None,
+            cursor_position: Vec2::ZERO,
+            hrir_path: None,
+            config: Default::default(),
+            autosave: Default::default(),
+            emergency_save_path: None,
+            watchdog: None,
+            redraw: Default::default(),
+            secondary_windows: Vec::new(),
+            counters: Default::default(),
+            last_counters: Default::default(),
+            ui_scale: 1.0,
+            window_builder: None,
+            context_lost: false,
+            #[cfg(feature = "alloc_audit")]
+            allocation_audit: Default::default(),
             context,
         })
     }
 
     /// Returns reference to main window.  Could be useful to set fullscreen mode, change
     /// size of window, its title, etc.
+    ///
+    /// # Panics
+    ///
+    /// Panics if engine is embedded into external context, it has no window.
     #[inline]
     pub fn get_window(&self) -> &Window {
-        self.context.window()
+        self.main_context().window()
+    }
+
+    fn main_context(&self) -> &WindowedContext<PossiblyCurrent> {
+        self.context.as_ref().expect("Engine is embedded into external context and has no window!")
+    }
+
+    /// Returns true if engine renders into context of host application, see
+    /// `from_external_context`.
+    pub fn is_embedded(&self) -> bool {
+        self.context.is_none()
+    }
+
+    /// Returns size of frame in pixels: size of client area of main window, or size set to
+    /// renderer if engine is embedded.
+    fn frame_size(&self) -> Vec2 {
+        match self.context.as_ref() {
+            Some(context) => {
+                let inner_size = context.window().inner_size();
+                Vec2::new(inner_size.width as f32, inner_size.height as f32)
+            }
+            None => {
+                let (width, height) = self.renderer.get_frame_size();
+                Vec2::new(width as f32, height as f32)
+            }
+        }
     }
 
     /// Performs single update tick with given time delta. Engine internally will perform update
     /// of all scenes, sub-systems, user interface, etc. Must be called in order to get engine
-    /// functioning.
+    /// functioning. Use `update_with` to run game logic in sync with steps of scenes.
     pub fn update(&mut self, dt: f32) {
-        let inner_size = self.context.window().inner_size();
-        let frame_size = Vec2::new(inner_size.width as f32, inner_size.height as f32);
+        self.update_with(dt, &mut ())
+    }
+
+    /// Same as `update`, but calls hooks of given handler in guaranteed order: for each
+    /// update of scenes `fixed_update` is called right before it, then `frame_update` is
+    /// called once. See [`UpdateHandler`] for more info.
+    pub fn update_with<H>(&mut self, dt: f32, handler: &mut H) where H: UpdateHandler<M, C> {
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.begin_frame();
+        }
+        #[cfg(feature = "alloc_audit")]
+        self.allocation_audit.begin_frame();
+
+        if self.emergency_save_path.is_some() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| self.update_internal(dt, handler)));
+            if result.is_err() {
+                self.emergency_abort();
+            }
+        } else {
+            self.update_internal(dt, handler);
+        }
+
+        if let Some(path) = self.autosave.advance(dt) {
+            let start = PhaseStart::now();
+            if let Err(e) = self.save_to_file(&path) {
+                Log::writeln(format!("Autosave to {} failed! Reason: {:?}", path.display(), e));
+            }
+            self.record_time("Autosave", start);
+        }
+    }
+
+    /// Adds time since start of phase to current frame of watchdog, if it is enabled, and
+    /// allocations made by phase to allocation audit.
+    fn record_time(&mut self, name: &'static str, start: PhaseStart) {
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.record(name, start.instant.elapsed());
+        }
+        #[cfg(feature = "alloc_audit")]
+        self.allocation_audit.record(name, start.allocations);
+    }
+
+    fn update_internal<H>(&mut self, dt: f32, handler: &mut H) where H: UpdateHandler<M, C> {
+        self.apply_cvars();
+
+        let frame_size = self.frame_size();
 
         // Resource manager might be locked by some other worker thread and it cannot be updated,
         // engine will try to update it in next frame. Resource update is just controls TTLs of
         // resource so it is not problem to defer update call.
+        let start = PhaseStart::now();
         if let Ok(mut resource_manager) = self.resource_manager.try_lock() {
             resource_manager.update(dt);
         }
+        self.record_time("Resources", start);
+
+        let alpha = if let Some(fixed_timestep) = self.fixed_timestep.as_mut() {
+            let step = fixed_timestep.settings().fixed_step;
+            let steps = fixed_timestep.advance(dt);
+            for _ in 0..steps {
+                self.fixed_step(frame_size, step, handler);
+            }
+            // Handler may switch time step mode.
+            let alpha = self.fixed_timestep.as_ref().map_or(1.0, |fixed_timestep| fixed_timestep.alpha());
+            for scene in self.scenes.iter_mut() {
+                scene.interpolate_physics(alpha);
+            }
+            alpha
+        } else {
+            self.fixed_step(frame_size, dt, handler);
+            1.0
+        };
+
+        let start = PhaseStart::now();
+        handler.frame_update(self, dt, alpha);
+        self.record_time("FrameUpdate", start);
+
+        let start = PhaseStart::now();
+        let mut sound_context = self.sound_context.lock().unwrap();
+        for scene in self.scenes.iter_mut() {
+            scene.sound_binder.update(&scene.graph, &mut sound_context, dt);
+            scene.sound_bank.update(&scene.graph, &scene.events, &mut sound_context, dt);
+        }
+        drop(sound_context);
+        self.record_time("Sound", start);
+
+        if let Some(day_night) = self.scenes.iter().filter_map(|scene| scene.day_night.as_ref()).next() {
+            self.renderer.set_ambient_color(day_night.ambient_color());
+        }
+
+        let start = PhaseStart::now();
+        let ui_scale = self.effective_ui_scale();
+        self.renderer.set_ui_scale(ui_scale);
+        self.user_interface.update(frame_size.scale(1.0 / ui_scale), dt);
+        self.ui_time = start.instant.elapsed();
+        self.record_time("UI", start);
+
+        self.last_counters = std::mem::take(&mut self.counters);
+    }
+
+    fn fixed_step<H>(&mut self, frame_size: Vec2, dt: f32, handler: &mut H) where H: UpdateHandler<M, C> {
+        let start = PhaseStart::now();
+        handler.fixed_update(self, dt);
+        self.record_time("FixedUpdate", start);
 
+        let start = PhaseStart::now();
         for scene in self.scenes.iter_mut() {
             scene.update(frame_size, dt);
+            self.counters.count_scene(scene);
         }
+        self.record_time("Scenes", start);
+    }
+
+    /// Switches sound to binaural rendering with head-related transfer function from given
+    /// HRIR sphere file, or back to plain stereo panning if `None`. Binaural rendering gives
+    /// accurate 3D positioning of sounds on headphones, but it costs more CPU time and sounds
+    /// worse on speakers, so it is usually exposed to players as an option. Can be switched
+    /// at any time, playing sources continue without interruption.
+    pub fn set_hrtf<P: AsRef<Path>>(&mut self, hrir_path: Option<P>) -> Result<(), EngineError> {
+        let renderer = make_sound_renderer(hrir_path.as_ref().map(|path| path.as_ref()))?;
+        self.sound_context.lock().unwrap().set_renderer(renderer);
+        self.hrir_path = hrir_path.map(|path| path.as_ref().to_owned());
+        Ok(())
+    }
+
+    /// Returns path of HRIR sphere used by sound, `None` if sound uses plain stereo panning.
+    pub fn hrir_path(&self) -> Option<&Path> {
+        self.hrir_path.as_deref()
+    }
+
+    /// Returns connected monitors with their display modes, empty if engine is embedded into
+    /// context of host application. See `display` module docs.
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        match self.context.as_ref() {
+            Some(context) => {
+                let window = context.window();
+                let current = window.current_monitor();
+                window.available_monitors()
+                    .enumerate()
+                    .map(|(index, monitor)| MonitorInfo::new(index, &monitor, monitor == current))
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns monitor on which window of engine is, `None` if engine is embedded.
+    pub fn current_monitor(&self) -> Option<MonitorInfo> {
+        self.monitors().into_iter().find(|monitor| monitor.is_current)
+    }
+
+    /// Returns DPI scale factor of window, 1.0 if engine is embedded.
+    pub fn scale_factor(&self) -> f64 {
+        self.context.as_ref().map_or(1.0, |context| context.window().scale_factor())
+    }
+
+    /// Sets scale of user interface on top of DPI scale factor of window, options menus
+    /// usually expose it as size of interface. User interface is laid out in logical units,
+    /// one unit is `scale factor * ui scale` pixels, so it has the same physical size on
+    /// displays with any pixel density. Scenes are always rendered at physical resolution.
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale.max(0.1);
+        self.redraw.request();
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
 
-        let time = time::Instant::now();
-        self.user_interface.update(frame_size, dt);
-        self.ui_time = time::Instant::now() - time;
+    /// Returns amount of pixels per unit of user interface: DPI scale factor of window
+    /// multiplied by scale set by `set_ui_scale`. Fonts of user interface should be loaded
+    /// with height multiplied by this value to stay sharp, otherwise glyphs are stretched.
+    pub fn effective_ui_scale(&self) -> f32 {
+        self.scale_factor() as f32 * self.ui_scale
+    }
+
+    /// Returns size of frame in logical units of user interface, see `set_ui_scale`.
+    pub fn logical_frame_size(&self) -> Vec2 {
+        self.frame_size().scale(1.0 / self.effective_ui_scale())
+    }
+
+    /// Switches main window to given mode and returns mode which was actually applied. If
+    /// monitor does not exist, current monitor is used; if exclusive mode is not supported
+    /// or can't be set, closest mode with the same resolution or borderless full screen is
+    /// used instead. See `display` module docs.
+    pub fn set_window_mode(&mut self, mode: WindowMode) -> Result<WindowMode, EngineError> {
+        let window = match self.context.as_ref() {
+            Some(context) => context.window(),
+            None => return Err(EngineError::InternalError(
+                "Engine is embedded into external context and has no window!".to_owned())),
+        };
+
+        let monitors = window.available_monitors().collect::<Vec<_>>();
+        let current = window.current_monitor();
+        let find_monitor = |index: usize| match monitors.get(index) {
+            Some(monitor) => (index, monitor.clone()),
+            None => {
+                Log::writeln(format!("Monitor {} does not exist, using current monitor.", index));
+                (monitors.iter().position(|monitor| *monitor == current).unwrap_or(0), current.clone())
+            }
+        };
+        let borderless = |index: usize, monitor: MonitorHandle| {
+            window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+            WindowMode::Borderless { monitor: index }
+        };
+
+        let applied = match mode {
+            WindowMode::Windowed { width, height } => {
+                window.set_fullscreen(None);
+                window.set_inner_size(PhysicalSize::new(width, height));
+                mode
+            }
+            WindowMode::Borderless { monitor } => {
+                let (index, monitor) = find_monitor(monitor);
+                borderless(index, monitor)
+            }
+            WindowMode::Exclusive { monitor, mode: requested } => {
+                let (index, monitor) = find_monitor(monitor);
+                let video_modes = monitor.video_modes().collect::<Vec<_>>();
+                let modes = video_modes.iter().map(DisplayMode::from_video_mode).collect::<Vec<_>>();
+                match display::closest_mode(&modes, requested) {
+                    Some(i) => {
+                        window.set_fullscreen(Some(Fullscreen::Exclusive(video_modes[i].clone())));
+                        if window.fullscreen().is_some() {
+                            WindowMode::Exclusive { monitor: index, mode: modes[i] }
+                        } else {
+                            Log::writeln(format!("Unable to set display mode {}, using borderless full screen.", modes[i]));
+                            borderless(index, monitor)
+                        }
+                    }
+                    None => {
+                        Log::writeln(format!("Display mode {} is not supported, using borderless full screen.", requested));
+                        borderless(index, monitor)
+                    }
+                }
+            }
+        };
+
+        self.redraw.request();
+        Ok(applied)
+    }
+
+    /// Applies settings of window, renderer, audio and input from given config. If any part
+    /// of config can't be applied, error is returned and engine keeps previous settings.
+    /// Vertical synchronization can't be changed at runtime, it is used only by `Engine::new`.
+    /// See `config` module docs.
+    pub fn apply_config(&mut self, config: &EngineConfig) -> Result<(), EngineError> {
+        // Fallible parts go first, so failure leaves engine untouched.
+        let sound_renderer = make_sound_renderer(config.audio.hrir_path.as_deref())?;
+        let old_quality = self.renderer.get_quality_settings();
+        if let Err(e) = self.renderer.set_quality_settings(&config.renderer) {
+            // Previous settings were applied successfully, so they can be restored.
+            let _ = self.renderer.set_quality_settings(&old_quality);
+            return Err(e.into());
+        }
+
+        // Window of embedded engine is controlled by host.
+        if let Some(context) = self.context.as_ref() {
+            let window = context.window();
+            if config.window.fullscreen {
+                window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+            } else {
+                window.set_fullscreen(None);
+                window.set_inner_size(PhysicalSize::new(config.window.width, config.window.height));
+            }
+        }
+        self.renderer.set_frame_rate_limit(config.window.frame_rate_limit);
+
+        let mut sound_context = self.sound_context.lock().unwrap();
+        sound_context.set_renderer(sound_renderer);
+        sound_context.set_master_gain(config.audio.master_volume);
+        drop(sound_context);
+        self.hrir_path = config.audio.hrir_path.clone();
+
+        self.config = config.clone();
+        cvar::sync_renderer_cvars(&mut self.cvars, &config.renderer);
+        self.cvars_revision = self.cvars.revision();
+        self.redraw.request();
+        Ok(())
+    }
+
+    /// Applies engine cvars changed since last call to renderer.
+    fn apply_cvars(&mut self) {
+        if self.cvars.revision() == self.cvars_revision {
+            return;
+        }
+        self.cvars_revision = self.cvars.revision();
+
+        let mut settings = self.renderer.get_quality_settings();
+        cvar::apply_renderer_cvars(&self.cvars, &mut settings);
+        if settings != self.renderer.get_quality_settings() {
+            if let Err(e) = self.renderer.set_quality_settings(&settings) {
+                Log::writeln(format!("Unable to apply renderer cvars! Reason: {:?}", e));
+            }
+            self.redraw.request();
+        }
+    }
+
+    /// Returns config which was applied last, input bindings and volumes of music and effects
+    /// are used by game from here.
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Sets current locale of localization and updates texts of localized nodes of every
+    /// scene. Widgets of user interface must be rebuilt (or their texts re-set) by game.
+    pub fn set_locale(&mut self, locale: &str) {
+        self.localization.set_locale(locale);
+        for scene in self.scenes.iter_mut() {
+            self.localization.localize_graph(&mut scene.graph);
+        }
+    }
+
+    /// Switches engine to deterministic update mode. In this mode scenes are updated only by
+    /// steps of fixed length, regardless of time delta passed to `update`, and global random
+    /// number generator is re-seeded with seed from settings. See `determinism` module docs
+    /// for more info.
+    pub fn enable_deterministic_mode(&mut self, settings: DeterminismSettings) {
+        random::seed(settings.seed);
+        self.fixed_timestep = Some(FixedTimestep::new(settings));
+    }
+
+    /// Switches engine back to variable time step.
+    pub fn disable_deterministic_mode(&mut self) {
+        self.fixed_timestep = None;
+    }
+
+    /// Returns true if engine runs in deterministic mode.
+    pub fn is_deterministic(&self) -> bool {
+        self.fixed_timestep.is_some()
+    }
+
+    /// Returns amount of fixed steps performed since deterministic mode was enabled, or `None`
+    /// if engine is not in deterministic mode.
+    pub fn step_count(&self) -> Option<u64> {
+        self.fixed_timestep.as_ref().map(|t| t.step_count())
+    }
+
+    /// Calculates hash of simulation state of every scene and global random number generator.
+    /// Peers of lockstep game should compare hashes for the same step to detect desync.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        for scene in self.scenes.iter() {
+            hasher.write_u64(scene.state_hash());
+        }
+        hasher.write_u64(random::global().state());
+        hasher.finish()
+    }
+
+    /// Returns approximate memory usage of each subsystem: surface data of scenes and model
+    /// resources, textures in RAM and on GPU, animation key frames and occupancy of pools.
+    /// It is relatively slow, because it iterates over all scenes and resources, so it is
+    /// intended for debugging and diagnostics of leaks.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        let mut counter = SceneMemoryCounter::default();
+
+        for scene in self.scenes.iter() {
+            counter.count_scene(scene, true, &mut usage);
+        }
+
+        let resource_manager = self.resource_manager.lock().unwrap();
+        for texture in resource_manager.textures() {
+            usage.textures_cpu += texture.lock().unwrap().memory_usage();
+        }
+        for model in resource_manager.models() {
+            counter.count_scene(model.lock().unwrap().get_scene(), false, &mut usage);
+        }
+        usage.texture_count = resource_manager.textures().len();
+        usage.model_count = resource_manager.models().len();
+
+        let (textures_gpu, geometry_gpu) = self.renderer.gpu_memory_usage();
+        usage.textures_gpu = textures_gpu;
+        usage.geometry_gpu = geometry_gpu;
+
+        usage
+    }
+
+    /// Returns allocations made by main thread in each phase of last finished frame (update
+    /// and render), see `engine::allocations` module docs.
+    #[cfg(feature = "alloc_audit")]
+    pub fn frame_allocations(&self) -> &allocations::FrameAllocations {
+        self.allocation_audit.last_frame()
+    }
+
+    /// Returns counters of work done by last update and amounts of resident resources, see
+    /// `engine::counters` module docs.
+    pub fn counters(&self) -> EngineCounters {
+        let mut counters = self.last_counters;
+        let resource_manager = self.resource_manager.lock().unwrap();
+        counters.textures_resident = resource_manager.textures().len();
+        counters.models_resident = resource_manager.models().len();
+        counters.sound_buffers_resident = resource_manager.sound_buffers().len();
+        counters
+    }
+
+    /// Processes window event: remembers cursor position and passes event to user interface.
+    /// Should be called for every `Event::WindowEvent` of main window, otherwise neither UI
+    /// nor cursor-related methods of engine will work.
+    pub fn process_window_event(&mut self, event: &WindowEvent) {
+        self.counters.window_events += 1;
+
+        if let WindowEvent::CursorMoved { position, .. } = event {
+            self.cursor_position = Vec2::new(position.x as f32, position.y as f32);
+        }
+
+        if let Some(mut os_event) = translate_event(event) {
+            // User interface is laid out in logical units, see `set_ui_scale`.
+            if let OsEvent::CursorMoved { position } = &mut os_event {
+                *position = position.scale(1.0 / self.effective_ui_scale());
+            }
+            self.user_interface.process_os_event(&os_event);
+        }
+
+        self.redraw.request();
+    }
+
+    /// Returns last known position of cursor in window coordinates (in pixels, relative to
+    /// left upper corner of window client area).
+    pub fn cursor_position(&self) -> Vec2 {
+        self.cursor_position
+    }
+
+    /// Creates world-space ray from position of cursor using given camera of given scene.
+    /// Cursor position is converted to camera viewport coordinates, so it works for split
+    /// screen too. Returns `None` if scene or node is invalid or node is not a camera.
+    ///
+    /// Camera matrices are updated during `update`, so ray is built using matrices of last
+    /// update. Cursor position is tracked by `process_window_event`.
+    pub fn cursor_ray(&self, scene: Handle<Scene>, camera: Handle<Node>) -> Option<Ray> {
+        let scene = self.scenes.try_get(scene)?;
+        if !scene.graph.is_valid_handle(camera) {
+            return None;
+        }
+        if let Node::Camera(camera) = &scene.graph[camera] {
+            let frame_size = self.frame_size();
+            let viewport = camera.viewport_pixels(frame_size);
+            let screen_coord = Vec2::new(
+                self.cursor_position.x - viewport.x as f32,
+                self.cursor_position.y - viewport.y as f32,
+            );
+            Some(camera.make_ray(screen_coord, frame_size))
+        } else {
+            None
+        }
+    }
+
+    /// Returns handle of mesh visible in given pixel of view of given camera in last rendered
+    /// frame, position is in pixels relative to left upper corner of client area of window.
+    /// Requires picking to be enabled by `Renderer::set_picking_enabled`. Unlike ray casts it
+    /// is exact to pixel and respects holes of alpha tested surfaces, but reads back data
+    /// from GPU, so call it on demand only. Use `cursor_position` to pick node under cursor.
+    /// Returns `None` if scene was removed.
+    pub fn pick_node(&mut self, scene: Handle<Scene>, camera: Handle<Node>, position: Vec2) -> Option<Handle<Node>> {
+        let graph = &self.scenes.try_get(scene)?.graph;
+        if !graph.is_valid_handle(camera) {
+            return None;
+        }
+        if let Node::Camera(camera_ref) = &graph[camera] {
+            let viewport = camera_ref.viewport_pixels(self.frame_size());
+            let local = Vec2::new(position.x - viewport.x as f32, position.y - viewport.y as f32);
+            let node = self.renderer.pick_node(camera, local)?;
+            // Node could be removed after frame was rendered.
+            if self.scenes[scene].graph.is_valid_handle(node) {
+                Some(node)
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Saves state of engine (the same data as `visit`) to given file. File is written to
+    /// temporary file first and then renamed, so existing file is never left half-written.
+    pub fn save_to_file<P: AsRef<Path>>(&mut self, path: P) -> VisitResult {
+        crash::save_atomically(self, "Engine", path.as_ref())
+    }
+
+    /// Enables periodic autosave with given settings or disables it if `None`. Time to next
+    /// save starts over. See `crash` module docs.
+    pub fn set_autosave(&mut self, settings: Option<AutosaveSettings>) {
+        self.autosave.settings = settings;
+        self.autosave.reset();
+    }
+
+    pub fn autosave_settings(&self) -> Option<&AutosaveSettings> {
+        self.autosave.settings.as_ref()
+    }
+
+    /// Installs panic hook which writes panic message to log and makes `update_with` and
+    /// `render` save state to given file and abort process on panic. See `crash` module docs.
+    pub fn enable_crash_guard<P: AsRef<Path>>(&mut self, emergency_save_path: P) {
+        crash::install_panic_hook();
+        self.emergency_save_path = Some(emergency_save_path.as_ref().to_owned());
+    }
+
+    /// Disables emergency save, panics are propagated to caller again. Panic hook stays
+    /// installed, it only writes to log.
+    pub fn disable_crash_guard(&mut self) {
+        self.emergency_save_path = None;
+    }
+
+    pub fn is_crash_guard_enabled(&self) -> bool {
+        self.emergency_save_path.is_some()
+    }
+
+    fn emergency_abort(&mut self) -> ! {
+        if let Some(path) = self.emergency_save_path.take() {
+            Log::writeln(format!("Engine panicked, trying to save state to {}", path.display()));
+            // State may be broken so badly that saving panics too.
+            match panic::catch_unwind(AssertUnwindSafe(|| self.save_to_file(&path))) {
+                Ok(Ok(())) => Log::writeln("Emergency save succeeded".to_owned()),
+                Ok(Err(e)) => Log::writeln(format!("Emergency save failed! Reason: {:?}", e)),
+                Err(_) => Log::writeln("Emergency save panicked!".to_owned()),
+            }
+        }
+        Log::flush();
+        process::abort()
+    }
+
+    /// Enables detection of long frames with given settings, see `watchdog` module docs.
+    pub fn enable_watchdog(&mut self, settings: WatchdogSettings) {
+        self.watchdog = Some(Watchdog::new(settings));
+    }
+
+    pub fn disable_watchdog(&mut self) {
+        self.watchdog = None;
+    }
+
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    /// Returns reports of long frames made since last call, empty if watchdog is disabled.
+    pub fn take_long_frame_reports(&mut self) -> Vec<LongFrameReport> {
+        self.watchdog.as_mut().map_or_else(Vec::new, |watchdog| watchdog.take_reports())
     }
 
     pub fn get_ui_mut(&mut self) -> &mut UserInterface<M, C> {
         &mut self.user_interface
     }
 
+    /// Sets render mode, see `redraw` module docs.
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.redraw.set_mode(mode);
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.redraw.mode()
+    }
+
+    /// Forces next call of `render` to render frame in `RenderMode::OnDemand`. Must be called
+    /// after changes which are not detected automatically, see `redraw` module docs.
+    pub fn request_redraw(&mut self) {
+        self.redraw.request();
+    }
+
+    /// Returns true if next call of `render` will render frame, always true in
+    /// `RenderMode::Continuous`. Application can wait for events instead of spinning when
+    /// nothing has to be redrawn.
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw.mode() == RenderMode::Continuous || self.redraw.needs_redraw(self.frame_signature())
+    }
+
+    /// Returns amount of frames which were skipped in `RenderMode::OnDemand`.
+    pub fn skipped_frames(&self) -> u64 {
+        self.redraw.skipped_frames()
+    }
+
+    /// Calculates signature of everything that is tracked by render on demand.
+    fn frame_signature(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        for scene in self.scenes.iter() {
+            hasher.write_u64(scene.state_hash());
+        }
+        hasher.write_usize(self.scenes.iter().count());
+        let frame_size = self.frame_size();
+        hasher.write_f32(frame_size.x);
+        hasher.write_f32(frame_size.y);
+        for window in self.secondary_windows.iter() {
+            let inner_size = window.window().inner_size();
+            hasher.write_u32(inner_size.width);
+            hasher.write_u32(inner_size.height);
+        }
+        // Textures are loaded in background, frame must be redrawn when they are ready.
+        if let Ok(resource_manager) = self.resource_manager.try_lock() {
+            let loaded = resource_manager.textures()
+                .iter()
+                .filter(|texture| texture.lock().unwrap().is_loaded())
+                .count();
+            hasher.write_usize(loaded);
+        }
+        hasher.finish()
+    }
+
+    /// Renders frame of all scenes and user interface. In `RenderMode::OnDemand` frame is
+    /// rendered only if something has changed since last rendered frame.
     #[inline]
     pub fn render(&mut self, dt: f32) -> Result<(), RendererError> {
+        // Nothing can be drawn until context is recovered.
+        if self.context_lost {
+            return Err(RendererError::ContextLost);
+        }
+
+        if self.redraw.mode() == RenderMode::OnDemand {
+            let signature = self.frame_signature();
+            if !self.redraw.begin_frame(signature) {
+                return Ok(());
+            }
+        }
+
+        if self.emergency_save_path.is_some() {
+            match panic::catch_unwind(AssertUnwindSafe(|| self.render_internal(dt))) {
+                Ok(result) => result,
+                Err(_) => self.emergency_abort(),
+            }
+        } else {
+            self.render_internal(dt)
+        }
+    }
+
+    fn render_internal(&mut self, dt: f32) -> Result<(), RendererError> {
+        let start = PhaseStart::now();
+        self.task_pool.wait_frame_jobs();
+        self.record_time("FrameJobs", start);
+
+        let start = PhaseStart::now();
         self.user_interface.draw();
-        self.renderer.render_and_swap_buffers(&self.scenes, &self.user_interface.get_drawing_context(), &self.context, dt)
+        for window in self.secondary_windows.iter_mut() {
+            window.sync_size();
+        }
+        let mut views = self.secondary_windows.iter_mut().map(|window| &mut window.view).collect::<Vec<_>>();
+        let result = self.renderer.render_and_swap_buffers(
+            &self.scenes,
+            &self.user_interface.get_drawing_context(),
+            self.context.as_ref(),
+            &mut views,
+            dt);
+        drop(views);
+        if let Err(RendererError::ContextLost) = result {
+            Log::writeln("Context is lost! Call Engine::recover_context to continue rendering.".to_owned());
+            self.context_lost = true;
+            self.record_time("Render", start);
+            return result;
+        }
+        let presented = self.present_secondary_windows();
+        self.record_time("Render", start);
+        result.and(presented)
+    }
+
+    /// Returns true if context was lost by driver and `recover_context` (or
+    /// `recover_external_context` for embedded engine) must be called to continue rendering.
+    /// Scenes and user interface keep updating while context is lost.
+    pub fn is_context_lost(&self) -> bool {
+        self.context_lost
+    }
+
+    /// Re-creates main window with its context after context was lost and re-creates all GPU
+    /// objects of renderer, textures and geometry are uploaded again from their CPU copies.
+    /// New window has the same size and full screen mode as old one, but its id is different,
+    /// so it is returned. Secondary windows share objects with lost context, so they are
+    /// closed and must be created again by game.
+    pub fn recover_context(&mut self, window_target: &EventLoopWindowTarget<()>) -> Result<WindowId, EngineError> {
+        let window_builder = match (self.window_builder.as_ref(), self.context.as_ref()) {
+            (Some(window_builder), Some(context)) => window_builder.clone()
+                .with_inner_size(context.window().inner_size())
+                .with_fullscreen(context.window().fullscreen()),
+            _ => return Err(EngineError::InternalError(
+                "Engine is embedded into external context, use recover_external_context".to_owned())),
+        };
+
+        // Old context stays in place if new one can't be created.
+        let context = make_main_context(window_builder, window_target, self.renderer.is_vsync_enabled())?;
+        self.renderer.recreate(|symbol| context.get_proc_address(symbol) as *const _)?;
+        self.renderer.set_frame_size(context.window().inner_size().into());
+
+        forget_lost_objects(std::mem::take(&mut self.secondary_windows));
+        let id = context.window().id();
+        self.context = Some(context);
+        self.context_lost = false;
+        self.redraw.request();
+        Ok(id)
+    }
+
+    /// Re-creates all GPU objects of renderer of embedded engine in new context of host
+    /// application after old one was lost. New context must be current, `loader` is the
+    /// same as for `from_external_context`.
+    pub fn recover_external_context<F>(&mut self, loader: F) -> Result<(), EngineError>
+        where F: FnMut(&str) -> *const c_void {
+        if !self.is_embedded() {
+            return Err(EngineError::InternalError(
+                "Engine owns its window, use recover_context".to_owned()));
+        }
+        self.renderer.recreate(loader)?;
+        self.context_lost = false;
+        self.redraw.request();
+        Ok(())
+    }
+
+    fn present_secondary_windows(&mut self) -> Result<(), RendererError> {
+        if self.secondary_windows.is_empty() {
+            return Ok(());
+        }
+        for window in self.secondary_windows.iter_mut() {
+            window.present();
+        }
+        secondary_window::make_current(&mut self.context)?;
+        Ok(())
+    }
+
+    /// Creates new OS window which shows given camera of given scene, see `secondary_window`
+    /// module docs. Returns id of new window. Not available for embedded engine.
+    pub fn create_window(&mut self,
+                         window_builder: WindowBuilder,
+                         events_loop: &EventLoop<()>,
+                         scene: Handle<Scene>,
+                         camera: Handle<Node>,
+    ) -> Result<WindowId, EngineError> {
+        if self.is_embedded() {
+            return Err(EngineError::InternalError("Embedded engine can't create windows".to_owned()));
+        }
+
+        let context_wrapper: WindowedContext<NotCurrent> = glutin::ContextBuilder::new()
+            .with_gl_profile(GlProfile::Core)
+            .with_gl(GlRequest::Specific(Api::OpenGl, (3, 3)))
+            .with_gl_robustness(Robustness::TryRobustLoseContextOnReset)
+            .with_shared_lists(self.main_context().context())
+            .build_windowed(window_builder, events_loop)?;
+
+        // Context must be current at least once to become usable, main one is restored
+        // in any case.
+        let made_current = unsafe { context_wrapper.make_current() };
+        secondary_window::make_current(&mut self.context)?;
+        let context = match made_current {
+            Ok(context) => context,
+            Err((_, e)) => return Err(EngineError::from(e)),
+        };
+
+        let window = SecondaryWindow::new(context, scene, camera);
+        let id = window.id();
+        self.secondary_windows.push(window);
+        self.redraw.request();
+        Ok(id)
+    }
+
+    /// Closes secondary window with given id, returns false if there is no such window.
+    pub fn close_window(&mut self, id: WindowId) -> bool {
+        match self.secondary_windows.iter().position(|window| window.id() == id) {
+            Some(index) => {
+                let mut window = self.secondary_windows.remove(index);
+                window.destroy_context();
+                // Target of view belongs to main context, it must be current when target
+                // is dropped.
+                if let Err(e) = secondary_window::make_current(&mut self.context) {
+                    Log::writeln(format!("Unable to make main context current! Reason: {:?}", e));
+                }
+                drop(window);
+                self.redraw.request();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn secondary_window(&self, id: WindowId) -> Option<&SecondaryWindow> {
+        self.secondary_windows.iter().find(|window| window.id() == id)
+    }
+
+    pub fn secondary_window_mut(&mut self, id: WindowId) -> Option<&mut SecondaryWindow> {
+        self.secondary_windows.iter_mut().find(|window| window.id() == id)
+    }
+
+    pub fn secondary_windows(&self) -> &[SecondaryWindow] {
+        &self.secondary_windows
     }
 }
 
@@ -0,0 +1,118 @@
+//! Render on demand - redraw frame only when something visible has changed.
+//!
+//! Games redraw every frame anyway, but editors and other tool-style applications show
+//! the same picture most of the time, and rendering it again and again only drains battery
+//! and heats GPU. In `RenderMode::OnDemand` engine skips `Engine::render` if nothing has
+//! changed since last rendered frame. Changes are detected automatically by signature of
+//! frame: global transforms and visibility of nodes, time positions of animations, bodies
+//! of physics, amount of scenes, size of window and amount of loaded textures. Window events
+//! passed to `Engine::process_window_event` and applied configs request redraw too.
+//!
+//! Everything else - colors of lights, materials and textures of surfaces, particle systems,
+//! quality settings set directly on renderer - is not tracked, after changing it call
+//! `Engine::request_redraw`. Time-based effects (particles, eye adaptation) stop when frame
+//! is not redrawn.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::{Engine, redraw::RenderMode},
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn setup(engine: &mut Engine<(), StubNode>) {
+//!     engine.set_render_mode(RenderMode::OnDemand);
+//! }
+//!
+//! fn frame(engine: &mut Engine<(), StubNode>, dt: f32) {
+//!     engine.update(dt);
+//!     // Renders only if something has changed.
+//!     engine.render(dt).unwrap();
+//! }
+//! ```
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Every call of `Engine::render` renders frame.
+    Continuous,
+    /// Frame is rendered only when something has changed or redraw was requested.
+    OnDemand,
+}
+
+impl Default for RenderMode {
+    fn default() -> Self {
+        RenderMode::Continuous
+    }
+}
+
+/// Decides whether frame must be rendered, see module docs.
+#[derive(Default)]
+pub struct RedrawTracker {
+    mode: RenderMode,
+    requested: bool,
+    signature: Option<u64>,
+    skipped_frames: u64,
+}
+
+impl RedrawTracker {
+    pub fn mode(&self) -> RenderMode {
+        self.mode
+    }
+
+    /// Sets render mode, next frame is rendered in any mode.
+    pub fn set_mode(&mut self, mode: RenderMode) {
+        self.mode = mode;
+        self.requested = true;
+    }
+
+    /// Forces next frame to be rendered.
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Returns true if frame with given signature differs from last rendered one or redraw
+    /// was requested. Does not change state of tracker.
+    pub fn needs_redraw(&self, signature: u64) -> bool {
+        self.mode == RenderMode::Continuous || self.requested || self.signature != Some(signature)
+    }
+
+    /// Returns true if frame with given signature must be rendered, and remembers it as last
+    /// rendered frame if so.
+    pub fn begin_frame(&mut self, signature: u64) -> bool {
+        if self.needs_redraw(signature) {
+            self.requested = false;
+            self.signature = Some(signature);
+            true
+        } else {
+            self.skipped_frames += 1;
+            false
+        }
+    }
+
+    /// Returns amount of frames which were not rendered because nothing has changed.
+    pub fn skipped_frames(&self) -> u64 {
+        self.skipped_frames
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::redraw::{RedrawTracker, RenderMode};
+
+    #[test]
+    fn test_redraw_on_demand() {
+        let mut tracker = RedrawTracker::default();
+        assert!(tracker.begin_frame(1));
+        assert!(tracker.begin_frame(1));
+
+        tracker.set_mode(RenderMode::OnDemand);
+        assert!(tracker.begin_frame(1));
+        assert!(!tracker.begin_frame(1));
+        assert!(tracker.begin_frame(2));
+        assert!(!tracker.needs_redraw(2));
+
+        tracker.request();
+        assert!(tracker.begin_frame(2));
+        assert!(!tracker.begin_frame(2));
+        assert_eq!(tracker.skipped_frames(), 2);
+    }
+}
@@ -12,8 +12,14 @@ use crate::{
         texture::Texture,
         model::Model,
         texture::TextureKind,
+        material::Material,
+        string_table::StringTable,
     },
     utils::log::Log,
+    engine::{
+        task::TaskPool,
+        manifest::AssetManifest,
+    },
 };
 use std::ops::{Deref, DerefMut};
 
@@ -71,29 +77,254 @@ impl<T> Visit for TimedEntry<T> where T: Default + Visit {
 pub type SharedTexture = Arc<Mutex<Texture>>;
 pub type SharedModel = Arc<Mutex<Model>>;
 pub type SharedSoundBuffer = Arc<Mutex<SoundBuffer>>;
+pub type SharedMaterial = Arc<Mutex<Material>>;
+pub type SharedStringTable = Arc<Mutex<StringTable>>;
+
+/// Rule which replaces beginning of requested path, see [`ResourceManager::add_path_remap`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathRemap {
+    pub from: String,
+    pub to: PathBuf,
+}
+
+/// Path is normalized to forward slashes, so paths written on Windows can be remapped
+/// on any platform.
+fn normalize(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn apply_remaps(path: &Path, remaps: &[PathRemap], case_insensitive: bool) -> Option<PathBuf> {
+    let normalized = normalize(path);
+    for remap in remaps {
+        let from = remap.from.replace('\\', "/");
+        let matches = if case_insensitive {
+            normalized.len() >= from.len()
+                && normalized.is_char_boundary(from.len())
+                && normalized[..from.len()].eq_ignore_ascii_case(&from)
+        } else {
+            normalized.starts_with(&from)
+        };
+        if matches {
+            let rest = normalized[from.len()..].trim_start_matches('/');
+            return Some(remap.to.join(rest));
+        }
+    }
+    None
+}
+
+/// Returns file name of path, both slashes are treated as separators.
+pub(in crate) fn file_name_of(path: &Path) -> Option<String> {
+    normalize(path)
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+}
+
+/// Searches path ignoring case of each component, slow - reads every directory on the way.
+fn find_case_insensitive(path: &Path) -> Option<PathBuf> {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        let candidate = result.join(component);
+        if candidate.exists() {
+            result = candidate;
+            continue;
+        }
+        let name = component.as_os_str().to_string_lossy().to_lowercase();
+        let dir = if result.as_os_str().is_empty() { Path::new(".") } else { result.as_path() };
+        let entry = std::fs::read_dir(dir).ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == name)?;
+        result = result.join(entry.file_name());
+    }
+    Some(result)
+}
+
+/// Checks file against manifest, if there is one. Failure is written to log.
+fn verify(manifest: Option<&AssetManifest>, path: &Path) -> bool {
+    match manifest.map(|manifest| manifest.verify(path)) {
+        Some(Err(e)) => {
+            Log::writeln(format!("{}! Resource is not loaded.", e));
+            false
+        }
+        _ => true,
+    }
+}
 
 pub struct ResourceManager {
     textures: Vec<TimedEntry<SharedTexture>>,
     models: Vec<TimedEntry<SharedModel>>,
     sound_buffers: Vec<TimedEntry<SharedSoundBuffer>>,
+    materials: Vec<TimedEntry<SharedMaterial>>,
+    string_tables: Vec<TimedEntry<SharedStringTable>>,
     /// Path to textures, extensively used for resource files which stores path in weird
     /// format (either relative or absolute) which is obviously not good for engine.
     textures_path: PathBuf,
+    search_paths: Vec<PathBuf>,
+    path_remaps: Vec<PathRemap>,
+    case_insensitive_lookup: bool,
+    use_fallback_texture: bool,
+    unresolved: Vec<PathBuf>,
+    task_pool: Arc<TaskPool>,
+    manifest: Option<Arc<AssetManifest>>,
 }
 
 impl ResourceManager {
     /// Lifetime of orphaned resource in seconds (with only one strong ref which is resource manager itself)
     pub const MAX_RESOURCE_TTL: f32 = 20.0;
 
-    pub(in crate::engine) fn new() -> ResourceManager {
+    pub(in crate::engine) fn new(task_pool: Arc<TaskPool>) -> ResourceManager {
         Self {
             textures: Vec::new(),
             models: Vec::new(),
             sound_buffers: Vec::new(),
+            materials: Vec::new(),
+            string_tables: Vec::new(),
             textures_path: PathBuf::from("data/textures/"),
+            search_paths: Vec::new(),
+            path_remaps: Vec::new(),
+            case_insensitive_lookup: true,
+            use_fallback_texture: true,
+            unresolved: Vec::new(),
+            task_pool,
+            manifest: None,
         }
     }
 
+    /// Adds directory in which resources are searched by file name when requested path does
+    /// not exist. Useful for models which reference textures by absolute paths of author's
+    /// machine.
+    pub fn add_search_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.search_paths.push(path.as_ref().to_owned());
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    pub fn clear_search_paths(&mut self) {
+        self.search_paths.clear();
+    }
+
+    /// Adds rule which replaces `from` prefix of requested path with `to`, for example
+    /// `C:/Users/artist/project/` to `data/`. Both kinds of slashes are treated the same.
+    /// Rules are checked in order they were added, first matching rule is used.
+    pub fn add_path_remap<P: AsRef<Path>>(&mut self, from: &str, to: P) {
+        self.path_remaps.push(PathRemap {
+            from: from.to_owned(),
+            to: to.as_ref().to_owned(),
+        });
+    }
+
+    pub fn path_remaps(&self) -> &[PathRemap] {
+        &self.path_remaps
+    }
+
+    pub fn clear_path_remaps(&mut self) {
+        self.path_remaps.clear();
+    }
+
+    /// Enables lookup that ignores case of file and directory names, it is used only when
+    /// exact path does not exist. Enabled by default.
+    pub fn set_case_insensitive_lookup(&mut self, enabled: bool) {
+        self.case_insensitive_lookup = enabled;
+    }
+
+    pub fn is_case_insensitive_lookup(&self) -> bool {
+        self.case_insensitive_lookup
+    }
+
+    /// Enables checkerboard texture which is used instead of textures that can't be found.
+    /// Enabled by default, when disabled requests of missing textures return `None` (or
+    /// untextured placeholder for async requests).
+    pub fn set_use_fallback_texture(&mut self, enabled: bool) {
+        self.use_fallback_texture = enabled;
+    }
+
+    pub fn is_use_fallback_texture(&self) -> bool {
+        self.use_fallback_texture
+    }
+
+    /// Returns paths of resources which were requested but could not be found, each path
+    /// is reported once.
+    pub fn unresolved_paths(&self) -> &[PathBuf] {
+        &self.unresolved
+    }
+
+    pub fn clear_unresolved_paths(&mut self) {
+        self.unresolved.clear();
+    }
+
+    /// Sets manifest against which files are checked before loading, files which do not
+    /// match manifest are not loaded. See `engine::manifest` module docs.
+    pub fn set_manifest(&mut self, manifest: Option<AssetManifest>) {
+        self.manifest = manifest.map(Arc::new);
+    }
+
+    pub fn manifest(&self) -> Option<&AssetManifest> {
+        self.manifest.as_deref()
+    }
+
+    fn try_path(&self, path: &Path) -> Option<PathBuf> {
+        if path.exists() {
+            Some(path.to_owned())
+        } else if self.case_insensitive_lookup {
+            find_case_insensitive(path)
+        } else {
+            None
+        }
+    }
+
+    /// Returns actual path of resource: requested path itself if it exists, otherwise
+    /// path produced by remap rules, otherwise file with same name in one of search paths.
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        let path = path.as_ref();
+
+        if let Some(resolved) = self.try_path(path) {
+            return Some(resolved);
+        }
+
+        if let Some(remapped) = apply_remaps(path, &self.path_remaps, self.case_insensitive_lookup) {
+            if let Some(resolved) = self.try_path(&remapped) {
+                return Some(resolved);
+            }
+        }
+
+        let file_name = file_name_of(path)?;
+        self.search_paths
+            .iter()
+            .filter_map(|dir| self.try_path(&dir.join(&file_name)))
+            .next()
+    }
+
+    fn resolve_or_report(&mut self, path: &Path) -> Option<PathBuf> {
+        let resolved = self.resolve_path(path);
+        if resolved.is_none() && !self.unresolved.iter().any(|unresolved| unresolved == path) {
+            Log::writeln(format!("Unable to find resource {}!", path.display()));
+            self.unresolved.push(path.to_owned());
+        }
+        resolved
+    }
+
+    /// Resolves path and checks file against manifest.
+    fn resolve_verified(&mut self, path: &Path) -> Option<PathBuf> {
+        let resolved = self.resolve_or_report(path)?;
+        if verify(self.manifest.as_deref(), &resolved) {
+            Some(resolved)
+        } else {
+            None
+        }
+    }
+
+    fn add_fallback_texture(&mut self, path: &Path, kind: TextureKind) -> SharedTexture {
+        let texture = Arc::new(Mutex::new(Texture::checkerboard(path, kind)));
+        self.textures.push(TimedEntry {
+            value: texture.clone(),
+            time_to_live: Self::MAX_RESOURCE_TTL,
+        });
+        texture
+    }
+
     /// Experimental async texture loader. Always returns valid texture object which could still
     /// be not loaded, you should check is_loaded flag to ensure.
     ///
@@ -103,6 +334,17 @@ impl ResourceManager {
             return texture;
         }
 
+        let resolved = match self.resolve_or_report(path.as_ref()) {
+            Some(resolved) => resolved,
+            None => {
+                if self.use_fallback_texture {
+                    return self.add_fallback_texture(path.as_ref(), kind);
+                }
+                // Keep old behaviour - placeholder which is never loaded.
+                PathBuf::from(path.as_ref())
+            }
+        };
+
         let texture = Arc::new(Mutex::new(Texture::default()));
         self.textures.push(TimedEntry {
             value: texture.clone(),
@@ -111,11 +353,22 @@ impl ResourceManager {
         let result = texture.clone();
 
         let path = PathBuf::from(path.as_ref());
-        std::thread::spawn(move || {
+        let manifest = self.manifest.clone();
+        let use_fallback_texture = self.use_fallback_texture;
+        self.task_pool.spawn_detached(move || {
             if let Ok(mut texture) = texture.lock() {
+                // Hashing may take a while for big textures, so it is done in the task too.
+                if !verify(manifest.as_deref(), &resolved) {
+                    if use_fallback_texture {
+                        *texture = Texture::checkerboard(&path, kind);
+                    }
+                    return;
+                }
                 let time = time::Instant::now();
-                match Texture::load_from_file(&path, kind) {
-                    Ok(raw_texture) => {
+                match Texture::load_from_file(&resolved, kind) {
+                    Ok(mut raw_texture) => {
+                        // Texture is identified by requested path, not by actual one.
+                        raw_texture.path = path.clone();
                         *texture = raw_texture;
                         Log::writeln(format!("Texture {:?} is loaded in {:?}!", path, time.elapsed()));
                     }
@@ -134,8 +387,21 @@ impl ResourceManager {
             return Some(texture);
         }
 
-        match Texture::load_from_file(path.as_ref(), kind) {
-            Ok(texture) => {
+        let resolved = match self.resolve_verified(path.as_ref()) {
+            Some(resolved) => resolved,
+            None => {
+                return if self.use_fallback_texture {
+                    Some(self.add_fallback_texture(path.as_ref(), kind))
+                } else {
+                    None
+                };
+            }
+        };
+
+        match Texture::load_from_file(&resolved, kind) {
+            Ok(mut texture) => {
+                // Texture is identified by requested path, not by actual one.
+                texture.path = path.as_ref().to_owned();
                 let shared_texture = Arc::new(Mutex::new(texture));
                 self.textures.push(TimedEntry {
                     value: shared_texture.clone(),
@@ -156,8 +422,12 @@ impl ResourceManager {
             return Some(model);
         }
 
-        match Model::load(path.as_ref(), self) {
-            Ok(model) => {
+        let resolved = self.resolve_verified(path.as_ref())?;
+
+        match Model::load(&resolved, self) {
+            Ok(mut model) => {
+                // Model is identified by requested path, not by actual one.
+                model.path = path.as_ref().to_owned();
                 let model = Arc::new(Mutex::new(model));
                 model.lock().unwrap().self_weak_ref = Some(Arc::downgrade(&model));
                 self.models.push(TimedEntry {
@@ -179,7 +449,12 @@ impl ResourceManager {
             return Some(sound_buffer);
         }
 
-        match DataSource::from_file(path.as_ref()) {
+        let resolved = self.resolve_verified(path.as_ref())?;
+        if let Some(sound_buffer) = self.find_sound_buffer(&resolved) {
+            return Some(sound_buffer);
+        }
+
+        match DataSource::from_file(&resolved) {
             Ok(source) => {
                 let buffer = if stream {
                     SoundBuffer::new_streaming(source)
@@ -208,6 +483,61 @@ impl ResourceManager {
         }
     }
 
+    /// Loads material from file or returns already loaded one, see `resource::material`.
+    pub fn request_material<P: AsRef<Path>>(&mut self, path: P) -> Option<SharedMaterial> {
+        if let Some(material) = self.find_material(path.as_ref()) {
+            return Some(material);
+        }
+
+        let resolved = self.resolve_verified(path.as_ref())?;
+
+        match Material::load(&resolved, self) {
+            Ok(mut material) => {
+                // Material is identified by requested path, not by actual one.
+                material.path = path.as_ref().to_owned();
+                let material = Arc::new(Mutex::new(material));
+                self.materials.push(TimedEntry {
+                    value: material.clone(),
+                    time_to_live: Self::MAX_RESOURCE_TTL,
+                });
+                Log::writeln(format!("Material {} is loaded!", path.as_ref().display()));
+                Some(material)
+            }
+            Err(e) => {
+                Log::writeln(format!("Unable to load material from {}! Reason {}", path.as_ref().display(), e));
+                None
+            }
+        }
+    }
+
+    /// Loads string table from file or returns already loaded one, see
+    /// `resource::string_table`.
+    pub fn request_string_table<P: AsRef<Path>>(&mut self, path: P) -> Option<SharedStringTable> {
+        if let Some(table) = self.find_string_table(path.as_ref()) {
+            return Some(table);
+        }
+
+        let resolved = self.resolve_verified(path.as_ref())?;
+
+        match StringTable::load(&resolved) {
+            Ok(mut table) => {
+                // Table is identified by requested path, not by actual one.
+                table.path = path.as_ref().to_owned();
+                let table = Arc::new(Mutex::new(table));
+                self.string_tables.push(TimedEntry {
+                    value: table.clone(),
+                    time_to_live: Self::MAX_RESOURCE_TTL,
+                });
+                Log::writeln(format!("String table {} is loaded!", path.as_ref().display()));
+                Some(table)
+            }
+            Err(e) => {
+                Log::writeln(format!("Unable to load string table from {}! Reason {}", path.as_ref().display(), e));
+                None
+            }
+        }
+    }
+
     #[inline]
     pub fn textures(&self) -> &[TimedEntry<SharedTexture>] {
         &self.textures
@@ -252,6 +582,34 @@ impl ResourceManager {
         None
     }
 
+    #[inline]
+    pub fn materials(&self) -> &[TimedEntry<SharedMaterial>] {
+        &self.materials
+    }
+
+    pub fn find_material<P: AsRef<Path>>(&self, path: P) -> Option<SharedMaterial> {
+        for material in self.materials.iter() {
+            if material.lock().unwrap().path.as_path() == path.as_ref() {
+                return Some(material.value.clone());
+            }
+        }
+        None
+    }
+
+    #[inline]
+    pub fn string_tables(&self) -> &[TimedEntry<SharedStringTable>] {
+        &self.string_tables
+    }
+
+    pub fn find_string_table<P: AsRef<Path>>(&self, path: P) -> Option<SharedStringTable> {
+        for table in self.string_tables.iter() {
+            if table.lock().unwrap().path.as_path() == path.as_ref() {
+                return Some(table.value.clone());
+            }
+        }
+        None
+    }
+
     #[inline]
     pub fn textures_path(&self) -> &Path {
         self.textures_path.as_path()
@@ -312,17 +670,63 @@ impl ResourceManager {
         });
     }
 
+    fn update_materials(&mut self, dt: f32) {
+        for material in self.materials.iter_mut() {
+            material.time_to_live -= dt;
+            if Arc::strong_count(material) > 1 {
+                material.time_to_live = Self::MAX_RESOURCE_TTL;
+            }
+        }
+        self.materials.retain(|material| {
+            let retain = material.time_to_live > 0.0;
+            if !retain {
+                Log::writeln(format!("Material resource {:?} destroyed because it not used anymore!", material.lock().unwrap().path));
+            }
+            retain
+        });
+    }
+
+    fn update_string_tables(&mut self, dt: f32) {
+        for table in self.string_tables.iter_mut() {
+            table.time_to_live -= dt;
+            if Arc::strong_count(table) > 1 {
+                table.time_to_live = Self::MAX_RESOURCE_TTL;
+            }
+        }
+        self.string_tables.retain(|table| {
+            let retain = table.time_to_live > 0.0;
+            if !retain {
+                Log::writeln(format!("String table resource {:?} destroyed because it not used anymore!", table.lock().unwrap().path));
+            }
+            retain
+        });
+    }
+
     pub(in crate) fn update(&mut self, dt: f32) {
         self.update_textures(dt);
         self.update_model(dt);
         self.update_sound_buffers(dt);
+        self.update_materials(dt);
+        self.update_string_tables(dt);
     }
 
     fn reload_textures(&mut self) {
         for old_texture in self.textures.iter() {
             let mut old_texture = old_texture.lock().unwrap();
-            let new_texture = match Texture::load_from_file(old_texture.path.as_path(), old_texture.kind) {
-                Ok(texture) => texture,
+            let path = match self.resolve_path(old_texture.path.as_path()) {
+                Some(path) => path,
+                None => {
+                    Log::writeln(format!("Unable to reload {:?} texture! Reason: not found", old_texture.path));
+                    continue;
+                }
+            };
+            let new_texture = match Texture::load_from_file(path, old_texture.kind) {
+                Ok(mut texture) => {
+                    texture.path = old_texture.path.clone();
+                    texture.filter = old_texture.filter;
+                    texture.anisotropy = old_texture.anisotropy;
+                    texture
+                }
                 Err(e) => {
                     Log::writeln(format!("Unable to reload {:?} texture! Reason: {}", old_texture.path, e));
                     continue;
@@ -337,8 +741,18 @@ impl ResourceManager {
         for old_model in self.models().to_vec() {
             let old_model_arc = old_model.clone();
             let mut old_model = old_model.lock().unwrap();
-            let mut new_model = match Model::load(old_model.path.as_path(), self) {
-                Ok(new_model) => new_model,
+            let path = match self.resolve_path(old_model.path.as_path()) {
+                Some(path) => path,
+                None => {
+                    Log::writeln(format!("Unable to reload {:?} model! Reason: not found", old_model.path));
+                    continue;
+                }
+            };
+            let mut new_model = match Model::load(path, self) {
+                Ok(mut new_model) => {
+                    new_model.path = old_model.path.clone();
+                    new_model
+                }
                 Err(e) => {
                     Log::writeln(format!("Unable to reload {:?} model! Reason: {}", old_model.path, e));
                     continue;
@@ -372,10 +786,58 @@ impl ResourceManager {
         }
     }
 
+    /// Materials are reloaded in place, so surfaces which use them get new content.
+    fn reload_materials(&mut self) {
+        for old_material in self.materials().to_vec() {
+            let mut old_material = old_material.lock().unwrap();
+            let path = match self.resolve_path(old_material.path.as_path()) {
+                Some(path) => path,
+                None => {
+                    Log::writeln(format!("Unable to reload {:?} material! Reason: not found", old_material.path));
+                    continue;
+                }
+            };
+            match Material::load(path, self) {
+                Ok(mut new_material) => {
+                    new_material.path = old_material.path.clone();
+                    *old_material = new_material;
+                }
+                Err(e) => {
+                    Log::writeln(format!("Unable to reload {:?} material! Reason: {}", old_material.path, e));
+                }
+            }
+        }
+    }
+
+    /// String tables are reloaded in place, new texts are applied on next change of locale.
+    fn reload_string_tables(&mut self) {
+        for old_table in self.string_tables().to_vec() {
+            let mut old_table = old_table.lock().unwrap();
+            let path = match self.resolve_path(old_table.path.as_path()) {
+                Some(path) => path,
+                None => {
+                    Log::writeln(format!("Unable to reload {:?} string table! Reason: not found", old_table.path));
+                    continue;
+                }
+            };
+            match StringTable::load(path) {
+                Ok(mut new_table) => {
+                    new_table.path = old_table.path.clone();
+                    *old_table = new_table;
+                }
+                Err(e) => {
+                    Log::writeln(format!("Unable to reload {:?} string table! Reason: {}", old_table.path, e));
+                }
+            }
+        }
+    }
+
     pub fn reload_resources(&mut self) {
         self.reload_textures();
         self.reload_models();
         self.reload_sound_buffers();
+        self.reload_materials();
+        self.reload_string_tables();
     }
 }
 
@@ -386,7 +848,30 @@ impl Visit for ResourceManager {
         self.textures.visit("Textures", visitor)?;
         self.models.visit("Models", visitor)?;
         self.sound_buffers.visit("SoundBuffers", visitor)?;
+        self.materials.visit("Materials", visitor)?;
 
         visitor.leave_region()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use crate::engine::resource_manager::{apply_remaps, file_name_of, PathRemap};
+
+    #[test]
+    fn test_path_remap() {
+        let remaps = vec![PathRemap {
+            from: "C:\\Users\\artist\\project".to_owned(),
+            to: PathBuf::from("data"),
+        }];
+
+        assert_eq!(apply_remaps(Path::new("C:\\Users\\artist\\project\\textures\\wood.png"), &remaps, false),
+                   Some(PathBuf::from("data/textures/wood.png")));
+        assert_eq!(apply_remaps(Path::new("c:/users/artist/project/wood.png"), &remaps, true),
+                   Some(PathBuf::from("data/wood.png")));
+        assert_eq!(apply_remaps(Path::new("c:/users/artist/project/wood.png"), &remaps, false), None);
+
+        assert_eq!(file_name_of(Path::new("C:\\textures\\wood.png")), Some("wood.png".to_owned()));
+        assert_eq!(file_name_of(Path::new("textures/")), None);
+    }
+}
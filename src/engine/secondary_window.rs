@@ -0,0 +1,143 @@
+//! Secondary OS windows which show scenes through their own cameras, for editor-like tools
+//! with several views (top, side, perspective) or for monitoring tools.
+//!
+//! Each secondary window has its own OpenGL context which shares textures, buffers and
+//! shaders with context of main window, so resources are loaded and uploaded once. Frame
+//! buffers and vertex arrays can't be shared, that's why all rendering is done by main
+//! renderer in main context: camera of window is rendered into offscreen target of window
+//! size, and context of window only copies that target to its back buffer. Camera shown in
+//! secondary window is not rendered in main window. User interface is drawn only in main
+//! window. Secondary windows are presented without vertical synchronization, otherwise
+//! every window would wait for vertical blank one after another.
+//!
+//! Events of all windows come to the same event loop, use `SecondaryWindow::id` to tell
+//! them apart. Size of window is tracked automatically, window is closed by
+//! `Engine::close_window`.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::Engine,
+//!     window::WindowBuilder,
+//!     event_loop::EventLoop,
+//!     scene::{Scene, node::Node},
+//!     core::pool::Handle,
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn open_top_view(engine: &mut Engine<(), StubNode>, event_loop: &EventLoop<()>, scene: Handle<Scene>, top_camera: Handle<Node>) {
+//!     let window_builder = WindowBuilder::new().with_title("Top");
+//!     let id = engine.create_window(window_builder, event_loop, scene, top_camera).unwrap();
+//!     assert!(engine.secondary_window(id).is_some());
+//! }
+//! ```
+
+use crate::{
+    core::pool::Handle,
+    scene::{
+        Scene,
+        node::Node,
+    },
+    renderer::window_view::{
+        WindowView,
+        ViewPresenter,
+    },
+    window::{
+        Window,
+        WindowId,
+    },
+    utils::log::Log,
+    ContextError,
+    PossiblyCurrent,
+    WindowedContext,
+};
+
+/// See module docs.
+pub struct SecondaryWindow {
+    /// Context is taken out only while it is being made current.
+    context: Option<WindowedContext<PossiblyCurrent>>,
+    pub(in crate::engine) view: WindowView,
+    presenter: ViewPresenter,
+}
+
+impl SecondaryWindow {
+    pub(in crate::engine) fn new(context: WindowedContext<PossiblyCurrent>, scene: Handle<Scene>, camera: Handle<Node>) -> Self {
+        let size = context.window().inner_size();
+        Self {
+            context: Some(context),
+            view: WindowView::new(scene, camera, (size.width, size.height)),
+            presenter: Default::default(),
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        self.context.as_ref().unwrap().window()
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window().id()
+    }
+
+    pub fn scene(&self) -> Handle<Scene> {
+        self.view.scene
+    }
+
+    pub fn camera(&self) -> Handle<Node> {
+        self.view.camera
+    }
+
+    /// Sets scene and camera shown in window. Camera must belong to given scene.
+    pub fn set_view(&mut self, scene: Handle<Scene>, camera: Handle<Node>) {
+        self.view.scene = scene;
+        self.view.camera = camera;
+    }
+
+    /// Updates size of view and surface of context if window was resized.
+    pub(in crate::engine) fn sync_size(&mut self) {
+        let context = self.context.as_ref().unwrap();
+        let size = context.window().inner_size();
+        if (size.width, size.height) != self.view.size {
+            context.resize(size);
+            self.view.size = (size.width, size.height);
+        }
+    }
+
+    /// Copies last frame of view to window and swaps buffers. Makes context of window
+    /// current, caller must make main context current again.
+    pub(in crate::engine) fn present(&mut self) {
+        if let Err(e) = make_current(&mut self.context) {
+            Log::writeln(format!("Unable to make context of window current! Reason: {:?}", e));
+            return;
+        }
+        self.presenter.present(&self.view, self.view.size);
+        if let Err(e) = self.context.as_ref().unwrap().swap_buffers() {
+            Log::writeln(format!("Unable to swap buffers of window! Reason: {:?}", e));
+        }
+    }
+
+    /// Releases objects of context of window and destroys context. Main context must be
+    /// made current after that.
+    pub(in crate::engine) fn destroy_context(&mut self) {
+        if make_current(&mut self.context).is_ok() {
+            self.presenter.destroy();
+        }
+        self.context = None;
+    }
+}
+
+/// Makes given context current, context stays in place even if it fails.
+pub(in crate::engine) fn make_current(context: &mut Option<WindowedContext<PossiblyCurrent>>) -> Result<(), ContextError> {
+    let current = match context.take() {
+        Some(current) => current,
+        None => return Ok(()),
+    };
+    match unsafe { current.make_current() } {
+        Ok(current) => {
+            *context = Some(current);
+            Ok(())
+        }
+        Err((current, e)) => {
+            *context = Some(current);
+            Err(e)
+        }
+    }
+}
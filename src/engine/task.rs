@@ -0,0 +1,280 @@
+//! Task pool - shared set of worker threads which run jobs of engine and game code.
+//!
+//! Spawning a thread for each small job is expensive, and many independent pools compete
+//! for CPU cores. Engine owns single task pool (see `Engine::task_pool`), resource manager
+//! loads resources on it and game code can use it for its own background work: path
+//! finding, procedural generation, saving, etc.
+//!
+//! There are three kinds of jobs:
+//!
+//! - `spawn` - job with result, result is taken from returned `TaskHandle` when ready.
+//! - `spawn_frame` - job which must be finished before rendering of current frame, engine
+//!   waits for such jobs at the beginning of `Engine::render`. Use it to compute something
+//!   during update of game logic which renderer needs in the same frame.
+//! - `parallel_for` - processes items of a slice in parallel and returns when every item
+//!   is processed, it can borrow data from caller.
+//!
+//! ```no_run
+//! use rg3d::engine::task::TaskPool;
+//!
+//! fn work(pool: &TaskPool) {
+//!     let handle = pool.spawn(|| (0..1_000_000u64).sum::<u64>());
+//!
+//!     let mut values = vec![1.0f32; 4096];
+//!     pool.parallel_for(&mut values, |value| *value = value.sqrt());
+//!
+//!     println!("sum is {:?}", handle.wait());
+//! }
+//! ```
+//!
+//! Panic inside of a job does not kill worker thread, handle of such job returns `None`.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Handle of job spawned by [`TaskPool::spawn`].
+pub struct TaskHandle<T> {
+    receiver: Receiver<T>,
+    result: Option<T>,
+    finished: bool,
+}
+
+impl<T> TaskHandle<T> {
+    fn poll(&mut self) {
+        if !self.finished {
+            match self.receiver.try_recv() {
+                Ok(result) => {
+                    self.result = Some(result);
+                    self.finished = true;
+                }
+                Err(TryRecvError::Disconnected) => self.finished = true,
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+    }
+
+    /// Returns true if job is finished (successfully or not).
+    pub fn is_finished(&mut self) -> bool {
+        self.poll();
+        self.finished
+    }
+
+    /// Returns result of job if it is finished, result can be taken only once. Returns
+    /// `None` if job is still running, result was already taken or job has panicked.
+    pub fn try_take(&mut self) -> Option<T> {
+        self.poll();
+        self.result.take()
+    }
+
+    /// Blocks until job is finished and returns its result, `None` if job has panicked or
+    /// result was already taken.
+    pub fn wait(mut self) -> Option<T> {
+        if self.finished {
+            self.result.take()
+        } else {
+            self.receiver.recv().ok()
+        }
+    }
+}
+
+/// Decrements counter of frame jobs when dropped, even if job has panicked.
+struct FrameJobGuard(Arc<(Mutex<usize>, Condvar)>);
+
+impl Drop for FrameJobGuard {
+    fn drop(&mut self) {
+        let (count, condvar) = &*self.0;
+        if let Ok(mut count) = count.lock() {
+            *count -= 1;
+            condvar.notify_all();
+        }
+    }
+}
+
+/// See module docs.
+pub struct TaskPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    frame_jobs: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl Default for TaskPool {
+    fn default() -> Self {
+        let worker_count = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1)
+            // Main thread has its own work.
+            .saturating_sub(1)
+            .max(1);
+        Self::new(worker_count)
+    }
+}
+
+impl TaskPool {
+    /// Creates pool with given amount of worker threads, at least one thread is created.
+    pub fn new(worker_count: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..worker_count.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("TaskWorker{}", i))
+                    .spawn(move || loop {
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => break,
+                        };
+                        match job {
+                            Ok(job) => {
+                                // Result sender of panicked job is dropped, so its handle
+                                // gets `None`.
+                                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                            }
+                            // Pool was dropped.
+                            Err(_) => break,
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+            frame_jobs: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(sender) = self.sender.as_ref() {
+            // Workers live as long as pool, so send can't fail.
+            let _ = sender.send(job);
+        }
+    }
+
+    /// Runs given job on worker thread and returns handle to its result.
+    pub fn spawn<F, T>(&self, job: F) -> TaskHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Box::new(move || {
+            let _ = sender.send(job());
+        }));
+        TaskHandle {
+            receiver,
+            result: None,
+            finished: false,
+        }
+    }
+
+    /// Runs given job on worker thread without a way to get its result or wait for it.
+    pub fn spawn_detached<F>(&self, job: F) where F: FnOnce() + Send + 'static {
+        self.send(Box::new(job));
+    }
+
+    /// Runs given job on worker thread, [`wait_frame_jobs`](Self::wait_frame_jobs) blocks
+    /// until every such job is finished. Engine waits for them before rendering.
+    pub fn spawn_frame<F>(&self, job: F) where F: FnOnce() + Send + 'static {
+        *self.frame_jobs.0.lock().unwrap() += 1;
+        let guard = FrameJobGuard(self.frame_jobs.clone());
+        self.send(Box::new(move || {
+            let _guard = guard;
+            job();
+        }));
+    }
+
+    /// Blocks until every job spawned by `spawn_frame` is finished.
+    pub fn wait_frame_jobs(&self) {
+        let (count, condvar) = &*self.frame_jobs;
+        let mut count = count.lock().unwrap();
+        while *count > 0 {
+            count = condvar.wait(count).unwrap();
+        }
+    }
+
+    /// Calls given function for every item of slice in parallel and returns when every item
+    /// is processed. Items are split into chunks, one chunk per worker, calling thread
+    /// processes a chunk too. Function may borrow anything from caller, so chunks run on
+    /// scoped threads instead of workers of the pool, amount of threads is the same.
+    pub fn parallel_for<T, F>(&self, items: &mut [T], func: F)
+        where T: Send,
+              F: Fn(&mut T) + Sync {
+        let thread_count = self.workers.len() + 1;
+        let chunk_size = items.len().div_ceil(thread_count);
+        if chunk_size == 0 || items.len() == chunk_size {
+            items.iter_mut().for_each(&func);
+            return;
+        }
+
+        let func = &func;
+        thread::scope(|scope| {
+            let mut chunks = items.chunks_mut(chunk_size);
+            let first = chunks.next();
+            for chunk in chunks {
+                scope.spawn(move || chunk.iter_mut().for_each(func));
+            }
+            if let Some(first) = first {
+                first.iter_mut().for_each(func);
+            }
+        });
+    }
+}
+
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        // Closing channel stops workers once they finish queued jobs.
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use crate::engine::task::TaskPool;
+
+    #[test]
+    fn test_task_pool() {
+        let pool = TaskPool::new(2);
+
+        let handle = pool.spawn(|| 2 + 2);
+        assert_eq!(handle.wait(), Some(4));
+
+        let panicked = pool.spawn(|| -> u32 { panic!("job failed") });
+        assert_eq!(panicked.wait(), None);
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..16 {
+            let counter = counter.clone();
+            pool.spawn_frame(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        pool.wait_frame_jobs();
+        assert_eq!(counter.load(Ordering::SeqCst), 16);
+
+        let mut values = (0..100).collect::<Vec<u32>>();
+        pool.parallel_for(&mut values, |value| *value *= 2);
+        assert!(values.iter().enumerate().all(|(i, &value)| value == i as u32 * 2));
+    }
+}
@@ -0,0 +1,230 @@
+//! Watchdog of long frames - captures timings of engine systems when frame takes too long.
+//!
+//! Hitches which happen once in a while on machines of players are hard to reproduce, so
+//! watchdog collects data when they happen: if time between two consecutive calls of
+//! `Engine::update_with` exceeds threshold, a report is made with time spent in each part
+//! of engine during that frame (resources, game logic hooks, scenes, sound, user interface,
+//! rendering) and last lines of log. Time not spent in engine is reported as `Other`, it is
+//! game code outside of engine, waiting for vsync, or OS. Reports are written to log, saved
+//! to report directory (if set) and kept in memory to be sent with bug report by game.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use rg3d::{
+//!     engine::{Engine, watchdog::WatchdogSettings},
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn setup(engine: &mut Engine<(), StubNode>) {
+//!     engine.enable_watchdog(WatchdogSettings {
+//!         threshold: Duration::from_millis(100),
+//!         report_directory: Some("hitches".into()),
+//!         ..Default::default()
+//!     });
+//! }
+//!
+//! fn after_frame(engine: &mut Engine<(), StubNode>) {
+//!     for report in engine.take_long_frame_reports() {
+//!         println!("{}", report.to_text());
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use crate::utils::log::Log;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchdogSettings {
+    /// Frames longer than this are reported.
+    pub threshold: Duration,
+    /// Directory to save reports to, each report is a separate text file. `None` means
+    /// reports are only written to log and kept in memory.
+    pub report_directory: Option<PathBuf>,
+    /// Max amount of reports kept in memory, older reports are dropped.
+    pub max_reports: usize,
+    /// Amount of last lines of log included in report.
+    pub log_lines: usize,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_millis(100),
+            report_directory: None,
+            max_reports: 16,
+            log_lines: 32,
+        }
+    }
+}
+
+/// Data captured for long frame.
+#[derive(Clone, Debug)]
+pub struct LongFrameReport {
+    /// Index of frame since watchdog was enabled.
+    pub frame_index: u64,
+    /// Time when report was made.
+    pub time: SystemTime,
+    pub frame_time: Duration,
+    pub threshold: Duration,
+    /// Time spent in each part of engine in order of execution, the same part can be
+    /// present multiple times (for example, scenes with fixed time step).
+    pub timings: Vec<(&'static str, Duration)>,
+    /// Last lines of log at the moment of report.
+    pub recent_log: Vec<String>,
+}
+
+impl LongFrameReport {
+    /// Returns time of frame which was not spent in engine.
+    pub fn other_time(&self) -> Duration {
+        let engine_time = self.timings.iter().map(|(_, time)| *time).sum();
+        self.frame_time.checked_sub(engine_time).unwrap_or_default()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let timestamp = self.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        writeln!(out, "Long frame {} at {} (unix time)", self.frame_index, timestamp).unwrap();
+        writeln!(out, "Frame time: {:.2} ms, threshold: {:.2} ms",
+                 milliseconds(self.frame_time), milliseconds(self.threshold)).unwrap();
+        writeln!(out, "\nTimings:").unwrap();
+        for (name, time) in self.timings.iter() {
+            writeln!(out, "    {}: {:.2} ms", name, milliseconds(*time)).unwrap();
+        }
+        writeln!(out, "    Other: {:.2} ms", milliseconds(self.other_time())).unwrap();
+        writeln!(out, "\nRecent log:").unwrap();
+        for line in self.recent_log.iter() {
+            writeln!(out, "    {}", line).unwrap();
+        }
+        out
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// See module docs.
+pub struct Watchdog {
+    settings: WatchdogSettings,
+    frame_start: Option<Instant>,
+    frame_index: u64,
+    timings: Vec<(&'static str, Duration)>,
+    reports: Vec<LongFrameReport>,
+    total_reports: u64,
+}
+
+impl Watchdog {
+    pub fn new(settings: WatchdogSettings) -> Self {
+        Self {
+            settings,
+            frame_start: None,
+            frame_index: 0,
+            timings: Vec::new(),
+            reports: Vec::new(),
+            total_reports: 0,
+        }
+    }
+
+    pub fn settings(&self) -> &WatchdogSettings {
+        &self.settings
+    }
+
+    /// Finishes previous frame (makes report if it was too long) and starts new one. Called
+    /// by engine at the beginning of each update.
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(frame_start) = self.frame_start {
+            self.end_frame(now - frame_start);
+        }
+        self.frame_start = Some(now);
+    }
+
+    /// Adds time spent in given part of engine to current frame.
+    pub fn record(&mut self, name: &'static str, time: Duration) {
+        self.timings.push((name, time));
+    }
+
+    fn end_frame(&mut self, frame_time: Duration) {
+        if frame_time > self.settings.threshold {
+            let report = LongFrameReport {
+                frame_index: self.frame_index,
+                time: SystemTime::now(),
+                frame_time,
+                threshold: self.settings.threshold,
+                timings: self.timings.clone(),
+                recent_log: Log::recent(self.settings.log_lines),
+            };
+            // Report is logged after recent lines are captured, so it is not in its own log.
+            Log::writeln(format!("Long frame {}: {:.2} ms", self.frame_index, milliseconds(frame_time)));
+            if let Some(directory) = self.settings.report_directory.as_ref() {
+                let path = directory.join(format!("long_frame_{}.txt", self.total_reports));
+                if let Err(e) = std::fs::create_dir_all(directory).and_then(|_| report.save(&path)) {
+                    Log::writeln(format!("Unable to save long frame report to {}! Reason: {}", path.display(), e));
+                }
+            }
+            if self.reports.len() >= self.settings.max_reports {
+                self.reports.remove(0);
+            }
+            if self.settings.max_reports > 0 {
+                self.reports.push(report);
+            }
+            self.total_reports += 1;
+        }
+        self.timings.clear();
+        self.frame_index += 1;
+    }
+
+    /// Returns reports which are kept in memory, oldest first.
+    pub fn reports(&self) -> &[LongFrameReport] {
+        &self.reports
+    }
+
+    pub fn take_reports(&mut self) -> Vec<LongFrameReport> {
+        std::mem::take(&mut self.reports)
+    }
+
+    /// Returns amount of long frames since watchdog was created.
+    pub fn total_reports(&self) -> u64 {
+        self.total_reports
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::engine::watchdog::{Watchdog, WatchdogSettings};
+
+    #[test]
+    fn test_watchdog_reports_long_frames() {
+        let mut watchdog = Watchdog::new(WatchdogSettings {
+            threshold: Duration::from_millis(10),
+            max_reports: 1,
+            ..Default::default()
+        });
+
+        watchdog.record("Scenes", Duration::from_millis(2));
+        watchdog.end_frame(Duration::from_millis(5));
+        assert!(watchdog.reports().is_empty());
+
+        watchdog.record("Scenes", Duration::from_millis(15));
+        watchdog.record("Render", Duration::from_millis(3));
+        watchdog.end_frame(Duration::from_millis(20));
+        watchdog.end_frame(Duration::from_millis(30));
+        assert_eq!(watchdog.total_reports(), 2);
+
+        let reports = watchdog.take_reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].frame_index, 2);
+        assert!(reports[0].timings.is_empty());
+        assert_eq!(reports[0].other_time(), Duration::from_millis(30));
+        assert!(reports[0].to_text().contains("Other: 30.00 ms"));
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! Features:
 //! - Scene graph with pivot, camera, mesh, light, particle system, sprite nodes.
-//! - FBX Loader - both ASCII and binary. Note: Only 7100 - 7400 versions are supported!
+//! - FBX Loader - both ASCII and binary. Note: Only 7100 - 7700 versions are supported!
 //! - Advanced node-based UI with these widgets:
 //!     - Border
 //!     - Button
@@ -1,3 +1,7 @@
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
 use crate::{
     core::{
         scope_profile,
@@ -22,7 +26,7 @@ use crate::{
             },
             geometry_buffer::{
                 GeometryBuffer,
-                GeometryBufferKind,
+                DynamicBufferRing,
                 AttributeDefinition,
                 AttributeKind,
                 ElementKind
@@ -76,8 +80,8 @@ pub struct Line {
 }
 
 impl DebugRenderer {
-    pub(in crate) fn new(state: &mut State) -> Result<Self, RendererError> {
-        let geometry = GeometryBuffer::new(GeometryBufferKind::DynamicDraw, ElementKind::Line);
+    pub(in crate) fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry = GeometryBuffer::new_in_ring(state, ring, ElementKind::Line);
 
         geometry.bind(state)
             .describe_attributes(vec![
@@ -30,14 +30,17 @@ use crate::{
         error::RendererError,
         shadow_map_renderer::{
             PointShadowMapRenderContext,
+            SpotShadowMapRenderContext,
             SpotShadowMapRenderer,
             PointShadowMapRenderer,
         },
         QualitySettings,
+        ShadowFilter,
         RenderPassStatistics,
         GeometryCache,
         TextureCache,
         ssao::ScreenSpaceAmbientOcclusionRenderer,
+        ssr::ScreenSpaceReflectionRenderer,
     },
     scene::{
         camera::Camera,
@@ -64,6 +67,7 @@ struct AmbientLightShader {
     diffuse_texture: UniformLocation,
     ambient_color: UniformLocation,
     ao_sampler: UniformLocation,
+    ambient_texture: UniformLocation,
 }
 
 impl AmbientLightShader {
@@ -76,6 +80,7 @@ impl AmbientLightShader {
             diffuse_texture: program.uniform_location("diffuseTexture")?,
             ambient_color: program.uniform_location("ambientColor")?,
             ao_sampler: program.uniform_location("aoSampler")?,
+            ambient_texture: program.uniform_location("ambientTexture")?,
             program,
         })
     }
@@ -88,13 +93,21 @@ struct SpotLightShader {
     color_sampler: UniformLocation,
     normal_sampler: UniformLocation,
     spot_shadow_texture: UniformLocation,
+    spot_shadow_compare_texture: UniformLocation,
+    cookie_texture: UniformLocation,
+    cookie_enabled: UniformLocation,
     light_view_proj_matrix: UniformLocation,
     shadows_enabled: UniformLocation,
-    soft_shadows: UniformLocation,
+    shadow_filter: UniformLocation,
+    shadow_samples: UniformLocation,
+    esm_exponent: UniformLocation,
+    shadow_z_near: UniformLocation,
     shadow_map_inv_size: UniformLocation,
+    shadow_strength: UniformLocation,
     light_position: UniformLocation,
     light_radius: UniformLocation,
     light_color: UniformLocation,
+    light_intensity: UniformLocation,
     light_direction: UniformLocation,
     half_hotspot_cone_angle_cos: UniformLocation,
     half_cone_angle_cos: UniformLocation,
@@ -113,13 +126,21 @@ impl SpotLightShader {
             color_sampler: program.uniform_location("colorTexture")?,
             normal_sampler: program.uniform_location("normalTexture")?,
             spot_shadow_texture: program.uniform_location("spotShadowTexture")?,
+            spot_shadow_compare_texture: program.uniform_location("spotShadowCompareTexture")?,
+            cookie_texture: program.uniform_location("cookieTexture")?,
+            cookie_enabled: program.uniform_location("cookieEnabled")?,
             light_view_proj_matrix: program.uniform_location("lightViewProjMatrix")?,
             shadows_enabled: program.uniform_location("shadowsEnabled")?,
-            soft_shadows: program.uniform_location("softShadows")?,
+            shadow_filter: program.uniform_location("shadowFilter")?,
+            shadow_samples: program.uniform_location("shadowSamples")?,
+            esm_exponent: program.uniform_location("esmExponent")?,
+            shadow_z_near: program.uniform_location("shadowZNear")?,
             shadow_map_inv_size: program.uniform_location("shadowMapInvSize")?,
+            shadow_strength: program.uniform_location("shadowStrength")?,
             light_position: program.uniform_location("lightPos")?,
             light_radius: program.uniform_location("lightRadius")?,
             light_color: program.uniform_location("lightColor")?,
+            light_intensity: program.uniform_location("lightIntensity")?,
             light_direction: program.uniform_location("lightDirection")?,
             half_hotspot_cone_angle_cos: program.uniform_location("halfHotspotConeAngleCos")?,
             half_cone_angle_cos: program.uniform_location("halfConeAngleCos")?,
@@ -139,10 +160,15 @@ struct PointLightShader {
     normal_sampler: UniformLocation,
     point_shadow_texture: UniformLocation,
     shadows_enabled: UniformLocation,
-    soft_shadows: UniformLocation,
+    shadow_filter: UniformLocation,
+    shadow_samples: UniformLocation,
+    esm_exponent: UniformLocation,
+    shadow_map_inv_size: UniformLocation,
+    shadow_strength: UniformLocation,
     light_position: UniformLocation,
     light_radius: UniformLocation,
     light_color: UniformLocation,
+    light_intensity: UniformLocation,
     inv_view_proj_matrix: UniformLocation,
     camera_position: UniformLocation,
 }
@@ -159,10 +185,15 @@ impl PointLightShader {
             normal_sampler: program.uniform_location("normalTexture")?,
             point_shadow_texture: program.uniform_location("pointShadowTexture")?,
             shadows_enabled: program.uniform_location("shadowsEnabled")?,
-            soft_shadows: program.uniform_location("softShadows")?,
+            shadow_filter: program.uniform_location("shadowFilter")?,
+            shadow_samples: program.uniform_location("shadowSamples")?,
+            esm_exponent: program.uniform_location("esmExponent")?,
+            shadow_map_inv_size: program.uniform_location("shadowMapInvSize")?,
+            shadow_strength: program.uniform_location("shadowStrength")?,
             light_position: program.uniform_location("lightPos")?,
             light_radius: program.uniform_location("lightRadius")?,
             light_color: program.uniform_location("lightColor")?,
+            light_intensity: program.uniform_location("lightIntensity")?,
             inv_view_proj_matrix: program.uniform_location("invViewProj")?,
             camera_position: program.uniform_location("cameraPosition")?,
 
@@ -179,6 +210,7 @@ struct DirectionalLightShader {
     normal_sampler: UniformLocation,
     light_direction: UniformLocation,
     light_color: UniformLocation,
+    light_intensity: UniformLocation,
     inv_view_proj_matrix: UniformLocation,
     camera_position: UniformLocation,
 }
@@ -195,6 +227,7 @@ impl DirectionalLightShader {
             normal_sampler: program.uniform_location("normalTexture")?,
             light_direction: program.uniform_location("lightDirection")?,
             light_color: program.uniform_location("lightColor")?,
+            light_intensity: program.uniform_location("lightIntensity")?,
             inv_view_proj_matrix: program.uniform_location("invViewProj")?,
             camera_position: program.uniform_location("cameraPosition")?,
             program,
@@ -202,8 +235,22 @@ impl DirectionalLightShader {
     }
 }
 
+/// Distance to near clipping plane of projection of spot light shadow map.
+const SPOT_SHADOW_Z_NEAR: f32 = 0.01;
+
+/// Returns strength of shadows of a light at given distance from camera, it goes from one
+/// to zero in the last `fade_distance` units before max shadow distance.
+fn shadow_fade(distance: f32, max_distance: f32, fade_distance: f32) -> f32 {
+    if fade_distance <= 0.0 {
+        1.0
+    } else {
+        ((max_distance - distance) / fade_distance).min(1.0).max(0.0)
+    }
+}
+
 pub struct DeferredLightRenderer {
     pub ssao_renderer: ScreenSpaceAmbientOcclusionRenderer,
+    ssr_renderer: ScreenSpaceReflectionRenderer,
     spot_light_shader: SpotLightShader,
     point_light_shader: PointLightShader,
     directional_light_shader: DirectionalLightShader,
@@ -232,6 +279,11 @@ impl DeferredLightRenderer {
     pub fn new(state: &mut State, frame_size: (u32, u32), settings: &QualitySettings) -> Result<Self, RendererError> {
         Ok(Self {
             ssao_renderer: ScreenSpaceAmbientOcclusionRenderer::new(state, frame_size.0 as usize, frame_size.1 as usize)?,
+            ssr_renderer: {
+                let mut ssr_renderer = ScreenSpaceReflectionRenderer::new(state, frame_size.0 as usize, frame_size.1 as usize)?;
+                ssr_renderer.set_max_distance(settings.ssr_max_distance);
+                ssr_renderer
+            },
             spot_light_shader: SpotLightShader::new()?,
             point_light_shader: PointLightShader::new()?,
             directional_light_shader: DirectionalLightShader::new()?,
@@ -253,16 +305,19 @@ impl DeferredLightRenderer {
             self.point_shadow_map_renderer = PointShadowMapRenderer::new(state, settings.point_shadow_map_size)?;
         }
         self.ssao_renderer.set_radius(settings.ssao_radius);
+        self.ssr_renderer.set_max_distance(settings.ssr_max_distance);
         Ok(())
     }
 
     pub fn set_frame_size(&mut self, state: &mut State, frame_size: (u32, u32)) -> Result<(), RendererError> {
         self.ssao_renderer = ScreenSpaceAmbientOcclusionRenderer::new(state, frame_size.0 as usize, frame_size.1 as usize)?;
+        let max_distance = self.ssr_renderer.max_distance();
+        self.ssr_renderer = ScreenSpaceReflectionRenderer::new(state, frame_size.0 as usize, frame_size.1 as usize)?;
+        self.ssr_renderer.set_max_distance(max_distance);
         Ok(())
     }
 
-    #[must_use]
-    pub fn render(&mut self, args: DeferredRendererContext) -> RenderPassStatistics {
+    pub fn render(&mut self, args: DeferredRendererContext) -> Result<RenderPassStatistics, RendererError> {
         scope_profile!();
 
         let mut statistics = RenderPassStatistics::default();
@@ -273,6 +328,10 @@ impl DeferredLightRenderer {
             settings, textures, geometry_cache
         } = args;
 
+        // Settings may differ between cameras.
+        self.ssao_renderer.set_radius(settings.ssao_radius);
+        self.ssr_renderer.set_max_distance(settings.ssr_max_distance);
+
         let viewport = Rect::new(0, 0, gbuffer.width, gbuffer.height);
         let frustum = Frustum::from(camera.view_projection_matrix()).unwrap();
 
@@ -326,6 +385,10 @@ impl DeferredLightRenderer {
                     } else {
                         white_dummy.clone()
                     },
+                }),
+                (self.ambient_light_shader.ambient_texture, UniformValue::Sampler {
+                    index: 2,
+                    texture: gbuffer.ambient_texture(),
                 })
             ],
         );
@@ -333,8 +396,8 @@ impl DeferredLightRenderer {
         state.set_blend(true);
         state.set_blend_func(gl::ONE, gl::ONE);
 
-        for light in scene.graph.linear_iter().filter_map(|node| {
-            if let Node::Light(light) = node { Some(light) } else { None }
+        for (light_handle, light) in scene.graph.pair_iter().filter_map(|(handle, node)| {
+            if let Node::Light(light) = node { Some((handle, light)) } else { None }
         }) {
             if !light.global_visibility() {
                 continue;
@@ -357,15 +420,38 @@ impl DeferredLightRenderer {
                 continue;
             }
 
+            let fade = light.fade_factor(camera.global_position()) * light.flicker_factor();
+            if fade <= 0.0 {
+                continue;
+            }
+            let light_intensity = light.intensity() * fade;
+
+            // Skip lights that do not touch any visible surface, there is no need to render
+            // shadow maps and light volumes for them.
+            let affects_anything = match light.kind() {
+                LightKind::Directional => true,
+                LightKind::Spot(_) | LightKind::Point(_) => scene.graph.linear_iter().any(|node| {
+                    if let Node::Mesh(mesh) = node {
+                        mesh.global_visibility() && mesh.is_intersect_sphere(light_position, light_radius)
+                    } else {
+                        false
+                    }
+                })
+            };
+
+            if !affects_anything {
+                continue;
+            }
+
             let distance_to_camera = (light.global_position() - camera.global_position()).len();
 
-            let mut light_view_projection = Mat4::IDENTITY;
-            let shadows_enabled = light.is_cast_shadows() && match light.kind() {
-                LightKind::Spot(spot) if distance_to_camera <= settings.spot_shadows_distance && settings.spot_shadows_enabled => {
+            // View projection matrix of spot light is used for both shadows and cookie.
+            let light_view_projection = match light.kind() {
+                LightKind::Spot(spot) => {
                     let light_projection_matrix = Mat4::perspective(
                         spot.full_cone_angle(),
                         1.0,
-                        0.01,
+                        SPOT_SHADOW_Z_NEAR,
                         light_radius,
                     );
 
@@ -376,16 +462,24 @@ impl DeferredLightRenderer {
                     let light_view_matrix = Mat4::look_at(light_position, light_look_at, light_up_vec)
                         .unwrap_or_default();
 
-                    light_view_projection = light_projection_matrix * light_view_matrix;
+                    light_projection_matrix * light_view_matrix
+                }
+                _ => Mat4::IDENTITY
+            };
 
+            let shadows_enabled = light.is_cast_shadows() && match light.kind() {
+                LightKind::Spot(_) if distance_to_camera <= settings.spot_shadows_distance && settings.spot_shadows_enabled => {
                     statistics += self.spot_shadow_map_renderer.render(
-                        state,
-                        &scene.graph,
-                        &light_view_projection,
-                        white_dummy.clone(),
-                        textures,
-                        geometry_cache,
-                    );
+                        SpotShadowMapRenderContext {
+                            state,
+                            graph: &scene.graph,
+                            light_handle,
+                            light_view_projection: &light_view_projection,
+                            white_dummy: white_dummy.clone(),
+                            textures,
+                            geom_map: geometry_cache,
+                        }
+                    )?;
 
                     true
                 }
@@ -394,13 +488,14 @@ impl DeferredLightRenderer {
                         PointShadowMapRenderContext {
                             state,
                             graph: &scene.graph,
+                            light_handle,
                             white_dummy: white_dummy.clone(),
                             light_pos: light_position,
                             light_radius,
                             texture_cache: textures,
                             geom_cache: geometry_cache,
                         }
-                    );
+                    )?;
 
                     true
                 }
@@ -411,6 +506,12 @@ impl DeferredLightRenderer {
                 _ => false
             };
 
+            let shadow_strength = match light.kind() {
+                LightKind::Spot(_) => shadow_fade(distance_to_camera, settings.spot_shadows_distance, settings.shadows_fade_distance),
+                LightKind::Point(_) => shadow_fade(distance_to_camera, settings.point_shadows_distance, settings.shadows_fade_distance),
+                LightKind::Directional => 1.0,
+            };
+
             // Mark lighted areas in stencil buffer to do light calculations only on them.
             state.set_stencil_mask(0xFFFF_FFFF);
             state.set_stencil_func(StencilFunc { func: gl::ALWAYS, ..Default::default() });
@@ -482,15 +583,34 @@ impl DeferredLightRenderer {
                 LightKind::Spot(spot_light) => {
                     let shader = &self.spot_light_shader;
 
+                    let cookie_texture = spot_light.cookie_texture()
+                        .and_then(|texture| textures.get(state, texture));
+                    let cookie_enabled = cookie_texture.is_some();
+
+                    // Hardware filter samples shadow map through shadow sampler, regular one
+                    // gets dummy texture then, because the same texture must not be sampled
+                    // with and without comparison.
+                    let filter = settings.spot_shadow_filter;
+                    let hardware_pcf = filter == ShadowFilter::HardwarePcf;
+                    let shadow_texture = self.spot_shadow_map_renderer.texture();
+                    shadow_texture.borrow_mut()
+                        .bind_mut(state, 3)
+                        .set_depth_comparison(hardware_pcf);
+
                     let uniforms = [
                         (shader.shadows_enabled, UniformValue::Bool(shadows_enabled)),
                         (shader.light_view_proj_matrix, UniformValue::Mat4(light_view_projection)),
-                        (shader.soft_shadows, UniformValue::Bool(settings.spot_soft_shadows)),
+                        (shader.shadow_filter, UniformValue::Integer(filter.shader_index())),
+                        (shader.shadow_samples, UniformValue::Integer(filter.samples() as i32)),
+                        (shader.esm_exponent, UniformValue::Float(settings.shadows_esm_exponent)),
+                        (shader.shadow_z_near, UniformValue::Float(SPOT_SHADOW_Z_NEAR)),
+                        (shader.shadow_strength, UniformValue::Float(shadow_strength)),
                         (shader.light_position, UniformValue::Vec3(light_position)),
                         (shader.light_direction, UniformValue::Vec3(emit_direction)),
                         (shader.light_radius, UniformValue::Float(light_radius)),
                         (shader.inv_view_proj_matrix, UniformValue::Mat4(inv_view_projection)),
                         (shader.light_color, UniformValue::Color(light.color())),
+                        (shader.light_intensity, UniformValue::Float(light_intensity)),
                         (shader.half_hotspot_cone_angle_cos, UniformValue::Float((spot_light.hotspot_cone_angle() * 0.5).cos())),
                         (shader.half_cone_angle_cos, UniformValue::Float((spot_light.full_cone_angle() * 0.5).cos())),
                         (shader.wvp_matrix, UniformValue::Mat4(frame_matrix)),
@@ -499,7 +619,13 @@ impl DeferredLightRenderer {
                         (shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
                         (shader.color_sampler, UniformValue::Sampler { index: 1, texture: gbuffer.diffuse_texture() }),
                         (shader.normal_sampler, UniformValue::Sampler { index: 2, texture: gbuffer.normal_texture() }),
-                        (shader.spot_shadow_texture, UniformValue::Sampler { index: 3, texture: self.spot_shadow_map_renderer.texture() }),
+                        (shader.spot_shadow_texture, UniformValue::Sampler {
+                            index: 3,
+                            texture: if hardware_pcf { white_dummy.clone() } else { shadow_texture.clone() },
+                        }),
+                        (shader.spot_shadow_compare_texture, UniformValue::Sampler { index: 5, texture: shadow_texture }),
+                        (shader.cookie_enabled, UniformValue::Bool(cookie_enabled)),
+                        (shader.cookie_texture, UniformValue::Sampler { index: 4, texture: cookie_texture.unwrap_or_else(|| white_dummy.clone()) }),
                     ];
 
                     gbuffer.final_frame.draw(
@@ -513,13 +639,20 @@ impl DeferredLightRenderer {
                 LightKind::Point(_) => {
                     let shader = &self.point_light_shader;
 
+                    let filter = settings.point_shadow_filter.point_fallback();
+
                     let uniforms = [
                         (shader.shadows_enabled, UniformValue::Bool(shadows_enabled)),
-                        (shader.soft_shadows, UniformValue::Bool(settings.point_soft_shadows)),
+                        (shader.shadow_filter, UniformValue::Integer(filter.shader_index())),
+                        (shader.shadow_samples, UniformValue::Integer(filter.samples() as i32)),
+                        (shader.esm_exponent, UniformValue::Float(settings.shadows_esm_exponent)),
+                        (shader.shadow_map_inv_size, UniformValue::Float(1.0 / (self.point_shadow_map_renderer.size as f32))),
+                        (shader.shadow_strength, UniformValue::Float(shadow_strength)),
                         (shader.light_position, UniformValue::Vec3(light_position)),
                         (shader.light_radius, UniformValue::Float(light_radius)),
                         (shader.inv_view_proj_matrix, UniformValue::Mat4(inv_view_projection)),
                         (shader.light_color, UniformValue::Color(light.color())),
+                        (shader.light_intensity, UniformValue::Float(light_intensity)),
                         (shader.wvp_matrix, UniformValue::Mat4(frame_matrix)),
                         (shader.camera_position, UniformValue::Vec3(camera.global_position())),
                         (shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
@@ -543,6 +676,7 @@ impl DeferredLightRenderer {
                         (shader.light_direction, UniformValue::Vec3(emit_direction)),
                         (shader.inv_view_proj_matrix, UniformValue::Mat4(inv_view_projection)),
                         (shader.light_color, UniformValue::Color(light.color())),
+                        (shader.light_intensity, UniformValue::Float(light_intensity)),
                         (shader.wvp_matrix, UniformValue::Mat4(frame_matrix)),
                         (shader.camera_position, UniformValue::Vec3(camera.global_position())),
                         (shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
@@ -572,6 +706,7 @@ impl DeferredLightRenderer {
                 statistics += self.light_volume.render_volume(
                     state,
                     light,
+                    fade,
                     gbuffer,
                     &self.quad,
                     geometry_cache,
@@ -583,6 +718,17 @@ impl DeferredLightRenderer {
             }
         }
 
-        statistics
+        // Reflections must be traced when frame is fully lit.
+        if settings.use_ssr {
+            statistics += self.ssr_renderer.render(
+                state,
+                gbuffer,
+                geometry_cache,
+                projection_matrix,
+                camera.view_matrix().basis(),
+            );
+        }
+
+        Ok(statistics)
     }
 }
\ No newline at end of file
@@ -36,7 +36,15 @@ pub enum RendererError {
 
     FailedToConstructFBO,
 
-    Context(ContextError)
+    /// Means that node hierarchy passed to impostor baker, top-down capture or preview has
+    /// no meshes to render.
+    NothingToBake,
+
+    Context(ContextError),
+
+    /// Means that context was reset by driver (GPU hang, driver update, etc.) and all
+    /// objects of it are lost, see `Engine::recover_context`.
+    ContextLost,
 }
 
 impl From<NulError> for RendererError {
@@ -47,6 +55,9 @@ impl From<NulError> for RendererError {
 
 impl From<ContextError> for RendererError {
     fn from(err: ContextError) -> Self {
-        RendererError::Context(err)
+        match err {
+            ContextError::ContextLost => RendererError::ContextLost,
+            err => RendererError::Context(err),
+        }
     }
 }
\ No newline at end of file
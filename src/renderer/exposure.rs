@@ -0,0 +1,280 @@
+//! Maps HDR frame of camera to screen colors, see "Exposure" section of `scene::camera`
+//! module docs.
+//!
+//! Auto exposure works fully on GPU: average color of frame is taken from last mip level
+//! of frame texture, its luminance is blended with adapted luminance of previous frame and
+//! stored into 1x1 texture. Each camera has two such textures which are swapped every frame.
+
+use std::{
+    rc::Rc,
+    cell::RefCell,
+    collections::HashMap,
+};
+use crate::{
+    core::{
+        scope_profile,
+        math::{
+            mat4::Mat4,
+            Rect,
+            vec3::Vec3,
+        },
+        pool::Handle,
+    },
+    scene::{
+        node::Node,
+        camera::{Camera, Exposure},
+    },
+    renderer::{
+        framework::{
+            gpu_program::{
+                UniformValue,
+                GpuProgram,
+                UniformLocation,
+            },
+            framebuffer::{
+                DrawParameters,
+                CullFace,
+                FrameBuffer,
+                Attachment,
+                AttachmentKind,
+                FrameBufferTrait,
+            },
+            gpu_texture::{
+                GpuTexture,
+                GpuTextureKind,
+                PixelKind,
+                MininificationFilter,
+            },
+            state::State,
+        },
+        error::RendererError,
+        GeometryCache,
+        RenderPassStatistics,
+        surface::SurfaceSharedData,
+    },
+};
+
+struct AdaptationShader {
+    program: GpuProgram,
+    wvp_matrix: UniformLocation,
+    frame_texture: UniformLocation,
+    previous_luminance: UniformLocation,
+    adaptation: UniformLocation,
+}
+
+impl AdaptationShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/adaptation_fs.glsl");
+        let vertex_source = include_str!("shaders/flat_vs.glsl");
+        let program = GpuProgram::from_source("AdaptationShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            wvp_matrix: program.uniform_location("worldViewProjection")?,
+            frame_texture: program.uniform_location("frameTexture")?,
+            previous_luminance: program.uniform_location("previousLuminance")?,
+            adaptation: program.uniform_location("adaptation")?,
+            program,
+        })
+    }
+}
+
+struct ToneMapShader {
+    program: GpuProgram,
+    wvp_matrix: UniformLocation,
+    frame_texture: UniformLocation,
+    luminance_texture: UniformLocation,
+    auto_exposure: UniformLocation,
+    exposure: UniformLocation,
+    key_value: UniformLocation,
+    min_luminance: UniformLocation,
+    max_luminance: UniformLocation,
+}
+
+impl ToneMapShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/tone_map_fs.glsl");
+        let vertex_source = include_str!("shaders/flat_vs.glsl");
+        let program = GpuProgram::from_source("ToneMapShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            wvp_matrix: program.uniform_location("worldViewProjection")?,
+            frame_texture: program.uniform_location("frameTexture")?,
+            luminance_texture: program.uniform_location("luminanceTexture")?,
+            auto_exposure: program.uniform_location("autoExposure")?,
+            exposure: program.uniform_location("exposure")?,
+            key_value: program.uniform_location("keyValue")?,
+            min_luminance: program.uniform_location("minLuminance")?,
+            max_luminance: program.uniform_location("maxLuminance")?,
+            program,
+        })
+    }
+}
+
+/// Adapted luminance of one camera.
+struct Adaptation {
+    buffers: [FrameBuffer; 2],
+    current: usize,
+    initialized: bool,
+}
+
+impl Adaptation {
+    fn new(state: &mut State) -> Result<Self, RendererError> {
+        let mut make_buffer = || -> Result<FrameBuffer, RendererError> {
+            let kind = GpuTextureKind::Rectangle { width: 1, height: 1 };
+            let texture = GpuTexture::new(state, kind, PixelKind::F32, None)?;
+            FrameBuffer::new(state, None, vec![
+                Attachment {
+                    kind: AttachmentKind::Color,
+                    texture: Rc::new(RefCell::new(texture)),
+                }
+            ])
+        };
+        Ok(Self {
+            buffers: [make_buffer()?, make_buffer()?],
+            current: 0,
+            initialized: false,
+        })
+    }
+
+    fn luminance(&self) -> Rc<RefCell<GpuTexture>> {
+        self.buffers[self.current].color_attachments()[0].texture.clone()
+    }
+}
+
+pub struct ExposureRenderer {
+    adaptation_shader: AdaptationShader,
+    tone_map_shader: ToneMapShader,
+    quad: SurfaceSharedData,
+    adaptations: HashMap<Handle<Node>, Adaptation>,
+}
+
+pub struct ExposureRenderContext<'a, 'b, F: FrameBufferTrait> {
+    pub state: &'a mut State,
+    /// Back buffer of window or offscreen target of view.
+    pub backbuffer: &'a mut F,
+    pub camera: &'b Camera,
+    pub camera_handle: Handle<Node>,
+    /// HDR frame of camera.
+    pub frame_texture: Rc<RefCell<GpuTexture>>,
+    pub viewport: Rect<i32>,
+    pub dt: f32,
+    pub geometry_cache: &'a mut GeometryCache,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+}
+
+impl ExposureRenderer {
+    pub fn new() -> Result<Self, RendererError> {
+        Ok(Self {
+            adaptation_shader: AdaptationShader::new()?,
+            tone_map_shader: ToneMapShader::new()?,
+            quad: SurfaceSharedData::make_unit_xy_quad(),
+            adaptations: Default::default(),
+        })
+    }
+
+    /// Renders frame of camera into given frame buffer with exposure applied.
+    pub fn render<F: FrameBufferTrait>(&mut self, args: ExposureRenderContext<F>) -> Result<RenderPassStatistics, RendererError> {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let ExposureRenderContext {
+            state, backbuffer, camera, camera_handle,
+            frame_texture, viewport, dt, geometry_cache, white_dummy
+        } = args;
+
+        let draw_parameters = DrawParameters {
+            cull_face: CullFace::Back,
+            culling: false,
+            color_write: Default::default(),
+            depth_write: false,
+            stencil_test: false,
+            depth_test: false,
+            blend: false,
+        };
+
+        let exposure = camera.exposure();
+
+        let luminance = if let Exposure::Auto { adaptation_speed, .. } = exposure {
+            frame_texture.borrow_mut()
+                .bind_mut(state, 0)
+                .generate_mip_maps()
+                .set_minification_filter(MininificationFilter::LinearMip);
+
+            let adaptation = match self.adaptations.entry(camera_handle) {
+                std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                std::collections::hash_map::Entry::Vacant(entry) => entry.insert(Adaptation::new(state)?),
+            };
+
+            // First frame takes luminance as is, otherwise exposure would adapt from black.
+            let factor = if adaptation.initialized {
+                1.0 - (-dt * adaptation_speed.max(0.0)).exp()
+            } else {
+                1.0
+            };
+            adaptation.initialized = true;
+
+            let previous = adaptation.luminance();
+            adaptation.current = 1 - adaptation.current;
+
+            statistics += adaptation.buffers[adaptation.current].draw(
+                geometry_cache.get(state, &self.quad),
+                state,
+                Rect::new(0, 0, 1, 1),
+                &self.adaptation_shader.program,
+                draw_parameters,
+                &[
+                    (self.adaptation_shader.wvp_matrix, UniformValue::Mat4(Mat4::ortho(0.0, 1.0, 1.0, 0.0, -1.0, 1.0))),
+                    (self.adaptation_shader.frame_texture, UniformValue::Sampler {
+                        index: 0,
+                        texture: frame_texture.clone(),
+                    }),
+                    (self.adaptation_shader.previous_luminance, UniformValue::Sampler {
+                        index: 1,
+                        texture: previous,
+                    }),
+                    (self.adaptation_shader.adaptation, UniformValue::Float(factor)),
+                ],
+            );
+
+            Some(adaptation.luminance())
+        } else {
+            None
+        };
+
+        let (auto_exposure, manual_exposure, key_value, min_luminance, max_luminance) = match exposure {
+            Exposure::Auto { key_value, min_luminance, max_luminance, .. } => {
+                (true, 1.0, key_value, min_luminance.max(0.0001), max_luminance.max(0.0001))
+            }
+            Exposure::Manual(exposure) => (false, exposure, 0.0, 1.0, 1.0),
+        };
+
+        statistics += backbuffer.draw(
+            geometry_cache.get(state, &self.quad),
+            state,
+            viewport,
+            &self.tone_map_shader.program,
+            draw_parameters,
+            &[
+                (self.tone_map_shader.wvp_matrix, UniformValue::Mat4({
+                    Mat4::ortho(0.0, viewport.w as f32, viewport.h as f32, 0.0, -1.0, 1.0) *
+                        Mat4::scale(Vec3::new(viewport.w as f32, viewport.h as f32, 0.0))
+                })),
+                (self.tone_map_shader.frame_texture, UniformValue::Sampler {
+                    index: 0,
+                    texture: frame_texture,
+                }),
+                (self.tone_map_shader.luminance_texture, UniformValue::Sampler {
+                    index: 1,
+                    // Not used by shader when exposure is manual, but sampler must be bound.
+                    texture: luminance.unwrap_or(white_dummy),
+                }),
+                (self.tone_map_shader.auto_exposure, UniformValue::Bool(auto_exposure)),
+                (self.tone_map_shader.exposure, UniformValue::Float(manual_exposure)),
+                (self.tone_map_shader.key_value, UniformValue::Float(key_value)),
+                (self.tone_map_shader.min_luminance, UniformValue::Float(min_luminance)),
+                (self.tone_map_shader.max_luminance, UniformValue::Float(max_luminance)),
+            ],
+        );
+
+        Ok(statistics)
+    }
+}
@@ -0,0 +1,133 @@
+//! Frame arena - storage of temporary buffers which are reused from frame to frame.
+//!
+//! Renderer needs many short-living vectors each frame: snapshots of graphs, pending render
+//! lists, draw commands, etc. Allocating them anew every frame puts pressure on allocator
+//! and causes hitches when allocator decides to do some housekeeping. Instead, buffer is
+//! taken from arena, used during the frame and given back, arena keeps its memory (the
+//! buffer is cleared, capacity stays) and hands it out next time buffer of same type is
+//! needed. After a couple of frames every buffer has enough capacity and renderer stops
+//! allocating.
+//!
+//! Arena is reset at the beginning of each frame: statistics of previous frame are stored
+//! and buffers which were not used for many frames are released, so memory of rare peaks
+//! is not held forever.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+/// Amount of frames after which unused buffer is released.
+const MAX_IDLE_FRAMES: u32 = 120;
+
+struct FreeBuffer {
+    buffer: Box<dyn Any>,
+    idle_frames: u32,
+}
+
+/// Usage of frame arena during one frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameArenaStatistics {
+    /// Amount of buffers taken from arena.
+    pub taken: usize,
+    /// Amount of taken buffers which had to be created because arena had no free buffer of
+    /// requested type. Zero in steady state.
+    pub created: usize,
+    /// Amount of free buffers kept by arena.
+    pub free: usize,
+}
+
+/// See module docs.
+#[derive(Default)]
+pub struct FrameArena {
+    free: HashMap<TypeId, Vec<FreeBuffer>>,
+    current: FrameArenaStatistics,
+    last: FrameArenaStatistics,
+}
+
+impl FrameArena {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns empty vector, its capacity is left from previous use if there was any.
+    pub fn take<T: 'static>(&mut self) -> Vec<T> {
+        self.current.taken += 1;
+        let buffer = self.free
+            .get_mut(&TypeId::of::<Vec<T>>())
+            .and_then(|buffers| buffers.pop())
+            .and_then(|free| free.buffer.downcast::<Vec<T>>().ok());
+        match buffer {
+            Some(buffer) => *buffer,
+            None => {
+                self.current.created += 1;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Gives vector back to arena, vector is cleared, but its memory is kept for next
+    /// `take`.
+    pub fn recycle<T: 'static>(&mut self, mut buffer: Vec<T>) {
+        if buffer.capacity() == 0 {
+            return;
+        }
+        buffer.clear();
+        self.free
+            .entry(TypeId::of::<Vec<T>>())
+            .or_insert_with(Vec::new)
+            .push(FreeBuffer {
+                buffer: Box::new(buffer),
+                idle_frames: 0,
+            });
+    }
+
+    /// Finishes frame: remembers statistics and releases buffers which were idle for too
+    /// long. Called by renderer at the beginning of each frame.
+    pub fn reset(&mut self) {
+        for buffers in self.free.values_mut() {
+            for free in buffers.iter_mut() {
+                free.idle_frames += 1;
+            }
+            buffers.retain(|free| free.idle_frames <= MAX_IDLE_FRAMES);
+        }
+        self.free.retain(|_, buffers| !buffers.is_empty());
+
+        self.current.free = self.free.values().map(|buffers| buffers.len()).sum();
+        self.last = self.current;
+        self.current = Default::default();
+    }
+
+    /// Returns statistics of last finished frame.
+    pub fn statistics(&self) -> FrameArenaStatistics {
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renderer::frame_arena::FrameArena;
+
+    #[test]
+    fn test_frame_arena_reuse() {
+        let mut arena = FrameArena::new();
+
+        let mut buffer = arena.take::<u32>();
+        buffer.extend(0..100);
+        let capacity = buffer.capacity();
+        arena.recycle(buffer);
+        arena.reset();
+        assert_eq!(arena.statistics().created, 1);
+
+        let buffer = arena.take::<u32>();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+        // Buffers of other types are not mixed.
+        assert_eq!(arena.take::<u64>().capacity(), 0);
+        arena.recycle(buffer);
+        arena.reset();
+        assert_eq!(arena.statistics().taken, 2);
+        assert_eq!(arena.statistics().created, 1);
+        assert_eq!(arena.statistics().free, 1);
+    }
+}
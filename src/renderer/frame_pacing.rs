@@ -0,0 +1,146 @@
+//! Frame rate limiting and frame time measurement.
+//!
+//! Limiter uses hybrid approach to wait for next frame: it sleeps most of the time (sleep
+//! is cheap, but OS scheduler can wake thread up much later than requested) and spins for
+//! small amount of time before deadline to hit it precisely.
+
+use std::time::{Duration, Instant};
+
+/// Amount of frames used to calculate frame time percentiles.
+const HISTORY_SIZE: usize = 256;
+
+pub struct FrameLimiter {
+    frame_duration: Option<Duration>,
+    spin_threshold: Duration,
+    deadline: Instant,
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self {
+            frame_duration: None,
+            spin_threshold: Duration::from_millis(2),
+            deadline: Instant::now(),
+        }
+    }
+}
+
+impl FrameLimiter {
+    /// Sets desired maximum amount of frames per second. `None` disables limiter.
+    pub fn set_target_fps(&mut self, fps: Option<u32>) {
+        self.frame_duration = fps
+            .filter(|fps| *fps > 0)
+            .map(|fps| Duration::from_secs_f64(1.0 / f64::from(fps)));
+        self.deadline = Instant::now();
+    }
+
+    pub fn target_fps(&self) -> Option<u32> {
+        self.frame_duration.map(|d| (1.0 / d.as_secs_f64()).round() as u32)
+    }
+
+    /// Sets amount of time before deadline which limiter will spin instead of sleep.
+    /// Bigger values gives more precise timings, but burns more CPU time.
+    pub fn set_spin_threshold(&mut self, threshold: Duration) {
+        self.spin_threshold = threshold;
+    }
+
+    pub fn spin_threshold(&self) -> Duration {
+        self.spin_threshold
+    }
+
+    /// Blocks current thread until it is time to start next frame.
+    pub(in crate) fn wait(&mut self) {
+        let frame_duration = match self.frame_duration {
+            Some(frame_duration) => frame_duration,
+            None => return,
+        };
+
+        self.deadline += frame_duration;
+
+        let now = Instant::now();
+        if self.deadline <= now {
+            // We're late, do not try to catch up because it will produce burst of frames.
+            self.deadline = now;
+            return;
+        }
+
+        let remaining = self.deadline - now;
+        if remaining > self.spin_threshold {
+            std::thread::sleep(remaining - self.spin_threshold);
+        }
+
+        while Instant::now() < self.deadline {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// Ring buffer of recent frame times which is used to calculate percentiles.
+pub struct FrameTimeHistory {
+    samples: Vec<f32>,
+    position: usize,
+    last_frame_time: Option<Instant>,
+}
+
+impl Default for FrameTimeHistory {
+    fn default() -> Self {
+        Self {
+            samples: Vec::with_capacity(HISTORY_SIZE),
+            position: 0,
+            last_frame_time: None,
+        }
+    }
+}
+
+impl FrameTimeHistory {
+    /// Registers end of frame. Time between two consecutive calls is frame time.
+    pub(in crate) fn push_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame_time) = self.last_frame_time {
+            let frame_time = now.duration_since(last_frame_time).as_secs_f32();
+            if self.samples.len() < HISTORY_SIZE {
+                self.samples.push(frame_time);
+            } else {
+                self.samples[self.position] = frame_time;
+            }
+            self.position = (self.position + 1) % HISTORY_SIZE;
+        }
+        self.last_frame_time = Some(now);
+    }
+
+    /// Calculates frame time percentiles of recent frames.
+    pub fn percentiles(&self) -> FrameTimePercentiles {
+        if self.samples.is_empty() {
+            return Default::default();
+        }
+
+        let mut sorted = self.samples.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let percentile = |p: f32| {
+            let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+            sorted[index]
+        };
+
+        FrameTimePercentiles {
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Frame times (in seconds) of recent frames. Percentiles are much more useful to
+/// tune frame pacing than average frame rate, because they show stutters.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameTimePercentiles {
+    /// Median frame time.
+    pub p50: f32,
+    /// 95% of frames were rendered faster than this time.
+    pub p95: f32,
+    /// 99% of frames were rendered faster than this time.
+    pub p99: f32,
+    /// Longest frame.
+    pub max: f32,
+}
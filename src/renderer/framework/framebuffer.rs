@@ -1,6 +1,7 @@
 use std::{
     rc::Rc,
     cell::RefCell,
+    ffi::c_void,
 };
 use crate::{
     core::{
@@ -22,8 +23,13 @@ use crate::{
                 self,
             },
             gpu_program::{UniformLocation, GpuProgram, UniformValue},
-            state::State,
-            state::ColorMask
+            state::{
+                State,
+                ColorMask,
+                ObjectKind,
+                notify_deleted,
+                is_context_alive,
+            },
         }
     }
 };
@@ -100,6 +106,9 @@ unsafe fn set_attachment(gl_attachment_kind: u32, texture: &GpuTexture) {
         GpuTextureKind::Volume { .. } => {
             gl::FramebufferTexture3D(gl::FRAMEBUFFER, gl_attachment_kind, gl::TEXTURE_3D, texture.id(), 0, 0);
         }
+        GpuTextureKind::Array { .. } => {
+            gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl_attachment_kind, texture.id(), 0, 0);
+        }
     }
 }
 
@@ -157,6 +166,36 @@ impl FrameBuffer {
         self.depth_attachment.as_ref()
     }
 
+    /// Clears color attachment with integer pixels to given value, regular `clear` can't be
+    /// used for them because clear color is converted to floats.
+    pub fn clear_uint_attachment(&mut self, state: &mut State, viewport: Rect<i32>, attachment_index: usize, value: [u32; 4]) {
+        state.set_viewport(viewport);
+        state.set_framebuffer(self.fbo);
+        state.set_color_write(ColorMask::default());
+
+        unsafe {
+            gl::ClearBufferuiv(gl::COLOR, attachment_index as i32, value.as_ptr());
+        }
+    }
+
+    /// Reads back single pixel of color attachment, (0; 0) is left bottom corner. This call
+    /// stalls pipeline until attachment is rendered, so it must be used only on demand.
+    pub fn read_pixel(&self, state: &mut State, attachment_index: usize, x: i32, y: i32) -> Vec<u8> {
+        let pixel_kind = self.color_attachments[attachment_index].texture.borrow().pixel_kind();
+        let (type_, format, _) = pixel_kind.gl_formats();
+        let mut pixel = vec![0u8; pixel_kind.size_bytes()];
+
+        state.set_framebuffer(self.fbo);
+
+        unsafe {
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment_index as u32);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, pixel_kind.unpack_alignment());
+            gl::ReadPixels(x, y, 1, 1, format, type_, pixel.as_mut_ptr() as *mut c_void);
+        }
+
+        pixel
+    }
+
     pub fn set_cubemap_face(&mut self, state: &mut State, attachment_index: usize, face: CubeMapFace) -> &mut Self {
         unsafe {
             state.set_framebuffer(self.fbo);
@@ -234,6 +273,33 @@ pub trait FrameBufferTrait {
         }
     }
 
+    /// Copies region of attachments into the same region of `dest`, formats of attachments
+    /// must match.
+    fn blit_to<F: FrameBufferTrait>(&self, state: &mut State, dest: &mut F, region: Rect<i32>, color: bool, depth: bool) {
+        scope_profile!();
+
+        let mut mask = 0;
+        if color {
+            mask |= gl::COLOR_BUFFER_BIT;
+        }
+        if depth {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+
+        state.set_framebuffer(dest.id());
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id());
+            gl::BlitFramebuffer(
+                region.x, region.y, region.x + region.w, region.y + region.h,
+                region.x, region.y, region.x + region.w, region.y + region.h,
+                mask, gl::NEAREST,
+            );
+            // Restore read binding, state tracks both bindings as one.
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, dest.id());
+        }
+    }
+
     fn draw<T>(&mut self,
                geometry: &GeometryBuffer<T>,
                state: &mut State,
@@ -248,6 +314,21 @@ pub trait FrameBufferTrait {
         geometry.bind(state).draw()
     }
 
+    fn draw_instanced<T>(&mut self,
+                         instance_count: usize,
+                         geometry: &GeometryBuffer<T>,
+                         state: &mut State,
+                         viewport: Rect<i32>,
+                         program: &GpuProgram,
+                         params: DrawParameters,
+                         uniforms: &[(UniformLocation, UniformValue<'_>)],
+    ) -> DrawCallStatistics {
+        scope_profile!();
+
+        pre_draw(self.id(), state, viewport, program, params, uniforms);
+        geometry.bind(state).draw_instanced(instance_count)
+    }
+
     fn draw_part<T>(&mut self, args: DrawPartContext<T>) -> Result<DrawCallStatistics, RendererError> {
         scope_profile!();
 
@@ -271,18 +352,79 @@ impl FrameBufferTrait for FrameBuffer {
     }
 }
 
-pub struct BackBuffer;
+/// Default frame buffer of context, or frame buffer object of host application if engine is
+/// embedded.
+#[derive(Copy, Clone, Default)]
+pub struct BackBuffer {
+    fbo: GLuint,
+}
+
+impl BackBuffer {
+    pub fn new(fbo: GLuint) -> Self {
+        Self { fbo }
+    }
+}
 
 impl FrameBufferTrait for BackBuffer {
     fn id(&self) -> u32 {
-        0
+        self.fbo
     }
 }
 
 impl Drop for FrameBuffer {
     fn drop(&mut self) {
+        if !is_context_alive() {
+            return;
+        }
+
         unsafe {
             gl::DeleteFramebuffers(1, &self.fbo);
+            notify_deleted(ObjectKind::FrameBuffer, self.fbo);
+        }
+    }
+}
+
+/// Frame buffer object of other context, which reads color texture shared with main context
+/// and copies it to back buffer of that context. Frame buffer objects are not shared between
+/// contexts, so each context needs its own. All methods must be called when that context is
+/// current, they bypass `State` because it tracks state of main context.
+#[derive(Default)]
+pub struct SharedTextureReader {
+    fbo: GLuint,
+    texture: GLuint,
+}
+
+impl SharedTextureReader {
+    /// Copies given texture to back buffer of current context, texture is stretched to
+    /// size of back buffer.
+    pub fn blit_to_back_buffer(&mut self, texture: &GpuTexture, source_size: (u32, u32), dest_size: (u32, u32)) {
+        unsafe {
+            if self.fbo == 0 {
+                gl::GenFramebuffers(1, &mut self.fbo);
+            }
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            if self.texture != texture.id() {
+                self.texture = texture.id();
+                gl::FramebufferTexture2D(gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.texture, 0);
+            }
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::BlitFramebuffer(
+                0, 0, source_size.0 as i32, source_size.1 as i32,
+                0, 0, dest_size.0 as i32, dest_size.1 as i32,
+                gl::COLOR_BUFFER_BIT, gl::LINEAR,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Deletes frame buffer object, context must be current.
+    pub fn destroy(&mut self) {
+        if self.fbo != 0 {
+            unsafe {
+                gl::DeleteFramebuffers(1, &self.fbo);
+            }
+            self.fbo = 0;
+            self.texture = 0;
         }
     }
 }
@@ -2,7 +2,8 @@ use std::{
     marker::PhantomData,
     mem::size_of,
     ffi::c_void,
-    cell::Cell,
+    cell::{Cell, RefCell},
+    rc::Rc,
 };
 use crate::{
     renderer::{
@@ -16,7 +17,12 @@ use crate::{
     core::scope_profile,
     utils::log::Log,
 };
-use crate::renderer::framework::state::State;
+use crate::renderer::framework::state::{
+    State,
+    ObjectKind,
+    notify_deleted,
+    is_context_alive,
+};
 
 /// Safe wrapper over OpenGL's Vertex Array Objects for interleaved vertices (where
 /// position, normal, etc. stored together, not in separate arrays)
@@ -29,10 +35,140 @@ pub struct GeometryBuffer<T> {
     kind: GeometryBufferKind,
     element_count: Cell<usize>,
     element_kind: ElementKind,
+    /// Ring which holds data of buffer, if any. Such buffer does not own its vertex and
+    /// element buffer objects.
+    ring: Option<Rc<RefCell<DynamicBufferRing>>>,
+    /// Index of first vertex of buffer in ring.
+    base_vertex: Cell<usize>,
+    /// Index of first element index of buffer in ring.
+    first_index: Cell<usize>,
+    /// Element buffer binding is part of state of vertex array object, so it is enough to
+    /// bind element buffer once.
+    element_buffer_attached: Cell<bool>,
+    // Force compiler to not implement Send and Sync, because OpenGL is not thread-safe.
+    thread_mark: PhantomData<*const u8>,
+}
+
+/// Vertex and element memory shared by all small dynamic draws (sprites, particles, debug
+/// lines, UI, etc.). Instead of having a buffer per renderer which is fully re-specified
+/// on every upload, data is appended to the ring and written through unsynchronized
+/// mapping, so driver does not have to wait until GPU is done with previous draws. When
+/// ring is full, its storage is orphaned - driver gives new memory while old one is still
+/// used by pending draws - and writing starts from the beginning.
+///
+/// Data uploaded to the ring is valid until next upload of any of its users, so each
+/// user must draw its data right after upload.
+pub struct DynamicBufferRing {
+    vertex_buffer_object: GLuint,
+    element_buffer_object: GLuint,
+    vertex_capacity: usize,
+    element_capacity: usize,
+    vertex_cursor: usize,
+    element_cursor: usize,
     // Force compiler to not implement Send and Sync, because OpenGL is not thread-safe.
     thread_mark: PhantomData<*const u8>,
 }
 
+impl DynamicBufferRing {
+    /// Initial size in bytes of both vertex and element memory.
+    const INITIAL_CAPACITY: usize = 4 * 1024 * 1024;
+
+    pub fn new(state: &mut State) -> Self {
+        unsafe {
+            let mut vbo = 0;
+            gl::GenBuffers(1, &mut vbo);
+
+            let mut ebo = 0;
+            gl::GenBuffers(1, &mut ebo);
+
+            Log::writeln(format!("GL dynamic buffer ring was created - VBO: {}, EBO: {}!", vbo, ebo));
+
+            // Element array binding is part of state of vertex array object, so storage of
+            // both buffers is allocated through array buffer target.
+            for &buffer in [ebo, vbo].iter() {
+                state.set_vertex_buffer_object(buffer);
+                gl::BufferData(gl::ARRAY_BUFFER, Self::INITIAL_CAPACITY as isize, std::ptr::null(), gl::STREAM_DRAW);
+            }
+
+            Self {
+                vertex_buffer_object: vbo,
+                element_buffer_object: ebo,
+                vertex_capacity: Self::INITIAL_CAPACITY,
+                element_capacity: Self::INITIAL_CAPACITY,
+                vertex_cursor: 0,
+                element_cursor: 0,
+                thread_mark: PhantomData,
+            }
+        }
+    }
+
+    /// Writes data into bound buffer of given target at cursor aligned to `alignment`,
+    /// returns offset in bytes of written data.
+    unsafe fn write(target: GLuint, capacity: &mut usize, cursor: &mut usize, alignment: usize, data: *const c_void, size: usize) -> usize {
+        let mut offset = (*cursor + alignment - 1) / alignment * alignment;
+
+        if offset + size > *capacity {
+            if size > *capacity {
+                *capacity = (size * 2).next_power_of_two();
+            }
+            // Orphan storage, pending draws still use old one.
+            gl::BufferData(target, *capacity as isize, std::ptr::null(), gl::STREAM_DRAW);
+            offset = 0;
+        }
+
+        if size > 0 {
+            let access = gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT;
+            let ptr = gl::MapBufferRange(target, offset as isize, size as isize, access);
+            if !ptr.is_null() {
+                std::ptr::copy_nonoverlapping(data as *const u8, ptr as *mut u8, size);
+            }
+            gl::UnmapBuffer(target);
+        }
+
+        *cursor = offset + size;
+
+        offset
+    }
+
+    /// Writes vertices, vertex array object of user must be bound. Returns index of first
+    /// written vertex.
+    fn write_vertices<T>(&mut self, state: &mut State, vertices: &[T]) -> usize {
+        state.set_vertex_buffer_object(self.vertex_buffer_object);
+        let vertex_size = size_of::<T>().max(1);
+        let offset = unsafe {
+            Self::write(gl::ARRAY_BUFFER, &mut self.vertex_capacity, &mut self.vertex_cursor,
+                        vertex_size, vertices.as_ptr() as *const c_void, vertices.len() * vertex_size)
+        };
+        offset / vertex_size
+    }
+
+    /// Writes element indices, vertex array object of user must be bound. Returns position
+    /// of first written index.
+    unsafe fn write_elements(&mut self, elements: *const c_void, size: usize) -> usize {
+        let offset = Self::write(gl::ELEMENT_ARRAY_BUFFER, &mut self.element_capacity, &mut self.element_cursor,
+                                 size_of::<u32>(), elements, size);
+        offset / size_of::<u32>()
+    }
+}
+
+impl Drop for DynamicBufferRing {
+    fn drop(&mut self) {
+        if !is_context_alive() {
+            return;
+        }
+
+        unsafe {
+            Log::writeln(format!("GL dynamic buffer ring was destroyed - VBO: {}, EBO: {}!",
+                                 self.vertex_buffer_object, self.element_buffer_object));
+
+            gl::DeleteBuffers(1, &self.vertex_buffer_object);
+            gl::DeleteBuffers(1, &self.element_buffer_object);
+        }
+        notify_deleted(ObjectKind::Buffer, self.vertex_buffer_object);
+        notify_deleted(ObjectKind::Buffer, self.element_buffer_object);
+    }
+}
+
 #[derive(Copy, Clone)]
 #[allow(dead_code)]
 pub enum AttributeKind {
@@ -157,7 +293,8 @@ impl ElementKind {
 }
 
 pub struct GeometryBufferBinding<'a, T> {
-    buffer: &'a GeometryBuffer<T>
+    buffer: &'a GeometryBuffer<T>,
+    state: &'a mut State,
 }
 
 #[derive(Copy, Clone)]
@@ -176,6 +313,12 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
     pub fn set_vertices(self, vertices: &[T]) -> Self {
         scope_profile!();
 
+        if let Some(ring) = self.buffer.ring.as_ref() {
+            let base_vertex = ring.borrow_mut().write_vertices(self.state, vertices);
+            self.buffer.base_vertex.set(base_vertex);
+            return self;
+        }
+
         let size = (vertices.len() * size_of::<T>()) as isize;
         let data = vertices.as_ptr() as *const c_void;
         let usage = self.get_usage();
@@ -248,6 +391,12 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
     unsafe fn set_elements(&self, elements: *const c_void, size: isize) {
         scope_profile!();
 
+        if let Some(ring) = self.buffer.ring.as_ref() {
+            let first_index = ring.borrow_mut().write_elements(elements, size as usize);
+            self.buffer.first_index.set(first_index);
+            return;
+        }
+
         let usage = self.get_usage();
         gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, elements, usage);
     }
@@ -293,12 +442,32 @@ impl<'a, T> GeometryBufferBinding<'a, T> {
         DrawCallStatistics { triangles: self.buffer.element_count.get() }
     }
 
+    /// Draws all elements of buffer `instance_count` times using single draw call, shader
+    /// can distinguish instances by `gl_InstanceID`.
+    pub fn draw_instanced(&self, instance_count: usize) -> DrawCallStatistics {
+        scope_profile!();
+
+        let index_per_element = self.buffer.element_kind.index_per_element();
+        let index_count = self.buffer.element_count.get() * index_per_element;
+
+        if index_count > 0 && instance_count > 0 {
+            let indices = (self.buffer.first_index.get() * size_of::<u32>()) as *const c_void;
+            unsafe {
+                gl::DrawElementsInstancedBaseVertex(self.mode(), index_count as i32, gl::UNSIGNED_INT, indices,
+                                                    instance_count as i32, self.buffer.base_vertex.get() as i32);
+            }
+        }
+
+        DrawCallStatistics { triangles: self.buffer.element_count.get() * instance_count }
+    }
+
     unsafe fn draw_internal(&self, start_index: usize, index_count: usize) {
         scope_profile!();
 
         if index_count > 0 {
-            let indices = (start_index * size_of::<u32>()) as *const c_void;
-            gl::DrawElements(self.mode(), index_count as i32, gl::UNSIGNED_INT, indices);
+            let indices = ((self.buffer.first_index.get() + start_index) * size_of::<u32>()) as *const c_void;
+            gl::DrawElementsBaseVertex(self.mode(), index_count as i32, gl::UNSIGNED_INT, indices,
+                                       self.buffer.base_vertex.get() as i32);
         }
     }
 }
@@ -327,12 +496,54 @@ impl<T> GeometryBuffer<T> where T: Sized {
                 kind,
                 element_count: Cell::new(0),
                 element_kind,
+                ring: None,
+                base_vertex: Cell::new(0),
+                first_index: Cell::new(0),
+                element_buffer_attached: Cell::new(false),
                 thread_mark: PhantomData,
             }
         }
     }
 
-    pub fn bind(&self, state: &mut State) -> GeometryBufferBinding<'_, T> {
+    /// Creates buffer which keeps its data in given ring, see `DynamicBufferRing`. Attributes
+    /// must be described as usual.
+    pub fn new_in_ring(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>, element_kind: ElementKind) -> Self {
+        unsafe {
+            scope_profile!();
+
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao);
+
+            let (vbo, ebo) = {
+                let ring = ring.borrow();
+                (ring.vertex_buffer_object, ring.element_buffer_object)
+            };
+
+            // Attach element buffer of ring to vertex array object once, binding is stored
+            // in vertex array object.
+            state.set_vertex_array_object(vao);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+
+            Log::writeln(format!("GL geometry buffer in ring was created - VAO: {}!", vao));
+
+            Self {
+                vertex_array_object: vao,
+                vertex_buffer_object: vbo,
+                element_buffer_object: ebo,
+                meta: PhantomData,
+                kind: GeometryBufferKind::DynamicDraw,
+                element_count: Cell::new(0),
+                element_kind,
+                ring: Some(ring),
+                base_vertex: Cell::new(0),
+                first_index: Cell::new(0),
+                element_buffer_attached: Cell::new(true),
+                thread_mark: PhantomData,
+            }
+        }
+    }
+
+    pub fn bind<'a>(&'a self, state: &'a mut State) -> GeometryBufferBinding<'a, T> {
         scope_profile!();
 
         state.set_vertex_array_object(self.vertex_array_object);
@@ -340,18 +551,36 @@ impl<T> GeometryBuffer<T> where T: Sized {
 
         // Element buffer object binding is stored inside vertex array object, so
         // it does not modified state.
-        unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.element_buffer_object);
+        if !self.element_buffer_attached.get() {
+            unsafe {
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.element_buffer_object);
+            }
+            self.element_buffer_attached.set(true);
         }
 
         GeometryBufferBinding {
-            buffer: self
+            buffer: self,
+            state,
         }
     }
 }
 
 impl<T> Drop for GeometryBuffer<T> {
     fn drop(&mut self) {
+        if !is_context_alive() {
+            return;
+        }
+
+        if self.ring.is_some() {
+            unsafe {
+                Log::writeln(format!("GL geometry buffer in ring was destroyed - VAO: {}!", self.vertex_array_object));
+
+                gl::DeleteVertexArrays(1, &self.vertex_array_object);
+            }
+            notify_deleted(ObjectKind::VertexArray, self.vertex_array_object);
+            return;
+        }
+
         unsafe {
             Log::writeln(format!("GL geometry buffer was destroyed - VBO: {}, EBO: {}, VAO: {}!",
                                  self.vertex_buffer_object, self.element_buffer_object, self.vertex_array_object));
@@ -360,5 +589,8 @@ impl<T> Drop for GeometryBuffer<T> {
             gl::DeleteBuffers(1, &self.element_buffer_object);
             gl::DeleteVertexArrays(1, &self.vertex_array_object);
         }
+        notify_deleted(ObjectKind::Buffer, self.vertex_buffer_object);
+        notify_deleted(ObjectKind::Buffer, self.element_buffer_object);
+        notify_deleted(ObjectKind::VertexArray, self.vertex_array_object);
     }
 }
\ No newline at end of file
@@ -2,7 +2,8 @@ use std::{
     ffi::CString,
     marker::PhantomData,
     rc::Rc,
-    cell::RefCell
+    cell::{RefCell, Cell},
+    collections::HashMap,
 };
 use crate::{
     core::{
@@ -25,7 +26,14 @@ use crate::{
                     GLint,
                 }
             },
-            state::State
+            state::{
+                State,
+                ObjectKind,
+                LogDepth,
+                notify_deleted,
+                is_context_alive,
+            },
+            program_cache,
         }
     },
     utils::log::Log,
@@ -35,6 +43,14 @@ use rg3d_core::math::mat3::Mat3;
 pub struct GpuProgram {
     id: GLuint,
     name_buf: RefCell<Vec<u8>>,
+    /// Texture units assigned to samplers, uniforms are part of state of program so there
+    /// is no need to assign same unit on every draw.
+    sampler_units: RefCell<HashMap<GLint, usize>>,
+    /// Locations of global uniforms of logarithmic depth (-1 if program does not use them)
+    /// and last values passed to them.
+    log_depth_coefficient: GLint,
+    log_depth_projection: GLint,
+    log_depth: Cell<Option<LogDepth>>,
     // Force compiler to not implement Send and Sync, because OpenGL is not thread-safe.
     thread_mark: PhantomData<*const u8>,
 }
@@ -71,12 +87,10 @@ pub enum UniformValue<'a> {
     Mat4Array(&'a [Mat4])
 }
 
-fn create_shader(name: String, actual_type: GLuint, source: &str) -> Result<GLuint, RendererError> {
+fn create_shader(name: String, actual_type: GLuint, source: &CString) -> Result<GLuint, RendererError> {
     unsafe {
-        let csource = prepare_source_code(source)?;
-
         let shader = gl::CreateShader(actual_type);
-        gl::ShaderSource(shader, 1, &csource.as_ptr(), std::ptr::null());
+        gl::ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
         gl::CompileShader(shader);
 
         let mut status = 1;
@@ -116,37 +130,66 @@ fn prepare_source_code(code: &str) -> Result<CString, RendererError> {
     }
 }
 
-impl GpuProgram {
-    pub fn from_source(name: &str, vertex_source: &str, fragment_source: &str) -> Result<GpuProgram, RendererError> {
-        unsafe {
-            let vertex_shader = create_shader(format!("{}_VertexShader", name), gl::VERTEX_SHADER, vertex_source)?;
-            let fragment_shader = create_shader(format!("{}_FragmentShader", name), gl::FRAGMENT_SHADER, fragment_source)?;
-            let program: GLuint = gl::CreateProgram();
-            gl::AttachShader(program, vertex_shader);
-            gl::DeleteShader(vertex_shader);
-            gl::AttachShader(program, fragment_shader);
-            gl::DeleteShader(fragment_shader);
-            gl::LinkProgram(program);
-            let mut status = 1;
-            gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
-            if status == 0 {
-                let mut log_len = 0;
-                gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut log_len);
-                let mut buffer: Vec<u8> = Vec::with_capacity(log_len as usize);
-                gl::GetProgramInfoLog(program, log_len, std::ptr::null_mut(), buffer.as_mut_ptr() as *mut i8);
-                Err(RendererError::ShaderLinkingFailed {
-                    shader_name: name.to_owned(),
-                    error_message: String::from_utf8_unchecked(buffer),
-                })
-            } else {
-                Ok(Self {
-                    id: program,
-                    name_buf: Default::default(),
-                    thread_mark: PhantomData,
-                })
+fn link_program(name: &str, vertex_source: &CString, fragment_source: &CString, key: u64) -> Result<GLuint, RendererError> {
+    unsafe {
+        let vertex_shader = create_shader(format!("{}_VertexShader", name), gl::VERTEX_SHADER, vertex_source)?;
+        let fragment_shader = create_shader(format!("{}_FragmentShader", name), gl::FRAGMENT_SHADER, fragment_source)?;
+        let program: GLuint = gl::CreateProgram();
+        gl::AttachShader(program, vertex_shader);
+        gl::DeleteShader(vertex_shader);
+        gl::AttachShader(program, fragment_shader);
+        gl::DeleteShader(fragment_shader);
+        let cache_binary = program_cache::is_enabled();
+        if cache_binary {
+            gl::ProgramParameteri(program, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as GLint);
+        }
+        gl::LinkProgram(program);
+        let mut status = 1;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status == 0 {
+            let mut log_len = 0;
+            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut log_len);
+            let mut buffer: Vec<u8> = Vec::with_capacity(log_len as usize);
+            gl::GetProgramInfoLog(program, log_len, std::ptr::null_mut(), buffer.as_mut_ptr() as *mut i8);
+            Err(RendererError::ShaderLinkingFailed {
+                shader_name: name.to_owned(),
+                error_message: String::from_utf8_unchecked(buffer),
+            })
+        } else {
+            if cache_binary {
+                program_cache::store(key, program);
             }
+            Ok(program)
         }
     }
+}
+
+impl GpuProgram {
+    /// Creates program from sources of vertex and fragment shaders. Program is taken from
+    /// on-disk cache if it is enabled and has binary for same sources, see `program_cache`.
+    pub fn from_source(name: &str, vertex_source: &str, fragment_source: &str) -> Result<GpuProgram, RendererError> {
+        let vertex_source = prepare_source_code(vertex_source)?;
+        let fragment_source = prepare_source_code(fragment_source)?;
+        let key = program_cache::program_key(name, &vertex_source, &fragment_source);
+        let (id, from_cache) = match program_cache::load(key) {
+            Some(id) => (id, true),
+            None => (link_program(name, &vertex_source, &fragment_source, key)?, false),
+        };
+        program_cache::notify_ready(name, from_cache);
+        let (log_depth_coefficient, log_depth_projection) = unsafe {
+            (gl::GetUniformLocation(id, b"S_LogDepthCoefficient\0".as_ptr() as *const i8),
+             gl::GetUniformLocation(id, b"S_LogDepthProjection\0".as_ptr() as *const i8))
+        };
+        Ok(Self {
+            id,
+            name_buf: Default::default(),
+            sampler_units: Default::default(),
+            log_depth_coefficient,
+            log_depth_projection,
+            log_depth: Cell::new(None),
+            thread_mark: PhantomData,
+        })
+    }
 
     pub fn uniform_location(&self, name: &str) -> Result<UniformLocation, RendererError> {
         // Form c string in special buffer to reduce memory allocations
@@ -166,6 +209,17 @@ impl GpuProgram {
 
     pub fn bind(&self, state: &mut State) {
         state.set_program(self.id);
+
+        if self.log_depth_coefficient >= 0 || self.log_depth_projection >= 0 {
+            let log_depth = state.log_depth();
+            if self.log_depth.get() != Some(log_depth) {
+                self.log_depth.set(Some(log_depth));
+                unsafe {
+                    gl::Uniform1f(self.log_depth_coefficient, log_depth.coefficient);
+                    gl::Uniform2f(self.log_depth_projection, log_depth.projection.x, log_depth.projection.y);
+                }
+            }
+        }
     }
 
     pub fn set_uniform(&self, state: &mut State, location: UniformLocation, value: &UniformValue<'_>) {
@@ -175,8 +229,11 @@ impl GpuProgram {
         unsafe {
             match value {
                 UniformValue::Sampler { index, texture } => {
-                    gl::Uniform1i(location, *index as i32);
-                    texture.borrow().bind(state,*index);
+                    let mut sampler_units = self.sampler_units.borrow_mut();
+                    if sampler_units.insert(location, *index) != Some(*index) {
+                        gl::Uniform1i(location, *index as i32);
+                    }
+                    texture.borrow().bind(state, *index);
                 }
                 UniformValue::Bool(value) => {
                     gl::Uniform1i(location, if *value { gl::TRUE } else { gl::FALSE } as i32);
@@ -231,8 +288,13 @@ impl GpuProgram {
 
 impl Drop for GpuProgram {
     fn drop(&mut self) {
+        if !is_context_alive() {
+            return;
+        }
+
         unsafe {
             gl::DeleteProgram(self.id);
+            notify_deleted(ObjectKind::Program, self.id);
         }
     }
 }
\ No newline at end of file
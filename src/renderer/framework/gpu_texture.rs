@@ -11,7 +11,13 @@ use crate::{
         framework::{
             gl::types::GLuint,
             gl,
-            state::State
+            state::{
+                State,
+                ObjectKind,
+                notify_deleted,
+                is_context_alive,
+            },
+            pixel_buffer::PixelBuffer,
         },
         error::RendererError,
     },
@@ -37,6 +43,12 @@ pub enum GpuTextureKind {
         height: usize,
         depth: usize,
     },
+    /// Array of rectangle textures of the same size.
+    Array {
+        width: usize,
+        height: usize,
+        layers: usize,
+    },
 }
 
 impl GpuTextureKind {
@@ -46,6 +58,7 @@ impl GpuTextureKind {
             GpuTextureKind::Rectangle { .. } => gl::TEXTURE_2D,
             GpuTextureKind::Cube { .. } => gl::TEXTURE_CUBE_MAP,
             GpuTextureKind::Volume { .. } => gl::TEXTURE_3D,
+            GpuTextureKind::Array { .. } => gl::TEXTURE_2D_ARRAY,
         }
     }
 }
@@ -59,6 +72,10 @@ pub enum PixelKind {
     RGB8,
     RG8,
     R8,
+    RGBA32F,
+    RGBA16F,
+    /// Two unsigned integers, can't be filtered, sampled only by integer samplers.
+    RG32UI,
 }
 
 impl From<TextureKind> for PixelKind {
@@ -67,6 +84,8 @@ impl From<TextureKind> for PixelKind {
             TextureKind::R8 => PixelKind::R8,
             TextureKind::RGB8 => PixelKind::RGB8,
             TextureKind::RGBA8 => PixelKind::RGBA8,
+            TextureKind::RG8 => PixelKind::RG8,
+            TextureKind::RGBA16F => PixelKind::RGBA16F,
         }
     }
 }
@@ -74,13 +93,32 @@ impl From<TextureKind> for PixelKind {
 pub struct GpuTexture {
     texture: GLuint,
     kind: GpuTextureKind,
+    pixel_kind: PixelKind,
     // Force compiler to not implement Send and Sync, because OpenGL is not thread-safe.
     thread_mark: PhantomData<*const u8>,
 }
 
+/// Returns size of base level of texture in bytes.
+fn byte_count(kind: GpuTextureKind, pixel_kind: PixelKind) -> usize {
+    let bytes_per_pixel = pixel_kind.size_bytes();
+    match kind {
+        GpuTextureKind::Line { length } => length * bytes_per_pixel,
+        GpuTextureKind::Rectangle { width, height } => width * height * bytes_per_pixel,
+        GpuTextureKind::Cube { width, height } => 6 * width * height * bytes_per_pixel,
+        GpuTextureKind::Volume { width, height, depth } => {
+            width * height * depth * bytes_per_pixel
+        }
+        GpuTextureKind::Array { width, height, layers } => {
+            width * height * layers * bytes_per_pixel
+        }
+    }
+}
+
 impl PixelKind {
-    fn size_bytes(self) -> usize {
+    pub(in crate) fn size_bytes(self) -> usize {
         match self {
+            PixelKind::RGBA32F => 16,
+            PixelKind::RGBA16F | PixelKind::RG32UI => 8,
             PixelKind::RGBA8 | PixelKind::D24S8 | PixelKind::D32 | PixelKind::F32 => 4,
             PixelKind::RGB8 => 3,
             PixelKind::RG8 => 2,
@@ -88,13 +126,29 @@ impl PixelKind {
         }
     }
 
-    fn unpack_alignment(self) -> i32 {
+    pub(in crate) fn unpack_alignment(self) -> i32 {
         match self {
-            PixelKind::RGBA8 | PixelKind::RGB8 | PixelKind::D24S8 | PixelKind::D32 | PixelKind::F32 => 4,
+            PixelKind::RGBA8 | PixelKind::RGB8 | PixelKind::D24S8 | PixelKind::D32 | PixelKind::F32 | PixelKind::RGBA32F | PixelKind::RGBA16F | PixelKind::RG32UI => 4,
             PixelKind::RG8 => 2,
             PixelKind::R8 => 1
         }
     }
+
+    /// Returns (type, format, internal format) triple for glTexImage* functions.
+    pub(in crate) fn gl_formats(self) -> (GLuint, GLuint, GLuint) {
+        match self {
+            PixelKind::F32 => (gl::FLOAT, gl::RED, gl::R32F),
+            PixelKind::D32 => (gl::FLOAT, gl::DEPTH_COMPONENT, gl::DEPTH_COMPONENT),
+            PixelKind::D24S8 => (gl::UNSIGNED_INT_24_8, gl::DEPTH_STENCIL, gl::DEPTH24_STENCIL8),
+            PixelKind::RGBA8 => (gl::UNSIGNED_BYTE, gl::RGBA, gl::RGBA8),
+            PixelKind::RGB8 => (gl::UNSIGNED_BYTE, gl::RGB, gl::RGB8),
+            PixelKind::RG8 => (gl::UNSIGNED_BYTE, gl::RG, gl::RG8),
+            PixelKind::R8 => (gl::UNSIGNED_BYTE, gl::RED, gl::R8),
+            PixelKind::RGBA32F => (gl::FLOAT, gl::RGBA, gl::RGBA32F),
+            PixelKind::RGBA16F => (gl::HALF_FLOAT, gl::RGBA, gl::RGBA16F),
+            PixelKind::RG32UI => (gl::UNSIGNED_INT, gl::RG_INTEGER, gl::RG32UI),
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -116,6 +170,8 @@ impl MagnificationFilter {
 pub enum MininificationFilter {
     Nearest,
     Linear,
+    /// Linear filtering of nearest mip level.
+    LinearMipNearest,
     LinearMip,
 }
 
@@ -124,6 +180,7 @@ impl MininificationFilter {
         (match self {
             MininificationFilter::Nearest => gl::NEAREST,
             MininificationFilter::Linear => gl::LINEAR,
+            MininificationFilter::LinearMipNearest => gl::LINEAR_MIPMAP_NEAREST,
             MininificationFilter::LinearMip => gl::LINEAR_MIPMAP_LINEAR,
         }) as i32
     }
@@ -189,11 +246,14 @@ impl CubeMapFace {
 }
 
 impl<'a> TextureBinding<'a> {
-    pub fn set_max_anisotropy(self) -> Self {
+    /// Sets max anisotropy, value is clamped to max anisotropy supported by hardware.
+    /// Value of one disables anisotropic filtering.
+    pub fn set_anisotropy(self, anisotropy: f32) -> Self {
         unsafe {
-            let mut aniso = 0.0;
-            gl::GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut aniso);
-            gl::TexParameterf(gl::TEXTURE_2D, gl::TEXTURE_MAX_ANISOTROPY_EXT, aniso);
+            let mut max_anisotropy = 0.0;
+            gl::GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max_anisotropy);
+            gl::TexParameterf(self.texture.kind.to_texture_target(), gl::TEXTURE_MAX_ANISOTROPY_EXT,
+                              anisotropy.min(max_anisotropy).max(1.0));
         }
         self
     }
@@ -228,6 +288,22 @@ impl<'a> TextureBinding<'a> {
         self
     }
 
+    /// Enables or disables comparison of depth texture with reference value on fetch, it
+    /// is required to sample texture through shadow sampler (hardware percentage closer
+    /// filtering). Texture with comparison enabled must not be sampled by regular sampler.
+    pub fn set_depth_comparison(self, enabled: bool) -> Self {
+        unsafe {
+            let target = self.texture.kind.to_texture_target();
+            if enabled {
+                gl::TexParameteri(target, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as i32);
+                gl::TexParameteri(target, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as i32);
+            } else {
+                gl::TexParameteri(target, gl::TEXTURE_COMPARE_MODE, gl::NONE as i32);
+            }
+        }
+        self
+    }
+
     pub fn generate_mip_maps(self) -> Self {
         unsafe {
             gl::GenerateMipmap(self.texture.kind.to_texture_target());
@@ -244,22 +320,15 @@ impl GpuTexture {
     /// In case of Cube texture, `bytes` should contain all 6 cube faces ordered like so,
     /// +X, -X, +Y, -Y, +Z, -Z
     ///
+    /// In case of Array texture, `bytes` should contain all layers one after another.
+    ///
     /// Produced texture can be used as render target for framebuffer, in this case `data`
     /// parameter can be None.
     pub fn new(state: &mut State,
                kind: GpuTextureKind,
                pixel_kind: PixelKind,
                data: Option<&[u8]>) -> Result<Self, RendererError> {
-        let bytes_per_pixel = pixel_kind.size_bytes();
-
-        let desired_byte_count = match kind {
-            GpuTextureKind::Line { length } => length * bytes_per_pixel,
-            GpuTextureKind::Rectangle { width, height } => width * height * bytes_per_pixel,
-            GpuTextureKind::Cube { width, height } => 6 * width * height * bytes_per_pixel,
-            GpuTextureKind::Volume { width, height, depth } => {
-                width * height * depth * bytes_per_pixel
-            }
-        };
+        let desired_byte_count = byte_count(kind, pixel_kind);
 
         if let Some(data) = data {
             if data.len() != desired_byte_count {
@@ -275,15 +344,7 @@ impl GpuTexture {
 
             state.set_texture(0, target, texture);
 
-            let (type_, format, internal_format) = match pixel_kind {
-                PixelKind::F32 => (gl::FLOAT, gl::RED, gl::R32F),
-                PixelKind::D32 => (gl::FLOAT, gl::DEPTH_COMPONENT, gl::DEPTH_COMPONENT),
-                PixelKind::D24S8 => (gl::UNSIGNED_INT_24_8, gl::DEPTH_STENCIL, gl::DEPTH24_STENCIL8),
-                PixelKind::RGBA8 => (gl::UNSIGNED_BYTE, gl::RGBA, gl::RGBA8),
-                PixelKind::RGB8 => (gl::UNSIGNED_BYTE, gl::RGB, gl::RGB8),
-                PixelKind::RG8 => (gl::UNSIGNED_BYTE, gl::RG, gl::RG8),
-                PixelKind::R8 => (gl::UNSIGNED_BYTE, gl::RED, gl::R8),
-            };
+            let (type_, format, internal_format) = pixel_kind.gl_formats();
 
             gl::PixelStorei(gl::UNPACK_ALIGNMENT, pixel_kind.unpack_alignment());
 
@@ -304,7 +365,7 @@ impl GpuTexture {
                 }
                 GpuTextureKind::Cube { width, height } => {
                     for face in 0..6 {
-                        let bytes_per_face = width * height * bytes_per_pixel;
+                        let bytes_per_face = width * height * pixel_kind.size_bytes();
 
                         let begin = face * bytes_per_face;
                         let end = (face + 1) * bytes_per_face;
@@ -324,6 +385,11 @@ impl GpuTexture {
                                    width as i32, height as i32, depth as i32,
                                    0, format, type_, pixels);
                 }
+                GpuTextureKind::Array { width, height, layers } => {
+                    gl::TexImage3D(gl::TEXTURE_2D_ARRAY, 0, internal_format as i32,
+                                   width as i32, height as i32, layers as i32,
+                                   0, format, type_, pixels);
+                }
             }
 
             gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
@@ -336,6 +402,7 @@ impl GpuTexture {
             Ok(Self {
                 texture,
                 kind,
+                pixel_kind,
                 thread_mark: PhantomData,
             })
         }
@@ -350,6 +417,112 @@ impl GpuTexture {
         state.set_texture(sampler_index, self.kind.to_texture_target(), self.texture);
     }
 
+    /// Replaces contents of rectangle texture with new data, size of texture stays the same.
+    /// This is much cheaper than creating new texture each frame, so it should be used for
+    /// data which changes frequently.
+    pub fn set_data(&mut self, state: &mut State, data: &[u8]) -> Result<(), RendererError> {
+        if let GpuTextureKind::Rectangle { width, height } = self.kind {
+            if data.len() != width * height * self.pixel_kind.size_bytes() {
+                return Err(RendererError::InvalidTextureData);
+            }
+
+            let (type_, format, _) = self.pixel_kind.gl_formats();
+
+            state.set_texture(0, gl::TEXTURE_2D, self.texture);
+
+            unsafe {
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, self.pixel_kind.unpack_alignment());
+                gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, width as i32, height as i32,
+                                  format, type_, data.as_ptr() as *const c_void);
+            }
+
+            state.set_texture(0, gl::TEXTURE_2D, 0);
+
+            Ok(())
+        } else {
+            Err(RendererError::InvalidTextureData)
+        }
+    }
+
+    /// Replaces contents of rectangular region of rectangle texture, `data` must contain
+    /// rows of region without padding.
+    pub fn set_region(&mut self, state: &mut State, x: usize, y: usize, width: usize, height: usize, data: &[u8]) -> Result<(), RendererError> {
+        if let GpuTextureKind::Rectangle { width: texture_width, height: texture_height } = self.kind {
+            if x + width > texture_width || y + height > texture_height ||
+                data.len() != width * height * self.pixel_kind.size_bytes() {
+                return Err(RendererError::InvalidTextureData);
+            }
+
+            let (type_, format, _) = self.pixel_kind.gl_formats();
+
+            state.set_texture(0, gl::TEXTURE_2D, self.texture);
+
+            unsafe {
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, self.pixel_kind.unpack_alignment());
+                gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as i32, y as i32, width as i32, height as i32,
+                                  format, type_, data.as_ptr() as *const c_void);
+            }
+
+            state.set_texture(0, gl::TEXTURE_2D, 0);
+
+            Ok(())
+        } else {
+            Err(RendererError::InvalidTextureData)
+        }
+    }
+
+    /// Fills base level of rectangle texture from pixel buffer, buffer must contain data of
+    /// whole level. Call returns immediately, copying happens asynchronously.
+    pub fn set_data_from_pixel_buffer(&mut self, state: &mut State, buffer: &PixelBuffer) -> Result<(), RendererError> {
+        if let GpuTextureKind::Rectangle { width, height } = self.kind {
+            let (type_, format, _) = self.pixel_kind.gl_formats();
+
+            state.set_texture(0, gl::TEXTURE_2D, self.texture);
+
+            unsafe {
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, buffer.id());
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, self.pixel_kind.unpack_alignment());
+                // Data pointer is offset in bound buffer.
+                gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, width as i32, height as i32,
+                                  format, type_, std::ptr::null());
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+            }
+
+            state.set_texture(0, gl::TEXTURE_2D, 0);
+
+            Ok(())
+        } else {
+            Err(RendererError::InvalidTextureData)
+        }
+    }
+
+    /// Reads back pixels of base level of rectangle texture. Rows are stored bottom to top,
+    /// as OpenGL does. This call stalls pipeline until texture is rendered, so it must not
+    /// be used every frame.
+    pub fn read_pixels(&self, state: &mut State) -> Result<Vec<u8>, RendererError> {
+        if let GpuTextureKind::Rectangle { .. } = self.kind {
+            let (type_, format, _) = self.pixel_kind.gl_formats();
+            let mut pixels = vec![0u8; self.size_bytes()];
+
+            state.set_texture(0, gl::TEXTURE_2D, self.texture);
+
+            unsafe {
+                gl::PixelStorei(gl::PACK_ALIGNMENT, self.pixel_kind.unpack_alignment());
+                gl::GetTexImage(gl::TEXTURE_2D, 0, format, type_, pixels.as_mut_ptr() as *mut c_void);
+            }
+
+            state.set_texture(0, gl::TEXTURE_2D, 0);
+
+            Ok(pixels)
+        } else {
+            Err(RendererError::InvalidTextureData)
+        }
+    }
+
+    pub fn pixel_kind(&self) -> PixelKind {
+        self.pixel_kind
+    }
+
     pub fn kind(&self) -> GpuTextureKind {
         self.kind
     }
@@ -357,14 +530,25 @@ impl GpuTexture {
     pub fn id(&self) -> u32 {
         self.texture
     }
+
+    /// Returns approximate amount of video memory occupied by texture, mip levels are not
+    /// included.
+    pub fn size_bytes(&self) -> usize {
+        byte_count(self.kind, self.pixel_kind)
+    }
 }
 
 impl Drop for GpuTexture {
     fn drop(&mut self) {
+        if !is_context_alive() {
+            return;
+        }
+
         unsafe {
             Log::writeln(format!("GL texture {} was destroyed!", self.texture));
 
             gl::DeleteTextures(1, &self.texture);
+            notify_deleted(ObjectKind::Texture, self.texture);
         }
     }
 }
\ No newline at end of file
@@ -14,6 +14,8 @@ pub mod geometry_buffer;
 pub mod gpu_texture;
 pub mod framebuffer;
 pub mod state;
+pub mod program_cache;
+pub mod pixel_buffer;
 
 pub fn check_gl_error_internal(line: u32, file: &str) {
     unsafe {
@@ -0,0 +1,81 @@
+//! Pixel buffer is a staging buffer for asynchronous texture uploads. Pixels are copied into
+//! mapped memory of buffer, then texture is filled from the buffer - driver does the copy
+//! by DMA without blocking the thread, unlike uploads from client memory which must be
+//! finished before `glTex(Sub)Image` returns.
+
+use std::marker::PhantomData;
+use crate::{
+    renderer::framework::{
+        gl::{
+            self,
+            types::GLuint,
+        },
+        state::{
+            ObjectKind,
+            notify_deleted,
+            is_context_alive,
+        },
+    },
+    utils::log::Log,
+};
+
+pub struct PixelBuffer {
+    id: GLuint,
+    capacity: usize,
+    // Force compiler to not implement Send and Sync, because OpenGL is not thread-safe.
+    thread_mark: PhantomData<*const u8>,
+}
+
+impl PixelBuffer {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+        Log::writeln(format!("GL pixel buffer {} was created!", id));
+        Self {
+            id,
+            capacity: 0,
+            thread_mark: PhantomData,
+        }
+    }
+
+    /// Copies data into buffer. Storage is re-allocated on every write, so previous content
+    /// may still be read by pending uploads without stalling.
+    pub fn write(&mut self, data: &[u8]) {
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, self.id);
+            self.capacity = self.capacity.max(data.len());
+            // Orphan storage, pending uploads still use old one.
+            gl::BufferData(gl::PIXEL_UNPACK_BUFFER, self.capacity as isize, std::ptr::null(), gl::STREAM_DRAW);
+            if !data.is_empty() {
+                let access = gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_BUFFER_BIT;
+                let ptr = gl::MapBufferRange(gl::PIXEL_UNPACK_BUFFER, 0, data.len() as isize, access);
+                if !ptr.is_null() {
+                    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+                }
+                gl::UnmapBuffer(gl::PIXEL_UNPACK_BUFFER);
+            }
+            gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for PixelBuffer {
+    fn drop(&mut self) {
+        if !is_context_alive() {
+            return;
+        }
+
+        unsafe {
+            Log::writeln(format!("GL pixel buffer {} was destroyed!", self.id));
+
+            gl::DeleteBuffers(1, &self.id);
+            notify_deleted(ObjectKind::Buffer, self.id);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! On-disk cache of linked GPU programs.
+//!
+//! Compilation of shaders is slow and happens on startup of renderer, so every launch of a
+//! game pays for it again. When cache directory is set, every linked program is retrieved
+//! from driver (GL_ARB_get_program_binary) and stored on disk, next launch loads binaries
+//! instead of compiling sources. Binaries are stored in sub-directory unique for driver
+//! (vendor, renderer and version strings), because binaries of one driver can't be used
+//! by other. Driver may reject binary at any time (after update for example), in this case
+//! program is silently compiled from sources and cache entry is overwritten.
+//!
+//! Cache and progress callback are per-thread, they must be set on the thread that creates
+//! engine before engine is created.
+
+use std::{
+    cell::RefCell,
+    collections::hash_map::DefaultHasher,
+    ffi::{CStr, CString},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+use crate::{
+    renderer::framework::gl::{
+        self,
+        types::{GLenum, GLint, GLsizei, GLuint},
+    },
+    utils::log::Log,
+};
+
+/// Describes GPU program which was just made ready by renderer.
+pub struct ShaderCompilationProgress<'a> {
+    /// Name of program.
+    pub name: &'a str,
+    /// Total amount of programs made ready so far, including this one.
+    pub ready: usize,
+    /// True if program was loaded from cache instead of compiling it from sources.
+    pub from_cache: bool,
+}
+
+/// Callback which is called every time renderer makes GPU program ready.
+pub type ShaderCompilationCallback = Box<dyn FnMut(&ShaderCompilationProgress)>;
+
+#[derive(Default)]
+struct ProgramCache {
+    directory: Option<PathBuf>,
+    // Resolved lazily, because driver strings are available only after GL is loaded.
+    driver_directory: Option<PathBuf>,
+    progress: Option<ShaderCompilationCallback>,
+    ready: usize,
+}
+
+thread_local! {
+    static PROGRAM_CACHE: RefCell<ProgramCache> = RefCell::new(Default::default());
+}
+
+/// Sets directory where program binaries will be stored, `None` disables the cache. Has
+/// no effect on programs which were already created.
+pub fn set_program_cache_directory<P: AsRef<Path>>(directory: Option<P>) {
+    PROGRAM_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.directory = directory.map(|d| d.as_ref().to_owned());
+        cache.driver_directory = None;
+    })
+}
+
+/// Sets callback which will be called every time renderer makes GPU program ready, can be
+/// used to show progress of renderer initialization.
+pub fn set_shader_compilation_callback(callback: Option<ShaderCompilationCallback>) {
+    PROGRAM_CACHE.with(|cache| cache.borrow_mut().progress = callback)
+}
+
+fn driver_string(name: GLenum) -> String {
+    unsafe {
+        let string = gl::GetString(name);
+        if string.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(string as *const _).to_string_lossy().into_owned()
+        }
+    }
+}
+
+fn is_binary_supported() -> bool {
+    if !gl::GetProgramBinary::is_loaded() || !gl::ProgramBinary::is_loaded() {
+        return false;
+    }
+    let mut formats = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats);
+    }
+    formats > 0
+}
+
+impl ProgramCache {
+    fn driver_directory(&mut self) -> Option<PathBuf> {
+        if self.driver_directory.is_none() {
+            let directory = self.directory.as_ref()?;
+            if !is_binary_supported() {
+                return None;
+            }
+            let mut hasher = DefaultHasher::new();
+            driver_string(gl::VENDOR).hash(&mut hasher);
+            driver_string(gl::RENDERER).hash(&mut hasher);
+            driver_string(gl::VERSION).hash(&mut hasher);
+            self.driver_directory = Some(directory.join(format!("{:016x}", hasher.finish())));
+        }
+        self.driver_directory.clone()
+    }
+}
+
+/// Calculates key of program from its name and final source code of its shaders.
+pub(in crate) fn program_key(name: &str, vertex_source: &CString, fragment_source: &CString) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    vertex_source.hash(&mut hasher);
+    fragment_source.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn binary_path(key: u64) -> Option<PathBuf> {
+    PROGRAM_CACHE.with(|cache| cache.borrow_mut().driver_directory())
+        .map(|directory| directory.join(format!("{:016x}.bin", key)))
+}
+
+/// Returns true if linked programs should be stored in cache, driver must be hinted about it
+/// before linking.
+pub(in crate) fn is_enabled() -> bool {
+    PROGRAM_CACHE.with(|cache| cache.borrow_mut().driver_directory().is_some())
+}
+
+/// Tries to create program from cached binary. Entry is removed if driver rejects binary.
+pub(in crate) fn load(key: u64) -> Option<GLuint> {
+    let path = binary_path(key)?;
+    let data = fs::read(&path).ok()?;
+    if data.len() <= 4 {
+        return None;
+    }
+    let format = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let binary = &data[4..];
+    unsafe {
+        let program = gl::CreateProgram();
+        gl::ProgramBinary(program, format, binary.as_ptr() as *const _, binary.len() as GLsizei);
+        let mut status = 0;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status == 0 {
+            gl::DeleteProgram(program);
+            let _ = fs::remove_file(&path);
+            None
+        } else {
+            Some(program)
+        }
+    }
+}
+
+/// Stores binary of linked program in cache. Failures are logged, they must not prevent
+/// renderer from working.
+pub(in crate) fn store(key: u64, program: GLuint) {
+    let path = match binary_path(key) {
+        Some(path) => path,
+        None => return,
+    };
+    let binary = unsafe {
+        let mut length: GLint = 0;
+        gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut length);
+        if length <= 0 {
+            return;
+        }
+        let mut format: GLenum = 0;
+        let mut written: GLsizei = 0;
+        let mut binary = vec![0u8; length as usize];
+        gl::GetProgramBinary(program, length, &mut written, &mut format, binary.as_mut_ptr() as *mut _);
+        binary.truncate(written.max(0) as usize);
+        let mut data = format.to_le_bytes().to_vec();
+        data.extend_from_slice(&binary);
+        data
+    };
+    let result = path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, binary));
+    if let Err(e) = result {
+        Log::writeln(format!("Unable to write program binary {:?} to cache. Reason: {}", path, e));
+    }
+}
+
+/// Notifies progress callback that program is ready.
+pub(in crate) fn notify_ready(name: &str, from_cache: bool) {
+    // Callback is taken out of cache while it is called, so it can reconfigure cache.
+    let (callback, ready) = PROGRAM_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.ready += 1;
+        (cache.progress.take(), cache.ready)
+    });
+    if let Some(mut callback) = callback {
+        callback(&ShaderCompilationProgress { name, ready, from_cache });
+        PROGRAM_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.progress.is_none() {
+                cache.progress = Some(callback);
+            }
+        })
+    }
+}
@@ -1,3 +1,12 @@
+//! Cache of OpenGL state. Every change of state goes through `State` which compares new
+//! value with cached one and skips the call if nothing changes - renderers set full state
+//! for each draw, so most of the calls are redundant.
+//!
+//! Bindings of GL objects are cached by names of objects. When object is deleted, GL unbinds
+//! it and its name can be reused by new object, so framework notifies state about deleted
+//! objects (see `notify_deleted`) and state forgets bindings of them.
+
+use std::cell::{Cell, RefCell};
 use crate::{
     renderer::{
         framework::{
@@ -17,11 +26,76 @@ use crate::{
         }
     },
     core::{
-        math::Rect,
+        math::{
+            Rect,
+            mat4::Mat4,
+            vec2::Vec2,
+        },
         color::Color,
     },
 };
 
+/// Parameters of logarithmic depth of current view. They are not GL state, but global
+/// uniforms which every program gets when it is bound, see `S_LogDepth` in shared.glsl.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct LogDepth {
+    /// `2 / log2(z_far + 1)`, zero means that logarithmic depth is disabled.
+    pub coefficient: f32,
+    /// Depth part of projection matrix, regular depth in NDC is `x + y / w`.
+    pub projection: Vec2,
+}
+
+impl LogDepth {
+    /// Creates parameters for given projection matrix with given far plane.
+    pub fn new(projection: &Mat4, z_far: f32) -> Self {
+        Self {
+            coefficient: 2.0 / (z_far + 1.0).log2(),
+            projection: Vec2::new(projection.f[10] / projection.f[11], projection.f[14]),
+        }
+    }
+}
+
+/// Amount of state changes issued to GL and amount of redundant changes which were skipped.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct StateStatistics {
+    pub changes: usize,
+    pub skipped: usize,
+}
+
+/// Kinds of GL objects which bindings are cached by state.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(in crate) enum ObjectKind {
+    Texture,
+    Program,
+    VertexArray,
+    Buffer,
+    FrameBuffer,
+}
+
+thread_local! {
+    static DELETED_OBJECTS: RefCell<Vec<(ObjectKind, GLuint)>> = RefCell::new(Vec::new());
+    /// False while objects of lost context are dropped, see `forget_lost_objects`.
+    static CONTEXT_ALIVE: Cell<bool> = Cell::new(true);
+}
+
+/// Drops given value without deleting GL objects it owns. Used when context is lost: its
+/// objects are gone with it and their names may already belong to objects of new context.
+pub(in crate) fn forget_lost_objects<T>(value: T) {
+    CONTEXT_ALIVE.with(|alive| alive.set(false));
+    drop(value);
+    CONTEXT_ALIVE.with(|alive| alive.set(true));
+}
+
+/// Returns false if objects being dropped belong to lost context and must not be deleted.
+pub(in crate) fn is_context_alive() -> bool {
+    CONTEXT_ALIVE.with(|alive| alive.get())
+}
+
+/// Must be called when GL object is deleted, so state will forget its bindings.
+pub(in crate) fn notify_deleted(kind: ObjectKind, name: GLuint) {
+    DELETED_OBJECTS.with(|objects| objects.borrow_mut().push((kind, name)));
+}
+
 pub struct State {
     blend: bool,
     depth_test: bool,
@@ -43,12 +117,17 @@ pub struct State {
 
     program: GLuint,
     texture_units: [TextureUnit; 32],
+    active_texture_unit: usize,
 
     stencil_func: StencilFunc,
     stencil_op: StencilOp,
 
     vao: GLuint,
     vbo: GLuint,
+
+    log_depth: LogDepth,
+
+    statistics: StateStatistics,
 }
 
 #[derive(Copy, Clone)]
@@ -166,12 +245,117 @@ impl State {
             stencil_func: Default::default(),
             stencil_op: Default::default(),
             vao: 0,
-            vbo: 0
+            vbo: 0,
+            active_texture_unit: 0,
+            log_depth: Default::default(),
+            statistics: Default::default(),
         }
     }
 
+    /// Counts change of state, returns `changed` back.
+    fn track(&mut self, changed: bool) -> bool {
+        if changed {
+            self.statistics.changes += 1;
+        } else {
+            self.statistics.skipped += 1;
+        }
+        changed
+    }
+
+    /// Submits issued commands to GPU without waiting for them, results become visible to
+    /// other contexts which share objects with this one.
+    pub fn flush(&mut self) {
+        unsafe {
+            gl::Flush();
+        }
+    }
+
+    /// Applies all cached state to context unconditionally. Needed when context is shared
+    /// with host application which may change its state between frames, so cached values
+    /// no longer match real ones.
+    pub fn restore(&mut self) {
+        self.forget_deleted_objects();
+
+        fn set_enabled(capability: GLenum, enabled: bool) {
+            unsafe {
+                if enabled {
+                    gl::Enable(capability);
+                } else {
+                    gl::Disable(capability);
+                }
+            }
+        }
+
+        set_enabled(gl::BLEND, self.blend);
+        set_enabled(gl::DEPTH_TEST, self.depth_test);
+        set_enabled(gl::STENCIL_TEST, self.stencil_test);
+        set_enabled(gl::CULL_FACE, self.culling);
+
+        let rgba = self.clear_color.as_frgba();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(self.viewport.x, self.viewport.y, self.viewport.w, self.viewport.h);
+            gl::DepthMask(bool_to_gl_bool(self.depth_write));
+            gl::ColorMask(bool_to_gl_bool(self.color_write.red),
+                          bool_to_gl_bool(self.color_write.green),
+                          bool_to_gl_bool(self.color_write.blue),
+                          bool_to_gl_bool(self.color_write.alpha));
+            gl::CullFace(self.cull_face.into_gl_value());
+            gl::StencilMask(self.stencil_mask);
+            gl::ClearColor(rgba.x, rgba.y, rgba.z, rgba.w);
+            gl::ClearDepth(self.clear_depth as f64);
+            gl::ClearStencil(self.clear_stencil);
+            gl::BlendFunc(self.blend_src_factor, self.blend_dst_factor);
+            gl::UseProgram(self.program);
+            for (index, unit) in self.texture_units.iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + index as u32);
+                gl::BindTexture(unit.target, unit.texture);
+            }
+            gl::ActiveTexture(gl::TEXTURE0 + self.active_texture_unit as u32);
+            gl::StencilFunc(self.stencil_func.func, self.stencil_func.ref_value, self.stencil_func.mask);
+            gl::StencilOp(self.stencil_op.fail, self.stencil_op.zfail, self.stencil_op.zpass);
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        }
+    }
+
+    /// Returns statistics accumulated since last call and resets it.
+    pub fn take_statistics(&mut self) -> StateStatistics {
+        std::mem::take(&mut self.statistics)
+    }
+
+    /// Forgets bindings of deleted objects, GL binds zero instead of them.
+    fn forget_deleted_objects(&mut self) {
+        DELETED_OBJECTS.with(|objects| {
+            let mut objects = objects.borrow_mut();
+            for (kind, name) in objects.drain(..) {
+                match kind {
+                    ObjectKind::Texture => {
+                        for unit in self.texture_units.iter_mut().filter(|unit| unit.texture == name) {
+                            unit.texture = 0;
+                        }
+                    }
+                    ObjectKind::Program => if self.program == name {
+                        self.program = 0;
+                    }
+                    ObjectKind::VertexArray => if self.vao == name {
+                        self.vao = 0;
+                    }
+                    ObjectKind::Buffer => if self.vbo == name {
+                        self.vbo = 0;
+                    }
+                    ObjectKind::FrameBuffer => if self.framebuffer == name {
+                        self.framebuffer = 0;
+                    }
+                }
+            }
+        });
+    }
+
     pub fn set_framebuffer(&mut self, framebuffer: GLuint) {
-        if self.framebuffer != framebuffer {
+        self.forget_deleted_objects();
+
+        if self.track(self.framebuffer != framebuffer) {
             self.framebuffer = framebuffer;
 
             unsafe {
@@ -181,7 +365,7 @@ impl State {
     }
 
     pub fn set_viewport(&mut self, viewport: Rect<i32>) {
-        if self.viewport != viewport {
+        if self.track(self.viewport != viewport) {
             self.viewport = viewport;
 
             unsafe {
@@ -191,7 +375,7 @@ impl State {
     }
 
     pub fn set_blend(&mut self, blend: bool) {
-        if self.blend != blend {
+        if self.track(self.blend != blend) {
             self.blend = blend;
 
             unsafe {
@@ -205,7 +389,7 @@ impl State {
     }
 
     pub fn set_depth_test(&mut self, depth_test: bool) {
-        if self.depth_test != depth_test {
+        if self.track(self.depth_test != depth_test) {
             self.depth_test = depth_test;
 
             unsafe {
@@ -219,7 +403,7 @@ impl State {
     }
 
     pub fn set_depth_write(&mut self, depth_write: bool) {
-        if self.depth_write != depth_write {
+        if self.track(self.depth_write != depth_write) {
             self.depth_write = depth_write;
 
             unsafe {
@@ -229,7 +413,7 @@ impl State {
     }
 
     pub fn set_color_write(&mut self, color_write: ColorMask) {
-        if self.color_write != color_write {
+        if self.track(self.color_write != color_write) {
             self.color_write = color_write;
 
             unsafe {
@@ -242,7 +426,7 @@ impl State {
     }
 
     pub fn set_stencil_test(&mut self, stencil_test: bool) {
-        if self.stencil_test != stencil_test {
+        if self.track(self.stencil_test != stencil_test) {
             self.stencil_test = stencil_test;
 
             unsafe {
@@ -256,7 +440,7 @@ impl State {
     }
 
     pub fn set_cull_face(&mut self, cull_face: CullFace) {
-        if self.cull_face != cull_face {
+        if self.track(self.cull_face != cull_face) {
             self.cull_face = cull_face;
 
             unsafe {
@@ -266,7 +450,7 @@ impl State {
     }
 
     pub fn set_culling(&mut self, culling: bool) {
-        if self.culling != culling {
+        if self.track(self.culling != culling) {
             self.culling = culling;
 
             unsafe {
@@ -280,7 +464,7 @@ impl State {
     }
 
     pub fn set_stencil_mask(&mut self, stencil_mask: u32) {
-        if self.stencil_mask != stencil_mask {
+        if self.track(self.stencil_mask != stencil_mask) {
             self.stencil_mask = stencil_mask;
 
             unsafe {
@@ -290,7 +474,7 @@ impl State {
     }
 
     pub fn set_clear_color(&mut self, color: Color) {
-        if self.clear_color != color {
+        if self.track(self.clear_color != color) {
             self.clear_color = color;
 
             let rgba = color.as_frgba();
@@ -301,7 +485,7 @@ impl State {
     }
 
     pub fn set_clear_depth(&mut self, depth: f32) {
-        if (self.clear_depth - depth).abs() > std::f32::EPSILON {
+        if self.track((self.clear_depth - depth).abs() > std::f32::EPSILON) {
             self.clear_depth = depth;
 
             unsafe {
@@ -311,7 +495,7 @@ impl State {
     }
 
     pub fn set_clear_stencil(&mut self, stencil: i32) {
-        if self.clear_stencil != stencil {
+        if self.track(self.clear_stencil != stencil) {
             self.clear_stencil = stencil;
 
             unsafe {
@@ -321,7 +505,7 @@ impl State {
     }
 
     pub fn set_blend_func(&mut self, sfactor: GLenum, dfactor: GLenum) {
-        if self.blend_src_factor != sfactor || self.blend_dst_factor != dfactor {
+        if self.track(self.blend_src_factor != sfactor || self.blend_dst_factor != dfactor) {
             self.blend_src_factor = sfactor;
             self.blend_dst_factor = dfactor;
 
@@ -331,8 +515,19 @@ impl State {
         }
     }
 
+    /// Sets parameters of logarithmic depth which will be passed to programs on bind.
+    pub fn set_log_depth(&mut self, log_depth: LogDepth) {
+        self.log_depth = log_depth;
+    }
+
+    pub fn log_depth(&self) -> LogDepth {
+        self.log_depth
+    }
+
     pub fn set_program(&mut self, program: GLuint) {
-        if self.program != program {
+        self.forget_deleted_objects();
+
+        if self.track(self.program != program) {
             self.program = program;
 
             unsafe {
@@ -342,21 +537,24 @@ impl State {
     }
 
     pub fn set_texture(&mut self, sampler_index: usize, target: GLenum, texture: GLuint) {
-        let unit = self.texture_units.get_mut(sampler_index).unwrap();
+        self.forget_deleted_objects();
 
-        if unit.target != target || unit.texture != texture {
-            unit.texture = texture;
-            unit.target = target;
+        let unit = self.texture_units[sampler_index];
+        if self.track(unit.target != target || unit.texture != texture) {
+            self.texture_units[sampler_index] = TextureUnit { target, texture };
 
             unsafe {
-                gl::ActiveTexture(gl::TEXTURE0 + sampler_index as u32);
+                if self.active_texture_unit != sampler_index {
+                    self.active_texture_unit = sampler_index;
+                    gl::ActiveTexture(gl::TEXTURE0 + sampler_index as u32);
+                }
                 gl::BindTexture(target, texture);
             }
         }
     }
 
     pub fn set_stencil_func(&mut self, func: StencilFunc) {
-        if self.stencil_func != func {
+        if self.track(self.stencil_func != func) {
             self.stencil_func = func;
 
             unsafe {
@@ -366,7 +564,7 @@ impl State {
     }
 
     pub fn set_stencil_op(&mut self, op: StencilOp) {
-        if self.stencil_op != op {
+        if self.track(self.stencil_op != op) {
             self.stencil_op = op;
 
             unsafe {
@@ -376,7 +574,9 @@ impl State {
     }
 
     pub fn set_vertex_array_object(&mut self, vao: GLuint) {
-        if self.vao != vao {
+        self.forget_deleted_objects();
+
+        if self.track(self.vao != vao) {
             self.vao = vao;
 
             unsafe {
@@ -386,7 +586,9 @@ impl State {
     }
 
     pub fn set_vertex_buffer_object(&mut self, vbo: GLuint) {
-        if self.vbo != vbo {
+        self.forget_deleted_objects();
+
+        if self.track(self.vbo != vbo) {
             self.vbo = vbo;
 
             unsafe {
@@ -395,11 +597,6 @@ impl State {
         }
     }
 
-    pub fn invalidate_resource_bindings_cache(&mut self) {
-        self.texture_units = Default::default();
-        self.program = 0;
-    }
-
     pub fn apply_draw_parameters(&mut self, draw_params: &DrawParameters) {
         self.set_blend(draw_params.blend);
         self.set_depth_test(draw_params.depth_test);
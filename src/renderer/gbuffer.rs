@@ -3,6 +3,7 @@ use std::{
     cell::RefCell,
 };
 use crate::{
+    resource::material::BlendMode,
     renderer::{
         framework::{
             gpu_program::{
@@ -31,10 +32,15 @@ use crate::{
         RenderPassStatistics,
         TextureCache,
         GeometryCache,
+        matrix_storage::MatrixStorage,
+        render_list::{RenderList, RenderCommand},
+        surface::Surface,
     },
     scene::{
         node::Node,
+        mesh::Mesh,
         graph::Graph,
+        light_probe::LightProbeGrid,
         camera::Camera,
     },
     core::{
@@ -42,24 +48,67 @@ use crate::{
         math::{
             Rect,
             mat4::Mat4,
+            vec2::Vec2,
             frustum::Frustum,
         },
         color::Color,
     },
 };
 
-struct GBufferShader {
+/// Maximum amount of instances of scatter drawn by one draw call.
+const MAX_INSTANCES_PER_BATCH: usize = 4096;
+
+/// Maximum amount of bone matrices of all copies of skinned surface drawn by one draw call.
+const MAX_BONE_MATRICES_PER_BATCH: usize = 8192;
+
+/// Shader is shared between all G-Buffers, so it is compiled once on renderer startup
+/// instead of on first frame of each camera.
+pub struct GBufferShader {
     program: GpuProgram,
     world_matrix: UniformLocation,
     wvp_matrix: UniformLocation,
     use_skeletal_animation: UniformLocation,
     bone_matrices: UniformLocation,
+    bones_per_instance: UniformLocation,
+    use_instancing: UniformLocation,
+    instance_matrices: UniformLocation,
     diffuse_texture: UniformLocation,
     normal_texture: UniformLocation,
+    height_texture: UniformLocation,
+    use_parallax: UniformLocation,
+    parallax_scale: UniformLocation,
+    parallax_min_samples: UniformLocation,
+    parallax_max_samples: UniformLocation,
+    camera_position: UniformLocation,
+    tex_coord_scale: UniformLocation,
+    tex_coord_offset: UniformLocation,
+    detail_diffuse_texture: UniformLocation,
+    detail_normal_texture: UniformLocation,
+    use_detail_diffuse: UniformLocation,
+    use_detail_normal: UniformLocation,
+    detail_tiling: UniformLocation,
+    detail_strength: UniformLocation,
+    diffuse_array: UniformLocation,
+    normal_array: UniformLocation,
+    use_diffuse_array: UniformLocation,
+    use_normal_array: UniformLocation,
+    array_layer: UniformLocation,
+    use_vertex_animation: UniformLocation,
+    vertex_animation_positions: UniformLocation,
+    vertex_animation_normals: UniformLocation,
+    vertex_animation_frame_count: UniformLocation,
+    receive_shadows: UniformLocation,
+    reflectivity: UniformLocation,
+    diffuse_color: UniformLocation,
+    use_alpha_test: UniformLocation,
+    alpha_cutoff: UniformLocation,
+    wetness: UniformLocation,
+    use_ambient_cube: UniformLocation,
+    ambient_cube: UniformLocation,
 }
 
 impl GBufferShader {
-    fn new() -> Result<Self, RendererError> {
+    pub fn new() -> Result<Self, RendererError> {
         let fragment_source = include_str!("shaders/gbuffer_fs.glsl");
         let vertex_source = include_str!("shaders/gbuffer_vs.glsl");
         let program = GpuProgram::from_source("GBufferShader", vertex_source, fragment_source)?;
@@ -68,18 +117,143 @@ impl GBufferShader {
             wvp_matrix: program.uniform_location("worldViewProjection")?,
             use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
             bone_matrices: program.uniform_location("boneMatrices")?,
+            bones_per_instance: program.uniform_location("bonesPerInstance")?,
+            use_instancing: program.uniform_location("useInstancing")?,
+            instance_matrices: program.uniform_location("instanceMatrices")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
             normal_texture: program.uniform_location("normalTexture")?,
+            height_texture: program.uniform_location("heightTexture")?,
+            use_parallax: program.uniform_location("useParallax")?,
+            parallax_scale: program.uniform_location("parallaxScale")?,
+            parallax_min_samples: program.uniform_location("parallaxMinSamples")?,
+            parallax_max_samples: program.uniform_location("parallaxMaxSamples")?,
+            camera_position: program.uniform_location("cameraPosition")?,
+            tex_coord_scale: program.uniform_location("texCoordScale")?,
+            tex_coord_offset: program.uniform_location("texCoordOffset")?,
+            detail_diffuse_texture: program.uniform_location("detailDiffuseTexture")?,
+            detail_normal_texture: program.uniform_location("detailNormalTexture")?,
+            use_detail_diffuse: program.uniform_location("useDetailDiffuse")?,
+            use_detail_normal: program.uniform_location("useDetailNormal")?,
+            detail_tiling: program.uniform_location("detailTiling")?,
+            detail_strength: program.uniform_location("detailStrength")?,
+            diffuse_array: program.uniform_location("diffuseArray")?,
+            normal_array: program.uniform_location("normalArray")?,
+            use_diffuse_array: program.uniform_location("useDiffuseArray")?,
+            use_normal_array: program.uniform_location("useNormalArray")?,
+            array_layer: program.uniform_location("arrayLayer")?,
+            use_vertex_animation: program.uniform_location("useVertexAnimation")?,
+            vertex_animation_positions: program.uniform_location("vertexAnimationPositions")?,
+            vertex_animation_normals: program.uniform_location("vertexAnimationNormals")?,
+            vertex_animation_frame_count: program.uniform_location("vertexAnimationFrameCount")?,
+            receive_shadows: program.uniform_location("receiveShadows")?,
+            reflectivity: program.uniform_location("reflectivity")?,
+            diffuse_color: program.uniform_location("diffuseColor")?,
+            use_alpha_test: program.uniform_location("useAlphaTest")?,
+            alpha_cutoff: program.uniform_location("alphaCutoff")?,
+            wetness: program.uniform_location("wetness")?,
+            use_ambient_cube: program.uniform_location("useAmbientCube")?,
+            ambient_cube: program.uniform_location("ambientCube")?,
             program,
         })
     }
 }
 
+/// GPU textures and parameters of detail layer of surface.
+struct SurfaceDetail {
+    diffuse_texture: Option<Rc<RefCell<GpuTexture>>>,
+    normal_texture: Option<Rc<RefCell<GpuTexture>>>,
+    tiling: Vec2,
+    strength: f32,
+}
+
+impl SurfaceDetail {
+    fn new(surface: &Surface, state: &mut State, texture_cache: &mut TextureCache) -> Self {
+        match surface.detail_layer() {
+            Some(layer) => Self {
+                diffuse_texture: layer.diffuse_texture.clone().and_then(|texture| texture_cache.get(state, texture)),
+                normal_texture: layer.normal_texture.clone().and_then(|texture| texture_cache.get(state, texture)),
+                tiling: layer.tiling,
+                strength: layer.strength,
+            },
+            None => Self {
+                diffuse_texture: None,
+                normal_texture: None,
+                tiling: Vec2::new(1.0, 1.0),
+                strength: 0.0,
+            }
+        }
+    }
+}
+
+/// GPU textures and layer of texture arrays of surface.
+struct SurfaceArrays {
+    diffuse: Option<Rc<RefCell<GpuTexture>>>,
+    normal: Option<Rc<RefCell<GpuTexture>>>,
+    layer: f32,
+}
+
+impl SurfaceArrays {
+    fn new(surface: &Surface, state: &mut State, texture_cache: &mut TextureCache) -> Self {
+        match surface.texture_array() {
+            Some(texture_array) => {
+                let layer_count = texture_array.diffuse.lock().unwrap().layer_count();
+                Self {
+                    diffuse: texture_cache.get_array(state, &texture_array.diffuse),
+                    normal: texture_array.normal.as_ref().and_then(|normal| texture_cache.get_array(state, normal)),
+                    layer: texture_array.layer.min(layer_count.saturating_sub(1)) as f32,
+                }
+            }
+            None => Self {
+                diffuse: None,
+                normal: None,
+                layer: 0.0,
+            }
+        }
+    }
+}
+
+fn mesh_surface<'a>(graph: &'a Graph, command: &RenderCommand) -> Option<(&'a Mesh, &'a Surface)> {
+    match &graph[command.mesh] {
+        Node::Mesh(mesh) => Some((mesh, &mesh.surfaces()[command.surface])),
+        _ => None,
+    }
+}
+
+/// Returns amount of leading commands which can be drawn by one instanced draw call - these
+/// are copies of the same skinned surface which differ only by bones. Commands are sorted by
+/// material and geometry, so such copies go one after another. Meshes which use light
+/// probes have their own ambient lighting, so they are never batched.
+fn skinned_batch_len(graph: &Graph, commands: &[RenderCommand]) -> usize {
+    let (mesh, surface) = match commands.first().and_then(|command| mesh_surface(graph, command)) {
+        Some(pair) => pair,
+        None => return 1,
+    };
+
+    if surface.bones.is_empty() || mesh.is_use_light_probes() {
+        return 1;
+    }
+
+    let max_len = (MAX_BONE_MATRICES_PER_BATCH / surface.bone_count()).max(1);
+
+    1 + commands[1..].iter()
+        .take(max_len - 1)
+        .take_while(|command| match mesh_surface(graph, command) {
+            Some((other_mesh, other_surface)) => !other_mesh.is_use_light_probes()
+                && other_mesh.is_receive_shadows() == mesh.is_receive_shadows()
+                && surface.can_share_draw_with(other_surface),
+            None => false,
+        })
+        .count()
+}
+
 pub struct GBuffer {
     framebuffer: FrameBuffer,
     pub final_frame: FrameBuffer,
-    shader: GBufferShader,
+    shader: Rc<GBufferShader>,
     bone_matrices: Vec<Mat4>,
+    bone_matrix_storage: MatrixStorage,
+    instance_matrices: Vec<Mat4>,
+    instance_matrix_storage: MatrixStorage,
     pub width: i32,
     pub height: i32,
 }
@@ -87,15 +261,21 @@ pub struct GBuffer {
 pub struct GBufferRenderContext<'a, 'b> {
     pub state: &'a mut State,
     pub graph: &'b Graph,
+    pub light_probes: &'b LightProbeGrid,
     pub camera: &'b Camera,
+    /// Visible meshes, must be built for same camera.
+    pub render_list: &'b RenderList,
     pub white_dummy: Rc<RefCell<GpuTexture>>,
     pub normal_dummy: Rc<RefCell<GpuTexture>>,
+    pub array_dummy: Rc<RefCell<GpuTexture>>,
     pub texture_cache: &'a mut TextureCache,
     pub geom_cache: &'a mut GeometryCache,
+    /// Wetness of scene caused by weather, in [0; 1] range.
+    pub wetness: f32,
 }
 
 impl GBuffer {
-    pub fn new(state: &mut State, width: usize, height: usize) -> Result<Self, RendererError> {
+    pub fn new(state: &mut State, shader: Rc<GBufferShader>, width: usize, height: usize) -> Result<Self, RendererError> {
         let mut depth_stencil_texture = GpuTexture::new(state, GpuTextureKind::Rectangle { width, height }, PixelKind::D24S8, None)?;
         depth_stencil_texture.bind_mut(state, 0)
             .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
@@ -113,6 +293,13 @@ impl GBuffer {
             .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
             .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
 
+        // Ambient light from light probes, alpha is non-zero only for pixels of meshes
+        // which use light probes.
+        let mut ambient_texture = GpuTexture::new(state, GpuTextureKind::Rectangle { width, height }, PixelKind::RGBA8, None)?;
+        ambient_texture.bind_mut(state, 0)
+            .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
+            .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
+
         let framebuffer = FrameBuffer::new(
             state,
             Some(Attachment {
@@ -128,9 +315,14 @@ impl GBuffer {
                     kind: AttachmentKind::Color,
                     texture: Rc::new(RefCell::new(normal_texture)),
                 },
+                Attachment {
+                    kind: AttachmentKind::Color,
+                    texture: Rc::new(RefCell::new(ambient_texture)),
+                },
             ])?;
 
-        let frame_texture = GpuTexture::new(state, GpuTextureKind::Rectangle { width, height }, PixelKind::RGBA8, None)?;
+        // Lit frame is in high dynamic range, it is mapped to screen colors by exposure renderer.
+        let frame_texture = GpuTexture::new(state, GpuTextureKind::Rectangle { width, height }, PixelKind::RGBA16F, None)?;
 
         let opt_framebuffer = FrameBuffer::new(
             state,
@@ -147,8 +339,11 @@ impl GBuffer {
 
         Ok(GBuffer {
             framebuffer,
-            shader: GBufferShader::new()?,
+            shader,
             bone_matrices: Vec::new(),
+            bone_matrix_storage: MatrixStorage::new(state)?,
+            instance_matrices: Vec::new(),
+            instance_matrix_storage: MatrixStorage::new(state)?,
             width: width as i32,
             height: height as i32,
             final_frame: opt_framebuffer,
@@ -171,16 +366,19 @@ impl GBuffer {
         self.framebuffer.color_attachments()[1].texture.clone()
     }
 
-    #[must_use]
-    pub fn fill(&mut self, args: GBufferRenderContext) -> RenderPassStatistics {
+    pub fn ambient_texture(&self) -> Rc<RefCell<GpuTexture>> {
+        self.framebuffer.color_attachments()[2].texture.clone()
+    }
+
+    pub fn fill(&mut self, args: GBufferRenderContext) -> Result<RenderPassStatistics, RendererError> {
         scope_profile!();
 
         let mut statistics = RenderPassStatistics::default();
 
         let GBufferRenderContext {
-            state, graph, camera,
-            white_dummy, normal_dummy,
-            texture_cache, geom_cache
+            state, graph, light_probes, camera, render_list,
+            white_dummy, normal_dummy, array_dummy,
+            texture_cache, geom_cache, wetness
         } = args;
 
         let frustum = Frustum::from(camera.view_projection_matrix()).unwrap();
@@ -189,89 +387,393 @@ impl GBuffer {
         self.framebuffer.clear(state, viewport, Some(Color::from_rgba(0, 0, 0, 0)), Some(1.0), Some(0));
 
         let view_projection = camera.view_projection_matrix();
+        let camera_position = camera.global_position();
 
-        'mesh_loop: for mesh in graph.linear_iter().filter_map(|node| {
-            if let Node::Mesh(mesh) = node { Some(mesh) } else { None }
-        }) {
-            if !mesh.is_intersect_frustum(graph, &frustum) {
-                continue 'mesh_loop;
-            }
+        let mut commands = render_list.commands();
+        while let Some(command) = commands.first() {
+            // Copies of the same skinned surface are drawn by one instanced draw call, bone
+            // matrices of all copies are stored one after another in bone matrix storage.
+            let batch = &commands[..skinned_batch_len(graph, commands)];
+            commands = &commands[batch.len()..];
 
-            if !mesh.global_visibility() {
-                continue 'mesh_loop;
-            }
+            let (mesh, surface) = match mesh_surface(graph, command) {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let is_skinned = !surface.bones.is_empty();
 
-            for surface in mesh.surfaces().iter() {
-                let is_skinned = !surface.bones.is_empty();
+            let ambient_cube = if mesh.is_use_light_probes() {
+                light_probes.sample(mesh.global_position())
+            } else {
+                None
+            };
+            let ambient_colors = ambient_cube.map(|cube| cube.colors).unwrap_or_default();
 
-                let world = if is_skinned {
-                    Mat4::IDENTITY
-                } else {
-                    mesh.global_transform()
-                };
-                let mvp = view_projection * world;
-
-                let diffuse_texture = if let Some(texture) = surface.get_diffuse_texture() {
-                    if let Some(texture) = texture_cache.get(state, texture) {
-                        texture
-                    } else {
-                        white_dummy.clone()
-                    }
+            let diffuse_texture = if let Some(texture) = surface.get_diffuse_texture() {
+                if let Some(texture) = texture_cache.get(state, texture) {
+                    texture
                 } else {
                     white_dummy.clone()
-                };
-
-                let normal_texture = if let Some(texture) = surface.get_normal_texture() {
-                    if let Some(texture) = texture_cache.get(state, texture) {
-                        texture
-                    } else {
-                        normal_dummy.clone()
-                    }
+                }
+            } else {
+                white_dummy.clone()
+            };
+
+            let normal_texture = if let Some(texture) = surface.get_normal_texture() {
+                if let Some(texture) = texture_cache.get(state, texture) {
+                    texture
                 } else {
                     normal_dummy.clone()
-                };
-
-                statistics += self.framebuffer.draw(
-                    geom_cache.get(state,&surface.get_data().lock().unwrap()),
-                    state,
-                    viewport,
-                    &self.shader.program,
-                    DrawParameters {
-                        cull_face: CullFace::Back,
-                        culling: true,
-                        color_write: Default::default(),
-                        depth_write: true,
-                        stencil_test: false,
-                        depth_test: true,
-                        blend: false,
-                    },
-                    &[
-                        (self.shader.diffuse_texture, UniformValue::Sampler {
-                            index: 0,
-                            texture: diffuse_texture,
-                        }),
-                        (self.shader.normal_texture, UniformValue::Sampler {
-                            index: 1,
-                            texture: normal_texture,
-                        }),
-                        (self.shader.wvp_matrix, UniformValue::Mat4(mvp)),
-                        (self.shader.world_matrix, UniformValue::Mat4(world)),
-                        (self.shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
-                        (self.shader.bone_matrices, UniformValue::Mat4Array({
-                            self.bone_matrices.clear();
-                            for &bone_handle in surface.bones.iter() {
-                                let bone_node = &graph[bone_handle];
-                                self.bone_matrices.push(
-                                    bone_node.global_transform() *
-                                        bone_node.inv_bind_pose_transform());
-                            }
-                            &self.bone_matrices
-                        }))
-                    ],
-                );
+                }
+            } else {
+                normal_dummy.clone()
+            };
+
+            let height_texture = surface.get_height_texture()
+                .and_then(|texture| texture_cache.get(state, texture));
+            let parallax = surface.parallax();
+            let detail = SurfaceDetail::new(surface, state, texture_cache);
+            let arrays = SurfaceArrays::new(surface, state, texture_cache);
+
+            if is_skinned {
+                self.bone_matrices.clear();
+                for copy in batch.iter().filter_map(|command| mesh_surface(graph, command)) {
+                    copy.1.append_bone_matrices(graph, &mut self.bone_matrices);
+                }
+                self.bone_matrix_storage.upload(state, &self.bone_matrices)?;
+            }
+
+            let geometry = geom_cache.get(state, &surface.get_data().lock().unwrap());
+            let (cull_face, culling) = surface.cull_mode().cull_face();
+            let draw_params = DrawParameters {
+                cull_face,
+                culling,
+                color_write: Default::default(),
+                depth_write: true,
+                stencil_test: false,
+                depth_test: true,
+                blend: false,
+            };
+            let uniforms = [
+                (self.shader.diffuse_texture, UniformValue::Sampler {
+                    index: 0,
+                    texture: diffuse_texture,
+                }),
+                (self.shader.normal_texture, UniformValue::Sampler {
+                    index: 1,
+                    texture: normal_texture,
+                }),
+                (self.shader.height_texture, UniformValue::Sampler {
+                    index: 4,
+                    texture: height_texture.clone().unwrap_or_else(|| white_dummy.clone()),
+                }),
+                (self.shader.use_parallax, UniformValue::Bool(height_texture.is_some())),
+                (self.shader.parallax_scale, UniformValue::Float(parallax.scale)),
+                (self.shader.parallax_min_samples, UniformValue::Float(parallax.min_samples as f32)),
+                (self.shader.parallax_max_samples, UniformValue::Float(parallax.max_samples as f32)),
+                (self.shader.camera_position, UniformValue::Vec3(camera_position)),
+                (self.shader.tex_coord_scale, UniformValue::Vec2(surface.tex_coord_scale())),
+                (self.shader.tex_coord_offset, UniformValue::Vec2(surface.tex_coord_offset())),
+                (self.shader.detail_diffuse_texture, UniformValue::Sampler {
+                    index: 5,
+                    texture: detail.diffuse_texture.clone().unwrap_or_else(|| white_dummy.clone()),
+                }),
+                (self.shader.detail_normal_texture, UniformValue::Sampler {
+                    index: 6,
+                    texture: detail.normal_texture.clone().unwrap_or_else(|| normal_dummy.clone()),
+                }),
+                (self.shader.use_detail_diffuse, UniformValue::Bool(detail.diffuse_texture.is_some())),
+                (self.shader.use_detail_normal, UniformValue::Bool(detail.normal_texture.is_some())),
+                (self.shader.detail_tiling, UniformValue::Vec2(detail.tiling)),
+                (self.shader.detail_strength, UniformValue::Float(detail.strength)),
+                (self.shader.diffuse_array, UniformValue::Sampler {
+                    index: 9,
+                    texture: arrays.diffuse.clone().unwrap_or_else(|| array_dummy.clone()),
+                }),
+                (self.shader.normal_array, UniformValue::Sampler {
+                    index: 10,
+                    texture: arrays.normal.clone().unwrap_or_else(|| array_dummy.clone()),
+                }),
+                (self.shader.use_diffuse_array, UniformValue::Bool(arrays.diffuse.is_some())),
+                (self.shader.use_normal_array, UniformValue::Bool(arrays.normal.is_some())),
+                (self.shader.array_layer, UniformValue::Float(arrays.layer)),
+                (self.shader.wvp_matrix, UniformValue::Mat4(command.world_view_projection)),
+                (self.shader.world_matrix, UniformValue::Mat4(command.world)),
+                (self.shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
+                (self.shader.receive_shadows, UniformValue::Bool(mesh.is_receive_shadows())),
+                (self.shader.reflectivity, UniformValue::Float(surface.reflectivity())),
+                (self.shader.diffuse_color, UniformValue::Color(surface.color())),
+                (self.shader.use_alpha_test, UniformValue::Bool(surface.blend_mode() == BlendMode::AlphaTest)),
+                (self.shader.alpha_cutoff, UniformValue::Float(surface.alpha_cutoff())),
+                (self.shader.wetness, UniformValue::Float(wetness * surface.wetness_factor())),
+                (self.shader.use_ambient_cube, UniformValue::Bool(ambient_cube.is_some())),
+                (self.shader.ambient_cube, UniformValue::Vec3Array(&ambient_colors)),
+                (self.shader.bone_matrices, UniformValue::Sampler {
+                    index: 2,
+                    texture: self.bone_matrix_storage.texture(),
+                }),
+                (self.shader.bones_per_instance, UniformValue::Integer(surface.bone_count() as i32)),
+                (self.shader.use_instancing, UniformValue::Bool(false)),
+                (self.shader.instance_matrices, UniformValue::Sampler {
+                    index: 3,
+                    texture: self.instance_matrix_storage.texture(),
+                }),
+            ];
+
+            statistics += if batch.len() > 1 {
+                self.framebuffer.draw_instanced(batch.len(), geometry, state, viewport, &self.shader.program, draw_params, &uniforms)
+            } else {
+                self.framebuffer.draw(geometry, state, viewport, &self.shader.program, draw_params, &uniforms)
+            };
+        }
+
+        for cloth in graph.linear_iter().filter_map(|node| {
+            if let Node::Cloth(cloth) = node { Some(cloth) } else { None }
+        }) {
+            if !cloth.global_visibility() {
+                continue;
+            }
+
+            let surface = cloth.surface();
+
+            let diffuse_texture = surface.get_diffuse_texture()
+                .and_then(|texture| texture_cache.get(state, texture))
+                .unwrap_or_else(|| white_dummy.clone());
+
+            let normal_texture = surface.get_normal_texture()
+                .and_then(|texture| texture_cache.get(state, texture))
+                .unwrap_or_else(|| normal_dummy.clone());
+
+            let height_texture = surface.get_height_texture()
+                .and_then(|texture| texture_cache.get(state, texture));
+            let parallax = surface.parallax();
+            let detail = SurfaceDetail::new(surface, state, texture_cache);
+            let arrays = SurfaceArrays::new(surface, state, texture_cache);
+
+            // Vertices of cloth are already in world space. Both sides of cloth are visible,
+            // so back face culling is disabled.
+            statistics += self.framebuffer.draw(
+                geom_cache.get(state, &surface.get_data().lock().unwrap()),
+                state,
+                viewport,
+                &self.shader.program,
+                DrawParameters {
+                    cull_face: CullFace::Back,
+                    culling: false,
+                    color_write: Default::default(),
+                    depth_write: true,
+                    stencil_test: false,
+                    depth_test: true,
+                    blend: false,
+                },
+                &[
+                    (self.shader.diffuse_texture, UniformValue::Sampler {
+                        index: 0,
+                        texture: diffuse_texture,
+                    }),
+                    (self.shader.normal_texture, UniformValue::Sampler {
+                        index: 1,
+                        texture: normal_texture,
+                    }),
+                    (self.shader.height_texture, UniformValue::Sampler {
+                        index: 4,
+                        texture: height_texture.clone().unwrap_or_else(|| white_dummy.clone()),
+                    }),
+                    (self.shader.use_parallax, UniformValue::Bool(height_texture.is_some())),
+                    (self.shader.parallax_scale, UniformValue::Float(parallax.scale)),
+                    (self.shader.parallax_min_samples, UniformValue::Float(parallax.min_samples as f32)),
+                    (self.shader.parallax_max_samples, UniformValue::Float(parallax.max_samples as f32)),
+                    (self.shader.camera_position, UniformValue::Vec3(camera_position)),
+                    (self.shader.tex_coord_scale, UniformValue::Vec2(surface.tex_coord_scale())),
+                    (self.shader.tex_coord_offset, UniformValue::Vec2(surface.tex_coord_offset())),
+                    (self.shader.detail_diffuse_texture, UniformValue::Sampler {
+                        index: 5,
+                        texture: detail.diffuse_texture.clone().unwrap_or_else(|| white_dummy.clone()),
+                    }),
+                    (self.shader.detail_normal_texture, UniformValue::Sampler {
+                        index: 6,
+                        texture: detail.normal_texture.clone().unwrap_or_else(|| normal_dummy.clone()),
+                    }),
+                    (self.shader.use_detail_diffuse, UniformValue::Bool(detail.diffuse_texture.is_some())),
+                    (self.shader.use_detail_normal, UniformValue::Bool(detail.normal_texture.is_some())),
+                    (self.shader.detail_tiling, UniformValue::Vec2(detail.tiling)),
+                    (self.shader.detail_strength, UniformValue::Float(detail.strength)),
+                    (self.shader.diffuse_array, UniformValue::Sampler {
+                        index: 9,
+                        texture: arrays.diffuse.clone().unwrap_or_else(|| array_dummy.clone()),
+                    }),
+                    (self.shader.normal_array, UniformValue::Sampler {
+                        index: 10,
+                        texture: arrays.normal.clone().unwrap_or_else(|| array_dummy.clone()),
+                    }),
+                    (self.shader.use_diffuse_array, UniformValue::Bool(arrays.diffuse.is_some())),
+                    (self.shader.use_normal_array, UniformValue::Bool(arrays.normal.is_some())),
+                    (self.shader.array_layer, UniformValue::Float(arrays.layer)),
+                    (self.shader.wvp_matrix, UniformValue::Mat4(view_projection)),
+                    (self.shader.world_matrix, UniformValue::Mat4(Mat4::IDENTITY)),
+                    (self.shader.use_skeletal_animation, UniformValue::Bool(false)),
+                    (self.shader.receive_shadows, UniformValue::Bool(true)),
+                    (self.shader.reflectivity, UniformValue::Float(surface.reflectivity())),
+                    (self.shader.diffuse_color, UniformValue::Color(surface.color())),
+                    (self.shader.use_alpha_test, UniformValue::Bool(surface.blend_mode() == BlendMode::AlphaTest)),
+                    (self.shader.alpha_cutoff, UniformValue::Float(surface.alpha_cutoff())),
+                    (self.shader.wetness, UniformValue::Float(wetness * surface.wetness_factor())),
+                    (self.shader.use_ambient_cube, UniformValue::Bool(false)),
+                    (self.shader.bone_matrices, UniformValue::Sampler {
+                        index: 2,
+                        texture: self.bone_matrix_storage.texture(),
+                    }),
+                    (self.shader.use_instancing, UniformValue::Bool(false)),
+                    (self.shader.instance_matrices, UniformValue::Sampler {
+                        index: 3,
+                        texture: self.instance_matrix_storage.texture(),
+                    })
+                ],
+            );
+        }
+
+        // Scatters and crowds are drawn instanced, crowds animate vertices using vertex
+        // animation textures.
+        for node in graph.linear_iter() {
+            let (surfaces, animation) = match node {
+                Node::Scatter(scatter) if scatter.global_visibility() => {
+                    scatter.visible_instances(camera_position, &frustum, &mut self.instance_matrices);
+                    (scatter.surfaces(), None)
+                }
+                Node::Crowd(crowd) if crowd.global_visibility() => {
+                    crowd.visible_instances(camera_position, &frustum, &mut self.instance_matrices);
+                    (crowd.surfaces(), crowd.animation())
+                }
+                _ => continue,
+            };
+
+            if self.instance_matrices.is_empty() {
+                continue;
+            }
+
+            // Animation is not used until both textures are uploaded, crowd is in rest
+            // pose meanwhile.
+            let animation = animation.and_then(|animation| {
+                let positions = texture_cache.get(state, animation.positions())?;
+                let normals = texture_cache.get(state, animation.normals())?;
+                Some((positions, normals, animation.frame_count()))
+            });
+
+            for surface in surfaces.iter() {
+                let diffuse_texture = surface.get_diffuse_texture()
+                    .and_then(|texture| texture_cache.get(state, texture))
+                    .unwrap_or_else(|| white_dummy.clone());
+
+                let normal_texture = surface.get_normal_texture()
+                    .and_then(|texture| texture_cache.get(state, texture))
+                    .unwrap_or_else(|| normal_dummy.clone());
+
+                let height_texture = surface.get_height_texture()
+                    .and_then(|texture| texture_cache.get(state, texture));
+                let parallax = surface.parallax();
+                let detail = SurfaceDetail::new(surface, state, texture_cache);
+                let arrays = SurfaceArrays::new(surface, state, texture_cache);
+                let (cull_face, culling) = surface.cull_mode().cull_face();
+
+                // Instances are drawn in batches to keep size of matrix storage texture within
+                // limits of hardware.
+                for batch in self.instance_matrices.chunks(MAX_INSTANCES_PER_BATCH) {
+                    self.instance_matrix_storage.upload(state, batch)?;
+
+                    statistics += self.framebuffer.draw_instanced(
+                        batch.len(),
+                        geom_cache.get(state, &surface.get_data().lock().unwrap()),
+                        state,
+                        viewport,
+                        &self.shader.program,
+                        DrawParameters {
+                            cull_face,
+                            culling,
+                            color_write: Default::default(),
+                            depth_write: true,
+                            stencil_test: false,
+                            depth_test: true,
+                            blend: false,
+                        },
+                        &[
+                            (self.shader.diffuse_texture, UniformValue::Sampler {
+                                index: 0,
+                                texture: diffuse_texture.clone(),
+                            }),
+                            (self.shader.normal_texture, UniformValue::Sampler {
+                                index: 1,
+                                texture: normal_texture.clone(),
+                            }),
+                            (self.shader.height_texture, UniformValue::Sampler {
+                                index: 4,
+                                texture: height_texture.clone().unwrap_or_else(|| white_dummy.clone()),
+                            }),
+                            (self.shader.use_parallax, UniformValue::Bool(height_texture.is_some())),
+                            (self.shader.parallax_scale, UniformValue::Float(parallax.scale)),
+                            (self.shader.parallax_min_samples, UniformValue::Float(parallax.min_samples as f32)),
+                            (self.shader.parallax_max_samples, UniformValue::Float(parallax.max_samples as f32)),
+                            (self.shader.camera_position, UniformValue::Vec3(camera_position)),
+                            (self.shader.tex_coord_scale, UniformValue::Vec2(surface.tex_coord_scale())),
+                            (self.shader.tex_coord_offset, UniformValue::Vec2(surface.tex_coord_offset())),
+                            (self.shader.detail_diffuse_texture, UniformValue::Sampler {
+                                index: 5,
+                                texture: detail.diffuse_texture.clone().unwrap_or_else(|| white_dummy.clone()),
+                            }),
+                            (self.shader.detail_normal_texture, UniformValue::Sampler {
+                                index: 6,
+                                texture: detail.normal_texture.clone().unwrap_or_else(|| normal_dummy.clone()),
+                            }),
+                            (self.shader.use_detail_diffuse, UniformValue::Bool(detail.diffuse_texture.is_some())),
+                            (self.shader.use_detail_normal, UniformValue::Bool(detail.normal_texture.is_some())),
+                            (self.shader.detail_tiling, UniformValue::Vec2(detail.tiling)),
+                            (self.shader.detail_strength, UniformValue::Float(detail.strength)),
+                            (self.shader.diffuse_array, UniformValue::Sampler {
+                                index: 9,
+                                texture: arrays.diffuse.clone().unwrap_or_else(|| array_dummy.clone()),
+                            }),
+                            (self.shader.normal_array, UniformValue::Sampler {
+                                index: 10,
+                                texture: arrays.normal.clone().unwrap_or_else(|| array_dummy.clone()),
+                            }),
+                            (self.shader.use_diffuse_array, UniformValue::Bool(arrays.diffuse.is_some())),
+                            (self.shader.use_normal_array, UniformValue::Bool(arrays.normal.is_some())),
+                            (self.shader.array_layer, UniformValue::Float(arrays.layer)),
+                            (self.shader.wvp_matrix, UniformValue::Mat4(view_projection)),
+                            (self.shader.world_matrix, UniformValue::Mat4(Mat4::IDENTITY)),
+                            (self.shader.use_skeletal_animation, UniformValue::Bool(false)),
+                            (self.shader.receive_shadows, UniformValue::Bool(true)),
+                            (self.shader.reflectivity, UniformValue::Float(surface.reflectivity())),
+                            (self.shader.diffuse_color, UniformValue::Color(surface.color())),
+                            (self.shader.use_alpha_test, UniformValue::Bool(surface.blend_mode() == BlendMode::AlphaTest)),
+                            (self.shader.alpha_cutoff, UniformValue::Float(surface.alpha_cutoff())),
+                            (self.shader.wetness, UniformValue::Float(wetness * surface.wetness_factor())),
+                            (self.shader.use_ambient_cube, UniformValue::Bool(false)),
+                            (self.shader.bone_matrices, UniformValue::Sampler {
+                                index: 2,
+                                texture: self.bone_matrix_storage.texture(),
+                            }),
+                            (self.shader.use_instancing, UniformValue::Bool(true)),
+                            (self.shader.instance_matrices, UniformValue::Sampler {
+                                index: 3,
+                                texture: self.instance_matrix_storage.texture(),
+                            }),
+                            (self.shader.use_vertex_animation, UniformValue::Bool(animation.is_some())),
+                            (self.shader.vertex_animation_positions, UniformValue::Sampler {
+                                index: 7,
+                                texture: animation.as_ref().map_or_else(|| white_dummy.clone(), |(positions, _, _)| positions.clone()),
+                            }),
+                            (self.shader.vertex_animation_normals, UniformValue::Sampler {
+                                index: 8,
+                                texture: animation.as_ref().map_or_else(|| normal_dummy.clone(), |(_, normals, _)| normals.clone()),
+                            }),
+                            (self.shader.vertex_animation_frame_count, UniformValue::Integer(
+                                animation.as_ref().map_or(1, |(_, _, frame_count)| *frame_count as i32))),
+                        ],
+                    );
+                }
             }
         }
 
-        statistics
+        Ok(statistics)
     }
 }
\ No newline at end of file
@@ -0,0 +1,162 @@
+//! Renders node hierarchy into impostor atlas, see `scene::impostor` module docs.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            mat4::Mat4,
+            aabb::AxisAlignedBoundingBox,
+        },
+        pool::Handle,
+    },
+    scene::{
+        Scene,
+        node::Node,
+        camera::Camera,
+        base::BaseBuilder,
+        sprite::SpriteBuilder,
+        transform::TransformBuilder,
+        impostor::{
+            ImpostorLod,
+            ImpostorSettings,
+        },
+    },
+    resource::texture::{
+        Texture,
+        TextureKind,
+    },
+    renderer::{
+        Renderer,
+        error::RendererError,
+        gbuffer::GBuffer,
+    },
+};
+
+/// Field of view of baking camera, small angle gives almost orthographic frames.
+const BAKE_FOV: f32 = 30.0 * std::f32::consts::PI / 180.0;
+
+impl Renderer {
+    /// Renders hierarchy starting from `root` from viewpoints around its vertical axis into
+    /// an atlas, creates billboard sprite for it and registers level of detail in scene
+    /// which switches between hierarchy and billboard. Frames are lit by current lights of
+    /// scene. Billboard is linked to parent of `root`, so it moves together with the parent,
+    /// but not with `root` itself. Returns handle of level of detail in `scene.impostors`.
+    ///
+    /// This is heavy operation which reads back rendered frames from GPU, it should be done
+    /// when level is loaded, not every frame.
+    pub fn bake_impostor(&mut self, scene: &mut Scene, root: Handle<Node>, settings: ImpostorSettings)
+                         -> Result<Handle<ImpostorLod>, RendererError> {
+        let mut bounds = AxisAlignedBoundingBox::default();
+        let mut has_meshes = false;
+        for node in scene.graph.traverse_iter(root) {
+            if let Node::Mesh(mesh) = node {
+                let mesh_bounds = mesh.world_bounding_box();
+                bounds.add_point(mesh_bounds.min);
+                bounds.add_point(mesh_bounds.max);
+                has_meshes = true;
+            }
+        }
+        if !has_meshes || settings.view_count == 0 || settings.frame_size == 0 {
+            return Err(RendererError::NothingToBake);
+        }
+        let center = (bounds.min + bounds.max).scale(0.5);
+        let radius = ((bounds.max - bounds.min).len() * 0.5).max(0.001);
+
+        // Hide everything except hierarchy, renderers check global visibility only.
+        let subtree = scene.graph.traverse_handle_iter(root).collect::<HashSet<_>>();
+        let mut saved_visibility = Vec::new();
+        for (handle, node) in scene.graph.pair_iter_mut() {
+            saved_visibility.push((handle, node.global_visibility));
+            node.global_visibility = subtree.contains(&handle) && node.visibility();
+        }
+
+        let atlas = self.render_impostor_atlas(scene, root, center, radius, &settings);
+
+        for (handle, visibility) in saved_visibility {
+            scene.graph[handle].global_visibility = visibility;
+        }
+
+        let frame_size = settings.frame_size;
+        let texture = Texture::from_bytes(
+            frame_size * settings.atlas_columns(),
+            frame_size * settings.atlas_rows(),
+            TextureKind::RGBA8,
+            atlas?,
+        ).map_err(|_| RendererError::InvalidTextureData)?;
+
+        let parent = match scene.graph[root].parent() {
+            parent if parent.is_some() => parent,
+            _ => scene.graph.get_root(),
+        };
+        let local_center = scene.graph[parent].global_transform()
+            .inverse()
+            .unwrap_or(Mat4::IDENTITY)
+            .transform_vector(center);
+
+        let mut lod = ImpostorLod::new(root, Handle::NONE, settings);
+        let billboard = scene.graph.add_node(Node::Sprite(SpriteBuilder::new(BaseBuilder::new()
+            .with_name(&format!("{}Impostor", scene.graph[root].name()))
+            .with_visibility(false)
+            .with_local_transform(TransformBuilder::new()
+                .with_local_position(local_center)
+                .build()))
+            .with_texture(Arc::new(Mutex::new(texture)))
+            .with_size(radius)
+            .with_uv_rect(lod.frame_uv_rect(0))
+            .build()));
+        scene.graph.link_nodes(billboard, parent);
+        lod.set_billboard(billboard);
+
+        Ok(scene.impostors.add(lod))
+    }
+
+    /// Renders frames of impostor and packs them into RGBA8 atlas. Color is taken from lit
+    /// frame, alpha from normal buffer, so background is transparent.
+    fn render_impostor_atlas(&mut self, scene: &Scene, root: Handle<Node>, center: Vec3, radius: f32,
+                             settings: &ImpostorSettings) -> Result<Vec<u8>, RendererError> {
+        let frame_size = settings.frame_size as usize;
+        let columns = settings.atlas_columns() as usize;
+        let atlas_width = frame_size * columns;
+        let mut atlas = vec![0u8; atlas_width * frame_size * settings.atlas_rows() as usize * 4];
+
+        let transform = scene.graph[root].global_transform();
+        let side = transform.side().normalized().unwrap_or_else(|| Vec3::new(1.0, 0.0, 0.0));
+        let up = transform.up().normalized().unwrap_or(Vec3::UP);
+        let look = transform.look().normalized().unwrap_or(Vec3::LOOK);
+
+        let distance = radius / (BAKE_FOV * 0.5).sin();
+
+        let mut gbuffer = GBuffer::new(&mut self.state, self.gbuffer_shader.clone(), frame_size, frame_size)?;
+
+        for view in 0..settings.view_count {
+            let angle = settings.view_angle(view);
+            let eye = center + (side.scale(angle.sin()) + look.scale(angle.cos())).scale(distance);
+            let camera = Camera::looking_at(eye, center, up, BAKE_FOV,
+                                            (distance - radius).max(0.01), distance + radius);
+
+            let (color, normals) = self.render_offscreen(scene, &mut gbuffer, &camera)?;
+
+            // Both frame and atlas rows go bottom to top, which matches texture coordinates
+            // of sprites.
+            let column = view as usize % columns;
+            let row = view as usize / columns;
+            for y in 0..frame_size {
+                for x in 0..frame_size {
+                    let src = (y * frame_size + x) * 4;
+                    let dst = ((row * frame_size + y) * atlas_width + column * frame_size + x) * 4;
+                    atlas[dst..(dst + 3)].copy_from_slice(&color[src..(src + 3)]);
+                    // Normals are packed into [0; 1] range, so only background has zero
+                    // normal. Alpha of diffuse buffer can't be used - it holds shadow flag.
+                    let covered = normals[src..(src + 3)].iter().any(|&n| n != 0);
+                    atlas[dst + 3] = if covered { 255 } else { 0 };
+                }
+            }
+        }
+
+        Ok(atlas)
+    }
+}
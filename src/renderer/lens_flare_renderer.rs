@@ -0,0 +1,247 @@
+use crate::{
+    scene::{
+        node::Node,
+        graph::Graph,
+        camera::Camera,
+        light::LightKind,
+    },
+    core::{
+        scope_profile,
+        math::{
+            Rect,
+            vec2::Vec2,
+            vec3::Vec3,
+            vec4::Vec4,
+        },
+        color::Color,
+    },
+    renderer::{
+        TextureCache,
+        TriangleDefinition,
+        error::RendererError,
+        framework::{
+            gpu_texture::GpuTexture,
+            gl,
+            gpu_program::{
+                UniformValue,
+                GpuProgram,
+                UniformLocation,
+            },
+            geometry_buffer::{
+                GeometryBuffer,
+                DynamicBufferRing,
+                AttributeDefinition,
+                AttributeKind,
+                ElementKind,
+            },
+            framebuffer::{
+                FrameBuffer,
+                DrawParameters,
+                DrawPartContext,
+                CullFace,
+                FrameBufferTrait,
+            },
+            state::State,
+        },
+        RenderPassStatistics,
+    },
+};
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
+
+struct LensFlareShader {
+    program: GpuProgram,
+    diffuse_texture: UniformLocation,
+    depth_buffer_texture: UniformLocation,
+    light_screen_position: UniformLocation,
+    occlusion_radius: UniformLocation,
+    light_color: UniformLocation,
+}
+
+impl LensFlareShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/lens_flare_fs.glsl");
+        let vertex_source = include_str!("shaders/lens_flare_vs.glsl");
+        let program = GpuProgram::from_source("LensFlareShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            diffuse_texture: program.uniform_location("diffuseTexture")?,
+            depth_buffer_texture: program.uniform_location("depthBufferTexture")?,
+            light_screen_position: program.uniform_location("lightScreenPosition")?,
+            occlusion_radius: program.uniform_location("occlusionRadius")?,
+            light_color: program.uniform_location("lightColor")?,
+            program,
+        })
+    }
+}
+
+/// OpenGL expects this structure packed as in C.
+#[repr(C)]
+struct LensFlareVertex {
+    /// Position in normalized device coordinates.
+    position: Vec2,
+    tex_coord: Vec2,
+    color: Color,
+}
+
+pub struct LensFlareRenderer {
+    shader: LensFlareShader,
+    geometry_buffer: GeometryBuffer<LensFlareVertex>,
+    vertices: Vec<LensFlareVertex>,
+    triangles: Vec<TriangleDefinition>,
+}
+
+pub struct LensFlareRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub framebuffer: &'b mut FrameBuffer,
+    pub graph: &'c Graph,
+    pub camera: &'c Camera,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub depth: Rc<RefCell<GpuTexture>>,
+    pub viewport: Rect<i32>,
+    pub textures: &'a mut TextureCache,
+}
+
+const CORNERS: [Vec2; 4] = [
+    Vec2 { x: -1.0, y: -1.0 },
+    Vec2 { x: 1.0, y: -1.0 },
+    Vec2 { x: 1.0, y: 1.0 },
+    Vec2 { x: -1.0, y: 1.0 },
+];
+
+impl LensFlareRenderer {
+    pub fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry_buffer = GeometryBuffer::new_in_ring(state, ring, ElementKind::Triangle);
+
+        geometry_buffer.bind(state)
+            .describe_attributes(vec![
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: true },
+            ])?;
+
+        Ok(Self {
+            shader: LensFlareShader::new()?,
+            geometry_buffer,
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+        })
+    }
+
+    pub fn render(&mut self, args: LensFlareRenderContext) -> Result<RenderPassStatistics, RendererError> {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let LensFlareRenderContext {
+            state, framebuffer, graph,
+            camera, white_dummy, depth,
+            viewport, textures,
+        } = args;
+
+        let view_projection = camera.view_projection_matrix();
+        let camera_position = camera.global_position();
+        let aspect = viewport.w as f32 / (viewport.h as f32).max(1.0);
+
+        state.set_blend_func(gl::SRC_ALPHA, gl::ONE);
+
+        for light in graph.linear_iter().filter_map(|node| {
+            if let Node::Light(light) = node { Some(light) } else { None }
+        }) {
+            let lens_flare = match light.lens_flare() {
+                Some(lens_flare) if light.global_visibility() && !lens_flare.elements().is_empty() => lens_flare,
+                _ => continue,
+            };
+
+            // Directional light has no position, so its flare is placed almost at far plane
+            // along direction to light.
+            let world_position = match light.kind() {
+                LightKind::Directional => {
+                    let to_light = light.up_vector().normalized().unwrap_or(Vec3::UP);
+                    camera_position + to_light.scale(0.99 * camera.projection_z_far())
+                }
+                LightKind::Spot(_) | LightKind::Point(_) => light.global_position(),
+            };
+
+            let clip = view_projection.transform_vector4(Vec4::new(world_position.x, world_position.y, world_position.z, 1.0));
+            if clip.w <= 0.0 {
+                continue;
+            }
+            let ndc = clip.xyz().scale(1.0 / clip.w);
+            if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z > 1.0 {
+                continue;
+            }
+
+            self.vertices.clear();
+            self.triangles.clear();
+
+            for element in lens_flare.elements() {
+                let center = Vec2::new(ndc.x, ndc.y).scale(1.0 - 2.0 * element.offset);
+                let half_size = Vec2::new(element.size / aspect, element.size);
+                let base = self.vertices.len() as u32;
+                for corner in CORNERS.iter() {
+                    self.vertices.push(LensFlareVertex {
+                        position: Vec2::new(center.x + corner.x * half_size.x, center.y + corner.y * half_size.y),
+                        tex_coord: Vec2::new(0.5 + 0.5 * corner.x, 0.5 + 0.5 * corner.y),
+                        color: element.color,
+                    });
+                }
+                self.triangles.push(TriangleDefinition([base, base + 1, base + 2]));
+                self.triangles.push(TriangleDefinition([base, base + 2, base + 3]));
+            }
+
+            self.geometry_buffer
+                .bind(state)
+                .set_triangles(&self.triangles)
+                .set_vertices(&self.vertices);
+
+            // Depth buffer stores depth in [0; 1] range, same as texture coordinates.
+            let light_screen_position = Vec3::new(0.5 * ndc.x + 0.5, 0.5 * ndc.y + 0.5, 0.5 * ndc.z + 0.5);
+            let occlusion_radius = Vec2::new(lens_flare.occlusion_radius() / aspect, lens_flare.occlusion_radius());
+            let light_color = light.color().as_frgba().xyz().scale(lens_flare.intensity() * light.fade_factor(camera_position) * light.flicker_factor());
+
+            for (i, element) in lens_flare.elements().iter().enumerate() {
+                let diffuse_texture = element.texture
+                    .clone()
+                    .and_then(|texture| textures.get(state, texture))
+                    .unwrap_or_else(|| white_dummy.clone());
+
+                statistics += framebuffer.draw_part(
+                    DrawPartContext {
+                        state,
+                        viewport,
+                        geometry: &mut self.geometry_buffer,
+                        program: &mut self.shader.program,
+                        params: DrawParameters {
+                            cull_face: CullFace::Back,
+                            culling: false,
+                            color_write: Default::default(),
+                            depth_write: false,
+                            stencil_test: false,
+                            depth_test: false,
+                            blend: true,
+                        },
+                        uniforms: &[
+                            (self.shader.depth_buffer_texture, UniformValue::Sampler {
+                                index: 0,
+                                texture: depth.clone(),
+                            }),
+                            (self.shader.diffuse_texture, UniformValue::Sampler {
+                                index: 1,
+                                texture: diffuse_texture,
+                            }),
+                            (self.shader.light_screen_position, UniformValue::Vec3(light_screen_position)),
+                            (self.shader.occlusion_radius, UniformValue::Vec2(occlusion_radius)),
+                            (self.shader.light_color, UniformValue::Vec3(light_color)),
+                        ],
+                        offset: 2 * i,
+                        count: 2,
+                    }
+                )?;
+            }
+        }
+
+        Ok(statistics)
+    }
+}
@@ -0,0 +1,99 @@
+//! Bakes light probes of scene, see `scene::light_probe` module docs.
+
+use std::f32::consts::PI;
+use crate::{
+    core::math::{
+        vec3::Vec3,
+        aabb::AxisAlignedBoundingBox,
+    },
+    scene::{
+        Scene,
+        node::Node,
+        camera::Camera,
+        light_probe::{
+            AmbientCube,
+            LightProbeGrid,
+        },
+    },
+    renderer::{
+        Renderer,
+        error::RendererError,
+        gbuffer::GBuffer,
+    },
+};
+
+/// Size of frame rendered for each face of probe, light is averaged over frame so it
+/// does not need to be large.
+const FACE_SIZE: usize = 16;
+const Z_NEAR: f32 = 0.05;
+const Z_FAR: f32 = 500.0;
+
+impl Renderer {
+    /// Places light probes every `spacing` units inside `bounds` and bakes them by rendering
+    /// lit scene in six directions around each probe. Meshes which use light probes are not
+    /// rendered, pixels without geometry get global ambient color. Previous probes of scene
+    /// are replaced.
+    ///
+    /// Each probe takes six full renders of scene, so this is heavy operation that should be
+    /// done when level is loaded or in editor.
+    pub fn bake_light_probes(&mut self, scene: &mut Scene, bounds: &AxisAlignedBoundingBox, spacing: f32)
+                             -> Result<(), RendererError> {
+        let mut grid = LightProbeGrid::new(bounds, spacing);
+
+        // Dynamic objects receive light from probes, but must not affect them.
+        let mut hidden = Vec::new();
+        for (handle, node) in scene.graph.pair_iter_mut() {
+            if let Node::Mesh(mesh) = node {
+                if mesh.is_use_light_probes() && mesh.global_visibility {
+                    mesh.global_visibility = false;
+                    hidden.push(handle);
+                }
+            }
+        }
+
+        let result = self.render_light_probes(scene, &mut grid);
+
+        for handle in hidden {
+            scene.graph[handle].global_visibility = true;
+        }
+
+        result?;
+        scene.light_probes = grid;
+
+        Ok(())
+    }
+
+    fn render_light_probes(&mut self, scene: &Scene, grid: &mut LightProbeGrid) -> Result<(), RendererError> {
+        let mut gbuffer = GBuffer::new(&mut self.state, self.gbuffer_shader.clone(), FACE_SIZE, FACE_SIZE)?;
+
+        let ambient = Vec3::new(
+            f32::from(self.ambient_color.r),
+            f32::from(self.ambient_color.g),
+            f32::from(self.ambient_color.b),
+        ).scale(1.0 / 255.0);
+
+        for index in 0..grid.len() {
+            let position = grid.probe_position(index);
+            for (face, direction) in AmbientCube::DIRECTIONS.iter().enumerate() {
+                let up = if direction.y != 0.0 { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::UP };
+                let camera = Camera::looking_at(position, position + *direction, up, PI * 0.5, Z_NEAR, Z_FAR);
+
+                let (color, normals) = self.render_offscreen(scene, &mut gbuffer, &camera)?;
+
+                let mut sum = Vec3::ZERO;
+                for (pixel, normal) in color.chunks(4).zip(normals.chunks(4)) {
+                    // Zero normal means that there is no geometry, light comes from "sky".
+                    sum += if normal[..3].iter().any(|&n| n != 0) {
+                        Vec3::new(f32::from(pixel[0]), f32::from(pixel[1]), f32::from(pixel[2])).scale(1.0 / 255.0)
+                    } else {
+                        ambient
+                    };
+                }
+
+                grid.probes_mut()[index].colors[face] = sum.scale(1.0 / (FACE_SIZE * FACE_SIZE) as f32);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -125,6 +125,7 @@ impl LightVolumeRenderer {
     pub fn render_volume(&mut self,
                          state: &mut State,
                          light: &Light,
+                         fade: f32,
                          gbuffer: &mut GBuffer,
                          quad: &SurfaceSharedData,
                          geom_cache: &mut GeometryCache,
@@ -217,7 +218,7 @@ impl LightVolumeRenderer {
                         (self.spot_light_shader.light_position, UniformValue::Vec3(position)),
                         (self.spot_light_shader.light_direction, UniformValue::Vec3(direction)),
                         (self.spot_light_shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
-                        (self.spot_light_shader.light_color, UniformValue::Vec3(light.color().as_frgba().xyz())),
+                        (self.spot_light_shader.light_color, UniformValue::Vec3(light.color().as_frgba().xyz().scale(fade))),
                         (self.spot_light_shader.scatter_factor, UniformValue::Vec3(light.scatter())),
                     ],
                 )
@@ -284,7 +285,7 @@ impl LightVolumeRenderer {
                         (self.point_light_shader.light_position, UniformValue::Vec3(position)),
                         (self.point_light_shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
                         (self.point_light_shader.light_radius, UniformValue::Float(point.radius())),
-                        (self.point_light_shader.light_color, UniformValue::Vec3(light.color().as_frgba().xyz())),
+                        (self.point_light_shader.light_color, UniformValue::Vec3(light.color().as_frgba().xyz().scale(fade))),
                         (self.point_light_shader.scatter_factor, UniformValue::Vec3(light.scatter())),
                     ],
                 )
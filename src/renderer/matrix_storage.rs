@@ -0,0 +1,80 @@
+//! Matrix storage is a texture which holds array of matrices, each row of texture
+//! contains one matrix (four RGBA32F texels - one per column). Uniform arrays are
+//! limited in size, texture has no such limitation, so it is used to pass bone
+//! matrices of skinned meshes to shaders. Use `S_FetchMatrix` from shared.glsl to
+//! read matrix in shader.
+
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
+use crate::{
+    core::math::mat4::Mat4,
+    renderer::{
+        framework::{
+            gpu_texture::{
+                GpuTexture,
+                GpuTextureKind,
+                PixelKind,
+                MagnificationFilter,
+                MininificationFilter,
+            },
+            state::State,
+        },
+        error::RendererError,
+    },
+};
+
+pub struct MatrixStorage {
+    texture: Rc<RefCell<GpuTexture>>,
+    capacity: usize,
+    bytes: Vec<u8>,
+}
+
+const BYTES_PER_MATRIX: usize = 16 * std::mem::size_of::<f32>();
+
+fn create_texture(state: &mut State, capacity: usize) -> Result<Rc<RefCell<GpuTexture>>, RendererError> {
+    let kind = GpuTextureKind::Rectangle { width: 4, height: capacity };
+    let mut texture = GpuTexture::new(state, kind, PixelKind::RGBA32F, None)?;
+    texture.bind_mut(state, 0)
+        .set_minification_filter(MininificationFilter::Nearest)
+        .set_magnification_filter(MagnificationFilter::Nearest);
+    Ok(Rc::new(RefCell::new(texture)))
+}
+
+impl MatrixStorage {
+    /// Initial amount of matrices, storage will grow if needed.
+    const INITIAL_CAPACITY: usize = 256;
+
+    pub fn new(state: &mut State) -> Result<Self, RendererError> {
+        Ok(Self {
+            texture: create_texture(state, Self::INITIAL_CAPACITY)?,
+            capacity: Self::INITIAL_CAPACITY,
+            bytes: Vec::new(),
+        })
+    }
+
+    /// Uploads matrices to GPU. Texture is re-created with larger size if there is not
+    /// enough space for given matrices.
+    pub fn upload(&mut self, state: &mut State, matrices: &[Mat4]) -> Result<(), RendererError> {
+        if matrices.len() > self.capacity {
+            self.capacity = matrices.len().next_power_of_two();
+            self.texture = create_texture(state, self.capacity)?;
+        }
+
+        self.bytes.clear();
+        for matrix in matrices {
+            for value in matrix.f.iter() {
+                self.bytes.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        // Texture is always updated entirely, rest of it is filled with zeros.
+        self.bytes.resize(self.capacity * BYTES_PER_MATRIX, 0);
+
+        self.texture.borrow_mut().set_data(state, &self.bytes)
+    }
+
+    pub fn texture(&self) -> Rc<RefCell<GpuTexture>> {
+        self.texture.clone()
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use crate::{
+    scene::{
+        node::Node,
+        graph::Graph,
+        camera::Camera,
+    },
+    core::{
+        scope_profile,
+        math::{
+            Rect,
+            vec2::Vec2,
+        },
+        pool::Handle,
+    },
+    renderer::{
+        surface::SurfaceSharedData,
+        gbuffer::GBuffer,
+        GeometryCache,
+        error::RendererError,
+        framework::{
+            gpu_program::{
+                UniformValue,
+                GpuProgram,
+                UniformLocation,
+            },
+            framebuffer::{
+                FrameBuffer,
+                DrawParameters,
+                CullFace,
+                FrameBufferTrait,
+            },
+            state::State,
+        },
+        RenderPassStatistics,
+    },
+};
+
+struct MirrorShader {
+    program: GpuProgram,
+    wvp_matrix: UniformLocation,
+    reflection_texture: UniformLocation,
+    inv_screen_size: UniformLocation,
+    tint: UniformLocation,
+}
+
+impl MirrorShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/mirror_fs.glsl");
+        let vertex_source = include_str!("shaders/mirror_vs.glsl");
+        let program = GpuProgram::from_source("MirrorShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            wvp_matrix: program.uniform_location("worldViewProjection")?,
+            reflection_texture: program.uniform_location("reflectionTexture")?,
+            inv_screen_size: program.uniform_location("invScreenSize")?,
+            tint: program.uniform_location("tint")?,
+            program,
+        })
+    }
+}
+
+/// Draws mirrors using reflections which were rendered for them before.
+pub struct MirrorRenderer {
+    shader: MirrorShader,
+    quad: SurfaceSharedData,
+}
+
+pub struct MirrorRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub framebuffer: &'b mut FrameBuffer,
+    pub graph: &'c Graph,
+    pub camera: &'c Camera,
+    pub camera_handle: Handle<Node>,
+    /// Reflections for each pair of camera and mirror.
+    pub reflections: &'c HashMap<(Handle<Node>, Handle<Node>), GBuffer>,
+    pub viewport: Rect<i32>,
+    pub geometry_cache: &'a mut GeometryCache,
+}
+
+impl MirrorRenderer {
+    pub fn new() -> Result<Self, RendererError> {
+        Ok(Self {
+            shader: MirrorShader::new()?,
+            quad: SurfaceSharedData::make_unit_xy_quad(),
+        })
+    }
+
+    pub fn render(&mut self, args: MirrorRenderContext) -> RenderPassStatistics {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let MirrorRenderContext {
+            state, framebuffer, graph,
+            camera, camera_handle, reflections,
+            viewport, geometry_cache
+        } = args;
+
+        let view_projection = camera.view_projection_matrix();
+        let inv_screen_size = Vec2::new(1.0 / viewport.w as f32, 1.0 / viewport.h as f32);
+
+        for (mirror_handle, mirror) in graph.pair_iter().filter_map(|(handle, node)| {
+            if let Node::Mirror(mirror) = node { Some((handle, mirror)) } else { None }
+        }) {
+            // Reflection is rendered only for visible mirrors.
+            let reflection = match reflections.get(&(camera_handle, mirror_handle)) {
+                Some(reflection) if mirror.global_visibility() && mirror.is_facing(camera.global_position()) => reflection,
+                _ => continue,
+            };
+
+            statistics += framebuffer.draw(
+                geometry_cache.get(state, &self.quad),
+                state,
+                viewport,
+                &self.shader.program,
+                DrawParameters {
+                    cull_face: CullFace::Back,
+                    culling: false,
+                    color_write: Default::default(),
+                    depth_write: true,
+                    stencil_test: false,
+                    depth_test: true,
+                    blend: false,
+                },
+                &[
+                    (self.shader.wvp_matrix, UniformValue::Mat4(view_projection * mirror.quad_transform())),
+                    (self.shader.reflection_texture, UniformValue::Sampler {
+                        index: 0,
+                        texture: reflection.frame_texture(),
+                    }),
+                    (self.shader.inv_screen_size, UniformValue::Vec2(inv_screen_size)),
+                    (self.shader.tint, UniformValue::Color(mirror.tint())),
+                ],
+            );
+        }
+
+        statistics
+    }
+}
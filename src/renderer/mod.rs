@@ -3,6 +3,11 @@
 pub mod surface;
 pub mod error;
 pub mod debug_renderer;
+pub mod frame_pacing;
+pub mod frame_arena;
+pub mod window_view;
+pub mod top_down_capture;
+pub mod preview;
 
 // Framework wraps all OpenGL calls so it has to be unsafe. Rest of renderer
 // code must be safe.
@@ -17,9 +22,34 @@ mod deferred_light_renderer;
 mod shadow_map_renderer;
 mod flat_shader;
 mod sprite_renderer;
+mod trail_renderer;
+mod text3d_renderer;
+mod transparent_renderer;
 mod ssao;
+mod ssr;
 mod blur;
 mod light_volume;
+mod matrix_storage;
+mod lens_flare_renderer;
+mod mirror_renderer;
+mod projector_renderer;
+mod impostor_baker;
+mod light_probe_baker;
+mod exposure;
+mod render_list;
+mod picking;
+pub(in crate) mod resource_tracker;
+
+pub use framework::{
+    state::StateStatistics,
+    program_cache::{
+        ShaderCompilationProgress,
+        ShaderCompilationCallback,
+        set_program_cache_directory,
+        set_shader_compilation_callback,
+    },
+};
+pub(in crate) use framework::state::forget_lost_objects;
 
 use glutin::PossiblyCurrent;
 use std::{
@@ -29,29 +59,61 @@ use std::{
         Mutex,
     },
     time,
-    collections::HashMap,
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
     cell::RefCell,
+    ffi::c_void,
 };
 use crate::{
-    resource::texture::Texture,
+    resource::{
+        texture::{
+            Texture,
+            TextureFilter,
+            f16_to_f32,
+        },
+        texture_array::TextureArray,
+    },
     renderer::{
         ui_renderer::{
             UiRenderer,
             UiRenderContext,
         },
         surface::SurfaceSharedData,
-        particle_system_renderer::{
-            ParticleSystemRenderer,
-            ParticleSystemRenderContext,
+        resource_tracker::{
+            SURFACE_DATA_QUEUE,
+            TEXTURE_QUEUE,
+        },
+        window_view::{
+            WindowView,
+            ViewTarget,
+            is_shown_in_view,
+        },
+        frame_arena::{
+            FrameArena,
+            FrameArenaStatistics,
+        },
+        render_list::{
+            RenderListBuilder,
+            PendingRenderList,
+            GraphSnapshot,
+            DistanceCulling,
         },
         gbuffer::{
             GBuffer,
+            GBufferShader,
             GBufferRenderContext,
         },
         deferred_light_renderer::{
             DeferredLightRenderer,
             DeferredRendererContext,
         },
+        picking::{
+            PickingRenderer,
+            PickingRenderContext,
+        },
         error::RendererError,
         framework::{
             gpu_texture::{
@@ -67,36 +129,62 @@ use crate::{
                 ElementKind,
                 AttributeKind,
                 AttributeDefinition,
-                DrawCallStatistics
+                DrawCallStatistics,
+                DynamicBufferRing,
             },
             framebuffer::{
                 BackBuffer,
                 FrameBufferTrait,
-                DrawParameters,
-                CullFace,
             },
-            gpu_program::UniformValue,
-            state::State,
+            state::{
+                State,
+                LogDepth,
+            },
+            pixel_buffer::PixelBuffer,
             gl,
         },
-        flat_shader::FlatShader,
-        sprite_renderer::{
-            SpriteRenderer,
-            SpriteRenderContext,
+        exposure::{
+            ExposureRenderer,
+            ExposureRenderContext,
+        },
+        transparent_renderer::{
+            TransparentRenderer,
+            TransparentRenderContext,
+        },
+        lens_flare_renderer::{
+            LensFlareRenderer,
+            LensFlareRenderContext,
+        },
+        mirror_renderer::{
+            MirrorRenderer,
+            MirrorRenderContext,
+        },
+        projector_renderer::{
+            ProjectorRenderer,
+            ProjectorRenderContext,
         },
         debug_renderer::DebugRenderer,
+        frame_pacing::{
+            FrameLimiter,
+            FrameTimeHistory,
+            FrameTimePercentiles,
+        },
     },
     scene::{
+        Scene,
         SceneContainer,
         node::Node,
+        camera::{
+            Camera,
+            PostEffectOverrides,
+        },
     },
     core::{
         scope_profile,
         math::{
-            vec3::Vec3,
-            mat4::Mat4,
             vec2::Vec2,
             TriangleDefinition,
+            frustum::Frustum,
         },
         color::Color,
         math::Rect,
@@ -104,6 +192,7 @@ use crate::{
     },
     gui::draw::DrawingContext,
     engine::resource_manager::TimedEntry,
+    utils::log::Log,
 };
 
 #[derive(Copy, Clone)]
@@ -117,6 +206,12 @@ pub struct Statistics {
     pub capped_frame_time: f32,
     /// Total amount of frames been rendered in one second.
     pub frames_per_second: usize,
+    /// Percentiles of time between consecutive frames, includes time spent in frame
+    /// limiter and vsync.
+    pub frame_time_percentiles: FrameTimePercentiles,
+    /// Amount of GL state changes made during last frame and amount of redundant changes
+    /// which were skipped by state cache.
+    pub state_changes: StateStatistics,
     frame_counter: usize,
     frame_start_time: time::Instant,
     last_fps_commit_time: time::Instant,
@@ -157,13 +252,84 @@ impl std::ops::AddAssign<RenderPassStatistics> for Statistics {
     }
 }
 
+/// Amount of samples of Poisson disc used to filter shadows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PoissonKernel {
+    /// 8 samples.
+    Small,
+    /// 16 samples.
+    Medium,
+    /// 32 samples.
+    Large,
+}
+
+impl PoissonKernel {
+    pub fn samples(self) -> usize {
+        match self {
+            PoissonKernel::Small => 8,
+            PoissonKernel::Medium => 16,
+            PoissonKernel::Large => 32,
+        }
+    }
+}
+
+/// Defines how shadow map is sampled. Shadow map is a grid of depths, so sampling it once
+/// gives jagged edges of shadows which shimmer when light or camera moves, filters take
+/// many samples around point to smooth edges.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadowFilter {
+    /// Single sample, fastest but with aliased edges.
+    Hard,
+    /// Hardware percentage closer filtering - bilinear interpolation of comparison results
+    /// of four nearest texels in one sample. Cheap, but edges are only one texel wide. Point
+    /// shadow maps store distance to light instead of depth and can't be compared by hardware,
+    /// so point shadows use small Poisson kernel instead.
+    HardwarePcf,
+    /// Percentage closer filtering with samples distributed over Poisson disc, more samples
+    /// give smoother edges with less noise.
+    Poisson(PoissonKernel),
+    /// Exponential shadow map - instead of binary comparison each sample gives exponentially
+    /// falling visibility, so edges are smooth without banding of PCF. Exponent is set by
+    /// `QualitySettings::shadows_esm_exponent`: higher values make edges sharper and reduce
+    /// light leaking near contact of caster and receiver.
+    Exponential(PoissonKernel),
+}
+
+impl ShadowFilter {
+    /// Returns filter which is used for point shadows instead of this one.
+    pub(in crate) fn point_fallback(self) -> Self {
+        match self {
+            ShadowFilter::HardwarePcf => ShadowFilter::Poisson(PoissonKernel::Small),
+            _ => self
+        }
+    }
+
+    /// Returns index of filter in shaders, see `S_SHADOW_FILTER_*` in shared.glsl.
+    pub(in crate) fn shader_index(self) -> i32 {
+        match self {
+            ShadowFilter::Hard => 0,
+            ShadowFilter::HardwarePcf => 1,
+            ShadowFilter::Poisson(_) => 2,
+            ShadowFilter::Exponential(_) => 3,
+        }
+    }
+
+    /// Returns amount of samples taken by filter.
+    pub fn samples(self) -> usize {
+        match self {
+            ShadowFilter::Hard | ShadowFilter::HardwarePcf => 1,
+            ShadowFilter::Poisson(kernel) | ShadowFilter::Exponential(kernel) => kernel.samples(),
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub struct QualitySettings {
     /// Point shadows
     /// Size of cube map face of shadow map texture in pixels.
     pub point_shadow_map_size: usize,
-    /// Use or not percentage close filtering (smoothing) for point shadows.
-    pub point_soft_shadows: bool,
+    /// Filter of point shadows, defines how smooth edges of shadows are.
+    pub point_shadow_filter: ShadowFilter,
     /// Point shadows enabled or not.
     pub point_shadows_enabled: bool,
     /// Maximum distance from camera to draw shadows.
@@ -172,22 +338,55 @@ pub struct QualitySettings {
     /// Spot shadows
     /// Size of square shadow map texture in pixels
     pub spot_shadow_map_size: usize,
-    /// Use or not percentage close filtering (smoothing) for spot shadows.
-    pub spot_soft_shadows: bool,
+    /// Filter of spot shadows, defines how smooth edges of shadows are.
+    pub spot_shadow_filter: ShadowFilter,
     /// Spot shadows enabled or not.
     pub spot_shadows_enabled: bool,
     /// Maximum distance from camera to draw shadows.
     pub spot_shadows_distance: f32,
 
+    /// Length of range before max shadow distance of point and spot lights in which
+    /// shadows of a light gradually fade out, so they do not pop when light crosses max
+    /// distance. Zero means shadows are switched off instantly.
+    pub shadows_fade_distance: f32,
+    /// Exponent of exponential shadow filter, see `ShadowFilter::Exponential`.
+    pub shadows_esm_exponent: f32,
+
     /// Whether to use screen space ambient occlusion or not.
     pub use_ssao: bool,
     /// Radius of sampling hemisphere used in SSAO, it defines much ambient
     /// occlusion will be in your scene.
     pub ssao_radius: f32,
 
+    /// Whether to use screen space reflections or not. Only surfaces with non-zero
+    /// reflectivity have reflections.
+    pub use_ssr: bool,
+    /// Maximum distance (in view space) at which reflected objects are searched.
+    pub ssr_max_distance: f32,
+
     /// Global switch to enable or disable light scattering. Each light can have
     /// its own scatter switch, but this one is able to globally disable scatter.
     pub light_scatter_enabled: bool,
+
+    /// Global distance from camera beyond which meshes are not rendered, `None` means
+    /// no limit. Each node can have its own limit, see `Base::set_max_render_distance`.
+    pub draw_distance: Option<f32>,
+    /// Fraction of draw distance which mesh hidden by distance must come closer by to be
+    /// shown again, prevents flickering of meshes near the limit.
+    pub draw_distance_hysteresis: f32,
+
+    /// Whether to use logarithmic depth buffer or not. Logarithmic depth distributes precision
+    /// evenly over distance, so it removes z-fighting of distant geometry in huge scenes
+    /// (space, flight simulators) where regular depth gives artifacts at kilometer distances.
+    /// It is a bit slower and may give artifacts on huge triangles close to camera.
+    pub use_logarithmic_depth: bool,
+
+    /// Filter of textures, each texture can override it, see `Texture::set_filter`.
+    pub texture_filter: TextureFilter,
+    /// Max anisotropy of textures, one disables anisotropic filtering. Value is clamped
+    /// to max anisotropy supported by hardware. Each texture can override it, see
+    /// `Texture::set_anisotropy`.
+    pub texture_anisotropy: f32,
 }
 
 impl Default for QualitySettings {
@@ -196,17 +395,45 @@ impl Default for QualitySettings {
             point_shadow_map_size: 1024,
             point_shadows_distance: 15.0,
             point_shadows_enabled: true,
-            point_soft_shadows: true,
+            point_shadow_filter: ShadowFilter::Poisson(PoissonKernel::Medium),
 
             spot_shadow_map_size: 1024,
             spot_shadows_distance: 15.0,
             spot_shadows_enabled: true,
-            spot_soft_shadows: true,
+            spot_shadow_filter: ShadowFilter::Poisson(PoissonKernel::Medium),
+
+            shadows_fade_distance: 3.0,
+            shadows_esm_exponent: 40.0,
 
             use_ssao: true,
             ssao_radius: 0.5,
 
-            light_scatter_enabled: true
+            use_ssr: false,
+            ssr_max_distance: 10.0,
+
+            light_scatter_enabled: true,
+
+            draw_distance: None,
+            draw_distance_hysteresis: 0.1,
+
+            use_logarithmic_depth: false,
+
+            texture_filter: TextureFilter::Trilinear,
+            texture_anisotropy: 16.0,
+        }
+    }
+}
+
+impl QualitySettings {
+    /// Returns copy of settings with per-camera overrides applied.
+    pub fn with_overrides(&self, overrides: &PostEffectOverrides) -> Self {
+        Self {
+            use_ssao: overrides.use_ssao.unwrap_or(self.use_ssao),
+            ssao_radius: overrides.ssao_radius.unwrap_or(self.ssao_radius),
+            use_ssr: overrides.use_ssr.unwrap_or(self.use_ssr),
+            ssr_max_distance: overrides.ssr_max_distance.unwrap_or(self.ssr_max_distance),
+            light_scatter_enabled: overrides.light_scatter_enabled.unwrap_or(self.light_scatter_enabled),
+            ..*self
         }
     }
 }
@@ -245,6 +472,8 @@ impl Default for Statistics {
             pure_frame_time: 0.0,
             capped_frame_time: 0.0,
             frames_per_second: 0,
+            frame_time_percentiles: Default::default(),
+            state_changes: Default::default(),
             frame_counter: 0,
             frame_start_time: time::Instant::now(),
             last_fps_commit_time: time::Instant::now(),
@@ -255,41 +484,71 @@ impl Default for Statistics {
 pub struct Renderer {
     state: State,
     backbuffer: BackBuffer,
+    /// Context is owned by host application, which may change its state between frames.
+    external_context: bool,
     deferred_light_renderer: DeferredLightRenderer,
-    flat_shader: FlatShader,
-    sprite_renderer: SpriteRenderer,
-    particle_system_renderer: ParticleSystemRenderer,
+    exposure_renderer: ExposureRenderer,
+    transparent_renderer: TransparentRenderer,
+    lens_flare_renderer: LensFlareRenderer,
+    mirror_renderer: MirrorRenderer,
+    projector_renderer: ProjectorRenderer,
+    /// Present while picking is enabled, see `picking` module docs.
+    picking_renderer: Option<PickingRenderer>,
     /// Dummy white one pixel texture which will be used as stub when rendering
     /// something without texture specified.
     white_dummy: Rc<RefCell<GpuTexture>>,
     /// Dummy one pixel texture with (0, 1, 0) vector is used as stub when rendering
     /// something without normal map.
     normal_dummy: Rc<RefCell<GpuTexture>>,
+    /// Dummy one layer texture array, bound to samplers of texture arrays of surfaces
+    /// without them.
+    array_dummy: Rc<RefCell<GpuTexture>>,
     ui_renderer: UiRenderer,
     statistics: Statistics,
-    quad: SurfaceSharedData,
     frame_size: (u32, u32),
     ambient_color: Color,
     quality_settings: QualitySettings,
     pub debug_renderer: DebugRenderer,
+    gbuffer_shader: Rc<GBufferShader>,
+    render_list_builder: RenderListBuilder,
+    /// Meshes hidden by distance in last frame of each camera.
+    distance_culled: HashMap<Handle<Node>, HashSet<Handle<Node>>>,
     gbuffers: HashMap<Handle<Node>, GBuffer>,
+    /// Reflections of mirrors for each pair of camera and mirror.
+    mirror_gbuffers: HashMap<(Handle<Node>, Handle<Node>), GBuffer>,
     backbuffer_clear_color: Color,
     texture_cache: TextureCache,
     geometry_cache: GeometryCache,
+    vsync: bool,
+    frame_limiter: FrameLimiter,
+    frame_time_history: FrameTimeHistory,
+    /// Temporary buffers of frame, see `frame_arena` module docs.
+    frame_arena: FrameArena,
+    /// Amount of pixels per unit of user interface.
+    ui_scale: f32,
 }
 
 #[derive(Default)]
+struct SurfaceGeometry {
+    buffer: GeometryBuffer<surface::Vertex>,
+    modification_count: u64,
+    /// Amount of uploaded data in bytes.
+    size_bytes: usize,
+}
+
 pub struct GeometryCache {
-    map: HashMap<usize, TimedEntry<GeometryBuffer<surface::Vertex>>>
+    map: HashMap<u64, TimedEntry<SurfaceGeometry>>
 }
 
 impl GeometryCache {
     fn get(&mut self, state: &mut State, data: &SurfaceSharedData) -> &mut GeometryBuffer<surface::Vertex> {
         scope_profile!();
 
-        let key = (data as *const _) as usize;
+        let key = data.tracker.id();
+
+        let geometry = self.map.entry(key).or_insert_with(|| {
+            data.tracker.mark_uploaded();
 
-        let geometry_buffer = self.map.entry(key).or_insert_with(|| {
             let geometry_buffer = GeometryBuffer::new(GeometryBufferKind::StaticDraw, ElementKind::Triangle);
 
             geometry_buffer.bind(state)
@@ -299,19 +558,41 @@ impl GeometryCache {
                     AttributeDefinition { kind: AttributeKind::Float3, normalized: false },
                     AttributeDefinition { kind: AttributeKind::Float4, normalized: false },
                     AttributeDefinition { kind: AttributeKind::Float4, normalized: false },
-                    AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: false }])
+                    AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: false },
+                    AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                    AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: true }])
                 .unwrap()
                 .set_vertices(data.vertices.as_slice())
                 .set_triangles(data.triangles());
 
-            TimedEntry { value: geometry_buffer, time_to_live: 20.0 }
+            TimedEntry {
+                value: SurfaceGeometry {
+                    buffer: geometry_buffer,
+                    modification_count: data.modification_count(),
+                    size_bytes: data.memory_usage(),
+                },
+                time_to_live: 20.0,
+            }
         });
 
-        geometry_buffer.time_to_live = 20.0;
-        geometry_buffer
+        // Data was changed since last upload.
+        if geometry.modification_count != data.modification_count() {
+            geometry.buffer.bind(state)
+                .set_vertices(data.vertices.as_slice())
+                .set_triangles(data.triangles());
+            geometry.value.modification_count = data.modification_count();
+            geometry.value.size_bytes = data.memory_usage();
+        }
+
+        geometry.time_to_live = 20.0;
+        &mut geometry.value.buffer
     }
 
     fn update(&mut self, dt: f32) {
+        // Free geometry of dropped surface data immediately.
+        for id in SURFACE_DATA_QUEUE.drain() {
+            self.map.remove(&id);
+        }
         for entry in self.map.values_mut() {
             entry.time_to_live -= dt;
         }
@@ -321,89 +602,309 @@ impl GeometryCache {
     fn clear(&mut self) {
         self.map.clear();
     }
+
+    fn memory_usage(&self) -> usize {
+        self.map.values().map(|entry| entry.value.size_bytes).sum()
+    }
+}
+
+/// Textures larger than this are uploaded asynchronously, smaller ones are cheap enough to
+/// be uploaded at first use.
+const ASYNC_TEXTURE_UPLOAD_THRESHOLD: usize = 256 * 1024;
+
+/// Default amount of bytes of textures uploaded asynchronously per frame.
+const DEFAULT_TEXTURE_UPLOAD_BUDGET: usize = 16 * 1024 * 1024;
+
+/// Sampling parameters of texture, global ones from quality settings with overrides of
+/// texture applied.
+#[derive(Copy, Clone, PartialEq)]
+struct TextureSampling {
+    filter: TextureFilter,
+    anisotropy: f32,
+}
+
+impl Default for TextureSampling {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Trilinear,
+            anisotropy: 16.0,
+        }
+    }
+}
+
+impl TextureSampling {
+    fn of(texture: &Texture, global: TextureSampling) -> Self {
+        Self {
+            filter: texture.filter.unwrap_or(global.filter),
+            anisotropy: texture.anisotropy.unwrap_or(global.anisotropy),
+        }
+    }
+
+    fn apply(self, state: &mut State, gpu_texture: &mut GpuTexture) {
+        let (min_filter, mag_filter) = match self.filter {
+            TextureFilter::Nearest => (MininificationFilter::Nearest, MagnificationFilter::Nearest),
+            TextureFilter::Bilinear => (MininificationFilter::LinearMipNearest, MagnificationFilter::Linear),
+            TextureFilter::Trilinear => (MininificationFilter::LinearMip, MagnificationFilter::Linear),
+        };
+        gpu_texture.bind_mut(state, 0)
+            .set_minification_filter(min_filter)
+            .set_magnification_filter(mag_filter)
+            .set_anisotropy(self.anisotropy);
+    }
+}
+
+struct CachedTexture {
+    gpu_texture: Rc<RefCell<GpuTexture>>,
+    sampling: TextureSampling,
+}
+
+fn cache_entry(state: &mut State, mut gpu_texture: GpuTexture, sampling: TextureSampling) -> TimedEntry<CachedTexture> {
+    sampling.apply(state, &mut gpu_texture);
+    TimedEntry {
+        value: CachedTexture {
+            gpu_texture: Rc::new(RefCell::new(gpu_texture)),
+            sampling,
+        },
+        time_to_live: 20.0,
+    }
+}
+
+fn create_gpu_texture(state: &mut State, texture: &Texture, staging: Option<&mut PixelBuffer>) -> Result<GpuTexture, RendererError> {
+    let kind = GpuTextureKind::Rectangle {
+        width: texture.width as usize,
+        height: texture.height as usize,
+    };
+    let mut gpu_texture = if let Some(staging) = staging {
+        staging.write(texture.bytes.as_slice());
+        let mut gpu_texture = GpuTexture::new(state, kind, PixelKind::from(texture.kind), None)?;
+        gpu_texture.set_data_from_pixel_buffer(state, staging)?;
+        gpu_texture
+    } else {
+        GpuTexture::new(state, kind, PixelKind::from(texture.kind), Some(texture.bytes.as_slice()))?
+    };
+    gpu_texture.bind_mut(state, 0).generate_mip_maps();
+    Ok(gpu_texture)
 }
 
-#[derive(Default)]
 pub struct TextureCache {
-    map: HashMap<usize, TimedEntry<Rc<RefCell<GpuTexture>>>>
+    map: HashMap<u64, TimedEntry<CachedTexture>>,
+    arrays: HashMap<u64, TimedEntry<CachedTexture>>,
+    /// Large textures waiting for upload, in order of first use.
+    upload_queue: VecDeque<(u64, Arc<Mutex<Texture>>)>,
+    queued: HashSet<u64>,
+    staging: Option<PixelBuffer>,
+    upload_budget: usize,
+    /// Sampling from quality settings.
+    sampling: TextureSampling,
+}
+
+impl Default for TextureCache {
+    fn default() -> Self {
+        Self {
+            map: Default::default(),
+            arrays: Default::default(),
+            upload_queue: Default::default(),
+            queued: Default::default(),
+            staging: None,
+            upload_budget: DEFAULT_TEXTURE_UPLOAD_BUDGET,
+            sampling: Default::default(),
+        }
+    }
 }
 
 impl TextureCache {
+    /// Returns GPU texture for given texture. Large textures are not available until they're
+    /// uploaded by `upload_resources`, `None` is returned for them meanwhile.
     fn get(&mut self, state: &mut State, texture: Arc<Mutex<Texture>>) -> Option<Rc<RefCell<GpuTexture>>> {
         scope_profile!();
 
-        if texture.lock().unwrap().loaded {
-            let key = (&*texture as *const _) as usize;
-            let gpu_texture = self.map.entry(key).or_insert_with(move || {
-                let texture = texture.lock().unwrap();
-                let kind = GpuTextureKind::Rectangle {
-                    width: texture.width as usize,
-                    height: texture.height as usize,
-                };
-                let mut gpu_texture = GpuTexture::new(
-                    state,
-                    kind,
-                    PixelKind::from(texture.kind),
-                    Some(texture.bytes.as_slice()))
-                    .unwrap();
-                gpu_texture.bind_mut(state, 0)
-                    .generate_mip_maps()
-                    .set_minification_filter(MininificationFilter::LinearMip)
-                    .set_magnification_filter(MagnificationFilter::Linear)
-                    .set_max_anisotropy();
-                TimedEntry {
-                    value: Rc::new(RefCell::new(gpu_texture)),
-                    time_to_live: 20.0,
+        let shared = texture.clone();
+        let mut texture = texture.lock().unwrap();
+        if texture.loaded {
+            let key = texture.tracker.id();
+            let mut created = false;
+            if !self.map.contains_key(&key) {
+                if texture.bytes.len() > ASYNC_TEXTURE_UPLOAD_THRESHOLD {
+                    if self.queued.insert(key) {
+                        self.upload_queue.push_back((key, shared));
+                    }
+                    return None;
                 }
-            });
+                created = true;
+                texture.tracker.mark_uploaded();
+                let gpu_texture = create_gpu_texture(state, &texture, None).unwrap();
+                let sampling = TextureSampling::of(&texture, self.sampling);
+                self.map.insert(key, cache_entry(state, gpu_texture, sampling));
+            }
+            let entry = self.map.get_mut(&key).unwrap();
+            // Upload pixels modified since last frame, new texture already has them.
+            if let Some(region) = texture.dirty_region.take() {
+                if !created {
+                    let bytes = texture.region_bytes(region);
+                    let mut gpu_texture = entry.value.gpu_texture.borrow_mut();
+                    if gpu_texture.set_region(state, region.x as usize, region.y as usize,
+                                              region.w as usize, region.h as usize, &bytes).is_ok() {
+                        gpu_texture.bind_mut(state, 0).generate_mip_maps();
+                    }
+                }
+            }
+            // Quality settings or overrides of texture could be changed since last frame.
+            let sampling = TextureSampling::of(&texture, self.sampling);
+            if entry.value.sampling != sampling {
+                sampling.apply(state, &mut entry.value.gpu_texture.borrow_mut());
+                entry.value.sampling = sampling;
+            }
             // Texture won't be destroyed while it used.
-            gpu_texture.time_to_live = 20.0;
-            Some(gpu_texture.value.clone())
+            entry.time_to_live = 20.0;
+            Some(entry.value.gpu_texture.clone())
         } else {
             None
         }
     }
 
+    /// Returns GPU texture for given texture array. Arrays are uploaded immediately, they
+    /// are usually created once on level load.
+    fn get_array(&mut self, state: &mut State, array: &Arc<Mutex<TextureArray>>) -> Option<Rc<RefCell<GpuTexture>>> {
+        scope_profile!();
+
+        let mut array = array.lock().unwrap();
+        let key = array.tracker.id();
+        if array.modified {
+            array.modified = false;
+            self.arrays.remove(&key);
+        }
+        if !self.arrays.contains_key(&key) {
+            let kind = GpuTextureKind::Array {
+                width: array.width() as usize,
+                height: array.height() as usize,
+                layers: array.layer_count() as usize,
+            };
+            let mut gpu_texture = match GpuTexture::new(state, kind, PixelKind::from(array.kind()), Some(array.bytes.as_slice())) {
+                Ok(gpu_texture) => gpu_texture,
+                Err(e) => {
+                    Log::writeln(format!("Unable to upload texture array. Reason: {:?}", e));
+                    return None;
+                }
+            };
+            gpu_texture.bind_mut(state, 0).generate_mip_maps();
+            array.tracker.mark_uploaded();
+            self.arrays.insert(key, cache_entry(state, gpu_texture, self.sampling));
+        }
+        let entry = self.arrays.get_mut(&key).unwrap();
+        if entry.value.sampling != self.sampling {
+            self.sampling.apply(state, &mut entry.value.gpu_texture.borrow_mut());
+            entry.value.sampling = self.sampling;
+        }
+        entry.time_to_live = 20.0;
+        Some(entry.value.gpu_texture.clone())
+    }
+
+    /// Uploads queued textures through pixel buffer until per-frame budget is spent. At
+    /// least one texture is uploaded per frame, even if it is larger than budget.
+    fn upload_resources(&mut self, state: &mut State) {
+        scope_profile!();
+
+        let mut uploaded = 0;
+        while uploaded < self.upload_budget {
+            let (key, texture) = match self.upload_queue.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            self.queued.remove(&key);
+
+            let mut texture = texture.lock().unwrap();
+            if !texture.loaded || texture.tracker.id() != key || self.map.contains_key(&key) {
+                continue;
+            }
+
+            let staging = self.staging.get_or_insert_with(PixelBuffer::new);
+            match create_gpu_texture(state, &texture, Some(staging)) {
+                Ok(gpu_texture) => {
+                    texture.tracker.mark_uploaded();
+                    // New texture already has all pixels.
+                    texture.dirty_region = None;
+                    uploaded += texture.bytes.len();
+                    let sampling = TextureSampling::of(&texture, self.sampling);
+                    self.map.insert(key, cache_entry(state, gpu_texture, sampling));
+                }
+                Err(e) => Log::writeln(format!("Unable to upload texture. Reason: {:?}", e)),
+            }
+        }
+    }
+
     fn update(&mut self, dt: f32) {
-        for entry in self.map.values_mut() {
+        // Free dropped textures immediately.
+        for id in TEXTURE_QUEUE.drain() {
+            self.map.remove(&id);
+            self.arrays.remove(&id);
+        }
+        for entry in self.map.values_mut().chain(self.arrays.values_mut()) {
             entry.time_to_live -= dt;
         }
         self.map.retain(|_, v| v.time_to_live > 0.0);
+        self.arrays.retain(|_, v| v.time_to_live > 0.0);
     }
 
     fn clear(&mut self) {
         self.map.clear();
+        self.arrays.clear();
+        self.upload_queue.clear();
+        self.queued.clear();
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.map.values()
+            .chain(self.arrays.values())
+            .map(|entry| entry.value.gpu_texture.borrow().size_bytes())
+            .sum()
     }
 }
 
 impl Renderer {
-    pub(in crate) fn new(context: &mut glutin::WindowedContext<PossiblyCurrent>, frame_size: (u32, u32)) -> Result<Self, RendererError> {
-        gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
+    /// Creates renderer in current context, `loader` returns address of GL function by name.
+    pub(in crate) fn new<F>(loader: F, frame_size: (u32, u32), vsync: bool) -> Result<Self, RendererError>
+        where F: FnMut(&str) -> *const c_void {
+        gl::load_with(loader);
 
         let settings = QualitySettings::default();
         let mut state = State::new();
+        let dynamic_buffer_ring = Rc::new(RefCell::new(DynamicBufferRing::new(&mut state)));
 
         Ok(Self {
-            backbuffer: BackBuffer,
+            backbuffer: Default::default(),
+            external_context: false,
             frame_size,
             deferred_light_renderer: DeferredLightRenderer::new(&mut state, frame_size, &settings)?,
-            flat_shader: FlatShader::new()?,
+            exposure_renderer: ExposureRenderer::new()?,
             statistics: Statistics::default(),
-            sprite_renderer: SpriteRenderer::new()?,
             white_dummy: Rc::new(RefCell::new(GpuTexture::new(&mut state, GpuTextureKind::Rectangle { width: 1, height: 1 },
                                                               PixelKind::RGBA8, Some(&[255, 255, 255, 255]))?)),
             normal_dummy: Rc::new(RefCell::new(GpuTexture::new(&mut state, GpuTextureKind::Rectangle { width: 1, height: 1 },
                                                                PixelKind::RGBA8, Some(&[128, 128, 255, 255]))?)),
-            quad: SurfaceSharedData::make_unit_xy_quad(),
-            ui_renderer: UiRenderer::new(&mut state)?,
-            particle_system_renderer: ParticleSystemRenderer::new(&mut state)?,
+            array_dummy: Rc::new(RefCell::new(GpuTexture::new(&mut state, GpuTextureKind::Array { width: 1, height: 1, layers: 1 },
+                                                              PixelKind::RGBA8, Some(&[255, 255, 255, 255]))?)),
+            ui_renderer: UiRenderer::new(&mut state, dynamic_buffer_ring.clone())?,
+            transparent_renderer: TransparentRenderer::new(&mut state, dynamic_buffer_ring.clone())?,
+            lens_flare_renderer: LensFlareRenderer::new(&mut state, dynamic_buffer_ring.clone())?,
+            mirror_renderer: MirrorRenderer::new()?,
+            projector_renderer: ProjectorRenderer::new()?,
+            picking_renderer: None,
             ambient_color: Color::opaque(100, 100, 100),
             quality_settings: settings,
-            debug_renderer: DebugRenderer::new(&mut state)?,
+            debug_renderer: DebugRenderer::new(&mut state, dynamic_buffer_ring.clone())?,
+            gbuffer_shader: Rc::new(GBufferShader::new()?),
+            render_list_builder: RenderListBuilder::new(),
+            distance_culled: Default::default(),
             gbuffers: Default::default(),
+            mirror_gbuffers: Default::default(),
             backbuffer_clear_color: Color::from_rgba(0, 0, 0, 0),
             texture_cache: Default::default(),
             geometry_cache: Default::default(),
+            vsync,
+            frame_limiter: Default::default(),
+            frame_time_history: Default::default(),
+            frame_arena: Default::default(),
+            ui_scale: 1.0,
             state,
         })
     }
@@ -420,10 +921,94 @@ impl Renderer {
         self.statistics
     }
 
+    /// Returns usage of temporary buffers in last frame, `created` should be zero when
+    /// nothing changes in scenes.
+    pub fn frame_arena_statistics(&self) -> FrameArenaStatistics {
+        self.frame_arena.statistics()
+    }
+
+    /// Returns approximate amount of video memory occupied by cached textures and geometry
+    /// of surfaces, in bytes. Render targets are not included.
+    pub fn gpu_memory_usage(&self) -> (usize, usize) {
+        (self.texture_cache.memory_usage(), self.geometry_cache.memory_usage())
+    }
+
+    /// Returns true if vertical synchronization was requested on context creation. Vsync
+    /// can be switched only by re-creating the engine, see `Engine::new`.
+    pub fn is_vsync_enabled(&self) -> bool {
+        self.vsync
+    }
+
+    /// Sets maximum amount of frames per second renderer will produce, `None` removes the
+    /// limit. Useful to not burn 100% of GPU in menus or when vsync is off.
+    pub fn set_frame_rate_limit(&mut self, fps: Option<u32>) {
+        self.frame_limiter.set_target_fps(fps)
+    }
+
+    pub fn frame_rate_limit(&self) -> Option<u32> {
+        self.frame_limiter.target_fps()
+    }
+
+    /// Sets amount of bytes of large textures uploaded per frame. Large textures are uploaded
+    /// asynchronously and spread across frames, so loading of big level does not cause
+    /// hitches; objects are drawn with stub texture until their textures are uploaded.
+    pub fn set_texture_upload_budget(&mut self, bytes: usize) {
+        self.texture_cache.upload_budget = bytes.max(1);
+    }
+
+    pub fn texture_upload_budget(&self) -> usize {
+        self.texture_cache.upload_budget
+    }
+
+    /// Returns reference to frame limiter, can be used to tune frame pacing.
+    pub fn frame_limiter_mut(&mut self) -> &mut FrameLimiter {
+        &mut self.frame_limiter
+    }
+
     pub fn set_backbuffer_clear_color(&mut self, color: Color) {
         self.backbuffer_clear_color = color;
     }
 
+    /// Returns true if context was reset by driver and all GPU objects are lost. Reset is
+    /// reported only by contexts created with robustness, which is requested by `Engine::new`
+    /// but is not supported everywhere.
+    pub fn is_context_lost(&self) -> bool {
+        gl::GetGraphicsResetStatus::is_loaded() && unsafe { gl::GetGraphicsResetStatus() } != gl::NO_ERROR
+    }
+
+    /// Re-creates all GPU objects of renderer in current context after previous context was
+    /// lost, settings of renderer are kept. Objects of lost context are forgotten without
+    /// deletion; caches of textures and geometry start empty and are filled again from CPU
+    /// copies of resources when they are drawn.
+    pub(in crate) fn recreate<F>(&mut self, loader: F) -> Result<(), RendererError>
+        where F: FnMut(&str) -> *const c_void {
+        let mut renderer = Renderer::new(loader, self.frame_size, self.vsync)?;
+        renderer.set_quality_settings(&self.quality_settings)?;
+        renderer.set_picking_enabled(self.picking_renderer.is_some())?;
+        renderer.external_context = self.external_context;
+        renderer.ambient_color = self.ambient_color;
+        renderer.backbuffer_clear_color = self.backbuffer_clear_color;
+        renderer.ui_scale = self.ui_scale;
+        renderer.texture_cache.upload_budget = self.texture_cache.upload_budget;
+        // Target frame buffer of host and frame pacing are not objects of renderer.
+        std::mem::swap(&mut renderer.backbuffer, &mut self.backbuffer);
+        std::mem::swap(&mut renderer.frame_limiter, &mut self.frame_limiter);
+        std::mem::swap(&mut renderer.frame_time_history, &mut self.frame_time_history);
+        forget_lost_objects(std::mem::replace(self, renderer));
+        Ok(())
+    }
+
+    /// Sets amount of pixels per unit of user interface, user interface is laid out in
+    /// frame of `frame size / scale` units and stretched to whole frame. Engine sets it
+    /// each update, see `Engine::set_ui_scale`.
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale.max(0.1);
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
     /// Sets new frame size, should be called when received a Resize event.
     ///
     /// # Notes
@@ -436,6 +1021,49 @@ impl Renderer {
         self.frame_size.1 = new_size.1.max(1);
         // Invalidate all g-buffers.
         self.gbuffers.clear();
+        self.mirror_gbuffers.clear();
+        if let Some(picking_renderer) = self.picking_renderer.as_mut() {
+            picking_renderer.clear();
+        }
+    }
+
+    /// Enables or disables picking buffer: meshes seen by each camera are additionally
+    /// rendered into offscreen buffer of node handles, so `Engine::pick_node` can tell exactly
+    /// which mesh is visible in a pixel. It costs one more geometry pass per camera. Buffers
+    /// of cameras are filled starting from next rendered frame.
+    pub fn set_picking_enabled(&mut self, enabled: bool) -> Result<(), RendererError> {
+        if !enabled {
+            self.picking_renderer = None;
+        } else if self.picking_renderer.is_none() {
+            self.picking_renderer = Some(PickingRenderer::new(&mut self.state)?);
+        }
+        Ok(())
+    }
+
+    pub fn is_picking_enabled(&self) -> bool {
+        self.picking_renderer.is_some()
+    }
+
+    /// Returns handle of mesh visible in given pixel of view of camera in last rendered
+    /// frame. Position is in pixels relative to left upper corner of viewport of camera.
+    /// Returns `None` if picking is disabled or there is no mesh in pixel. Returned node
+    /// may be already removed from scene, check handle before use. Use `Engine::pick_node`
+    /// which takes care of both viewport and validity of handle.
+    pub fn pick_node(&mut self, camera: Handle<Node>, position: Vec2) -> Option<Handle<Node>> {
+        let picking_renderer = self.picking_renderer.as_ref()?;
+        picking_renderer.pick(&mut self.state, camera, position.x as i32, position.y as i32)
+    }
+
+    /// Sets frame buffer object which is used as back buffer, zero (default) is back buffer
+    /// of window. Needed only when engine is embedded into host which renders into its own
+    /// frame buffer object, see `Engine::from_external_context`.
+    pub fn set_target_framebuffer(&mut self, framebuffer: u32) {
+        self.backbuffer = BackBuffer::new(framebuffer);
+    }
+
+    /// Marks context as owned by host application, see `Engine::from_external_context`.
+    pub(in crate) fn set_external_context(&mut self) {
+        self.external_context = true;
     }
 
     pub fn get_frame_size(&self) -> (u32, u32) {
@@ -444,6 +1072,10 @@ impl Renderer {
 
     pub fn set_quality_settings(&mut self, settings: &QualitySettings) -> Result<(), RendererError> {
         self.quality_settings = *settings;
+        self.texture_cache.sampling = TextureSampling {
+            filter: settings.texture_filter,
+            anisotropy: settings.texture_anisotropy,
+        };
         self.deferred_light_renderer.set_quality_settings(&mut self.state, settings)
     }
 
@@ -456,23 +1088,68 @@ impl Renderer {
         self.geometry_cache.clear();
     }
 
+    /// Renders lit scene into given buffer and reads back pixels of lit frame and normals.
+    /// Used by bakers, normals are needed to tell geometry from background. Colors are not
+    /// tone mapped.
+    fn render_offscreen(&mut self, scene: &Scene, gbuffer: &mut GBuffer, camera: &Camera)
+                        -> Result<(Vec<u8>, Vec<u8>), RendererError> {
+        let snapshot = GraphSnapshot::new(&scene.graph);
+        let render_list = self.render_list_builder.begin(&snapshot, camera, Default::default())
+            .wait(&snapshot, &mut self.frame_arena);
+
+        self.state.set_log_depth(Default::default());
+
+        gbuffer.fill(GBufferRenderContext {
+            state: &mut self.state,
+            graph: &scene.graph,
+            light_probes: &scene.light_probes,
+            camera,
+            render_list: &render_list,
+            white_dummy: self.white_dummy.clone(),
+            normal_dummy: self.normal_dummy.clone(),
+            array_dummy: self.array_dummy.clone(),
+            texture_cache: &mut self.texture_cache,
+            geom_cache: &mut self.geometry_cache,
+            wetness: scene.weather.as_ref().map_or(0.0, |weather| weather.wetness()),
+        })?;
+        render_list.recycle(&mut self.frame_arena);
+
+        self.deferred_light_renderer.render(DeferredRendererContext {
+            state: &mut self.state,
+            scene,
+            camera,
+            gbuffer,
+            white_dummy: self.white_dummy.clone(),
+            ambient_color: self.ambient_color,
+            settings: &self.quality_settings,
+            textures: &mut self.texture_cache,
+            geometry_cache: &mut self.geometry_cache,
+        })?;
+
+        // Frame is in half floats, bakers need RGBA8 so colors are clamped to [0; 1] range.
+        let color = gbuffer.frame_texture().borrow().read_pixels(&mut self.state)?
+            .chunks_exact(2)
+            .map(|half| (f16_to_f32(u16::from_ne_bytes([half[0], half[1]])).max(0.0).min(1.0) * 255.0) as u8)
+            .collect();
+        let normals = gbuffer.normal_texture().borrow().read_pixels(&mut self.state)?;
+
+        Ok((color, normals))
+    }
+
     fn render_frame(&mut self, scenes: &SceneContainer,
                     drawing_context: &DrawingContext,
+                    views: &mut [&mut WindowView],
                     dt: f32,
     ) -> Result<(), RendererError> {
         scope_profile!();
 
-        // We have to invalidate resource bindings cache because some textures or programs,
-        // or other GL resources can be destroyed and then on their "names" some new resource
-        // are created, but cache still thinks that resource is correctly bound, but it is different
-        // object have same name.
-        self.state.invalidate_resource_bindings_cache();
-
         // Update caches - this will remove timed out resources.
         self.geometry_cache.update(dt);
         self.texture_cache.update(dt);
+        self.texture_cache.upload_resources(&mut self.state);
 
         self.statistics.begin_frame();
+        self.frame_arena.reset();
 
         let window_viewport = Rect::new(0, 0, self.frame_size.0 as i32, self.frame_size.1 as i32);
         self.backbuffer.clear(&mut self.state, window_viewport, Some(self.backbuffer_clear_color), Some(1.0), Some(0));
@@ -480,110 +1157,52 @@ impl Renderer {
         let frame_width = self.frame_size.0 as f32;
         let frame_height = self.frame_size.1 as f32;
 
-        for scene in scenes.iter() {
-            let graph = &scene.graph;
+        // Start culling for all cameras first, so workers build render lists of next views
+        // while previous views are submitted to GPU.
+        let mut snapshots = self.frame_arena.take::<GraphSnapshot>();
+        let mut pending_lists = self.frame_arena.take::<PendingRenderList>();
+        for (scene_handle, scene) in scenes.pair_iter() {
+            let snapshot = GraphSnapshot::new(&scene.graph);
+            // Must visit cameras in same order as loop below.
+            for (camera_handle, camera) in scene.graph.pair_iter().filter_map(|(handle, node)| {
+                if let Node::Camera(camera) = node { Some((handle, camera)) } else { None }
+            }) {
+                if camera.is_enabled() && camera.is_globally_enabled() && !is_shown_in_view(views, scene_handle, camera_handle) {
+                    let settings = self.quality_settings.with_overrides(camera.post_effects());
+                    pending_lists.push(self.render_list_builder.begin(&snapshot, camera, DistanceCulling {
+                        draw_distance: settings.draw_distance,
+                        hysteresis: settings.draw_distance_hysteresis,
+                        hidden: self.distance_culled.get(&camera_handle),
+                    }));
+                }
+            }
+            snapshots.push(snapshot);
+        }
+        let mut pending_list_iter = pending_lists.drain(..);
+        let mut backbuffer = self.backbuffer;
 
-            for (camera_handle, camera) in graph.pair_iter().filter_map(|(handle, node)| {
+        for ((scene_handle, scene), snapshot) in scenes.pair_iter().zip(snapshots.iter()) {
+            for (camera_handle, camera) in scene.graph.pair_iter().filter_map(|(handle, node)| {
                 if let Node::Camera(camera) = node { Some((handle, camera)) } else { None }
             }) {
-                if !camera.is_enabled() {
+                if !camera.is_enabled() || !camera.is_globally_enabled() || is_shown_in_view(views, scene_handle, camera_handle) {
                     continue;
                 }
 
-                let viewport = camera.viewport_pixels(Vec2::new(frame_width, frame_height));
-
-                let state = &mut self.state;
-                let gbuffer = self.gbuffers
-                    .entry(camera_handle)
-                    .and_modify(|buf| {
-                        if buf.width != viewport.w || buf.height != viewport.h {
-                            *buf = GBuffer::new(state, viewport.w as usize, viewport.h as usize).unwrap();
-                        }
-                    })
-                    .or_insert_with(|| GBuffer::new(state, viewport.w as usize, viewport.h as usize).unwrap());
-
-                self.statistics += gbuffer.fill(
-                    GBufferRenderContext {
-                        state,
-                        graph,
-                        camera,
-                        white_dummy: self.white_dummy.clone(),
-                        normal_dummy: self.normal_dummy.clone(),
-                        texture_cache: &mut self.texture_cache,
-                        geom_cache: &mut self.geometry_cache,
-                    });
-
-                self.statistics += self.deferred_light_renderer.render(
-                    DeferredRendererContext {
-                        state,
-                        scene,
-                        camera,
-                        gbuffer,
-                        white_dummy: self.white_dummy.clone(),
-                        ambient_color: self.ambient_color,
-                        settings: &self.quality_settings,
-                        textures: &mut self.texture_cache,
-                        geometry_cache: &mut self.geometry_cache,
-                    });
-
-                let depth = gbuffer.depth();
-
-                self.statistics += self.particle_system_renderer.render(
-                    ParticleSystemRenderContext {
-                        state,
-                        framebuffer: &mut gbuffer.final_frame,
-                        graph,
-                        camera,
-                        white_dummy: self.white_dummy.clone(),
-                        depth,
-                        frame_width,
-                        frame_height,
-                        viewport,
-                        texture_cache: &mut self.texture_cache,
-                    });
-
-                self.statistics += self.sprite_renderer.render(
-                    SpriteRenderContext {
-                        state,
-                        framebuffer: &mut gbuffer.final_frame,
-                        graph,
-                        camera,
-                        white_dummy: self.white_dummy.clone(),
-                        viewport,
-                        textures: &mut self.texture_cache,
-                        geom_map: &mut self.geometry_cache,
-                    });
-
-                self.statistics += self.debug_renderer.render(state, viewport, &mut gbuffer.final_frame, camera);
-
-                // Finally render everything into back buffer.
-                self.statistics.geometry += self.backbuffer.draw(
-                    self.geometry_cache.get(state, &self.quad),
-                    state,
-                    viewport,
-                    &self.flat_shader.program,
-                    DrawParameters {
-                        cull_face: CullFace::Back,
-                        culling: false,
-                        color_write: Default::default(),
-                        depth_write: true,
-                        stencil_test: false,
-                        depth_test: false,
-                        blend: false,
-                    },
-                    &[
-                        (self.flat_shader.wvp_matrix, UniformValue::Mat4({
-                            Mat4::ortho(0.0, viewport.w as f32, viewport.h as f32, 0.0, -1.0, 1.0) *
-                                Mat4::scale(Vec3::new(viewport.w as f32, viewport.h as f32, 0.0))
-                        })),
-                        (self.flat_shader.diffuse_texture, UniformValue::Sampler {
-                            index: 0,
-                            texture: gbuffer.frame_texture(),
-                        })
-                    ],
-                );
+                let pending_list = pending_list_iter.next().unwrap();
+                self.render_camera(scene, snapshot, camera_handle, camera, pending_list,
+                                   Vec2::new(frame_width, frame_height), &mut backbuffer, dt)?;
             }
         }
+        drop(pending_list_iter);
+        self.frame_arena.recycle(pending_lists);
+        self.frame_arena.recycle(snapshots);
+
+        for view in views.iter_mut() {
+            self.render_view(scenes, view, dt)?;
+        }
+
+        self.state.set_log_depth(Default::default());
 
         // Render UI on top of everything.
         self.statistics += self.ui_renderer.render(
@@ -593,6 +1212,7 @@ impl Renderer {
                 backbuffer: &mut self.backbuffer,
                 frame_width,
                 frame_height,
+                scale: self.ui_scale,
                 drawing_context,
                 white_dummy: self.white_dummy.clone(),
                 texture_cache: &mut self.texture_cache,
@@ -603,20 +1223,291 @@ impl Renderer {
     }
 
 
+    /// Renders view of given camera into given frame buffer: reflections of mirrors, lit scene,
+    /// transparent and special nodes and, finally, tone mapped frame.
+    #[allow(clippy::too_many_arguments)]
+    fn render_camera<F: FrameBufferTrait>(&mut self,
+                                          scene: &Scene,
+                                          snapshot: &GraphSnapshot,
+                                          camera_handle: Handle<Node>,
+                                          camera: &Camera,
+                                          pending_list: PendingRenderList,
+                                          frame_size: Vec2,
+                                          target: &mut F,
+                                          dt: f32,
+    ) -> Result<(), RendererError> {
+        let graph = &scene.graph;
+        let frame_width = frame_size.x;
+        let frame_height = frame_size.y;
+        let viewport = camera.viewport_pixels(frame_size);
+        let settings = self.quality_settings.with_overrides(camera.post_effects());
+
+        // Reflected cameras have same projection, so parameters are shared by mirrors.
+        self.state.set_log_depth(if settings.use_logarithmic_depth {
+            LogDepth::new(&camera.projection_matrix(), camera.projection_z_far())
+        } else {
+            Default::default()
+        });
+
+        // Render reflections for mirrors first, main view will need them. Each
+        // reflection is full render of scene from camera reflected by mirror.
+        let frustum = Frustum::from(camera.view_projection_matrix()).unwrap();
+        for (mirror_handle, mirror) in graph.pair_iter().filter_map(|(handle, node)| {
+            if let Node::Mirror(mirror) = node { Some((handle, mirror)) } else { None }
+        }) {
+            if !mirror.global_visibility()
+                || !mirror.is_facing(camera.global_position())
+                || !frustum.is_intersects_sphere(mirror.global_position(), mirror.bounding_radius()) {
+                continue;
+            }
+
+            let reflected_camera = camera.reflected(mirror.reflection_matrix());
+            let render_list = self.render_list_builder.begin(snapshot, &reflected_camera, DistanceCulling {
+                draw_distance: settings.draw_distance,
+                ..Default::default()
+            }).wait(snapshot, &mut self.frame_arena);
+
+            let state = &mut self.state;
+            let gbuffer_shader = &self.gbuffer_shader;
+            let mirror_gbuffer = self.mirror_gbuffers
+                .entry((camera_handle, mirror_handle))
+                .and_modify(|buf| {
+                    if buf.width != viewport.w || buf.height != viewport.h {
+                        *buf = GBuffer::new(state, gbuffer_shader.clone(), viewport.w as usize, viewport.h as usize).unwrap();
+                    }
+                })
+                .or_insert_with(|| GBuffer::new(state, gbuffer_shader.clone(), viewport.w as usize, viewport.h as usize).unwrap());
+
+            self.statistics += mirror_gbuffer.fill(
+                GBufferRenderContext {
+                    state,
+                    graph,
+                    light_probes: &scene.light_probes,
+                    camera: &reflected_camera,
+                    render_list: &render_list,
+                    white_dummy: self.white_dummy.clone(),
+                    normal_dummy: self.normal_dummy.clone(),
+                    array_dummy: self.array_dummy.clone(),
+                    texture_cache: &mut self.texture_cache,
+                    geom_cache: &mut self.geometry_cache,
+                    wetness: scene.weather.as_ref().map_or(0.0, |weather| weather.wetness()),
+                })?;
+            render_list.recycle(&mut self.frame_arena);
+
+            self.statistics += self.deferred_light_renderer.render(
+                DeferredRendererContext {
+                    state,
+                    scene,
+                    camera: &reflected_camera,
+                    gbuffer: mirror_gbuffer,
+                    white_dummy: self.white_dummy.clone(),
+                    ambient_color: self.ambient_color,
+                    settings: &settings,
+                    textures: &mut self.texture_cache,
+                    geometry_cache: &mut self.geometry_cache,
+                })?;
+        }
+
+        let mut render_list = pending_list.wait(snapshot, &mut self.frame_arena);
+        self.distance_culled.insert(camera_handle, render_list.take_distance_culled());
+
+        let state = &mut self.state;
+        let gbuffer_shader = &self.gbuffer_shader;
+        let gbuffer = self.gbuffers
+            .entry(camera_handle)
+            .and_modify(|buf| {
+                if buf.width != viewport.w || buf.height != viewport.h {
+                    *buf = GBuffer::new(state, gbuffer_shader.clone(), viewport.w as usize, viewport.h as usize).unwrap();
+                }
+            })
+            .or_insert_with(|| GBuffer::new(state, gbuffer_shader.clone(), viewport.w as usize, viewport.h as usize).unwrap());
+
+        self.statistics += gbuffer.fill(
+            GBufferRenderContext {
+                state,
+                graph,
+                light_probes: &scene.light_probes,
+                camera,
+                render_list: &render_list,
+                white_dummy: self.white_dummy.clone(),
+                normal_dummy: self.normal_dummy.clone(),
+                array_dummy: self.array_dummy.clone(),
+                texture_cache: &mut self.texture_cache,
+                geom_cache: &mut self.geometry_cache,
+                wetness: scene.weather.as_ref().map_or(0.0, |weather| weather.wetness()),
+            })?;
+        render_list.recycle(&mut self.frame_arena);
+
+        if let Some(picking_renderer) = self.picking_renderer.as_mut() {
+            self.statistics += picking_renderer.render(
+                PickingRenderContext {
+                    state,
+                    graph,
+                    camera,
+                    camera_handle,
+                    viewport,
+                    white_dummy: self.white_dummy.clone(),
+                    textures: &mut self.texture_cache,
+                    geom_cache: &mut self.geometry_cache,
+                })?;
+        }
+
+        self.statistics += self.deferred_light_renderer.render(
+            DeferredRendererContext {
+                state,
+                scene,
+                camera,
+                gbuffer,
+                white_dummy: self.white_dummy.clone(),
+                ambient_color: self.ambient_color,
+                settings: &settings,
+                textures: &mut self.texture_cache,
+                geometry_cache: &mut self.geometry_cache,
+            })?;
+
+        // Projections are applied to lit opaque geometry, before mirrors and transparent
+        // objects are drawn.
+        self.statistics += self.projector_renderer.render(
+            ProjectorRenderContext {
+                state,
+                gbuffer,
+                graph,
+                camera,
+                white_dummy: self.white_dummy.clone(),
+                textures: &mut self.texture_cache,
+                geometry_cache: &mut self.geometry_cache,
+            });
+
+        self.statistics += self.mirror_renderer.render(
+            MirrorRenderContext {
+                state,
+                framebuffer: &mut gbuffer.final_frame,
+                graph,
+                camera,
+                camera_handle,
+                reflections: &self.mirror_gbuffers,
+                viewport,
+                geometry_cache: &mut self.geometry_cache,
+            });
+
+        let depth = gbuffer.depth();
+
+        // Particles, sprites, trails and texts are drawn in one back-to-front queue, so
+        // mixed effects are composited in correct order.
+        self.statistics += self.transparent_renderer.render(
+            TransparentRenderContext {
+                state,
+                framebuffer: &mut gbuffer.final_frame,
+                graph,
+                camera,
+                white_dummy: self.white_dummy.clone(),
+                depth: depth.clone(),
+                frame_width,
+                frame_height,
+                viewport,
+                texture_cache: &mut self.texture_cache,
+            })?;
+
+        // Lens flares are rendered on top of everything, they are effect of camera lens.
+        self.statistics += self.lens_flare_renderer.render(
+            LensFlareRenderContext {
+                state,
+                framebuffer: &mut gbuffer.final_frame,
+                graph,
+                camera,
+                white_dummy: self.white_dummy.clone(),
+                depth,
+                viewport,
+                textures: &mut self.texture_cache,
+            })?;
+
+        self.statistics += self.debug_renderer.render(state, viewport, &mut gbuffer.final_frame, camera);
+
+        // Finally map HDR frame to screen colors and put it into back buffer.
+        self.statistics.geometry += self.exposure_renderer.render(ExposureRenderContext {
+            state,
+            backbuffer: target,
+            camera,
+            camera_handle,
+            frame_texture: gbuffer.frame_texture(),
+            viewport,
+            dt,
+            geometry_cache: &mut self.geometry_cache,
+            white_dummy: self.white_dummy.clone(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Renders camera of view into offscreen target of view, target is (re)created to match
+    /// size of view. Views with invalid scene or camera are skipped.
+    fn render_view(&mut self, scenes: &SceneContainer, view: &mut WindowView, dt: f32) -> Result<(), RendererError> {
+        if !scenes.is_valid_handle(view.scene) {
+            return Ok(());
+        }
+        let scene = &scenes[view.scene];
+        if !scene.graph.is_valid_handle(view.camera) {
+            return Ok(());
+        }
+        let camera = match &scene.graph[view.camera] {
+            Node::Camera(camera) => camera,
+            _ => return Ok(()),
+        };
+
+        let (width, height) = (view.size.0.max(1), view.size.1.max(1));
+        if view.target.as_ref().map_or(true, |target| target.size() != (width, height)) {
+            view.target = Some(ViewTarget::new(&mut self.state, width, height)?);
+        }
+        let target = view.target.as_mut().unwrap();
+        target.clear(&mut self.state, self.backbuffer_clear_color);
+
+        let snapshot = GraphSnapshot::new(&scene.graph);
+        let settings = self.quality_settings.with_overrides(camera.post_effects());
+        let pending_list = self.render_list_builder.begin(&snapshot, camera, DistanceCulling {
+            draw_distance: settings.draw_distance,
+            hysteresis: settings.draw_distance_hysteresis,
+            hidden: self.distance_culled.get(&view.camera),
+        });
+        self.render_camera(scene, &snapshot, view.camera, camera, pending_list,
+                           Vec2::new(width as f32, height as f32), target.framebuffer_mut(), dt)
+    }
+
+
     pub(in crate) fn render_and_swap_buffers(&mut self,
                                              scenes: &SceneContainer,
                                              drawing_context: &DrawingContext,
-                                             context: &glutin::WindowedContext<PossiblyCurrent>,
+                                             context: Option<&glutin::WindowedContext<PossiblyCurrent>>,
+                                             views: &mut [&mut WindowView],
                                              dt: f32,
     ) -> Result<(), RendererError> {
         scope_profile!();
 
-        self.render_frame(scenes, drawing_context, dt)?;
+        if self.is_context_lost() {
+            return Err(RendererError::ContextLost);
+        }
+
+        if self.external_context {
+            self.state.restore();
+        }
 
+        self.render_frame(scenes, drawing_context, views, dt)?;
+        if !views.is_empty() {
+            // Targets of views are read by contexts of other windows, commands must be
+            // submitted before other context uses results.
+            self.state.flush();
+        }
+
+        self.statistics.state_changes = self.state.take_statistics();
         self.statistics.end_frame();
-        context.swap_buffers()?;
+        // Host application swaps buffers of external context itself.
+        if let Some(context) = context {
+            context.swap_buffers()?;
+        }
         check_gl_error!();
+        self.frame_limiter.wait();
         self.statistics.finalize();
+        self.frame_time_history.push_frame();
+        self.statistics.frame_time_percentiles = self.frame_time_history.percentiles();
         Ok(())
     }
 }
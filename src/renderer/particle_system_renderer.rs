@@ -1,8 +1,9 @@
 use crate::{
     scene::{
-        node::Node,
-        particle_system,
-        graph::Graph,
+        particle_system::{
+            self,
+            ParticleSystem,
+        },
         camera::Camera,
     },
     core::{
@@ -24,7 +25,7 @@ use crate::{
             gl,
             geometry_buffer::{
                 GeometryBuffer,
-                GeometryBufferKind,
+                DynamicBufferRing,
                 AttributeDefinition,
                 AttributeKind,
                 ElementKind,
@@ -87,7 +88,7 @@ pub struct ParticleSystemRenderer {
 pub struct ParticleSystemRenderContext<'a, 'b, 'c> {
     pub state: &'a mut State,
     pub framebuffer: &'b mut FrameBuffer,
-    pub graph: &'c Graph,
+    pub particle_system: &'c ParticleSystem,
     pub camera: &'c Camera,
     pub white_dummy: Rc<RefCell<GpuTexture>>,
     pub depth: Rc<RefCell<GpuTexture>>,
@@ -98,8 +99,8 @@ pub struct ParticleSystemRenderContext<'a, 'b, 'c> {
 }
 
 impl ParticleSystemRenderer {
-    pub fn new(state: &mut State) -> Result<Self, RendererError> {
-        let geometry_buffer = GeometryBuffer::new(GeometryBufferKind::DynamicDraw, ElementKind::Triangle);
+    pub fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry_buffer = GeometryBuffer::new_in_ring(state, ring, ElementKind::Triangle);
 
         geometry_buffer.bind(state)
             .describe_attributes(vec![
@@ -118,15 +119,14 @@ impl ParticleSystemRenderer {
         })
     }
 
+    /// Draws one particle system, particles are sorted back-to-front within it.
     #[must_use]
     pub fn render(&mut self, args: ParticleSystemRenderContext) -> RenderPassStatistics {
         scope_profile!();
 
-        let mut statistics = RenderPassStatistics::default();
-
         let ParticleSystemRenderContext {
-            state, framebuffer, graph
-            , camera, white_dummy, depth,
+            state, framebuffer, particle_system,
+            camera, white_dummy, depth,
             frame_width, frame_height, viewport, texture_cache
         } = args;
 
@@ -137,64 +137,54 @@ impl ParticleSystemRenderer {
         let camera_up = inv_view.up();
         let camera_side = inv_view.side();
 
-        for node in graph.linear_iter() {
-            let particle_system = if let Node::ParticleSystem(particle_system) = node {
-                particle_system
-            } else {
-                continue;
-            };
-
-            particle_system.generate_draw_data(&mut self.sorted_particles,
-                                               &mut self.draw_data,
-                                               &camera.global_position());
-
-            self.geometry_buffer
-                .bind(state)
-                .set_triangles(self.draw_data.get_triangles())
-                .set_vertices(self.draw_data.get_vertices());
-
-            let uniforms = [
-                (self.shader.depth_buffer_texture, UniformValue::Sampler { index: 0, texture: depth.clone() }),
-                (self.shader.diffuse_texture, UniformValue::Sampler {
-                    index: 1,
-                    texture: if let Some(texture) = particle_system.texture() {
-                        if let Some(texture) = texture_cache.get(state, texture) {
-                            texture
-                        } else {
-                            white_dummy.clone()
-                        }
+        particle_system.generate_draw_data(&mut self.sorted_particles,
+                                           &mut self.draw_data,
+                                           &camera.global_position());
+
+        self.geometry_buffer
+            .bind(state)
+            .set_triangles(self.draw_data.get_triangles())
+            .set_vertices(self.draw_data.get_vertices());
+
+        let uniforms = [
+            (self.shader.depth_buffer_texture, UniformValue::Sampler { index: 0, texture: depth }),
+            (self.shader.diffuse_texture, UniformValue::Sampler {
+                index: 1,
+                texture: if let Some(texture) = particle_system.texture() {
+                    if let Some(texture) = texture_cache.get(state, texture) {
+                        texture
                     } else {
                         white_dummy.clone()
-                    },
-                }),
-                (self.shader.camera_side_vector, UniformValue::Vec3(camera_side)),
-                (self.shader.camera_up_vector, UniformValue::Vec3(camera_up)),
-                (self.shader.view_projection_matrix, UniformValue::Mat4(camera.view_projection_matrix())),
-                (self.shader.world_matrix, UniformValue::Mat4(node.global_transform())),
-                (self.shader.inv_screen_size, UniformValue::Vec2(Vec2::new(1.0 / frame_width, 1.0 / frame_height))),
-                (self.shader.proj_params, UniformValue::Vec2(Vec2::new(camera.z_far(), camera.z_near())))
-            ];
-
-            let draw_params = DrawParameters {
-                cull_face: CullFace::Front,
-                culling: false,
-                color_write: Default::default(),
-                depth_write: false,
-                stencil_test: false,
-                depth_test: true,
-                blend: true,
-            };
-
-            statistics += framebuffer.draw(
-                &self.geometry_buffer,
-                state,
-                viewport,
-                &self.shader.program,
-                draw_params,
-                &uniforms,
-            );
-        }
-
-        statistics
+                    }
+                } else {
+                    white_dummy.clone()
+                },
+            }),
+            (self.shader.camera_side_vector, UniformValue::Vec3(camera_side)),
+            (self.shader.camera_up_vector, UniformValue::Vec3(camera_up)),
+            (self.shader.view_projection_matrix, UniformValue::Mat4(camera.view_projection_matrix())),
+            (self.shader.world_matrix, UniformValue::Mat4(particle_system.global_transform())),
+            (self.shader.inv_screen_size, UniformValue::Vec2(Vec2::new(1.0 / frame_width, 1.0 / frame_height))),
+            (self.shader.proj_params, UniformValue::Vec2(Vec2::new(camera.projection_z_far(), camera.projection_z_near())))
+        ];
+
+        let draw_params = DrawParameters {
+            cull_face: CullFace::Front,
+            culling: false,
+            color_write: Default::default(),
+            depth_write: false,
+            stencil_test: false,
+            depth_test: true,
+            blend: true,
+        };
+
+        framebuffer.draw(
+            &self.geometry_buffer,
+            state,
+            viewport,
+            &self.shader.program,
+            draw_params,
+            &uniforms,
+        )
     }
 }
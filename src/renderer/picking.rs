@@ -0,0 +1,281 @@
+//! Picking buffer - exact per-pixel picking of meshes.
+//!
+//! Ray-versus-mesh tests are either coarse (bounding volumes) or slow (every triangle of
+//! every mesh), and both ignore alpha tested holes of foliage and fences. When picking is
+//! enabled renderer additionally draws meshes seen by each camera into offscreen integer
+//! buffer, each pixel stores handle of node which is visible in it. Query reads back one
+//! pixel of buffer of last rendered frame, see `Renderer::pick_node`.
+//!
+//! Reading pixel waits until GPU finishes frame, so query only on demand (click, hover
+//! with cursor moved), not for many pixels per frame. Only meshes are written to buffer,
+//! sprites, particles and other special nodes are not pickable.
+
+use std::{
+    rc::Rc,
+    cell::RefCell,
+    collections::HashMap,
+};
+use crate::{
+    core::{
+        pool::Handle,
+        scope_profile,
+        math::{
+            mat4::Mat4,
+            frustum::Frustum,
+            Rect,
+        },
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+        camera::Camera,
+    },
+    resource::material::BlendMode,
+    renderer::{
+        framework::{
+            framebuffer::{
+                DrawParameters,
+                FrameBufferTrait,
+                FrameBuffer,
+                Attachment,
+                AttachmentKind,
+            },
+            gpu_program::{
+                UniformValue,
+                GpuProgram,
+                UniformLocation,
+            },
+            gpu_texture::{
+                GpuTextureKind,
+                PixelKind,
+                MagnificationFilter,
+                MininificationFilter,
+                GpuTexture,
+            },
+            state::State,
+        },
+        TextureCache,
+        GeometryCache,
+        RenderPassStatistics,
+        error::RendererError,
+        matrix_storage::MatrixStorage,
+    },
+};
+
+struct PickingShader {
+    program: GpuProgram,
+    bone_matrices: UniformLocation,
+    world_view_projection_matrix: UniformLocation,
+    use_skeletal_animation: UniformLocation,
+    diffuse_texture: UniformLocation,
+    alpha_cutoff: UniformLocation,
+    node_index: UniformLocation,
+    node_generation: UniformLocation,
+}
+
+impl PickingShader {
+    pub fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/picking_fs.glsl");
+        // Only position and texture coordinates are needed, the same as for shadows.
+        let vertex_source = include_str!("shaders/spot_shadow_map_vs.glsl");
+        let program = GpuProgram::from_source("PickingShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            bone_matrices: program.uniform_location("boneMatrices")?,
+            world_view_projection_matrix: program.uniform_location("worldViewProjection")?,
+            use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
+            diffuse_texture: program.uniform_location("diffuseTexture")?,
+            alpha_cutoff: program.uniform_location("alphaCutoff")?,
+            node_index: program.uniform_location("nodeIndex")?,
+            node_generation: program.uniform_location("nodeGeneration")?,
+            program,
+        })
+    }
+}
+
+/// Picking buffer of one camera.
+struct PickingBuffer {
+    framebuffer: FrameBuffer,
+    width: i32,
+    height: i32,
+}
+
+impl PickingBuffer {
+    fn new(state: &mut State, width: usize, height: usize) -> Result<Self, RendererError> {
+        let kind = GpuTextureKind::Rectangle { width, height };
+
+        let depth = GpuTexture::new(state, kind, PixelKind::D32, None)?;
+
+        let mut ids = GpuTexture::new(state, kind, PixelKind::RG32UI, None)?;
+        ids.bind_mut(state, 0)
+            .set_magnification_filter(MagnificationFilter::Nearest)
+            .set_minification_filter(MininificationFilter::Nearest);
+
+        let framebuffer = FrameBuffer::new(
+            state,
+            Some(Attachment {
+                kind: AttachmentKind::Depth,
+                texture: Rc::new(RefCell::new(depth)),
+            }),
+            vec![
+                Attachment {
+                    kind: AttachmentKind::Color,
+                    texture: Rc::new(RefCell::new(ids)),
+                }
+            ])?;
+
+        Ok(Self {
+            framebuffer,
+            width: width as i32,
+            height: height as i32,
+        })
+    }
+}
+
+pub struct PickingRenderer {
+    shader: PickingShader,
+    buffers: HashMap<Handle<Node>, PickingBuffer>,
+    bone_matrices: Vec<Mat4>,
+    bone_matrix_storage: MatrixStorage,
+}
+
+pub struct PickingRenderContext<'a, 'c> {
+    pub state: &'a mut State,
+    pub graph: &'c Graph,
+    pub camera: &'c Camera,
+    pub camera_handle: Handle<Node>,
+    pub viewport: Rect<i32>,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub textures: &'a mut TextureCache,
+    pub geom_cache: &'a mut GeometryCache,
+}
+
+impl PickingRenderer {
+    pub fn new(state: &mut State) -> Result<Self, RendererError> {
+        Ok(Self {
+            shader: PickingShader::new()?,
+            buffers: Default::default(),
+            bone_matrices: Vec::new(),
+            bone_matrix_storage: MatrixStorage::new(state)?,
+        })
+    }
+
+    pub fn render(&mut self, args: PickingRenderContext) -> Result<RenderPassStatistics, RendererError> {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let PickingRenderContext {
+            state, graph, camera, camera_handle, viewport,
+            white_dummy, textures, geom_cache
+        } = args;
+
+        let buffer = match self.buffers.get_mut(&camera_handle) {
+            Some(buffer) if buffer.width == viewport.w && buffer.height == viewport.h => buffer,
+            _ => {
+                let buffer = PickingBuffer::new(state, viewport.w as usize, viewport.h as usize)?;
+                self.buffers.insert(camera_handle, buffer);
+                self.buffers.get_mut(&camera_handle).unwrap()
+            }
+        };
+
+        let viewport = Rect::new(0, 0, buffer.width, buffer.height);
+        buffer.framebuffer.clear(state, viewport, None, Some(1.0), None);
+        // Zero index means that there is no node in pixel.
+        buffer.framebuffer.clear_uint_attachment(state, viewport, 0, [0; 4]);
+
+        let view_projection = camera.view_projection_matrix();
+        let frustum = Frustum::from(view_projection).unwrap();
+
+        for (handle, node) in graph.pair_iter() {
+            let mesh = match node {
+                Node::Mesh(mesh) if node.global_visibility() => mesh,
+                _ => continue,
+            };
+
+            if !mesh.is_intersect_frustum(graph, &frustum) {
+                continue;
+            }
+
+            for surface in mesh.surfaces().iter() {
+                let is_skinned = !surface.bones.is_empty();
+
+                let world = if is_skinned {
+                    Mat4::IDENTITY
+                } else {
+                    node.global_transform()
+                };
+
+                // Holes of alpha tested surfaces are not pickable, like they are not visible.
+                let alpha_texture = surface.get_diffuse_texture()
+                    .filter(|_| surface.blend_mode() == BlendMode::AlphaTest);
+                let diffuse_texture = alpha_texture
+                    .and_then(|texture| textures.get(state, texture))
+                    .unwrap_or_else(|| white_dummy.clone());
+
+                if is_skinned {
+                    surface.fill_bone_matrices(graph, &mut self.bone_matrices);
+                    self.bone_matrix_storage.upload(state, &self.bone_matrices)?;
+                }
+
+                let (cull_face, culling) = surface.cull_mode().cull_face();
+                statistics += buffer.framebuffer.draw(
+                    geom_cache.get(state, &surface.get_data().lock().unwrap()),
+                    state,
+                    viewport,
+                    &self.shader.program,
+                    DrawParameters {
+                        cull_face,
+                        culling,
+                        color_write: Default::default(),
+                        depth_write: true,
+                        stencil_test: false,
+                        depth_test: true,
+                        blend: false,
+                    },
+                    &[
+                        (self.shader.world_view_projection_matrix, UniformValue::Mat4(view_projection * world)),
+                        (self.shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
+                        (self.shader.bone_matrices, UniformValue::Sampler {
+                            index: 1,
+                            texture: self.bone_matrix_storage.texture(),
+                        }),
+                        (self.shader.diffuse_texture, UniformValue::Sampler {
+                            index: 0,
+                            texture: diffuse_texture,
+                        }),
+                        (self.shader.alpha_cutoff, UniformValue::Float(surface.alpha_cutoff())),
+                        (self.shader.node_index, UniformValue::Integer(handle.index() as i32)),
+                        (self.shader.node_generation, UniformValue::Integer(handle.generation() as i32)),
+                    ],
+                );
+            }
+        }
+
+        Ok(statistics)
+    }
+
+    /// Returns handle of node visible in given pixel of view of camera in last rendered
+    /// frame, `None` if there is no node or no picking buffer of camera. Position is in
+    /// pixels relative to left upper corner of viewport of camera.
+    pub fn pick(&self, state: &mut State, camera: Handle<Node>, x: i32, y: i32) -> Option<Handle<Node>> {
+        let buffer = self.buffers.get(&camera)?;
+        if x < 0 || y < 0 || x >= buffer.width || y >= buffer.height {
+            return None;
+        }
+
+        // OpenGL has origin at left bottom corner.
+        let pixel = buffer.framebuffer.read_pixel(state, 0, x, buffer.height - 1 - y);
+        let index = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        let generation = u32::from_ne_bytes([pixel[4], pixel[5], pixel[6], pixel[7]]);
+        if index == 0 {
+            None
+        } else {
+            Some(Handle::new(index - 1, generation))
+        }
+    }
+
+    /// Removes buffers of cameras, they are re-created on next frame.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+}
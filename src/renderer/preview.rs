@@ -0,0 +1,190 @@
+//! Preview images of models and scenes for asset browsers and save slot thumbnails.
+//!
+//! Camera is placed automatically: it looks at center of bounding box of meshes of given
+//! hierarchy from given direction, at distance where whole box fits into view. Previews of
+//! scenes are lit by lights of scene, previews of models are rendered in separate scene
+//! lit by a light placed at camera. Background is transparent, so previews can be shown on
+//! any backdrop. Rendering reads back frame from GPU, do it once and cache result, for
+//! example save it next to save file with `Texture::save`.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::Engine,
+//!     renderer::preview::PreviewSettings,
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn make_thumbnail(engine: &mut Engine<(), StubNode>) {
+//!     let model = engine.resource_manager.lock().unwrap().request_model("data/barrel.fbx").unwrap();
+//!     let preview = engine.renderer.render_model_preview(&model.lock().unwrap(), &PreviewSettings::default()).unwrap();
+//!     preview.save("barrel_preview.png").unwrap();
+//! }
+//! ```
+
+use std::collections::HashSet;
+use crate::{
+    core::{
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
+            aabb::AxisAlignedBoundingBox,
+        },
+        pool::Handle,
+    },
+    scene::{
+        Scene,
+        node::Node,
+        camera::Camera,
+        base::BaseBuilder,
+        transform::TransformBuilder,
+        light::{
+            LightBuilder,
+            LightKind,
+            PointLight,
+        },
+    },
+    resource::{
+        model::Model,
+        texture::{
+            Texture,
+            TextureKind,
+        },
+    },
+    renderer::{
+        Renderer,
+        error::RendererError,
+        gbuffer::GBuffer,
+    },
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PreviewSettings {
+    /// Size of square image in pixels.
+    pub size: u32,
+    /// Rotation of camera around vertical axis in radians, zero means that camera looks
+    /// along -Z axis.
+    pub yaw: f32,
+    /// Elevation of camera in radians, positive values look from above.
+    pub pitch: f32,
+    /// Vertical field of view of camera in radians.
+    pub fov: f32,
+    /// Empty space around hierarchy, fraction of its size.
+    pub margin: f32,
+}
+
+impl Default for PreviewSettings {
+    fn default() -> Self {
+        Self {
+            size: 128,
+            yaw: 30.0f32.to_radians(),
+            pitch: 25.0f32.to_radians(),
+            fov: 30.0f32.to_radians(),
+            margin: 0.1,
+        }
+    }
+}
+
+/// Returns bounding box of meshes of hierarchy, `None` if there are no meshes.
+fn hierarchy_bounds(scene: &Scene, root: Handle<Node>) -> Option<AxisAlignedBoundingBox> {
+    let mut bounds = AxisAlignedBoundingBox::default();
+    let mut has_meshes = false;
+    for node in scene.graph.traverse_iter(root) {
+        if let Node::Mesh(mesh) = node {
+            let mesh_bounds = mesh.world_bounding_box();
+            bounds.add_point(mesh_bounds.min);
+            bounds.add_point(mesh_bounds.max);
+            has_meshes = true;
+        }
+    }
+    if has_meshes {
+        Some(bounds)
+    } else {
+        None
+    }
+}
+
+/// Returns position of camera which sees sphere with given center and radius entirely.
+fn camera_position(center: Vec3, radius: f32, settings: &PreviewSettings) -> Vec3 {
+    let direction = Vec3::new(
+        settings.yaw.sin() * settings.pitch.cos(),
+        settings.pitch.sin(),
+        settings.yaw.cos() * settings.pitch.cos(),
+    );
+    let distance = radius * (1.0 + settings.margin.max(0.0)) / (settings.fov * 0.5).sin();
+    center + direction.scale(distance)
+}
+
+impl Renderer {
+    /// Renders hierarchy starting from `root` into RGBA8 preview image, other nodes of scene
+    /// are not rendered. Rows of image go from top to bottom. Returns
+    /// `RendererError::NothingToBake` if hierarchy has no meshes.
+    pub fn render_preview(&mut self, scene: &mut Scene, root: Handle<Node>, settings: &PreviewSettings)
+                          -> Result<Texture, RendererError> {
+        let bounds = hierarchy_bounds(scene, root).ok_or(RendererError::NothingToBake)?;
+        let center = (bounds.min + bounds.max).scale(0.5);
+        let radius = ((bounds.max - bounds.min).len() * 0.5).max(0.001);
+        let eye = camera_position(center, radius, settings);
+        let distance = (eye - center).len();
+        let camera = Camera::looking_at(eye, center, Vec3::UP, settings.fov,
+                                        (distance - radius).max(0.01), distance + radius);
+
+        // Hide everything except hierarchy, renderers check global visibility only.
+        let subtree = scene.graph.traverse_handle_iter(root).collect::<HashSet<_>>();
+        let mut saved_visibility = Vec::new();
+        for (handle, node) in scene.graph.pair_iter_mut() {
+            saved_visibility.push((handle, node.global_visibility));
+            node.global_visibility = (subtree.contains(&handle) || matches!(node, Node::Light(_))) && node.visibility();
+        }
+
+        let size = settings.size.max(1) as usize;
+        let frame = GBuffer::new(&mut self.state, self.gbuffer_shader.clone(), size, size)
+            .and_then(|mut gbuffer| self.render_offscreen(scene, &mut gbuffer, &camera));
+
+        for (handle, visibility) in saved_visibility {
+            scene.graph[handle].global_visibility = visibility;
+        }
+
+        let (color, normals) = frame?;
+
+        let mut pixels = vec![0u8; size * size * 4];
+        // Frame rows go bottom to top, image rows go top to bottom.
+        for y in 0..size {
+            for x in 0..size {
+                let src = ((size - 1 - y) * size + x) * 4;
+                let dst = (y * size + x) * 4;
+                pixels[dst..(dst + 3)].copy_from_slice(&color[src..(src + 3)]);
+                // Normals are packed into [0; 1] range, so only background has zero normal.
+                let covered = normals[src..(src + 3)].iter().any(|&n| n != 0);
+                pixels[dst + 3] = if covered { 255 } else { 0 };
+            }
+        }
+
+        Texture::from_bytes(size as u32, size as u32, TextureKind::RGBA8, pixels)
+            .map_err(|_| RendererError::InvalidTextureData)
+    }
+
+    /// Renders preview of model resource, see `render_preview`. Model is instantiated into
+    /// temporary scene with a light at camera.
+    pub fn render_model_preview(&mut self, model: &Model, settings: &PreviewSettings) -> Result<Texture, RendererError> {
+        let mut scene = Scene::new();
+        let root = model.instantiate_geometry(&mut scene);
+        let frame_size = Vec2::new(settings.size as f32, settings.size as f32);
+        scene.graph.update_nodes(frame_size, 0.0);
+
+        let bounds = hierarchy_bounds(&scene, root).ok_or(RendererError::NothingToBake)?;
+        let center = (bounds.min + bounds.max).scale(0.5);
+        let radius = ((bounds.max - bounds.min).len() * 0.5).max(0.001);
+        let eye = camera_position(center, radius, settings);
+        scene.graph.add_node(Node::Light(LightBuilder::new(
+            LightKind::Point(PointLight::new((eye - center).len() + radius * 2.0)),
+            BaseBuilder::new().with_local_transform(TransformBuilder::new()
+                .with_local_position(eye)
+                .build()))
+            .cast_shadows(false)
+            .with_scatter_enabled(false)
+            .build()));
+        scene.graph.update_nodes(frame_size, 0.0);
+
+        self.render_preview(&mut scene, root, settings)
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
+use crate::{
+    scene::{
+        node::Node,
+        graph::Graph,
+        camera::Camera,
+        projector::ProjectorMode,
+    },
+    core::{
+        scope_profile,
+        math::{
+            Rect,
+            vec3::Vec3,
+            mat4::Mat4,
+            frustum::Frustum,
+        },
+    },
+    renderer::{
+        surface::SurfaceSharedData,
+        gbuffer::GBuffer,
+        GeometryCache,
+        TextureCache,
+        error::RendererError,
+        framework::{
+            gpu_program::{
+                UniformValue,
+                GpuProgram,
+                UniformLocation,
+            },
+            gpu_texture::GpuTexture,
+            framebuffer::{
+                DrawParameters,
+                CullFace,
+                FrameBufferTrait,
+            },
+            state::State,
+            gl,
+        },
+        RenderPassStatistics,
+    },
+};
+
+struct ProjectorShader {
+    program: GpuProgram,
+    wvp_matrix: UniformLocation,
+    depth_sampler: UniformLocation,
+    normal_sampler: UniformLocation,
+    projected_texture: UniformLocation,
+    inv_view_proj_matrix: UniformLocation,
+    projector_view_proj_matrix: UniformLocation,
+    projector_position: UniformLocation,
+    projector_direction: UniformLocation,
+    perspective: UniformLocation,
+    color: UniformLocation,
+    intensity: UniformLocation,
+    uv_offset: UniformLocation,
+    mode: UniformLocation,
+}
+
+impl ProjectorShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/projector_fs.glsl");
+        let vertex_source = include_str!("shaders/projector_vs.glsl");
+        let program = GpuProgram::from_source("ProjectorShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            wvp_matrix: program.uniform_location("worldViewProjection")?,
+            depth_sampler: program.uniform_location("depthTexture")?,
+            normal_sampler: program.uniform_location("normalTexture")?,
+            projected_texture: program.uniform_location("projectedTexture")?,
+            inv_view_proj_matrix: program.uniform_location("invViewProj")?,
+            projector_view_proj_matrix: program.uniform_location("projectorViewProj")?,
+            projector_position: program.uniform_location("projectorPosition")?,
+            projector_direction: program.uniform_location("projectorDirection")?,
+            perspective: program.uniform_location("perspective")?,
+            color: program.uniform_location("projectorColor")?,
+            intensity: program.uniform_location("intensity")?,
+            uv_offset: program.uniform_location("uvOffset")?,
+            mode: program.uniform_location("mode")?,
+            program,
+        })
+    }
+}
+
+/// Projects textures of projectors onto lit frame.
+pub struct ProjectorRenderer {
+    shader: ProjectorShader,
+    quad: SurfaceSharedData,
+}
+
+pub struct ProjectorRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub gbuffer: &'b mut GBuffer,
+    pub graph: &'c Graph,
+    pub camera: &'c Camera,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub textures: &'a mut TextureCache,
+    pub geometry_cache: &'a mut GeometryCache,
+}
+
+impl ProjectorRenderer {
+    pub fn new() -> Result<Self, RendererError> {
+        Ok(Self {
+            shader: ProjectorShader::new()?,
+            quad: SurfaceSharedData::make_unit_xy_quad(),
+        })
+    }
+
+    pub fn render(&mut self, args: ProjectorRenderContext) -> RenderPassStatistics {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let ProjectorRenderContext {
+            state, gbuffer, graph, camera,
+            white_dummy, textures, geometry_cache
+        } = args;
+
+        let viewport = Rect::new(0, 0, gbuffer.width, gbuffer.height);
+        let frustum = Frustum::from(camera.view_projection_matrix()).unwrap();
+
+        let frame_matrix =
+            Mat4::ortho(0.0, viewport.w as f32, viewport.h as f32, 0.0, -1.0, 1.0) *
+                Mat4::scale(Vec3::new(viewport.w as f32, viewport.h as f32, 0.0));
+
+        let inv_view_projection = camera.view_projection_matrix().inverse().unwrap_or_default();
+
+        for projector in graph.linear_iter().filter_map(|node| {
+            if let Node::Projector(projector) = node { Some(projector) } else { None }
+        }) {
+            let (center, radius) = projector.bounding_sphere();
+            if !projector.global_visibility() || !frustum.is_intersects_sphere(center, radius) {
+                continue;
+            }
+
+            // Projector without texture projects its color. Texture which is not uploaded
+            // yet is skipped, otherwise it would flash with plain color.
+            let texture = match projector.texture() {
+                Some(texture) => match textures.get(state, texture) {
+                    Some(texture) => texture,
+                    None => continue,
+                },
+                None => white_dummy.clone(),
+            };
+
+            match projector.mode() {
+                ProjectorMode::Additive => state.set_blend_func(gl::ONE, gl::ONE),
+                ProjectorMode::Multiply => state.set_blend_func(gl::DST_COLOR, gl::ZERO),
+                ProjectorMode::AlphaBlend => state.set_blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            }
+
+            let mode = match projector.mode() {
+                ProjectorMode::Additive => 0,
+                ProjectorMode::Multiply => 1,
+                ProjectorMode::AlphaBlend => 2,
+            };
+
+            statistics += gbuffer.final_frame.draw(
+                geometry_cache.get(state, &self.quad),
+                state,
+                viewport,
+                &self.shader.program,
+                DrawParameters {
+                    cull_face: CullFace::Back,
+                    culling: false,
+                    color_write: Default::default(),
+                    depth_write: false,
+                    stencil_test: false,
+                    depth_test: false,
+                    blend: true,
+                },
+                &[
+                    (self.shader.wvp_matrix, UniformValue::Mat4(frame_matrix)),
+                    (self.shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
+                    (self.shader.normal_sampler, UniformValue::Sampler { index: 1, texture: gbuffer.normal_texture() }),
+                    (self.shader.projected_texture, UniformValue::Sampler { index: 2, texture }),
+                    (self.shader.inv_view_proj_matrix, UniformValue::Mat4(inv_view_projection)),
+                    (self.shader.projector_view_proj_matrix, UniformValue::Mat4(projector.view_projection_matrix())),
+                    (self.shader.projector_position, UniformValue::Vec3(projector.global_position())),
+                    (self.shader.projector_direction, UniformValue::Vec3(projector.look_vector().normalized().unwrap_or(Vec3::new(0.0, 0.0, 1.0)))),
+                    (self.shader.perspective, UniformValue::Bool(projector.is_perspective())),
+                    (self.shader.color, UniformValue::Color(projector.color())),
+                    (self.shader.intensity, UniformValue::Float(projector.intensity())),
+                    (self.shader.uv_offset, UniformValue::Vec2(projector.scroll_offset())),
+                    (self.shader.mode, UniformValue::Integer(mode)),
+                ],
+            );
+        }
+
+        statistics
+    }
+}
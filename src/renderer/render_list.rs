@@ -0,0 +1,451 @@
+//! Render lists are lists of surfaces visible from a camera, sorted to minimize state
+//! changes. Building a list is split in two phases: snapshot of graph is made on main
+//! thread (it is cheap - just copying of transforms and bounds), then culling and sorting
+//! of snapshot is done on worker threads. Lists for all cameras are started at beginning
+//! of frame, so culling for one camera runs while commands for previous camera are
+//! submitted to GPU on main thread.
+//!
+//! Graph is not thread-safe, so workers never see nodes, they operate on indices in the
+//! snapshot which are mapped back to handles on main thread.
+
+use std::{
+    collections::{
+        hash_map::DefaultHasher,
+        HashSet,
+    },
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+use crate::{
+    core::{
+        pool::Handle,
+        math::{
+            aabb::AxisAlignedBoundingBox,
+            frustum::Frustum,
+            mat4::Mat4,
+            vec3::Vec3,
+        },
+    },
+    scene::{
+        graph::Graph,
+        node::Node,
+        camera::Camera,
+    },
+    renderer::frame_arena::FrameArena,
+};
+
+/// Amount of meshes culled by one job, small enough to balance load between workers.
+const MESHES_PER_JOB: usize = 256;
+
+/// Sort key of a draw, commands are sorted by it in ascending order. Layout from most to
+/// least significant bits:
+///
+/// - 1 bit - skinned flag, skinned surfaces need different shader state.
+/// - 23 bits - hash of textures.
+/// - 16 bits - hash of geometry.
+/// - 24 bits - distance to camera, so surfaces with same material are drawn front to
+/// back and hidden pixels are rejected by depth test early.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
+struct SortKey(u64);
+
+impl SortKey {
+    const DEPTH_BITS: u32 = 24;
+    const GEOMETRY_BITS: u32 = 16;
+    const TEXTURE_BITS: u32 = 23;
+
+    fn new(skinned: bool, texture_hash: u64, geometry_hash: u64, normalized_depth: f32) -> Self {
+        let depth_max = (1u64 << Self::DEPTH_BITS) - 1;
+        let depth = (normalized_depth.max(0.0).min(1.0) * depth_max as f32) as u64;
+        let geometry = geometry_hash & ((1 << Self::GEOMETRY_BITS) - 1);
+        let texture = texture_hash & ((1 << Self::TEXTURE_BITS) - 1);
+        let material = ((((skinned as u64) << Self::TEXTURE_BITS) | texture) << Self::GEOMETRY_BITS) | geometry;
+        SortKey((material << Self::DEPTH_BITS) | depth)
+    }
+}
+
+fn hash_of<T: Hash>(value: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Copy of mesh data required for culling, made on main thread.
+struct MeshSnapshot {
+    world: Mat4,
+    bounding_box: AxisAlignedBoundingBox,
+    /// Bounding sphere in world space, used for sorting and distance culling.
+    center: Vec3,
+    radius: f32,
+    max_render_distance: Option<f32>,
+    /// Positions of bones of skinned surfaces, skinned mesh is visible if any of its bones
+    /// is visible, because its bounding box does not follow bones.
+    bone_positions: Vec<Vec3>,
+    surfaces: Vec<SurfaceSnapshot>,
+}
+
+struct SurfaceSnapshot {
+    skinned: bool,
+    texture_hash: u64,
+    geometry_hash: u64,
+}
+
+/// Single draw of surface of a mesh.
+pub struct RenderCommand {
+    pub mesh: Handle<Node>,
+    pub surface: usize,
+    /// Matrix which must be used as world matrix, it is identity for skinned surfaces,
+    /// because their vertices are transformed by bones.
+    pub world: Mat4,
+    pub world_view_projection: Mat4,
+}
+
+/// Culled and sorted list of draws for a camera.
+#[derive(Default)]
+pub struct RenderList {
+    commands: Vec<RenderCommand>,
+    distance_culled: HashSet<Handle<Node>>,
+}
+
+impl RenderList {
+    /// Returns commands sorted by material first and by distance to camera (front to back)
+    /// second, see `SortKey`.
+    pub fn commands(&self) -> &[RenderCommand] {
+        &self.commands
+    }
+
+    /// Takes set of meshes hidden because of distance, it must be passed to next frame
+    /// of same camera, see `DistanceCulling`.
+    pub fn take_distance_culled(&mut self) -> HashSet<Handle<Node>> {
+        std::mem::replace(&mut self.distance_culled, Default::default())
+    }
+
+    /// Gives buffer of commands back to frame arena.
+    pub fn recycle(self, arena: &mut FrameArena) {
+        arena.recycle(self.commands);
+    }
+}
+
+/// Settings of culling of meshes by distance to camera. Each mesh can have its own limit
+/// (see `Base::set_max_render_distance`), global limit is applied to all meshes. Mesh
+/// which was hidden by distance becomes visible again only when it comes closer than
+/// limit reduced by hysteresis, otherwise meshes near limit would flicker when camera
+/// moves back and forth.
+pub struct DistanceCulling<'a> {
+    pub draw_distance: Option<f32>,
+    /// Fraction of limit, in [0; 1] range.
+    pub hysteresis: f32,
+    /// Meshes hidden by distance in previous frame of same camera.
+    pub hidden: Option<&'a HashSet<Handle<Node>>>,
+}
+
+impl<'a> Default for DistanceCulling<'a> {
+    fn default() -> Self {
+        Self {
+            draw_distance: None,
+            hysteresis: 0.0,
+            hidden: None,
+        }
+    }
+}
+
+/// What job produces, indices are indices of meshes in snapshot.
+struct JobResult {
+    commands: Vec<PendingCommand>,
+    distance_culled: Vec<usize>,
+}
+
+/// Command with index of mesh in snapshot instead of handle, this is what workers produce.
+struct PendingCommand {
+    mesh: usize,
+    surface: usize,
+    world: Mat4,
+    world_view_projection: Mat4,
+    sort_key: SortKey,
+}
+
+/// Copy of visible meshes of graph, made on main thread. One snapshot is shared by all
+/// cameras of a scene.
+pub struct GraphSnapshot {
+    handles: Vec<Handle<Node>>,
+    meshes: Arc<Vec<MeshSnapshot>>,
+}
+
+impl GraphSnapshot {
+    pub fn new(graph: &Graph) -> Self {
+        let mut handles = Vec::new();
+        let mut meshes = Vec::new();
+
+        for (handle, node) in graph.pair_iter() {
+            if let Node::Mesh(mesh) = node {
+                if !mesh.global_visibility() {
+                    continue;
+                }
+
+                let mut bone_positions = Vec::new();
+                let surfaces = mesh.surfaces()
+                    .iter()
+                    .map(|surface| {
+                        bone_positions.extend(surface.bones.iter().map(|&bone| graph[bone].global_position()));
+
+                        let textures = (
+                            surface.get_diffuse_texture().map(|t| Arc::as_ptr(&t) as usize),
+                            surface.get_normal_texture().map(|t| Arc::as_ptr(&t) as usize),
+                        );
+
+                        SurfaceSnapshot {
+                            skinned: !surface.bones.is_empty(),
+                            texture_hash: hash_of(textures),
+                            geometry_hash: hash_of(Arc::as_ptr(&surface.get_data()) as usize),
+                        }
+                    })
+                    .collect();
+
+                let world = mesh.global_transform();
+                let bounding_box = mesh.bounding_box();
+                let max_scale = world.side().len().max(world.up().len()).max(world.look().len());
+
+                handles.push(handle);
+                meshes.push(MeshSnapshot {
+                    world,
+                    center: world.transform_vector((bounding_box.min + bounding_box.max).scale(0.5)),
+                    radius: (bounding_box.max - bounding_box.min).len() * 0.5 * max_scale,
+                    max_render_distance: mesh.max_render_distance(),
+                    bounding_box,
+                    bone_positions,
+                    surfaces,
+                });
+            }
+        }
+
+        Self {
+            handles,
+            meshes: Arc::new(meshes),
+        }
+    }
+}
+
+/// Render list which is being built by workers.
+pub struct PendingRenderList {
+    receiver: Receiver<JobResult>,
+    job_count: usize,
+}
+
+impl PendingRenderList {
+    /// Blocks until all workers finished their jobs and returns render list. Snapshot must
+    /// be the one list was started with.
+    /// Buffers of list are taken from given arena, give them back by `RenderList::recycle`.
+    pub fn wait(self, snapshot: &GraphSnapshot, arena: &mut FrameArena) -> RenderList {
+        let handles = &snapshot.handles;
+        let mut pending = arena.take::<PendingCommand>();
+        let mut distance_culled = HashSet::new();
+        for _ in 0..self.job_count {
+            // Worker can only fail to send if it panicked, rest of list is still valid.
+            if let Ok(result) = self.receiver.recv() {
+                pending.extend(result.commands);
+                distance_culled.extend(result.distance_culled.into_iter().map(|index| handles[index]));
+            }
+        }
+
+        // Each job sorts its own part, so this is merging of sorted runs.
+        pending.sort_by_key(|command| command.sort_key);
+
+        let mut commands = arena.take::<RenderCommand>();
+        commands.extend(pending.drain(..)
+            .map(|command| RenderCommand {
+                mesh: handles[command.mesh],
+                surface: command.surface,
+                world: command.world,
+                world_view_projection: command.world_view_projection,
+            }));
+        arena.recycle(pending);
+
+        RenderList {
+            commands,
+            distance_culled,
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Pool of worker threads which build render lists.
+pub struct RenderListBuilder {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl RenderListBuilder {
+    pub fn new() -> Self {
+        let worker_count = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1)
+            // Main thread is busy with submission.
+            .saturating_sub(1)
+            .max(1);
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..worker_count)
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("RenderListWorker{}", i))
+                    .spawn(move || loop {
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => break,
+                        };
+                        match job {
+                            Ok(job) => job(),
+                            // Builder was dropped.
+                            Err(_) => break,
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Starts culling of snapshot for given camera on worker threads.
+    pub fn begin(&self, snapshot: &GraphSnapshot, camera: &Camera, distance_culling: DistanceCulling) -> PendingRenderList {
+        let view_projection = camera.view_projection_matrix();
+        let camera_position = camera.global_position();
+        let z_far = camera.z_far();
+        let draw_distance = distance_culling.draw_distance;
+        let hysteresis = distance_culling.hysteresis.max(0.0).min(1.0);
+        let hidden = Arc::new(distance_culling.hidden
+            .map(|hidden| snapshot.handles.iter().map(|handle| hidden.contains(handle)).collect())
+            .unwrap_or_else(Vec::new));
+
+        let (result_sender, receiver) = mpsc::channel();
+        let mut job_count = 0;
+        let mut start = 0;
+        while start < snapshot.meshes.len() {
+            let range = start..(start + MESHES_PER_JOB).min(snapshot.meshes.len());
+            start = range.end;
+
+            let meshes = snapshot.meshes.clone();
+            let hidden = hidden.clone();
+            let result_sender = result_sender.clone();
+            let job: Job = Box::new(move || {
+                let context = CullContext {
+                    view_projection,
+                    camera_position,
+                    z_far,
+                    draw_distance,
+                    hysteresis,
+                    hidden: &hidden,
+                };
+                let _ = result_sender.send(cull(&meshes, range, context));
+            });
+            if let Some(sender) = self.sender.as_ref() {
+                if sender.send(job).is_ok() {
+                    job_count += 1;
+                }
+            }
+        }
+
+        PendingRenderList {
+            receiver,
+            job_count,
+        }
+    }
+}
+
+struct CullContext<'a> {
+    view_projection: Mat4,
+    camera_position: Vec3,
+    z_far: f32,
+    draw_distance: Option<f32>,
+    hysteresis: f32,
+    /// Flags of meshes hidden by distance in previous frame, empty if there is no history.
+    hidden: &'a [bool],
+}
+
+fn cull(meshes: &[MeshSnapshot], range: Range<usize>, context: CullContext) -> JobResult {
+    let CullContext { view_projection, camera_position, z_far, draw_distance, hysteresis, hidden } = context;
+
+    let frustum = Frustum::from(view_projection).unwrap();
+
+    let mut commands = Vec::new();
+    let mut distance_culled = Vec::new();
+    for (index, mesh) in meshes[range.clone()].iter().enumerate() {
+        let mesh_index = range.start + index;
+        let distance = ((mesh.center - camera_position).len() - mesh.radius).max(0.0);
+
+        let limit = match (mesh.max_render_distance, draw_distance) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if let Some(limit) = limit {
+            let was_hidden = hidden.get(mesh_index).cloned().unwrap_or(false);
+            let limit = if was_hidden { limit * (1.0 - hysteresis) } else { limit };
+            if distance > limit {
+                distance_culled.push(mesh_index);
+                continue;
+            }
+        }
+
+        let visible = frustum.is_intersects_aabb_transform(&mesh.bounding_box, &mesh.world)
+            || mesh.bone_positions.iter().any(|&position| frustum.is_contains_point(position));
+        if !visible {
+            continue;
+        }
+
+        let normalized_depth = distance / z_far.max(std::f32::EPSILON);
+        for (surface_index, surface) in mesh.surfaces.iter().enumerate() {
+            let world = if surface.skinned { Mat4::IDENTITY } else { mesh.world };
+            commands.push(PendingCommand {
+                mesh: mesh_index,
+                surface: surface_index,
+                world,
+                world_view_projection: view_projection * world,
+                sort_key: SortKey::new(surface.skinned, surface.texture_hash, surface.geometry_hash, normalized_depth),
+            });
+        }
+    }
+    commands.sort_by_key(|command| command.sort_key);
+    JobResult {
+        commands,
+        distance_culled,
+    }
+}
+
+impl Drop for RenderListBuilder {
+    fn drop(&mut self) {
+        // Closing channel stops workers.
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renderer::render_list::SortKey;
+
+    #[test]
+    fn test_sort_key_order() {
+        // Material has priority over depth.
+        assert!(SortKey::new(false, 1, 0, 1.0) < SortKey::new(false, 2, 0, 0.0));
+        assert!(SortKey::new(false, 1, 1, 1.0) < SortKey::new(false, 1, 2, 0.0));
+        assert!(SortKey::new(false, 5, 5, 1.0) < SortKey::new(true, 0, 0, 0.0));
+        // Same material is sorted front to back.
+        assert!(SortKey::new(false, 1, 1, 0.1) < SortKey::new(false, 1, 1, 0.2));
+        // Depth out of range is clamped and does not spill into material bits.
+        assert_eq!(SortKey::new(false, 1, 1, 2.0), SortKey::new(false, 1, 1, 1.0));
+    }
+}
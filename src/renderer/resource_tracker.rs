@@ -0,0 +1,73 @@
+//! Tracks lifetime of CPU resources which have GPU copies in renderer caches.
+//!
+//! Each tracked resource (surface data or texture) gets unique id on creation, renderer
+//! caches use this id as a key. When a resource that was uploaded to GPU is dropped, its id
+//! is put into queue and renderer frees corresponding GPU objects on next frame instead of
+//! waiting for cache entry to expire. This also guarantees that new resource allocated at
+//! the address of dropped one never gets stale GPU data.
+
+use std::sync::{
+    Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+pub(in crate) struct DropQueue {
+    next_id: AtomicU64,
+    dropped: Mutex<Vec<u64>>,
+}
+
+impl DropQueue {
+    fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            dropped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Drains ids of dropped resources.
+    pub(in crate) fn drain(&self) -> Vec<u64> {
+        std::mem::replace(&mut *self.dropped.lock().unwrap(), Vec::new())
+    }
+}
+
+lazy_static! {
+    pub(in crate) static ref SURFACE_DATA_QUEUE: DropQueue = DropQueue::new();
+    pub(in crate) static ref TEXTURE_QUEUE: DropQueue = DropQueue::new();
+}
+
+/// Identity of tracked resource, it must be a field of the resource. Renderer marks it as
+/// uploaded when it creates GPU copy, and when tracker is dropped together with the resource
+/// renderer is notified.
+pub(in crate) struct ResourceTracker {
+    id: u64,
+    uploaded: AtomicBool,
+    queue: &'static DropQueue,
+}
+
+impl ResourceTracker {
+    pub(in crate) fn new(queue: &'static DropQueue) -> Self {
+        Self {
+            id: queue.next_id.fetch_add(1, Ordering::Relaxed),
+            uploaded: AtomicBool::new(false),
+            queue,
+        }
+    }
+
+    pub(in crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(in crate) fn mark_uploaded(&self) {
+        self.uploaded.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ResourceTracker {
+    fn drop(&mut self) {
+        // Resources which were never uploaded do not need notification, this keeps queue
+        // empty when there is no renderer at all.
+        if self.uploaded.load(Ordering::Relaxed) {
+            self.queue.dropped.lock().unwrap().push(self.id);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+uniform sampler2D frameTexture;
+uniform sampler2D previousLuminance;
+uniform float adaptation;
+
+out float FragColor;
+
+void main()
+{
+    // Last mip level of frame holds its average color.
+    vec3 average = textureLod(frameTexture, vec2(0.5), 16.0).rgb;
+    float luminance = dot(average, vec3(0.2126, 0.7152, 0.0722));
+    float previous = texture(previousLuminance, vec2(0.5)).r;
+    FragColor = mix(previous, luminance, adaptation);
+}
\ No newline at end of file
@@ -2,6 +2,7 @@
 
 uniform sampler2D diffuseTexture;
 uniform sampler2D aoSampler;
+uniform sampler2D ambientTexture;
 uniform vec4 ambientColor;
 
 out vec4 FragColor;
@@ -10,6 +11,9 @@ in vec2 texCoord;
 void main()
 {
     float ambientOcclusion =  texture(aoSampler, texCoord).r;
-    FragColor = ambientColor * texture(diffuseTexture, texCoord);
+    // Pixels of meshes which use light probes have their own ambient light.
+    vec4 probeAmbient = texture(ambientTexture, texCoord);
+    vec4 ambient = probeAmbient.a > 0.5 ? vec4(probeAmbient.rgb, ambientColor.a) : ambientColor;
+    FragColor = ambient * vec4(texture(diffuseTexture, texCoord).rgb, 1.0);
     FragColor.rgb *= ambientOcclusion;
 }
\ No newline at end of file
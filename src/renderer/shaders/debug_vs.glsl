@@ -10,5 +10,5 @@ out vec4 color;
 void main()
 {
     color = vertexColor;
-    gl_Position = worldViewProjection * vec4(vertexPosition, 1.0);
+    gl_Position = S_LogDepth(worldViewProjection * vec4(vertexPosition, 1.0));
 }
\ No newline at end of file
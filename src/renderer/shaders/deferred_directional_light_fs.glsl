@@ -7,6 +7,7 @@ uniform samplerCube pointShadowTexture;
 
 uniform vec3 lightDirection;
 uniform vec4 lightColor;
+uniform float lightIntensity;
 uniform mat4 invViewProj;
 uniform vec3 cameraPosition;
 
@@ -16,7 +17,7 @@ out vec4 FragColor;
 void main()
 {
     vec3 fragmentNormal = normalize(texture2D(normalTexture, texCoord).xyz * 2.0 - 1.0);
-    vec3 fragmentPosition = S_UnProject(vec3(texCoord, texture2D(depthTexture, texCoord).r), invViewProj);
+    vec3 fragmentPosition = S_UnProject(vec3(texCoord, S_SceneDepth(texture2D(depthTexture, texCoord).r)), invViewProj);
     const float specularPower = 80.0;
 
     vec3 h = normalize(lightDirection + (cameraPosition - fragmentPosition));
@@ -24,7 +25,7 @@ void main()
 
     float lambertian = max(dot(fragmentNormal, lightDirection), 0);
 
-    FragColor = texture2D(colorTexture, texCoord);
+    FragColor = vec4(texture2D(colorTexture, texCoord).rgb, 1.0);
     FragColor.xyz += 0.4 * specular;
-    FragColor *= lambertian * lightColor;
+    FragColor *= lightIntensity * lambertian * lightColor;
 }
\ No newline at end of file
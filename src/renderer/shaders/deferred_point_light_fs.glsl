@@ -8,10 +8,15 @@ uniform samplerCube pointShadowTexture;
 uniform vec3 lightPos;
 uniform float lightRadius;
 uniform vec4 lightColor;
+uniform float lightIntensity;
 uniform mat4 invViewProj;
 uniform vec3 cameraPosition;
-uniform bool softShadows;
+uniform int shadowFilter;
+uniform int shadowSamples;
+uniform float esmExponent;
+uniform float shadowMapInvSize;
 uniform bool shadowsEnabled;
+uniform float shadowStrength;
 
 in vec2 texCoord;
 out vec4 FragColor;
@@ -22,7 +27,7 @@ void main()
     ctx.lightPosition = lightPos;
     ctx.lightRadius = lightRadius;
     ctx.fragmentNormal = normalize(texture2D(normalTexture, texCoord).xyz * 2.0 - 1.0);
-    ctx.fragmentPosition = S_UnProject(vec3(texCoord, texture2D(depthTexture, texCoord).r), invViewProj);
+    ctx.fragmentPosition = S_UnProject(vec3(texCoord, S_SceneDepth(texture2D(depthTexture, texCoord).r)), invViewProj);
     ctx.cameraPosition = cameraPosition;
     ctx.specularPower = 80.0;
     TBlinnPhong lighting = S_BlinnPhong(ctx);
@@ -33,43 +38,49 @@ void main()
 
     if (shadowsEnabled)
     {
-        if (softShadows)
+        vec3 direction = -lighting.direction;
+        float receiverDistance = lighting.distance - bias;
+        if (shadowFilter == S_SHADOW_FILTER_POISSON || shadowFilter == S_SHADOW_FILTER_EXPONENTIAL)
         {
-            const int samples = 20;
+            // Kernel lies in plane perpendicular to direction, face of cube map spans two
+            // units of that plane, so radius is about one and a half texel.
+            vec3 tangent = normalize(cross(direction, abs(direction.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0)));
+            vec3 bitangent = cross(direction, tangent);
+            float filterRadius = 3.0 * shadowMapInvSize;
 
-            const vec3 directions[samples] = vec3[samples] (
-            vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
-            vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
-            vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
-            vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
-            vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
-            );
-
-            const float diskRadius = 0.0025;
-
-            for (int i = 0; i < samples; ++i)
+            float visibility = 0.0;
+            for (int i = 0; i < shadowSamples; ++i)
             {
-                vec3 fetchDirection = -lighting.direction + directions[i] * diskRadius;
+                vec2 offset = S_PoissonDisc[i] * filterRadius;
+                vec3 fetchDirection = direction + tangent * offset.x + bitangent * offset.y;
                 float shadowDistanceToLight = texture(pointShadowTexture, fetchDirection).r;
-                if (lighting.distance - bias > shadowDistanceToLight)
+                if (shadowFilter == S_SHADOW_FILTER_EXPONENTIAL)
                 {
-                    shadow += 1.0;
+                    visibility += S_ExponentialShadow(shadowDistanceToLight / lightRadius, lighting.distance / lightRadius, esmExponent);
+                }
+                else if (receiverDistance <= shadowDistanceToLight)
+                {
+                    visibility += 1.0;
                 }
             }
 
-            shadow = clamp(1.0 - shadow / float(samples), 0.0, 1.0);
+            shadow = visibility / float(shadowSamples);
         }
         else
         {
-            float shadowDistanceToLight = texture(pointShadowTexture, -lighting.direction).r;
-            if (lighting.distance - bias > shadowDistanceToLight)
+            float shadowDistanceToLight = texture(pointShadowTexture, direction).r;
+            if (receiverDistance > shadowDistanceToLight)
             {
                 shadow = 0.0;
             }
         }
     }
 
-    FragColor = texture2D(colorTexture, texCoord);
+    // Alpha channel of diffuse texture holds receive-shadows flag.
+    vec4 diffuseColor = texture2D(colorTexture, texCoord);
+    shadow = mix(1.0, shadow, diffuseColor.a * shadowStrength);
+
+    FragColor = vec4(diffuseColor.rgb, 1.0);
     FragColor.xyz += 0.4 * lighting.specular;
-    FragColor *= lighting.attenuation * shadow * lightColor;
+    FragColor *= lightIntensity * lighting.attenuation * shadow * lightColor;
 }
\ No newline at end of file
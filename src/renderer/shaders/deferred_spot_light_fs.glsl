@@ -4,30 +4,45 @@ uniform sampler2D depthTexture;
 uniform sampler2D colorTexture;
 uniform sampler2D normalTexture;
 uniform sampler2D spotShadowTexture;
+uniform sampler2DShadow spotShadowCompareTexture;
+uniform sampler2D cookieTexture;
 
 uniform mat4 lightViewProjMatrix;
 uniform vec3 lightPos;
 uniform float lightRadius;
 uniform vec4 lightColor;
+uniform float lightIntensity;
 uniform vec3 lightDirection;
 uniform float halfHotspotConeAngleCos;
 uniform float halfConeAngleCos;
 uniform mat4 invViewProj;
 uniform vec3 cameraPosition;
 uniform bool shadowsEnabled;
-uniform bool softShadows;
+uniform bool cookieEnabled;
+uniform int shadowFilter;
+uniform int shadowSamples;
+uniform float esmExponent;
+uniform float shadowZNear;
 uniform float shadowMapInvSize;
+uniform float shadowStrength;
 
 in vec2 texCoord;
 out vec4 FragColor;
 
+// Converts depth from shadow map to distance along direction of light normalized by radius.
+float LinearShadowDepth(float depth)
+{
+    float z = depth * 2.0 - 1.0;
+    return 2.0 * shadowZNear / (lightRadius + shadowZNear - z * (lightRadius - shadowZNear));
+}
+
 void main()
 {
     TBlinnPhongContext ctx;
     ctx.lightPosition = lightPos;
     ctx.lightRadius = lightRadius;
     ctx.fragmentNormal = normalize(texture2D(normalTexture, texCoord).xyz * 2.0 - 1.0);
-    ctx.fragmentPosition = S_UnProject(vec3(texCoord, texture2D(depthTexture, texCoord).r), invViewProj);
+    ctx.fragmentPosition = S_UnProject(vec3(texCoord, S_SceneDepth(texture2D(depthTexture, texCoord).r)), invViewProj);
     ctx.cameraPosition = cameraPosition;
     ctx.specularPower = 80.0;
     TBlinnPhong lighting = S_BlinnPhong(ctx);
@@ -35,37 +50,60 @@ void main()
     float spotAngleCos = dot(lightDirection, lighting.direction);
     float coneFactor = smoothstep(halfConeAngleCos, halfHotspotConeAngleCos, spotAngleCos);
 
+    vec3 lightSpacePosition = S_Project(ctx.fragmentPosition, lightViewProjMatrix);
+
     float shadow = 1.0;
     if (shadowsEnabled)
     {
-        vec3 lightSpacePosition = S_Project(ctx.fragmentPosition, lightViewProjMatrix);
         const float bias = 0.00005;
-        if (softShadows)
+        // Radius of filter kernel in texels.
+        const float filterRadius = 1.5;
+
+        float receiverDepth = lightSpacePosition.z - bias;
+        if (shadowFilter == S_SHADOW_FILTER_HARDWARE_PCF)
+        {
+            shadow = texture(spotShadowCompareTexture, vec3(lightSpacePosition.xy, receiverDepth));
+        }
+        else if (shadowFilter == S_SHADOW_FILTER_POISSON || shadowFilter == S_SHADOW_FILTER_EXPONENTIAL)
         {
-            for (float y = -1.5; y <= 1.5; y += 0.5)
+            float linearReceiverDepth = LinearShadowDepth(receiverDepth);
+            float visibility = 0.0;
+            for (int i = 0; i < shadowSamples; ++i)
             {
-                for (float x = -1.5; x <= 1.5; x += 0.5)
+                vec2 fetchTexCoord = lightSpacePosition.xy + S_PoissonDisc[i] * filterRadius * shadowMapInvSize;
+                float occluderDepth = texture(spotShadowTexture, fetchTexCoord).r;
+                if (shadowFilter == S_SHADOW_FILTER_EXPONENTIAL)
                 {
-                    vec2 fetchTexCoord = lightSpacePosition.xy + vec2(x, y) * shadowMapInvSize;
-                    if (lightSpacePosition.z - bias > texture(spotShadowTexture, fetchTexCoord).r)
-                    {
-                        shadow += 1.0;
-                    }
+                    visibility += S_ExponentialShadow(LinearShadowDepth(occluderDepth), linearReceiverDepth, esmExponent);
+                }
+                else if (receiverDepth <= occluderDepth)
+                {
+                    visibility += 1.0;
                 }
             }
 
-            shadow = clamp(1.0 - shadow / 9.0, 0.0, 1.0);
+            shadow = visibility / float(shadowSamples);
         }
         else
         {
-            if (lightSpacePosition.z - bias > texture(spotShadowTexture, lightSpacePosition.xy).r)
+            if (receiverDepth > texture(spotShadowTexture, lightSpacePosition.xy).r)
             {
                 shadow = 0.0;
             }
         }
     }
 
-    FragColor = texture2D(colorTexture, texCoord);
+    vec4 cookie = vec4(1.0);
+    if (cookieEnabled)
+    {
+        cookie = texture(cookieTexture, lightSpacePosition.xy);
+    }
+
+    // Alpha channel of diffuse texture holds receive-shadows flag.
+    vec4 diffuseColor = texture2D(colorTexture, texCoord);
+    shadow = mix(1.0, shadow, diffuseColor.a * shadowStrength);
+
+    FragColor = vec4(diffuseColor.rgb, 1.0);
     FragColor.xyz += 0.4 * lighting.specular;
-    FragColor *= coneFactor * shadow * lighting.attenuation * lightColor;
+    FragColor *= lightIntensity * coneFactor * shadow * lighting.attenuation * lightColor * cookie;
 }
\ No newline at end of file
@@ -2,23 +2,124 @@
 
 layout(location = 0) out vec4 outColor;
 layout(location = 1) out vec4 outNormal;
+layout(location = 2) out vec4 outAmbient;
 
 uniform sampler2D diffuseTexture;
 uniform sampler2D normalTexture;
-uniform sampler2D specularTexture;
+uniform sampler2D heightTexture;
+uniform bool useParallax;
+uniform float parallaxScale;
+uniform float parallaxMinSamples;
+uniform float parallaxMaxSamples;
+uniform vec3 cameraPosition;
+uniform vec2 texCoordScale;
+uniform vec2 texCoordOffset;
+uniform sampler2D detailDiffuseTexture;
+uniform sampler2D detailNormalTexture;
+uniform bool useDetailDiffuse;
+uniform bool useDetailNormal;
+uniform vec2 detailTiling;
+uniform float detailStrength;
+uniform sampler2DArray diffuseArray;
+uniform sampler2DArray normalArray;
+uniform bool useDiffuseArray;
+uniform bool useNormalArray;
+uniform float arrayLayer;
+uniform bool receiveShadows;
+uniform float reflectivity;
+uniform vec4 diffuseColor;
+uniform bool useAlphaTest;
+uniform float alphaCutoff;
+uniform float wetness;
+uniform bool useAmbientCube;
+// Light from +X, -X, +Y, -Y, +Z, -Z directions.
+uniform vec3 ambientCube[6];
 
 in vec3 normal;
 in vec2 texCoord;
 in vec3 tangent;
 in vec3 binormal;
+in vec4 color;
+in vec3 worldPosition;
+
+// Parallax occlusion mapping, steps along view ray in tangent space until ray goes below
+// surface defined by height map, then refines intersection between last two steps.
+vec2 ParallaxOcclusion(vec2 uv, vec3 viewDir)
+{
+    // Grazing angles need more samples.
+    float samples = mix(parallaxMaxSamples, parallaxMinSamples, abs(viewDir.z));
+    float layerDepth = 1.0 / samples;
+    vec2 deltaUv = viewDir.xy / max(viewDir.z, 0.05) * parallaxScale / samples;
+
+    // Gradients are taken outside of loop, texture fetches in non-uniform flow have no
+    // derivatives.
+    vec2 dx = dFdx(uv);
+    vec2 dy = dFdy(uv);
+
+    vec2 currentUv = uv;
+    float currentLayerDepth = 0.0;
+    float currentDepth = 1.0 - textureGrad(heightTexture, currentUv, dx, dy).r;
+    for (int i = 0; i < 128 && currentLayerDepth < currentDepth; ++i)
+    {
+        currentUv -= deltaUv;
+        currentDepth = 1.0 - textureGrad(heightTexture, currentUv, dx, dy).r;
+        currentLayerDepth += layerDepth;
+    }
+
+    vec2 previousUv = currentUv + deltaUv;
+    float after = currentDepth - currentLayerDepth;
+    float before = 1.0 - textureGrad(heightTexture, previousUv, dx, dy).r - currentLayerDepth + layerDepth;
+    float denominator = after - before;
+    float weight = abs(denominator) > 0.00001 ? after / denominator : 0.0;
+    return mix(currentUv, previousUv, weight);
+}
 
 void main()
 {
-    outColor = texture2D(diffuseTexture, texCoord);
-    if (outColor.a < 0.5) discard;
-    outColor.a = 1;
-    vec4 n = normalize(texture2D(normalTexture, texCoord) * 2.0 - 1.0);
+    vec2 uv = texCoord * texCoordScale + texCoordOffset;
+    if (useParallax)
+    {
+        vec3 toCamera = cameraPosition - worldPosition;
+        vec3 viewDir = normalize(vec3(dot(toCamera, tangent), dot(toCamera, binormal), dot(toCamera, normal)));
+        uv = ParallaxOcclusion(uv, viewDir);
+    }
+    vec2 detailUv = uv * detailTiling;
+
+    vec4 diffuse = useDiffuseArray ? texture(diffuseArray, vec3(uv, arrayLayer)) : texture2D(diffuseTexture, uv);
+    outColor = diffuseColor * color * diffuse;
+    if (useDetailDiffuse)
+    {
+        // Detail texture is centered around 0.5, so it both lightens and darkens.
+        outColor.rgb *= mix(vec3(1.0), 2.0 * texture2D(detailDiffuseTexture, detailUv).rgb, detailStrength);
+    }
+    if (useAlphaTest && outColor.a < alphaCutoff) discard;
+    // Alpha channel is free after alpha test, so it is used to store receive-shadows flag.
+    outColor.a = receiveShadows ? 1.0 : 0.0;
+    vec3 packedNormal = useNormalArray ? texture(normalArray, vec3(uv, arrayLayer)).xyz : texture2D(normalTexture, uv).xyz;
+    vec3 n = normalize(packedNormal * 2.0 - 1.0);
+    if (useDetailNormal)
+    {
+        vec3 detailNormal = texture2D(detailNormalTexture, detailUv).xyz * 2.0 - 1.0;
+        n = normalize(vec3(n.xy + detailNormal.xy * detailStrength, n.z));
+    }
     mat3 tangentSpace = mat3(tangent, binormal, normal);
-    outNormal.xyz = normalize(tangentSpace * n.xyz) * 0.5 + 0.5;
-    outNormal.w = texture2D(specularTexture, texCoord).r;
+    vec3 worldNormal = normalize(tangentSpace * n);
+    // Back faces are visible only on two-sided surfaces, they are lit from their own side.
+    if (!gl_FrontFacing) {
+        worldNormal = -worldNormal;
+    }
+    outNormal.xyz = worldNormal * 0.5 + 0.5;
+    // Wet surfaces are darker and more reflective, rain wets up-facing surfaces the most.
+    float wet = wetness * clamp(worldNormal.y * 0.5 + 0.5, 0.0, 1.0);
+    outColor.rgb *= 1.0 - 0.4 * wet;
+    outNormal.w = mix(reflectivity, 1.0, 0.5 * wet);
+    if (useAmbientCube) {
+        vec3 nSquared = worldNormal * worldNormal;
+        outAmbient.rgb = nSquared.x * ambientCube[worldNormal.x >= 0.0 ? 0 : 1] +
+                         nSquared.y * ambientCube[worldNormal.y >= 0.0 ? 2 : 3] +
+                         nSquared.z * ambientCube[worldNormal.z >= 0.0 ? 4 : 5];
+        outAmbient.a = 1.0;
+    } else {
+        outAmbient = vec4(0.0);
+    }
 }
\ No newline at end of file
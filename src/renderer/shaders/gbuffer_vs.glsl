@@ -6,45 +6,43 @@ layout(location = 2) in vec3 vertexNormal;
 layout(location = 3) in vec4 vertexTangent;
 layout(location = 4) in vec4 boneWeights;
 layout(location = 5) in vec4 boneIndices;
+layout(location = 7) in vec4 vertexColor;
 
 uniform mat4 worldMatrix;
 uniform mat4 worldViewProjection;
 uniform bool useSkeletalAnimation;
-uniform mat4 boneMatrices[60];
+uniform sampler2D boneMatrices;
+// Bones of each instance of skinned surface are stored one after another, so index of
+// bone of instance is offset by amount of bones of previous instances.
+uniform int bonesPerInstance;
+uniform bool useInstancing;
+uniform sampler2D instanceMatrices;
+uniform bool useVertexAnimation;
+uniform sampler2D vertexAnimationPositions;
+uniform sampler2D vertexAnimationNormals;
+uniform int vertexAnimationFrameCount;
 
 out vec3 normal;
 out vec2 texCoord;
 out vec3 tangent;
 out vec3 binormal;
+out vec4 color;
+out vec3 worldPosition;
 
 void main()
 {
     vec4 localPosition = vec4(0);
     vec3 localNormal = vec3(0);
     vec3 localTangent = vec3(0);
+    // Handedness of tangent space, mirroring transforms flip it.
+    float handedness = vertexTangent.w * sign(determinant(mat3(worldMatrix)));
     if (useSkeletalAnimation)
     {
-        vec4 vertex = vec4(vertexPosition, 1.0);
+        mat4 skinning = S_SkinningMatrix(boneMatrices, gl_InstanceID * bonesPerInstance, boneIndices, boneWeights);
 
-        int i0 = int(boneIndices.x);
-        int i1 = int(boneIndices.y);
-        int i2 = int(boneIndices.z);
-        int i3 = int(boneIndices.w);
-
-        localPosition += boneMatrices[i0] * vertex * boneWeights.x;
-        localPosition += boneMatrices[i1] * vertex * boneWeights.y;
-        localPosition += boneMatrices[i2] * vertex * boneWeights.z;
-        localPosition += boneMatrices[i3] * vertex * boneWeights.w;
-
-        localNormal += mat3(boneMatrices[i0]) * vertexNormal * boneWeights.x;
-        localNormal += mat3(boneMatrices[i1]) * vertexNormal * boneWeights.y;
-        localNormal += mat3(boneMatrices[i2]) * vertexNormal * boneWeights.z;
-        localNormal += mat3(boneMatrices[i3]) * vertexNormal * boneWeights.w;
-
-        localTangent += mat3(boneMatrices[i0]) * vertexTangent.xyz * boneWeights.x;
-        localTangent += mat3(boneMatrices[i1]) * vertexTangent.xyz * boneWeights.y;
-        localTangent += mat3(boneMatrices[i2]) * vertexTangent.xyz * boneWeights.z;
-        localTangent += mat3(boneMatrices[i3]) * vertexTangent.xyz * boneWeights.w;
+        localPosition = skinning * vec4(vertexPosition, 1.0);
+        localNormal = mat3(skinning) * vertexNormal;
+        localTangent = mat3(skinning) * vertexTangent.xyz;
     }
     else
     {
@@ -52,9 +50,34 @@ void main()
         localNormal = vertexNormal;
         localTangent = vertexTangent.xyz;
     }
-    gl_Position = worldViewProjection * localPosition;
+    if (useInstancing)
+    {
+        mat4 instanceMatrix = S_FetchMatrix(instanceMatrices, gl_InstanceID);
+        // Frame of vertex animation is packed into matrix, see `Crowd::visible_instances`.
+        float frame = instanceMatrix[0][3];
+        instanceMatrix[0][3] = 0.0;
+        if (useVertexAnimation)
+        {
+            int frame0 = int(floor(frame)) % vertexAnimationFrameCount;
+            int frame1 = (frame0 + 1) % vertexAnimationFrameCount;
+            float t = fract(frame);
+            vec3 position0 = texelFetch(vertexAnimationPositions, ivec2(gl_VertexID, frame0), 0).xyz;
+            vec3 position1 = texelFetch(vertexAnimationPositions, ivec2(gl_VertexID, frame1), 0).xyz;
+            vec3 normal0 = texelFetch(vertexAnimationNormals, ivec2(gl_VertexID, frame0), 0).xyz * 2.0 - 1.0;
+            vec3 normal1 = texelFetch(vertexAnimationNormals, ivec2(gl_VertexID, frame1), 0).xyz * 2.0 - 1.0;
+            localPosition = vec4(mix(position0, position1, t), 1.0);
+            localNormal = normalize(mix(normal0, normal1, t));
+        }
+        localPosition = instanceMatrix * localPosition;
+        localNormal = mat3(instanceMatrix) * localNormal;
+        localTangent = mat3(instanceMatrix) * localTangent;
+        handedness *= sign(determinant(mat3(instanceMatrix)));
+    }
+    gl_Position = S_LogDepth(worldViewProjection * localPosition);
+    worldPosition = (worldMatrix * localPosition).xyz;
     normal = normalize(mat3(worldMatrix) * localNormal);
     tangent = normalize(mat3(worldMatrix) * localTangent);
-    binormal = normalize(vertexTangent.w * cross(tangent, normal));
+    binormal = normalize(handedness * cross(tangent, normal));
     texCoord = vertexTexCoord;
+    color = vertexColor;
 }
\ No newline at end of file
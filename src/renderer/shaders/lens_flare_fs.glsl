@@ -0,0 +1,14 @@
+#version 330 core
+
+uniform sampler2D diffuseTexture;
+uniform vec3 lightColor;
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+in vec4 color;
+
+void main()
+{
+    FragColor = vec4(color.rgb * lightColor, color.a) * texture(diffuseTexture, texCoord).r;
+}
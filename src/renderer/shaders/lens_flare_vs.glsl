@@ -0,0 +1,34 @@
+#version 330 core
+
+layout(location = 0) in vec2 vertexPosition;
+layout(location = 1) in vec2 vertexTexCoord;
+layout(location = 2) in vec4 vertexColor;
+
+uniform sampler2D depthBufferTexture;
+uniform vec3 lightScreenPosition;
+uniform vec2 occlusionRadius;
+
+out vec2 texCoord;
+out vec4 color;
+
+void main()
+{
+    // Check grid of points around light position, each point which is not covered by
+    // geometry closer than light adds to visibility of flare.
+    const int halfGridSize = 2;
+    float visible = 0.0;
+    float total = 0.0;
+    for (int y = -halfGridSize; y <= halfGridSize; ++y) {
+        for (int x = -halfGridSize; x <= halfGridSize; ++x) {
+            vec2 uv = lightScreenPosition.xy + vec2(x, y) * occlusionRadius / float(halfGridSize);
+            float sceneDepth = S_SceneDepth(textureLod(depthBufferTexture, uv, 0.0).r);
+            visible += sceneDepth >= lightScreenPosition.z ? 1.0 : 0.0;
+            total += 1.0;
+        }
+    }
+
+    texCoord = vertexTexCoord;
+    color = vertexColor;
+    color.a *= visible / total;
+    gl_Position = vec4(vertexPosition, 0.0, 1.0);
+}
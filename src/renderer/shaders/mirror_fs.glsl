@@ -0,0 +1,16 @@
+#version 330 core
+
+uniform sampler2D reflectionTexture;
+uniform vec2 invScreenSize;
+uniform vec4 tint;
+
+out vec4 FragColor;
+
+void main()
+{
+    // Projective mapping - reflection was rendered with the same projection as main view,
+    // so screen coordinates of fragment are texture coordinates of reflection. Reflection
+    // is mirrored horizontally, see Camera::reflected.
+    vec2 screenCoord = gl_FragCoord.xy * invScreenSize;
+    FragColor = tint * vec4(texture(reflectionTexture, vec2(1.0 - screenCoord.x, screenCoord.y)).rgb, 1.0);
+}
@@ -18,8 +18,8 @@ float toProjSpace(float z)
 
 void main()
 {
-    float sceneDepth = toProjSpace(texture(depthBufferTexture, gl_FragCoord.xy * invScreenSize).r);
-    float depthOpacity = clamp((sceneDepth - gl_FragCoord.z / gl_FragCoord.w) * 2.0f, 0.0, 1.0);
+    float sceneDepth = toProjSpace(S_SceneDepth(texture(depthBufferTexture, gl_FragCoord.xy * invScreenSize).r));
+    float depthOpacity = clamp((sceneDepth - S_SceneDepth(gl_FragCoord.z) / gl_FragCoord.w) * 2.0f, 0.0, 1.0);
     FragColor = color * texture(diffuseTexture, texCoord).r;
     FragColor.a *= depthOpacity;
 }
\ No newline at end of file
@@ -29,5 +29,5 @@ void main()
     vec2 vertexOffset = rotateVec2(vertexTexCoord * 2.0 - 1.0, particleRotation);
     vec4 worldPosition = worldMatrix * vec4(vertexPosition, 1.0);
     vec3 offset = (vertexOffset.x * cameraSideVector + vertexOffset.y * cameraUpVector) * particleSize;
-    gl_Position = viewProjectionMatrix * (worldPosition + vec4(offset.x, offset.y, offset.z, 0.0));
+    gl_Position = S_LogDepth(viewProjectionMatrix * (worldPosition + vec4(offset.x, offset.y, offset.z, 0.0)));
 }
\ No newline at end of file
@@ -0,0 +1,17 @@
+#version 330 core
+
+uniform sampler2D diffuseTexture;
+uniform float alphaCutoff;
+uniform int nodeIndex;
+uniform int nodeGeneration;
+
+in vec2 texCoord;
+
+layout(location = 0) out uvec2 nodeId;
+
+void main()
+{
+    if (texture(diffuseTexture, texCoord).a < alphaCutoff) discard;
+    // Zero means empty pixel, so index is shifted by one.
+    nodeId = uvec2(uint(nodeIndex) + 1u, uint(nodeGeneration));
+}
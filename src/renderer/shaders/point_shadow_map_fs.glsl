@@ -1,6 +1,7 @@
 #version 330 core
 
 uniform sampler2D diffuseTexture;
+uniform float alphaCutoff;
 uniform vec3 lightPosition;
 
 in vec2 texCoord;
@@ -10,6 +11,6 @@ layout(location = 0) out float depth;
 
 void main()
 {
-    if (texture(diffuseTexture, texCoord).a < 0.2) discard;
+    if (texture(diffuseTexture, texCoord).a < alphaCutoff) discard;
     depth = length(lightPosition - worldPosition);
 }
\ No newline at end of file
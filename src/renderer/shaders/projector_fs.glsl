@@ -0,0 +1,61 @@
+#version 330 core
+
+// Must be in sync with ProjectorRenderer.
+#define MODE_ADDITIVE 0
+#define MODE_MULTIPLY 1
+#define MODE_ALPHA_BLEND 2
+
+uniform sampler2D depthTexture;
+uniform sampler2D normalTexture;
+uniform sampler2D projectedTexture;
+
+uniform mat4 invViewProj;
+uniform mat4 projectorViewProj;
+uniform vec3 projectorPosition;
+uniform vec3 projectorDirection;
+uniform bool perspective;
+uniform vec4 projectorColor;
+uniform float intensity;
+uniform vec2 uvOffset;
+uniform int mode;
+
+in vec2 texCoord;
+out vec4 FragColor;
+
+void main()
+{
+    float depth = texture(depthTexture, texCoord).r;
+    // Nothing to project on, there is only sky.
+    if (depth >= 1.0)
+    {
+        discard;
+    }
+
+    vec3 fragmentPosition = S_UnProject(vec3(texCoord, S_SceneDepth(depth)), invViewProj);
+    vec3 projected = S_Project(fragmentPosition, projectorViewProj);
+    if (any(lessThan(projected, vec3(0.0))) || any(greaterThan(projected, vec3(1.0))))
+    {
+        discard;
+    }
+
+    // Surfaces turned away from projector do not receive projection, grazing ones fade out.
+    vec3 normal = normalize(texture(normalTexture, texCoord).xyz * 2.0 - 1.0);
+    vec3 toProjector = perspective ? normalize(projectorPosition - fragmentPosition) : -projectorDirection;
+    float facing = clamp(dot(normal, toProjector) * 4.0, 0.0, 1.0);
+
+    vec4 color = texture(projectedTexture, projected.xy + uvOffset) * projectorColor;
+    float strength = color.a * intensity * facing;
+
+    if (mode == MODE_ADDITIVE)
+    {
+        FragColor = vec4(color.rgb * strength, 1.0);
+    }
+    else if (mode == MODE_MULTIPLY)
+    {
+        FragColor = vec4(mix(vec3(1.0), color.rgb, clamp(strength, 0.0, 1.0)), 1.0);
+    }
+    else
+    {
+        FragColor = vec4(color.rgb, clamp(strength, 0.0, 1.0));
+    }
+}
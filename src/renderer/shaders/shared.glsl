@@ -1,6 +1,32 @@
 // Shared functions for all shaders in the engine. Contents of this
 // file will be *automatically* included in all shaders!
 
+// Filters of shadow maps, must match `ShadowFilter::shader_index`.
+const int S_SHADOW_FILTER_HARD = 0;
+const int S_SHADOW_FILTER_HARDWARE_PCF = 1;
+const int S_SHADOW_FILTER_POISSON = 2;
+const int S_SHADOW_FILTER_EXPONENTIAL = 3;
+
+// Points of Poisson disc of unit radius. Points are ordered so first 8, 16 or 32 of them
+// cover disc evenly, so the same array is used for kernels of any of these sizes.
+const vec2 S_PoissonDisc[32] = vec2[32](
+    vec2(-0.3523, -0.6983), vec2(0.5679, 0.7941), vec2(-0.6625, 0.5697), vec2(0.7603, -0.3429),
+    vec2(0.0266, 0.0610), vec2(-0.8812, -0.2843), vec2(0.3789, -0.9243), vec2(-0.1118, 0.9157),
+    vec2(0.9286, 0.2866), vec2(0.2081, -0.4226), vec2(0.4607, 0.2975), vec2(-0.4603, -0.0256),
+    vec2(-0.2136, 0.5106), vec2(0.1900, 0.6294), vec2(-0.9132, 0.2248), vec2(-0.0117, -0.9878),
+    vec2(0.4534, -0.1024), vec2(-0.1689, -0.2995), vec2(-0.7197, -0.6678), vec2(0.5193, -0.6059),
+    vec2(-0.4316, 0.8239), vec2(0.2324, 0.9711), vec2(-0.5459, -0.3946), vec2(0.0033, -0.6559),
+    vec2(0.9230, -0.0443), vec2(0.1172, 0.3327), vec2(-0.4973, 0.3008), vec2(0.7432, 0.4915),
+    vec2(-0.7507, -0.0154), vec2(0.1728, -0.1671), vec2(0.6924, 0.1873), vec2(0.6913, -0.1062)
+);
+
+// Visibility of receiver for exponential shadow map. Depths must be linear and normalized
+// to [0; 1] range, otherwise exponent means different sharpness at different distances.
+float S_ExponentialShadow(float occluderDepth, float receiverDepth, float exponent)
+{
+    return clamp(exp(exponent * (occluderDepth - receiverDepth)), 0.0, 1.0);
+}
+
 // Tries to solve quadratic equation. Returns true iff there are any real roots.
 bool S_SolveQuadraticEq(float a, float b, float c, out float minT, out float maxT)
 {
@@ -26,11 +52,67 @@ bool S_SolveQuadraticEq(float a, float b, float c, out float minT, out float max
     return true;
 }
 
-// Returns attenuation in inverse square model. It falls to zero at given radius.
+// Returns attenuation in inverse square model. Inverse square law is multiplied by smooth
+// window function, so attenuation falls to zero exactly at given radius without visible
+// edge. One is added to squared distance to avoid singularity near light source.
 float S_LightDistanceAttenuation(float distance, float radius)
 {
-    float attenuation = clamp(1.0 - distance * distance / (radius * radius), 0.0, 1.0);
-    return attenuation * attenuation;
+    float distanceOverRadius = distance / radius;
+    float k = distanceOverRadius * distanceOverRadius;
+    float window = clamp(1.0 - k * k, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// Fetches matrix from matrix storage texture, each row of texture contains one matrix,
+// each texel of row contains one column of matrix.
+mat4 S_FetchMatrix(sampler2D storage, int index)
+{
+    return mat4(
+        texelFetch(storage, ivec2(0, index), 0),
+        texelFetch(storage, ivec2(1, index), 0),
+        texelFetch(storage, ivec2(2, index), 0),
+        texelFetch(storage, ivec2(3, index), 0)
+    );
+}
+
+// Returns blend of matrices of four bones which affect vertex. Used by every pass which
+// draws skinned surfaces, so skinned geometry is deformed the same way in all of them.
+// Bones of instance are at given offset in storage.
+mat4 S_SkinningMatrix(sampler2D boneMatrices, int boneOffset, vec4 boneIndices, vec4 boneWeights)
+{
+    return S_FetchMatrix(boneMatrices, boneOffset + int(boneIndices.x)) * boneWeights.x +
+           S_FetchMatrix(boneMatrices, boneOffset + int(boneIndices.y)) * boneWeights.y +
+           S_FetchMatrix(boneMatrices, boneOffset + int(boneIndices.z)) * boneWeights.z +
+           S_FetchMatrix(boneMatrices, boneOffset + int(boneIndices.w)) * boneWeights.w;
+}
+
+// Parameters of logarithmic depth, renderer sets them automatically for every program.
+// Zero coefficient means that logarithmic depth is disabled.
+uniform float S_LogDepthCoefficient;
+// Depth part of projection matrix: regular depth in NDC is x + y / w.
+uniform vec2 S_LogDepthProjection;
+
+// Replaces depth of clip space position by logarithmic depth if it is enabled. Every vertex
+// shader which draws into depth buffer of scene or tests against it must use it.
+vec4 S_LogDepth(vec4 clipPosition)
+{
+    if (S_LogDepthCoefficient > 0.0)
+    {
+        clipPosition.z = (log2(max(1e-6, 1.0 + clipPosition.w)) * S_LogDepthCoefficient - 1.0) * clipPosition.w;
+    }
+    return clipPosition;
+}
+
+// Converts value from depth buffer of scene to regular depth, so it can be unprojected by
+// inverse projection matrix. Returns depth as is if logarithmic depth is disabled.
+float S_SceneDepth(float depth)
+{
+    if (S_LogDepthCoefficient > 0.0)
+    {
+        float w = max(1e-6, exp2(2.0 * depth / S_LogDepthCoefficient) - 1.0);
+        return (S_LogDepthProjection.x + S_LogDepthProjection.y / w) * 0.5 + 0.5;
+    }
+    return depth;
 }
 
 // Projects world space position (typical use case) by given matrix.
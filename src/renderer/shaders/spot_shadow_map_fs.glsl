@@ -1,10 +1,11 @@
 #version 330 core
 
 uniform sampler2D diffuseTexture;
+uniform float alphaCutoff;
 
 in vec2 texCoord;
 
 void main()
 {
-    if (texture(diffuseTexture, texCoord).a < 0.2) discard;
+    if (texture(diffuseTexture, texCoord).a < alphaCutoff) discard;
 }
\ No newline at end of file
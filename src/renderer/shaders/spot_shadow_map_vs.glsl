@@ -7,7 +7,7 @@ layout(location = 5) in vec4 boneIndices;
 
 uniform mat4 worldViewProjection;
 uniform bool useSkeletalAnimation;
-uniform mat4 boneMatrices[60];
+uniform sampler2D boneMatrices;
 
 out vec2 texCoord;
 
@@ -17,12 +17,8 @@ void main()
 
     if (useSkeletalAnimation)
     {
-        vec4 vertex = vec4(vertexPosition, 1.0);
-
-        localPosition += boneMatrices[int(boneIndices.x)] * vertex * boneWeights.x;
-        localPosition += boneMatrices[int(boneIndices.y)] * vertex * boneWeights.y;
-        localPosition += boneMatrices[int(boneIndices.z)] * vertex * boneWeights.z;
-        localPosition += boneMatrices[int(boneIndices.w)] * vertex * boneWeights.w;
+        // Same skinning as in g-buffer pass, otherwise shadow won't match deformed mesh.
+        localPosition = S_SkinningMatrix(boneMatrices, 0, boneIndices, boneWeights) * vec4(vertexPosition, 1.0);
     }
     else
     {
@@ -15,7 +15,7 @@ in vec2 texCoord;
 
 void main()
 {
-    vec3 fragmentPosition = S_UnProject(vec3(texCoord, texture(depthSampler, texCoord).r), invProj);
+    vec3 fragmentPosition = S_UnProject(vec3(texCoord, S_SceneDepth(texture(depthSampler, texCoord).r)), invProj);
     float fragmentDepth = length(fragmentPosition);
     vec3 viewDirection = fragmentPosition / fragmentDepth;
 
@@ -2,15 +2,17 @@
 
 layout(location = 0) in vec3 vertexPosition;
 layout(location = 1) in vec2 vertexTexCoord;
+layout(location = 2) in vec2 vertexCorner;
+layout(location = 3) in float spriteSize;
+layout(location = 4) in float spriteRotation;
+layout(location = 5) in vec4 vertexColor;
 
 uniform mat4 viewProjectionMatrix;
-uniform mat4 worldMatrix;
 uniform vec3 cameraUpVector;
 uniform vec3 cameraSideVector;
-uniform float size;
-uniform float rotation;
 
 out vec2 texCoord;
+out vec4 color;
 
 vec2 rotateVec2(vec2 v, float angle)
 {
@@ -23,8 +25,8 @@ vec2 rotateVec2(vec2 v, float angle)
 void main()
 {
     texCoord = vertexTexCoord;
-    vec2 vertexOffset = rotateVec2(vertexTexCoord * 2.0 - 1.0, rotation);
-    vec4 worldPosition = worldMatrix * vec4(vertexPosition, 1.0);
-    vec3 offset = (vertexOffset.x * cameraSideVector + vertexOffset.y * cameraUpVector) * size;
-    gl_Position = viewProjectionMatrix * (worldPosition + vec4(offset.x, offset.y, offset.z, 0.0));
+    color = vertexColor;
+    vec2 vertexOffset = rotateVec2(vertexCorner * 2.0 - 1.0, spriteRotation);
+    vec3 offset = (vertexOffset.x * cameraSideVector + vertexOffset.y * cameraUpVector) * spriteSize;
+    gl_Position = S_LogDepth(viewProjectionMatrix * vec4(vertexPosition + offset, 1.0));
 }
\ No newline at end of file
@@ -18,7 +18,7 @@ out float finalOcclusion;
 in vec2 texCoord;
 
 vec3 GetViewSpacePosition(vec2 screenCoord) {
-    return S_UnProject(vec3(screenCoord, texture(depthSampler, screenCoord).r), inverseProjectionMatrix);
+    return S_UnProject(vec3(screenCoord, S_SceneDepth(texture(depthSampler, screenCoord).r)), inverseProjectionMatrix);
 }
 
 void main() {
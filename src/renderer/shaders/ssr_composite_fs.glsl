@@ -0,0 +1,28 @@
+// Adds reflections to frame, less reflective surfaces get blurred reflections.
+
+#version 330 core
+
+#define MAX_BLUR_RADIUS 8.0
+
+uniform sampler2D reflectionSampler;
+uniform sampler2D normalSampler;
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+void main()
+{
+    float roughness = 1.0 - texture(normalSampler, texCoord).w;
+    vec2 texelSize = 1.0 / vec2(textureSize(reflectionSampler, 0));
+    vec2 offsetStep = texelSize * roughness * MAX_BLUR_RADIUS * 0.5;
+    vec4 result = vec4(0.0);
+    for (int y = -2; y <= 2; ++y)
+    {
+        for (int x = -2; x <= 2; ++x)
+        {
+            result += texture(reflectionSampler, texCoord + vec2(float(x), float(y)) * offsetStep);
+        }
+    }
+    FragColor = result / 25.0;
+}
@@ -0,0 +1,88 @@
+#version 330 core
+
+#define MAX_STEPS 64
+#define REFINE_STEPS 6
+
+uniform sampler2D depthSampler;
+uniform sampler2D normalSampler;
+uniform sampler2D frameSampler;
+
+uniform mat4 projectionMatrix;
+uniform mat4 inverseProjectionMatrix;
+uniform mat3 viewMatrix;
+uniform float maxDistance;
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+vec3 GetViewSpacePosition(vec2 screenCoord) {
+    return S_UnProject(vec3(screenCoord, S_SceneDepth(texture(depthSampler, screenCoord).r)), inverseProjectionMatrix);
+}
+
+vec3 ProjectToScreen(vec3 viewSpacePosition) {
+    vec4 position = projectionMatrix * vec4(viewSpacePosition, 1.0);
+    return position.xyz / position.w * 0.5 + 0.5;
+}
+
+bool IsOnScreen(vec3 screenPosition) {
+    return all(greaterThanEqual(screenPosition.xy, vec2(0.0))) && all(lessThanEqual(screenPosition.xy, vec2(1.0)));
+}
+
+void main() {
+    FragColor = vec4(0.0);
+
+    vec4 normalReflectivity = texture(normalSampler, texCoord);
+    float reflectivity = normalReflectivity.w;
+    float depth = texture(depthSampler, texCoord).r;
+    if (reflectivity <= 0.0 || depth >= 1.0) {
+        return;
+    }
+
+    vec3 fragPos = S_UnProject(vec3(texCoord, S_SceneDepth(depth)), inverseProjectionMatrix);
+    vec3 viewSpaceNormal = normalize(viewMatrix * (normalReflectivity.xyz * 2.0 - 1.0));
+    vec3 direction = normalize(reflect(normalize(fragPos), viewSpaceNormal));
+
+    // Camera looks along -Z in view space, rays which go towards camera can't hit anything
+    // visible on screen.
+    if (direction.z > 0.0) {
+        return;
+    }
+
+    float stepLength = maxDistance / float(MAX_STEPS);
+    vec3 rayPosition = fragPos;
+    for (int i = 0; i < MAX_STEPS; ++i) {
+        rayPosition += direction * stepLength;
+
+        vec3 screenPosition = ProjectToScreen(rayPosition);
+        if (!IsOnScreen(screenPosition)) {
+            return;
+        }
+
+        // Ray is behind surface stored in depth buffer, but not too far behind - otherwise
+        // it went behind an object and does not actually hit it.
+        float delta = GetViewSpacePosition(screenPosition.xy).z - rayPosition.z;
+        if (delta > 0.0 && delta < 2.0 * stepLength) {
+            // Refine hit position using binary search between last two steps.
+            vec3 begin = rayPosition - direction * stepLength;
+            vec3 end = rayPosition;
+            for (int j = 0; j < REFINE_STEPS; ++j) {
+                vec3 middle = 0.5 * (begin + end);
+                if (GetViewSpacePosition(ProjectToScreen(middle).xy).z - middle.z > 0.0) {
+                    end = middle;
+                } else {
+                    begin = middle;
+                }
+            }
+            screenPosition = ProjectToScreen(end);
+
+            // Fade reflections near edges of screen and at max distance to hide the moment
+            // when ray leaves screen.
+            vec2 edgeFade = smoothstep(0.0, 0.1, screenPosition.xy) * (1.0 - smoothstep(0.9, 1.0, screenPosition.xy));
+            float distanceFade = 1.0 - float(i) / float(MAX_STEPS);
+
+            FragColor = vec4(texture(frameSampler, screenPosition.xy).rgb, reflectivity * edgeFade.x * edgeFade.y * distanceFade);
+            return;
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+uniform sampler2D fontTexture;
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+in vec4 color;
+
+void main()
+{
+    FragColor = color;
+    FragColor.a *= texture(fontTexture, texCoord).r;
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 330 core
+
+layout(location = 0) in vec3 vertexPosition;
+layout(location = 1) in vec2 vertexOffset;
+layout(location = 2) in vec2 vertexTexCoord;
+layout(location = 3) in vec4 vertexColor;
+
+uniform mat4 viewProjectionMatrix;
+uniform vec3 cameraUpVector;
+uniform vec3 cameraSideVector;
+
+out vec2 texCoord;
+out vec4 color;
+
+void main()
+{
+    color = vertexColor;
+    texCoord = vertexTexCoord;
+    vec3 offset = vertexOffset.x * cameraSideVector + vertexOffset.y * cameraUpVector;
+    gl_Position = S_LogDepth(viewProjectionMatrix * vec4(vertexPosition + offset, 1.0));
+}
\ No newline at end of file
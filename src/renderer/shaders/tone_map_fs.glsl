@@ -0,0 +1,26 @@
+#version 330 core
+
+uniform sampler2D frameTexture;
+uniform sampler2D luminanceTexture;
+uniform bool autoExposure;
+uniform float exposure;
+uniform float keyValue;
+uniform float minLuminance;
+uniform float maxLuminance;
+
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+void main()
+{
+    float finalExposure = exposure;
+    if (autoExposure) {
+        float luminance = clamp(texture(luminanceTexture, vec2(0.5)).r, minLuminance, maxLuminance);
+        finalExposure = keyValue / luminance;
+    }
+
+    // Reinhard operator.
+    vec3 color = texture(frameTexture, texCoord).rgb * finalExposure;
+    FragColor = vec4(color / (vec3(1.0) + color), 1.0);
+}
\ No newline at end of file
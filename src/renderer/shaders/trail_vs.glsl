@@ -0,0 +1,17 @@
+#version 330 core
+
+layout(location = 0) in vec3 vertexPosition;
+layout(location = 1) in vec2 vertexTexCoord;
+layout(location = 2) in vec4 vertexColor;
+
+uniform mat4 viewProjectionMatrix;
+
+out vec2 texCoord;
+out vec4 color;
+
+void main()
+{
+    color = vertexColor;
+    texCoord = vertexTexCoord;
+    gl_Position = S_LogDepth(viewProjectionMatrix * vec4(vertexPosition, 1.0));
+}
\ No newline at end of file
@@ -18,6 +18,8 @@ uniform vec2 gradientOrigin;
 uniform vec2 gradientEnd;
 
 uniform vec2 resolution;
+// Pixels per unit of user interface, bounds are in units.
+uniform float scale;
 uniform vec2 boundsMin;
 uniform vec2 boundsMax;
 
@@ -45,7 +47,7 @@ int find_stop_index(float t) {
 void main()
 {
     vec2 size = vec2(boundsMax.x - boundsMin.x, boundsMax.y - boundsMin.y);
-    vec2 localPosition = (vec2(gl_FragCoord.x, resolution.y - gl_FragCoord.y) - boundsMin) / size;
+    vec2 localPosition = (vec2(gl_FragCoord.x, resolution.y - gl_FragCoord.y) / scale - boundsMin) / size;
 
     if (brushType == 0) {
         // Solid color
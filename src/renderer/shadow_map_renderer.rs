@@ -1,13 +1,29 @@
+//! Shadow map renderers for spot and point lights.
+//!
+//! Shadows of static meshes (see `Mesh::set_static`) are cached per light: static casters are
+//! rendered into separate map of light which is re-rendered only when some static caster in
+//! range of light changes or the light itself changes. Each frame cached map is copied into
+//! shadow map and only dynamic casters are rendered on top of it. Skinned meshes are always
+//! dynamic casters, their shadow follows current pose of skeleton.
+
 use std::{
     cell::RefCell,
     rc::Rc,
+    collections::{
+        HashMap,
+        hash_map::DefaultHasher,
+    },
+    hash::{Hash, Hasher},
 };
 use crate::{
+    resource::material::BlendMode,
     scene::{
         node::Node,
         graph::Graph,
+        mesh::Mesh,
     },
     core::{
+        pool::Handle,
         scope_profile,
         math::{
             mat4::Mat4,
@@ -21,7 +37,6 @@ use crate::{
         framework::{
             framebuffer::{
                 DrawParameters,
-                CullFace,
                 FrameBufferTrait,
                 FrameBuffer,
                 Attachment,
@@ -48,6 +63,7 @@ use crate::{
         GeometryCache,
         RenderPassStatistics,
         error::RendererError,
+        matrix_storage::MatrixStorage,
     },
 };
 
@@ -57,6 +73,7 @@ struct SpotShadowMapShader {
     world_view_projection_matrix: UniformLocation,
     use_skeletal_animation: UniformLocation,
     diffuse_texture: UniformLocation,
+    alpha_cutoff: UniformLocation,
 }
 
 impl SpotShadowMapShader {
@@ -69,32 +86,110 @@ impl SpotShadowMapShader {
             world_view_projection_matrix: program.uniform_location("worldViewProjection")?,
             use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
+            alpha_cutoff: program.uniform_location("alphaCutoff")?,
 
             program,
         })
     }
 }
 
+/// Defines which shadow casters are rendered by a pass.
+#[derive(Copy, Clone, PartialEq)]
+enum CasterFilter {
+    Static,
+    Dynamic,
+    All,
+}
+
+/// Returns true if shadow of mesh can be cached. Skinned mesh changes its shape even if
+/// its node never moves, cached shadow would keep pose it had when cache was filled.
+fn is_static_caster(mesh: &Mesh) -> bool {
+    mesh.is_static() && !mesh.is_skinned()
+}
+
+impl CasterFilter {
+    fn pass(self, mesh: &Mesh) -> bool {
+        match self {
+            CasterFilter::Static => is_static_caster(mesh),
+            CasterFilter::Dynamic => !is_static_caster(mesh),
+            CasterFilter::All => true,
+        }
+    }
+}
+
+/// Calculates signature of static shadow casters accepted by `in_range` together with
+/// parameters of light. Cached shadow map is valid while signature stays the same. Returns
+/// `None` if there are no static casters in range - there is nothing to cache.
+fn static_casters_signature<F>(graph: &Graph, light_parameters: &[f32], mut in_range: F) -> Option<u64>
+    where F: FnMut(&Mesh) -> bool {
+    let mut hasher = DefaultHasher::new();
+    let mut count = 0;
+
+    for (handle, node) in graph.pair_iter() {
+        if let Node::Mesh(mesh) = node {
+            if is_static_caster(mesh) && node.global_visibility() && mesh.is_cast_shadows() && in_range(mesh) {
+                handle.hash(&mut hasher);
+                for value in mesh.global_transform().f.iter() {
+                    value.to_bits().hash(&mut hasher);
+                }
+                mesh.surfaces().len().hash(&mut hasher);
+                count += 1;
+            }
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    count.hash(&mut hasher);
+    for value in light_parameters {
+        value.to_bits().hash(&mut hasher);
+    }
+
+    Some(hasher.finish())
+}
+
+fn make_depth_texture(state: &mut State, size: usize) -> Result<GpuTexture, RendererError> {
+    let kind = GpuTextureKind::Rectangle { width: size, height: size };
+    let mut texture = GpuTexture::new(state, kind, PixelKind::D32, None)?;
+    texture.bind_mut(state, 0)
+        .set_magnification_filter(MagnificationFilter::Linear)
+        .set_minification_filter(MininificationFilter::Linear)
+        .set_wrap(Coordinate::T, WrapMode::ClampToBorder)
+        .set_wrap(Coordinate::S, WrapMode::ClampToBorder)
+        .set_border_color(Color::WHITE);
+    Ok(texture)
+}
+
+/// Shadow map of static casters of a light.
+struct StaticShadowCache {
+    framebuffer: FrameBuffer,
+    signature: Option<u64>,
+}
+
 pub struct SpotShadowMapRenderer {
     shader: SpotShadowMapShader,
     framebuffer: FrameBuffer,
     bone_matrices: Vec<Mat4>,
+    bone_matrix_storage: MatrixStorage,
+    static_caches: HashMap<Handle<Node>, StaticShadowCache>,
     pub size: usize,
 }
 
+pub struct SpotShadowMapRenderContext<'a, 'c> {
+    pub state: &'a mut State,
+    pub graph: &'c Graph,
+    pub light_handle: Handle<Node>,
+    pub light_view_projection: &'c Mat4,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub textures: &'a mut TextureCache,
+    pub geom_map: &'a mut GeometryCache,
+}
+
 impl SpotShadowMapRenderer {
     pub fn new(state: &mut State, size: usize) -> Result<Self, RendererError> {
-        let depth = {
-            let kind = GpuTextureKind::Rectangle { width: size, height: size };
-            let mut texture = GpuTexture::new(state, kind, PixelKind::D32, None)?;
-            texture.bind_mut(state, 0)
-                .set_magnification_filter(MagnificationFilter::Linear)
-                .set_minification_filter(MininificationFilter::Linear)
-                .set_wrap(Coordinate::T, WrapMode::ClampToBorder)
-                .set_wrap(Coordinate::S, WrapMode::ClampToBorder)
-                .set_border_color(Color::WHITE);
-            texture
-        };
+        let depth = make_depth_texture(state, size)?;
 
         let framebuffer = FrameBuffer::new(
             state,
@@ -109,6 +204,8 @@ impl SpotShadowMapRenderer {
             framebuffer,
             shader: SpotShadowMapShader::new()?,
             bone_matrices: Vec::new(),
+            bone_matrix_storage: MatrixStorage::new(state)?,
+            static_caches: Default::default(),
         })
     }
 
@@ -116,96 +213,167 @@ impl SpotShadowMapRenderer {
         self.framebuffer.depth_attachment().unwrap().texture.clone()
     }
 
-    pub fn render(&mut self,
-                  state: &mut State,
-                  graph: &Graph,
-                  light_view_projection: &Mat4,
-                  white_dummy: Rc<RefCell<GpuTexture>>,
-                  textures: &mut TextureCache,
-                  geom_map: &mut GeometryCache,
-    ) -> RenderPassStatistics {
+    pub fn render(&mut self, args: SpotShadowMapRenderContext) -> Result<RenderPassStatistics, RendererError> {
         scope_profile!();
 
         let mut statistics = RenderPassStatistics::default();
 
+        let SpotShadowMapRenderContext {
+            state, graph, light_handle, light_view_projection,
+            white_dummy, textures, geom_map
+        } = args;
+
         let viewport = Rect::new(0, 0, self.size as i32, self.size as i32);
 
-        self.framebuffer.clear(state, viewport, None, Some(1.0), None);
         let frustum = Frustum::from(*light_view_projection).unwrap();
 
-        for node in graph.linear_iter() {
-            if let Node::Mesh(mesh) = node {
-                if !node.global_visibility() {
-                    continue;
-                }
+        // Caches of removed lights are useless.
+        self.static_caches.retain(|handle, _| graph.is_valid_handle(*handle));
 
-                let global_transform = node.global_transform();
+        let signature = static_casters_signature(graph, &light_view_projection.f, |mesh| {
+            mesh.is_intersect_frustum(graph, &frustum)
+        });
 
-                if !mesh.is_intersect_frustum(graph, &frustum) {
-                    continue;
+        let filter = if signature.is_some() {
+            let cache = match self.static_caches.get_mut(&light_handle) {
+                Some(cache) => cache,
+                None => {
+                    let depth = make_depth_texture(state, self.size)?;
+                    let framebuffer = FrameBuffer::new(
+                        state,
+                        Some(Attachment {
+                            kind: AttachmentKind::Depth,
+                            texture: Rc::new(RefCell::new(depth)),
+                        }),
+                        vec![])?;
+                    self.static_caches.entry(light_handle).or_insert(StaticShadowCache {
+                        framebuffer,
+                        signature: None,
+                    })
                 }
+            };
+
+            if cache.signature != signature {
+                cache.framebuffer.clear(state, viewport, None, Some(1.0), None);
+                statistics += render_spot_casters(
+                    &mut cache.framebuffer, viewport, &self.shader, &mut self.bone_matrices,
+                    &mut self.bone_matrix_storage, state, graph, light_view_projection, &frustum,
+                    white_dummy.clone(), textures, geom_map, CasterFilter::Static,
+                )?;
+                cache.signature = signature;
+            }
 
-                for surface in mesh.surfaces().iter() {
-                    let is_skinned = !surface.bones.is_empty();
+            cache.framebuffer.blit_to(state, &mut self.framebuffer, viewport, false, true);
 
-                    let world = if is_skinned {
-                        Mat4::IDENTITY
-                    } else {
-                        global_transform
-                    };
-                    let mvp = *light_view_projection * world;
-
-                    let diffuse_texture = if let Some(texture) = surface.get_diffuse_texture() {
-                        if let Some(texture) = textures.get(state, texture) {
-                            texture
-                        } else {
-                            white_dummy.clone()
-                        }
+            CasterFilter::Dynamic
+        } else {
+            self.static_caches.remove(&light_handle);
+            self.framebuffer.clear(state, viewport, None, Some(1.0), None);
+
+            CasterFilter::All
+        };
+
+        statistics += render_spot_casters(
+            &mut self.framebuffer, viewport, &self.shader, &mut self.bone_matrices,
+            &mut self.bone_matrix_storage, state, graph, light_view_projection, &frustum,
+            white_dummy, textures, geom_map, filter,
+        )?;
+
+        Ok(statistics)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_spot_casters(framebuffer: &mut FrameBuffer,
+                       viewport: Rect<i32>,
+                       shader: &SpotShadowMapShader,
+                       bone_matrices: &mut Vec<Mat4>,
+                       bone_matrix_storage: &mut MatrixStorage,
+                       state: &mut State,
+                       graph: &Graph,
+                       light_view_projection: &Mat4,
+                       frustum: &Frustum,
+                       white_dummy: Rc<RefCell<GpuTexture>>,
+                       textures: &mut TextureCache,
+                       geom_map: &mut GeometryCache,
+                       filter: CasterFilter,
+) -> Result<RenderPassStatistics, RendererError> {
+    let mut statistics = RenderPassStatistics::default();
+
+    for node in graph.linear_iter() {
+        if let Node::Mesh(mesh) = node {
+            if !node.global_visibility() || !mesh.is_cast_shadows() || !filter.pass(mesh) {
+                continue;
+            }
+
+            let global_transform = node.global_transform();
+
+            if !mesh.is_intersect_frustum(graph, frustum) {
+                continue;
+            }
+
+            for surface in mesh.surfaces().iter() {
+                let is_skinned = !surface.bones.is_empty();
+
+                let world = if is_skinned {
+                    Mat4::IDENTITY
+                } else {
+                    global_transform
+                };
+                let mvp = *light_view_projection * world;
+
+                // Opaque surfaces are rendered with white texture, so nothing is discarded.
+                let alpha_texture = surface.get_diffuse_texture()
+                    .filter(|_| surface.blend_mode() == BlendMode::AlphaTest);
+                let diffuse_texture = if let Some(texture) = alpha_texture {
+                    if let Some(texture) = textures.get(state, texture) {
+                        texture
                     } else {
                         white_dummy.clone()
-                    };
+                    }
+                } else {
+                    white_dummy.clone()
+                };
 
-                    statistics += self.framebuffer.draw(
-                        geom_map.get(state, &surface.get_data().lock().unwrap()),
-                        state,
-                        viewport,
-                        &self.shader.program,
-                        DrawParameters {
-                            cull_face: CullFace::Back,
-                            culling: true,
-                            color_write: ColorMask::all(false),
-                            depth_write: true,
-                            stencil_test: false,
-                            depth_test: true,
-                            blend: false,
-                        },
-                        &[
-                            (self.shader.world_view_projection_matrix, UniformValue::Mat4(mvp)),
-                            (self.shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
-                            (self.shader.bone_matrices, UniformValue::Mat4Array({
-                                self.bone_matrices.clear();
-
-                                for &bone_handle in surface.bones.iter() {
-                                    let bone = &graph[bone_handle];
-                                    self.bone_matrices.push(
-                                        bone.global_transform() *
-                                            bone.inv_bind_pose_transform());
-                                }
-
-                                &self.bone_matrices
-                            })),
-                            (self.shader.diffuse_texture, UniformValue::Sampler {
-                                index: 0,
-                                texture: diffuse_texture,
-                            })
-                        ],
-                    );
+                if is_skinned {
+                    surface.fill_bone_matrices(graph, bone_matrices);
+                    bone_matrix_storage.upload(state, bone_matrices)?;
                 }
+
+                let (cull_face, culling) = surface.cull_mode().cull_face();
+                statistics += framebuffer.draw(
+                    geom_map.get(state, &surface.get_data().lock().unwrap()),
+                    state,
+                    viewport,
+                    &shader.program,
+                    DrawParameters {
+                        cull_face,
+                        culling,
+                        color_write: ColorMask::all(false),
+                        depth_write: true,
+                        stencil_test: false,
+                        depth_test: true,
+                        blend: false,
+                    },
+                    &[
+                        (shader.world_view_projection_matrix, UniformValue::Mat4(mvp)),
+                        (shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
+                        (shader.bone_matrices, UniformValue::Sampler {
+                            index: 1,
+                            texture: bone_matrix_storage.texture(),
+                        }),
+                        (shader.diffuse_texture, UniformValue::Sampler {
+                            index: 0,
+                            texture: diffuse_texture,
+                        }),
+                        (shader.alpha_cutoff, UniformValue::Float(surface.alpha_cutoff())),
+                    ],
+                );
             }
         }
-
-        statistics
     }
+
+    Ok(statistics)
 }
 
 struct PointShadowMapShader {
@@ -215,6 +383,7 @@ struct PointShadowMapShader {
     world_view_projection_matrix: UniformLocation,
     use_skeletal_animation: UniformLocation,
     diffuse_texture: UniformLocation,
+    alpha_cutoff: UniformLocation,
     light_position: UniformLocation,
 }
 
@@ -229,6 +398,7 @@ impl PointShadowMapShader {
             world_view_projection_matrix: program.uniform_location("worldViewProjection")?,
             use_skeletal_animation: program.uniform_location("useSkeletalAnimation")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
+            alpha_cutoff: program.uniform_location("alphaCutoff")?,
             light_position: program.uniform_location("lightPosition")?,
             program,
         })
@@ -237,8 +407,12 @@ impl PointShadowMapShader {
 
 pub struct PointShadowMapRenderer {
     bone_matrices: Vec<Mat4>,
+    bone_matrix_storage: MatrixStorage,
     shader: PointShadowMapShader,
     framebuffer: FrameBuffer,
+    /// Each face of cached map is separate framebuffer, because faces must have their own
+    /// depth to let dynamic casters be correctly depth tested against static ones.
+    static_caches: HashMap<Handle<Node>, Vec<StaticShadowCache>>,
     pub size: usize,
 }
 
@@ -251,6 +425,7 @@ struct PointShadowCubeMapFace {
 pub struct PointShadowMapRenderContext<'a, 'c> {
     pub state: &'a mut State,
     pub graph: &'c Graph,
+    pub light_handle: Handle<Node>,
     pub white_dummy: Rc<RefCell<GpuTexture>>,
     pub light_pos: Vec3,
     pub light_radius: f32,
@@ -258,6 +433,45 @@ pub struct PointShadowMapRenderContext<'a, 'c> {
     pub geom_cache: &'a mut GeometryCache,
 }
 
+fn make_point_depth_texture(state: &mut State, size: usize) -> Result<GpuTexture, RendererError> {
+    let kind = GpuTextureKind::Rectangle { width: size, height: size };
+    let mut texture = GpuTexture::new(state, kind, PixelKind::D32, None)?;
+    texture.bind_mut(state, 0)
+        .set_minification_filter(MininificationFilter::Nearest)
+        .set_magnification_filter(MagnificationFilter::Nearest)
+        .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
+        .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
+    Ok(texture)
+}
+
+fn make_point_face_cache(state: &mut State, size: usize) -> Result<StaticShadowCache, RendererError> {
+    let depth = make_point_depth_texture(state, size)?;
+    let distance = {
+        let kind = GpuTextureKind::Rectangle { width: size, height: size };
+        let mut texture = GpuTexture::new(state, kind, PixelKind::F32, None)?;
+        texture.bind_mut(state, 0)
+            .set_minification_filter(MininificationFilter::Nearest)
+            .set_magnification_filter(MagnificationFilter::Nearest);
+        texture
+    };
+
+    Ok(StaticShadowCache {
+        framebuffer: FrameBuffer::new(
+            state,
+            Some(Attachment {
+                kind: AttachmentKind::Depth,
+                texture: Rc::new(RefCell::new(depth)),
+            }),
+            vec![
+                Attachment {
+                    kind: AttachmentKind::Color,
+                    texture: Rc::new(RefCell::new(distance)),
+                }
+            ])?,
+        signature: None,
+    })
+}
+
 impl PointShadowMapRenderer {
     const FACES: [PointShadowCubeMapFace; 6] = [
         PointShadowCubeMapFace {
@@ -293,16 +507,7 @@ impl PointShadowMapRenderer {
     ];
 
     pub fn new(state: &mut State, size: usize) -> Result<PointShadowMapRenderer, RendererError> {
-        let depth = {
-            let kind = GpuTextureKind::Rectangle { width: size, height: size };
-            let mut texture = GpuTexture::new(state, kind, PixelKind::D32, None)?;
-            texture.bind_mut(state, 0)
-                .set_minification_filter(MininificationFilter::Nearest)
-                .set_magnification_filter(MagnificationFilter::Nearest)
-                .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
-                .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
-            texture
-        };
+        let depth = make_point_depth_texture(state, size)?;
 
         let cube_map = {
             let kind = GpuTextureKind::Cube { width: size, height: size };
@@ -333,7 +538,9 @@ impl PointShadowMapRenderer {
             framebuffer,
             size,
             bone_matrices: Vec::new(),
+            bone_matrix_storage: MatrixStorage::new(state)?,
             shader: PointShadowMapShader::new()?,
+            static_caches: Default::default(),
         })
     }
 
@@ -341,105 +548,175 @@ impl PointShadowMapRenderer {
         self.framebuffer.color_attachments()[0].texture.clone()
     }
 
-    pub fn render(&mut self, args: PointShadowMapRenderContext) -> RenderPassStatistics {
+    pub fn render(&mut self, args: PointShadowMapRenderContext) -> Result<RenderPassStatistics, RendererError> {
         scope_profile!();
 
         let mut statistics = RenderPassStatistics::default();
 
         let PointShadowMapRenderContext {
-            state, graph, white_dummy
-            , light_pos, light_radius, texture_cache, geom_cache
+            state, graph, light_handle, white_dummy,
+            light_pos, light_radius, texture_cache, geom_cache
         } = args;
 
         let viewport = Rect::new(0, 0, self.size as i32, self.size as i32);
 
         let light_projection_matrix = Mat4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 0.01, light_radius);
 
-        for face in Self::FACES.iter() {
-            self.framebuffer
-                .set_cubemap_face(state, 0, face.face)
-                .clear(state, viewport, Some(Color::WHITE), Some(1.0), None);
+        // Caches of removed lights are useless.
+        self.static_caches.retain(|handle, _| graph.is_valid_handle(*handle));
+
+        let signature = static_casters_signature(graph, &[light_pos.x, light_pos.y, light_pos.z, light_radius], |mesh| {
+            mesh.is_intersect_sphere(light_pos, light_radius)
+        });
 
+        if signature.is_some() {
+            if !self.static_caches.contains_key(&light_handle) {
+                let mut faces = Vec::with_capacity(Self::FACES.len());
+                for _ in Self::FACES.iter() {
+                    faces.push(make_point_face_cache(state, self.size)?);
+                }
+                self.static_caches.insert(light_handle, faces);
+            }
+        } else {
+            self.static_caches.remove(&light_handle);
+        }
+
+        for (face_index, face) in Self::FACES.iter().enumerate() {
             let light_look_at = light_pos + face.look;
             let light_view_matrix = Mat4::look_at(light_pos, light_look_at, face.up).unwrap_or_default();
             let light_view_projection_matrix = light_projection_matrix * light_view_matrix;
 
             let frustum = Frustum::from(light_view_projection_matrix).unwrap();
 
-            for node in graph.linear_iter() {
-                if let Node::Mesh(mesh) = node {
-                    if !node.global_visibility() {
-                        continue;
-                    }
+            self.framebuffer.set_cubemap_face(state, 0, face.face);
 
-                    let global_transform = node.global_transform();
+            let filter = if let Some(faces) = self.static_caches.get_mut(&light_handle) {
+                let cache = &mut faces[face_index];
 
-                    if !mesh.is_intersect_frustum(graph, &frustum) {
-                        continue;
-                    }
+                if cache.signature != signature {
+                    cache.framebuffer.clear(state, viewport, Some(Color::WHITE), Some(1.0), None);
+                    statistics += render_point_casters(
+                        &mut cache.framebuffer, viewport, &self.shader, &mut self.bone_matrices,
+                        &mut self.bone_matrix_storage, state, graph, light_pos,
+                        &light_view_projection_matrix, &frustum, white_dummy.clone(),
+                        texture_cache, geom_cache, CasterFilter::Static,
+                    )?;
+                    cache.signature = signature;
+                }
+
+                cache.framebuffer.blit_to(state, &mut self.framebuffer, viewport, true, true);
+
+                CasterFilter::Dynamic
+            } else {
+                self.framebuffer.clear(state, viewport, Some(Color::WHITE), Some(1.0), None);
+
+                CasterFilter::All
+            };
+
+            statistics += render_point_casters(
+                &mut self.framebuffer, viewport, &self.shader, &mut self.bone_matrices,
+                &mut self.bone_matrix_storage, state, graph, light_pos,
+                &light_view_projection_matrix, &frustum, white_dummy.clone(),
+                texture_cache, geom_cache, filter,
+            )?;
+        }
+
+        Ok(statistics)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_point_casters(framebuffer: &mut FrameBuffer,
+                        viewport: Rect<i32>,
+                        shader: &PointShadowMapShader,
+                        bone_matrices: &mut Vec<Mat4>,
+                        bone_matrix_storage: &mut MatrixStorage,
+                        state: &mut State,
+                        graph: &Graph,
+                        light_pos: Vec3,
+                        light_view_projection_matrix: &Mat4,
+                        frustum: &Frustum,
+                        white_dummy: Rc<RefCell<GpuTexture>>,
+                        texture_cache: &mut TextureCache,
+                        geom_cache: &mut GeometryCache,
+                        filter: CasterFilter,
+) -> Result<RenderPassStatistics, RendererError> {
+    let mut statistics = RenderPassStatistics::default();
+
+    for node in graph.linear_iter() {
+        if let Node::Mesh(mesh) = node {
+            if !node.global_visibility() || !mesh.is_cast_shadows() || !filter.pass(mesh) {
+                continue;
+            }
+
+            let global_transform = node.global_transform();
+
+            if !mesh.is_intersect_frustum(graph, frustum) {
+                continue;
+            }
 
-                    for surface in mesh.surfaces().iter() {
-                        let is_skinned = !surface.bones.is_empty();
-
-                        let world = if is_skinned {
-                            Mat4::IDENTITY
-                        } else {
-                            global_transform
-                        };
-                        let mvp = light_view_projection_matrix * world;
-
-                        let diffuse_texture = if let Some(texture) = surface.get_diffuse_texture() {
-                            if let Some(texture) = texture_cache.get(state, texture) {
-                                texture
-                            } else {
-                                white_dummy.clone()
-                            }
-                        } else {
-                            white_dummy.clone()
-                        };
-
-                        statistics += self.framebuffer.draw(
-                            geom_cache.get(state, &surface.get_data().lock().unwrap()),
-                            state,
-                            viewport,
-                            &self.shader.program,
-                            DrawParameters {
-                                cull_face: CullFace::Back,
-                                culling: true,
-                                color_write: Default::default(),
-                                depth_write: true,
-                                stencil_test: false,
-                                depth_test: true,
-                                blend: false,
-                            },
-                            &[
-                                (self.shader.light_position, UniformValue::Vec3(light_pos)),
-                                (self.shader.world_matrix, UniformValue::Mat4(world)),
-                                (self.shader.world_view_projection_matrix, UniformValue::Mat4(mvp)),
-                                (self.shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
-                                (self.shader.bone_matrices, UniformValue::Mat4Array({
-                                    self.bone_matrices.clear();
-
-                                    for &bone_handle in surface.bones.iter() {
-                                        let bone = &graph[bone_handle];
-                                        self.bone_matrices.push(
-                                            bone.global_transform() *
-                                                bone.inv_bind_pose_transform());
-                                    }
-
-                                    &self.bone_matrices
-                                })),
-                                (self.shader.diffuse_texture, UniformValue::Sampler {
-                                    index: 0,
-                                    texture: diffuse_texture,
-                                })
-                            ],
-                        );
+            for surface in mesh.surfaces().iter() {
+                let is_skinned = !surface.bones.is_empty();
+
+                let world = if is_skinned {
+                    Mat4::IDENTITY
+                } else {
+                    global_transform
+                };
+                let mvp = *light_view_projection_matrix * world;
+
+                // Opaque surfaces are rendered with white texture, so nothing is discarded.
+                let alpha_texture = surface.get_diffuse_texture()
+                    .filter(|_| surface.blend_mode() == BlendMode::AlphaTest);
+                let diffuse_texture = if let Some(texture) = alpha_texture {
+                    if let Some(texture) = texture_cache.get(state, texture) {
+                        texture
+                    } else {
+                        white_dummy.clone()
                     }
+                } else {
+                    white_dummy.clone()
+                };
+
+                if is_skinned {
+                    surface.fill_bone_matrices(graph, bone_matrices);
+                    bone_matrix_storage.upload(state, bone_matrices)?;
                 }
+
+                let (cull_face, culling) = surface.cull_mode().cull_face();
+                statistics += framebuffer.draw(
+                    geom_cache.get(state, &surface.get_data().lock().unwrap()),
+                    state,
+                    viewport,
+                    &shader.program,
+                    DrawParameters {
+                        cull_face,
+                        culling,
+                        color_write: Default::default(),
+                        depth_write: true,
+                        stencil_test: false,
+                        depth_test: true,
+                        blend: false,
+                    },
+                    &[
+                        (shader.light_position, UniformValue::Vec3(light_pos)),
+                        (shader.world_matrix, UniformValue::Mat4(world)),
+                        (shader.world_view_projection_matrix, UniformValue::Mat4(mvp)),
+                        (shader.use_skeletal_animation, UniformValue::Bool(is_skinned)),
+                        (shader.bone_matrices, UniformValue::Sampler {
+                            index: 1,
+                            texture: bone_matrix_storage.texture(),
+                        }),
+                        (shader.diffuse_texture, UniformValue::Sampler {
+                            index: 0,
+                            texture: diffuse_texture,
+                        }),
+                        (shader.alpha_cutoff, UniformValue::Float(surface.alpha_cutoff())),
+                    ],
+                );
             }
         }
-
-        statistics
     }
+
+    Ok(statistics)
 }
\ No newline at end of file
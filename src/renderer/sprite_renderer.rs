@@ -1,17 +1,21 @@
 use crate::{
+    resource::texture::Texture,
     scene::{
-        node::Node,
-        graph::Graph,
+        sprite::Sprite,
         camera::Camera,
     },
     core::{
         scope_profile,
-        math::Rect,
+        math::{
+            Rect,
+            vec2::Vec2,
+            vec3::Vec3,
+        },
+        color::Color,
     },
     renderer::{
         TextureCache,
-        GeometryCache,
-        surface::SurfaceSharedData,
+        TriangleDefinition,
         error::RendererError,
         framework::{
             gpu_texture::GpuTexture,
@@ -21,9 +25,17 @@ use crate::{
                 GpuProgram,
                 UniformLocation,
             },
+            geometry_buffer::{
+                GeometryBuffer,
+                DynamicBufferRing,
+                AttributeDefinition,
+                AttributeKind,
+                ElementKind,
+            },
             framebuffer::{
                 FrameBuffer,
                 DrawParameters,
+                DrawPartContext,
                 CullFace,
                 FrameBufferTrait,
             },
@@ -35,18 +47,15 @@ use crate::{
 use std::{
     rc::Rc,
     cell::RefCell,
+    sync::{Arc, Mutex},
 };
 
 struct SpriteShader {
     program: GpuProgram,
     view_projection_matrix: UniformLocation,
-    world_matrix: UniformLocation,
     camera_side_vector: UniformLocation,
     camera_up_vector: UniformLocation,
-    color: UniformLocation,
     diffuse_texture: UniformLocation,
-    size: UniformLocation,
-    rotation: UniformLocation,
 }
 
 impl SpriteShader {
@@ -56,56 +65,152 @@ impl SpriteShader {
         let program = GpuProgram::from_source("FlatShader", vertex_source, fragment_source)?;
         Ok(Self {
             view_projection_matrix: program.uniform_location("viewProjectionMatrix")?,
-            world_matrix: program.uniform_location("worldMatrix")?,
             camera_side_vector: program.uniform_location("cameraSideVector")?,
             camera_up_vector: program.uniform_location("cameraUpVector")?,
-            size: program.uniform_location("size")?,
             diffuse_texture: program.uniform_location("diffuseTexture")?,
-            color: program.uniform_location("color")?,
-            rotation: program.uniform_location("rotation")?,
             program,
         })
     }
 }
 
+/// OpenGL expects this structure packed as in C.
+#[repr(C)]
+struct SpriteVertex {
+    /// Position of sprite center in world space, same for each corner of sprite.
+    position: Vec3,
+    tex_coord: Vec2,
+    /// Corner of quad in [0; 1] range, shader uses it to calculate offset from center.
+    corner: Vec2,
+    size: f32,
+    rotation: f32,
+    color: Color,
+}
+
+/// Range of triangles which can be drawn with one texture and same render state.
+struct Batch {
+    texture: Option<Arc<Mutex<Texture>>>,
+    texture_key: usize,
+    depth_test: bool,
+    start_triangle: usize,
+    triangle_count: usize,
+}
+
 pub struct SpriteRenderer {
     shader: SpriteShader,
-    surface: SurfaceSharedData,
+    geometry_buffer: GeometryBuffer<SpriteVertex>,
+    vertices: Vec<SpriteVertex>,
+    triangles: Vec<TriangleDefinition>,
+    batches: Vec<Batch>,
 }
 
 pub struct SpriteRenderContext<'a, 'b, 'c> {
     pub state: &'a mut State,
     pub framebuffer: &'b mut FrameBuffer,
-    pub graph: &'c Graph,
+    /// Sprites in order of drawing.
+    pub sprites: &'c [&'c Sprite],
     pub camera: &'c Camera,
     pub white_dummy: Rc<RefCell<GpuTexture>>,
     pub viewport: Rect<i32>,
     pub textures: &'a mut TextureCache,
-    pub geom_map: &'a mut GeometryCache,
 }
 
+const CORNERS: [Vec2; 4] = [
+    Vec2 { x: 0.0, y: 0.0 },
+    Vec2 { x: 1.0, y: 0.0 },
+    Vec2 { x: 1.0, y: 1.0 },
+    Vec2 { x: 0.0, y: 1.0 },
+];
+
 impl SpriteRenderer {
-    pub fn new() -> Result<Self, RendererError> {
-        let surface = SurfaceSharedData::make_collapsed_xy_quad();
+    pub fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry_buffer = GeometryBuffer::new_in_ring(state, ring, ElementKind::Triangle);
+
+        geometry_buffer.bind(state)
+            .describe_attributes(vec![
+                AttributeDefinition { kind: AttributeKind::Float3, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float, normalized: false },
+                AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: true },
+            ])?;
 
         Ok(Self {
             shader: SpriteShader::new()?,
-            surface,
+            geometry_buffer,
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            batches: Vec::new(),
         })
     }
 
-    #[must_use]
-    pub fn render(&mut self, args: SpriteRenderContext) -> RenderPassStatistics {
+    /// Draws given sprites in given order. Consecutive sprites with same texture (or same
+    /// atlas) and render state form contiguous range of triangles which is drawn in one
+    /// draw call.
+    pub fn render(&mut self, args: SpriteRenderContext) -> Result<RenderPassStatistics, RendererError> {
         scope_profile!();
 
         let mut statistics = RenderPassStatistics::default();
 
         let SpriteRenderContext {
-            state, framebuffer, graph,
+            state, framebuffer, sprites,
             camera, white_dummy, viewport,
-            textures, geom_map
+            textures,
         } = args;
 
+        if sprites.is_empty() {
+            return Ok(statistics);
+        }
+
+        let camera_position = camera.global_position();
+
+        self.vertices.clear();
+        self.triangles.clear();
+        self.batches.clear();
+
+        for &sprite in sprites {
+            let texture_key = sprite.texture()
+                .map_or(0, |texture| (&*texture as *const _) as usize);
+            let start_triangle = self.triangles.len();
+            match self.batches.last_mut() {
+                Some(batch) if batch.texture_key == texture_key
+                    && batch.depth_test == sprite.is_depth_test_enabled() => batch.triangle_count += 2,
+                _ => self.batches.push(Batch {
+                    texture: sprite.texture(),
+                    texture_key,
+                    depth_test: sprite.is_depth_test_enabled(),
+                    start_triangle,
+                    triangle_count: 2,
+                })
+            }
+
+            let mut position = sprite.global_position();
+            if sprite.depth_bias() != 0.0 {
+                if let Some(to_camera) = (camera_position - position).normalized() {
+                    position += to_camera.scale(sprite.depth_bias());
+                }
+            }
+            let uv_rect = sprite.uv_rect();
+            let base = self.vertices.len() as u32;
+            for corner in CORNERS.iter() {
+                self.vertices.push(SpriteVertex {
+                    position,
+                    tex_coord: Vec2::new(uv_rect.x + corner.x * uv_rect.w, uv_rect.y + corner.y * uv_rect.h),
+                    corner: *corner,
+                    size: sprite.size(),
+                    rotation: sprite.rotation(),
+                    color: sprite.color(),
+                });
+            }
+            self.triangles.push(TriangleDefinition([base, base + 1, base + 2]));
+            self.triangles.push(TriangleDefinition([base, base + 2, base + 3]));
+        }
+
+        self.geometry_buffer
+            .bind(state)
+            .set_triangles(&self.triangles)
+            .set_vertices(&self.vertices);
+
         state.set_blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
 
         let inv_view = camera.inv_view_matrix().unwrap();
@@ -113,53 +218,42 @@ impl SpriteRenderer {
         let camera_up = inv_view.up();
         let camera_side = inv_view.side();
 
-        for node in graph.linear_iter() {
-            let sprite = if let Node::Sprite(sprite) = node {
-                sprite
-            } else {
-                continue;
-            };
-
-            let diffuse_texture = if let Some(texture) = sprite.texture() {
-                if let Some(texture) = textures.get(state, texture) {
-                    texture
-                } else {
-                    white_dummy.clone()
+        for batch in self.batches.iter() {
+            let diffuse_texture = batch.texture
+                .clone()
+                .and_then(|texture| textures.get(state, texture))
+                .unwrap_or_else(|| white_dummy.clone());
+
+            statistics += framebuffer.draw_part(
+                DrawPartContext {
+                    state,
+                    viewport,
+                    geometry: &mut self.geometry_buffer,
+                    program: &mut self.shader.program,
+                    params: DrawParameters {
+                        cull_face: CullFace::Back,
+                        culling: true,
+                        color_write: Default::default(),
+                        depth_write: false,
+                        stencil_test: false,
+                        depth_test: batch.depth_test,
+                        blend: true,
+                    },
+                    uniforms: &[
+                        (self.shader.diffuse_texture, UniformValue::Sampler {
+                            index: 0,
+                            texture: diffuse_texture,
+                        }),
+                        (self.shader.view_projection_matrix, UniformValue::Mat4(camera.view_projection_matrix())),
+                        (self.shader.camera_up_vector, UniformValue::Vec3(camera_up)),
+                        (self.shader.camera_side_vector, UniformValue::Vec3(camera_side)),
+                    ],
+                    offset: batch.start_triangle,
+                    count: batch.triangle_count,
                 }
-            } else {
-                white_dummy.clone()
-            };
-
-            statistics += framebuffer.draw(
-                geom_map.get(state, &self.surface),
-                state,
-                viewport,
-                &self.shader.program,
-                DrawParameters {
-                    cull_face: CullFace::Back,
-                    culling: true,
-                    color_write: Default::default(),
-                    depth_write: false,
-                    stencil_test: false,
-                    depth_test: true,
-                    blend: true,
-                },
-                &[
-                    (self.shader.diffuse_texture, UniformValue::Sampler {
-                        index: 0,
-                        texture: diffuse_texture,
-                    }),
-                    (self.shader.view_projection_matrix, UniformValue::Mat4(camera.view_projection_matrix())),
-                    (self.shader.world_matrix, UniformValue::Mat4(node.global_transform())),
-                    (self.shader.camera_up_vector, UniformValue::Vec3(camera_up)),
-                    (self.shader.camera_side_vector, UniformValue::Vec3(camera_side)),
-                    (self.shader.size, UniformValue::Float(sprite.size())),
-                    (self.shader.color, UniformValue::Color(sprite.color())),
-                    (self.shader.rotation, UniformValue::Float(sprite.rotation()))
-                ],
-            );
+            )?;
         }
 
-        statistics
+        Ok(statistics)
     }
-}
\ No newline at end of file
+}
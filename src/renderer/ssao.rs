@@ -2,8 +2,8 @@ use std::{
     cell::RefCell,
     rc::Rc,
 };
-use rand::Rng;
 use crate::{
+    utils::random::RandomGenerator,
     renderer::{
         surface::SurfaceSharedData,
         gbuffer::GBuffer,
@@ -115,7 +115,8 @@ impl ScreenSpaceAmbientOcclusionRenderer {
             texture
         };
 
-        let mut rng = rand::thread_rng();
+        // Fixed seed makes kernel and noise the same on every run.
+        let mut rng = RandomGenerator::new(KERNEL_SIZE as u64);
 
         Ok(Self {
             blur: Blur::new(state, width, height)?,
@@ -138,14 +139,14 @@ impl ScreenSpaceAmbientOcclusionRenderer {
                     let k = i as f32 / KERNEL_SIZE as f32;
                     let scale = lerpf(0.1, 1.0, k * k);
                     *v = Vec3::new(
-                        rng.gen_range(-1.0, 1.0),
-                        rng.gen_range(-1.0, 1.0),
-                        rng.gen_range(0.0, 1.0))
+                        rng.range(-1.0, 1.0),
+                        rng.range(-1.0, 1.0),
+                        rng.range(0.0, 1.0))
                         // Make sphere
                         .normalized()
                         .unwrap()
                         // Use non-uniform distribution to shuffle points inside hemisphere.
-                        .scale(scale * rng.gen_range(0.0, 1.0));
+                        .scale(scale * rng.unit());
                 }
                 kernel
             },
@@ -153,8 +154,8 @@ impl ScreenSpaceAmbientOcclusionRenderer {
                 const RGB_PIXEL_SIZE: usize = 3;
                 let mut pixels = [0; RGB_PIXEL_SIZE * NOISE_SIZE * NOISE_SIZE];
                 for pixel in pixels.chunks_exact_mut(RGB_PIXEL_SIZE) {
-                    pixel[0] = rng.gen_range(0, 255); // R
-                    pixel[1] = rng.gen_range(0, 255); // G
+                    pixel[0] = rng.range_i32(0, 255) as u8; // R
+                    pixel[1] = rng.range_i32(0, 255) as u8; // G
                     pixel[2] = 0; // B
                 }
                 let kind = GpuTextureKind::Rectangle { width: NOISE_SIZE, height: NOISE_SIZE };
@@ -0,0 +1,240 @@
+//! Screen space reflections.
+//!
+//! Reflections are found by ray marching in view space using depth buffer, color of
+//! reflected object is taken from lit frame. Only objects which are visible on screen
+//! can be reflected, rays which leave screen or go towards camera produce no reflection
+//! and are faded out smoothly. Reflections are blurred by roughness of surface (which
+//! is one minus reflectivity) when they are added to frame.
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+use crate::{
+    renderer::{
+        surface::SurfaceSharedData,
+        gbuffer::GBuffer,
+        GeometryCache,
+        error::RendererError,
+        RenderPassStatistics,
+        framework::{
+            gl,
+            framebuffer::{
+                DrawParameters,
+                CullFace,
+                FrameBuffer,
+                Attachment,
+                AttachmentKind,
+                FrameBufferTrait,
+            },
+            state::State,
+            gpu_texture::{
+                GpuTexture,
+                GpuTextureKind,
+                PixelKind,
+                MininificationFilter,
+                MagnificationFilter,
+                Coordinate,
+                WrapMode,
+            },
+            gpu_program::{
+                GpuProgram,
+                UniformLocation,
+                UniformValue,
+            },
+        },
+    },
+    core::{
+        scope_profile,
+        math::{
+            vec3::Vec3,
+            Rect,
+            mat3::Mat3,
+            mat4::Mat4,
+        },
+        color::Color,
+    },
+};
+
+struct Shader {
+    program: GpuProgram,
+    depth_sampler: UniformLocation,
+    normal_sampler: UniformLocation,
+    frame_sampler: UniformLocation,
+    projection_matrix: UniformLocation,
+    inv_proj_matrix: UniformLocation,
+    view_matrix: UniformLocation,
+    max_distance: UniformLocation,
+    world_view_proj_matrix: UniformLocation,
+}
+
+impl Shader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/ssr_fs.glsl");
+        let vertex_source = include_str!("shaders/ssr_vs.glsl");
+        let program = GpuProgram::from_source("SsrShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            depth_sampler: program.uniform_location("depthSampler")?,
+            normal_sampler: program.uniform_location("normalSampler")?,
+            frame_sampler: program.uniform_location("frameSampler")?,
+            projection_matrix: program.uniform_location("projectionMatrix")?,
+            inv_proj_matrix: program.uniform_location("inverseProjectionMatrix")?,
+            view_matrix: program.uniform_location("viewMatrix")?,
+            max_distance: program.uniform_location("maxDistance")?,
+            world_view_proj_matrix: program.uniform_location("worldViewProjection")?,
+            program,
+        })
+    }
+}
+
+struct CompositeShader {
+    program: GpuProgram,
+    reflection_sampler: UniformLocation,
+    normal_sampler: UniformLocation,
+    world_view_proj_matrix: UniformLocation,
+}
+
+impl CompositeShader {
+    fn new() -> Result<Self, RendererError> {
+        let fragment_source = include_str!("shaders/ssr_composite_fs.glsl");
+        let vertex_source = include_str!("shaders/ssr_composite_vs.glsl");
+        let program = GpuProgram::from_source("SsrCompositeShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            reflection_sampler: program.uniform_location("reflectionSampler")?,
+            normal_sampler: program.uniform_location("normalSampler")?,
+            world_view_proj_matrix: program.uniform_location("worldViewProjection")?,
+            program,
+        })
+    }
+}
+
+pub struct ScreenSpaceReflectionRenderer {
+    shader: Shader,
+    composite_shader: CompositeShader,
+    framebuffer: FrameBuffer,
+    quad: SurfaceSharedData,
+    width: i32,
+    height: i32,
+    max_distance: f32,
+}
+
+impl ScreenSpaceReflectionRenderer {
+    pub fn new(state: &mut State, width: usize, height: usize) -> Result<Self, RendererError> {
+        let reflection = {
+            let kind = GpuTextureKind::Rectangle { width, height };
+            let mut texture = GpuTexture::new(state, kind, PixelKind::RGBA8, None)?;
+            texture.bind_mut(state, 0)
+                .set_minification_filter(MininificationFilter::Linear)
+                .set_magnification_filter(MagnificationFilter::Linear)
+                .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
+                .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
+            texture
+        };
+
+        Ok(Self {
+            shader: Shader::new()?,
+            composite_shader: CompositeShader::new()?,
+            framebuffer: FrameBuffer::new(
+                state,
+                None,
+                vec![
+                    Attachment {
+                        kind: AttachmentKind::Color,
+                        texture: Rc::new(RefCell::new(reflection)),
+                    },
+                ])?,
+            quad: SurfaceSharedData::make_unit_xy_quad(),
+            width: width as i32,
+            height: height as i32,
+            max_distance: 10.0,
+        })
+    }
+
+    /// Sets maximum length of reflected ray in view space.
+    pub fn set_max_distance(&mut self, max_distance: f32) {
+        self.max_distance = max_distance.max(std::f32::EPSILON);
+    }
+
+    pub fn max_distance(&self) -> f32 {
+        self.max_distance
+    }
+
+    fn reflection_map(&self) -> Rc<RefCell<GpuTexture>> {
+        self.framebuffer.color_attachments()[0].texture.clone()
+    }
+
+    /// Traces reflections of lit frame and adds them to final frame of g-buffer.
+    pub fn render(&mut self,
+                  state: &mut State,
+                  gbuffer: &mut GBuffer,
+                  geom_cache: &mut GeometryCache,
+                  projection_matrix: Mat4,
+                  view_matrix: Mat3,
+    ) -> RenderPassStatistics {
+        scope_profile!();
+
+        let mut stats = RenderPassStatistics::default();
+
+        let viewport = Rect::new(0, 0, self.width, self.height);
+
+        let frame_matrix =
+            Mat4::ortho(0.0, viewport.w as f32, viewport.h as f32, 0.0, -1.0, 1.0) *
+                Mat4::scale(Vec3::new(viewport.w as f32, viewport.h as f32, 0.0));
+
+        self.framebuffer.clear(state, viewport, Some(Color::from_rgba(0, 0, 0, 0)), None, None);
+
+        stats += self.framebuffer.draw(
+            geom_cache.get(state, &self.quad),
+            state,
+            viewport,
+            &self.shader.program,
+            DrawParameters {
+                cull_face: CullFace::Back,
+                culling: false,
+                color_write: Default::default(),
+                depth_write: false,
+                stencil_test: false,
+                depth_test: false,
+                blend: false,
+            },
+            &[
+                (self.shader.depth_sampler, UniformValue::Sampler { index: 0, texture: gbuffer.depth() }),
+                (self.shader.normal_sampler, UniformValue::Sampler { index: 1, texture: gbuffer.normal_texture() }),
+                (self.shader.frame_sampler, UniformValue::Sampler { index: 2, texture: gbuffer.frame_texture() }),
+                (self.shader.max_distance, UniformValue::Float(self.max_distance)),
+                (self.shader.world_view_proj_matrix, UniformValue::Mat4(frame_matrix)),
+                (self.shader.projection_matrix, UniformValue::Mat4(projection_matrix)),
+                (self.shader.inv_proj_matrix, UniformValue::Mat4(projection_matrix.inverse().unwrap_or_default())),
+                (self.shader.view_matrix, UniformValue::Mat3(view_matrix))
+            ]);
+
+        let frame_viewport = Rect::new(0, 0, gbuffer.width, gbuffer.height);
+        let frame_matrix =
+            Mat4::ortho(0.0, frame_viewport.w as f32, frame_viewport.h as f32, 0.0, -1.0, 1.0) *
+                Mat4::scale(Vec3::new(frame_viewport.w as f32, frame_viewport.h as f32, 0.0));
+
+        state.set_blend_func(gl::SRC_ALPHA, gl::ONE);
+
+        stats += gbuffer.final_frame.draw(
+            geom_cache.get(state, &self.quad),
+            state,
+            frame_viewport,
+            &self.composite_shader.program,
+            DrawParameters {
+                cull_face: CullFace::Back,
+                culling: false,
+                color_write: Default::default(),
+                depth_write: false,
+                stencil_test: false,
+                depth_test: false,
+                blend: true,
+            },
+            &[
+                (self.composite_shader.reflection_sampler, UniformValue::Sampler { index: 0, texture: self.reflection_map() }),
+                (self.composite_shader.normal_sampler, UniformValue::Sampler { index: 1, texture: gbuffer.normal_texture() }),
+                (self.composite_shader.world_view_proj_matrix, UniformValue::Mat4(frame_matrix)),
+            ]);
+
+        stats
+    }
+}
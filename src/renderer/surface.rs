@@ -1,5 +1,6 @@
 use crate::{
     core::{
+        color::Color,
         math::{
             vec2::Vec2,
             vec3::Vec3,
@@ -11,11 +12,27 @@ use crate::{
             ErasedHandle,
         },
     },
-    scene::node::Node,
-    resource::texture::Texture,
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+    resource::{
+        texture::Texture,
+        texture_array::SharedTextureArray,
+        material::{BlendMode, Parallax, DEFAULT_ALPHA_CUTOFF},
+    },
+    engine::resource_manager::SharedMaterial,
+    renderer::{
+        resource_tracker::{
+            ResourceTracker,
+            SURFACE_DATA_QUEUE,
+        },
+        framework::framebuffer::CullFace,
+    },
     utils::raw_mesh::{
         RawMesh,
         RawMeshBuilder,
+        RawVertex,
     },
 };
 use std::{
@@ -27,6 +44,7 @@ use std::{
         Hash,
         Hasher,
     },
+    collections::HashMap,
 };
 use rg3d_core::math::mat4::Mat4;
 
@@ -39,6 +57,10 @@ pub struct Vertex {
     pub tangent: Vec4,
     pub bone_weights: [f32; 4],
     pub bone_indices: [u8; 4],
+    /// Second texture coordinates, usually used for lightmaps. See `utils::uvgen`.
+    pub second_tex_coord: Vec2,
+    /// Color of vertex, it is multiplied with diffuse texture. White by default.
+    pub color: Color,
 }
 
 impl Vertex {
@@ -50,8 +72,45 @@ impl Vertex {
             tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
             bone_weights: [0.0, 0.0, 0.0, 0.0],
             bone_indices: Default::default(),
+            second_tex_coord: Vec2::ZERO,
+            color: Color::WHITE,
         }
     }
+
+    /// Calculates position of vertex after skinning, using the same formula as vertex
+    /// shader does. `bone_matrices` must be in the same order as bones of surface, see
+    /// [`Surface::fill_bone_matrices`]. Position of vertex which is not affected by any
+    /// bone is returned as is.
+    pub fn skinned_position(&self, bone_matrices: &[Mat4]) -> Vec3 {
+        let mut position = Vec3::ZERO;
+        let mut total_weight = 0.0;
+        for (&index, &weight) in self.bone_indices.iter().zip(self.bone_weights.iter()) {
+            if weight > 0.0 {
+                if let Some(matrix) = bone_matrices.get(index as usize) {
+                    position += matrix.transform_vector(self.position).scale(weight);
+                    total_weight += weight;
+                }
+            }
+        }
+        if total_weight > 0.0 {
+            position
+        } else {
+            self.position
+        }
+    }
+
+    /// Calculates normal of vertex after skinning, see [`skinned_position`](Self::skinned_position).
+    pub fn skinned_normal(&self, bone_matrices: &[Mat4]) -> Vec3 {
+        let mut normal = Vec3::ZERO;
+        for (&index, &weight) in self.bone_indices.iter().zip(self.bone_weights.iter()) {
+            if weight > 0.0 {
+                if let Some(matrix) = bone_matrices.get(index as usize) {
+                    normal += matrix.transform_vector_normal(self.normal).scale(weight);
+                }
+            }
+        }
+        normal.normalized().unwrap_or(self.normal)
+    }
 }
 
 impl PartialEq for Vertex {
@@ -61,7 +120,31 @@ impl PartialEq for Vertex {
             self.normal == other.normal &&
             self.tangent == other.tangent &&
             self.bone_weights == other.bone_weights &&
-            self.bone_indices == other.bone_indices
+            self.bone_indices == other.bone_indices &&
+            self.second_tex_coord == other.second_tex_coord &&
+            self.color == other.color
+    }
+}
+
+impl RawVertex for Vertex {
+    fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    fn is_approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() <= epsilon);
+        close(&[self.position.x, self.position.y, self.position.z],
+              &[other.position.x, other.position.y, other.position.z]) &&
+            close(&[self.tex_coord.x, self.tex_coord.y], &[other.tex_coord.x, other.tex_coord.y]) &&
+            close(&[self.normal.x, self.normal.y, self.normal.z],
+                  &[other.normal.x, other.normal.y, other.normal.z]) &&
+            close(&[self.tangent.x, self.tangent.y, self.tangent.z, self.tangent.w],
+                  &[other.tangent.x, other.tangent.y, other.tangent.z, other.tangent.w]) &&
+            close(&self.bone_weights, &other.bone_weights) &&
+            close(&[self.second_tex_coord.x, self.second_tex_coord.y],
+                  &[other.second_tex_coord.x, other.second_tex_coord.y]) &&
+            self.bone_indices == other.bone_indices &&
+            self.color == other.color
     }
 }
 
@@ -81,25 +164,67 @@ impl Hash for Vertex {
 pub struct SurfaceSharedData {
     pub(in crate) vertices: Vec<Vertex>,
     pub(in crate) triangles: Vec<TriangleDefinition>,
+    modification_count: u64,
+    /// Frees GPU copy of data when data is dropped.
+    pub(in crate) tracker: ResourceTracker,
 }
 
 impl Default for SurfaceSharedData {
     fn default() -> Self {
-        Self {
-            vertices: Default::default(),
-            triangles: Default::default(),
-        }
+        Self::new(Default::default(), Default::default())
     }
 }
 
+/// Defines how normals of faces are weighted when normals of vertices are calculated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// Large faces have more influence. Fast, works well for meshes with uniform tessellation.
+    Area,
+    /// Normal of face is weighted by angle of face at vertex, result does not depend on
+    /// tessellation of mesh.
+    Angle,
+}
+
+/// Defines how tangents of vertices are calculated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TangentMethod {
+    /// Tangents of faces are accumulated in vertices. Fast, but vertex shared by faces with
+    /// mirrored texture coordinates gets wrong tangent.
+    Fast,
+    /// MikkTSpace-compatible tangents, the same as most DCC tools and bakers use, so normal
+    /// maps baked in external tools shade correctly. Vertices which are shared by faces with
+    /// mirrored texture coordinates are split.
+    MikkTSpace,
+}
+
 impl SurfaceSharedData {
     pub fn new(vertices: Vec<Vertex>, triangles: Vec<TriangleDefinition>) -> Self {
         Self {
             vertices,
             triangles,
+            modification_count: 0,
+            tracker: ResourceTracker::new(&SURFACE_DATA_QUEUE),
         }
     }
 
+    /// Tells renderer that data was changed and must be uploaded to GPU again. Renderer
+    /// uploads data only once by default, so this method must be called after each change
+    /// of data which is already rendered, for example by procedural animation.
+    #[inline]
+    pub fn mark_modified(&mut self) {
+        self.modification_count = self.modification_count.wrapping_add(1);
+    }
+
+    #[inline]
+    pub fn modification_count(&self) -> u64 {
+        self.modification_count
+    }
+
+    /// Returns amount of memory occupied by vertices and triangles in RAM.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of_val(self.vertices.as_slice()) + std::mem::size_of_val(self.triangles.as_slice())
+    }
+
     #[inline]
     pub fn get_vertices(&self) -> &[Vertex] {
         &self.vertices
@@ -115,7 +240,16 @@ impl SurfaceSharedData {
         self.triangles.as_slice()
     }
 
-    pub fn calculate_tangents(&mut self) {
+    /// Calculates tangents of vertices using given method, normals and texture coordinates
+    /// must be calculated before.
+    pub fn calculate_tangents(&mut self, method: TangentMethod) {
+        match method {
+            TangentMethod::Fast => self.calculate_tangents_fast(),
+            TangentMethod::MikkTSpace => self.calculate_tangents_mikktspace(),
+        }
+    }
+
+    fn calculate_tangents_fast(&mut self) {
         let mut tan1 = vec![Vec3::ZERO; self.vertices.len()];
         let mut tan2 = vec![Vec3::ZERO; self.vertices.len()];
 
@@ -176,6 +310,101 @@ impl SurfaceSharedData {
         }
     }
 
+    fn calculate_tangents_mikktspace(&mut self) {
+        // Tangent of each face and whether its texture mapping preserves orientation.
+        let mut face_tangents = Vec::with_capacity(self.triangles.len());
+        for triangle in self.triangles.iter() {
+            let v1 = &self.vertices[triangle[0] as usize];
+            let v2 = &self.vertices[triangle[1] as usize];
+            let v3 = &self.vertices[triangle[2] as usize];
+
+            let d1 = v2.position - v1.position;
+            let d2 = v3.position - v1.position;
+            let (s1, t1) = (v2.tex_coord.x - v1.tex_coord.x, v2.tex_coord.y - v1.tex_coord.y);
+            let (s2, t2) = (v3.tex_coord.x - v1.tex_coord.x, v3.tex_coord.y - v1.tex_coord.y);
+
+            let signed_area = s1 * t2 - s2 * t1;
+            let tangent = (d1.scale(t2) - d2.scale(t1)).scale(signed_area.signum())
+                .normalized()
+                .unwrap_or(Vec3::ZERO);
+            face_tangents.push((tangent, signed_area > 0.0));
+        }
+
+        // Corners of faces are grouped by vertex attributes and orientation, so vertices
+        // which differ only by index are smoothed together, while faces with mirrored
+        // texture coordinates never affect each other.
+        let vertex_key = |v: &Vertex, orientation: bool| ([
+            v.position.x.to_bits(), v.position.y.to_bits(), v.position.z.to_bits(),
+            v.normal.x.to_bits(), v.normal.y.to_bits(), v.normal.z.to_bits(),
+            v.tex_coord.x.to_bits(), v.tex_coord.y.to_bits(),
+        ], orientation);
+        let mut groups: HashMap<([u32; 8], bool), Vec3> = HashMap::new();
+        for (triangle, (face_tangent, orientation)) in self.triangles.iter().zip(face_tangents.iter()) {
+            for k in 0..3 {
+                let vertex = &self.vertices[triangle[k] as usize];
+                let normal = vertex.normal;
+                // Tangent of face is projected onto tangent plane of vertex and weighted by
+                // angle of face at the corner.
+                let projected = (*face_tangent - normal.scale(normal.dot(face_tangent))).normalized();
+                let e1 = self.vertices[triangle[(k + 1) % 3] as usize].position - vertex.position;
+                let e2 = self.vertices[triangle[(k + 2) % 3] as usize].position - vertex.position;
+                let e1 = (e1 - normal.scale(normal.dot(&e1))).normalized();
+                let e2 = (e2 - normal.scale(normal.dot(&e2))).normalized();
+                let sum = groups.entry(vertex_key(vertex, *orientation)).or_insert(Vec3::ZERO);
+                if let (Some(projected), Some(e1), Some(e2)) = (projected, e1, e2) {
+                    *sum += projected.scale(e1.dot(&e2).max(-1.0).min(1.0).acos());
+                }
+            }
+        }
+
+        let mut assigned: Vec<Option<bool>> = vec![None; self.vertices.len()];
+        // Maps source vertex to vertex which was split from it for mirrored faces.
+        let mut splits: HashMap<usize, u32> = HashMap::new();
+        for n in 0..self.triangles.len() {
+            let (_, orientation) = face_tangents[n];
+            for k in 0..3 {
+                let index = self.triangles[n][k] as usize;
+                let vertex = &self.vertices[index];
+                let tangent = groups[&vertex_key(vertex, orientation)]
+                    .normalized()
+                    .unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0));
+                let tangent = Vec4::from_vec3(tangent, if orientation { 1.0 } else { -1.0 });
+                match assigned[index] {
+                    None => {
+                        assigned[index] = Some(orientation);
+                        self.vertices[index].tangent = tangent;
+                    }
+                    Some(existing) if existing == orientation => (),
+                    Some(_) => {
+                        // Vertex is shared by faces with opposite orientation, split it.
+                        let vertices = &mut self.vertices;
+                        let new_index = *splits.entry(index).or_insert_with(|| {
+                            let mut vertex = vertices[index];
+                            vertex.tangent = tangent;
+                            vertices.push(vertex);
+                            (vertices.len() - 1) as u32
+                        });
+                        self.triangles[n].0[k] = new_index;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bakes given transform into vertices, normals and tangents.
+    pub fn transform_geometry(&mut self, transform: &Mat4) {
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = transform.transform_vector(vertex.position);
+            vertex.normal = transform.transform_vector_normal(vertex.normal)
+                .normalized()
+                .unwrap_or(vertex.normal);
+            let tangent = transform.transform_vector_normal(Vec3::new(vertex.tangent.x, vertex.tangent.y, vertex.tangent.z))
+                .normalized()
+                .unwrap_or_else(|| Vec3::new(vertex.tangent.x, vertex.tangent.y, vertex.tangent.z));
+            vertex.tangent = Vec4::from_vec3(tangent, vertex.tangent.w);
+        }
+    }
+
     pub fn make_unit_xy_quad() -> Self {
         let vertices = vec![
             Vertex {
@@ -185,6 +414,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 1.0, y: 0.0, z: 0.0 },
@@ -193,6 +424,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 1.0, y: 1.0, z: 0.0 },
@@ -201,6 +434,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.0, y: 1.0, z: 0.0 },
@@ -209,6 +444,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             }
         ];
 
@@ -229,6 +466,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
@@ -237,6 +476,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
@@ -245,6 +486,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
@@ -253,6 +496,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             }
         ];
 
@@ -282,6 +527,202 @@ impl SurfaceSharedData {
         }
     }
 
+    /// Calculates smooth normals. Normals of faces are accumulated in vertices which share the
+    /// same position (even if vertices differ by other attributes, such as texture coordinates),
+    /// each normal is weighted according to `weighting`.
+    ///
+    /// If `crease_angle` (in radians) is set, faces which normals differ more than this angle
+    /// do not affect each other, so hard edges stay hard. Vertices on such edges are split
+    /// when needed. Tangents must be re-calculated after this method.
+    pub fn calculate_smooth_normals(&mut self, weighting: NormalWeighting, crease_angle: Option<f32>) {
+        let face_normals = self.triangles.iter()
+            .map(|triangle| {
+                let a = self.vertices[triangle[0] as usize].position;
+                let b = self.vertices[triangle[1] as usize].position;
+                let c = self.vertices[triangle[2] as usize].position;
+                (b - a).cross(&(c - a))
+            })
+            .collect::<Vec<_>>();
+
+        // Weighted normal for each corner of each triangle.
+        let mut corner_normals = Vec::with_capacity(self.triangles.len() * 3);
+        for (triangle, face_normal) in self.triangles.iter().zip(face_normals.iter()) {
+            let unit_normal = face_normal.normalized().unwrap_or(Vec3::ZERO);
+            for k in 0..3 {
+                let weighted = match weighting {
+                    // Length of cross product is proportional to area of triangle.
+                    NormalWeighting::Area => *face_normal,
+                    NormalWeighting::Angle => {
+                        let p = self.vertices[triangle[k] as usize].position;
+                        let e1 = (self.vertices[triangle[(k + 1) % 3] as usize].position - p).normalized();
+                        let e2 = (self.vertices[triangle[(k + 2) % 3] as usize].position - p).normalized();
+                        match (e1, e2) {
+                            (Some(e1), Some(e2)) => unit_normal.scale(e1.dot(&e2).max(-1.0).min(1.0).acos()),
+                            _ => Vec3::ZERO,
+                        }
+                    }
+                };
+                corner_normals.push(weighted);
+            }
+        }
+
+        // Group corners by position of vertex.
+        let position_key = |v: &Vertex| (v.position.x.to_bits(), v.position.y.to_bits(), v.position.z.to_bits());
+        let mut groups: HashMap<(u32, u32, u32), Vec<usize>> = HashMap::new();
+        for (n, triangle) in self.triangles.iter().enumerate() {
+            for k in 0..3 {
+                groups.entry(position_key(&self.vertices[triangle[k] as usize]))
+                    .or_insert_with(Vec::new)
+                    .push(n * 3 + k);
+            }
+        }
+
+        let min_cos = crease_angle.map(|angle| angle.cos());
+        let mut assigned: Vec<Option<Vec3>> = vec![None; self.vertices.len()];
+        // Maps (source vertex, normal) pair to vertex which was split from source vertex.
+        let mut splits: HashMap<(usize, (u32, u32, u32)), u32> = HashMap::new();
+
+        for corners in groups.values() {
+            for &corner in corners.iter() {
+                let n = corner / 3;
+                let this_normal = face_normals[n].normalized().unwrap_or(Vec3::ZERO);
+
+                let mut sum = Vec3::ZERO;
+                for &other in corners.iter() {
+                    let smooth = match min_cos {
+                        Some(min_cos) => {
+                            let other_normal = face_normals[other / 3].normalized().unwrap_or(Vec3::ZERO);
+                            this_normal.dot(&other_normal) >= min_cos
+                        }
+                        None => true,
+                    };
+                    if smooth {
+                        sum += corner_normals[other];
+                    }
+                }
+                let normal = sum.normalized().unwrap_or(this_normal);
+
+                let index = self.triangles[n][corner % 3] as usize;
+                match assigned[index] {
+                    None => {
+                        assigned[index] = Some(normal);
+                        self.vertices[index].normal = normal;
+                    }
+                    Some(existing) if existing.sqr_distance(&normal) <= std::f32::EPSILON => (),
+                    Some(_) => {
+                        // Vertex is shared by faces on different sides of crease, split it.
+                        let key = (index, (normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()));
+                        let vertices = &mut self.vertices;
+                        let new_index = *splits.entry(key).or_insert_with(|| {
+                            let mut vertex = vertices[index];
+                            vertex.normal = normal;
+                            vertices.push(vertex);
+                            (vertices.len() - 1) as u32
+                        });
+                        self.triangles[n].0[corner % 3] = new_index;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates grid mesh from height map. Height is taken from first channel of each pixel of
+    /// image and scaled by `height_scale`, distance between neighbour vertices is `cell_size`.
+    /// Mesh is centered at origin in XZ plane. Texture coordinates repeat once per cell, second
+    /// texture coordinates cover whole mesh once. Image must be loaded, otherwise empty mesh
+    /// is returned.
+    pub fn from_heightmap(image: &Texture, cell_size: f32, height_scale: f32) -> Self {
+        let columns = image.width.saturating_sub(1);
+        let rows = image.height.saturating_sub(1);
+        Self::from_heightmap_chunked(image, cell_size, height_scale, columns.max(rows).max(1))
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| Self::new(Vec::new(), Vec::new()))
+    }
+
+    /// Creates set of grid meshes from height map, each mesh covers at most `chunk_size` x
+    /// `chunk_size` cells. Chunks share border vertices and normals are calculated using whole
+    /// height map, so there are no visible seams between chunks. Chunks are placed in the
+    /// same coordinate system as mesh produced by [`from_heightmap`](Self::from_heightmap),
+    /// row by row from -Z to +Z. Use chunks to let frustum culling reject invisible parts of
+    /// large terrains.
+    pub fn from_heightmap_chunked(image: &Texture, cell_size: f32, height_scale: f32, chunk_size: u32) -> Vec<Self> {
+        let width = image.width as usize;
+        let depth = image.height as usize;
+        if !image.is_loaded() || width < 2 || depth < 2 {
+            return Vec::new();
+        }
+
+        let height_at = |x: usize, z: usize| -> f32 {
+            image.first_channel(x.min(width - 1), z.min(depth - 1)) * height_scale
+        };
+
+        let origin = Vec3::new(
+            -0.5 * (width - 1) as f32 * cell_size,
+            0.0,
+            -0.5 * (depth - 1) as f32 * cell_size,
+        );
+        let make_vertex = |x: usize, z: usize| -> Vertex {
+            // Normal using central differences.
+            let left = height_at(x.saturating_sub(1), z);
+            let right = height_at(x + 1, z);
+            let back = height_at(x, z.saturating_sub(1));
+            let front = height_at(x, z + 1);
+            let normal = Vec3::new(left - right, 2.0 * cell_size, back - front)
+                .normalized()
+                .unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+            Vertex {
+                position: origin + Vec3::new(x as f32 * cell_size, height_at(x, z), z as f32 * cell_size),
+                tex_coord: Vec2::new(x as f32, z as f32),
+                normal,
+                tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
+                bone_weights: [0.0, 0.0, 0.0, 0.0],
+                bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::new(x as f32 / (width - 1) as f32, z as f32 / (depth - 1) as f32),
+                color: Color::WHITE,
+            }
+        };
+
+        let chunk_size = chunk_size.max(1) as usize;
+        let mut chunks = Vec::new();
+        let mut z0 = 0;
+        while z0 < depth - 1 {
+            let z1 = (z0 + chunk_size).min(depth - 1);
+            let mut x0 = 0;
+            while x0 < width - 1 {
+                let x1 = (x0 + chunk_size).min(width - 1);
+                let chunk_width = x1 - x0 + 1;
+
+                let mut vertices = Vec::with_capacity(chunk_width * (z1 - z0 + 1));
+                for z in z0..=z1 {
+                    for x in x0..=x1 {
+                        vertices.push(make_vertex(x, z));
+                    }
+                }
+
+                let mut triangles = Vec::with_capacity(2 * (x1 - x0) * (z1 - z0));
+                for z in 0..(z1 - z0) {
+                    for x in 0..(x1 - x0) {
+                        let i0 = (z * chunk_width + x) as u32;
+                        let i1 = i0 + 1;
+                        let i2 = i0 + chunk_width as u32;
+                        let i3 = i2 + 1;
+                        triangles.push(TriangleDefinition([i0, i2, i1]));
+                        triangles.push(TriangleDefinition([i1, i2, i3]));
+                    }
+                }
+
+                let mut data = Self::new(vertices, triangles);
+                data.calculate_tangents(TangentMethod::Fast);
+                chunks.push(data);
+
+                x0 = x1;
+            }
+            z0 = z1;
+        }
+        chunks
+    }
+
     pub fn make_sphere(slices: usize, stacks: usize, r: f32) -> Self {
         let mut builder = RawMeshBuilder::<Vertex>::new(stacks * slices, stacks * slices * 3);
 
@@ -321,7 +762,7 @@ impl SurfaceSharedData {
 
         let mut data = Self::from(builder.build());
         data.calculate_normals();
-        data.calculate_tangents();
+        data.calculate_tangents(TangentMethod::Fast);
         data
     }
 
@@ -358,7 +799,7 @@ impl SurfaceSharedData {
 
         let mut data = Self::from(builder.build());
         data.calculate_normals();
-        data.calculate_tangents();
+        data.calculate_tangents(TangentMethod::Fast);
         data
     }
 
@@ -404,7 +845,7 @@ impl SurfaceSharedData {
 
         let mut data = Self::from(builder.build());
         data.calculate_normals();
-        data.calculate_tangents();
+        data.calculate_tangents(TangentMethod::Fast);
         data
     }
 
@@ -419,6 +860,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: 0.5, z: 0.5 },
@@ -427,6 +870,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: 0.5, z: 0.5 },
@@ -435,6 +880,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: -0.5, z: 0.5 },
@@ -443,6 +890,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
 
             // Back
@@ -453,6 +902,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: 0.5, z: -0.5 },
@@ -461,6 +912,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: 0.5, z: -0.5 },
@@ -469,6 +922,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: -0.5, z: -0.5 },
@@ -477,6 +932,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
 
             // Left
@@ -487,6 +944,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: 0.5, z: -0.5 },
@@ -495,6 +954,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: 0.5, z: 0.5 },
@@ -503,6 +964,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: -0.5, z: 0.5 },
@@ -511,6 +974,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
 
             // Right
@@ -521,6 +986,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: 0.5, z: -0.5 },
@@ -529,6 +996,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: 0.5, z: 0.5 },
@@ -537,6 +1006,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: -0.5, z: 0.5 },
@@ -545,6 +1016,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
 
             // Top
@@ -555,6 +1028,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: 0.5, z: -0.5 },
@@ -563,6 +1038,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: 0.5, z: -0.5 },
@@ -571,6 +1048,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: 0.5, z: 0.5 },
@@ -579,6 +1058,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
 
             // Bottom
@@ -589,6 +1070,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: -0.5, y: -0.5, z: -0.5 },
@@ -597,6 +1080,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: -0.5, z: -0.5 },
@@ -605,6 +1090,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
             Vertex {
                 position: Vec3 { x: 0.5, y: -0.5, z: 0.5 },
@@ -613,6 +1100,8 @@ impl SurfaceSharedData {
                 tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
                 bone_weights: [0.0, 0.0, 0.0, 0.0],
                 bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
             },
         ];
 
@@ -632,7 +1121,7 @@ impl SurfaceSharedData {
         ];
 
         let mut data = Self::new(vertices, indices);
-        data.calculate_tangents();
+        data.calculate_tangents(TangentMethod::Fast);
         data
     }
 }
@@ -711,10 +1200,87 @@ impl VertexWeightSet {
     }
 }
 
+/// Additional texture layer which is tiled much more densely than main textures of
+/// surface, it adds fine details to large surfaces, like terrains or walls, which would
+/// look blurry up close otherwise.
+#[derive(Clone)]
+pub struct DetailLayer {
+    /// Grayscale-ish texture which is multiplied with diffuse color, value of 0.5 keeps
+    /// color as is, brighter values lighten and darker values darken it.
+    pub diffuse_texture: Option<Arc<Mutex<Texture>>>,
+    /// Normal map which is blended with main normal map.
+    pub normal_texture: Option<Arc<Mutex<Texture>>>,
+    /// Scale of texture coordinates of layer.
+    pub tiling: Vec2,
+    /// How much layer affects surface, in [0; 1] range.
+    pub strength: f32,
+}
+
+impl Default for DetailLayer {
+    fn default() -> Self {
+        Self {
+            diffuse_texture: None,
+            normal_texture: None,
+            tiling: Vec2::new(8.0, 8.0),
+            strength: 1.0,
+        }
+    }
+}
+
+/// Texture arrays of surface and layer of them which surface uses, see
+/// `resource::texture_array`.
+#[derive(Clone)]
+pub struct SurfaceTextureArray {
+    /// Replaces diffuse texture of surface.
+    pub diffuse: SharedTextureArray,
+    /// Replaces normal texture of surface, normal texture of surface is used if `None`.
+    pub normal: Option<SharedTextureArray>,
+    /// Index of layer, it is clamped to amount of layers of array.
+    pub layer: u32,
+}
+
+/// Defines which faces of surface are not drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    /// Faces which look away from camera are not drawn, default mode for closed meshes.
+    Back,
+    /// Faces which look to camera are not drawn, useful for inner sides of domes and rooms
+    /// seen from inside.
+    Front,
+    /// Both sides are drawn, back side is lit as if its normal is flipped. Use it for thin
+    /// geometry like foliage cards, cloth or flags instead of duplicating and flipping
+    /// geometry.
+    None,
+}
+
+impl Default for CullMode {
+    fn default() -> Self {
+        CullMode::Back
+    }
+}
+
+impl CullMode {
+    /// Returns face to cull and whether culling is enabled at all.
+    pub(in crate) fn cull_face(self) -> (CullFace, bool) {
+        match self {
+            CullMode::Back => (CullFace::Back, true),
+            CullMode::Front => (CullFace::Front, true),
+            CullMode::None => (CullFace::Back, false),
+        }
+    }
+}
+
 pub struct Surface {
     data: Arc<Mutex<SurfaceSharedData>>,
     diffuse_texture: Option<Arc<Mutex<Texture>>>,
     normal_texture: Option<Arc<Mutex<Texture>>>,
+    reflectivity: f32,
+    material: Option<SharedMaterial>,
+    tex_coord_scale: Vec2,
+    tex_coord_offset: Vec2,
+    detail_layer: Option<DetailLayer>,
+    texture_array: Option<SurfaceTextureArray>,
+    cull_mode: CullMode,
     /// Temporal array for FBX conversion needs, it holds skinning data (weight + bone handle)
     /// and will be used to fill actual bone indices and weight in vertices that will be
     /// sent to GPU. The idea is very simple: GPU needs to know only indices of matrices of
@@ -737,6 +1303,13 @@ impl Clone for Surface {
             data: Arc::clone(&self.data),
             diffuse_texture: self.diffuse_texture.clone(),
             normal_texture: self.normal_texture.clone(),
+            reflectivity: self.reflectivity,
+            material: self.material.clone(),
+            tex_coord_scale: self.tex_coord_scale,
+            tex_coord_offset: self.tex_coord_offset,
+            detail_layer: self.detail_layer.clone(),
+            texture_array: self.texture_array.clone(),
+            cull_mode: self.cull_mode,
             bones: self.bones.clone(),
             vertex_weights: Vec::new(),
         }
@@ -744,12 +1317,23 @@ impl Clone for Surface {
 }
 
 impl Surface {
+    /// Maximum amount of bones per surface, bone index is stored in one byte in vertex.
+    /// Bone matrices are passed to shaders using texture, so there is no other limits.
+    pub const MAX_BONES: usize = 256;
+
     #[inline]
     pub fn new(data: Arc<Mutex<SurfaceSharedData>>) -> Self {
         Self {
             data,
             diffuse_texture: None,
             normal_texture: None,
+            reflectivity: 0.0,
+            material: None,
+            tex_coord_scale: Vec2::new(1.0, 1.0),
+            tex_coord_offset: Vec2::new(0.0, 0.0),
+            detail_layer: None,
+            texture_array: None,
+            cull_mode: CullMode::Back,
             bones: Vec::new(),
             vertex_weights: Vec::new(),
         }
@@ -760,14 +1344,30 @@ impl Surface {
         self.data.clone()
     }
 
+    /// Replaces geometry of surface, textures and material are kept.
+    #[inline]
+    pub fn set_data(&mut self, data: Arc<Mutex<SurfaceSharedData>>) {
+        self.data = data;
+    }
+
+    /// Returns diffuse texture of material if surface has material, otherwise own diffuse
+    /// texture of surface.
     #[inline]
     pub fn get_diffuse_texture(&self) -> Option<Arc<Mutex<Texture>>> {
-        self.diffuse_texture.clone()
+        match self.material.as_ref() {
+            Some(material) => material.lock().unwrap().diffuse_texture(),
+            None => self.diffuse_texture.clone(),
+        }
     }
 
+    /// Returns normal texture of material if surface has material, otherwise own normal
+    /// texture of surface.
     #[inline]
     pub fn get_normal_texture(&self) -> Option<Arc<Mutex<Texture>>> {
-        self.normal_texture.clone()
+        match self.material.as_ref() {
+            Some(material) => material.lock().unwrap().normal_texture(),
+            None => self.normal_texture.clone(),
+        }
     }
 
     #[inline]
@@ -779,13 +1379,228 @@ impl Surface {
     pub fn set_normal_texture(&mut self, tex: Arc<Mutex<Texture>>) {
         self.normal_texture = Some(tex);
     }
+
+    /// Sets how much surface reflects surrounding objects, in [0; 1] range. Reflections
+    /// are rendered only if screen space reflections are enabled in quality settings.
+    /// Reflectivity also defines glossiness - less reflective surfaces have more blurry
+    /// reflections.
+    #[inline]
+    pub fn set_reflectivity(&mut self, reflectivity: f32) {
+        self.reflectivity = reflectivity.max(0.0).min(1.0);
+    }
+
+    /// Returns reflectivity of material if surface has material, otherwise own reflectivity
+    /// of surface.
+    #[inline]
+    pub fn reflectivity(&self) -> f32 {
+        match self.material.as_ref() {
+            Some(material) => material.lock().unwrap().reflectivity(),
+            None => self.reflectivity,
+        }
+    }
+
+    /// Sets shared material of surface, see `resource::material`. While surface has
+    /// material, own textures and parameters of surface are ignored.
+    #[inline]
+    pub fn set_material(&mut self, material: Option<SharedMaterial>) {
+        self.material = material;
+    }
+
+    #[inline]
+    pub fn material(&self) -> Option<SharedMaterial> {
+        self.material.clone()
+    }
+
+    /// Returns height texture of material, surfaces without material have no height
+    /// texture. Parallax occlusion mapping is used for surfaces with height texture.
+    #[inline]
+    pub fn get_height_texture(&self) -> Option<Arc<Mutex<Texture>>> {
+        self.material.as_ref().and_then(|material| material.lock().unwrap().height_texture())
+    }
+
+    /// Returns parallax parameters of material, surfaces without material use defaults.
+    #[inline]
+    pub fn parallax(&self) -> Parallax {
+        self.material.as_ref().map_or_else(Default::default, |material| material.lock().unwrap().parallax())
+    }
+
+    /// Sets tiling of texture coordinates, texture coordinates of vertices are multiplied
+    /// by scale before offset is added. Applied to every texture of surface (and its
+    /// material) except detail layer, which has its own tiling on top of this one.
+    #[inline]
+    pub fn set_tex_coord_transform(&mut self, scale: Vec2, offset: Vec2) {
+        self.tex_coord_scale = scale;
+        self.tex_coord_offset = offset;
+    }
+
+    #[inline]
+    pub fn tex_coord_scale(&self) -> Vec2 {
+        self.tex_coord_scale
+    }
+
+    #[inline]
+    pub fn tex_coord_offset(&self) -> Vec2 {
+        self.tex_coord_offset
+    }
+
+    /// Sets detail layer of surface, see [`DetailLayer`]. Detail layer is a property of
+    /// surface, so it is used even if surface has material.
+    #[inline]
+    pub fn set_detail_layer(&mut self, layer: Option<DetailLayer>) {
+        self.detail_layer = layer;
+    }
+
+    #[inline]
+    pub fn detail_layer(&self) -> Option<&DetailLayer> {
+        self.detail_layer.as_ref()
+    }
+
+    #[inline]
+    pub fn detail_layer_mut(&mut self) -> Option<&mut DetailLayer> {
+        self.detail_layer.as_mut()
+    }
+
+    /// Sets texture arrays of surface, see [`SurfaceTextureArray`]. Texture arrays are a
+    /// property of surface, so they replace textures of material too.
+    #[inline]
+    pub fn set_texture_array(&mut self, texture_array: Option<SurfaceTextureArray>) {
+        self.texture_array = texture_array;
+    }
+
+    #[inline]
+    pub fn texture_array(&self) -> Option<&SurfaceTextureArray> {
+        self.texture_array.as_ref()
+    }
+
+    /// Selects layer of texture arrays of surface, does nothing if surface has no arrays.
+    #[inline]
+    pub fn set_texture_array_layer(&mut self, layer: u32) {
+        if let Some(texture_array) = self.texture_array.as_mut() {
+            texture_array.layer = layer;
+        }
+    }
+
+    /// Sets which faces of surface are not drawn, see [`CullMode`]. Mode is used for shadows
+    /// too, so two-sided surfaces cast shadows from both sides.
+    #[inline]
+    pub fn set_cull_mode(&mut self, cull_mode: CullMode) {
+        self.cull_mode = cull_mode;
+    }
+
+    #[inline]
+    pub fn cull_mode(&self) -> CullMode {
+        self.cull_mode
+    }
+
+    /// Returns color of material, surfaces without material are white.
+    #[inline]
+    pub fn color(&self) -> Color {
+        self.material.as_ref().map_or(Color::WHITE, |material| material.lock().unwrap().color())
+    }
+
+    /// Returns blend mode of material, surfaces without material use alpha test.
+    #[inline]
+    pub fn blend_mode(&self) -> BlendMode {
+        self.material.as_ref().map_or(BlendMode::AlphaTest, |material| material.lock().unwrap().blend_mode())
+    }
+
+    /// Returns alpha cutoff of material, surfaces without material use default cutoff.
+    #[inline]
+    pub fn alpha_cutoff(&self) -> f32 {
+        self.material.as_ref().map_or(DEFAULT_ALPHA_CUTOFF, |material| material.lock().unwrap().alpha_cutoff())
+    }
+
+    /// Returns how strong surface reacts on wetness of scene (see `scene::weather`),
+    /// surfaces without material are fully affected.
+    #[inline]
+    pub fn wetness_factor(&self) -> f32 {
+        self.material.as_ref().map_or(1.0, |material| material.lock().unwrap().wetness_factor())
+    }
+
+    /// Returns amount of bones that affect vertices of surface, zero means that
+    /// surface is not skinned.
+    #[inline]
+    pub fn bone_count(&self) -> usize {
+        self.bones.len()
+    }
+
+    /// Returns true if given surface looks exactly the same as this one - it has the same
+    /// geometry, material, textures, texture coordinates transform and cull mode, and the
+    /// same amount of bones. Such surfaces are copies of one model and may be drawn by one
+    /// instanced draw call, each with its own bone matrices.
+    pub fn can_share_draw_with(&self, other: &Surface) -> bool {
+        fn same<T>(a: &Option<Arc<T>>, b: &Option<Arc<T>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        let same_detail = match (self.detail_layer.as_ref(), other.detail_layer.as_ref()) {
+            (Some(a), Some(b)) => same(&a.diffuse_texture, &b.diffuse_texture)
+                && same(&a.normal_texture, &b.normal_texture)
+                && a.tiling == b.tiling
+                && a.strength == b.strength,
+            (None, None) => true,
+            _ => false,
+        };
+
+        let same_array = match (self.texture_array.as_ref(), other.texture_array.as_ref()) {
+            (Some(a), Some(b)) => Arc::ptr_eq(&a.diffuse, &b.diffuse)
+                && same(&a.normal, &b.normal)
+                && a.layer == b.layer,
+            (None, None) => true,
+            _ => false,
+        };
+
+        Arc::ptr_eq(&self.data, &other.data)
+            && self.bones.len() == other.bones.len()
+            && same(&self.material, &other.material)
+            && (self.material.is_some() || (same(&self.diffuse_texture, &other.diffuse_texture)
+            && same(&self.normal_texture, &other.normal_texture)
+            && self.reflectivity == other.reflectivity))
+            && self.tex_coord_scale == other.tex_coord_scale
+            && self.tex_coord_offset == other.tex_coord_offset
+            && self.cull_mode == other.cull_mode
+            && same_detail
+            && same_array
+    }
+
+    /// Fills given array with current matrices of bones of surface. Matrices transform
+    /// vertices from bind pose to world space, so skinned surfaces must not be additionally
+    /// transformed by global transform of mesh.
+    pub fn fill_bone_matrices(&self, graph: &Graph, matrices: &mut Vec<Mat4>) {
+        matrices.clear();
+        self.append_bone_matrices(graph, matrices);
+    }
+
+    /// Same as `fill_bone_matrices`, but matrices are added to the end of given array.
+    /// Used to store bones of many copies of a surface in one array. Bones which were not
+    /// found after sync of instance (see `Graph::sync_instances`) produce identity matrix.
+    pub fn append_bone_matrices(&self, graph: &Graph, matrices: &mut Vec<Mat4>) {
+        for &bone_handle in self.bones.iter() {
+            if graph.is_valid_handle(bone_handle) {
+                let bone = &graph[bone_handle];
+                matrices.push(bone.global_transform() * bone.inv_bind_pose_transform());
+            } else {
+                matrices.push(Mat4::IDENTITY);
+            }
+        }
+    }
+
+    /// Calculates positions of vertices after skinning on CPU, positions are in world space.
+    /// This is relatively heavy operation and should be used only when exact positions
+    /// of deformed geometry are needed, for example for precise hit detection.
+    pub fn skinned_vertex_positions(&self, bone_matrices: &[Mat4], positions: &mut Vec<Vec3>) {
+        positions.clear();
+        let data = self.data.lock().unwrap();
+        positions.extend(data.get_vertices().iter().map(|v| v.skinned_position(bone_matrices)));
+    }
 }
 
 impl From<RawMesh<Vertex>> for SurfaceSharedData {
     fn from(raw: RawMesh<Vertex>) -> Self {
-        Self {
-            vertices: raw.vertices,
-            triangles: raw.triangles,
-        }
+        Self::new(raw.vertices, raw.triangles)
     }
 }
\ No newline at end of file
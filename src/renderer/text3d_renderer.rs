@@ -0,0 +1,177 @@
+use crate::{
+    scene::{
+        text3d::{
+            self,
+            Text3D,
+        },
+        camera::Camera,
+    },
+    core::{
+        scope_profile,
+        math::Rect,
+    },
+    renderer::{
+        ui_renderer::font_atlas_texture,
+        error::RendererError,
+        framework::{
+            gpu_program::{
+                GpuProgram,
+                UniformLocation,
+                UniformValue,
+            },
+            gl,
+            geometry_buffer::{
+                GeometryBuffer,
+                DynamicBufferRing,
+                AttributeDefinition,
+                AttributeKind,
+                ElementKind,
+            },
+            framebuffer::{
+                FrameBuffer,
+                DrawParameters,
+                CullFace,
+                FrameBufferTrait,
+            },
+            state::State,
+        },
+        RenderPassStatistics,
+        TextureCache,
+    },
+};
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
+
+struct Text3DShader {
+    program: GpuProgram,
+    view_projection_matrix: UniformLocation,
+    camera_side_vector: UniformLocation,
+    camera_up_vector: UniformLocation,
+    font_texture: UniformLocation,
+}
+
+impl Text3DShader {
+    fn new() -> Result<Self, RendererError> {
+        let vertex_source = include_str!("shaders/text3d_vs.glsl");
+        let fragment_source = include_str!("shaders/text3d_fs.glsl");
+        let program = GpuProgram::from_source("Text3DShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            view_projection_matrix: program.uniform_location("viewProjectionMatrix")?,
+            camera_side_vector: program.uniform_location("cameraSideVector")?,
+            camera_up_vector: program.uniform_location("cameraUpVector")?,
+            font_texture: program.uniform_location("fontTexture")?,
+            program,
+        })
+    }
+}
+
+pub struct Text3DRenderer {
+    shader: Text3DShader,
+    draw_data: text3d::DrawData,
+    geometry_buffer: GeometryBuffer<text3d::Vertex>,
+}
+
+pub struct Text3DRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub framebuffer: &'b mut FrameBuffer,
+    pub text: &'c Text3D,
+    pub camera: &'c Camera,
+    pub viewport: Rect<i32>,
+    pub texture_cache: &'a mut TextureCache,
+}
+
+impl Text3DRenderer {
+    pub fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry_buffer = GeometryBuffer::new_in_ring(state, ring, ElementKind::Triangle);
+
+        geometry_buffer.bind(state)
+            .describe_attributes(vec![
+                AttributeDefinition { kind: AttributeKind::Float3, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: true },
+            ])?;
+
+        Ok(Self {
+            shader: Text3DShader::new()?,
+            draw_data: Default::default(),
+            geometry_buffer,
+        })
+    }
+
+    /// Draws one text, nothing is drawn until font atlas is uploaded.
+    #[must_use]
+    pub fn render(&mut self, args: Text3DRenderContext) -> RenderPassStatistics {
+        scope_profile!();
+
+        let Text3DRenderContext {
+            state, framebuffer, text,
+            camera, viewport, texture_cache
+        } = args;
+
+        state.set_blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        let inv_view = camera.inv_view_matrix().unwrap();
+
+        let camera_up = inv_view.up();
+        let camera_side = inv_view.side();
+
+        let font_arc = if let Some(font) = text.font() {
+            font
+        } else {
+            return RenderPassStatistics::default();
+        };
+
+        let mut font = font_arc.lock().unwrap();
+
+        text.generate_draw_data(&font, &mut self.draw_data);
+
+        if self.draw_data.get_triangles().is_empty() {
+            return RenderPassStatistics::default();
+        }
+
+        // Font atlas is shared with user interface.
+        let font_texture = match font_atlas_texture(&mut font) {
+            Some(texture) => texture,
+            None => return RenderPassStatistics::default(),
+        };
+
+        let font_texture = match texture_cache.get(state, font_texture) {
+            Some(texture) => texture,
+            None => return RenderPassStatistics::default(),
+        };
+
+        self.geometry_buffer
+            .bind(state)
+            .set_triangles(self.draw_data.get_triangles())
+            .set_vertices(self.draw_data.get_vertices());
+
+        let uniforms = [
+            (self.shader.font_texture, UniformValue::Sampler { index: 0, texture: font_texture }),
+            (self.shader.camera_side_vector, UniformValue::Vec3(camera_side)),
+            (self.shader.camera_up_vector, UniformValue::Vec3(camera_up)),
+            (self.shader.view_projection_matrix, UniformValue::Mat4(camera.view_projection_matrix())),
+        ];
+
+        let draw_params = DrawParameters {
+            cull_face: CullFace::Back,
+            culling: false,
+            color_write: Default::default(),
+            depth_write: false,
+            stencil_test: false,
+            depth_test: true,
+            blend: true,
+        };
+
+        framebuffer.draw(
+            &self.geometry_buffer,
+            state,
+            viewport,
+            &self.shader.program,
+            draw_params,
+            &uniforms,
+        )
+    }
+}
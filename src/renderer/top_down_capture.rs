@@ -0,0 +1,187 @@
+//! Top-down capture of scene - source image for minimaps and world maps.
+//!
+//! Drawing map of level by hand goes out of date as soon as level changes. Instead renderer
+//! can render hierarchy of nodes (whole scene or its part, for example only terrain and
+//! buildings) straight from above with orthographic camera into a texture. Capture is lit
+//! by current lights of scene, background is transparent. It is a heavy operation which
+//! reads back rendered frame from GPU, so capture once when level is loaded and refresh
+//! on demand, for example when a building is destroyed.
+//!
+//! Top of image is +Z side of captured region. Use `TopDownCapture::world_to_uv` to place
+//! markers of player and objectives on map.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     engine::Engine,
+//!     renderer::top_down_capture::TopDownSettings,
+//!     core::pool::Handle,
+//!     scene::Scene,
+//!     gui::node::StubNode,
+//! };
+//!
+//! fn make_minimap(engine: &mut Engine<(), StubNode>, scene: Handle<Scene>) {
+//!     let scene = &mut engine.scenes[scene];
+//!     let root = scene.graph.get_root();
+//!     let mut minimap = engine.renderer.capture_top_down(scene, root, TopDownSettings::default()).unwrap();
+//!     // Later, when level has changed.
+//!     engine.renderer.refresh_top_down(scene, &mut minimap).unwrap();
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use crate::{
+    core::{
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
+            vec4::Vec4,
+            mat4::Mat4,
+            aabb::AxisAlignedBoundingBox,
+        },
+        pool::Handle,
+    },
+    scene::{
+        Scene,
+        node::Node,
+        camera::Camera,
+    },
+    resource::texture::{
+        Texture,
+        TextureKind,
+    },
+    engine::resource_manager::SharedTexture,
+    renderer::{
+        Renderer,
+        error::RendererError,
+        gbuffer::GBuffer,
+    },
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TopDownSettings {
+    /// Size of square image in pixels.
+    pub size: u32,
+    /// Distance in world units added around bounds of captured meshes.
+    pub padding: f32,
+}
+
+impl Default for TopDownSettings {
+    fn default() -> Self {
+        Self {
+            size: 512,
+            padding: 1.0,
+        }
+    }
+}
+
+/// Result of capture, see module docs.
+pub struct TopDownCapture {
+    /// RGBA8 image, rows go from top to bottom. Texture is updated in place by
+    /// `Renderer::refresh_top_down`, so it can be shown by user interface directly.
+    pub texture: SharedTexture,
+    root: Handle<Node>,
+    settings: TopDownSettings,
+    view_projection: Mat4,
+}
+
+impl TopDownCapture {
+    pub fn root(&self) -> Handle<Node> {
+        self.root
+    }
+
+    pub fn settings(&self) -> TopDownSettings {
+        self.settings
+    }
+
+    /// Returns texture coordinates of point of world on captured image, (0; 0) is left upper
+    /// corner. Points outside of captured region are outside of [0; 1] range, height of
+    /// point does not matter.
+    pub fn world_to_uv(&self, position: Vec3) -> Vec2 {
+        let clip = self.view_projection.transform_vector4(Vec4::new(position.x, position.y, position.z, 1.0));
+        Vec2::new(clip.x * 0.5 + 0.5, 0.5 - clip.y * 0.5)
+    }
+}
+
+impl Renderer {
+    /// Renders hierarchy starting from `root` from above into new texture, see module docs.
+    /// Returns `RendererError::NothingToBake` if hierarchy has no meshes.
+    pub fn capture_top_down(&mut self, scene: &mut Scene, root: Handle<Node>, settings: TopDownSettings)
+                            -> Result<TopDownCapture, RendererError> {
+        let size = settings.size.max(1);
+        let texture = Texture::from_bytes(size, size, TextureKind::RGBA8, vec![0; (size * size * 4) as usize])
+            .map_err(|_| RendererError::InvalidTextureData)?;
+        let mut capture = TopDownCapture {
+            texture: Arc::new(Mutex::new(texture)),
+            root,
+            settings: TopDownSettings { size, ..settings },
+            view_projection: Mat4::IDENTITY,
+        };
+        self.refresh_top_down(scene, &mut capture)?;
+        Ok(capture)
+    }
+
+    /// Renders hierarchy of capture again into its texture, region is recalculated from
+    /// current bounds of hierarchy.
+    pub fn refresh_top_down(&mut self, scene: &mut Scene, capture: &mut TopDownCapture) -> Result<(), RendererError> {
+        let mut bounds = AxisAlignedBoundingBox::default();
+        let mut has_meshes = false;
+        for node in scene.graph.traverse_iter(capture.root) {
+            if let Node::Mesh(mesh) = node {
+                let mesh_bounds = mesh.world_bounding_box();
+                bounds.add_point(mesh_bounds.min);
+                bounds.add_point(mesh_bounds.max);
+                has_meshes = true;
+            }
+        }
+        if !has_meshes {
+            return Err(RendererError::NothingToBake);
+        }
+
+        let padding = capture.settings.padding.max(0.0);
+        let center = (bounds.min + bounds.max).scale(0.5);
+        let extent = (bounds.max.x - bounds.min.x).max(bounds.max.z - bounds.min.z) + padding * 2.0;
+        let depth = bounds.max.y - bounds.min.y + padding * 2.0;
+        let eye = Vec3::new(center.x, bounds.max.y + padding, center.z);
+        let camera = Camera::orthographic_looking_at(eye, center, Vec3::LOOK,
+                                                     extent.max(0.001), extent.max(0.001), 0.0, depth.max(0.001));
+
+        // Hide everything except hierarchy, renderers check global visibility only.
+        let subtree = scene.graph.traverse_handle_iter(capture.root).collect::<HashSet<_>>();
+        let mut saved_visibility = Vec::new();
+        for (handle, node) in scene.graph.pair_iter_mut() {
+            saved_visibility.push((handle, node.global_visibility));
+            node.global_visibility = subtree.contains(&handle) && node.visibility();
+        }
+
+        let size = capture.settings.size as usize;
+        let frame = GBuffer::new(&mut self.state, self.gbuffer_shader.clone(), size, size)
+            .and_then(|mut gbuffer| self.render_offscreen(scene, &mut gbuffer, &camera));
+
+        for (handle, visibility) in saved_visibility {
+            scene.graph[handle].global_visibility = visibility;
+        }
+
+        let (color, normals) = frame?;
+
+        let mut texture = capture.texture.lock().unwrap();
+        let pixels = texture.pixels_mut();
+        // Frame rows go bottom to top, image rows go top to bottom.
+        for y in 0..size {
+            for x in 0..size {
+                let src = ((size - 1 - y) * size + x) * 4;
+                let dst = (y * size + x) * 4;
+                pixels[dst..(dst + 3)].copy_from_slice(&color[src..(src + 3)]);
+                // Normals are packed into [0; 1] range, so only background has zero normal.
+                let covered = normals[src..(src + 3)].iter().any(|&n| n != 0);
+                pixels[dst + 3] = if covered { 255 } else { 0 };
+            }
+        }
+
+        capture.view_projection = camera.view_projection_matrix();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    scene::{
+        trail::{
+            self,
+            Trail,
+        },
+        camera::Camera,
+    },
+    core::{
+        scope_profile,
+        math::Rect,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            gpu_texture::GpuTexture,
+            gpu_program::{
+                GpuProgram,
+                UniformLocation,
+                UniformValue,
+            },
+            gl,
+            geometry_buffer::{
+                GeometryBuffer,
+                DynamicBufferRing,
+                AttributeDefinition,
+                AttributeKind,
+                ElementKind,
+            },
+            framebuffer::{
+                FrameBuffer,
+                DrawParameters,
+                CullFace,
+                FrameBufferTrait,
+            },
+            state::State,
+        },
+        RenderPassStatistics,
+        TextureCache,
+    },
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+
+struct TrailShader {
+    program: GpuProgram,
+    view_projection_matrix: UniformLocation,
+    diffuse_texture: UniformLocation,
+}
+
+impl TrailShader {
+    fn new() -> Result<Self, RendererError> {
+        let vertex_source = include_str!("shaders/trail_vs.glsl");
+        let fragment_source = include_str!("shaders/trail_fs.glsl");
+        let program = GpuProgram::from_source("TrailShader", vertex_source, fragment_source)?;
+        Ok(Self {
+            view_projection_matrix: program.uniform_location("viewProjectionMatrix")?,
+            diffuse_texture: program.uniform_location("diffuseTexture")?,
+            program,
+        })
+    }
+}
+
+pub struct TrailRenderer {
+    shader: TrailShader,
+    draw_data: trail::DrawData,
+    geometry_buffer: GeometryBuffer<trail::Vertex>,
+}
+
+pub struct TrailRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub framebuffer: &'b mut FrameBuffer,
+    pub trail: &'c Trail,
+    pub camera: &'c Camera,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub viewport: Rect<i32>,
+    pub texture_cache: &'a mut TextureCache,
+}
+
+impl TrailRenderer {
+    pub fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry_buffer = GeometryBuffer::new_in_ring(state, ring, ElementKind::Triangle);
+
+        geometry_buffer.bind(state)
+            .describe_attributes(vec![
+                AttributeDefinition { kind: AttributeKind::Float3, normalized: false },
+                AttributeDefinition { kind: AttributeKind::Float2, normalized: false },
+                AttributeDefinition { kind: AttributeKind::UnsignedByte4, normalized: true },
+            ])?;
+
+        Ok(Self {
+            shader: TrailShader::new()?,
+            draw_data: Default::default(),
+            geometry_buffer,
+        })
+    }
+
+    /// Draws one trail.
+    #[must_use]
+    pub fn render(&mut self, args: TrailRenderContext) -> RenderPassStatistics {
+        scope_profile!();
+
+        let TrailRenderContext {
+            state, framebuffer, trail,
+            camera, white_dummy, viewport,
+            texture_cache
+        } = args;
+
+        state.set_blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        let camera_position = camera.global_position();
+
+        trail.generate_draw_data(&mut self.draw_data, &camera_position);
+
+        if self.draw_data.get_triangles().is_empty() {
+            return RenderPassStatistics::default();
+        }
+
+        self.geometry_buffer
+            .bind(state)
+            .set_triangles(self.draw_data.get_triangles())
+            .set_vertices(self.draw_data.get_vertices());
+
+        let uniforms = [
+            (self.shader.diffuse_texture, UniformValue::Sampler {
+                index: 0,
+                texture: if let Some(texture) = trail.texture() {
+                    if let Some(texture) = texture_cache.get(state, texture) {
+                        texture
+                    } else {
+                        white_dummy.clone()
+                    }
+                } else {
+                    white_dummy.clone()
+                },
+            }),
+            (self.shader.view_projection_matrix, UniformValue::Mat4(camera.view_projection_matrix())),
+        ];
+
+        // Ribbon is visible from both sides, so culling is disabled.
+        let draw_params = DrawParameters {
+            cull_face: CullFace::Back,
+            culling: false,
+            color_write: Default::default(),
+            depth_write: false,
+            stencil_test: false,
+            depth_test: true,
+            blend: true,
+        };
+
+        framebuffer.draw(
+            &self.geometry_buffer,
+            state,
+            viewport,
+            &self.shader.program,
+            draw_params,
+            &uniforms,
+        )
+    }
+}
@@ -0,0 +1,228 @@
+//! Transparent pass - particle systems, sprites, trails and 3D texts in one queue.
+//!
+//! Transparent objects are blended over lit frame, so they must be drawn back-to-front,
+//! otherwise smoke behind a billboard would be drawn over it. All visible transparent
+//! nodes are sorted by distance from camera to their position and drawn in that order
+//! regardless of their kind. Consecutive sprites are still batched together. Sprites
+//! without depth test are markers and icons, they're drawn last on top of everything.
+//!
+//! Sorting is per node: particles are sorted within their particle system, but two
+//! intersecting particle systems are not interleaved.
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    cmp::Ordering,
+};
+use crate::{
+    scene::{
+        node::Node,
+        graph::Graph,
+        camera::Camera,
+        sprite::Sprite,
+    },
+    core::{
+        scope_profile,
+        math::Rect,
+        pool::Handle,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            gpu_texture::GpuTexture,
+            geometry_buffer::DynamicBufferRing,
+            framebuffer::FrameBuffer,
+            state::State,
+        },
+        particle_system_renderer::{
+            ParticleSystemRenderer,
+            ParticleSystemRenderContext,
+        },
+        sprite_renderer::{
+            SpriteRenderer,
+            SpriteRenderContext,
+        },
+        trail_renderer::{
+            TrailRenderer,
+            TrailRenderContext,
+        },
+        text3d_renderer::{
+            Text3DRenderer,
+            Text3DRenderContext,
+        },
+        RenderPassStatistics,
+        TextureCache,
+    },
+};
+
+/// Transparent node with its distance to camera.
+struct TransparentItem {
+    node: Handle<Node>,
+    distance: f32,
+}
+
+pub struct TransparentRenderer {
+    particle_system_renderer: ParticleSystemRenderer,
+    sprite_renderer: SpriteRenderer,
+    trail_renderer: TrailRenderer,
+    text3d_renderer: Text3DRenderer,
+    queue: Vec<TransparentItem>,
+    overlays: Vec<TransparentItem>,
+}
+
+pub struct TransparentRenderContext<'a, 'b, 'c> {
+    pub state: &'a mut State,
+    pub framebuffer: &'b mut FrameBuffer,
+    pub graph: &'c Graph,
+    pub camera: &'c Camera,
+    pub white_dummy: Rc<RefCell<GpuTexture>>,
+    pub depth: Rc<RefCell<GpuTexture>>,
+    pub frame_width: f32,
+    pub frame_height: f32,
+    pub viewport: Rect<i32>,
+    pub texture_cache: &'a mut TextureCache,
+}
+
+fn back_to_front(a: &TransparentItem, b: &TransparentItem) -> Ordering {
+    b.distance.partial_cmp(&a.distance).unwrap_or(Ordering::Equal)
+}
+
+impl TransparentRenderer {
+    pub fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        Ok(Self {
+            particle_system_renderer: ParticleSystemRenderer::new(state, ring.clone())?,
+            sprite_renderer: SpriteRenderer::new(state, ring.clone())?,
+            trail_renderer: TrailRenderer::new(state, ring.clone())?,
+            text3d_renderer: Text3DRenderer::new(state, ring)?,
+            queue: Default::default(),
+            overlays: Default::default(),
+        })
+    }
+
+    /// Fills queue with visible transparent nodes sorted back-to-front. Nodes farther than
+    /// their max render distance are skipped.
+    fn build_queue(&mut self, graph: &Graph, camera: &Camera) {
+        self.queue.clear();
+        self.overlays.clear();
+
+        let camera_position = camera.global_position();
+
+        for (handle, node) in graph.pair_iter() {
+            let radius = match node {
+                Node::Sprite(sprite) => sprite.size(),
+                Node::ParticleSystem(_) | Node::Trail(_) | Node::Text3D(_) => 0.0,
+                _ => continue,
+            };
+
+            if !node.global_visibility() {
+                continue;
+            }
+
+            let distance = (node.global_position() - camera_position).len();
+            if let Some(max_distance) = node.max_render_distance() {
+                if distance - radius > max_distance {
+                    continue;
+                }
+            }
+
+            let item = TransparentItem { node: handle, distance };
+            match node {
+                Node::Sprite(sprite) if !sprite.is_depth_test_enabled() => self.overlays.push(item),
+                _ => self.queue.push(item),
+            }
+        }
+
+        // Sort is stable, so nodes at the same distance keep order of graph.
+        self.queue.sort_by(back_to_front);
+        self.overlays.sort_by(back_to_front);
+    }
+
+    pub fn render(&mut self, args: TransparentRenderContext) -> Result<RenderPassStatistics, RendererError> {
+        scope_profile!();
+
+        let mut statistics = RenderPassStatistics::default();
+
+        let TransparentRenderContext {
+            state, framebuffer, graph, camera,
+            white_dummy, depth, frame_width,
+            frame_height, viewport, texture_cache
+        } = args;
+
+        self.build_queue(graph, camera);
+
+        let mut sprites: Vec<&Sprite> = Vec::new();
+
+        for item in self.queue.iter().chain(self.overlays.iter()) {
+            let node = &graph[item.node];
+
+            if let Node::Sprite(sprite) = node {
+                sprites.push(sprite);
+                continue;
+            }
+
+            // Run of sprites ended, draw it before next node.
+            if !sprites.is_empty() {
+                statistics += self.sprite_renderer.render(SpriteRenderContext {
+                    state,
+                    framebuffer,
+                    sprites: &sprites,
+                    camera,
+                    white_dummy: white_dummy.clone(),
+                    viewport,
+                    textures: texture_cache,
+                })?;
+                sprites.clear();
+            }
+
+            statistics += match node {
+                Node::ParticleSystem(particle_system) => self.particle_system_renderer.render(
+                    ParticleSystemRenderContext {
+                        state,
+                        framebuffer,
+                        particle_system,
+                        camera,
+                        white_dummy: white_dummy.clone(),
+                        depth: depth.clone(),
+                        frame_width,
+                        frame_height,
+                        viewport,
+                        texture_cache,
+                    }),
+                Node::Trail(trail) => self.trail_renderer.render(
+                    TrailRenderContext {
+                        state,
+                        framebuffer,
+                        trail,
+                        camera,
+                        white_dummy: white_dummy.clone(),
+                        viewport,
+                        texture_cache,
+                    }),
+                Node::Text3D(text) => self.text3d_renderer.render(
+                    Text3DRenderContext {
+                        state,
+                        framebuffer,
+                        text,
+                        camera,
+                        viewport,
+                        texture_cache,
+                    }),
+                _ => RenderPassStatistics::default(),
+            };
+        }
+
+        if !sprites.is_empty() {
+            statistics += self.sprite_renderer.render(SpriteRenderContext {
+                state,
+                framebuffer,
+                sprites: &sprites,
+                camera,
+                white_dummy,
+                viewport,
+                textures: texture_cache,
+            })?;
+        }
+
+        Ok(statistics)
+    }
+}
@@ -16,7 +16,7 @@ use crate::{
                 GeometryBuffer,
                 AttributeDefinition,
                 AttributeKind,
-                GeometryBufferKind,
+                DynamicBufferRing,
             },
             gpu_texture::GpuTexture,
             gpu_program::{
@@ -47,6 +47,7 @@ use crate::{
             CommandKind,
             CommandTexture,
         },
+        ttf::Font,
         self,
     },
     resource::texture::{
@@ -63,9 +64,29 @@ use crate::{
         },
         color::Color,
     },
+    utils::into_any_arc,
 };
 use crate::renderer::framework::framebuffer::DrawPartContext;
 
+/// Returns texture of font atlas which is shared by user interface and 3D texts. Texture is
+/// created when font has none and re-created when size of atlas does not match texture, so
+/// font with dynamically rasterized glyphs can either grow its atlas or drop its texture to
+/// get new glyphs uploaded.
+pub(in crate) fn font_atlas_texture(font: &mut Font) -> Option<Arc<Mutex<Texture>>> {
+    let size = font.get_atlas_size() as u32;
+    let current = font.texture.clone().and_then(|texture| texture.downcast::<Mutex<Texture>>().ok());
+    if let Some(texture) = current {
+        if texture.lock().unwrap().width == size {
+            return Some(texture);
+        }
+    }
+    // Old texture is dropped with its GPU copy when nothing uses it anymore.
+    let texture = Texture::from_bytes(size, size, TextureKind::R8, font.get_atlas_pixels().to_vec()).ok()?;
+    let texture = Arc::new(Mutex::new(texture));
+    font.texture = into_any_arc(Some(texture.clone()));
+    Some(texture)
+}
+
 struct UiShader {
     program: GpuProgram,
     wvp_matrix: UniformLocation,
@@ -79,6 +100,7 @@ struct UiShader {
     gradient_origin: UniformLocation,
     gradient_end: UniformLocation,
     resolution: UniformLocation,
+    scale: UniformLocation,
     bounds_min: UniformLocation,
     bounds_max: UniformLocation,
 }
@@ -102,6 +124,7 @@ impl UiShader {
             bounds_min: program.uniform_location("boundsMin")?,
             bounds_max: program.uniform_location("boundsMax")?,
             resolution: program.uniform_location("resolution")?,
+            scale: program.uniform_location("scale")?,
             program,
         })
     }
@@ -118,14 +141,16 @@ pub struct UiRenderContext<'a, 'b, 'c> {
     pub backbuffer: &'b mut BackBuffer,
     pub frame_width: f32,
     pub frame_height: f32,
+    /// Pixels per unit of user interface.
+    pub scale: f32,
     pub drawing_context: &'c DrawingContext,
     pub white_dummy: Rc<RefCell<GpuTexture>>,
     pub texture_cache: &'a mut TextureCache,
 }
 
 impl UiRenderer {
-    pub(in crate::renderer) fn new(state: &mut State) -> Result<Self, RendererError> {
-        let geometry_buffer = GeometryBuffer::new(GeometryBufferKind::DynamicDraw, ElementKind::Triangle);
+    pub(in crate::renderer) fn new(state: &mut State, ring: Rc<RefCell<DynamicBufferRing>>) -> Result<Self, RendererError> {
+        let geometry_buffer = GeometryBuffer::new_in_ring(state, ring, ElementKind::Triangle);
 
         geometry_buffer.bind(state)
             .describe_attributes(vec![
@@ -144,7 +169,7 @@ impl UiRenderer {
 
         let UiRenderContext {
             state, viewport, backbuffer,
-            frame_width, frame_height, drawing_context, white_dummy
+            frame_width, frame_height, scale, drawing_context, white_dummy
             , texture_cache
         } = args;
 
@@ -158,7 +183,8 @@ impl UiRenderer {
             .set_triangles(drawing_context.get_triangles())
             .set_vertices(drawing_context.get_vertices());
 
-        let ortho = Mat4::ortho(0.0, frame_width, frame_height,
+        // Geometry of user interface is in logical units.
+        let ortho = Mat4::ortho(0.0, frame_width / scale, frame_height / scale,
                                 0.0, -1.0, 1.0);
 
         for cmd in drawing_context.get_commands() {
@@ -185,17 +211,10 @@ impl UiRenderer {
                     match cmd.texture() {
                         CommandTexture::Font(font_arc) => {
                             let mut font = font_arc.lock().unwrap();
-                            if font.texture.is_none() {
-                                let tex = Texture::from_bytes(
-                                    font.get_atlas_size() as u32,
-                                    font.get_atlas_size() as u32,
-                                    TextureKind::R8,
-                                    font.get_atlas_pixels().to_vec(),
-                                );
-                                font.texture = Some(Arc::new(Mutex::new(tex)));
-                            }
-                            if let Some(texture) = texture_cache.get(state, font.texture.clone().unwrap().downcast::<Mutex<Texture>>().unwrap()) {
-                                diffuse_texture = texture;
+                            if let Some(texture) = font_atlas_texture(&mut font) {
+                                if let Some(texture) = texture_cache.get(state, texture) {
+                                    diffuse_texture = texture;
+                                }
                             }
                             is_font_texture = true;
                         }
@@ -221,6 +240,7 @@ impl UiRenderer {
                 (self.shader.diffuse_texture, UniformValue::Sampler { index: 0, texture: diffuse_texture }),
                 (self.shader.wvp_matrix, UniformValue::Mat4(ortho)),
                 (self.shader.resolution, UniformValue::Vec2(Vec2::new(frame_width, frame_height))),
+                (self.shader.scale, UniformValue::Float(scale)),
                 (self.shader.bounds_min, UniformValue::Vec2(cmd.min())),
                 (self.shader.bounds_max, UniformValue::Vec2(cmd.max())),
                 (self.shader.is_font, UniformValue::Bool(is_font_texture)),
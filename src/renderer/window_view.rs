@@ -0,0 +1,137 @@
+//! View of a scene shown in secondary window, see `engine::secondary_window` module docs.
+//!
+//! Secondary windows have their own contexts which share textures with main context, but
+//! not frame buffers and vertex arrays, so all rendering is done in main context: camera
+//! of view is rendered into offscreen target, and context of window only copies it to its
+//! back buffer.
+
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
+use crate::{
+    core::{
+        pool::Handle,
+        math::Rect,
+        color::Color,
+    },
+    scene::{
+        Scene,
+        node::Node,
+    },
+    renderer::{
+        error::RendererError,
+        framework::{
+            gpu_texture::{
+                GpuTexture,
+                GpuTextureKind,
+                PixelKind,
+                MininificationFilter,
+                MagnificationFilter,
+                Coordinate,
+                WrapMode,
+            },
+            framebuffer::{
+                FrameBuffer,
+                FrameBufferTrait,
+                Attachment,
+                AttachmentKind,
+                SharedTextureReader,
+            },
+            state::State,
+        },
+    },
+};
+
+/// Scene and camera shown in secondary window.
+pub struct WindowView {
+    pub scene: Handle<Scene>,
+    pub camera: Handle<Node>,
+    /// Size of back buffer of window in pixels.
+    pub(in crate) size: (u32, u32),
+    pub(in crate) target: Option<ViewTarget>,
+}
+
+impl WindowView {
+    pub(in crate) fn new(scene: Handle<Scene>, camera: Handle<Node>, size: (u32, u32)) -> Self {
+        Self {
+            scene,
+            camera,
+            size,
+            target: None,
+        }
+    }
+}
+
+/// Offscreen frame buffer of view in main context.
+pub(in crate) struct ViewTarget {
+    framebuffer: FrameBuffer,
+    width: u32,
+    height: u32,
+}
+
+impl ViewTarget {
+    pub fn new(state: &mut State, width: u32, height: u32) -> Result<Self, RendererError> {
+        let kind = GpuTextureKind::Rectangle { width: width as usize, height: height as usize };
+        let mut texture = GpuTexture::new(state, kind, PixelKind::RGBA8, None)?;
+        texture.bind_mut(state, 0)
+            .set_minification_filter(MininificationFilter::Linear)
+            .set_magnification_filter(MagnificationFilter::Linear)
+            .set_wrap(Coordinate::S, WrapMode::ClampToEdge)
+            .set_wrap(Coordinate::T, WrapMode::ClampToEdge);
+
+        let framebuffer = FrameBuffer::new(state, None, vec![
+            Attachment {
+                kind: AttachmentKind::Color,
+                texture: Rc::new(RefCell::new(texture)),
+            }
+        ])?;
+
+        Ok(Self {
+            framebuffer,
+            width,
+            height,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn clear(&mut self, state: &mut State, color: Color) {
+        let viewport = Rect::new(0, 0, self.width as i32, self.height as i32);
+        self.framebuffer.clear(state, viewport, Some(color), None, None);
+    }
+
+    pub fn framebuffer_mut(&mut self) -> &mut FrameBuffer {
+        &mut self.framebuffer
+    }
+}
+
+/// Copies target of view to back buffer of window, lives in context of window.
+#[derive(Default)]
+pub(in crate) struct ViewPresenter {
+    reader: SharedTextureReader,
+}
+
+impl ViewPresenter {
+    /// Copies last rendered frame of view to back buffer of current context, does nothing if
+    /// view was not rendered yet. Context of window must be current.
+    pub fn present(&mut self, view: &WindowView, window_size: (u32, u32)) {
+        if let Some(target) = view.target.as_ref() {
+            let texture = target.framebuffer.color_attachments()[0].texture.borrow();
+            self.reader.blit_to_back_buffer(&texture, target.size(), window_size);
+        }
+    }
+
+    /// Releases objects of context of window, it must be current.
+    pub fn destroy(&mut self) {
+        self.reader.destroy();
+    }
+}
+
+/// Returns true if camera of given scene is shown in one of views, such cameras are not
+/// rendered into main window.
+pub(in crate) fn is_shown_in_view(views: &[&mut WindowView], scene: Handle<Scene>, camera: Handle<Node>) -> bool {
+    views.iter().any(|view| view.scene == scene && view.camera == camera)
+}
@@ -79,20 +79,44 @@ fn read_string<R>(file: &mut R) -> Result<FbxAttribute, FbxError> where R: Read
     Ok(FbxAttribute::String(string))
 }
 
+/// Reads raw binary data, for example content of embedded media.
+fn read_raw<R>(file: &mut R) -> Result<FbxAttribute, FbxError> where R: Read {
+    let length = file.read_u32::<LittleEndian>()? as usize;
+    let mut data = vec![0; length];
+    file.read_exact(data.as_mut_slice())?;
+    Ok(FbxAttribute::Raw(data))
+}
+
 /// Read binary FBX DOM using this specification:
 /// https://code.blender.org/2013/08/fbx-binary-file-format-specification/
 /// In case of success returns Ok(valid_handle), in case if no more nodes
 /// are present returns Ok(none_handle), in case of error returns some FbxError.
-fn read_binary_node<R>(file: &mut R, pool: &mut Pool<FbxNode>) -> Result<Handle<FbxNode>, FbxError>
+///
+/// Starting from version 7500 offsets and counts in node header are 64-bit, so null
+/// record is longer too.
+fn read_binary_node<R>(file: &mut R, pool: &mut Pool<FbxNode>, version: i32) -> Result<Handle<FbxNode>, FbxError>
     where R: Read + Seek {
-    let end_offset = u64::from(file.read_u32::<LittleEndian>()?);
+    let is_64_bit = version >= 7500;
+
+    let end_offset = if is_64_bit {
+        file.read_u64::<LittleEndian>()?
+    } else {
+        u64::from(file.read_u32::<LittleEndian>()?)
+    };
     if end_offset == 0 {
         // Footer found. We're done.
         return Ok(Handle::NONE);
     }
 
-    let num_attrib = file.read_u32::<LittleEndian>()? as usize;
-    let _attrib_list_len = file.read_u32::<LittleEndian>()?;
+    let num_attrib = if is_64_bit {
+        let num_attrib = file.read_u64::<LittleEndian>()? as usize;
+        let _attrib_list_len = file.read_u64::<LittleEndian>()?;
+        num_attrib
+    } else {
+        let num_attrib = file.read_u32::<LittleEndian>()? as usize;
+        let _attrib_list_len = file.read_u32::<LittleEndian>()?;
+        num_attrib
+    };
 
     // Read name.
     let name_len = file.read_u8()? as usize;
@@ -122,19 +146,18 @@ fn read_binary_node<R>(file: &mut R, pool: &mut Pool<FbxNode>) -> Result<Handle<
                 node.children.push(a_handle);
             }
             b'S' => pool.borrow_mut(node_handle).attributes.push(read_string(file)?),
-            b'R' => {
-                // Ignore Raw data
-                let length = i64::from(file.read_u32::<LittleEndian>()?);
-                file.seek(SeekFrom::Current(length))?;
-            }
-            _ => ()
+            b'R' => pool.borrow_mut(node_handle).attributes.push(read_raw(file)?),
+            // Size of attribute of unknown type is unknown too, so rest of the file
+            // can't be read.
+            _ => return Err(FbxError::UnknownAttributeType(type_code))
         }
     }
 
     if file.seek(SeekFrom::Current(0))? < end_offset {
-        let null_record_position = end_offset - 13;
+        let null_record_len = if is_64_bit { 25 } else { 13 };
+        let null_record_position = end_offset - null_record_len as u64;
         while file.seek(SeekFrom::Current(0))? < null_record_position {
-            let child_handle = read_binary_node(file, pool)?;
+            let child_handle = read_binary_node(file, pool, version)?;
             if child_handle.is_none() {
                 return Ok(child_handle);
             }
@@ -143,8 +166,9 @@ fn read_binary_node<R>(file: &mut R, pool: &mut Pool<FbxNode>) -> Result<Handle<
         }
 
         // Check if we have a null-record
-        let mut null_record = [0; 13];
-        file.read_exact(&mut null_record)?;
+        let mut null_record = [0; 25];
+        let null_record = &mut null_record[..null_record_len];
+        file.read_exact(null_record)?;
         if !null_record.iter().all(|i| *i == 0) {
             return Err(FbxError::InvalidNullRecord);
         }
@@ -164,7 +188,7 @@ pub fn read_binary<R>(file: &mut R) -> Result<FbxDocument, FbxError>
 
     // Verify version.
     let version = file.read_u32::<LittleEndian>()? as i32;
-    if version < 7100 || version > 7400 {
+    if version < 7100 || version > 7700 {
         return Err(FbxError::UnsupportedVersion(version));
     }
 
@@ -176,7 +200,7 @@ pub fn read_binary<R>(file: &mut R) -> Result<FbxDocument, FbxError>
     // FBX document can have multiple root nodes, so we must read the file
     // until the end.
     while file.seek(SeekFrom::Current(0))? < total_length {
-        let root_child = read_binary_node(file, &mut nodes)?;
+        let root_child = read_binary_node(file, &mut nodes, version)?;
         if root_child.is_none() {
             break;
         }
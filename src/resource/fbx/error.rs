@@ -1,4 +1,5 @@
 use std::fmt::Formatter;
+use crate::renderer::surface::Surface;
 
 #[derive(Debug)]
 pub enum FbxError {
@@ -16,6 +17,7 @@ pub enum FbxError {
     UnableToRemapModelToNode,
     InvalidMapping,
     InvalidReference,
+    TooManyBones(usize),
 }
 
 impl std::fmt::Display for FbxError {
@@ -35,6 +37,7 @@ impl std::fmt::Display for FbxError {
             FbxError::UnableToRemapModelToNode => write!(f, "Unable to remap model to node."),
             FbxError::InvalidMapping => write!(f, "Unknown mapping"),
             FbxError::InvalidReference => write!(f, "Unknown reference"),
+            FbxError::TooManyBones(count) => write!(f, "Surface uses {} bones, but only {} is supported.", count, Surface::MAX_BONES),
         }
     }
 }
@@ -3,7 +3,7 @@ mod document;
 pub mod error;
 
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     collections::{HashMap, HashSet},
     time::Instant,
     sync::{Arc, Mutex},
@@ -13,6 +13,7 @@ use crate::{
     renderer::surface::{
         SurfaceSharedData,
         Surface,
+        TangentMethod,
         Vertex,
         VertexWeightSet,
     },
@@ -29,8 +30,9 @@ use crate::{
         mesh::Mesh,
         base::Base,
     },
-    engine::resource_manager::ResourceManager,
+    engine::resource_manager::{ResourceManager, file_name_of},
     core::{
+        color::Color,
         pool::Handle,
         math::{
             vec4::Vec4,
@@ -47,6 +49,7 @@ use crate::{
     },
     resource::{
         texture::TextureKind,
+        import::ModelImportOptions,
         fbx::{
             scene::{
                 animation::FbxAnimationCurveNodeType,
@@ -55,6 +58,7 @@ use crate::{
                 FbxComponent,
                 FbxScene,
                 model::FbxModel,
+                camera::FbxCamera,
             },
             error::FbxError,
             document::FbxDocument,
@@ -143,6 +147,8 @@ struct UnpackedVertex {
     normal: Vec3,
     tangent: Vec3,
     uv: Vec2,
+    second_uv: Vec2,
+    color: Color,
     // Set of weights for skinning.
     weights: Option<VertexWeightSet>,
 }
@@ -158,6 +164,8 @@ impl Into<Vertex> for UnpackedVertex {
             // when all nodes will be converted.
             bone_weights: Default::default(),
             bone_indices: Default::default(),
+            second_tex_coord: self.second_uv,
+            color: self.color,
         }
     }
 }
@@ -188,6 +196,16 @@ fn convert_vertex(geom: &FbxGeometry,
         None => Vec2::ZERO
     };
 
+    let second_uv = match geom.second_uvs.as_ref() {
+        Some(uvs) => *uvs.get(index, index_in_polygon)?,
+        None => Vec2::ZERO
+    };
+
+    let color = match geom.colors.as_ref() {
+        Some(colors) => *colors.get(index, index_in_polygon)?,
+        None => Color::WHITE
+    };
+
     let material = match geom.materials.as_ref() {
         Some(materials) => *materials.get(material_index, index_in_polygon)?,
         None => 0
@@ -198,6 +216,8 @@ fn convert_vertex(geom: &FbxGeometry,
         normal: geometric_transform.transform_vector_normal(normal),
         tangent: geometric_transform.transform_vector_normal(tangent),
         uv: Vec2 { x: uv.x, y: -uv.y }, // Invert Y because OpenGL has origin at left *bottom* corner.
+        second_uv: Vec2 { x: second_uv.x, y: -second_uv.y },
+        color,
         surface: material as usize,
         weights: if skin_data.is_empty() {
             None
@@ -213,39 +233,117 @@ struct SurfaceData {
     skin_data: Vec<VertexWeightSet>,
 }
 
+fn make_surface_data(builder: RawMeshBuilder<Vertex>) -> SurfaceSharedData {
+    let mut raw = builder.build();
+    // Only triangles are reordered here, vertices must stay in the same order
+    // because skin data is stored in parallel array.
+    raw.optimize_vertex_cache();
+    raw.optimize_overdraw(1.05);
+    SurfaceSharedData::from(raw)
+}
+
+/// Tells where to look for textures of materials.
+struct TextureLookup {
+    /// Directories in which textures are searched before textures path of resource manager.
+    search_paths: Vec<PathBuf>,
+    /// Paths of extracted embedded textures, by handle of texture component.
+    embedded: HashMap<Handle<FbxComponent>, PathBuf>,
+}
+
+impl TextureLookup {
+    fn texture_path(&self, resource_manager: &ResourceManager, texture_handle: Handle<FbxComponent>, filename: &str) -> PathBuf {
+        let default_path = resource_manager.textures_path().join(filename);
+        self.search_paths
+            .iter()
+            .map(|dir| dir.join(filename))
+            .chain(std::iter::once(default_path.clone()))
+            .find(|path| resource_manager.resolve_path(path).is_some())
+            .or_else(|| self.embedded.get(&texture_handle).cloned())
+            .unwrap_or(default_path)
+    }
+}
+
+/// Writes content of embedded media into `media_dir`, so it can be loaded as usual texture.
+/// Existing files are not overwritten, they could be edited by user. Returns paths of
+/// extracted textures by handle of texture component.
+fn extract_embedded_media(fbx_scene: &FbxScene, media_dir: &Path) -> HashMap<Handle<FbxComponent>, PathBuf> {
+    let mut embedded = HashMap::new();
+    for (texture_handle, component) in fbx_scene.pair_iter() {
+        if let FbxComponent::Texture(texture) = component {
+            if texture.video.is_none() {
+                continue;
+            }
+            if let Ok(video) = fbx_scene.get(texture.video).as_video() {
+                if video.content().is_empty() {
+                    continue;
+                }
+                if let Some(filename) = file_name_of(video.get_file_path()) {
+                    let path = media_dir.join(filename);
+                    if !path.exists() {
+                        let result = std::fs::create_dir_all(media_dir)
+                            .and_then(|_| std::fs::write(&path, video.content()));
+                        if let Err(e) = result {
+                            Log::writeln(format!("FBX: Unable to extract embedded texture to {:?}. Reason: {}", path, e));
+                            continue;
+                        }
+                    }
+                    embedded.insert(texture_handle, path);
+                }
+            }
+        }
+    }
+    embedded
+}
+
 fn create_surfaces(fbx_scene: &FbxScene,
                    data_set: Vec<SurfaceData>,
                    mesh: &mut Mesh,
                    resource_manager: &mut ResourceManager,
+                   texture_lookup: &TextureLookup,
                    model: &FbxModel) -> Result<(), FbxError> {
     // Create surfaces per material
     if model.materials.is_empty() {
         assert_eq!(data_set.len(), 1);
         let data = data_set.into_iter().next().unwrap();
-        let mut surface = Surface::new(Arc::new(Mutex::new(SurfaceSharedData::from(data.builder.build()))));
+        let mut surface = Surface::new(Arc::new(Mutex::new(make_surface_data(data.builder))));
         surface.vertex_weights = data.skin_data;
         mesh.add_surface(surface);
     } else {
         assert_eq!(data_set.len(), model.materials.len());
         for (&material_handle, data) in model.materials.iter().zip(data_set.into_iter()) {
-            let mut surface = Surface::new(Arc::new(Mutex::new(SurfaceSharedData::from(data.builder.build()))));
+            let mut surface = Surface::new(Arc::new(Mutex::new(make_surface_data(data.builder))));
             surface.vertex_weights = data.skin_data;
             let material = fbx_scene.get(material_handle).as_material()?;
             for (name, texture_handle) in material.textures.iter() {
                 let texture = fbx_scene.get(*texture_handle).as_texture()?;
                 let path = texture.get_file_path();
-                if let Some(filename) = path.file_name() {
-                    let diffuse_path = resource_manager.textures_path().join(&filename);
+                // Path can be written on other platform, so file name is extracted manually.
+                if let Some(filename) = file_name_of(path) {
+                    let texture_path = texture_lookup.texture_path(resource_manager, *texture_handle, &filename);
                     // Here we will load *every* texture as RGBA8, this probably is overkill,
                     // that will lead to higher memory consumption, but this will remove
                     // problems with transparent textures (like mesh texture, etc.)
-                    let texture = resource_manager.request_texture_async(diffuse_path.as_path(), TextureKind::RGBA8);
                     match name.as_str() {
-                        "AmbientColor" => (), // TODO: Add ambient occlusion (AO) map support.
-                        "DiffuseColor" => surface.set_diffuse_texture(texture),
+                        "DiffuseColor" => surface.set_diffuse_texture(
+                            resource_manager.request_texture_async(texture_path.as_path(), TextureKind::RGBA8)),
                         // No idea why it can be different for normal maps.
-                        "Bump" | "NormalMap" => surface.set_normal_texture(texture),
-                        _ => ()
+                        "Bump" | "NormalMap" => surface.set_normal_texture(
+                            resource_manager.request_texture_async(texture_path.as_path(), TextureKind::RGBA8)),
+                        // TODO: Add ambient occlusion (AO) map support.
+                        _ => Log::writeln(format!("FBX: {} texture of material {} is not supported, texture {} is ignored.",
+                                                  name, material.name, filename)),
+                    }
+                }
+            }
+            if let Some(reflectivity) = material.reflectivity {
+                surface.set_reflectivity(reflectivity);
+            }
+            if surface.get_diffuse_texture().is_none() {
+                if let Some(diffuse_color) = material.diffuse_color {
+                    // Surface without texture is rendered using white texture, so color of
+                    // material is applied through vertex colors.
+                    for vertex in surface.get_data().lock().unwrap().get_vertices_mut() {
+                        vertex.color = modulate(vertex.color, diffuse_color);
                     }
                 }
             }
@@ -256,8 +354,15 @@ fn create_surfaces(fbx_scene: &FbxScene,
     Ok(())
 }
 
+fn modulate(a: Color, b: Color) -> Color {
+    let mul = |a: u8, b: u8| ((u16::from(a) * u16::from(b)) / 255) as u8;
+    Color::from_rgba(mul(a.r, b.r), mul(a.g, b.g), mul(a.b, b.b), mul(a.a, b.a))
+}
+
 fn convert_mesh(fbx_scene: &FbxScene,
                 resource_manager: &mut ResourceManager,
+                options: &ModelImportOptions,
+                texture_lookup: &TextureLookup,
                 model: &FbxModel) -> Result<Mesh, FbxError> {
     let mut mesh = Mesh::default();
 
@@ -288,8 +393,14 @@ fn convert_mesh(fbx_scene: &FbxScene,
             let origin = n;
             n += prepare_next_face(&geom.vertices, &geom.indices[origin..], &mut temp_vertices, &mut triangles, &mut face_triangles);
             for (triangle, face_triangle) in triangles.iter().zip(face_triangles.iter()) {
-                for (&index, &face_vertex_index) in triangle.iter().zip(face_triangle.iter()) {
-                    let polygon_vertex_index = origin + face_vertex_index;
+                let mut corners = [0, 1, 2];
+                if options.flip_handedness {
+                    // Model is mirrored, so winding must be reversed to keep faces front-facing.
+                    corners.reverse();
+                }
+                for &corner in corners.iter() {
+                    let index = triangle[corner];
+                    let polygon_vertex_index = origin + face_triangle[corner];
                     let vertex = convert_vertex(geom, &geometric_transform, material_index, index, polygon_vertex_index, &skin_data)?;
                     let data = data_set.get_mut(vertex.surface).unwrap();
                     let weights = vertex.weights;
@@ -308,14 +419,23 @@ fn convert_mesh(fbx_scene: &FbxScene,
             }
         }
 
-        create_surfaces(fbx_scene, data_set, &mut mesh, resource_manager, model)?;
+        create_surfaces(fbx_scene, data_set, &mut mesh, resource_manager, texture_lookup, model)?;
 
-        if geom.tangents.is_none() {
+        if geom.tangents.is_none() && options.generate_tangents {
             for surface in mesh.surfaces_mut() {
                 surface.get_data()
                     .lock()
                     .unwrap()
-                    .calculate_tangents();
+                    .calculate_tangents(options.tangent_method);
+            }
+        }
+    }
+
+    if options.flip_handedness {
+        // Mirroring flips bitangents, compensate it by handedness of tangents.
+        for surface in mesh.surfaces_mut() {
+            for vertex in surface.get_data().lock().unwrap().get_vertices_mut() {
+                vertex.tangent.w = -vertex.tangent.w;
             }
         }
     }
@@ -326,6 +446,8 @@ fn convert_mesh(fbx_scene: &FbxScene,
 fn convert_model(fbx_scene: &FbxScene,
                  model: &FbxModel,
                  resource_manager: &mut ResourceManager,
+                 options: &ModelImportOptions,
+                 texture_lookup: &TextureLookup,
                  graph: &mut Graph,
                  animations: &mut AnimationContainer,
                  animation_handle: Handle<Animation>)
@@ -333,21 +455,30 @@ fn convert_model(fbx_scene: &FbxScene,
     // Create node with correct kind.
     let mut node =
         if !model.geoms.is_empty() {
-            Node::Mesh(convert_mesh(fbx_scene, resource_manager, model)?)
-        } else if model.light.is_some() {
+            Node::Mesh(convert_mesh(fbx_scene, resource_manager, options, texture_lookup, model)?)
+        } else if model.light.is_some() && options.import_lights {
             let fbx_light_component = fbx_scene.get(model.light);
             Node::Light(fbx_light_component.as_light()?.convert())
+        } else if model.camera.is_some() && options.import_cameras {
+            let fbx_camera_component = fbx_scene.get(model.camera);
+            Node::Camera(fbx_camera_component.as_camera()?.convert())
         } else {
             Node::Base(Base::default())
         };
 
     let node_local_rotation = quat_from_euler(model.rotation);
+    let mut post_rotation = quat_from_euler(model.post_rotation);
+    if let Node::Camera(_) = node {
+        // Inverse of post rotation is applied after rotation, so correction is applied in
+        // local space of camera and it is not affected by animation of rotation.
+        post_rotation = FbxCamera::post_rotation_correction() * post_rotation;
+    }
     node.set_name(model.name.as_str())
         .local_transform_mut()
         .set_rotation(node_local_rotation)
         .set_scale(model.scale)
         .set_position(model.translation)
-        .set_post_rotation(quat_from_euler(model.post_rotation))
+        .set_post_rotation(post_rotation)
         .set_pre_rotation(quat_from_euler(model.pre_rotation))
         .set_rotation_offset(model.rotation_offset)
         .set_rotation_pivot(model.rotation_pivot)
@@ -358,7 +489,7 @@ fn convert_model(fbx_scene: &FbxScene,
     let node_handle = graph.add_node(node);
 
     // Convert animations
-    if !model.animation_curve_nodes.is_empty() {
+    if !model.animation_curve_nodes.is_empty() && animation_handle.is_some() {
         // Find supported curve nodes (translation, rotation, scale)
         let mut lcl_translation = None;
         let mut lcl_rotation = None;
@@ -435,13 +566,34 @@ fn convert(
     fbx_scene: &FbxScene,
     resource_manager: &mut ResourceManager,
     scene: &mut Scene,
+    options: &ModelImportOptions,
+    model_path: &Path,
 ) -> Result<Handle<Node>, FbxError> {
     let root = scene.graph.add_node(Node::Base(Base::default()));
-    let animation_handle = scene.animations.add(Animation::default());
+    scene.graph[root]
+        .local_transform_mut()
+        .set_rotation(options.root_rotation())
+        .set_scale(options.root_scale());
+    let animation_handle = if options.import_animations {
+        scene.animations.add(Animation::default())
+    } else {
+        Handle::NONE
+    };
+    let model_dir = model_path.parent().unwrap_or_else(|| Path::new(""));
+    // Embedded media is extracted next to model in the same way as FBX SDK does it.
+    let media_dir = model_path.with_extension("fbm");
+    let texture_lookup = TextureLookup {
+        search_paths: options.material_search_paths
+            .iter()
+            .map(|path| model_dir.join(path))
+            .collect(),
+        embedded: extract_embedded_media(fbx_scene, &media_dir),
+    };
     let mut fbx_model_to_node_map = HashMap::new();
     for (component_handle, component) in fbx_scene.pair_iter() {
         if let FbxComponent::Model(model) = component {
-            let node = convert_model(fbx_scene, model, resource_manager, &mut scene.graph, &mut scene.animations, animation_handle)?;
+            let node = convert_model(fbx_scene, model, resource_manager, options, &texture_lookup,
+                                     &mut scene.graph, &mut scene.animations, animation_handle)?;
             scene.graph.link_nodes(node, root);
             fbx_model_to_node_map.insert(component_handle, node);
         }
@@ -474,6 +626,9 @@ fn convert(
                     }
                 }
                 surface.bones = surface_bones.iter().copied().collect();
+                if surface.bones.len() > Surface::MAX_BONES {
+                    return Err(FbxError::TooManyBones(surface.bones.len()));
+                }
 
                 let data_rc = surface.get_data();
                 let mut data = data_rc.lock().unwrap();
@@ -496,7 +651,10 @@ fn convert(
 }
 
 
-pub fn load_to_scene<P: AsRef<Path>>(scene: &mut Scene, resource_manager: &mut ResourceManager, path: P) -> Result<Handle<Node>, FbxError> {
+pub fn load_to_scene<P: AsRef<Path>>(scene: &mut Scene,
+                                     resource_manager: &mut ResourceManager,
+                                     path: P,
+                                     options: &ModelImportOptions) -> Result<Handle<Node>, FbxError> {
     let start_time = Instant::now();
 
     Log::writeln(format!("Trying to load {:?}", path.as_ref()));
@@ -510,7 +668,7 @@ pub fn load_to_scene<P: AsRef<Path>>(scene: &mut Scene, resource_manager: &mut R
     let dom_prepare_time = now.elapsed().as_millis();
 
     let now = Instant::now();
-    let result = convert(&fbx_scene, resource_manager, scene);
+    let result = convert(&fbx_scene, resource_manager, scene, options, path.as_ref());
     let conversion_time = now.elapsed().as_millis();
 
     Log::writeln(format!("FBX {:?} loaded in {} ms\n\t- Parsing - {} ms\n\t- DOM Prepare - {} ms\n\t- Conversion - {} ms",
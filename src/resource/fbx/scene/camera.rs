@@ -0,0 +1,81 @@
+use crate::{
+    core::{
+        pool::Handle,
+        math::{
+            quat::Quat,
+            vec3::Vec3,
+        },
+    },
+    resource::fbx::document::{
+        FbxNodeContainer,
+        FbxNode,
+    },
+    scene::{
+        base::BaseBuilder,
+        camera::{Camera, CameraBuilder},
+    },
+};
+
+pub struct FbxCamera {
+    fov: f32,
+    z_near: f32,
+    z_far: f32,
+}
+
+impl FbxCamera {
+    pub(in crate::resource::fbx) fn read(camera_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer) -> Result<Self, String> {
+        let mut camera = Self {
+            fov: 75.0f32.to_radians(),
+            z_near: 0.025,
+            z_far: 2048.0,
+        };
+
+        let mut field_of_view = None;
+        let mut aperture_mode = 0;
+        let mut aspect_width = 1.0;
+        let mut aspect_height = 1.0;
+
+        let props = nodes.get_by_name(camera_node_handle, "Properties70")?;
+        for prop_handle in props.children() {
+            let prop = nodes.get(*prop_handle);
+            match prop.get_attrib(0)?.as_string().as_str() {
+                "FieldOfView" => field_of_view = Some((prop.get_attrib(4)?.as_f64()? as f32).to_radians()),
+                "ApertureMode" => aperture_mode = prop.get_attrib(4)?.as_i32()?,
+                "AspectWidth" => aspect_width = prop.get_attrib(4)?.as_f64()? as f32,
+                "AspectHeight" => aspect_height = prop.get_attrib(4)?.as_f64()? as f32,
+                "NearPlane" => camera.z_near = prop.get_attrib(4)?.as_f64()? as f32,
+                "FarPlane" => camera.z_far = prop.get_attrib(4)?.as_f64()? as f32,
+                _ => ()
+            }
+        }
+
+        if let Some(fov) = field_of_view {
+            // Engine uses vertical field of view, FBX uses horizontal unless aperture
+            // mode is "Vertical" (2).
+            camera.fov = if aperture_mode == 2 || aspect_width <= 0.0 || aspect_height <= 0.0 {
+                fov
+            } else {
+                let aspect = aspect_width / aspect_height;
+                2.0 * ((fov * 0.5).tan() / aspect).atan()
+            };
+        }
+
+        Ok(camera)
+    }
+
+    /// Returns post rotation which turns engine camera (looks along Z) to look along X
+    /// like FBX cameras do. Up axis is Y in both cases. Post rotation is inverted when
+    /// applied, so this is rotation by -90 degrees instead of 90.
+    pub fn post_rotation_correction() -> Quat {
+        Quat::from_axis_angle(Vec3::UP, -std::f32::consts::FRAC_PI_2)
+    }
+
+    /// Imported cameras are disabled, otherwise every camera of model would render.
+    pub fn convert(&self) -> Camera {
+        CameraBuilder::new(BaseBuilder::new().with_enabled(false))
+            .with_fov(self.fov)
+            .with_z_near(self.z_near)
+            .with_z_far(self.z_far)
+            .build()
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     core::{
+        color::Color,
         math::{
             vec3::Vec3,
             vec2::Vec2,
@@ -11,6 +12,7 @@ use crate::{
             document::{
                 FbxNodeContainer,
                 FbxNode,
+                attribute::FbxAttribute,
             },
             error::FbxError,
             scene::{
@@ -25,6 +27,7 @@ use crate::{
         VertexWeightSet,
         VertexWeight,
     },
+    utils::log::Log,
 };
 
 pub struct FbxGeometry {
@@ -35,6 +38,9 @@ pub struct FbxGeometry {
     // Normals, UVs, etc. are optional.
     pub normals: Option<FbxContainer<Vec3>>,
     pub uvs: Option<FbxContainer<Vec2>>,
+    /// Second UV set, usually used for lightmaps.
+    pub second_uvs: Option<FbxContainer<Vec2>>,
+    pub colors: Option<FbxContainer<Color>>,
     pub materials: Option<FbxContainer<i32>>,
     pub tangents: Option<FbxContainer<Vec3>>,
     pub binormals: Option<FbxContainer<Vec3>>,
@@ -96,18 +102,67 @@ fn read_binormals(geom_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer)
     }
 }
 
+/// Returns handles of all layer elements of given kind in the order of their indices,
+/// geometry can have few layers of the same kind (for example few UV sets).
+fn find_layer_elements(geom_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer, name: &str)
+                       -> Result<Vec<Handle<FbxNode>>, FbxError> {
+    let mut layer_elements = Vec::new();
+    for &child_handle in nodes.get(geom_node_handle).children() {
+        let child = nodes.get(child_handle);
+        if child.name() == name {
+            layer_elements.push((child.get_attrib(0)?.as_i32()?, child_handle));
+        }
+    }
+    layer_elements.sort_by_key(|&(index, _)| index);
+    Ok(layer_elements.into_iter().map(|(_, handle)| handle).collect())
+}
+
+fn read_uv_set(layer_element_uv: Handle<FbxNode>, nodes: &FbxNodeContainer)
+               -> Result<FbxContainer<Vec2>, FbxError> {
+    FbxContainer::new(nodes, layer_element_uv, "UV", |attributes| {
+        let mut uvs = Vec::with_capacity(attributes.len() / 2);
+        for uv in attributes.chunks_exact(2) {
+            uvs.push(Vec2 {
+                x: uv[0].as_f32()?,
+                y: uv[1].as_f32()?,
+            });
+        }
+        Ok(uvs)
+    })
+}
+
+/// Reads first and second UV sets, rest of sets are ignored since vertex has only
+/// two texture coordinates.
 fn read_uvs(geom_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer)
-            -> Result<Option<FbxContainer<Vec2>>, FbxError> {
-    if let Ok(layer_element_uv) = nodes.find(geom_node_handle, "LayerElementUV") {
-        Ok(Some(FbxContainer::new(nodes, layer_element_uv, "UV",  |attributes| {
-            let mut uvs = Vec::with_capacity(attributes.len() / 2);
-            for uv in attributes.chunks_exact(2) {
-                uvs.push(Vec2 {
-                    x: uv[0].as_f32()?,
-                    y: uv[1].as_f32()?,
-                });
+            -> Result<(Option<FbxContainer<Vec2>>, Option<FbxContainer<Vec2>>), FbxError> {
+    let layer_elements = find_layer_elements(geom_node_handle, nodes, "LayerElementUV")?;
+    if layer_elements.len() > 2 {
+        Log::writeln(format!("FBX: Geometry has {} UV sets, only first two are imported.", layer_elements.len()));
+    }
+    let mut sets = layer_elements.into_iter();
+    let uvs = match sets.next() {
+        Some(handle) => Some(read_uv_set(handle, nodes)?),
+        None => None
+    };
+    let second_uvs = match sets.next() {
+        Some(handle) => Some(read_uv_set(handle, nodes)?),
+        None => None
+    };
+    Ok((uvs, second_uvs))
+}
+
+fn read_colors(geom_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer)
+               -> Result<Option<FbxContainer<Color>>, FbxError> {
+    if let Ok(layer_element_color) = nodes.find(geom_node_handle, "LayerElementColor") {
+        Ok(Some(FbxContainer::new(nodes, layer_element_color, "Colors", |attributes| {
+            let to_u8 = |attribute: &FbxAttribute| -> Result<u8, FbxError> {
+                Ok((attribute.as_f32()?.max(0.0).min(1.0) * 255.0) as u8)
+            };
+            let mut colors = Vec::with_capacity(attributes.len() / 4);
+            for color in attributes.chunks_exact(4) {
+                colors.push(Color::from_rgba(to_u8(&color[0])?, to_u8(&color[1])?, to_u8(&color[2])?, to_u8(&color[3])?));
             }
-            Ok(uvs)
+            Ok(colors)
         })?))
     } else {
         Ok(None)
@@ -131,11 +186,14 @@ fn read_materials(geom_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer)
 
 impl FbxGeometry {
     pub(in crate::resource::fbx) fn read(geom_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer) -> Result<FbxGeometry, FbxError> {
+        let (uvs, second_uvs) = read_uvs(geom_node_handle, nodes)?;
         Ok(FbxGeometry {
             vertices: read_vertices(geom_node_handle, nodes)?,
             indices: read_indices(geom_node_handle, nodes)?,
             normals: read_normals(geom_node_handle, nodes)?,
-            uvs: read_uvs(geom_node_handle, nodes)?,
+            uvs,
+            second_uvs,
+            colors: read_colors(geom_node_handle, nodes)?,
             materials: read_materials(geom_node_handle, nodes)?,
             tangents: read_tangents(geom_node_handle, nodes)?,
             binormals: read_binormals(geom_node_handle, nodes)?,
@@ -11,6 +11,7 @@ use crate::{
     },
     utils::log::Log,
     scene::light::{
+        DEFAULT_INTENSITY,
         LightKind,
         PointLight,
         SpotLight,
@@ -30,6 +31,8 @@ pub struct FbxLight {
     actual_type: FbxLightType,
     color: Color,
     radius: f32,
+    intensity: f32,
+    cast_shadows: bool,
     hotspot_cone_angle: f32,
     falloff_cone_angle_delta: f32,
 }
@@ -40,15 +43,25 @@ impl FbxLight {
             actual_type: FbxLightType::Point,
             color: Color::WHITE,
             radius: 10.0,
+            intensity: DEFAULT_INTENSITY,
+            cast_shadows: true,
             hotspot_cone_angle: 90.0f32.to_radians(),
             falloff_cone_angle_delta: 5.0f32.to_radians(),
         };
 
+        let mut far_attenuation = None;
+        let mut far_attenuation_end = 0.0;
+
         let props = nodes.get_by_name(light_node_handle, "Properties70")?;
         for prop_handle in props.children() {
             let prop = nodes.get(*prop_handle);
             match prop.get_attrib(0)?.as_string().as_str() {
                 "DecayStart" => light.radius = prop.get_attrib(4)?.as_f64()? as f32,
+                "EnableFarAttenuation" => far_attenuation = Some(prop.get_attrib(4)?.as_i32()? != 0),
+                "FarAttenuationEnd" => far_attenuation_end = prop.get_attrib(4)?.as_f64()? as f32,
+                // Intensity is in percents, 100% matches default intensity of engine lights.
+                "Intensity" => light.intensity = DEFAULT_INTENSITY * prop.get_attrib(4)?.as_f64()? as f32 / 100.0,
+                "CastShadows" => light.cast_shadows = prop.get_attrib(4)?.as_i32()? != 0,
                 "Color" => {
                     let r = (prop.get_attrib(4)?.as_f64()? * 255.0) as u8;
                     let g = (prop.get_attrib(5)?.as_f64()? * 255.0) as u8;
@@ -79,6 +92,12 @@ impl FbxLight {
             }
         }
 
+        // Far attenuation defines where light fades out completely, so it is the best
+        // match for radius of light.
+        if far_attenuation == Some(true) && far_attenuation_end > 0.0 {
+            light.radius = far_attenuation_end;
+        }
+
         Ok(light)
     }
 
@@ -98,6 +117,8 @@ impl FbxLight {
         let mut light = Light::new(light_kind);
 
         light.set_color(Color::opaque(self.color.r, self.color.g, self.color.b));
+        light.set_intensity(self.intensity);
+        light.set_cast_shadows(self.cast_shadows);
 
         light
     }
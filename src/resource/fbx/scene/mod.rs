@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use crate::{
     core::{
+        color::Color,
         pool::{
             Handle,
             Pool,
@@ -21,7 +22,8 @@ use crate::{
         error::FbxError,
         scene::{
             light::FbxLight,
-            texture::FbxTexture,
+            camera::FbxCamera,
+            texture::{FbxTexture, FbxVideo},
             geometry::FbxGeometry,
             animation::{
                 FbxAnimationCurve,
@@ -30,11 +32,13 @@ use crate::{
             model::FbxModel,
         },
     },
+    utils::log::Log,
 };
 
 pub mod animation;
 pub mod geometry;
 pub mod light;
+pub mod camera;
 pub mod model;
 pub mod texture;
 
@@ -48,6 +52,7 @@ impl FbxScene {
     pub fn new(document: &FbxDocument) -> Result<FbxScene, FbxError> {
         let mut components = Pool::new();
         let mut index_to_component = HashMap::new();
+        let mut unsupported = HashMap::new();
 
         let nodes = document.nodes();
 
@@ -55,7 +60,7 @@ impl FbxScene {
         let header_handle = nodes.find(document.root(), "FBXHeaderExtension")?;
         let version = nodes.get_by_name(header_handle, "FBXVersion")?;
         let version = version.get_attrib(0)?.as_i32()?;
-        if version < 7100 && version > 7400 {
+        if version < 7100 || version > 7700 {
             return Err(FbxError::UnsupportedVersion(version));
         }
 
@@ -67,8 +72,14 @@ impl FbxScene {
             let mut component_handle: Handle<FbxComponent> = Handle::NONE;
             match object.name() {
                 "Geometry" => {
-                    component_handle = components.spawn(FbxComponent::Geometry(
-                        Box::new(FbxGeometry::read(*object_handle, nodes)?)));
+                    match object.get_attrib(2)?.as_string().as_str() {
+                        "Mesh" => {
+                            component_handle = components.spawn(FbxComponent::Geometry(
+                                Box::new(FbxGeometry::read(*object_handle, nodes)?)));
+                        }
+                        // Blend shapes, curves, etc.
+                        kind => *unsupported.entry(format!("Geometry ({})", kind)).or_insert(0) += 1,
+                    }
                 }
                 "Model" => {
                     component_handle = components.spawn(FbxComponent::Model(
@@ -76,16 +87,31 @@ impl FbxScene {
                 }
                 "Material" => {
                     component_handle = components.spawn(FbxComponent::Material(
-                        FbxMaterial::read(*object_handle)?));
+                        FbxMaterial::read(*object_handle, nodes)?));
                 }
                 "Texture" => {
                     component_handle = components.spawn(FbxComponent::Texture(
                         FbxTexture::read(*object_handle, nodes)?));
                 }
+                "Video" => {
+                    component_handle = components.spawn(FbxComponent::Video(
+                        FbxVideo::read(*object_handle, nodes)?));
+                }
                 "NodeAttribute" => {
-                    if object.attrib_count() > 2 && object.get_attrib(2)?.as_string() == "Light" {
-                        component_handle = components.spawn(FbxComponent::Light(
-                            FbxLight::read(*object_handle, nodes)?));
+                    if object.attrib_count() > 2 {
+                        match object.get_attrib(2)?.as_string().as_str() {
+                            "Light" => {
+                                component_handle = components.spawn(FbxComponent::Light(
+                                    FbxLight::read(*object_handle, nodes)?));
+                            }
+                            "Camera" => {
+                                component_handle = components.spawn(FbxComponent::Camera(
+                                    FbxCamera::read(*object_handle, nodes)?));
+                            }
+                            // Skeleton and empty nodes have no data which is needed.
+                            "LimbNode" | "Root" | "Null" => (),
+                            kind => *unsupported.entry(format!("NodeAttribute ({})", kind)).or_insert(0) += 1,
+                        }
                     }
                 }
                 "AnimationCurve" => {
@@ -106,16 +132,22 @@ impl FbxScene {
                             component_handle = components.spawn(FbxComponent::Deformer(
                                 FbxDeformer::read(*object_handle, nodes)?));
                         }
-                        _ => ()
+                        kind => *unsupported.entry(format!("Deformer ({})", kind)).or_insert(0) += 1,
                     }
                 }
-                _ => ()
+                // Bind poses, animation stacks and layers are not needed for conversion.
+                "Pose" | "AnimationStack" | "AnimationLayer" => (),
+                name => *unsupported.entry(name.to_owned()).or_insert(0) += 1,
             }
             if !component_handle.is_none() {
                 index_to_component.insert(index, component_handle);
             }
         }
 
+        for (kind, count) in unsupported {
+            Log::writeln(format!("FBX: {} object(s) of type {} are not supported and were ignored.", count, kind));
+        }
+
         // Read connections
         let connections_node = nodes.get_by_name(document.root(), "Connections")?;
         for connection_handle in connections_node.children() {
@@ -157,6 +189,7 @@ fn link_child_with_parent_component(parent: &mut FbxComponent, child: &mut FbxCo
                 FbxComponent::Material(_) => model.materials.push(child_handle),
                 FbxComponent::AnimationCurveNode(_) => model.animation_curve_nodes.push(child_handle),
                 FbxComponent::Light(_) => model.light = child_handle,
+                FbxComponent::Camera(_) => model.camera = child_handle,
                 FbxComponent::Model(_) => model.children.push(child_handle),
                 _ => ()
             }
@@ -167,6 +200,12 @@ fn link_child_with_parent_component(parent: &mut FbxComponent, child: &mut FbxCo
                 material.textures.push((property, child_handle));
             }
         }
+        // Link texture with its media
+        FbxComponent::Texture(texture) => {
+            if let FbxComponent::Video(_) = child {
+                texture.video = child_handle;
+            }
+        }
         // Link animation curve node with animation curve
         FbxComponent::AnimationCurveNode(anim_curve_node) => {
             if let FbxComponent::AnimationCurve(_) = child {
@@ -201,7 +240,9 @@ pub enum FbxComponent {
     Deformer(FbxDeformer),
     SubDeformer(FbxSubDeformer),
     Texture(FbxTexture),
+    Video(FbxVideo),
     Light(FbxLight),
+    Camera(FbxCamera),
     Model(Box<FbxModel>),
     Material(FbxMaterial),
     AnimationCurveNode(FbxAnimationCurveNode),
@@ -225,7 +266,9 @@ impl FbxComponent {
     define_as!(self, as_deformer, FbxDeformer, Deformer);
     define_as!(self, as_sub_deformer, FbxSubDeformer, SubDeformer);
     define_as!(self, as_texture, FbxTexture, Texture);
+    define_as!(self, as_video, FbxVideo, Video);
     define_as!(self, as_light, FbxLight, Light);
+    define_as!(self, as_camera, FbxCamera, Camera);
     define_as!(self, as_material, FbxMaterial, Material);
     define_as!(self, as_geometry, FbxGeometry, Geometry);
 }
@@ -288,13 +331,54 @@ impl FbxSubDeformer {
 }
 
 pub struct FbxMaterial {
-    pub textures: Vec<(String, Handle<FbxComponent>)>
+    pub name: String,
+    pub textures: Vec<(String, Handle<FbxComponent>)>,
+    /// Diffuse color multiplied by diffuse factor, it is used only if material has
+    /// no diffuse texture.
+    pub diffuse_color: Option<Color>,
+    /// Reflection factor multiplied by average of reflection color.
+    pub reflectivity: Option<f32>,
 }
 
 impl FbxMaterial {
-    fn read(_material_node_handle: Handle<FbxNode>) -> Result<FbxMaterial, String> {
+    fn read(material_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer) -> Result<FbxMaterial, String> {
+        let mut name = nodes.get(material_node_handle).get_attrib(1)
+            .map(|attrib| attrib.as_string())
+            .unwrap_or_default();
+        // Remove prefix
+        if name.starts_with("Material::") {
+            name = name.chars().skip(10).collect();
+        }
+
+        let mut diffuse_color = None;
+        let mut diffuse_factor = 1.0;
+        let mut reflection_color = None;
+        let mut reflection_factor = None;
+
+        // Materials exported by some tools have no properties at all.
+        if let Ok(props) = nodes.get_by_name(material_node_handle, "Properties70") {
+            for prop_handle in props.children() {
+                let prop = nodes.get(*prop_handle);
+                match prop.get_attrib(0)?.as_string().as_str() {
+                    "DiffuseColor" => diffuse_color = Some(prop.get_vec3_at(4)?),
+                    "DiffuseFactor" => diffuse_factor = prop.get_attrib(4)?.as_f32()?,
+                    "ReflectionColor" => reflection_color = Some(prop.get_vec3_at(4)?),
+                    "ReflectionFactor" => reflection_factor = Some(prop.get_attrib(4)?.as_f32()?),
+                    _ => ()
+                }
+            }
+        }
+
+        let to_u8 = |c: f32| ((c * diffuse_factor).max(0.0).min(1.0) * 255.0) as u8;
+
         Ok(FbxMaterial {
-            textures: Default::default()
+            name,
+            textures: Default::default(),
+            diffuse_color: diffuse_color.map(|c| Color::opaque(to_u8(c.x), to_u8(c.y), to_u8(c.z))),
+            reflectivity: match (reflection_factor, reflection_color) {
+                (Some(factor), Some(color)) => Some(factor * (color.x + color.y + color.z) / 3.0),
+                _ => None,
+            },
         })
     }
 }
@@ -376,7 +460,12 @@ impl<T> FbxContainer<T> {
         // See: https://developer.blender.org/D402
         if data_name.as_ref() != "Materials" {
             if reference == FbxReference::IndexToDirect {
-                let index_node = nodes.find(container_node, format!("{}Index", data_name.as_ref()).as_str())?;
+                // Index of colors is the only one which is named in singular.
+                let index_name = match data_name.as_ref() {
+                    "Colors" => String::from("ColorIndex"),
+                    name => format!("{}Index", name),
+                };
+                let index_node = nodes.find(container_node, index_name.as_str())?;
                 let index_array_node = nodes.get_by_name(index_node, "a")?;
                 for attribute in index_array_node.attributes() {
                     index.push(attribute.as_i32()?);
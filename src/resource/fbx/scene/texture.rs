@@ -4,33 +4,72 @@ use crate::{
         document::{
             FbxNode,
             FbxNodeContainer
-        }
+        },
+        scene::FbxComponent,
     },
     core::pool::Handle,
 };
 
+/// Since most of FBX files were made on Windows in 3ds MAX or Maya, it contains
+/// paths with double back slashes, we must fix this so this path can be used
+/// on linux.
+fn read_file_name(node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer) -> Result<PathBuf, String> {
+    if let Ok(relative_file_name_node) = nodes.get_by_name(node_handle, "RelativeFilename") {
+        let str_path = relative_file_name_node.get_attrib(0)?
+            .as_string()
+            .replace("\\", "/");
+        Ok(PathBuf::from(str_path))
+    } else {
+        Ok(PathBuf::new())
+    }
+}
+
 pub struct FbxTexture {
     filename: PathBuf,
+    /// Handle to video component which holds embedded content of texture, if any.
+    pub video: Handle<FbxComponent>,
 }
 
 impl FbxTexture {
     pub(in crate::resource::fbx) fn read(texture_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer) -> Result<Self, String> {
-        let mut texture = FbxTexture {
-            filename: PathBuf::new()
+        Ok(FbxTexture {
+            filename: read_file_name(texture_node_handle, nodes)?,
+            video: Handle::NONE,
+        })
+    }
+
+    pub(in crate::resource::fbx) fn get_file_path(&self) -> &PathBuf {
+        &self.filename
+    }
+}
+
+/// Media of texture, binary FBX may have content of file embedded in it.
+pub struct FbxVideo {
+    filename: PathBuf,
+    content: Vec<u8>,
+}
+
+impl FbxVideo {
+    pub(in crate::resource::fbx) fn read(video_node_handle: Handle<FbxNode>, nodes: &FbxNodeContainer) -> Result<Self, String> {
+        let mut video = FbxVideo {
+            filename: read_file_name(video_node_handle, nodes)?,
+            content: Vec::new(),
         };
-        if let Ok(relative_file_name_node) = nodes.get_by_name(texture_node_handle, "RelativeFilename") {
-            // Since most of FBX files were made on Windows in 3ds MAX or Maya, it contains
-            // paths with double back slashes, we must fix this so this path can be used
-            // on linux.
-            let str_path = relative_file_name_node.get_attrib(0)?
-                .as_string()
-                .replace("\\", "/");
-            texture.filename = PathBuf::from(str_path);
+        if let Ok(content_node) = nodes.get_by_name(video_node_handle, "Content") {
+            // ASCII FBX stores content as base64 string, such content is not supported.
+            if let Some(Ok(content)) = content_node.attributes().first().map(|a| a.as_raw()) {
+                video.content = content.to_vec();
+            }
         }
-        Ok(texture)
+        Ok(video)
     }
 
     pub(in crate::resource::fbx) fn get_file_path(&self) -> &PathBuf {
         &self.filename
     }
-}
\ No newline at end of file
+
+    /// Returns embedded content of file, it is empty if media is not embedded.
+    pub(in crate::resource::fbx) fn content(&self) -> &[u8] {
+        &self.content
+    }
+}
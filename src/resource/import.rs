@@ -0,0 +1,246 @@
+//! Import options of models.
+//!
+//! Exporters of DCC tools differ a lot: one writes in centimeters, another uses Z as up
+//! axis, third one puts every camera of the working file into the model. Import options
+//! tell loader how to convert such model. Options are stored in a sidecar text file next
+//! to the model with `.import` added to its name (`data/barrel.fbx.import`), so they are
+//! applied every time model is loaded, including reloads. Example of sidecar file:
+//!
+//! ```text
+//! # Exported from a Z-up tool in centimeters.
+//! scale = 0.01
+//! axis_conversion = z_up
+//! flip_handedness = false
+//! import_animations = true
+//! import_lights = false
+//! import_cameras = false
+//! generate_tangents = true
+//! tangent_method = mikktspace
+//! material_search_path = textures
+//! material_search_path = ../shared/textures
+//! ```
+//!
+//! Every line is optional, missing values have defaults which keep behaviour of loader
+//! as it was without options. Material search paths are relative to directory of model.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+use crate::{
+    core::math::{
+        quat::Quat,
+        vec3::Vec3,
+    },
+    renderer::surface::TangentMethod,
+};
+
+/// Conversion of up axis of model to Y-up coordinate system of engine.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AxisConversion {
+    /// Model is already Y-up.
+    None,
+    /// Model is Z-up, it is rotated by -90 degrees around X axis.
+    ZUp,
+}
+
+impl AxisConversion {
+    fn name(self) -> &'static str {
+        match self {
+            AxisConversion::None => "none",
+            AxisConversion::ZUp => "z_up",
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelImportOptions {
+    /// Uniform scale applied to whole model.
+    pub scale: f32,
+    pub axis_conversion: AxisConversion,
+    /// Mirrors model along Z axis to convert it from left-handed coordinate system.
+    /// Winding of triangles is reversed, so faces stay front-facing.
+    pub flip_handedness: bool,
+    pub import_animations: bool,
+    /// Imported lights keep color, intensity, radius and shadows of source lights. Area
+    /// and volume lights are imported as point lights.
+    pub import_lights: bool,
+    /// Imported cameras are disabled, so they do not render until game enables them.
+    pub import_cameras: bool,
+    /// Generates tangents for meshes which have none, normal mapping does not work
+    /// without them.
+    pub generate_tangents: bool,
+    /// Method of tangent generation, MikkTSpace by default to match normal maps baked in
+    /// external tools.
+    pub tangent_method: TangentMethod,
+    /// Directories (relative to model) in which textures of materials are searched before
+    /// textures path of resource manager.
+    pub material_search_paths: Vec<PathBuf>,
+}
+
+impl Default for ModelImportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            axis_conversion: AxisConversion::None,
+            flip_handedness: false,
+            import_animations: true,
+            import_lights: true,
+            import_cameras: false,
+            generate_tangents: true,
+            tangent_method: TangentMethod::MikkTSpace,
+            material_search_paths: Vec::new(),
+        }
+    }
+}
+
+fn parse_bool(value: &str, line: usize) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Line {}: expected true or false, got {}", line, value))
+    }
+}
+
+impl ModelImportOptions {
+    /// Returns path of sidecar file of given model.
+    pub fn sidecar_path<P: AsRef<Path>>(model_path: P) -> PathBuf {
+        let mut path = model_path.as_ref().as_os_str().to_owned();
+        path.push(".import");
+        PathBuf::from(path)
+    }
+
+    /// Parses options from text in format described in module docs.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+            let value = parts.next()
+                .ok_or_else(|| format!("Line {}: expected key = value", line_number))?
+                .trim();
+
+            match key {
+                "scale" => {
+                    options.scale = value.parse()
+                        .map_err(|_| format!("Line {}: invalid scale {}", line_number, value))?;
+                }
+                "axis_conversion" => {
+                    options.axis_conversion = match value {
+                        "none" => AxisConversion::None,
+                        "z_up" => AxisConversion::ZUp,
+                        _ => return Err(format!("Line {}: unknown axis conversion {}", line_number, value))
+                    };
+                }
+                "flip_handedness" => options.flip_handedness = parse_bool(value, line_number)?,
+                "import_animations" => options.import_animations = parse_bool(value, line_number)?,
+                "import_lights" => options.import_lights = parse_bool(value, line_number)?,
+                "import_cameras" => options.import_cameras = parse_bool(value, line_number)?,
+                "generate_tangents" => options.generate_tangents = parse_bool(value, line_number)?,
+                "tangent_method" => {
+                    options.tangent_method = match value {
+                        "fast" => TangentMethod::Fast,
+                        "mikktspace" => TangentMethod::MikkTSpace,
+                        _ => return Err(format!("Line {}: unknown tangent method {}", line_number, value))
+                    };
+                }
+                "material_search_path" => options.material_search_paths.push(PathBuf::from(value)),
+                _ => return Err(format!("Line {}: unknown option {}", line_number, key))
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Writes options in format described in module docs.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Writing into String can't fail.
+        let _ = writeln!(text, "scale = {}", self.scale);
+        let _ = writeln!(text, "axis_conversion = {}", self.axis_conversion.name());
+        let _ = writeln!(text, "flip_handedness = {}", self.flip_handedness);
+        let _ = writeln!(text, "import_animations = {}", self.import_animations);
+        let _ = writeln!(text, "import_lights = {}", self.import_lights);
+        let _ = writeln!(text, "import_cameras = {}", self.import_cameras);
+        let _ = writeln!(text, "generate_tangents = {}", self.generate_tangents);
+        let _ = writeln!(text, "tangent_method = {}", match self.tangent_method {
+            TangentMethod::Fast => "fast",
+            TangentMethod::MikkTSpace => "mikktspace",
+        });
+        for path in self.material_search_paths.iter() {
+            let _ = writeln!(text, "material_search_path = {}", path.display());
+        }
+        text
+    }
+
+    /// Loads options from sidecar file of given model, returns default options if there
+    /// is no sidecar file.
+    pub fn load_for_model<P: AsRef<Path>>(model_path: P) -> Result<Self, String> {
+        let path = Self::sidecar_path(model_path);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {}! Reason: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Saves options to sidecar file of given model.
+    pub fn save_for_model<P: AsRef<Path>>(&self, model_path: P) -> std::io::Result<()> {
+        std::fs::write(Self::sidecar_path(model_path), self.to_text())
+    }
+
+    /// Returns rotation which must be applied to root of imported model.
+    pub(in crate) fn root_rotation(&self) -> Quat {
+        match self.axis_conversion {
+            AxisConversion::None => Quat::IDENTITY,
+            AxisConversion::ZUp => Quat::from_axis_angle(Vec3::RIGHT, -std::f32::consts::FRAC_PI_2),
+        }
+    }
+
+    /// Returns scale which must be applied to root of imported model.
+    pub(in crate) fn root_scale(&self) -> Vec3 {
+        let handedness = if self.flip_handedness { -1.0 } else { 1.0 };
+        Vec3::new(self.scale, self.scale, self.scale * handedness)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use crate::{
+        renderer::surface::TangentMethod,
+        resource::import::{ModelImportOptions, AxisConversion},
+    };
+
+    #[test]
+    fn test_import_options_round_trip() {
+        let options = ModelImportOptions {
+            scale: 0.01,
+            axis_conversion: AxisConversion::ZUp,
+            import_cameras: true,
+            tangent_method: TangentMethod::Fast,
+            material_search_paths: vec![PathBuf::from("textures")],
+            ..Default::default()
+        };
+        assert_eq!(ModelImportOptions::parse(&options.to_text()).unwrap(), options);
+
+        let partial = ModelImportOptions::parse("# comment\n\nimport_lights = false\n").unwrap();
+        assert!(!partial.import_lights);
+        assert_eq!(partial.scale, 1.0);
+
+        assert!(ModelImportOptions::parse("scale = big").is_err());
+        assert!(ModelImportOptions::parse("unknown = 1").is_err());
+
+        assert_eq!(ModelImportOptions::sidecar_path(Path::new("data/barrel.fbx")),
+                   PathBuf::from("data/barrel.fbx.import"));
+    }
+}
@@ -0,0 +1,378 @@
+//! Materials shared between surfaces.
+//!
+//! Material is a set of textures and parameters of surface. Without materials each surface
+//! has its own copy of parameters, so changing look of, for example, every wooden box on
+//! level means changing every surface of every box. Material is a resource instead: it is
+//! loaded once by `ResourceManager::request_material` and every surface which uses it
+//! holds a shared reference to it, so change of material is visible on every surface at
+//! once. When surface has material, its own textures and parameters are ignored.
+//!
+//! Materials are stored in text files, each line is `key = value`, lines which start
+//! with `#` are comments. Paths of textures are relative to working directory, like any
+//! other path of resource:
+//!
+//! ```text
+//! # Shared material of wooden props.
+//! diffuse_texture = data/textures/wood.png
+//! normal_texture = data/textures/wood_normal.png
+//! color = 255 230 200 255
+//! reflectivity = 0.1
+//! blend_mode = opaque
+//! wetness_factor = 0.5
+//! ```
+//!
+//! Materials of bricks, rocks and other rough surfaces can use parallax occlusion mapping,
+//! it makes surface look bumpy without extra geometry. Height texture is grayscale, white
+//! is top of surface, black is `parallax_scale` (in texture coordinates) below it. More
+//! samples give more precise result when surface is viewed at grazing angles:
+//!
+//! ```text
+//! height_texture = data/textures/bricks_height.png
+//! parallax_scale = 0.04
+//! parallax_min_samples = 8
+//! parallax_max_samples = 32
+//! ```
+//!
+//! Foliage cards, grass and fences use alpha test - pixels with alpha below cutoff are
+//! discarded. Lower cutoff keeps thin leaves from disappearing at distance where texture is
+//! minified and alpha gets averaged:
+//!
+//! ```text
+//! blend_mode = alpha_test
+//! alpha_cutoff = 0.3
+//! ```
+//!
+//! Alpha tested surfaces are written to G-buffer like opaque ones, so they have no sorting
+//! problems of alpha blending. Alpha-to-coverage is not used: G-buffer is not multisampled,
+//! coverage would be resolved to the same one-sample test.
+//!
+//! Every line is optional, missing values have defaults which give the same look as surface
+//! without material.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+use crate::{
+    core::{
+        color::Color,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    resource::texture::TextureKind,
+    engine::resource_manager::{ResourceManager, SharedTexture},
+};
+
+/// Alpha cutoff of materials and of surfaces without material.
+pub const DEFAULT_ALPHA_CUTOFF: f32 = 0.5;
+
+/// Defines how alpha channel of diffuse texture is used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Alpha is ignored, surface is fully opaque.
+    Opaque,
+    /// Pixels with alpha less than cutoff (0.5 by default, see `Material::set_alpha_cutoff`)
+    /// are discarded, useful for foliage, fences, etc.
+    AlphaTest,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::AlphaTest
+    }
+}
+
+impl BlendMode {
+    fn name(self) -> &'static str {
+        match self {
+            BlendMode::Opaque => "opaque",
+            BlendMode::AlphaTest => "alpha_test",
+        }
+    }
+}
+
+/// Parameters of parallax occlusion mapping, see module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parallax {
+    /// Depth of surface relief in texture coordinates.
+    pub scale: f32,
+    /// Amount of samples when surface is viewed straight on.
+    pub min_samples: u32,
+    /// Amount of samples when surface is viewed at grazing angle.
+    pub max_samples: u32,
+}
+
+impl Default for Parallax {
+    fn default() -> Self {
+        Self {
+            scale: 0.05,
+            min_samples: 8,
+            max_samples: 32,
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct Material {
+    pub(in crate) path: PathBuf,
+    diffuse_texture: Option<SharedTexture>,
+    normal_texture: Option<SharedTexture>,
+    height_texture: Option<SharedTexture>,
+    parallax: Parallax,
+    color: Color,
+    reflectivity: f32,
+    blend_mode: BlendMode,
+    alpha_cutoff: f32,
+    wetness_factor: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            diffuse_texture: None,
+            normal_texture: None,
+            height_texture: None,
+            parallax: Default::default(),
+            color: Color::WHITE,
+            reflectivity: 0.0,
+            blend_mode: BlendMode::default(),
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            wetness_factor: 1.0,
+        }
+    }
+}
+
+impl Visit for Material {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        // Content is reloaded from file after load.
+        self.path.visit("Path", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+fn parse_texture(value: &str, resource_manager: &mut ResourceManager) -> Option<SharedTexture> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(resource_manager.request_texture_async(value, TextureKind::RGBA8))
+    }
+}
+
+fn texture_path(texture: &Option<SharedTexture>) -> String {
+    texture.as_ref()
+        .map(|texture| texture.lock().unwrap().path.display().to_string())
+        .unwrap_or_default()
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parses material from text in format described in module docs, textures are
+    /// requested from given resource manager.
+    pub fn parse(text: &str, resource_manager: &mut ResourceManager) -> Result<Self, String> {
+        let mut material = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+            let value = parts.next()
+                .ok_or_else(|| format!("Line {}: expected key = value", line_number))?
+                .trim();
+
+            match key {
+                "diffuse_texture" => material.diffuse_texture = parse_texture(value, resource_manager),
+                "normal_texture" => material.normal_texture = parse_texture(value, resource_manager),
+                "height_texture" => material.height_texture = parse_texture(value, resource_manager),
+                "parallax_scale" => {
+                    material.parallax.scale = value.parse()
+                        .map_err(|_| format!("Line {}: invalid parallax scale {}", line_number, value))?;
+                }
+                "parallax_min_samples" => {
+                    material.parallax.min_samples = value.parse()
+                        .map_err(|_| format!("Line {}: invalid sample count {}", line_number, value))?;
+                }
+                "parallax_max_samples" => {
+                    material.parallax.max_samples = value.parse()
+                        .map_err(|_| format!("Line {}: invalid sample count {}", line_number, value))?;
+                }
+                "color" => {
+                    let components = value.split_whitespace()
+                        .map(|c| c.parse::<u8>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| format!("Line {}: invalid color {}", line_number, value))?;
+                    material.color = match components.as_slice() {
+                        &[r, g, b] => Color::opaque(r, g, b),
+                        &[r, g, b, a] => Color::from_rgba(r, g, b, a),
+                        _ => return Err(format!("Line {}: color must have 3 or 4 components", line_number))
+                    };
+                }
+                "reflectivity" => {
+                    let reflectivity = value.parse()
+                        .map_err(|_| format!("Line {}: invalid reflectivity {}", line_number, value))?;
+                    material.set_reflectivity(reflectivity);
+                }
+                "blend_mode" => {
+                    material.blend_mode = match value {
+                        "opaque" => BlendMode::Opaque,
+                        "alpha_test" => BlendMode::AlphaTest,
+                        _ => return Err(format!("Line {}: unknown blend mode {}", line_number, value))
+                    };
+                }
+                "alpha_cutoff" => {
+                    let cutoff = value.parse()
+                        .map_err(|_| format!("Line {}: invalid alpha cutoff {}", line_number, value))?;
+                    material.set_alpha_cutoff(cutoff);
+                }
+                "wetness_factor" => {
+                    let factor = value.parse()
+                        .map_err(|_| format!("Line {}: invalid wetness factor {}", line_number, value))?;
+                    material.set_wetness_factor(factor);
+                }
+                _ => return Err(format!("Line {}: unknown property {}", line_number, key))
+            }
+        }
+
+        material.set_parallax(material.parallax);
+
+        Ok(material)
+    }
+
+    /// Writes material in format described in module docs.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Writing into String can't fail.
+        let _ = writeln!(text, "diffuse_texture = {}", texture_path(&self.diffuse_texture));
+        let _ = writeln!(text, "normal_texture = {}", texture_path(&self.normal_texture));
+        let _ = writeln!(text, "height_texture = {}", texture_path(&self.height_texture));
+        let _ = writeln!(text, "parallax_scale = {}", self.parallax.scale);
+        let _ = writeln!(text, "parallax_min_samples = {}", self.parallax.min_samples);
+        let _ = writeln!(text, "parallax_max_samples = {}", self.parallax.max_samples);
+        let _ = writeln!(text, "color = {} {} {} {}", self.color.r, self.color.g, self.color.b, self.color.a);
+        let _ = writeln!(text, "reflectivity = {}", self.reflectivity);
+        let _ = writeln!(text, "blend_mode = {}", self.blend_mode.name());
+        let _ = writeln!(text, "alpha_cutoff = {}", self.alpha_cutoff);
+        let _ = writeln!(text, "wetness_factor = {}", self.wetness_factor);
+        text
+    }
+
+    /// Loads material from file, there is no need to call this method directly - use
+    /// `ResourceManager::request_material` which shares loaded materials.
+    pub fn load<P: AsRef<Path>>(path: P, resource_manager: &mut ResourceManager) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Unable to read {}! Reason: {}", path.as_ref().display(), e))?;
+        let mut material = Self::parse(&text, resource_manager)
+            .map_err(|e| format!("{}: {}", path.as_ref().display(), e))?;
+        material.path = path.as_ref().to_owned();
+        Ok(material)
+    }
+
+    /// Saves material to file, so it can be edited by hand or loaded later.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    /// Returns path of file from which material was loaded, empty for materials created
+    /// in code.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn set_diffuse_texture(&mut self, texture: Option<SharedTexture>) {
+        self.diffuse_texture = texture;
+    }
+
+    pub fn diffuse_texture(&self) -> Option<SharedTexture> {
+        self.diffuse_texture.clone()
+    }
+
+    pub fn set_normal_texture(&mut self, texture: Option<SharedTexture>) {
+        self.normal_texture = texture;
+    }
+
+    pub fn normal_texture(&self) -> Option<SharedTexture> {
+        self.normal_texture.clone()
+    }
+
+    /// Sets height texture, parallax occlusion mapping is used while material has it.
+    pub fn set_height_texture(&mut self, texture: Option<SharedTexture>) {
+        self.height_texture = texture;
+    }
+
+    pub fn height_texture(&self) -> Option<SharedTexture> {
+        self.height_texture.clone()
+    }
+
+    /// Sets parameters of parallax occlusion mapping, sample counts are clamped to [1; 128]
+    /// and max samples is never less than min samples.
+    pub fn set_parallax(&mut self, parallax: Parallax) {
+        let min_samples = parallax.min_samples.max(1).min(128);
+        self.parallax = Parallax {
+            scale: parallax.scale,
+            min_samples,
+            max_samples: parallax.max_samples.max(min_samples).min(128),
+        };
+    }
+
+    pub fn parallax(&self) -> Parallax {
+        self.parallax
+    }
+
+    /// Sets color which is multiplied with diffuse texture and vertex colors.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Sets how much surfaces reflect surrounding objects, in [0; 1] range. See
+    /// `Surface::set_reflectivity`.
+    pub fn set_reflectivity(&mut self, reflectivity: f32) {
+        self.reflectivity = reflectivity.max(0.0).min(1.0);
+    }
+
+    pub fn reflectivity(&self) -> f32 {
+        self.reflectivity
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Sets alpha below which pixels are discarded in `BlendMode::AlphaTest`, in [0; 1]
+    /// range. Shadows use the same cutoff, so they match visible shape of surface.
+    pub fn set_alpha_cutoff(&mut self, cutoff: f32) {
+        self.alpha_cutoff = cutoff.max(0.0).min(1.0);
+    }
+
+    pub fn alpha_cutoff(&self) -> f32 {
+        self.alpha_cutoff
+    }
+
+    /// Sets how strong surfaces darken when scene is wet (see `scene::weather`), in [0; 1]
+    /// range. Use zero for sheltered or non-porous surfaces.
+    pub fn set_wetness_factor(&mut self, factor: f32) {
+        self.wetness_factor = factor.max(0.0).min(1.0);
+    }
+
+    pub fn wetness_factor(&self) -> f32 {
+        self.wetness_factor
+    }
+}
@@ -1,3 +1,10 @@
 pub mod texture;
 pub mod fbx;
 pub mod model;
+pub mod import;
+pub mod material;
+pub mod texture_atlas;
+pub mod texture_array;
+pub mod video;
+pub mod string_table;
+pub mod vertex_animation;
@@ -4,7 +4,7 @@ use crate::{
         node::Node,
     },
     animation::Animation,
-    resource::{fbx, fbx::error::FbxError},
+    resource::{fbx, fbx::error::FbxError, import::ModelImportOptions},
     engine::resource_manager::ResourceManager,
     core::{
         pool::Handle,
@@ -82,8 +82,12 @@ fn upgrade_self_weak_ref(self_weak_ref: &Option<Weak<Mutex<Model>>>) -> Arc<Mute
 
 impl Model {
     pub(in crate) fn load<P: AsRef<Path>>(path: P, resource_manager: &mut ResourceManager) -> Result<Model, FbxError> {
+        let options = ModelImportOptions::load_for_model(path.as_ref()).unwrap_or_else(|e| {
+            Log::writeln(format!("Invalid import options, defaults are used. Reason: {}", e));
+            Default::default()
+        });
         let mut scene = Scene::new();
-        fbx::load_to_scene(&mut scene, resource_manager, path.as_ref())?;
+        fbx::load_to_scene(&mut scene, resource_manager, path.as_ref(), &options)?;
         Ok(Model {
             self_weak_ref: None,
             path: path.as_ref().to_path_buf(),
@@ -0,0 +1,265 @@
+//! Localized text.
+//!
+//! String table is a resource which maps keys to translated texts for each locale. Tables
+//! are stored in JSON files, top-level members are locales and each locale is an object of
+//! key-text pairs:
+//!
+//! ```text
+//! {
+//!     "en": { "menu.start": "Start game", "hud.ammo": "Ammo: {count}" },
+//!     "de": { "menu.start": "Spiel starten", "hud.ammo": "Munition: {count}" }
+//! }
+//! ```
+//!
+//! Tables are loaded by `ResourceManager::request_string_table` and added to localization
+//! of engine, which has current locale. Text is looked up in the current locale first,
+//! then in its base language (`de` for `de-AT`), then in fallback locale. If text is not
+//! found anywhere, key itself is returned, so missing translations are easy to spot.
+//!
+//! Text3D nodes with localization key get their text from localization when locale is
+//! changed by `Engine::set_locale`. For user interface, request texts by keys when widgets
+//! are built:
+//!
+//! ```no_run
+//! use rg3d::engine::resource_manager::ResourceManager;
+//! use rg3d::resource::string_table::Localization;
+//!
+//! fn setup(localization: &mut Localization, resource_manager: &mut ResourceManager) {
+//!     localization.add_table(resource_manager.request_string_table("data/strings.json").unwrap());
+//!     localization.set_locale("de");
+//!     let title = localization.text("menu.start");
+//!     let ammo = localization.format("hud.ammo", &[("count", "30")]);
+//!     println!("{} {}", title, ammo);
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+};
+use crate::{
+    engine::resource_manager::SharedStringTable,
+    scene::{
+        graph::Graph,
+        node::Node,
+    },
+    utils::json::{JsonValue, JsonError},
+};
+
+#[derive(Debug)]
+pub enum StringTableError {
+    Io(std::io::Error),
+    Json(JsonError),
+    /// File is valid JSON, but its layout is not a string table.
+    InvalidLayout(String),
+}
+
+impl Display for StringTableError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            StringTableError::Io(io) => write!(f, "Io error: {}", io),
+            StringTableError::Json(json) => write!(f, "{}", json),
+            StringTableError::InvalidLayout(msg) => write!(f, "Invalid string table: {}", msg),
+        }
+    }
+}
+
+impl From<std::io::Error> for StringTableError {
+    fn from(err: std::io::Error) -> Self {
+        StringTableError::Io(err)
+    }
+}
+
+impl From<JsonError> for StringTableError {
+    fn from(err: JsonError) -> Self {
+        StringTableError::Json(err)
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct StringTable {
+    pub(in crate) path: PathBuf,
+    locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl StringTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub(in crate) fn load<P: AsRef<Path>>(path: P) -> Result<Self, StringTableError> {
+        let mut table = Self::from_json(&std::fs::read_to_string(path.as_ref())?)?;
+        table.path = path.as_ref().to_owned();
+        Ok(table)
+    }
+
+    /// Parses table from JSON source, see module docs for layout.
+    pub fn from_json(source: &str) -> Result<Self, StringTableError> {
+        let root = JsonValue::parse(source)?;
+        let locales = root.as_object()
+            .ok_or_else(|| StringTableError::InvalidLayout("root must be an object".to_owned()))?;
+
+        let mut table = Self::new();
+        for (locale, texts) in locales {
+            let texts = texts.as_object()
+                .ok_or_else(|| StringTableError::InvalidLayout(format!("locale {} must be an object", locale)))?;
+            for (key, text) in texts {
+                let text = text.as_str()
+                    .ok_or_else(|| StringTableError::InvalidLayout(format!("text {} of locale {} must be a string", key, locale)))?;
+                table.insert(locale, key, text);
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds or replaces text of given key in given locale.
+    pub fn insert(&mut self, locale: &str, key: &str, text: &str) {
+        self.locales
+            .entry(locale.to_owned())
+            .or_insert_with(Default::default)
+            .insert(key.to_owned(), text.to_owned());
+    }
+
+    /// Returns text of given key in exactly given locale.
+    pub fn get(&self, locale: &str, key: &str) -> Option<&str> {
+        self.locales
+            .get(locale)
+            .and_then(|texts| texts.get(key))
+            .map(|text| text.as_str())
+    }
+
+    /// Returns names of locales which have at least one text.
+    pub fn locales(&self) -> impl Iterator<Item=&str> {
+        self.locales.keys().map(|locale| locale.as_str())
+    }
+}
+
+/// Returns base language of locale, `None` if locale has no region part.
+fn base_language(locale: &str) -> Option<&str> {
+    locale.find(|c| c == '-' || c == '_').map(|index| &locale[..index])
+}
+
+/// Set of string tables with current locale. See module docs.
+#[derive(Clone, Debug)]
+pub struct Localization {
+    locale: String,
+    fallback_locale: String,
+    tables: Vec<SharedStringTable>,
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Self {
+            locale: "en".to_owned(),
+            fallback_locale: "en".to_owned(),
+            tables: Default::default(),
+        }
+    }
+}
+
+impl Localization {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets current locale, for example `en` or `pt-BR`. Use `Engine::set_locale` to update
+    /// texts of scene nodes as well.
+    pub fn set_locale(&mut self, locale: &str) {
+        self.locale = locale.to_owned();
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Sets locale which is used when text is missing in current locale, `en` by default.
+    pub fn set_fallback_locale(&mut self, locale: &str) {
+        self.fallback_locale = locale.to_owned();
+    }
+
+    pub fn fallback_locale(&self) -> &str {
+        &self.fallback_locale
+    }
+
+    /// Adds table, when several tables have the same key, text of table which was added
+    /// first is used.
+    pub fn add_table(&mut self, table: SharedStringTable) {
+        self.tables.push(table);
+    }
+
+    pub fn tables(&self) -> &[SharedStringTable] {
+        &self.tables
+    }
+
+    pub fn clear_tables(&mut self) {
+        self.tables.clear();
+    }
+
+    fn find_in_locale(&self, locale: &str, key: &str) -> Option<String> {
+        self.tables
+            .iter()
+            .filter_map(|table| table.lock().unwrap().get(locale, key).map(|text| text.to_owned()))
+            .next()
+    }
+
+    /// Returns text of given key in current locale, see module docs for lookup order.
+    pub fn text(&self, key: &str) -> String {
+        self.find_in_locale(&self.locale, key)
+            .or_else(|| base_language(&self.locale).and_then(|language| self.find_in_locale(language, key)))
+            .or_else(|| self.find_in_locale(&self.fallback_locale, key))
+            .unwrap_or_else(|| key.to_owned())
+    }
+
+    /// Returns text of given key in which each `{name}` placeholder is replaced with value
+    /// of argument with the same name.
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let mut text = self.text(key);
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+
+    /// Sets text of every Text3D node with localization key in given graph. Engine does it
+    /// for every scene when locale is changed, call it for scenes loaded afterwards.
+    pub fn localize_graph(&self, graph: &mut Graph) {
+        for node in graph.linear_iter_mut() {
+            if let Node::Text3D(text) = node {
+                if let Some(key) = text.localization_key().map(|key| key.to_owned()) {
+                    text.set_text(self.text(&key));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use crate::resource::string_table::{StringTable, Localization};
+
+    #[test]
+    fn test_localization_lookup() {
+        let table = StringTable::from_json(r#"{
+            "en": { "start": "Start", "ammo": "Ammo: {count}", "quit": "Quit" },
+            "pt": { "start": "Iniciar" },
+            "pt-BR": { "ammo": "Munição: {count}" }
+        }"#).unwrap();
+
+        let mut localization = Localization::new();
+        localization.add_table(Arc::new(Mutex::new(table)));
+        localization.set_locale("pt-BR");
+        assert_eq!(localization.format("ammo", &[("count", "30")]), "Munição: 30");
+        assert_eq!(localization.text("start"), "Iniciar");
+        assert_eq!(localization.text("quit"), "Quit");
+        assert_eq!(localization.text("missing"), "missing");
+
+        assert!(StringTable::from_json(r#"{ "en": [] }"#).is_err());
+    }
+}
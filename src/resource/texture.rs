@@ -1,10 +1,17 @@
 use std::path::*;
 use crate::{
-    core::visitor::{
-        Visit,
-        VisitResult,
-        Visitor
-    }
+    core::{
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor
+        },
+        math::Rect,
+    },
+    renderer::resource_tracker::{
+        ResourceTracker,
+        TEXTURE_QUEUE,
+    },
 };
 use image::GenericImageView;
 
@@ -14,7 +21,14 @@ pub struct Texture {
     pub(in crate) height: u32,
     pub(in crate) bytes: Vec<u8>,
     pub(in crate) kind: TextureKind,
-    pub(in crate) loaded: bool
+    pub(in crate) loaded: bool,
+    /// Region of pixels which was modified since last upload to GPU.
+    pub(in crate) dirty_region: Option<Rect<u32>>,
+    /// Frees GPU copy of texture when texture is dropped.
+    pub(in crate) tracker: ResourceTracker,
+    /// Overrides of global sampling settings from quality settings of renderer.
+    pub(in crate) filter: Option<TextureFilter>,
+    pub(in crate) anisotropy: Option<f32>,
 }
 
 impl Default for Texture {
@@ -25,7 +39,11 @@ impl Default for Texture {
             height: 0,
             bytes: Vec::new(),
             kind: TextureKind::RGBA8,
-            loaded: false
+            loaded: false,
+            dirty_region: None,
+            tracker: ResourceTracker::new(&TEXTURE_QUEUE),
+            filter: None,
+            anisotropy: None,
         }
     }
 }
@@ -46,11 +64,33 @@ impl Visit for Texture {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// Defines how texels are fetched when texture is magnified or minified.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Nearest texel without mip-maps, gives pixelated look.
+    Nearest,
+    /// Linear interpolation of texels of nearest mip level.
+    Bilinear,
+    /// Linear interpolation of texels and between two nearest mip levels.
+    Trilinear,
+}
+
+impl Default for TextureFilter {
+    fn default() -> Self {
+        TextureFilter::Trilinear
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TextureKind {
     R8,
     RGB8,
     RGBA8,
+    /// Two 8-bit channels, when loaded from file it is luminance and alpha.
+    RG8,
+    /// Four 16-bit floating point channels, each pixel is stored as four `u16` with bits of
+    /// half-precision float in native byte order.
+    RGBA16F,
 }
 
 impl TextureKind {
@@ -59,6 +99,8 @@ impl TextureKind {
             0 => Ok(TextureKind::R8),
             1 => Ok(TextureKind::RGB8),
             2 => Ok(TextureKind::RGBA8),
+            3 => Ok(TextureKind::RG8),
+            4 => Ok(TextureKind::RGBA16F),
             _ => Err(format!("Invalid texture kind {}!", id))
         }
     }
@@ -68,10 +110,80 @@ impl TextureKind {
             TextureKind::R8 => 0,
             TextureKind::RGB8 => 1,
             TextureKind::RGBA8 => 2,
+            TextureKind::RG8 => 3,
+            TextureKind::RGBA16F => 4,
+        }
+    }
+
+    /// Returns size of one pixel in bytes.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            TextureKind::R8 => 1,
+            TextureKind::RG8 => 2,
+            TextureKind::RGB8 => 3,
+            TextureKind::RGBA8 => 4,
+            TextureKind::RGBA16F => 8,
         }
     }
 }
 
+/// Converts single precision float into bits of half precision float. Values out of half
+/// range are clamped to infinity, values too small to be represented become zero.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+
+    if exponent == 0xFF {
+        // Infinity or NaN.
+        return sign | 0x7C00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        sign | 0x7C00
+    } else if exponent <= 0 {
+        if exponent < -10 {
+            sign
+        } else {
+            // Subnormal half.
+            let mantissa = mantissa | 0x0080_0000;
+            sign | (mantissa >> (14 - exponent) as u32) as u16
+        }
+    } else {
+        sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16
+    }
+}
+
+/// Converts bits of half precision float into single precision float.
+pub fn f16_to_f32(value: u16) -> f32 {
+    let sign = u32::from(value & 0x8000) << 16;
+    let exponent = u32::from((value >> 10) & 0x1F);
+    let mantissa = u32::from(value & 0x03FF);
+
+    let bits = if exponent == 0 {
+        if mantissa == 0 {
+            sign
+        } else {
+            // Subnormal half is normal single, normalize it.
+            let mut exponent = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x0400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x03FF) << 13)
+        }
+    } else if exponent == 0x1F {
+        sign | 0x7F80_0000 | (mantissa << 13)
+    } else {
+        sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)
+    };
+
+    f32::from_bits(bits)
+}
+
 impl Texture {
     pub(in crate) fn load_from_file<P: AsRef<Path>>(path: P, kind: TextureKind) -> Result<Self, image::ImageError> {
         let dyn_img = image::open(path.as_ref())?;
@@ -83,6 +195,12 @@ impl Texture {
             TextureKind::R8 => dyn_img.to_luma().into_raw(),
             TextureKind::RGB8 => dyn_img.to_rgb().into_raw(),
             TextureKind::RGBA8 => dyn_img.to_rgba().into_raw(),
+            TextureKind::RG8 => dyn_img.to_luma_alpha().into_raw(),
+            TextureKind::RGBA16F => dyn_img.to_rgba()
+                .into_raw()
+                .iter()
+                .flat_map(|&c| f32_to_f16(f32::from(c) / 255.0).to_ne_bytes().to_vec())
+                .collect(),
         };
 
         Ok(Texture {
@@ -92,22 +210,240 @@ impl Texture {
             bytes,
             path: path.as_ref().to_path_buf(),
             loaded: true,
+            dirty_region: None,
+            tracker: ResourceTracker::new(&TEXTURE_QUEUE),
+            filter: None,
+            anisotropy: None,
         })
     }
 
-    pub(in crate) fn from_bytes(width: u32, height: u32, kind: TextureKind, bytes: Vec<u8>) -> Self {
+    /// Creates magenta-black checkerboard texture, it is used instead of textures which
+    /// can't be found, so missing assets are clearly visible instead of silently untextured.
+    /// Texture keeps given path, so it is saved as reference to original texture.
+    pub(in crate) fn checkerboard<P: AsRef<Path>>(path: P, kind: TextureKind) -> Self {
+        const SIZE: u32 = 8;
+
+        let mut bytes = Vec::with_capacity((SIZE * SIZE) as usize * kind.bytes_per_pixel());
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let c = if (x + y) % 2 == 0 { 255 } else { 0 };
+                match kind {
+                    TextureKind::R8 => bytes.push(c),
+                    TextureKind::RG8 => bytes.extend_from_slice(&[c, 255]),
+                    TextureKind::RGB8 => bytes.extend_from_slice(&[c, 0, c]),
+                    TextureKind::RGBA8 => bytes.extend_from_slice(&[c, 0, c, 255]),
+                    TextureKind::RGBA16F => {
+                        for &component in [c, 0, c, 255].iter() {
+                            bytes.extend_from_slice(&f32_to_f16(f32::from(component) / 255.0).to_ne_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
         Self {
+            path: path.as_ref().to_path_buf(),
+            width: SIZE,
+            height: SIZE,
+            bytes,
+            kind,
+            loaded: true,
+            dirty_region: None,
+            tracker: ResourceTracker::new(&TEXTURE_QUEUE),
+            filter: None,
+            anisotropy: None,
+        }
+    }
+
+    /// Creates texture from raw pixels, pixels are stored row by row without any padding.
+    /// Such texture has no path, so it won't be saved with scene, procedural textures should
+    /// be re-created by game after load. Returns error if size of `bytes` does not match
+    /// size and kind of texture.
+    pub fn from_bytes(width: u32, height: u32, kind: TextureKind, bytes: Vec<u8>) -> Result<Self, String> {
+        let expected = width as usize * height as usize * kind.bytes_per_pixel();
+        if bytes.len() != expected {
+            return Err(format!("Invalid texture data size, expected {} bytes, got {}!", expected, bytes.len()));
+        }
+
+        Ok(Self {
             path: Default::default(),
             width,
             height,
             bytes,
             kind,
-            loaded: true
-        }
+            loaded: true,
+            dirty_region: None,
+            tracker: ResourceTracker::new(&TEXTURE_QUEUE),
+            filter: None,
+            anisotropy: None,
+        })
+    }
+
+    /// Saves pixels of texture to image file, format is chosen by extension of path. Rows
+    /// are saved in the same order as they are stored. Floating point textures can't be saved.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let color_type = match self.kind {
+            TextureKind::R8 => image::ColorType::Gray(8),
+            TextureKind::RG8 => image::ColorType::GrayA(8),
+            TextureKind::RGB8 => image::ColorType::RGB(8),
+            TextureKind::RGBA8 => image::ColorType::RGBA(8),
+            TextureKind::RGBA16F => return Err("Floating point texture can't be saved to image!".to_owned()),
+        };
+        image::save_buffer(path.as_ref(), &self.bytes, self.width, self.height, color_type)
+            .map_err(|e| format!("Unable to save texture to {}! Reason: {}", path.as_ref().display(), e))
     }
 
     pub fn is_loaded(&self) -> bool {
         self.loaded
     }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn kind(&self) -> TextureKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Sets filter of this texture, `None` means that filter from quality settings of
+    /// renderer is used. Useful for pixel-art sprites and UI textures which must stay
+    /// sharp regardless of global settings.
+    pub fn set_filter(&mut self, filter: Option<TextureFilter>) {
+        self.filter = filter;
+    }
+
+    pub fn filter(&self) -> Option<TextureFilter> {
+        self.filter
+    }
+
+    /// Sets max anisotropy of this texture, `None` means that value from quality settings
+    /// of renderer is used. Value is clamped to max anisotropy supported by hardware, one
+    /// disables anisotropic filtering.
+    pub fn set_anisotropy(&mut self, anisotropy: Option<f32>) {
+        self.anisotropy = anisotropy;
+    }
+
+    pub fn anisotropy(&self) -> Option<f32> {
+        self.anisotropy
+    }
+
+    /// Returns pixels of texture, see [`from_bytes`](Self::from_bytes) for layout.
+    pub fn pixels(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns pixels of texture for modification, whole texture will be uploaded to GPU
+    /// again. Use [`write_region`](Self::write_region) to change small part of large texture.
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        self.dirty_region = Some(Rect::new(0, 0, self.width, self.height));
+        &mut self.bytes
+    }
+
+    /// Copies `data` into rectangular region of texture, `data` must contain rows of region
+    /// without padding. Only changed region will be uploaded to GPU. Returns error if region
+    /// is out of bounds of texture or size of data does not match region.
+    pub fn write_region(&mut self, region: Rect<u32>, data: &[u8]) -> Result<(), String> {
+        if region.x + region.w > self.width || region.y + region.h > self.height {
+            return Err(format!("Region {}x{} at ({}, {}) is out of bounds of {}x{} texture!",
+                               region.w, region.h, region.x, region.y, self.width, self.height));
+        }
+
+        let bpp = self.kind.bytes_per_pixel();
+        let row_size = region.w as usize * bpp;
+        if data.len() != row_size * region.h as usize {
+            return Err(format!("Invalid region data size, expected {} bytes, got {}!", row_size * region.h as usize, data.len()));
+        }
+
+        for (row, src) in data.chunks(row_size.max(1)).enumerate() {
+            let begin = ((region.y as usize + row) * self.width as usize + region.x as usize) * bpp;
+            self.bytes[begin..(begin + row_size)].copy_from_slice(src);
+        }
+
+        self.mark_dirty(region);
+
+        Ok(())
+    }
+
+    /// Marks region of texture as modified, modified regions are merged into one bounding
+    /// rectangle which will be uploaded to GPU on next frame.
+    pub fn mark_dirty(&mut self, region: Rect<u32>) {
+        self.dirty_region = Some(match self.dirty_region {
+            None => region,
+            Some(dirty) => {
+                let x = dirty.x.min(region.x);
+                let y = dirty.y.min(region.y);
+                let right = (dirty.x + dirty.w).max(region.x + region.w);
+                let bottom = (dirty.y + dirty.h).max(region.y + region.h);
+                Rect::new(x, y, right - x, bottom - y)
+            }
+        });
+    }
+
+    /// Returns value of first channel of pixel at given coordinates in [0; 1] range (not
+    /// clamped for floating point textures). Used to read height and density maps.
+    pub(in crate) fn first_channel(&self, x: usize, y: usize) -> f32 {
+        let offset = (y * self.width as usize + x) * self.kind.bytes_per_pixel();
+        if self.kind == TextureKind::RGBA16F {
+            f16_to_f32(u16::from_ne_bytes([self.bytes[offset], self.bytes[offset + 1]]))
+        } else {
+            f32::from(self.bytes[offset]) / 255.0
+        }
+    }
+
+    /// Returns pixels of region packed row by row.
+    pub(in crate) fn region_bytes(&self, region: Rect<u32>) -> Vec<u8> {
+        let bpp = self.kind.bytes_per_pixel();
+        let row_size = region.w as usize * bpp;
+        let mut bytes = Vec::with_capacity(row_size * region.h as usize);
+        for row in region.y..(region.y + region.h) {
+            let begin = (row as usize * self.width as usize + region.x as usize) * bpp;
+            bytes.extend_from_slice(&self.bytes[begin..(begin + row_size)]);
+        }
+        bytes
+    }
+
+    /// Returns amount of memory occupied by pixels of texture in RAM.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes.len()
+    }
 }
 
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::Rect,
+        resource::texture::{Texture, TextureKind, f16_to_f32, f32_to_f16},
+    };
+
+    #[test]
+    fn half_float_round_trip() {
+        for &value in [0.0, 1.0, -2.5, 0.5, 65504.0, 0.000_061_035_156].iter() {
+            assert_eq!(f16_to_f32(f32_to_f16(value)), value);
+        }
+        assert!(f16_to_f32(f32_to_f16(1.0e6)).is_infinite());
+    }
+
+    #[test]
+    fn texture_write_region() {
+        assert!(Texture::from_bytes(4, 4, TextureKind::RG8, vec![0; 31]).is_err());
+
+        let mut texture = Texture::from_bytes(4, 4, TextureKind::RG8, vec![0; 32]).unwrap();
+        texture.write_region(Rect::new(1, 2, 2, 1), &[1, 2, 3, 4]).unwrap();
+        assert_eq!(&texture.pixels()[20..24], &[1, 2, 3, 4]);
+        assert!(texture.write_region(Rect::new(3, 3, 2, 1), &[0; 4]).is_err());
+
+        texture.mark_dirty(Rect::new(0, 0, 1, 1));
+        let dirty = texture.dirty_region.unwrap();
+        assert_eq!((dirty.x, dirty.y, dirty.w, dirty.h), (0, 0, 3, 3));
+        assert_eq!(texture.region_bytes(Rect::new(1, 2, 2, 1)), vec![1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,226 @@
+//! Texture array is a set of same-sized images (layers) which is sampled as one texture
+//! with layer index as third texture coordinate.
+//!
+//! Terrain splatting and variations of materials need many textures, binding each of them
+//! to its own sampler quickly runs into limit of samplers, and packing them into giant atlas
+//! breaks tiling and mip-mapping at borders of regions. Texture array has neither problem:
+//! every layer is tiled and filtered independently, and all layers are bound at once.
+//!
+//! Surface selects layer of its texture arrays, see `Surface::set_texture_array`. So many
+//! surfaces (for example chunks of terrain with different ground) share one array and
+//! differ only by layer index.
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use rg3d::{
+//!     engine::resource_manager::ResourceManager,
+//!     renderer::surface::{Surface, SurfaceTextureArray},
+//!     resource::{texture::TextureKind, texture_array::TextureArray},
+//! };
+//!
+//! fn setup_ground(surface: &mut Surface, resource_manager: &mut ResourceManager) {
+//!     let array = TextureArray::load(&["grass.png", "rock.png", "sand.png"], TextureKind::RGBA8, resource_manager)
+//!         .unwrap();
+//!     surface.set_texture_array(Some(SurfaceTextureArray {
+//!         diffuse: Arc::new(Mutex::new(array)),
+//!         normal: None,
+//!         layer: 1,
+//!     }));
+//! }
+//! ```
+//!
+//! Texture arrays are not saved with scenes, like other properties of surfaces set by code,
+//! they must be re-created by game after load.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    fmt::{Display, Formatter},
+};
+use crate::{
+    engine::resource_manager::ResourceManager,
+    resource::texture::{Texture, TextureKind},
+    renderer::resource_tracker::{
+        ResourceTracker,
+        TEXTURE_QUEUE,
+    },
+};
+
+pub type SharedTextureArray = Arc<Mutex<TextureArray>>;
+
+#[derive(Debug)]
+pub enum TextureArrayError {
+    /// Array must have at least one layer.
+    NoLayers,
+    /// Layer has different size or kind than first layer.
+    LayerMismatch {
+        layer: usize,
+    },
+    /// Layer texture is not loaded or can't be loaded.
+    UnableToLoadLayer {
+        layer: usize,
+    },
+    /// Size of pixel data does not match size of array.
+    InvalidDataSize {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl Display for TextureArrayError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            TextureArrayError::NoLayers => write!(f, "Texture array must have at least one layer"),
+            TextureArrayError::LayerMismatch { layer } =>
+                write!(f, "Layer {} of texture array has different size or kind than first layer", layer),
+            TextureArrayError::UnableToLoadLayer { layer } => write!(f, "Unable to load layer {} of texture array", layer),
+            TextureArrayError::InvalidDataSize { expected, actual } =>
+                write!(f, "Invalid texture array data size, expected {} bytes, got {}", expected, actual),
+        }
+    }
+}
+
+/// See module docs.
+pub struct TextureArray {
+    width: u32,
+    height: u32,
+    layer_count: u32,
+    kind: TextureKind,
+    /// Pixels of layers one after another.
+    pub(in crate) bytes: Vec<u8>,
+    /// Array was modified since last upload to GPU.
+    pub(in crate) modified: bool,
+    pub(in crate) tracker: ResourceTracker,
+}
+
+impl TextureArray {
+    /// Creates texture array from raw pixels of layers, stored one after another, each
+    /// layer is stored row by row without any padding.
+    pub fn from_bytes(width: u32, height: u32, layer_count: u32, kind: TextureKind, bytes: Vec<u8>) -> Result<Self, TextureArrayError> {
+        if layer_count == 0 {
+            return Err(TextureArrayError::NoLayers);
+        }
+        let expected = width as usize * height as usize * layer_count as usize * kind.bytes_per_pixel();
+        if bytes.len() != expected {
+            return Err(TextureArrayError::InvalidDataSize { expected, actual: bytes.len() });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            layer_count,
+            kind,
+            bytes,
+            modified: false,
+            tracker: ResourceTracker::new(&TEXTURE_QUEUE),
+        })
+    }
+
+    /// Assembles texture array from given textures, all textures must be loaded and must
+    /// have the same size and kind.
+    pub fn from_textures(textures: &[&Texture]) -> Result<Self, TextureArrayError> {
+        let first = textures.first().ok_or(TextureArrayError::NoLayers)?;
+        let mut bytes = Vec::with_capacity(first.pixels().len() * textures.len());
+        for (layer, texture) in textures.iter().enumerate() {
+            if !texture.is_loaded() {
+                return Err(TextureArrayError::UnableToLoadLayer { layer });
+            }
+            if texture.width() != first.width() || texture.height() != first.height() || texture.kind() != first.kind() {
+                return Err(TextureArrayError::LayerMismatch { layer });
+            }
+            bytes.extend_from_slice(texture.pixels());
+        }
+        Self::from_bytes(first.width(), first.height(), textures.len() as u32, first.kind(), bytes)
+    }
+
+    /// Loads textures from given files using resource manager and assembles texture array
+    /// from them.
+    pub fn load<P: AsRef<Path>>(paths: &[P], kind: TextureKind, resource_manager: &mut ResourceManager) -> Result<Self, TextureArrayError> {
+        let mut textures = Vec::with_capacity(paths.len());
+        for (layer, path) in paths.iter().enumerate() {
+            let texture = resource_manager.request_texture(path, kind)
+                .ok_or(TextureArrayError::UnableToLoadLayer { layer })?;
+            textures.push(texture);
+        }
+        let guards = textures.iter().map(|texture| texture.lock().unwrap()).collect::<Vec<_>>();
+        Self::from_textures(&guards.iter().map(|texture| &**texture).collect::<Vec<_>>())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layer_count(&self) -> u32 {
+        self.layer_count
+    }
+
+    pub fn kind(&self) -> TextureKind {
+        self.kind
+    }
+
+    fn layer_size(&self) -> usize {
+        self.width as usize * self.height as usize * self.kind.bytes_per_pixel()
+    }
+
+    /// Returns pixels of given layer, `None` if there is no such layer.
+    pub fn layer_pixels(&self, layer: u32) -> Option<&[u8]> {
+        if layer < self.layer_count {
+            let size = self.layer_size();
+            let begin = layer as usize * size;
+            Some(&self.bytes[begin..(begin + size)])
+        } else {
+            None
+        }
+    }
+
+    /// Replaces pixels of given layer, whole array will be uploaded to GPU again.
+    pub fn set_layer_pixels(&mut self, layer: u32, data: &[u8]) -> Result<(), TextureArrayError> {
+        if layer >= self.layer_count {
+            return Err(TextureArrayError::LayerMismatch { layer: layer as usize });
+        }
+        let size = self.layer_size();
+        if data.len() != size {
+            return Err(TextureArrayError::InvalidDataSize { expected: size, actual: data.len() });
+        }
+        let begin = layer as usize * size;
+        self.bytes[begin..(begin + size)].copy_from_slice(data);
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Returns amount of memory occupied by pixels of all layers in RAM.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::resource::{
+        texture::{Texture, TextureKind},
+        texture_array::TextureArray,
+    };
+
+    #[test]
+    fn test_texture_array_from_textures() {
+        let a = Texture::from_bytes(2, 2, TextureKind::R8, vec![1; 4]).unwrap();
+        let b = Texture::from_bytes(2, 2, TextureKind::R8, vec![2; 4]).unwrap();
+        let c = Texture::from_bytes(4, 2, TextureKind::R8, vec![3; 8]).unwrap();
+
+        let mut array = TextureArray::from_textures(&[&a, &b]).unwrap();
+        assert_eq!(array.layer_count(), 2);
+        assert_eq!(array.layer_pixels(1), Some(&[2u8, 2, 2, 2][..]));
+        assert_eq!(array.layer_pixels(2), None);
+
+        array.set_layer_pixels(0, &[5; 4]).unwrap();
+        assert_eq!(array.layer_pixels(0), Some(&[5u8, 5, 5, 5][..]));
+        assert!(array.set_layer_pixels(0, &[5; 3]).is_err());
+
+        assert!(TextureArray::from_textures(&[&a, &c]).is_err());
+        assert!(TextureArray::from_textures(&[]).is_err());
+    }
+}
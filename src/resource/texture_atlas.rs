@@ -0,0 +1,195 @@
+//! Texture atlas is a texture with a set of named rectangular regions.
+//!
+//! Atlas can be defined as regular grid of cells, or loaded from JSON descriptor in
+//! "hash" or "array" format produced by TexturePacker and compatible tools:
+//!
+//! ```text
+//! {
+//!     "frames": {
+//!         "coin": { "frame": { "x": 0, "y": 0, "w": 32, "h": 32 } },
+//!         "heart": { "frame": { "x": 32, "y": 0, "w": 32, "h": 32 } }
+//!     },
+//!     "meta": { "image": "items.png", "size": { "w": 64, "h": 32 } }
+//! }
+//! ```
+//!
+//! Sprites which use regions of the same atlas are rendered in one draw call.
+
+use std::{
+    path::Path,
+    fmt::{Display, Formatter},
+};
+use crate::{
+    core::math::Rect,
+    engine::resource_manager::{
+        ResourceManager,
+        SharedTexture,
+    },
+    resource::texture::TextureKind,
+    utils::json::{JsonValue, JsonError},
+};
+
+#[derive(Debug)]
+pub enum TextureAtlasError {
+    Io(std::io::Error),
+    Json(JsonError),
+    /// Descriptor is valid JSON, but its layout is not supported.
+    InvalidDescriptor(String),
+    UnableToLoadTexture,
+}
+
+impl Display for TextureAtlasError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            TextureAtlasError::Io(io) => write!(f, "Io error: {}", io),
+            TextureAtlasError::Json(json) => write!(f, "{}", json),
+            TextureAtlasError::InvalidDescriptor(msg) => write!(f, "Invalid atlas descriptor: {}", msg),
+            TextureAtlasError::UnableToLoadTexture => write!(f, "Unable to load atlas texture"),
+        }
+    }
+}
+
+impl From<std::io::Error> for TextureAtlasError {
+    fn from(err: std::io::Error) -> Self {
+        TextureAtlasError::Io(err)
+    }
+}
+
+impl From<JsonError> for TextureAtlasError {
+    fn from(err: JsonError) -> Self {
+        TextureAtlasError::Json(err)
+    }
+}
+
+#[derive(Clone)]
+pub struct AtlasRegion {
+    /// Name of region, grid atlases use index of cell as name.
+    pub name: String,
+    /// Normalized texture coordinates of region.
+    pub uv_rect: Rect<f32>,
+}
+
+#[derive(Clone)]
+pub struct TextureAtlas {
+    texture: SharedTexture,
+    regions: Vec<AtlasRegion>,
+}
+
+impl TextureAtlas {
+    /// Creates atlas with regular grid of `columns` x `rows` cells. Cells are numbered from
+    /// left to right, from top to bottom.
+    pub fn from_grid(texture: SharedTexture, columns: u32, rows: u32) -> Self {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let w = 1.0 / columns as f32;
+        let h = 1.0 / rows as f32;
+        let mut regions = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                regions.push(AtlasRegion {
+                    name: (row * columns + column).to_string(),
+                    uv_rect: Rect { x: column as f32 * w, y: row as f32 * h, w, h },
+                });
+            }
+        }
+        Self {
+            texture,
+            regions,
+        }
+    }
+
+    /// Creates atlas from JSON descriptor. If descriptor does not contain size of image,
+    /// size of texture will be used, so texture must be loaded in this case.
+    pub fn from_json(texture: SharedTexture, json: &str) -> Result<Self, TextureAtlasError> {
+        let root = JsonValue::parse(json)?;
+
+        let (width, height) = match root.get("meta").and_then(|meta| meta.get("size")) {
+            Some(size) => (
+                size.get("w").and_then(|w| w.as_f32()).unwrap_or(0.0),
+                size.get("h").and_then(|h| h.as_f32()).unwrap_or(0.0)
+            ),
+            None => {
+                let texture = texture.lock().unwrap();
+                (texture.width as f32, texture.height as f32)
+            }
+        };
+
+        if width <= 0.0 || height <= 0.0 {
+            return Err(TextureAtlasError::InvalidDescriptor("unknown size of atlas image".to_owned()));
+        }
+
+        let frames = root.get("frames")
+            .ok_or_else(|| TextureAtlasError::InvalidDescriptor("no frames".to_owned()))?;
+
+        // Both "hash" (name -> frame) and "array" (list of frames with "filename") layouts
+        // are supported.
+        let named_frames: Vec<(&str, &JsonValue)> = match frames {
+            JsonValue::Object(members) => members.iter()
+                .map(|(name, frame)| (name.as_str(), frame))
+                .collect(),
+            JsonValue::Array(items) => items.iter()
+                .map(|frame| (frame.get("filename").and_then(|n| n.as_str()).unwrap_or(""), frame))
+                .collect(),
+            _ => return Err(TextureAtlasError::InvalidDescriptor("frames must be object or array".to_owned()))
+        };
+
+        let mut regions = Vec::with_capacity(named_frames.len());
+        for (name, frame) in named_frames {
+            let rect = frame.get("frame").unwrap_or(frame);
+            let component = |c: &str| {
+                rect.get(c)
+                    .and_then(|v| v.as_f32())
+                    .ok_or_else(|| TextureAtlasError::InvalidDescriptor(format!("frame {} has no {}", name, c)))
+            };
+            regions.push(AtlasRegion {
+                name: name.to_owned(),
+                uv_rect: Rect {
+                    x: component("x")? / width,
+                    y: component("y")? / height,
+                    w: component("w")? / width,
+                    h: component("h")? / height,
+                },
+            });
+        }
+
+        Ok(Self {
+            texture,
+            regions,
+        })
+    }
+
+    /// Loads atlas from JSON descriptor file. Texture is requested from resource manager
+    /// using `meta.image` path, which is relative to descriptor file.
+    pub fn load<P: AsRef<Path>>(path: P, resource_manager: &mut ResourceManager) -> Result<Self, TextureAtlasError> {
+        let json = std::fs::read_to_string(path.as_ref())?;
+        let root = JsonValue::parse(&json)?;
+        let image = root.get("meta")
+            .and_then(|meta| meta.get("image"))
+            .and_then(|image| image.as_str())
+            .ok_or_else(|| TextureAtlasError::InvalidDescriptor("no meta.image".to_owned()))?;
+        let image_path = match path.as_ref().parent() {
+            Some(parent) => parent.join(image),
+            None => Path::new(image).to_owned(),
+        };
+        let texture = resource_manager.request_texture(image_path, TextureKind::RGBA8)
+            .ok_or(TextureAtlasError::UnableToLoadTexture)?;
+        Self::from_json(texture, &json)
+    }
+
+    pub fn texture(&self) -> SharedTexture {
+        self.texture.clone()
+    }
+
+    pub fn regions(&self) -> &[AtlasRegion] {
+        &self.regions
+    }
+
+    pub fn region(&self, index: usize) -> Option<&AtlasRegion> {
+        self.regions.get(index)
+    }
+
+    /// Returns index of region with given name.
+    pub fn find_region(&self, name: &str) -> Option<usize> {
+        self.regions.iter().position(|r| r.name == name)
+    }
+}
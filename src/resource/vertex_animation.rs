@@ -0,0 +1,228 @@
+//! Vertex animation textures.
+//!
+//! Skinning is expensive for massive crowds: each character needs its own bone matrices
+//! and a separate draw call. Vertex animation texture (VAT) is an alternative - positions
+//! and normals of every vertex are baked for every frame of an animation into textures,
+//! one row per frame and one column per vertex, so vertex shader just reads them by index
+//! of vertex and current frame. Thousands of characters with the same mesh can be drawn
+//! with one instanced draw call, each with its own animation time, see `scene::crowd`.
+//!
+//! Animations are usually baked from skinned surface in a loading screen, or baked once
+//! and stored as frames:
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::math::mat4::Mat4,
+//!     renderer::surface::Surface,
+//!     resource::vertex_animation::VertexAnimation,
+//! };
+//!
+//! // Bone matrices of each frame are taken from animated skeleton, see
+//! // `Surface::fill_bone_matrices`.
+//! fn bake(surface: &Surface, frames: &[Vec<Mat4>]) -> VertexAnimation {
+//!     VertexAnimation::bake_skinned(surface, frames, 30.0).unwrap()
+//! }
+//! ```
+//!
+//! Textures are limited in size, so amount of vertices is limited by `MAX_VERTICES`.
+
+use std::{
+    fmt::{Display, Formatter},
+    sync::{Arc, Mutex},
+};
+use crate::{
+    core::math::{
+        vec3::Vec3,
+        mat4::Mat4,
+    },
+    engine::resource_manager::SharedTexture,
+    renderer::surface::Surface,
+    resource::texture::{Texture, TextureKind, TextureFilter, f32_to_f16},
+};
+
+#[derive(Debug)]
+pub enum VertexAnimationError {
+    NoFrames,
+    /// Surface has more vertices than texture can hold.
+    TooManyVertices(usize),
+    /// Frame has different amount of positions or normals than first frame.
+    VertexCountMismatch {
+        frame: usize,
+        expected: usize,
+        actual: usize,
+    },
+    Texture(String),
+}
+
+impl Display for VertexAnimationError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            VertexAnimationError::NoFrames => write!(f, "Vertex animation has no frames"),
+            VertexAnimationError::TooManyVertices(count) =>
+                write!(f, "Too many vertices for vertex animation: {}, max is {}", count, VertexAnimation::MAX_VERTICES),
+            VertexAnimationError::VertexCountMismatch { frame, expected, actual } =>
+                write!(f, "Frame {} has {} vertices, expected {}", frame, actual, expected),
+            VertexAnimationError::Texture(msg) => write!(f, "Unable to create texture: {}", msg),
+        }
+    }
+}
+
+/// Positions and normals of all vertices of a surface in one frame, in local coordinates
+/// of surface.
+#[derive(Clone, Debug, Default)]
+pub struct VertexAnimationFrame {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct VertexAnimation {
+    vertex_count: usize,
+    frame_count: usize,
+    frame_rate: f32,
+    positions: SharedTexture,
+    normals: SharedTexture,
+}
+
+fn make_texture(width: usize, height: usize, kind: TextureKind, bytes: Vec<u8>) -> Result<SharedTexture, VertexAnimationError> {
+    let mut texture = Texture::from_bytes(width as u32, height as u32, kind, bytes)
+        .map_err(VertexAnimationError::Texture)?;
+    // Texels are fetched exactly, filtering would mix neighbour vertices.
+    texture.set_filter(Some(TextureFilter::Nearest));
+    texture.set_anisotropy(Some(1.0));
+    Ok(Arc::new(Mutex::new(texture)))
+}
+
+impl VertexAnimation {
+    /// Max amount of vertices of animated surface, it is max width of texture which is
+    /// supported by most of hardware.
+    pub const MAX_VERTICES: usize = 8192;
+
+    /// Creates animation from frames, every frame must have the same amount of positions
+    /// and normals, which must match amount of vertices of animated surface.
+    pub fn from_frames(frames: &[VertexAnimationFrame], frame_rate: f32) -> Result<Self, VertexAnimationError> {
+        let first = frames.first().ok_or(VertexAnimationError::NoFrames)?;
+        let vertex_count = first.positions.len();
+        if vertex_count > Self::MAX_VERTICES {
+            return Err(VertexAnimationError::TooManyVertices(vertex_count));
+        }
+
+        let mut positions = Vec::with_capacity(vertex_count * frames.len() * 8);
+        let mut normals = Vec::with_capacity(vertex_count * frames.len() * 4);
+        for (n, frame) in frames.iter().enumerate() {
+            for actual in [frame.positions.len(), frame.normals.len()].iter() {
+                if *actual != vertex_count {
+                    return Err(VertexAnimationError::VertexCountMismatch {
+                        frame: n,
+                        expected: vertex_count,
+                        actual: *actual,
+                    });
+                }
+            }
+
+            for position in frame.positions.iter() {
+                for &component in [position.x, position.y, position.z, 1.0].iter() {
+                    positions.extend_from_slice(&f32_to_f16(component).to_ne_bytes());
+                }
+            }
+            for normal in frame.normals.iter() {
+                for &component in [normal.x, normal.y, normal.z].iter() {
+                    normals.push(((component.max(-1.0).min(1.0) * 0.5 + 0.5) * 255.0).round() as u8);
+                }
+                normals.push(255);
+            }
+        }
+
+        Ok(Self {
+            vertex_count,
+            frame_count: frames.len(),
+            frame_rate: frame_rate.max(std::f32::EPSILON),
+            positions: make_texture(vertex_count.max(1), frames.len(), TextureKind::RGBA16F, positions)?,
+            normals: make_texture(vertex_count.max(1), frames.len(), TextureKind::RGBA8, normals)?,
+        })
+    }
+
+    /// Bakes animation of skinned surface, each element of `frames` is a set of bone
+    /// matrices of the surface in one frame, see `Surface::fill_bone_matrices`. Positions
+    /// are baked as they are produced by bone matrices, so surface of crowd should be the
+    /// same as skinned one, but without bones.
+    pub fn bake_skinned(surface: &Surface, frames: &[Vec<Mat4>], frame_rate: f32) -> Result<Self, VertexAnimationError> {
+        let data = surface.get_data();
+        let data = data.lock().unwrap();
+        let frames = frames.iter()
+            .map(|bone_matrices| VertexAnimationFrame {
+                positions: data.get_vertices().iter().map(|v| v.skinned_position(bone_matrices)).collect(),
+                normals: data.get_vertices().iter().map(|v| v.skinned_normal(bone_matrices)).collect(),
+            })
+            .collect::<Vec<_>>();
+        Self::from_frames(&frames, frame_rate)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    pub fn frame_rate(&self) -> f32 {
+        self.frame_rate
+    }
+
+    /// Returns length of animation in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frame_count as f32 / self.frame_rate
+    }
+
+    /// Returns frame (with fractional part for interpolation between frames) at given time,
+    /// animation is looped.
+    pub fn frame_at(&self, time: f32) -> f32 {
+        let frames = self.frame_count as f32;
+        let frame = (time * self.frame_rate) % frames;
+        if frame < 0.0 {
+            frame + frames
+        } else {
+            frame
+        }
+    }
+
+    /// Returns texture with positions, one row per frame.
+    pub fn positions(&self) -> SharedTexture {
+        self.positions.clone()
+    }
+
+    /// Returns texture with normals, one row per frame.
+    pub fn normals(&self) -> SharedTexture {
+        self.normals.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        resource::vertex_animation::{VertexAnimation, VertexAnimationFrame},
+    };
+
+    #[test]
+    fn test_vertex_animation_frames() {
+        let frame = |y: f32| VertexAnimationFrame {
+            positions: vec![Vec3::new(0.0, y, 0.0), Vec3::new(1.0, y, 0.0)],
+            normals: vec![Vec3::new(0.0, 1.0, 0.0); 2],
+        };
+        let animation = VertexAnimation::from_frames(&[frame(0.0), frame(1.0), frame(2.0), frame(3.0)], 2.0).unwrap();
+        assert_eq!(animation.frame_count(), 4);
+        assert_eq!(animation.duration(), 2.0);
+        assert_eq!(animation.frame_at(0.75), 1.5);
+        assert_eq!(animation.frame_at(2.5), 1.0);
+        assert_eq!(animation.frame_at(-0.5), 3.0);
+        assert_eq!(animation.positions().lock().unwrap().pixels().len(), 2 * 4 * 8);
+
+        let mut broken = frame(4.0);
+        broken.normals.pop();
+        assert!(VertexAnimation::from_frames(&[frame(0.0), broken], 2.0).is_err());
+        assert!(VertexAnimation::from_frames(&[], 2.0).is_err());
+    }
+}
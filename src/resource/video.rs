@@ -0,0 +1,427 @@
+//! Video playback into a texture.
+//!
+//! Video player decodes frames of a video and writes them into a texture, which can be used
+//! as any other texture: for full-screen intro movies in UI or for in-world screens as
+//! diffuse texture of a mesh. Player keeps pace with time passed to `update`, frames are
+//! skipped if game runs slower than video.
+//!
+//! Decoding is done by implementations of `VideoDecoder` trait. Engine has built-in decoder
+//! of uncompressed YUV4MPEG2 (`.y4m`) streams, compressed formats like Theora or VP9 can be
+//! played by implementing the trait on top of a decoding library. Any video can be converted
+//! to y4m, for example by `ffmpeg -i intro.webm -pix_fmt yuv420p intro.y4m`.
+//!
+//! ```no_run
+//! use rg3d::resource::video::{VideoPlayer, Y4mDecoder};
+//!
+//! let decoder = Y4mDecoder::open("data/intro.y4m").unwrap();
+//! let mut player = VideoPlayer::new(Box::new(decoder));
+//! player.play();
+//! // Use player.texture() in UI image or material, and call each frame:
+//! player.update(1.0 / 60.0).unwrap();
+//! ```
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, BufReader},
+    path::Path,
+    sync::{Arc, Mutex},
+    fmt::{Display, Formatter},
+};
+use crate::{
+    engine::resource_manager::SharedTexture,
+    resource::texture::{Texture, TextureKind},
+};
+
+#[derive(Debug)]
+pub enum VideoError {
+    Io(std::io::Error),
+    /// Stream is damaged or is not a video of expected format.
+    InvalidFormat(String),
+    /// Stream is valid, but its parameters are not supported by decoder.
+    UnsupportedFormat(String),
+}
+
+impl Display for VideoError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            VideoError::Io(io) => write!(f, "Io error: {}", io),
+            VideoError::InvalidFormat(msg) => write!(f, "Invalid video: {}", msg),
+            VideoError::UnsupportedFormat(msg) => write!(f, "Unsupported video: {}", msg),
+        }
+    }
+}
+
+impl From<std::io::Error> for VideoError {
+    fn from(err: std::io::Error) -> Self {
+        VideoError::Io(err)
+    }
+}
+
+/// Source of video frames.
+pub trait VideoDecoder: Send {
+    fn width(&self) -> u32;
+
+    fn height(&self) -> u32;
+
+    /// Returns amount of frames per second.
+    fn frame_rate(&self) -> f32;
+
+    /// Decodes next frame into `rgba` buffer, which has `width * height * 4` bytes. Returns
+    /// `false` if there are no more frames.
+    fn read_frame(&mut self, rgba: &mut [u8]) -> Result<bool, VideoError>;
+
+    /// Moves to the first frame.
+    fn rewind(&mut self) -> Result<(), VideoError>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Chroma {
+    /// Chroma planes have half width and half height of luma plane.
+    Subsampled420,
+    /// Chroma planes have the same size as luma plane.
+    Full444,
+    /// There are no chroma planes.
+    Mono,
+}
+
+/// Decoder of YUV4MPEG2 streams with 8-bit 4:2:0, 4:4:4 or monochrome frames.
+pub struct Y4mDecoder<R: Read + Seek + Send> {
+    reader: R,
+    width: u32,
+    height: u32,
+    frame_rate: f32,
+    chroma: Chroma,
+    /// Offset of the first frame in stream.
+    data_start: u64,
+    planes: Vec<u8>,
+}
+
+impl Y4mDecoder<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, VideoError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Reads bytes until new line, returns `None` if stream ended before first byte.
+fn read_line<R: Read>(reader: &mut R) -> Result<Option<String>, VideoError> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            return Err(VideoError::InvalidFormat("unexpected end of stream".to_owned()));
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| VideoError::InvalidFormat("header is not valid text".to_owned()))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, what: &str) -> Result<T, VideoError> {
+    value.parse().map_err(|_| VideoError::InvalidFormat(format!("invalid {} {}", what, value)))
+}
+
+fn clamp_to_u8(value: f32) -> u8 {
+    value.round().max(0.0).min(255.0) as u8
+}
+
+/// Converts limited range BT.601 color into RGB.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let y = 1.164 * (f32::from(y) - 16.0);
+    let u = f32::from(u) - 128.0;
+    let v = f32::from(v) - 128.0;
+    [
+        clamp_to_u8(y + 1.596 * v),
+        clamp_to_u8(y - 0.392 * u - 0.813 * v),
+        clamp_to_u8(y + 2.017 * u),
+    ]
+}
+
+impl<R: Read + Seek + Send> Y4mDecoder<R> {
+    /// Reads header of stream, returns error if stream is not a supported y4m stream.
+    pub fn new(mut reader: R) -> Result<Self, VideoError> {
+        let header = read_line(&mut reader)?
+            .ok_or_else(|| VideoError::InvalidFormat("empty stream".to_owned()))?;
+        let mut tokens = header.split(' ');
+        if tokens.next() != Some("YUV4MPEG2") {
+            return Err(VideoError::InvalidFormat("missing YUV4MPEG2 signature".to_owned()));
+        }
+
+        let mut width = 0;
+        let mut height = 0;
+        let mut frame_rate = 25.0;
+        let mut chroma = Chroma::Subsampled420;
+        for token in tokens.filter(|token| !token.is_empty() && token.is_char_boundary(1)) {
+            let (tag, value) = token.split_at(1);
+            match tag {
+                "W" => width = parse_number(value, "width")?,
+                "H" => height = parse_number(value, "height")?,
+                "F" => {
+                    let mut parts = value.split(':');
+                    let numerator: f32 = parse_number(parts.next().unwrap_or(""), "frame rate")?;
+                    let denominator: f32 = parse_number(parts.next().unwrap_or("1"), "frame rate")?;
+                    if numerator <= 0.0 || denominator <= 0.0 {
+                        return Err(VideoError::InvalidFormat(format!("invalid frame rate {}", value)));
+                    }
+                    frame_rate = numerator / denominator;
+                }
+                "C" => chroma = match value {
+                    "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::Subsampled420,
+                    "444" => Chroma::Full444,
+                    "mono" => Chroma::Mono,
+                    _ => return Err(VideoError::UnsupportedFormat(format!("color space {}", value))),
+                },
+                // Interlacing, aspect ratio and comments do not affect decoding.
+                _ => (),
+            }
+        }
+
+        if width == 0 || height == 0 {
+            return Err(VideoError::InvalidFormat("missing frame size".to_owned()));
+        }
+
+        let data_start = reader.seek(SeekFrom::Current(0))?;
+
+        let mut decoder = Self {
+            reader,
+            width,
+            height,
+            frame_rate,
+            chroma,
+            data_start,
+            planes: Vec::new(),
+        };
+        decoder.planes = vec![0; decoder.frame_size()];
+        Ok(decoder)
+    }
+
+    fn chroma_size(&self) -> (usize, usize) {
+        match self.chroma {
+            Chroma::Subsampled420 => ((self.width as usize + 1) / 2, (self.height as usize + 1) / 2),
+            Chroma::Full444 => (self.width as usize, self.height as usize),
+            Chroma::Mono => (0, 0),
+        }
+    }
+
+    fn frame_size(&self) -> usize {
+        let (chroma_width, chroma_height) = self.chroma_size();
+        self.width as usize * self.height as usize + 2 * chroma_width * chroma_height
+    }
+}
+
+impl<R: Read + Seek + Send> VideoDecoder for Y4mDecoder<R> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn frame_rate(&self) -> f32 {
+        self.frame_rate
+    }
+
+    fn read_frame(&mut self, rgba: &mut [u8]) -> Result<bool, VideoError> {
+        let frame_header = match read_line(&mut self.reader)? {
+            Some(header) => header,
+            None => return Ok(false),
+        };
+        if !frame_header.starts_with("FRAME") {
+            return Err(VideoError::InvalidFormat("missing frame header".to_owned()));
+        }
+        self.reader.read_exact(&mut self.planes)?;
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let (chroma_width, chroma_height) = self.chroma_size();
+        let (luma, chroma) = self.planes.split_at(width * height);
+        let (u_plane, v_plane) = chroma.split_at(chroma_width * chroma_height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (u, v) = match self.chroma {
+                    Chroma::Subsampled420 => {
+                        let index = (y / 2) * chroma_width + x / 2;
+                        (u_plane[index], v_plane[index])
+                    }
+                    Chroma::Full444 => (u_plane[y * width + x], v_plane[y * width + x]),
+                    Chroma::Mono => (128, 128),
+                };
+                let [r, g, b] = yuv_to_rgb(luma[y * width + x], u, v);
+                let offset = (y * width + x) * 4;
+                rgba[offset] = r;
+                rgba[offset + 1] = g;
+                rgba[offset + 2] = b;
+                rgba[offset + 3] = 255;
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn rewind(&mut self) -> Result<(), VideoError> {
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    /// Video was not started yet or reached its end.
+    Stopped,
+}
+
+/// See module docs.
+pub struct VideoPlayer {
+    decoder: Box<dyn VideoDecoder>,
+    texture: SharedTexture,
+    frame: Vec<u8>,
+    status: PlaybackStatus,
+    looping: bool,
+    speed: f32,
+    time: f32,
+    /// Amount of frames decoded since start of playback.
+    decoded_frames: u64,
+}
+
+impl VideoPlayer {
+    /// Creates stopped player and RGBA texture of size of video.
+    pub fn new(decoder: Box<dyn VideoDecoder>) -> Self {
+        let (width, height) = (decoder.width(), decoder.height());
+        let frame = vec![0; width as usize * height as usize * 4];
+        let texture = Texture::from_bytes(width, height, TextureKind::RGBA8, frame.clone())
+            .expect("size of frame must match size of texture");
+        Self {
+            decoder,
+            texture: Arc::new(Mutex::new(texture)),
+            frame,
+            status: PlaybackStatus::Stopped,
+            looping: false,
+            speed: 1.0,
+            time: 0.0,
+            decoded_frames: 0,
+        }
+    }
+
+    /// Returns texture into which frames are written.
+    pub fn texture(&self) -> SharedTexture {
+        self.texture.clone()
+    }
+
+    /// Starts playback, if video was stopped it is played from start.
+    pub fn play(&mut self) {
+        self.status = PlaybackStatus::Playing;
+    }
+
+    pub fn pause(&mut self) {
+        if self.status == PlaybackStatus::Playing {
+            self.status = PlaybackStatus::Paused;
+        }
+    }
+
+    /// Stops playback and moves to start of video, current frame stays in texture.
+    pub fn stop(&mut self) -> Result<(), VideoError> {
+        self.status = PlaybackStatus::Stopped;
+        self.restart()
+    }
+
+    pub fn status(&self) -> PlaybackStatus {
+        self.status
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Sets playback speed multiplier.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Returns time in seconds since start of video.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    fn restart(&mut self) -> Result<(), VideoError> {
+        self.time = 0.0;
+        self.decoded_frames = 0;
+        self.decoder.rewind()
+    }
+
+    /// Advances playback by `dt` seconds and writes new frame into texture if there is one.
+    pub fn update(&mut self, dt: f32) -> Result<(), VideoError> {
+        if self.status != PlaybackStatus::Playing {
+            return Ok(());
+        }
+
+        self.time += dt * self.speed;
+        // Frame `n` is shown from `n / frame_rate` seconds.
+        let required_frames = (self.time * self.decoder.frame_rate()) as u64 + 1;
+        let mut new_frame = false;
+        while self.decoded_frames < required_frames {
+            if self.decoder.read_frame(&mut self.frame)? {
+                self.decoded_frames += 1;
+                new_frame = true;
+            } else if self.looping && self.decoded_frames > 0 {
+                self.restart()?;
+                break;
+            } else {
+                self.status = PlaybackStatus::Stopped;
+                self.restart()?;
+                break;
+            }
+        }
+
+        if new_frame {
+            self.texture.lock().unwrap().pixels_mut().copy_from_slice(&self.frame);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use crate::resource::video::{Y4mDecoder, VideoDecoder, VideoPlayer, PlaybackStatus};
+
+    #[test]
+    fn test_y4m_playback() {
+        // Two 2x2 4:2:0 frames: white and black.
+        let mut data = b"YUV4MPEG2 W2 H2 F2:1 Ip A1:1 C420jpeg\n".to_vec();
+        data.extend_from_slice(b"FRAME\n");
+        data.extend_from_slice(&[235, 235, 235, 235, 128, 128]);
+        data.extend_from_slice(b"FRAME\n");
+        data.extend_from_slice(&[16, 16, 16, 16, 128, 128]);
+
+        let decoder = Y4mDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!((decoder.width(), decoder.height(), decoder.frame_rate()), (2, 2, 2.0));
+
+        let mut player = VideoPlayer::new(Box::new(decoder));
+        player.play();
+        player.update(0.1).unwrap();
+        assert_eq!(&player.texture().lock().unwrap().pixels()[0..4], &[255, 255, 255, 255]);
+        player.update(0.5).unwrap();
+        assert_eq!(&player.texture().lock().unwrap().pixels()[0..4], &[0, 0, 0, 255]);
+        player.update(0.5).unwrap();
+        assert_eq!(player.status(), PlaybackStatus::Stopped);
+    }
+}
@@ -0,0 +1,284 @@
+//! Acoustics of a scene: reverb zones and occlusion of sounds by geometry.
+//!
+//! Sound context mixes sources without any knowledge of scene, so a shot in a cave sounds
+//! the same as a shot in an open field, and a radio behind a wall sounds like it is in the
+//! same room. Acoustics evaluates how a sound source should be heard by listener:
+//!
+//! - reverb zones are boxes placed in scene with reverb settings, a source which is
+//! inside of a zone is sent to reverb of the zone. When zones are nested (a room inside of
+//! a hangar) the smallest zone wins;
+//! - occlusion casts a ray from listener to source against static geometry of physics,
+//! each obstacle on the way attenuates sound and lowers cutoff frequency of low-pass
+//! filter, so sounds behind walls are quiet and muffled.
+//!
+//! Result of evaluation is a set of parameters for a source, game applies them to its
+//! sources each frame, for example gain is multiplied with gain of source and reverb settings
+//! select effect of sound context to which source is routed:
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, acoustics::{ReverbZone, ReverbSettings}};
+//! use rg3d::core::math::vec3::Vec3;
+//!
+//! fn setup(scene: &mut Scene) {
+//!     scene.acoustics.add_zone(ReverbZone::new(
+//!         Vec3::new(-10.0, 0.0, -10.0),
+//!         Vec3::new(10.0, 5.0, 10.0),
+//!         ReverbSettings { decay_time: 3.0, wet: 0.6 }));
+//! }
+//!
+//! fn evaluate(scene: &Scene, listener: Vec3, source: Vec3) {
+//!     let parameters = scene.acoustics.evaluate(&scene.physics, listener, source);
+//!     println!("gain {}, cutoff {} Hz", parameters.gain, parameters.low_pass_cutoff);
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            ray::Ray,
+        },
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    physics::{Physics, RayCastOptions},
+};
+
+/// Cutoff frequency of low-pass filter which does not change sound.
+pub const NO_LOW_PASS_CUTOFF: f32 = 22050.0;
+
+/// Parameters of reverb effect of a zone.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReverbSettings {
+    /// Time in seconds in which reverberation decays by 60 dB.
+    pub decay_time: f32,
+    /// Amount of reverberated signal in [0; 1] range.
+    pub wet: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        Self {
+            decay_time: 1.0,
+            wet: 0.5,
+        }
+    }
+}
+
+impl Visit for ReverbSettings {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.decay_time.visit("DecayTime", visitor)?;
+        self.wet.visit("Wet", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Box in world space with reverb settings.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ReverbZone {
+    min: Vec3,
+    max: Vec3,
+    pub settings: ReverbSettings,
+}
+
+impl Visit for ReverbZone {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.min.visit("Min", visitor)?;
+        self.max.visit("Max", visitor)?;
+        self.settings.visit("Settings", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl ReverbZone {
+    /// Creates zone from two opposite corners of box.
+    pub fn new(a: Vec3, b: Vec3, settings: ReverbSettings) -> Self {
+        Self {
+            min: Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            settings,
+        }
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.max
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x &&
+            point.y >= self.min.y && point.y <= self.max.y &&
+            point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    fn volume(&self) -> f32 {
+        let size = self.max - self.min;
+        size.x * size.y * size.z
+    }
+}
+
+/// How much obstacles affect sounds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OcclusionSettings {
+    /// Gain is multiplied by this value for each obstacle.
+    pub gain_per_obstacle: f32,
+    /// Cutoff frequency of low-pass filter is multiplied by this value for each obstacle.
+    pub cutoff_per_obstacle: f32,
+    /// Occluded sound is never quieter than this.
+    pub min_gain: f32,
+}
+
+impl Default for OcclusionSettings {
+    fn default() -> Self {
+        Self {
+            gain_per_obstacle: 0.5,
+            cutoff_per_obstacle: 0.2,
+            min_gain: 0.1,
+        }
+    }
+}
+
+impl Visit for OcclusionSettings {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.gain_per_obstacle.visit("GainPerObstacle", visitor)?;
+        self.cutoff_per_obstacle.visit("CutoffPerObstacle", visitor)?;
+        self.min_gain.visit("MinGain", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Parameters of a sound source evaluated by `Acoustics::evaluate`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SourceAcoustics {
+    /// Multiplier of gain of source.
+    pub gain: f32,
+    /// Cutoff frequency of low-pass filter in Hz, `NO_LOW_PASS_CUTOFF` if source is not
+    /// occluded.
+    pub low_pass_cutoff: f32,
+    /// Reverb of zone in which source is, if any.
+    pub reverb: Option<ReverbSettings>,
+    /// Amount of obstacles between listener and source.
+    pub obstacles: usize,
+}
+
+impl Default for SourceAcoustics {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            low_pass_cutoff: NO_LOW_PASS_CUTOFF,
+            reverb: None,
+            obstacles: 0,
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct Acoustics {
+    zones: Vec<ReverbZone>,
+    occlusion: Option<OcclusionSettings>,
+}
+
+impl Visit for Acoustics {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.zones.visit("Zones", visitor)?;
+        self.occlusion.visit("Occlusion", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl Acoustics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_zone(&mut self, zone: ReverbZone) {
+        self.zones.push(zone);
+    }
+
+    pub fn zones(&self) -> &[ReverbZone] {
+        &self.zones
+    }
+
+    pub fn zones_mut(&mut self) -> &mut Vec<ReverbZone> {
+        &mut self.zones
+    }
+
+    /// Enables occlusion with given settings, `None` disables it. Occlusion is disabled by
+    /// default, because each evaluation casts a ray.
+    pub fn set_occlusion(&mut self, occlusion: Option<OcclusionSettings>) {
+        self.occlusion = occlusion;
+    }
+
+    pub fn occlusion(&self) -> Option<OcclusionSettings> {
+        self.occlusion
+    }
+
+    /// Returns the smallest zone which contains given point.
+    pub fn zone_at(&self, point: Vec3) -> Option<&ReverbZone> {
+        self.zones
+            .iter()
+            .filter(|zone| zone.contains(point))
+            .min_by(|a, b| a.volume().partial_cmp(&b.volume()).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    /// Evaluates how a source at given position is heard by a listener.
+    pub fn evaluate(&self, physics: &Physics, listener: Vec3, source: Vec3) -> SourceAcoustics {
+        let mut result = SourceAcoustics {
+            reverb: self.zone_at(source).map(|zone| zone.settings),
+            ..Default::default()
+        };
+
+        if let Some(occlusion) = self.occlusion {
+            if let Some(ray) = Ray::from_two_points(&listener, &source) {
+                // Only static geometry occludes, otherwise characters would muffle each other.
+                let options = RayCastOptions {
+                    ignore_bodies: true,
+                    ignore_static_geometries: false,
+                    sort_results: false,
+                };
+                let mut hits = Vec::new();
+                physics.ray_cast(&ray, options, &mut hits);
+                result.obstacles = hits.len();
+                let obstacles = hits.len() as i32;
+                result.gain = occlusion.gain_per_obstacle.powi(obstacles).max(occlusion.min_gain);
+                result.low_pass_cutoff = NO_LOW_PASS_CUTOFF * occlusion.cutoff_per_obstacle.powi(obstacles);
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::math::vec3::Vec3;
+    use crate::scene::acoustics::{Acoustics, ReverbZone, ReverbSettings};
+
+    #[test]
+    fn test_nested_zones() {
+        let mut acoustics = Acoustics::new();
+        let hangar = ReverbSettings { decay_time: 4.0, wet: 0.8 };
+        let room = ReverbSettings { decay_time: 0.5, wet: 0.2 };
+        acoustics.add_zone(ReverbZone::new(Vec3::new(-50.0, 0.0, -50.0), Vec3::new(50.0, 20.0, 50.0), hangar));
+        acoustics.add_zone(ReverbZone::new(Vec3::new(2.0, 3.0, 2.0), Vec3::new(0.0, 0.0, 0.0), room));
+
+        assert_eq!(acoustics.zone_at(Vec3::new(1.0, 1.0, 1.0)).unwrap().settings, room);
+        assert_eq!(acoustics.zone_at(Vec3::new(10.0, 1.0, 1.0)).unwrap().settings, hangar);
+        assert!(acoustics.zone_at(Vec3::new(100.0, 1.0, 1.0)).is_none());
+    }
+}
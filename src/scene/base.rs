@@ -8,12 +8,18 @@
 
 #![warn(missing_docs)]
 
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    Arc,
+    Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
 use crate::{
     resource::model::Model,
     scene::{
         node::Node,
-        transform::Transform
+        graph::Graph,
+        transform::Transform,
+        instance::InstanceOverrides,
     },
     core::{
         math::{vec3::Vec3, mat4::Mat4},
@@ -22,15 +28,29 @@ use crate::{
     }
 };
 
+/// Incremented on every rename of any node, graphs use it to invalidate their name indices.
+static NAME_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns current generation of node names.
+pub(in crate) fn name_generation() -> usize {
+    NAME_GENERATION.load(Ordering::Relaxed)
+}
+
 /// See module docs.
 pub struct Base {
     name: String,
     local_transform: Transform,
     visibility: bool,
     pub(in crate) global_visibility: bool,
+    enabled: bool,
+    pub(in crate) global_enabled: bool,
     pub(in crate) parent: Handle<Node>,
     pub(in crate) children: Vec<Handle<Node>>,
     pub(in crate) global_transform: Mat4,
+    /// Set when local transform could have been changed (or node was re-linked), global
+    /// transforms of node and its descendants are recalculated only if this flag is set.
+    /// Non-serializable.
+    pub(in crate) transform_changed: bool,
     /// Bone-specific matrix. Non-serializable.
     pub(in crate) inv_bind_pose_transform: Mat4,
     /// A resource from which this node was instantiated from, can work in pair
@@ -46,12 +66,19 @@ pub struct Base {
     /// Maximum amount of Some(time) that node will "live" or None
     /// if node has undefined lifetime.
     lifetime: Option<f32>,
+    /// Node is not rendered when it is farther from camera than this distance.
+    max_render_distance: Option<f32>,
+    /// Whether dynamic state of node is stored in save games, see `scene::save`.
+    persistent: bool,
+    /// Properties changed on instance of resource, see `scene::instance`.
+    overrides: InstanceOverrides,
 }
 
 impl Base {
     /// Sets name of node. Can be useful to mark a node to be able to find it later on.
     pub fn set_name(&mut self, name: &str) -> &mut Self {
         self.name = name.to_owned();
+        NAME_GENERATION.fetch_add(1, Ordering::Relaxed);
         self
     }
 
@@ -69,12 +96,14 @@ impl Base {
     /// Returns mutable reference to local transform of a node, can be used to set
     /// some local spatial properties, such as position, rotation, scale, etc.
     pub fn local_transform_mut(&mut self) -> &mut Transform {
+        self.transform_changed = true;
         &mut self.local_transform
     }
 
     /// Sets new local transform of a node.
     pub fn set_local_transform(&mut self, transform: Transform) -> &mut Self {
         self.local_transform = transform;
+        self.transform_changed = true;
         self
     }
 
@@ -147,7 +176,7 @@ impl Base {
     /// Returns combined visibility of an node. This is the final visibility of a node.
     /// Global visibility calculated using visibility of all parent nodes until root one,
     /// so if some parent node upper on tree is invisible then all its children will be
-    /// invisible. It defines if object will be rendered. It is *not* the same as real
+    /// invisible. Disabled nodes are always invisible. It defines if object will be rendered. It is *not* the same as real
     /// visibility point of view of some camera. To check if object is visible from some
     /// camera, use frustum visibility check. However this still can't tell you if object
     /// is behind obstacle or not.
@@ -155,6 +184,66 @@ impl Base {
         self.global_visibility
     }
 
+    /// Enables or disables node. Disabled node and its whole subtree are excluded from
+    /// graph updates (particles, trails, cloth, lifetime, etc.), animation, spline and
+    /// tween application, physics sync and rendering, but nodes are kept in graph. This
+    /// is a cheap alternative to removing and re-adding nodes when some feature is
+    /// toggled on and off often, like a flashlight in hands of a character.
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Returns local enabled state of a node.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns true if node and all its ancestors are enabled. Value is calculated
+    /// during graph update, so it reflects changes made before last update.
+    pub fn is_globally_enabled(&self) -> bool {
+        self.global_enabled
+    }
+
+    /// Sets maximum distance from camera at which node is still rendered, `None` means
+    /// that node is rendered at any distance. Useful to hide small clutter far from camera
+    /// without setting up LODs. Distance is checked against bounding sphere of node, so
+    /// large objects do not disappear while part of them is still close to camera.
+    pub fn set_max_render_distance(&mut self, distance: Option<f32>) -> &mut Self {
+        self.max_render_distance = distance;
+        self
+    }
+
+    /// Returns maximum distance from camera at which node is still rendered.
+    pub fn max_render_distance(&self) -> Option<f32> {
+        self.max_render_distance
+    }
+
+    /// Marks node as persistent, dynamic state (transform, visibility and enabled state,
+    /// bound rigid body) of persistent nodes is stored by `Scene::visit_state`. See
+    /// `scene::save` module docs for more info.
+    pub fn set_persistent(&mut self, persistent: bool) -> &mut Self {
+        self.persistent = persistent;
+        self
+    }
+
+    /// Returns true if dynamic state of node is stored in save games.
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    /// Returns properties of node which were changed on instance of model resource and
+    /// are kept when resource is re-imported. See `scene::instance` module docs.
+    pub fn overrides(&self) -> &InstanceOverrides {
+        &self.overrides
+    }
+
+    /// Returns mutable reference to overrides of node, changes of overridden surfaces
+    /// are applied by `Graph::apply_overrides` or on next sync of instances.
+    pub fn overrides_mut(&mut self) -> &mut InstanceOverrides {
+        &mut self.overrides
+    }
+
     /// Handle to node in scene of model resource from which this node
     /// was instantiated from.
     pub fn original_handle(&self) -> Handle<Node> {
@@ -195,10 +284,15 @@ impl Clone for Base {
             global_transform: self.global_transform,
             visibility: self.visibility,
             global_visibility: self.global_visibility,
+            enabled: self.enabled,
+            global_enabled: self.global_enabled,
             inv_bind_pose_transform: self.inv_bind_pose_transform,
             resource: self.resource.clone(),
             is_resource_instance: self.is_resource_instance,
             lifetime: self.lifetime,
+            max_render_distance: self.max_render_distance,
+            persistent: self.persistent,
+            overrides: self.overrides.clone(),
             // Rest of data is *not* copied!
             ..Default::default()
         }
@@ -218,11 +312,15 @@ impl Visit for Base {
         self.name.visit("Name", visitor)?;
         self.local_transform.visit("Transform", visitor)?;
         self.visibility.visit("Visibility", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
         self.parent.visit("Parent", visitor)?;
         self.children.visit("Children", visitor)?;
         self.resource.visit("Resource", visitor)?;
         self.is_resource_instance.visit("IsResourceInstance", visitor)?;
         self.lifetime.visit("Lifetime", visitor)?;
+        self.max_render_distance.visit("MaxRenderDistance", visitor)?;
+        self.persistent.visit("Persistent", visitor)?;
+        self.overrides.visit("Overrides", visitor)?;
 
         visitor.leave_region()
     }
@@ -232,9 +330,12 @@ impl Visit for Base {
 pub struct BaseBuilder {
     name: Option<String>,
     visibility: Option<bool>,
+    enabled: Option<bool>,
     local_transform: Option<Transform>,
     children: Option<Vec<Handle<Node>>>,
     lifetime: Option<f32>,
+    max_render_distance: Option<f32>,
+    persistent: Option<bool>,
 }
 
 impl Default for BaseBuilder {
@@ -249,9 +350,12 @@ impl BaseBuilder {
         Self {
             name: None,
             visibility: None,
+            enabled: None,
             local_transform: None,
             children: None,
             lifetime: None,
+            max_render_distance: None,
+            persistent: None,
         }
     }
 
@@ -267,13 +371,33 @@ impl BaseBuilder {
         self
     }
 
+    /// Sets whether node is enabled or not. See [`Base::set_enabled`] for more info.
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
     /// Sets desired local transform.
     pub fn with_local_transform(mut self, transform: Transform) -> Self {
         self.local_transform = Some(transform);
         self
     }
 
-    /// Sets desired list of children nodes.
+    /// Sets desired list of children nodes, they are linked with the node when it is added
+    /// to graph. Children must be added to the same graph first, so small hierarchy can be
+    /// declared in one expression, for example camera rig:
+    ///
+    /// ```no_run
+    /// # use rg3d::scene::{Scene, base::BaseBuilder, camera::CameraBuilder};
+    /// # let mut scene = Scene::new();
+    /// let graph = &mut scene.graph;
+    /// let root = graph.get_root();
+    /// let yaw_pivot = BaseBuilder::new()
+    ///     .with_children(vec![BaseBuilder::new()
+    ///         .with_children(vec![CameraBuilder::new(BaseBuilder::new()).build_on(graph, root)])
+    ///         .build_on(graph, root)])
+    ///     .build_on(graph, root);
+    /// ```
     pub fn with_children(mut self, children: Vec<Handle<Node>>) -> Self {
         self.children = Some(children);
         self
@@ -285,6 +409,20 @@ impl BaseBuilder {
         self
     }
 
+    /// Sets maximum distance from camera at which node is still rendered. See
+    /// [`Base::set_max_render_distance`] for more info.
+    pub fn with_max_render_distance(mut self, distance: f32) -> Self {
+        self.max_render_distance = Some(distance);
+        self
+    }
+
+    /// Sets whether dynamic state of node is stored in save games. See
+    /// [`Base::set_persistent`] for more info.
+    pub fn with_persistent(mut self, persistent: bool) -> Self {
+        self.persistent = Some(persistent);
+        self
+    }
+
     /// Creates new instance of base scene node. Do not forget to add
     /// node to scene or pass to other nodes as base.
     pub fn build(self) -> Base {
@@ -293,14 +431,28 @@ impl BaseBuilder {
             children: self.children.unwrap_or_default(),
             local_transform: self.local_transform.unwrap_or_else(Transform::identity),
             lifetime: self.lifetime,
+            max_render_distance: self.max_render_distance,
             visibility: self.visibility.unwrap_or(true),
             global_visibility: true,
+            enabled: self.enabled.unwrap_or(true),
+            global_enabled: true,
             parent: Handle::NONE,
             global_transform: Mat4::IDENTITY,
+            transform_changed: true,
             inv_bind_pose_transform: Mat4::IDENTITY,
             resource: None,
             original: Handle::NONE,
             is_resource_instance: false,
+            persistent: self.persistent.unwrap_or(false),
+            overrides: Default::default(),
         }
     }
+
+    /// Creates new base node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Base(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
 }
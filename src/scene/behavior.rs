@@ -0,0 +1,143 @@
+//! Behaviors - small pieces of per-node logic for simple objects.
+//!
+//! Rotating pickups, blinking lights, swinging signs and similar objects need a few lines
+//! of code each frame, writing a dedicated game system for each of them is overkill.
+//! Behavior is a closure (or a type implementing `NodeBehavior`) attached to a node, it is
+//! called by `Scene::update` with time step, handle of its node and graph, after
+//! animations and before update of global transforms, so changes of local transforms are
+//! visible in the same frame. Node can have several behaviors, they're called in order of
+//! attaching.
+//!
+//! Behaviors are stored outside of graph, so they get graph mutably and can change any
+//! node, including removing their own node. Behaviors of removed nodes are dropped.
+//! Behaviors are not saved and not copied with scene, attach them again after load.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     scene::{Scene, node::Node},
+//!     core::{
+//!         pool::Handle,
+//!         math::{vec3::Vec3, quat::Quat},
+//!     },
+//! };
+//!
+//! fn make_pickup_rotate(scene: &mut Scene, pickup: Handle<Node>) {
+//!     let mut angle = 0.0f32;
+//!     scene.behaviors.add(pickup, move |dt, node, graph| {
+//!         angle += dt;
+//!         graph[node].local_transform_mut().set_rotation(Quat::from_axis_angle(Vec3::UP, angle));
+//!     });
+//! }
+//! ```
+
+use crate::{
+    core::pool::Handle,
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Logic attached to a node, see module docs.
+pub trait NodeBehavior: Send {
+    fn update(&mut self, dt: f32, node: Handle<Node>, graph: &mut Graph);
+}
+
+struct FnBehavior<F>(F);
+
+impl<F> NodeBehavior for FnBehavior<F> where F: FnMut(f32, Handle<Node>, &mut Graph) + Send {
+    fn update(&mut self, dt: f32, node: Handle<Node>, graph: &mut Graph) {
+        (self.0)(dt, node, graph)
+    }
+}
+
+/// See module docs.
+#[derive(Default)]
+pub struct BehaviorContainer {
+    behaviors: Vec<(Handle<Node>, Box<dyn NodeBehavior>)>,
+}
+
+impl BehaviorContainer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Attaches closure to node.
+    pub fn add<F>(&mut self, node: Handle<Node>, func: F)
+        where F: FnMut(f32, Handle<Node>, &mut Graph) + Send + 'static {
+        self.add_behavior(node, FnBehavior(func));
+    }
+
+    /// Attaches behavior to node.
+    pub fn add_behavior<B>(&mut self, node: Handle<Node>, behavior: B) where B: NodeBehavior + 'static {
+        self.behaviors.push((node, Box::new(behavior)));
+    }
+
+    /// Removes all behaviors of node.
+    pub fn remove(&mut self, node: Handle<Node>) {
+        self.behaviors.retain(|(n, _)| *n != node);
+    }
+
+    pub fn has_behaviors(&self, node: Handle<Node>) -> bool {
+        self.behaviors.iter().any(|(n, _)| *n == node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.behaviors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.behaviors.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.behaviors.clear();
+    }
+
+    /// Calls every behavior of alive node, behaviors of dead nodes are dropped.
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        self.behaviors.retain(|(node, _)| graph.is_valid_handle(*node));
+        for (node, behavior) in self.behaviors.iter_mut() {
+            // Node could be removed by previous behavior.
+            if graph.is_valid_handle(*node) {
+                behavior.update(dt, *node, graph);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{
+            graph::Graph,
+            node::Node,
+            base::Base,
+            behavior::BehaviorContainer,
+        },
+    };
+
+    #[test]
+    fn test_behaviors() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(Node::Base(Base::default()));
+        let b = graph.add_node(Node::Base(Base::default()));
+
+        let mut behaviors = BehaviorContainer::new();
+        behaviors.add(a, |dt, node, graph| {
+            graph[node].local_transform_mut().offset(Vec3::new(dt, 0.0, 0.0));
+        });
+        behaviors.add(b, move |_, _, graph| {
+            if graph.is_valid_handle(a) {
+                graph.remove_node(a);
+            }
+        });
+
+        behaviors.update(&mut graph, 1.0);
+        assert!(!graph.is_valid_handle(a));
+        behaviors.update(&mut graph, 1.0);
+        assert_eq!(behaviors.len(), 1);
+        assert!(behaviors.has_behaviors(b));
+    }
+}
@@ -9,6 +9,45 @@
 //! screen games, make picture-in-picture insertions in your main camera view and
 //! any other combinations you need.
 //!
+//! # Camera shake
+//!
+//! Each camera has shake settings, shake is driven by "trauma" - amount of shake in [0; 1]
+//! range which is added by explosions, impacts, etc. and decays over time. Shake offsets
+//! are added to view matrix only, so transform of camera node is never modified by shake.
+//!
+//! # Exposure
+//!
+//! Scene is rendered in high dynamic range, exposure defines how bright frame looks on
+//! screen. Exposure is either fixed or automatically adjusted to average luminance of
+//! frame, in the latter case it changes smoothly, like eyes adapt when you walk out of
+//! a dark room.
+//!
+//! # Clipping planes
+//!
+//! Precision of depth buffer is distributed non-linearly between near and far clipping
+//! planes - most of it is spent right behind near plane. Large outdoor scenes with small
+//! near plane and huge far plane suffer from z-fighting on distant geometry. There are two
+//! options to fight it:
+//!
+//! - Auto-fit of clipping planes - camera moves near plane forward and far plane backward
+//! as tight to visible meshes as possible, but never outside of range defined by `z_near`
+//! and `z_far`. For example when camera looks at the sky with only distant mountains in
+//! view, near plane is pushed far away, which gives much better precision.
+//! - Infinite far plane - far plane is placed so far away that nothing is clipped by it,
+//! `z_far` is still used for everything else (sorting, culling of lights, etc.).
+//!
+//! Reversed depth is not used, because it needs `glClipControl` which is not available
+//! in OpenGL 3.3.
+//!
+//! # Post effects
+//!
+//! Post effects (SSAO, screen space reflections, light scattering) are configured globally
+//! by quality settings of renderer, but each camera can override any of them. For example
+//! a camera which renders UI-like insertion may disable everything, while a cutscene camera
+//! may enable reflections which are too heavy for gameplay. Each camera is rendered into
+//! its own frame with its own settings, then the frame is put into camera's viewport, so
+//! cameras with different settings can be shown on screen at the same time.
+//!
 //! ## Performance
 //!
 //! Each camera forces engine to re-render same scene one more time, which may cause
@@ -18,6 +57,7 @@
 
 use crate::{
     core::{
+        pool::Handle,
         visitor::{
             Visitor,
             VisitResult,
@@ -27,17 +67,271 @@ use crate::{
             Rect,
             mat4::Mat4,
             vec2::Vec2,
+            vec3::Vec3,
+            quat::{Quat, RotationOrder},
+            frustum::Frustum,
         },
     },
-    scene::base::{
-        Base,
-        BaseBuilder,
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        graph::Graph,
+        node::Node,
     },
+    utils::noise,
 };
 use std::ops::{Deref, DerefMut};
 use rg3d_core::math::ray::Ray;
 use rg3d_core::math::vec4::Vec4;
 
+/// Trauma-based camera shake. Amplitude of shake is square of trauma, so small amounts
+/// of trauma give subtle shake and shake fades out smoothly. Offsets are taken from
+/// Perlin noise, so motion is smooth but chaotic.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraShake {
+    trauma: f32,
+    decay: f32,
+    frequency: f32,
+    max_angles: Vec3,
+    max_offset: Vec3,
+    seed: u32,
+    time: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 1.0,
+            frequency: 15.0,
+            max_angles: Vec3::new(
+                5.0f32.to_radians(),
+                5.0f32.to_radians(),
+                3.0f32.to_radians(),
+            ),
+            max_offset: Vec3::new(0.1, 0.1, 0.0),
+            seed: 0,
+            time: 0.0,
+        }
+    }
+}
+
+impl CameraShake {
+    /// Adds trauma, total trauma is clamped to [0; 1] range.
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).max(0.0).min(1.0);
+    }
+
+    /// Sets trauma, it is clamped to [0; 1] range.
+    pub fn set_trauma(&mut self, trauma: f32) {
+        self.trauma = trauma.max(0.0).min(1.0);
+    }
+
+    /// Returns current trauma.
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Sets how much trauma is removed per second.
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.max(0.0);
+    }
+
+    /// Returns how much trauma is removed per second.
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    /// Sets speed of shake, higher values give more violent shake.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency.max(0.0);
+    }
+
+    /// Returns speed of shake.
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// Sets maximum rotation angles (pitch, yaw, roll) in radians at full trauma.
+    pub fn set_max_angles(&mut self, max_angles: Vec3) {
+        self.max_angles = max_angles;
+    }
+
+    /// Returns maximum rotation angles (pitch, yaw, roll) in radians.
+    pub fn max_angles(&self) -> Vec3 {
+        self.max_angles
+    }
+
+    /// Sets maximum offset in camera space at full trauma.
+    pub fn set_max_offset(&mut self, max_offset: Vec3) {
+        self.max_offset = max_offset;
+    }
+
+    /// Returns maximum offset in camera space.
+    pub fn max_offset(&self) -> Vec3 {
+        self.max_offset
+    }
+
+    /// Sets seed of noise, cameras with different seeds shake differently.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    /// Advances shake and decays trauma. Called automatically by graph.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - self.decay * dt).max(0.0);
+    }
+
+    /// Returns matrix which is applied to view matrix of camera, identity when there is
+    /// no trauma.
+    pub fn matrix(&self) -> Mat4 {
+        if self.trauma <= 0.0 {
+            return Mat4::IDENTITY;
+        }
+
+        let amplitude = self.trauma * self.trauma;
+        // Each channel samples its own noise, half-integer offset avoids zeros of noise
+        // at integer points.
+        let t = self.time * self.frequency + 0.5;
+        let channel = |index: u32| amplitude * noise::perlin_1d(t, self.seed.wrapping_add(index));
+
+        let angles = Vec3::new(
+            self.max_angles.x * channel(0),
+            self.max_angles.y * channel(1),
+            self.max_angles.z * channel(2),
+        );
+        let offset = Vec3::new(
+            self.max_offset.x * channel(3),
+            self.max_offset.y * channel(4),
+            self.max_offset.z * channel(5),
+        );
+
+        Mat4::translate(offset) * Mat4::from_quat(Quat::from_euler(angles, RotationOrder::XYZ))
+    }
+}
+
+impl Visit for CameraShake {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.trauma.visit("Trauma", visitor)?;
+        self.decay.visit("Decay", visitor)?;
+        self.frequency.visit("Frequency", visitor)?;
+        self.max_angles.visit("MaxAngles", visitor)?;
+        self.max_offset.visit("MaxOffset", visitor)?;
+        self.seed.visit("Seed", visitor)?;
+        self.time.visit("Time", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Defines how HDR frame is mapped to screen colors, see module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Exposure {
+    /// Exposure is calculated from average luminance of frame.
+    Auto {
+        /// Desired average brightness of frame after exposure, 0.18 is "middle grey".
+        key_value: f32,
+        /// Minimum average luminance, limits how much dark frames are brightened.
+        min_luminance: f32,
+        /// Maximum average luminance, limits how much bright frames are darkened.
+        max_luminance: f32,
+        /// How fast exposure adapts to changes of luminance, larger values are faster.
+        adaptation_speed: f32,
+    },
+
+    /// Fixed multiplier of frame colors.
+    Manual(f32),
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure::Auto {
+            key_value: 0.18,
+            min_luminance: 0.05,
+            max_luminance: 10.0,
+            adaptation_speed: 1.5,
+        }
+    }
+}
+
+impl Exposure {
+    fn id(&self) -> u32 {
+        match self {
+            Exposure::Auto { .. } => 0,
+            Exposure::Manual(_) => 1,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(Exposure::default()),
+            1 => Ok(Exposure::Manual(1.0)),
+            _ => Err(format!("Invalid exposure id {}!", id))
+        }
+    }
+}
+
+impl Visit for Exposure {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = Exposure::from_id(id)?;
+        }
+
+        match self {
+            Exposure::Auto { key_value, min_luminance, max_luminance, adaptation_speed } => {
+                key_value.visit("KeyValue", visitor)?;
+                min_luminance.visit("MinLuminance", visitor)?;
+                max_luminance.visit("MaxLuminance", visitor)?;
+                adaptation_speed.visit("AdaptationSpeed", visitor)?;
+            }
+            Exposure::Manual(exposure) => {
+                exposure.visit("Value", visitor)?;
+            }
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Per-camera replacements of global post effect settings of renderer, see module docs.
+/// `None` means that global setting is used.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PostEffectOverrides {
+    /// Whether to use screen space ambient occlusion.
+    pub use_ssao: Option<bool>,
+    /// Radius of sampling hemisphere of screen space ambient occlusion.
+    pub ssao_radius: Option<f32>,
+    /// Whether to use screen space reflections.
+    pub use_ssr: Option<bool>,
+    /// Maximum distance (in view space) at which reflected objects are searched.
+    pub ssr_max_distance: Option<f32>,
+    /// Whether to render light scattering.
+    pub light_scatter_enabled: Option<bool>,
+}
+
+impl Visit for PostEffectOverrides {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.use_ssao.visit("UseSsao", visitor)?;
+        self.ssao_radius.visit("SsaoRadius", visitor)?;
+        self.use_ssr.visit("UseSsr", visitor)?;
+        self.ssr_max_distance.visit("SsrMaxDistance", visitor)?;
+        self.light_scatter_enabled.visit("LightScatterEnabled", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
 /// See module docs.
 #[derive(Clone)]
 pub struct Camera {
@@ -49,8 +343,24 @@ pub struct Camera {
     view_matrix: Mat4,
     projection_matrix: Mat4,
     enabled: bool,
+    shake: CameraShake,
+    exposure: Exposure,
+    post_effects: PostEffectOverrides,
+    infinite_far: bool,
+    auto_fit_clip_planes: bool,
+    // Clipping planes fitted to visible meshes, calculated by graph each frame.
+    fitted_clip_planes: Option<(f32, f32)>,
 }
 
+/// Ratio of far plane to near plane which is used to emulate infinite far plane. It is
+/// large enough to never clip anything in practice, but still keeps projection matrix
+/// well-defined, so frustum can be extracted from it.
+const INFINITE_FAR_RATIO: f32 = 1.0e7;
+
+/// Fraction of fitted depth range which is added to both sides of it, so geometry which
+/// moves between updates is not clipped.
+const CLIP_PLANES_FIT_MARGIN: f32 = 0.05;
+
 impl Deref for Camera {
     type Target = Base;
 
@@ -80,6 +390,11 @@ impl Visit for Camera {
         self.viewport.visit("Viewport", visitor)?;
         self.base.visit("Base", visitor)?;
         self.enabled.visit("Enabled", visitor)?;
+        self.shake.visit("Shake", visitor)?;
+        self.exposure.visit("Exposure", visitor)?;
+        self.post_effects.visit("PostEffects", visitor)?;
+        self.infinite_far.visit("InfiniteFar", visitor)?;
+        self.auto_fit_clip_planes.visit("AutoFitClipPlanes", visitor)?;
         visitor.leave_region()
     }
 }
@@ -94,13 +409,198 @@ impl Camera {
         let up = self.base.up_vector();
 
         if let Some(view_matrix) = Mat4::look_at(pos, pos + look, up) {
-            self.view_matrix = view_matrix;
+            self.view_matrix = self.shake.matrix() * view_matrix;
         } else {
             self.view_matrix = Mat4::IDENTITY;
         }
         let viewport = self.viewport_pixels(frame_size);
         let aspect = viewport.w as f32 / viewport.h as f32;
-        self.projection_matrix = Mat4::perspective(self.fov, aspect, self.z_near, self.z_far);
+        self.projection_matrix = Mat4::perspective(self.fov, aspect, self.projection_z_near(), self.projection_z_far());
+    }
+
+    /// Returns near plane which is actually used in projection matrix. It differs from
+    /// `z_near` when auto-fit of clipping planes is enabled.
+    #[inline]
+    pub fn projection_z_near(&self) -> f32 {
+        match self.fitted_clip_planes {
+            Some((z_near, _)) if self.auto_fit_clip_planes => z_near,
+            _ => self.z_near,
+        }
+    }
+
+    /// Returns far plane which is actually used in projection matrix. It differs from
+    /// `z_far` when auto-fit of clipping planes or infinite far plane is enabled.
+    #[inline]
+    pub fn projection_z_far(&self) -> f32 {
+        if self.infinite_far {
+            self.projection_z_near() * INFINITE_FAR_RATIO
+        } else {
+            match self.fitted_clip_planes {
+                Some((_, z_far)) if self.auto_fit_clip_planes => z_far,
+                _ => self.z_far,
+            }
+        }
+    }
+
+    /// Enables or disables infinite far plane, see module docs.
+    #[inline]
+    pub fn set_infinite_far(&mut self, infinite_far: bool) -> &mut Self {
+        self.infinite_far = infinite_far;
+        self
+    }
+
+    /// Returns true if camera uses infinite far plane.
+    #[inline]
+    pub fn is_infinite_far(&self) -> bool {
+        self.infinite_far
+    }
+
+    /// Enables or disables auto-fit of clipping planes to visible meshes, see module docs.
+    #[inline]
+    pub fn set_auto_fit_clip_planes(&mut self, auto_fit: bool) -> &mut Self {
+        self.auto_fit_clip_planes = auto_fit;
+        if !auto_fit {
+            self.fitted_clip_planes = None;
+        }
+        self
+    }
+
+    /// Returns true if clipping planes are fitted to visible meshes.
+    #[inline]
+    pub fn is_auto_fit_clip_planes(&self) -> bool {
+        self.auto_fit_clip_planes
+    }
+
+    pub(in crate) fn set_fitted_clip_planes(&mut self, planes: Option<(f32, f32)>) {
+        self.fitted_clip_planes = planes;
+    }
+
+    /// Calculates tightest near and far clipping planes which contain every visible mesh
+    /// of given graph. Planes are clamped to `[z_near; z_far]` range of camera. Returns
+    /// `None` if there is no visible meshes. Meshes are tested by their bounding spheres,
+    /// so result is conservative. Current view matrix is used, so it must be calculated
+    /// first.
+    pub fn calculate_clip_planes(&self, graph: &Graph) -> Option<(f32, f32)> {
+        // Aspect ratio is x_scale / y_scale of projection matrix.
+        let aspect = if self.projection_matrix.f[0] != 0.0 {
+            self.projection_matrix.f[5] / self.projection_matrix.f[0]
+        } else {
+            1.0
+        };
+        let projection = Mat4::perspective(self.fov, aspect, self.z_near, self.z_far);
+        let frustum = Frustum::from(projection * self.view_matrix).ok()?;
+
+        let mut range: Option<(f32, f32)> = None;
+        let mut add_depth = |near: f32, far: f32| {
+            range = Some(match range {
+                Some((min, max)) => (min.min(near), max.max(far)),
+                None => (near, far),
+            });
+        };
+
+        for node in graph.linear_iter() {
+            if let Node::Mesh(mesh) = node {
+                if !mesh.global_visibility() || !mesh.is_intersect_frustum(graph, &frustum) {
+                    continue;
+                }
+
+                let world = mesh.global_transform();
+                let bounding_box = mesh.bounding_box();
+                let max_scale = world.side().len().max(world.up().len()).max(world.look().len());
+                let center = world.transform_vector((bounding_box.min + bounding_box.max).scale(0.5));
+                let radius = (bounding_box.max - bounding_box.min).len() * 0.5 * max_scale;
+                // Camera looks along -Z in view space.
+                let depth = -self.view_matrix.transform_vector(center).z;
+                add_depth(depth - radius, depth + radius);
+
+                // Bounding box does not follow bones, so take them into account too.
+                for surface in mesh.surfaces() {
+                    for &bone in surface.bones.iter() {
+                        let depth = -self.view_matrix.transform_vector(graph[bone].global_position()).z;
+                        add_depth(depth, depth);
+                    }
+                }
+            }
+        }
+
+        let (near, far) = range?;
+        let margin = (far - near) * CLIP_PLANES_FIT_MARGIN;
+        let z_near = (near - margin).max(self.z_near).min(self.z_far);
+        let z_far = (far + margin).min(self.z_far).max(z_near);
+        if z_far > z_near {
+            Some((z_near, z_far))
+        } else {
+            None
+        }
+    }
+
+    /// Returns shared reference to shake settings of camera.
+    #[inline]
+    pub fn shake(&self) -> &CameraShake {
+        &self.shake
+    }
+
+    /// Returns mutable reference to shake settings of camera, use it to add trauma.
+    #[inline]
+    pub fn shake_mut(&mut self) -> &mut CameraShake {
+        &mut self.shake
+    }
+
+    /// Sets exposure of camera, see module docs.
+    #[inline]
+    pub fn set_exposure(&mut self, exposure: Exposure) -> &mut Self {
+        self.exposure = exposure;
+        self
+    }
+
+    /// Returns current exposure settings.
+    #[inline]
+    pub fn exposure(&self) -> Exposure {
+        self.exposure
+    }
+
+    /// Sets overrides of global post effect settings, see module docs.
+    #[inline]
+    pub fn set_post_effects(&mut self, post_effects: PostEffectOverrides) -> &mut Self {
+        self.post_effects = post_effects;
+        self
+    }
+
+    /// Returns current overrides of global post effect settings.
+    #[inline]
+    pub fn post_effects(&self) -> &PostEffectOverrides {
+        &self.post_effects
+    }
+
+    /// Returns copy of camera which sees world reflected by given reflection matrix. Reflection
+    /// flips handedness, so view is additionally flipped horizontally to keep winding of
+    /// triangles - image rendered by returned camera is mirrored horizontally.
+    pub(in crate) fn reflected(&self, reflection: Mat4) -> Camera {
+        let mut camera = self.clone();
+        camera.view_matrix = Mat4::scale(Vec3::new(-1.0, 1.0, 1.0)) * self.view_matrix * reflection;
+        camera
+    }
+
+    /// Creates camera which looks at `target` from `eye` with square viewport. Such camera
+    /// is not a part of any graph, it is used for off-screen rendering.
+    pub(in crate) fn looking_at(eye: Vec3, target: Vec3, up: Vec3, fov: f32, z_near: f32, z_far: f32) -> Camera {
+        let mut camera = CameraBuilder::new(BaseBuilder::new())
+            .with_fov(fov)
+            .with_z_near(z_near)
+            .with_z_far(z_far)
+            .build();
+        camera.view_matrix = Mat4::look_at(eye, target, up).unwrap_or(Mat4::IDENTITY);
+        camera.projection_matrix = Mat4::perspective(fov, 1.0, z_near, z_far);
+        camera.base.global_transform = camera.view_matrix.inverse().unwrap_or(Mat4::IDENTITY);
+        camera
+    }
+
+    /// Same as `looking_at`, but with orthographic projection which sees `width` x `height`
+    /// rectangle centered at `target`.
+    pub(in crate) fn orthographic_looking_at(eye: Vec3, target: Vec3, up: Vec3, width: f32, height: f32, z_near: f32, z_far: f32) -> Camera {
+        let mut camera = Self::looking_at(eye, target, up, 1.0, z_near, z_far);
+        camera.projection_matrix = Mat4::ortho(-width * 0.5, width * 0.5, -height * 0.5, height * 0.5, z_near, z_far);
+        camera
     }
 
     /// Sets new viewport in resolution-independent format. In other words
@@ -230,6 +730,11 @@ pub struct CameraBuilder {
     z_far: f32,
     viewport: Rect<f32>,
     enabled: bool,
+    shake: CameraShake,
+    exposure: Exposure,
+    post_effects: PostEffectOverrides,
+    infinite_far: bool,
+    auto_fit_clip_planes: bool,
 }
 
 impl CameraBuilder {
@@ -242,6 +747,11 @@ impl CameraBuilder {
             z_near: 0.025,
             z_far: 2048.0,
             viewport: Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 },
+            shake: Default::default(),
+            exposure: Default::default(),
+            post_effects: Default::default(),
+            infinite_far: false,
+            auto_fit_clip_planes: false,
         }
     }
 
@@ -275,6 +785,36 @@ impl CameraBuilder {
         self
     }
 
+    /// Sets desired shake settings.
+    pub fn with_shake(mut self, shake: CameraShake) -> Self {
+        self.shake = shake;
+        self
+    }
+
+    /// Sets desired exposure settings.
+    pub fn with_exposure(mut self, exposure: Exposure) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    /// Sets desired overrides of global post effect settings.
+    pub fn with_post_effects(mut self, post_effects: PostEffectOverrides) -> Self {
+        self.post_effects = post_effects;
+        self
+    }
+
+    /// Enables or disables infinite far plane.
+    pub fn with_infinite_far(mut self, infinite_far: bool) -> Self {
+        self.infinite_far = infinite_far;
+        self
+    }
+
+    /// Enables or disables auto-fit of clipping planes to visible meshes.
+    pub fn with_auto_fit_clip_planes(mut self, auto_fit: bool) -> Self {
+        self.auto_fit_clip_planes = auto_fit;
+        self
+    }
+
     /// Creates new instance of camera node. Do not forget to add node to scene,
     /// otherwise it is useless.
     pub fn build(self) -> Camera {
@@ -289,6 +829,20 @@ impl CameraBuilder {
             // recalculated before rendering.
             view_matrix: Mat4::IDENTITY,
             projection_matrix: Mat4::IDENTITY,
+            shake: self.shake,
+            exposure: self.exposure,
+            post_effects: self.post_effects,
+            infinite_far: self.infinite_far,
+            auto_fit_clip_planes: self.auto_fit_clip_planes,
+            fitted_clip_planes: None,
         }
     }
+
+    /// Creates new camera node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Camera(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
 }
\ No newline at end of file
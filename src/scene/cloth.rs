@@ -0,0 +1,737 @@
+//! Cloth is a rectangular piece of fabric simulated as grid of particles connected by
+//! distance constraints - capes, flags, curtains, etc.
+//!
+//! Simulation uses Verlet integration and iterative constraint relaxation, it is not
+//! physically accurate but stable and cheap enough to run every frame. Particles can be
+//! pinned to other nodes (bones of a character for example), so cloth follows them, and
+//! cloth collides with spheres and capsules attached to selected nodes.
+//!
+//! Particles are simulated in world space, so surface of cloth contains vertices in world
+//! coordinates and is updated every frame. Initial shape of cloth is a flat grid in XY plane
+//! of the node, first row of particles is at node position, rows go down along -Y axis.
+
+use std::{
+    sync::{Arc, Mutex},
+    ops::{Deref, DerefMut},
+    collections::HashMap,
+};
+use crate::{
+    renderer::surface::{
+        Surface,
+        SurfaceSharedData,
+        TangentMethod,
+        Vertex,
+    },
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        graph::Graph,
+        node::Node,
+        wind::Wind,
+    },
+    core::{
+        color::Color,
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
+            vec4::Vec4,
+            TriangleDefinition,
+        },
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClothColliderShape {
+    Sphere {
+        radius: f32
+    },
+    /// Capsule is oriented along local Y axis of node and centered at its origin, `height`
+    /// is distance between centers of caps.
+    Capsule {
+        radius: f32,
+        height: f32,
+    },
+}
+
+impl Default for ClothColliderShape {
+    fn default() -> Self {
+        ClothColliderShape::Sphere { radius: 0.5 }
+    }
+}
+
+impl ClothColliderShape {
+    fn id(&self) -> u32 {
+        match self {
+            ClothColliderShape::Sphere { .. } => 0,
+            ClothColliderShape::Capsule { .. } => 1,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(ClothColliderShape::Sphere { radius: 0.5 }),
+            1 => Ok(ClothColliderShape::Capsule { radius: 0.5, height: 1.0 }),
+            _ => Err(format!("Invalid cloth collider shape {}", id))
+        }
+    }
+}
+
+impl Visit for ClothColliderShape {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = Self::from_id(id)?;
+        }
+        match self {
+            ClothColliderShape::Sphere { radius } => {
+                radius.visit("Radius", visitor)?;
+            }
+            ClothColliderShape::Capsule { radius, height } => {
+                radius.visit("Radius", visitor)?;
+                height.visit("Height", visitor)?;
+            }
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Collision shape attached to a node, cloth particles are pushed out of it.
+#[derive(Copy, Clone, Default)]
+pub struct ClothCollider {
+    pub node: Handle<Node>,
+    pub shape: ClothColliderShape,
+}
+
+impl Visit for ClothCollider {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.shape.visit("Shape", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Attaches particle to a node, `offset` is in local coordinates of node.
+#[derive(Copy, Clone, Default)]
+pub struct ClothPin {
+    pub particle: u32,
+    pub node: Handle<Node>,
+    pub offset: Vec3,
+}
+
+impl Visit for ClothPin {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.particle.visit("Particle", visitor)?;
+        self.node.visit("Node", visitor)?;
+        self.offset.visit("Offset", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Particle {
+    position: Vec3,
+    last_position: Vec3,
+    pinned: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Constraint {
+    a: usize,
+    b: usize,
+    rest_length: f32,
+}
+
+enum WorldCollider {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    Capsule {
+        begin: Vec3,
+        end: Vec3,
+        radius: f32,
+    },
+}
+
+/// World space data of other nodes which is required to update cloth. It is gathered from
+/// graph before update, because cloth can't borrow graph while it is being updated.
+pub(in crate) struct ClothEnvironment {
+    /// World position for each pin, `None` if pin node is not valid anymore.
+    pins: Vec<Option<Vec3>>,
+    colliders: Vec<WorldCollider>,
+    wind: Wind,
+}
+
+pub struct Cloth {
+    base: Base,
+    columns: usize,
+    rows: usize,
+    spacing: f32,
+    gravity: Vec3,
+    wind: Vec3,
+    damping: f32,
+    iterations: usize,
+    pins: Vec<ClothPin>,
+    colliders: Vec<ClothCollider>,
+    particles: Vec<Particle>,
+    constraints: Vec<Constraint>,
+    surface: Surface,
+}
+
+impl Deref for Cloth {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Cloth {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Clone for Cloth {
+    fn clone(&self) -> Self {
+        // Surface data is updated every frame, so each copy of cloth must have its own.
+        let mut surface = Surface::new(Arc::new(Mutex::new(make_grid(self.columns, self.rows))));
+        if let Some(texture) = self.surface.get_diffuse_texture() {
+            surface.set_diffuse_texture(texture);
+        }
+        if let Some(texture) = self.surface.get_normal_texture() {
+            surface.set_normal_texture(texture);
+        }
+        surface.set_material(self.surface.material());
+        Self {
+            base: self.base.clone(),
+            columns: self.columns,
+            rows: self.rows,
+            spacing: self.spacing,
+            gravity: self.gravity,
+            wind: self.wind,
+            damping: self.damping,
+            iterations: self.iterations,
+            pins: self.pins.clone(),
+            colliders: self.colliders.clone(),
+            particles: self.particles.clone(),
+            constraints: self.constraints.clone(),
+            surface,
+        }
+    }
+}
+
+impl Default for Cloth {
+    fn default() -> Self {
+        ClothBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+fn make_grid(columns: usize, rows: usize) -> SurfaceSharedData {
+    let mut vertices = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            vertices.push(Vertex {
+                position: Vec3::ZERO,
+                tex_coord: Vec2::new(
+                    column as f32 / (columns - 1).max(1) as f32,
+                    row as f32 / (rows - 1).max(1) as f32,
+                ),
+                normal: Vec3::new(0.0, 0.0, 1.0),
+                tangent: Vec4 { x: 1.0, y: 0.0, z: 0.0, w: 1.0 },
+                bone_weights: [0.0, 0.0, 0.0, 0.0],
+                bone_indices: [0, 0, 0, 0],
+                second_tex_coord: Vec2::ZERO,
+                color: Color::WHITE,
+            });
+        }
+    }
+
+    let mut triangles = Vec::with_capacity(2 * columns * rows);
+    for row in 0..rows.saturating_sub(1) {
+        for column in 0..columns.saturating_sub(1) {
+            let i0 = (row * columns + column) as u32;
+            let i1 = i0 + 1;
+            let i2 = i0 + columns as u32;
+            let i3 = i2 + 1;
+            triangles.push(TriangleDefinition([i0, i2, i1]));
+            triangles.push(TriangleDefinition([i1, i2, i3]));
+        }
+    }
+
+    SurfaceSharedData::new(vertices, triangles)
+}
+
+impl Cloth {
+    /// Returns surface which contains current shape of cloth in world coordinates.
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    /// Returns mutable reference to surface, it can be used to set textures. Textures are
+    /// not serialized, they must be set again after load.
+    pub fn surface_mut(&mut self) -> &mut Surface {
+        &mut self.surface
+    }
+
+    /// Returns amount of particles in a row and amount of rows.
+    pub fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// Returns index of particle at given column and row, can be used to create pins.
+    pub fn particle_index(&self, column: usize, row: usize) -> u32 {
+        (row * self.columns + column) as u32
+    }
+
+    /// Sets acceleration of gravity.
+    pub fn set_gravity(&mut self, gravity: Vec3) {
+        self.gravity = gravity;
+    }
+
+    pub fn gravity(&self) -> Vec3 {
+        self.gravity
+    }
+
+    /// Sets own wind acceleration of cloth, it is added to wind of scene. Effect of wind
+    /// depends on orientation of cloth relative to wind.
+    pub fn set_wind(&mut self, wind: Vec3) {
+        self.wind = wind;
+    }
+
+    pub fn wind(&self) -> Vec3 {
+        self.wind
+    }
+
+    /// Sets fraction of velocity which is lost every step, in [0; 1] range.
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping = damping.max(0.0).min(1.0);
+    }
+
+    pub fn damping(&self) -> f32 {
+        self.damping
+    }
+
+    /// Sets amount of constraint relaxation iterations per update, more iterations make
+    /// cloth less stretchy.
+    pub fn set_iterations(&mut self, iterations: usize) {
+        self.iterations = iterations.max(1);
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    pub fn add_pin(&mut self, pin: ClothPin) {
+        self.pins.push(pin);
+    }
+
+    pub fn pins(&self) -> &[ClothPin] {
+        &self.pins
+    }
+
+    pub fn clear_pins(&mut self) {
+        self.pins.clear();
+        for particle in self.particles.iter_mut() {
+            particle.pinned = false;
+        }
+    }
+
+    pub fn add_collider(&mut self, collider: ClothCollider) {
+        self.colliders.push(collider);
+    }
+
+    pub fn colliders(&self) -> &[ClothCollider] {
+        &self.colliders
+    }
+
+    pub fn clear_colliders(&mut self) {
+        self.colliders.clear();
+    }
+
+    /// Remaps handles of pin and collider nodes after hierarchy was copied, handles
+    /// which are not in the map are left untouched.
+    pub(in crate) fn remap_handles(&mut self, old_new_mapping: &HashMap<Handle<Node>, Handle<Node>>) {
+        for pin in self.pins.iter_mut() {
+            if let Some(&new) = old_new_mapping.get(&pin.node) {
+                pin.node = new;
+            }
+        }
+        for collider in self.colliders.iter_mut() {
+            if let Some(&new) = old_new_mapping.get(&collider.node) {
+                collider.node = new;
+            }
+        }
+    }
+
+    /// Resets cloth to its initial shape at current position of node.
+    pub fn reset(&mut self) {
+        self.particles.clear();
+    }
+
+    fn initialize(&mut self) {
+        let global_transform = self.global_transform();
+        let half_width = 0.5 * (self.columns - 1) as f32 * self.spacing;
+
+        self.particles.clear();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let local = Vec3::new(column as f32 * self.spacing - half_width, -(row as f32) * self.spacing, 0.0);
+                let position = global_transform.transform_vector(local);
+                self.particles.push(Particle {
+                    position,
+                    last_position: position,
+                    pinned: false,
+                });
+            }
+        }
+
+        let diagonal = self.spacing * std::f32::consts::SQRT_2;
+        let mut constraints = Vec::new();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let index = row * self.columns + column;
+                // Structural constraints.
+                if column + 1 < self.columns {
+                    constraints.push((index, index + 1, self.spacing));
+                }
+                if row + 1 < self.rows {
+                    constraints.push((index, index + self.columns, self.spacing));
+                }
+                // Shear constraints.
+                if column + 1 < self.columns && row + 1 < self.rows {
+                    constraints.push((index, index + self.columns + 1, diagonal));
+                    constraints.push((index + 1, index + self.columns, diagonal));
+                }
+                // Bend constraints.
+                if column + 2 < self.columns {
+                    constraints.push((index, index + 2, 2.0 * self.spacing));
+                }
+                if row + 2 < self.rows {
+                    constraints.push((index, index + 2 * self.columns, 2.0 * self.spacing));
+                }
+            }
+        }
+        self.constraints = constraints.into_iter()
+            .map(|(a, b, rest_length)| Constraint { a, b, rest_length })
+            .collect();
+    }
+
+    pub(in crate) fn gather_environment(&self, graph: &Graph) -> ClothEnvironment {
+        let pins = self.pins.iter()
+            .map(|pin| {
+                if graph.is_valid_handle(pin.node) {
+                    Some(graph[pin.node].global_transform().transform_vector(pin.offset))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let colliders = self.colliders.iter()
+            .filter(|collider| graph.is_valid_handle(collider.node))
+            .map(|collider| {
+                let transform = graph[collider.node].global_transform();
+                match collider.shape {
+                    ClothColliderShape::Sphere { radius } => WorldCollider::Sphere {
+                        center: transform.position(),
+                        radius,
+                    },
+                    ClothColliderShape::Capsule { radius, height } => WorldCollider::Capsule {
+                        begin: transform.transform_vector(Vec3::new(0.0, -0.5 * height, 0.0)),
+                        end: transform.transform_vector(Vec3::new(0.0, 0.5 * height, 0.0)),
+                        radius,
+                    },
+                }
+            })
+            .collect();
+
+        ClothEnvironment {
+            pins,
+            colliders,
+            wind: *graph.wind(),
+        }
+    }
+
+    pub(in crate) fn update(&mut self, dt: f32, environment: &ClothEnvironment) {
+        if self.particles.len() != self.columns * self.rows {
+            self.initialize();
+        }
+
+        // Move pinned particles to their nodes.
+        for (pin, position) in self.pins.iter().zip(environment.pins.iter()) {
+            if let Some(particle) = self.particles.get_mut(pin.particle as usize) {
+                if let Some(position) = *position {
+                    particle.position = position;
+                    particle.last_position = position;
+                    particle.pinned = true;
+                } else {
+                    particle.pinned = false;
+                }
+            }
+        }
+
+        // Integrate. Wind affects only perpendicular to surface component, so cloth which is
+        // parallel to wind is not pushed by it. Wind of scene is added to own wind of cloth.
+        let normals = self.calculate_normals();
+        let sqr_dt = dt * dt;
+        for (particle, normal) in self.particles.iter_mut().zip(normals.iter()) {
+            if particle.pinned {
+                continue;
+            }
+            let velocity = (particle.position - particle.last_position).scale(1.0 - self.damping);
+            let wind = self.wind + environment.wind.velocity_at(particle.position);
+            let acceleration = self.gravity + normal.scale(normal.dot(&wind));
+            particle.last_position = particle.position;
+            particle.position += velocity + acceleration.scale(sqr_dt);
+        }
+
+        for _ in 0..self.iterations {
+            for constraint in self.constraints.iter() {
+                let a = self.particles[constraint.a];
+                let b = self.particles[constraint.b];
+                if a.pinned && b.pinned {
+                    continue;
+                }
+                let delta = b.position - a.position;
+                let length = delta.len();
+                if length <= std::f32::EPSILON {
+                    continue;
+                }
+                let correction = delta.scale((length - constraint.rest_length) / length);
+                if a.pinned {
+                    self.particles[constraint.b].position -= correction;
+                } else if b.pinned {
+                    self.particles[constraint.a].position += correction;
+                } else {
+                    self.particles[constraint.a].position += correction.scale(0.5);
+                    self.particles[constraint.b].position -= correction.scale(0.5);
+                }
+            }
+
+            for particle in self.particles.iter_mut().filter(|p| !p.pinned) {
+                for collider in environment.colliders.iter() {
+                    let (closest, radius) = match *collider {
+                        WorldCollider::Sphere { center, radius } => (center, radius),
+                        WorldCollider::Capsule { begin, end, radius } => {
+                            let axis = end - begin;
+                            let sqr_length = axis.dot(&axis);
+                            let t = if sqr_length > std::f32::EPSILON {
+                                ((particle.position - begin).dot(&axis) / sqr_length).max(0.0).min(1.0)
+                            } else {
+                                0.0
+                            };
+                            (begin + axis.scale(t), radius)
+                        }
+                    };
+                    let offset = particle.position - closest;
+                    let distance = offset.len();
+                    if distance < radius {
+                        if let Some(direction) = offset.normalized() {
+                            particle.position = closest + direction.scale(radius);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.update_surface();
+    }
+
+    fn calculate_normals(&self) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::ZERO; self.particles.len()];
+        for row in 0..self.rows.saturating_sub(1) {
+            for column in 0..self.columns.saturating_sub(1) {
+                let i0 = row * self.columns + column;
+                let i1 = i0 + 1;
+                let i2 = i0 + self.columns;
+                let i3 = i2 + 1;
+                for &(a, b, c) in [(i0, i2, i1), (i1, i2, i3)].iter() {
+                    let pa = self.particles[a].position;
+                    let pb = self.particles[b].position;
+                    let pc = self.particles[c].position;
+                    // Not normalized, so larger triangles have more influence.
+                    let normal = (pb - pa).cross(&(pc - pa));
+                    normals[a] += normal;
+                    normals[b] += normal;
+                    normals[c] += normal;
+                }
+            }
+        }
+        for normal in normals.iter_mut() {
+            *normal = normal.normalized().unwrap_or(Vec3::new(0.0, 0.0, 1.0));
+        }
+        normals
+    }
+
+    /// Particles are in world space, so they must be moved together with world.
+    pub(in crate) fn shift_origin(&mut self, offset: Vec3) {
+        for particle in self.particles.iter_mut() {
+            particle.position -= offset;
+            particle.last_position -= offset;
+        }
+        self.update_surface();
+    }
+
+    fn update_surface(&mut self) {
+        let normals = self.calculate_normals();
+        let data = self.surface.get_data();
+        let mut data = data.lock().unwrap();
+        for ((vertex, particle), normal) in data.get_vertices_mut().iter_mut().zip(self.particles.iter()).zip(normals.iter()) {
+            vertex.position = particle.position;
+            vertex.normal = *normal;
+        }
+        data.calculate_tangents(TangentMethod::Fast);
+        data.mark_modified();
+    }
+}
+
+impl Visit for Cloth {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut columns = self.columns as u32;
+        columns.visit("Columns", visitor)?;
+        let mut rows = self.rows as u32;
+        rows.visit("Rows", visitor)?;
+        let mut iterations = self.iterations as u32;
+        iterations.visit("Iterations", visitor)?;
+        self.spacing.visit("Spacing", visitor)?;
+        self.gravity.visit("Gravity", visitor)?;
+        self.wind.visit("Wind", visitor)?;
+        self.damping.visit("Damping", visitor)?;
+        self.pins.visit("Pins", visitor)?;
+        self.colliders.visit("Colliders", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        // State of particles is not serialized, cloth starts from initial shape after load.
+        if visitor.is_reading() {
+            self.columns = columns as usize;
+            self.rows = rows as usize;
+            self.iterations = iterations as usize;
+            self.particles.clear();
+            self.surface = Surface::new(Arc::new(Mutex::new(make_grid(self.columns, self.rows))));
+        }
+
+        visitor.leave_region()
+    }
+}
+
+pub struct ClothBuilder {
+    base_builder: BaseBuilder,
+    columns: Option<usize>,
+    rows: Option<usize>,
+    spacing: Option<f32>,
+    gravity: Option<Vec3>,
+    wind: Option<Vec3>,
+    damping: Option<f32>,
+    iterations: Option<usize>,
+    pins: Vec<ClothPin>,
+    colliders: Vec<ClothCollider>,
+}
+
+impl ClothBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            columns: None,
+            rows: None,
+            spacing: None,
+            gravity: None,
+            wind: None,
+            damping: None,
+            iterations: None,
+            pins: Default::default(),
+            colliders: Default::default(),
+        }
+    }
+
+    /// Sets amount of particles in a row and amount of rows, both must be at least two.
+    pub fn with_size(mut self, columns: usize, rows: usize) -> Self {
+        self.columns = Some(columns);
+        self.rows = Some(rows);
+        self
+    }
+
+    /// Sets distance between neighbour particles.
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = Some(spacing);
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: Vec3) -> Self {
+        self.gravity = Some(gravity);
+        self
+    }
+
+    pub fn with_wind(mut self, wind: Vec3) -> Self {
+        self.wind = Some(wind);
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = Some(damping);
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = Some(iterations);
+        self
+    }
+
+    pub fn with_pins(mut self, pins: Vec<ClothPin>) -> Self {
+        self.pins = pins;
+        self
+    }
+
+    pub fn with_colliders(mut self, colliders: Vec<ClothCollider>) -> Self {
+        self.colliders = colliders;
+        self
+    }
+
+    pub fn build(self) -> Cloth {
+        let columns = self.columns.unwrap_or(16).max(2);
+        let rows = self.rows.unwrap_or(16).max(2);
+        Cloth {
+            base: self.base_builder.build(),
+            columns,
+            rows,
+            spacing: self.spacing.unwrap_or(0.1),
+            gravity: self.gravity.unwrap_or(Vec3::new(0.0, -9.81, 0.0)),
+            wind: self.wind.unwrap_or(Vec3::ZERO),
+            damping: self.damping.unwrap_or(0.01).max(0.0).min(1.0),
+            iterations: self.iterations.unwrap_or(8).max(1),
+            pins: self.pins,
+            colliders: self.colliders,
+            particles: Vec::new(),
+            constraints: Vec::new(),
+            surface: Surface::new(Arc::new(Mutex::new(make_grid(columns, rows)))),
+        }
+    }
+
+    /// Creates new cloth node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Cloth(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
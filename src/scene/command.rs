@@ -0,0 +1,245 @@
+//! Command buffer - deferred mutation of scene.
+//!
+//! Game systems often want to change graph while they are iterating it: spawn a decal for
+//! each hit, remove dead enemies, attach picked items to hand. Instead of collecting
+//! handles into temporary arrays each system can record commands into a command buffer and
+//! let scene apply them later. Commands of `Scene::commands` are applied at the beginning
+//! of `Scene::update` (before physics), in the order they were recorded. Command buffer is
+//! `Send`, so systems running on worker threads can fill their own buffers with read-only
+//! access to scene and then append them to the buffer of scene.
+//!
+//! Commands which refer to nodes that do not exist at the moment of applying (for example
+//! node was removed by previous command) are skipped. Handle of spawned node is known only
+//! when command is applied, use `SceneCommandBuffer::custom` with `Scene::graph.add_node`
+//! if handle is needed right away.
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, node::Node};
+//!
+//! fn remove_invisible(scene: &mut Scene) {
+//!     for (handle, node) in scene.graph.pair_iter() {
+//!         if !node.visibility() {
+//!             scene.commands.remove(handle);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use crate::{
+    core::{
+        pool::Handle,
+        math::{
+            vec3::Vec3,
+            quat::Quat,
+        },
+    },
+    scene::{
+        Scene,
+        node::Node,
+    },
+};
+
+/// Single deferred change of scene, see module docs.
+pub enum SceneCommand {
+    /// Adds node to graph and links it with given parent.
+    Spawn {
+        node: Node,
+        parent: Handle<Node>,
+    },
+    /// Removes node with its descendants and associated entities, see `Scene::remove_node`.
+    Remove(Handle<Node>),
+    /// Links child with new parent.
+    Link {
+        child: Handle<Node>,
+        parent: Handle<Node>,
+    },
+    SetPosition(Handle<Node>, Vec3),
+    SetRotation(Handle<Node>, Quat),
+    SetScale(Handle<Node>, Vec3),
+    SetVisibility(Handle<Node>, bool),
+    SetEnabled(Handle<Node>, bool),
+    /// Arbitrary change of scene.
+    Custom(Box<dyn FnOnce(&mut Scene) + Send>),
+}
+
+impl Debug for SceneCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneCommand::Spawn { parent, .. } => write!(f, "Spawn {{ parent: {:?} }}", parent),
+            SceneCommand::Remove(node) => write!(f, "Remove({:?})", node),
+            SceneCommand::Link { child, parent } => write!(f, "Link {{ child: {:?}, parent: {:?} }}", child, parent),
+            SceneCommand::SetPosition(node, position) => write!(f, "SetPosition({:?}, {:?})", node, position),
+            SceneCommand::SetRotation(node, rotation) => write!(f, "SetRotation({:?}, {:?})", node, rotation),
+            SceneCommand::SetScale(node, scale) => write!(f, "SetScale({:?}, {:?})", node, scale),
+            SceneCommand::SetVisibility(node, visibility) => write!(f, "SetVisibility({:?}, {})", node, visibility),
+            SceneCommand::SetEnabled(node, enabled) => write!(f, "SetEnabled({:?}, {})", node, enabled),
+            SceneCommand::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Ordered list of scene commands, see module docs.
+#[derive(Default, Debug)]
+pub struct SceneCommandBuffer {
+    commands: Vec<SceneCommand>,
+}
+
+impl SceneCommandBuffer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push(&mut self, command: SceneCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn spawn(&mut self, node: Node, parent: Handle<Node>) {
+        self.push(SceneCommand::Spawn { node, parent });
+    }
+
+    pub fn remove(&mut self, node: Handle<Node>) {
+        self.push(SceneCommand::Remove(node));
+    }
+
+    pub fn link(&mut self, child: Handle<Node>, parent: Handle<Node>) {
+        self.push(SceneCommand::Link { child, parent });
+    }
+
+    pub fn set_position(&mut self, node: Handle<Node>, position: Vec3) {
+        self.push(SceneCommand::SetPosition(node, position));
+    }
+
+    pub fn set_rotation(&mut self, node: Handle<Node>, rotation: Quat) {
+        self.push(SceneCommand::SetRotation(node, rotation));
+    }
+
+    pub fn set_scale(&mut self, node: Handle<Node>, scale: Vec3) {
+        self.push(SceneCommand::SetScale(node, scale));
+    }
+
+    pub fn set_visibility(&mut self, node: Handle<Node>, visibility: bool) {
+        self.push(SceneCommand::SetVisibility(node, visibility));
+    }
+
+    pub fn set_enabled(&mut self, node: Handle<Node>, enabled: bool) {
+        self.push(SceneCommand::SetEnabled(node, enabled));
+    }
+
+    pub fn custom<F>(&mut self, func: F) where F: FnOnce(&mut Scene) + Send + 'static {
+        self.push(SceneCommand::Custom(Box::new(func)));
+    }
+
+    /// Moves all commands of other buffer to the end of this buffer, other buffer becomes
+    /// empty.
+    pub fn append(&mut self, other: &mut SceneCommandBuffer) {
+        self.commands.append(&mut other.commands);
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Applies commands to given scene in order they were recorded, buffer becomes empty.
+    pub fn apply(&mut self, scene: &mut Scene) {
+        for command in self.commands.drain(..) {
+            apply_command(scene, command);
+        }
+    }
+}
+
+fn apply_command(scene: &mut Scene, command: SceneCommand) {
+    let graph = &mut scene.graph;
+    match command {
+        SceneCommand::Spawn { node, parent } => {
+            let handle = graph.add_node(node);
+            if graph.is_valid_handle(parent) {
+                graph.link_nodes(handle, parent);
+            }
+        }
+        SceneCommand::Remove(node) => {
+            if graph.is_valid_handle(node) {
+                scene.remove_node(node);
+            }
+        }
+        SceneCommand::Link { child, parent } => {
+            // Node can't be linked with its own descendant.
+            if graph.is_valid_handle(child) && graph.is_valid_handle(parent)
+                && !graph.traverse_handle_iter(child).any(|node| node == parent) {
+                graph.link_nodes(child, parent);
+            }
+        }
+        SceneCommand::SetPosition(node, position) => {
+            if graph.is_valid_handle(node) {
+                graph[node].local_transform_mut().set_position(position);
+            }
+        }
+        SceneCommand::SetRotation(node, rotation) => {
+            if graph.is_valid_handle(node) {
+                graph[node].local_transform_mut().set_rotation(rotation);
+            }
+        }
+        SceneCommand::SetScale(node, scale) => {
+            if graph.is_valid_handle(node) {
+                graph[node].local_transform_mut().set_scale(scale);
+            }
+        }
+        SceneCommand::SetVisibility(node, visibility) => {
+            if graph.is_valid_handle(node) {
+                graph[node].set_visibility(visibility);
+            }
+        }
+        SceneCommand::SetEnabled(node, enabled) => {
+            if graph.is_valid_handle(node) {
+                graph[node].set_enabled(enabled);
+            }
+        }
+        SceneCommand::Custom(func) => func(scene),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{
+            Scene,
+            node::Node,
+            base::Base,
+            command::SceneCommandBuffer,
+        },
+    };
+
+    #[test]
+    fn test_command_buffer() {
+        let mut scene = Scene::new();
+        let root = scene.graph.get_root();
+        let a = scene.graph.add_node(Node::Base(Base::default()));
+        let b = scene.graph.add_node(Node::Base(Base::default()));
+
+        let mut commands = SceneCommandBuffer::new();
+        commands.link(b, a);
+        // Cycle is ignored.
+        commands.link(a, b);
+        commands.set_position(b, Vec3::new(1.0, 2.0, 3.0));
+        commands.remove(a);
+        // Node is already removed together with its parent.
+        commands.set_position(b, Vec3::ZERO);
+        commands.spawn(Node::Base(Base::default()), root);
+        assert_eq!(commands.len(), 6);
+
+        commands.apply(&mut scene);
+        assert!(commands.is_empty());
+        assert!(!scene.graph.is_valid_handle(a));
+        assert!(!scene.graph.is_valid_handle(b));
+        assert_eq!(scene.graph[root].children().len(), 1);
+    }
+}
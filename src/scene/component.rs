@@ -0,0 +1,344 @@
+//! Component store - gameplay data attached to scene nodes.
+//!
+//! Nodes of graph are fixed set of engine types, game data (health, inventory, AI state)
+//! usually lives in game structures which refer to nodes by handles. Component store of
+//! scene keeps such data right next to graph: any `Send + 'static` type can be attached to
+//! node as component, one component of each type per node. Each type is stored in its own
+//! sparse set, so iteration over components of one type is linear over dense array and
+//! lookup by handle is constant time. `join` iterates over nodes which have two given
+//! components.
+//!
+//! Components of removed nodes are removed too: `Scene::remove_node` removes them right
+//! away, components of nodes removed directly from graph are removed at the beginning of
+//! next `Scene::update`. Components are not saved and not copied with scene, they are
+//! runtime data of game systems.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     scene::Scene,
+//!     core::math::vec3::Vec3,
+//! };
+//!
+//! struct Health(f32);
+//! struct Velocity(Vec3);
+//!
+//! fn apply_damage(scene: &mut Scene, dt: f32) {
+//!     scene.components.for_each_mut::<Health, Velocity, _>(|_, health, velocity| {
+//!         // Fast falling hurts.
+//!         if velocity.0.y < -20.0 {
+//!             health.0 -= 10.0 * dt;
+//!         }
+//!     });
+//!     for (node, health) in scene.components.iter::<Health>() {
+//!         if health.0 <= 0.0 {
+//!             scene.commands.remove(node);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+use crate::{
+    core::pool::Handle,
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Components of one type, dense array with index by handle of node.
+pub struct SparseSet<T> {
+    /// Index of component in dense array for each index of node handle.
+    sparse: Vec<Option<usize>>,
+    nodes: Vec<Handle<Node>>,
+    components: Vec<T>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Vec::new(),
+            nodes: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    fn dense_index(&self, node: Handle<Node>) -> Option<usize> {
+        let index = (*self.sparse.get(node.index() as usize)?)?;
+        // Slot of node could be reused by other node.
+        if self.nodes[index] == node {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Adds component to node, returns previous component of node if any.
+    pub fn insert(&mut self, node: Handle<Node>, component: T) -> Option<T> {
+        if let Some(index) = self.dense_index(node) {
+            return Some(std::mem::replace(&mut self.components[index], component));
+        }
+        let slot = node.index() as usize;
+        // Component of dead node with the same slot is dropped.
+        self.remove_slot(slot);
+        if slot >= self.sparse.len() {
+            self.sparse.resize(slot + 1, None);
+        }
+        self.sparse[slot] = Some(self.components.len());
+        self.nodes.push(node);
+        self.components.push(component);
+        None
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> Option<T> {
+        let index = self.sparse.get_mut(slot)?.take()?;
+        self.nodes.swap_remove(index);
+        let component = self.components.swap_remove(index);
+        if let Some(&moved) = self.nodes.get(index) {
+            self.sparse[moved.index() as usize] = Some(index);
+        }
+        Some(component)
+    }
+
+    pub fn remove(&mut self, node: Handle<Node>) -> Option<T> {
+        self.dense_index(node)?;
+        self.remove_slot(node.index() as usize)
+    }
+
+    pub fn get(&self, node: Handle<Node>) -> Option<&T> {
+        self.dense_index(node).map(|index| &self.components[index])
+    }
+
+    pub fn get_mut(&mut self, node: Handle<Node>) -> Option<&mut T> {
+        self.dense_index(node).map(move |index| &mut self.components[index])
+    }
+
+    pub fn contains(&self, node: Handle<Node>) -> bool {
+        self.dense_index(node).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(Handle<Node>, &T)> {
+        self.nodes.iter().cloned().zip(self.components.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(Handle<Node>, &mut T)> {
+        self.nodes.iter().cloned().zip(self.components.iter_mut())
+    }
+}
+
+/// Type-erased sparse set, allows to remove components of node without knowing types.
+trait Storage: Send {
+    fn remove_node(&mut self, node: Handle<Node>);
+
+    fn retain_alive(&mut self, graph: &Graph);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Send + 'static> Storage for SparseSet<T> {
+    fn remove_node(&mut self, node: Handle<Node>) {
+        self.remove(node);
+    }
+
+    fn retain_alive(&mut self, graph: &Graph) {
+        let mut i = 0;
+        while i < self.nodes.len() {
+            let node = self.nodes[i];
+            if graph.is_valid_handle(node) {
+                i += 1;
+            } else {
+                // Last component is moved into this place, so index stays the same.
+                self.remove_slot(node.index() as usize);
+            }
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// See module docs.
+#[derive(Default)]
+pub struct ComponentStore {
+    storages: HashMap<TypeId, Box<dyn Storage>>,
+}
+
+impl ComponentStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns sparse set of components of given type, `None` if no component of this type
+    /// was ever added.
+    pub fn storage<T: Send + 'static>(&self) -> Option<&SparseSet<T>> {
+        self.storages.get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    pub fn storage_mut<T: Send + 'static>(&mut self) -> &mut SparseSet<T> {
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(SparseSet::<T>::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    /// Attaches component to node, returns previous component of the same type if any.
+    pub fn insert<T: Send + 'static>(&mut self, node: Handle<Node>, component: T) -> Option<T> {
+        self.storage_mut().insert(node, component)
+    }
+
+    pub fn remove<T: Send + 'static>(&mut self, node: Handle<Node>) -> Option<T> {
+        self.storages.get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<SparseSet<T>>())
+            .and_then(|storage| storage.remove(node))
+    }
+
+    pub fn get<T: Send + 'static>(&self, node: Handle<Node>) -> Option<&T> {
+        self.storage()?.get(node)
+    }
+
+    pub fn get_mut<T: Send + 'static>(&mut self, node: Handle<Node>) -> Option<&mut T> {
+        self.storages.get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<SparseSet<T>>())
+            .and_then(|storage| storage.get_mut(node))
+    }
+
+    pub fn contains<T: Send + 'static>(&self, node: Handle<Node>) -> bool {
+        self.storage::<T>().map_or(false, |storage| storage.contains(node))
+    }
+
+    /// Iterates over all components of given type with handles of their nodes.
+    pub fn iter<T: Send + 'static>(&self) -> impl Iterator<Item=(Handle<Node>, &T)> {
+        self.storage::<T>().into_iter().flat_map(|storage| storage.iter())
+    }
+
+    pub fn iter_mut<T: Send + 'static>(&mut self) -> impl Iterator<Item=(Handle<Node>, &mut T)> {
+        self.storage_mut::<T>().iter_mut()
+    }
+
+    /// Iterates over nodes which have both components. Iteration goes over components of
+    /// type `A`, so pass less common component first.
+    pub fn join<A: Send + 'static, B: Send + 'static>(&self) -> impl Iterator<Item=(Handle<Node>, &A, &B)> {
+        let others = self.storage::<B>();
+        self.iter::<A>().filter_map(move |(node, a)| others?.get(node).map(|b| (node, a, b)))
+    }
+
+    /// Calls given function for each node which has both components, first component is
+    /// mutable. `A` and `B` must be different types.
+    pub fn for_each_mut<A, B, F>(&mut self, mut func: F)
+        where A: Send + 'static,
+              B: Send + 'static,
+              F: FnMut(Handle<Node>, &mut A, &B) {
+        assert_ne!(TypeId::of::<A>(), TypeId::of::<B>());
+        // Storage of A is taken out of map, so storage of B can be borrowed at the same time.
+        let mut storage = match self.storages.remove(&TypeId::of::<A>()) {
+            Some(storage) => storage,
+            None => return,
+        };
+        if let Some(others) = self.storage::<B>() {
+            let storage = storage.as_any_mut().downcast_mut::<SparseSet<A>>().unwrap();
+            for (node, a) in storage.iter_mut() {
+                if let Some(b) = others.get(node) {
+                    func(node, a, b);
+                }
+            }
+        }
+        self.storages.insert(TypeId::of::<A>(), storage);
+    }
+
+    /// Removes all components of node.
+    pub fn remove_node(&mut self, node: Handle<Node>) {
+        for storage in self.storages.values_mut() {
+            storage.remove_node(node);
+        }
+    }
+
+    /// Removes components of nodes which are not in graph anymore.
+    pub fn retain_alive(&mut self, graph: &Graph) {
+        for storage in self.storages.values_mut() {
+            storage.retain_alive(graph);
+        }
+    }
+
+    /// Removes all components of given type and returns them.
+    pub fn take_storage<T: Send + 'static>(&mut self) -> Option<SparseSet<T>> {
+        self.storages.remove(&TypeId::of::<T>())
+            .and_then(|storage| storage.into_any().downcast().ok())
+            .map(|storage| *storage)
+    }
+
+    pub fn clear(&mut self) {
+        self.storages.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{
+        graph::Graph,
+        node::Node,
+        base::Base,
+        component::ComponentStore,
+    };
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Armor(u32);
+
+    #[test]
+    fn test_component_store() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(Node::Base(Base::default()));
+        let b = graph.add_node(Node::Base(Base::default()));
+        let c = graph.add_node(Node::Base(Base::default()));
+
+        let mut store = ComponentStore::new();
+        store.insert(a, Health(10));
+        store.insert(b, Health(20));
+        store.insert(c, Health(30));
+        store.insert(b, Armor(5));
+        assert_eq!(store.insert(a, Health(15)), Some(Health(10)));
+
+        assert_eq!(store.join::<Health, Armor>().map(|(node, _, _)| node).collect::<Vec<_>>(), vec![b]);
+        store.for_each_mut::<Health, Armor, _>(|_, health, armor| health.0 += armor.0);
+        assert_eq!(store.get::<Health>(b), Some(&Health(25)));
+
+        store.remove_node(a);
+        assert!(!store.contains::<Health>(a));
+        assert_eq!(store.get::<Health>(c), Some(&Health(30)));
+
+        graph.remove_node(b);
+        store.retain_alive(&graph);
+        assert_eq!(store.iter::<Health>().count(), 1);
+        assert_eq!(store.iter::<Armor>().count(), 0);
+    }
+}
@@ -0,0 +1,331 @@
+//! Crowd is a node which renders many animated copies (instances) of the same mesh using
+//! vertex animation texture (see `resource::vertex_animation`) instead of skinning.
+//!
+//! Each instance has its own position, rotation, scale and animation time offset, so the
+//! crowd does not move in lockstep. All visible instances of each surface are rendered
+//! using single instanced draw call. Instances which are farther from camera than cull
+//! distance are not rendered.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use rg3d::{
+//!     core::math::vec3::Vec3,
+//!     renderer::surface::Surface,
+//!     resource::vertex_animation::VertexAnimation,
+//!     scene::{
+//!         base::BaseBuilder,
+//!         crowd::{CrowdBuilder, CrowdInstance},
+//!         node::Node,
+//!         Scene,
+//!     },
+//! };
+//!
+//! fn create_crowd(scene: &mut Scene, surfaces: Vec<Surface>, animation: VertexAnimation) {
+//!     let instances = (0..1000)
+//!         .map(|i| CrowdInstance {
+//!             position: Vec3::new((i % 40) as f32, 0.0, (i / 40) as f32),
+//!             time_offset: i as f32 * 0.37,
+//!             ..Default::default()
+//!         })
+//!         .collect();
+//!     scene.graph.add_node(Node::Crowd(CrowdBuilder::new(BaseBuilder::new())
+//!         .with_surfaces(surfaces)
+//!         .with_animation(Arc::new(animation))
+//!         .with_instances(instances)
+//!         .build()));
+//! }
+//! ```
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+use crate::{
+    renderer::surface::Surface,
+    resource::vertex_animation::VertexAnimation,
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        pool::Handle,
+        math::{
+            vec3::Vec3,
+            mat4::Mat4,
+            quat::{Quat, RotationOrder},
+            frustum::Frustum,
+        },
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+/// Placement and animation parameters of single instance, in local coordinates of crowd
+/// node.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CrowdInstance {
+    pub position: Vec3,
+    /// Rotation around Y axis in radians.
+    pub rotation: f32,
+    pub scale: f32,
+    /// Offset of animation time in seconds.
+    pub time_offset: f32,
+    /// Multiplier of animation speed.
+    pub speed: f32,
+}
+
+impl Default for CrowdInstance {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: 0.0,
+            scale: 1.0,
+            time_offset: 0.0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl Visit for CrowdInstance {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.position.visit("Position", visitor)?;
+        self.rotation.visit("Rotation", visitor)?;
+        self.scale.visit("Scale", visitor)?;
+        self.time_offset.visit("TimeOffset", visitor)?;
+        self.speed.visit("Speed", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Clone)]
+pub struct Crowd {
+    base: Base,
+    surfaces: Vec<Surface>,
+    animation: Option<Arc<VertexAnimation>>,
+    instances: Vec<CrowdInstance>,
+    time: f32,
+    cull_distance: f32,
+}
+
+impl Deref for Crowd {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Crowd {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Crowd {
+    fn default() -> Self {
+        CrowdBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl Crowd {
+    /// Returns surfaces which are rendered for each instance. Vertices of surfaces must
+    /// match vertices of animation.
+    pub fn surfaces(&self) -> &[Surface] {
+        &self.surfaces
+    }
+
+    pub fn set_surfaces(&mut self, surfaces: Vec<Surface>) {
+        self.surfaces = surfaces;
+    }
+
+    /// Sets vertex animation, crowd without animation is rendered in rest pose.
+    pub fn set_animation(&mut self, animation: Option<Arc<VertexAnimation>>) {
+        self.animation = animation;
+    }
+
+    pub fn animation(&self) -> Option<&Arc<VertexAnimation>> {
+        self.animation.as_ref()
+    }
+
+    pub fn instances(&self) -> &[CrowdInstance] {
+        &self.instances
+    }
+
+    pub fn instances_mut(&mut self) -> &mut [CrowdInstance] {
+        &mut self.instances
+    }
+
+    pub fn add_instance(&mut self, instance: CrowdInstance) {
+        self.instances.push(instance);
+    }
+
+    pub fn set_instances(&mut self, instances: Vec<CrowdInstance>) {
+        self.instances = instances;
+    }
+
+    pub fn clear_instances(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Sets distance from camera beyond which instances are not rendered.
+    pub fn set_cull_distance(&mut self, cull_distance: f32) {
+        self.cull_distance = cull_distance.max(0.0);
+    }
+
+    pub fn cull_distance(&self) -> f32 {
+        self.cull_distance
+    }
+
+    /// Returns time of animation of crowd, time of each instance is offset from it.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Advances animation time, called by graph every frame.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        // Keep time in range where float precision is good.
+        if let Some(animation) = self.animation.as_ref() {
+            let duration = animation.duration();
+            if duration > 0.0 && self.time > duration * 1000.0 {
+                self.time %= duration;
+            }
+        }
+    }
+
+    /// Returns radius of sphere which encloses each surface of instance with scale of one.
+    pub fn bounding_radius(&self) -> f32 {
+        let mut radius = 0.0f32;
+        for surface in self.surfaces.iter() {
+            let data = surface.get_data();
+            for vertex in data.lock().unwrap().get_vertices() {
+                radius = radius.max(vertex.position.len());
+            }
+        }
+        radius
+    }
+
+    /// Collects world transforms of instances which are visible from given camera position
+    /// and inside given frustum. Current frame of animation of each instance is stored in
+    /// fourth row of first column of matrix, which is always zero for affine transforms,
+    /// shader resets it back after reading.
+    pub fn visible_instances(&self, camera_position: Vec3, frustum: &Frustum, matrices: &mut Vec<Mat4>) {
+        matrices.clear();
+
+        let global_transform = self.global_transform();
+        // Animated vertices can go beyond bounds of rest pose, so radius is enlarged.
+        let radius = self.bounding_radius() * 1.5;
+        let sqr_cull_distance = self.cull_distance * self.cull_distance;
+
+        for instance in self.instances.iter() {
+            let position = global_transform.transform_vector(instance.position);
+
+            if position.sqr_distance(&camera_position) > sqr_cull_distance {
+                continue;
+            }
+
+            if !frustum.is_intersects_sphere(position, radius * instance.scale) {
+                continue;
+            }
+
+            let rotation = Quat::from_euler(Vec3::new(0.0, instance.rotation, 0.0), RotationOrder::XYZ);
+            let mut matrix = global_transform
+                * Mat4::translate(instance.position)
+                * Mat4::from_quat(rotation)
+                * Mat4::scale(Vec3::new(instance.scale, instance.scale, instance.scale));
+
+            matrix.f[3] = self.animation
+                .as_ref()
+                .map_or(0.0, |animation| animation.frame_at(self.time * instance.speed + instance.time_offset));
+
+            matrices.push(matrix);
+        }
+    }
+}
+
+impl Visit for Crowd {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        // Surfaces and animation are not serialized, they must be set again after load.
+        self.instances.visit("Instances", visitor)?;
+        self.time.visit("Time", visitor)?;
+        self.cull_distance.visit("CullDistance", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct CrowdBuilder {
+    base_builder: BaseBuilder,
+    surfaces: Option<Vec<Surface>>,
+    animation: Option<Arc<VertexAnimation>>,
+    instances: Option<Vec<CrowdInstance>>,
+    cull_distance: Option<f32>,
+}
+
+impl CrowdBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            surfaces: None,
+            animation: None,
+            instances: None,
+            cull_distance: None,
+        }
+    }
+
+    pub fn with_surfaces(mut self, surfaces: Vec<Surface>) -> Self {
+        self.surfaces = Some(surfaces);
+        self
+    }
+
+    pub fn with_animation(mut self, animation: Arc<VertexAnimation>) -> Self {
+        self.animation = Some(animation);
+        self
+    }
+
+    pub fn with_instances(mut self, instances: Vec<CrowdInstance>) -> Self {
+        self.instances = Some(instances);
+        self
+    }
+
+    pub fn with_cull_distance(mut self, cull_distance: f32) -> Self {
+        self.cull_distance = Some(cull_distance);
+        self
+    }
+
+    pub fn build(self) -> Crowd {
+        Crowd {
+            base: self.base_builder.build(),
+            surfaces: self.surfaces.unwrap_or_default(),
+            animation: self.animation,
+            instances: self.instances.unwrap_or_default(),
+            time: 0.0,
+            cull_distance: self.cull_distance.unwrap_or(100.0).max(0.0),
+        }
+    }
+
+    /// Creates new crowd node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Crowd(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
@@ -0,0 +1,294 @@
+//! Day/night cycle of a scene.
+//!
+//! Cycle moves sun along the sky by time of day and gives colors of environment for current
+//! height of sun: color of sun light, colors of sky at zenith and at horizon and ambient
+//! color. Optionally it drives a directional light node - rotates it along sun rays and
+//! sets its color and intensity, so outdoor scenes get sunrises and sunsets without any
+//! code in game.
+//!
+//! Cycle is disabled by default, enable it with `Scene::day_night`:
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, day_night::DayNightCycle};
+//! use rg3d::core::pool::Handle;
+//! use rg3d::scene::node::Node;
+//!
+//! fn setup(scene: &mut Scene, sun_light: Handle<Node>) {
+//!     let mut cycle = DayNightCycle::new();
+//!     cycle.set_time_of_day(6.0);
+//!     // Whole day takes 20 minutes.
+//!     cycle.set_day_length(20.0 * 60.0);
+//!     cycle.set_sun(sun_light);
+//!     scene.day_night = Some(cycle);
+//! }
+//! ```
+//!
+//! Engine applies ambient color of the first scene with enabled cycle to renderer. Sky
+//! colors are not rendered by engine, use them for sky dome or clear color.
+//!
+//! Colors are defined by gradients over height of sun, location 0.0 of gradient is sun in
+//! nadir, 0.5 - sun at horizon and 1.0 - sun in zenith.
+
+use std::f32::consts::PI;
+use crate::{
+    core::{
+        color::Color,
+        color_gradient::{ColorGradient, GradientPoint},
+        math::{
+            vec3::Vec3,
+            quat::Quat,
+        },
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+        light::DEFAULT_INTENSITY,
+    },
+};
+
+fn gradient(points: &[(f32, Color)]) -> ColorGradient {
+    let mut gradient = ColorGradient::new();
+    for &(location, color) in points {
+        gradient.add_point(GradientPoint::new(location, color));
+    }
+    gradient
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct DayNightCycle {
+    time_of_day: f32,
+    day_length: f32,
+    sun_azimuth: f32,
+    latitude: f32,
+    sun_intensity: f32,
+    sun: Handle<Node>,
+    sun_color: ColorGradient,
+    zenith_color: ColorGradient,
+    horizon_color: ColorGradient,
+    ambient_color: ColorGradient,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        Self {
+            time_of_day: 12.0,
+            day_length: 0.0,
+            sun_azimuth: 0.0,
+            latitude: 0.5,
+            sun_intensity: DEFAULT_INTENSITY,
+            sun: Handle::NONE,
+            sun_color: gradient(&[
+                (0.45, Color::opaque(0, 0, 0)),
+                (0.5, Color::opaque(255, 110, 40)),
+                (0.6, Color::opaque(255, 200, 150)),
+                (1.0, Color::opaque(255, 250, 240)),
+            ]),
+            zenith_color: gradient(&[
+                (0.4, Color::opaque(5, 5, 20)),
+                (0.5, Color::opaque(40, 50, 110)),
+                (0.6, Color::opaque(70, 120, 200)),
+                (1.0, Color::opaque(40, 100, 220)),
+            ]),
+            horizon_color: gradient(&[
+                (0.4, Color::opaque(10, 10, 25)),
+                (0.5, Color::opaque(250, 130, 70)),
+                (0.6, Color::opaque(180, 200, 230)),
+                (1.0, Color::opaque(170, 200, 240)),
+            ]),
+            ambient_color: gradient(&[
+                (0.4, Color::opaque(15, 15, 30)),
+                (0.5, Color::opaque(60, 50, 60)),
+                (0.65, Color::opaque(100, 100, 100)),
+                (1.0, Color::opaque(120, 120, 125)),
+            ]),
+        }
+    }
+}
+
+impl DayNightCycle {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets time of day in hours, it is wrapped into [0; 24) range. Sun rises at 6 and sets
+    /// at 18.
+    pub fn set_time_of_day(&mut self, hours: f32) {
+        self.time_of_day = hours.rem_euclid(24.0);
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    /// Sets duration of whole day in seconds, zero stops the time.
+    pub fn set_day_length(&mut self, seconds: f32) {
+        self.day_length = seconds.max(0.0);
+    }
+
+    pub fn day_length(&self) -> f32 {
+        self.day_length
+    }
+
+    /// Sets direction of sunrise as angle around Y axis in radians, with zero angle sun
+    /// rises at +X.
+    pub fn set_sun_azimuth(&mut self, azimuth: f32) {
+        self.sun_azimuth = azimuth;
+    }
+
+    pub fn sun_azimuth(&self) -> f32 {
+        self.sun_azimuth
+    }
+
+    /// Sets tilt of sun path from zenith in radians, zero tilt means that sun passes
+    /// through zenith at noon.
+    pub fn set_latitude(&mut self, latitude: f32) {
+        self.latitude = latitude.max(-PI * 0.5).min(PI * 0.5);
+    }
+
+    pub fn latitude(&self) -> f32 {
+        self.latitude
+    }
+
+    /// Sets intensity of sun light at noon.
+    pub fn set_sun_intensity(&mut self, intensity: f32) {
+        self.sun_intensity = intensity.max(0.0);
+    }
+
+    pub fn sun_intensity(&self) -> f32 {
+        self.sun_intensity
+    }
+
+    /// Sets directional light which is driven by cycle, `Handle::NONE` to drive nothing.
+    pub fn set_sun(&mut self, sun: Handle<Node>) {
+        self.sun = sun;
+    }
+
+    pub fn sun(&self) -> Handle<Node> {
+        self.sun
+    }
+
+    pub fn set_sun_color_gradient(&mut self, gradient: ColorGradient) {
+        self.sun_color = gradient;
+    }
+
+    pub fn set_zenith_color_gradient(&mut self, gradient: ColorGradient) {
+        self.zenith_color = gradient;
+    }
+
+    pub fn set_horizon_color_gradient(&mut self, gradient: ColorGradient) {
+        self.horizon_color = gradient;
+    }
+
+    pub fn set_ambient_color_gradient(&mut self, gradient: ColorGradient) {
+        self.ambient_color = gradient;
+    }
+
+    /// Returns normalized direction from ground to sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = 2.0 * PI * (self.time_of_day - 6.0) / 24.0;
+        let (sin, cos) = angle.sin_cos();
+        let x = cos;
+        let y = sin * self.latitude.cos();
+        let z = sin * self.latitude.sin();
+        let (sin_az, cos_az) = self.sun_azimuth.sin_cos();
+        Vec3::new(x * cos_az + z * sin_az, y, z * cos_az - x * sin_az)
+    }
+
+    /// Returns location on gradients for current height of sun.
+    fn gradient_location(&self) -> f32 {
+        (self.sun_direction().y + 1.0) * 0.5
+    }
+
+    pub fn sun_color(&self) -> Color {
+        self.sun_color.get_color(self.gradient_location())
+    }
+
+    pub fn zenith_color(&self) -> Color {
+        self.zenith_color.get_color(self.gradient_location())
+    }
+
+    pub fn horizon_color(&self) -> Color {
+        self.horizon_color.get_color(self.gradient_location())
+    }
+
+    pub fn ambient_color(&self) -> Color {
+        self.ambient_color.get_color(self.gradient_location())
+    }
+
+    /// Returns true if sun is above horizon.
+    pub fn is_day(&self) -> bool {
+        self.sun_direction().y > 0.0
+    }
+
+    /// Advances time and applies sun to light node, called automatically by scene.
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        if self.day_length > 0.0 {
+            self.set_time_of_day(self.time_of_day + 24.0 * dt / self.day_length);
+        }
+
+        if !graph.is_valid_handle(self.sun) {
+            return;
+        }
+
+        let direction = self.sun_direction();
+        // Lights shine along local -Y, so +Y must point to the sun.
+        let axis = Vec3::UP.cross(&direction);
+        let rotation = match axis.normalized() {
+            Some(axis) => Quat::from_axis_angle(axis, Vec3::UP.dot(&direction).max(-1.0).min(1.0).acos()),
+            None if direction.y > 0.0 => Quat::IDENTITY,
+            None => Quat::from_axis_angle(Vec3::RIGHT, PI),
+        };
+        // Fade light out while sun goes below horizon.
+        let intensity = self.sun_intensity * (direction.y * 10.0).max(0.0).min(1.0);
+        let color = self.sun_color();
+
+        if let Node::Light(light) = &mut graph[self.sun] {
+            light.local_transform_mut().set_rotation(rotation);
+            light.set_color(color);
+            light.set_intensity(intensity);
+        }
+    }
+}
+
+impl Visit for DayNightCycle {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.time_of_day.visit("TimeOfDay", visitor)?;
+        self.day_length.visit("DayLength", visitor)?;
+        self.sun_azimuth.visit("SunAzimuth", visitor)?;
+        self.latitude.visit("Latitude", visitor)?;
+        self.sun_intensity.visit("SunIntensity", visitor)?;
+        self.sun.visit("Sun", visitor)?;
+        self.sun_color.visit("SunColor", visitor)?;
+        self.zenith_color.visit("ZenithColor", visitor)?;
+        self.horizon_color.visit("HorizonColor", visitor)?;
+        self.ambient_color.visit("AmbientColor", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::day_night::DayNightCycle;
+
+    #[test]
+    fn test_sun_path() {
+        let mut cycle = DayNightCycle::new();
+        cycle.set_time_of_day(12.0);
+        assert!(cycle.is_day());
+        cycle.set_time_of_day(0.0);
+        assert!(!cycle.is_day());
+        cycle.set_time_of_day(6.0);
+        assert!(cycle.sun_direction().y.abs() < 0.001);
+
+        cycle.set_time_of_day(23.0);
+        cycle.set_day_length(24.0);
+        cycle.update(&mut Default::default(), 2.0);
+        assert!((cycle.time_of_day() - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,419 @@
+//! Destructible objects - meshes which are swapped for pre-fractured pieces when destroyed.
+//!
+//! Real-time fracturing is expensive, so destructible object is prepared ahead: intact mesh
+//! node is associated with a set of piece nodes which are kept disabled in graph. Pieces can
+//! be imported together with model (usually as children of a separate node) or produced by
+//! [`create_pieces`] which splits mesh into Voronoi-like cells. When accumulated damage
+//! reaches health of object, intact node is disabled, pieces are enabled, detached into
+//! world space and get their own rigid bodies which are pushed away from hit point.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::{math::vec3::Vec3, pool::Handle},
+//!     scene::{
+//!         destructible::{create_pieces, Destructible, DestructionHit},
+//!         node::Node,
+//!         Scene,
+//!     },
+//! };
+//!
+//! fn make_destructible(scene: &mut Scene, vase: Handle<Node>) -> Destructible {
+//!     let pieces = create_pieces(scene, vase, 12, 42);
+//!     Destructible::new(vase, pieces)
+//!         .with_health(30.0)
+//!         .with_piece_lifetime(Some(10.0))
+//! }
+//!
+//! fn on_shot(vase: &mut Destructible, scene: &mut Scene, point: Vec3, direction: Vec3) {
+//!     vase.damage(scene, 10.0, DestructionHit {
+//!         point,
+//!         impulse: direction.scale(4.0),
+//!         radius: 1.0,
+//!     });
+//! }
+//! ```
+//!
+//! Pieces are driven by physics as spheres, which is enough for debris. Skinned meshes are
+//! not supported. Call `update` each frame after `damage` so initial velocities of pieces
+//! are applied with actual time step.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use crate::{
+    core::{
+        math::{
+            mat4::Mat4,
+            vec3::Vec3,
+            TriangleDefinition,
+        },
+        pool::Handle,
+    },
+    physics::{
+        convex_shape::{ConvexShape, SphereShape},
+        rigid_body::RigidBody,
+    },
+    renderer::surface::{
+        Surface,
+        SurfaceSharedData,
+        Vertex,
+    },
+    scene::{
+        base::BaseBuilder,
+        mesh::MeshBuilder,
+        node::Node,
+        transform::Transform,
+        Scene,
+    },
+    utils::random::RandomGenerator,
+};
+
+/// Describes hit which damages destructible object.
+#[derive(Copy, Clone, Debug)]
+pub struct DestructionHit {
+    /// Point of hit in world coordinates.
+    pub point: Vec3,
+    /// Velocity (in units per second) which is added to every piece, usually direction of
+    /// hit scaled by its strength.
+    pub impulse: Vec3,
+    /// Radius around hit point within which pieces are additionally pushed away from it.
+    /// Push fades out linearly with distance.
+    pub radius: f32,
+}
+
+impl Default for DestructionHit {
+    fn default() -> Self {
+        Self {
+            point: Vec3::ZERO,
+            impulse: Vec3::ZERO,
+            radius: 0.0,
+        }
+    }
+}
+
+/// See module docs.
+pub struct Destructible {
+    intact: Handle<Node>,
+    pieces: Vec<Handle<Node>>,
+    health: f32,
+    destroyed: bool,
+    piece_lifetime: Option<f32>,
+    /// Speed of radial push of pieces at hit point in units per second.
+    blast_speed: f32,
+    pending_velocities: Vec<(Handle<Node>, Vec3)>,
+}
+
+impl Destructible {
+    /// Creates new destructible object from intact node and its pieces. Pieces are disabled
+    /// until object is destroyed.
+    pub fn new(intact: Handle<Node>, pieces: Vec<Handle<Node>>) -> Self {
+        Self {
+            intact,
+            pieces,
+            health: 0.0,
+            destroyed: false,
+            piece_lifetime: None,
+            blast_speed: 5.0,
+            pending_velocities: Default::default(),
+        }
+    }
+
+    /// Sets amount of damage object can take, object with zero health is destroyed by any
+    /// hit. Default is zero.
+    pub fn with_health(mut self, health: f32) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Sets time in seconds after which pieces are removed from scene, `None` means that
+    /// pieces live forever. Default is `None`.
+    pub fn with_piece_lifetime(mut self, lifetime: Option<f32>) -> Self {
+        self.piece_lifetime = lifetime;
+        self
+    }
+
+    /// Sets speed of radial push of pieces at hit point. Default is 5 units per second.
+    pub fn with_blast_speed(mut self, blast_speed: f32) -> Self {
+        self.blast_speed = blast_speed;
+        self
+    }
+
+    pub fn intact(&self) -> Handle<Node> {
+        self.intact
+    }
+
+    pub fn pieces(&self) -> &[Handle<Node>] {
+        &self.pieces
+    }
+
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed
+    }
+
+    /// Applies damage to object and destroys it if its health drops to zero. Returns true
+    /// if object was destroyed by this hit.
+    pub fn damage(&mut self, scene: &mut Scene, amount: f32, hit: DestructionHit) -> bool {
+        if self.destroyed {
+            return false;
+        }
+
+        self.health -= amount;
+        if self.health <= 0.0 {
+            self.destroy(scene, hit);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Destroys object immediately regardless of its health: swaps intact node for pieces
+    /// and creates rigid bodies for pieces. Does nothing if object is already destroyed.
+    pub fn destroy(&mut self, scene: &mut Scene, hit: DestructionHit) {
+        if self.destroyed {
+            return;
+        }
+        self.destroyed = true;
+        self.health = 0.0;
+
+        if scene.graph.is_valid_handle(self.intact) {
+            scene.graph[self.intact].set_enabled(false);
+        }
+
+        let root = scene.graph.get_root();
+        for &piece in self.pieces.iter() {
+            if !scene.graph.is_valid_handle(piece) {
+                continue;
+            }
+
+            scene.graph[piece].set_enabled(true);
+            // Pieces must be in world space, because physics moves them by global position.
+            scene.graph.link_nodes_keep_world_transform(piece, root);
+            if let Some(lifetime) = self.piece_lifetime {
+                scene.graph[piece].set_lifetime(lifetime);
+            }
+
+            let (position, radius) = match &scene.graph[piece] {
+                Node::Mesh(mesh) => {
+                    let bounds = mesh.world_bounding_box();
+                    let size = bounds.max - bounds.min;
+                    (mesh.global_position(), (size.x.max(size.y).max(size.z) * 0.5).max(0.01))
+                }
+                node => (node.global_position(), 0.1),
+            };
+
+            let mut body = RigidBody::new(ConvexShape::Sphere(SphereShape::new(radius)));
+            body.set_position(position);
+            let body = scene.physics.add_body(body);
+            scene.physics_binder.bind(piece, body);
+
+            let mut velocity = hit.impulse;
+            if hit.radius > 0.0 {
+                let offset = position - hit.point;
+                let distance = offset.len();
+                if distance < hit.radius {
+                    if let Some(direction) = offset.normalized() {
+                        let k = 1.0 - distance / hit.radius;
+                        velocity += direction.scale(self.blast_speed * k);
+                    }
+                }
+            }
+            self.pending_velocities.push((piece, velocity));
+        }
+
+        scene.graph.update_hierachical_data();
+    }
+
+    /// Applies initial velocities of pieces of just destroyed object. Velocity of rigid body
+    /// is displacement per physics step, so it needs actual time step.
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) {
+        for (piece, velocity) in self.pending_velocities.drain(..) {
+            if let Some(body) = scene.physics_binder.body_of(piece) {
+                if scene.physics.is_valid_body_handle(body) {
+                    scene.physics.borrow_body_mut(body).set_velocity(velocity.scale(dt));
+                }
+            }
+        }
+    }
+}
+
+struct Cell {
+    vertices: Vec<Vertex>,
+    triangles: Vec<TriangleDefinition>,
+    /// Old-to-new vertex index mapping.
+    mapping: HashMap<u32, u32>,
+}
+
+impl Cell {
+    fn add_vertex(&mut self, index: u32, vertices: &[Vertex]) -> u32 {
+        let cell_vertices = &mut self.vertices;
+        *self.mapping.entry(index).or_insert_with(|| {
+            cell_vertices.push(vertices[index as usize]);
+            (cell_vertices.len() - 1) as u32
+        })
+    }
+}
+
+/// Splits surface into cells around given sites (in local coordinates of surface): each
+/// triangle goes to cell of site nearest to its center. Returns one entry per site, `None`
+/// if site got no triangles. Textures and material of pieces are the same as of source
+/// surface.
+///
+/// # Notes
+///
+/// Triangles are not cut and cells are not capped, so pieces are open shells. This looks
+/// fine for fast-moving debris, but not for close-ups - import pieces made in modelling
+/// software for such objects.
+pub fn fracture_surface(surface: &Surface, sites: &[Vec3]) -> Vec<Option<Surface>> {
+    let data = surface.get_data();
+    let data = data.lock().unwrap();
+    let vertices = data.get_vertices();
+
+    let mut cells = (0..sites.len())
+        .map(|_| Cell {
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            mapping: HashMap::new(),
+        })
+        .collect::<Vec<_>>();
+
+    for triangle in data.triangles() {
+        let center = (vertices[triangle[0] as usize].position
+            + vertices[triangle[1] as usize].position
+            + vertices[triangle[2] as usize].position).scale(1.0 / 3.0);
+
+        let nearest = sites.iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                a.sqr_distance(&center).partial_cmp(&b.sqr_distance(&center)).unwrap()
+            })
+            .map(|(index, _)| index);
+
+        if let Some(nearest) = nearest {
+            let cell = &mut cells[nearest];
+            let a = cell.add_vertex(triangle[0], vertices);
+            let b = cell.add_vertex(triangle[1], vertices);
+            let c = cell.add_vertex(triangle[2], vertices);
+            cell.triangles.push(TriangleDefinition([a, b, c]));
+        }
+    }
+
+    cells.into_iter()
+        .map(|cell| {
+            if cell.triangles.is_empty() {
+                None
+            } else {
+                let mut piece = surface.clone();
+                piece.set_data(Arc::new(Mutex::new(SurfaceSharedData::new(cell.vertices, cell.triangles))));
+                Some(piece)
+            }
+        })
+        .collect()
+}
+
+/// Fractures mesh node into at most `count` pieces around random sites within its bounding
+/// box (see [`fracture_surface`]) and adds them to graph as disabled mesh nodes with the
+/// same parent and placement as source mesh. Origin of each piece is moved to center of its
+/// vertices, so rigid bodies of pieces are placed correctly. Same seed gives same pieces.
+/// Returns empty vector if node is not a mesh.
+pub fn create_pieces(scene: &mut Scene, mesh: Handle<Node>, count: usize, seed: u64) -> Vec<Handle<Node>> {
+    let (surfaces, bounds, local_matrix, parent) = match &scene.graph[mesh] {
+        Node::Mesh(mesh) => (
+            mesh.surfaces().to_vec(),
+            mesh.bounding_box(),
+            mesh.local_transform().matrix(),
+            mesh.parent(),
+        ),
+        _ => return Vec::new(),
+    };
+
+    let mut rng = RandomGenerator::new(seed);
+    let sites = (0..count)
+        .map(|_| Vec3::new(
+            rng.range(bounds.min.x, bounds.max.x),
+            rng.range(bounds.min.y, bounds.max.y),
+            rng.range(bounds.min.z, bounds.max.z),
+        ))
+        .collect::<Vec<_>>();
+
+    let mut cells = vec![Vec::new(); sites.len()];
+    for surface in surfaces.iter() {
+        for (cell, piece) in cells.iter_mut().zip(fracture_surface(surface, &sites)) {
+            if let Some(piece) = piece {
+                cell.push(piece);
+            }
+        }
+    }
+
+    let mut pieces = Vec::new();
+    for (index, cell) in cells.into_iter().enumerate() {
+        if cell.is_empty() {
+            continue;
+        }
+
+        let mut center = Vec3::ZERO;
+        let mut vertex_count = 0;
+        for surface in cell.iter() {
+            for vertex in surface.get_data().lock().unwrap().get_vertices() {
+                center += vertex.position;
+                vertex_count += 1;
+            }
+        }
+        center = center.scale(1.0 / vertex_count as f32);
+
+        for surface in cell.iter() {
+            for vertex in surface.get_data().lock().unwrap().get_vertices_mut() {
+                vertex.position = vertex.position - center;
+            }
+        }
+
+        let mut transform = Transform::identity();
+        transform.set_matrix(local_matrix * Mat4::translate(center));
+
+        let name = format!("{}_Piece{}", scene.graph[mesh].name(), index);
+        let piece = scene.graph.add_node(Node::Mesh(MeshBuilder::new(BaseBuilder::new()
+            .with_name(&name)
+            .with_enabled(false)
+            .with_local_transform(transform))
+            .with_surfaces(cell)
+            .build()));
+        if parent.is_some() {
+            scene.graph.link_nodes(piece, parent);
+        }
+        pieces.push(piece);
+    }
+
+    scene.graph.update_hierachical_data();
+
+    pieces
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use crate::{
+        core::math::vec3::Vec3,
+        renderer::surface::{Surface, SurfaceSharedData},
+        scene::destructible::fracture_surface,
+    };
+
+    #[test]
+    fn test_fracture_keeps_all_triangles() {
+        let surface = Surface::new(Arc::new(Mutex::new(SurfaceSharedData::make_cube())));
+        let sites = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0)];
+        let pieces = fracture_surface(&surface, &sites);
+        assert_eq!(pieces.len(), 3);
+        // Far away site gets nothing.
+        assert!(pieces[2].is_none());
+
+        let total = pieces.iter()
+            .flatten()
+            .map(|piece| piece.get_data().lock().unwrap().triangles().len())
+            .sum::<usize>();
+        assert_eq!(total, surface.get_data().lock().unwrap().triangles().len());
+    }
+}
@@ -0,0 +1,547 @@
+//! Structural diff of scenes which can be applied as a patch.
+//!
+//! Diff describes how to turn one hierarchy of nodes into another: which nodes were added
+//! (with whole subtrees), which were removed and which common properties of existing nodes
+//! were changed. Use it to:
+//!
+//! - store modified level as a patch to its source scene - it is much smaller than whole
+//! scene and picks up changes of the source which were not touched by the patch;
+//! - exchange changes between level designers who edit the same level;
+//! - store changes of prefab instance relative to its resource (see `SceneDiff::from_instance`).
+//!
+//! Nodes are matched by path of names from root of hierarchy, siblings with same names are
+//! distinguished by their order. Renamed node is removed and added again. Compared properties
+//! are local transform, visibility, enabled state, max render distance and persistence, node
+//! whose kind was changed is replaced; kind-specific properties (color of light, surfaces of
+//! mesh, etc.) of matched nodes are not compared.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     scene::{Scene, diff::SceneDiff},
+//!     core::visitor::{Visitor, VisitResult, Visit},
+//! };
+//!
+//! fn save_changes(source: &Scene, edited: &Scene) -> VisitResult {
+//!     let mut diff = SceneDiff::compute(source, edited);
+//!     let mut visitor = Visitor::new();
+//!     diff.visit("Diff", &mut visitor)?;
+//!     visitor.save_binary("level.patch")
+//! }
+//!
+//! fn load_changes(source: &mut Scene) -> VisitResult {
+//!     let mut diff = SceneDiff::default();
+//!     let mut visitor = Visitor::load_binary("level.patch")?;
+//!     diff.visit("Diff", &mut visitor)?;
+//!     diff.apply(source);
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use crate::{
+    core::{
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+    scene::{
+        Scene,
+        node::Node,
+        graph::Graph,
+        transform::Transform,
+    },
+};
+
+/// Name of node and index of node among siblings with the same name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathSegment {
+    pub name: String,
+    pub index: u32,
+}
+
+impl Visit for PathSegment {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.name.visit("Name", visitor)?;
+        self.index.visit("Index", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Path of node from root of hierarchy, empty path is the root itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodePath {
+    pub segments: Vec<PathSegment>,
+}
+
+impl NodePath {
+    fn child(&self, segment: PathSegment) -> Self {
+        let mut segments = self.segments.clone();
+        segments.push(segment);
+        Self { segments }
+    }
+
+    /// Searches node by path starting from given root.
+    pub fn resolve(&self, graph: &Graph, root: Handle<Node>) -> Handle<Node> {
+        let mut current = root;
+        for segment in self.segments.iter() {
+            current = graph[current].children()
+                .iter()
+                .filter(|&&child| graph[child].name() == segment.name)
+                .nth(segment.index as usize)
+                .cloned()
+                .unwrap_or(Handle::NONE);
+            if current.is_none() {
+                break;
+            }
+        }
+        current
+    }
+}
+
+impl Visit for NodePath {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.segments.visit("Segments", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Changed property of node.
+#[derive(Clone)]
+pub enum NodeChange {
+    Transform(Transform),
+    Visibility(bool),
+    Enabled(bool),
+    MaxRenderDistance(Option<f32>),
+    Persistent(bool),
+}
+
+impl Default for NodeChange {
+    fn default() -> Self {
+        NodeChange::Visibility(true)
+    }
+}
+
+impl NodeChange {
+    fn id(&self) -> u32 {
+        match self {
+            NodeChange::Transform(_) => 0,
+            NodeChange::Visibility(_) => 1,
+            NodeChange::Enabled(_) => 2,
+            NodeChange::MaxRenderDistance(_) => 3,
+            NodeChange::Persistent(_) => 4,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(NodeChange::Transform(Default::default())),
+            1 => Ok(NodeChange::Visibility(Default::default())),
+            2 => Ok(NodeChange::Enabled(Default::default())),
+            3 => Ok(NodeChange::MaxRenderDistance(Default::default())),
+            4 => Ok(NodeChange::Persistent(Default::default())),
+            _ => Err(format!("Invalid node change {}", id))
+        }
+    }
+
+    fn apply(&self, node: &mut Node) {
+        match self {
+            NodeChange::Transform(transform) => {
+                node.set_local_transform(transform.clone());
+            }
+            NodeChange::Visibility(visibility) => {
+                node.set_visibility(*visibility);
+            }
+            NodeChange::Enabled(enabled) => {
+                node.set_enabled(*enabled);
+            }
+            NodeChange::MaxRenderDistance(distance) => {
+                node.set_max_render_distance(*distance);
+            }
+            NodeChange::Persistent(persistent) => {
+                node.set_persistent(*persistent);
+            }
+        }
+    }
+}
+
+impl Visit for NodeChange {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = NodeChange::from_id(id)?;
+        }
+        match self {
+            NodeChange::Transform(transform) => transform.visit("Value", visitor)?,
+            NodeChange::Visibility(visibility) => visibility.visit("Value", visitor)?,
+            NodeChange::Enabled(enabled) => enabled.visit("Value", visitor)?,
+            NodeChange::MaxRenderDistance(distance) => distance.visit("Value", visitor)?,
+            NodeChange::Persistent(persistent) => persistent.visit("Value", visitor)?,
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Single change of hierarchy, entries of diff must be applied in order.
+#[derive(Clone)]
+pub enum DiffEntry {
+    /// Properties of existing node were changed.
+    Modified {
+        path: NodePath,
+        changes: Vec<NodeChange>,
+    },
+    /// Subtree was attached to node. Nodes go in depth-first order, `parents` holds index
+    /// of parent of each node in `nodes` (`u32::MAX` for root of subtree), `handles` - handles
+    /// of nodes in source graph which are used to remap handles stored in nodes (bones, etc.).
+    Added {
+        parent: NodePath,
+        nodes: Vec<Node>,
+        parents: Vec<u32>,
+        handles: Vec<Handle<Node>>,
+    },
+    /// Node was removed with its descendants.
+    Removed {
+        path: NodePath,
+    },
+}
+
+impl Default for DiffEntry {
+    fn default() -> Self {
+        DiffEntry::Removed { path: Default::default() }
+    }
+}
+
+impl DiffEntry {
+    fn id(&self) -> u32 {
+        match self {
+            DiffEntry::Modified { .. } => 0,
+            DiffEntry::Added { .. } => 1,
+            DiffEntry::Removed { .. } => 2,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(DiffEntry::Modified { path: Default::default(), changes: Default::default() }),
+            1 => Ok(DiffEntry::Added {
+                parent: Default::default(),
+                nodes: Default::default(),
+                parents: Default::default(),
+                handles: Default::default(),
+            }),
+            2 => Ok(DiffEntry::Removed { path: Default::default() }),
+            _ => Err(format!("Invalid diff entry {}", id))
+        }
+    }
+}
+
+/// Node does not enter its own region for kind of node, so every node is visited in
+/// separate region.
+fn visit_nodes(nodes: &mut Vec<Node>, name: &str, visitor: &mut Visitor) -> VisitResult {
+    visitor.enter_region(name)?;
+
+    let mut count = nodes.len() as u32;
+    count.visit("Count", visitor)?;
+    if visitor.is_reading() {
+        nodes.clear();
+        nodes.resize_with(count as usize, Default::default);
+    }
+    for (i, node) in nodes.iter_mut().enumerate() {
+        visitor.enter_region(&format!("Item{}", i))?;
+        node.visit("Node", visitor)?;
+        visitor.leave_region()?;
+    }
+
+    visitor.leave_region()
+}
+
+impl Visit for DiffEntry {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = DiffEntry::from_id(id)?;
+        }
+        match self {
+            DiffEntry::Modified { path, changes } => {
+                path.visit("Path", visitor)?;
+                changes.visit("Changes", visitor)?;
+            }
+            DiffEntry::Added { parent, nodes, parents, handles } => {
+                parent.visit("Parent", visitor)?;
+                visit_nodes(nodes, "Nodes", visitor)?;
+                parents.visit("Parents", visitor)?;
+                handles.visit("Handles", visitor)?;
+            }
+            DiffEntry::Removed { path } => {
+                path.visit("Path", visitor)?;
+            }
+        }
+
+        visitor.leave_region()
+    }
+}
+
+fn transforms_equal(a: &Transform, b: &Transform) -> bool {
+    a.position() == b.position() &&
+        a.rotation() == b.rotation() &&
+        a.scale() == b.scale() &&
+        a.pre_rotation() == b.pre_rotation() &&
+        a.post_rotation() == b.post_rotation() &&
+        a.rotation_offset() == b.rotation_offset() &&
+        a.rotation_pivot() == b.rotation_pivot() &&
+        a.scaling_offset() == b.scaling_offset() &&
+        a.scaling_pivot() == b.scaling_pivot()
+}
+
+fn node_changes(old: &Node, new: &Node) -> Vec<NodeChange> {
+    let mut changes = Vec::new();
+    if !transforms_equal(old.local_transform(), new.local_transform()) {
+        changes.push(NodeChange::Transform(new.local_transform().clone()));
+    }
+    if old.visibility() != new.visibility() {
+        changes.push(NodeChange::Visibility(new.visibility()));
+    }
+    if old.is_enabled() != new.is_enabled() {
+        changes.push(NodeChange::Enabled(new.is_enabled()));
+    }
+    if old.max_render_distance() != new.max_render_distance() {
+        changes.push(NodeChange::MaxRenderDistance(new.max_render_distance()));
+    }
+    if old.is_persistent() != new.is_persistent() {
+        changes.push(NodeChange::Persistent(new.is_persistent()));
+    }
+    changes
+}
+
+/// Returns children of node with their path segments.
+fn child_segments(graph: &Graph, node: Handle<Node>) -> Vec<(PathSegment, Handle<Node>)> {
+    let mut counters = HashMap::new();
+    graph[node].children()
+        .iter()
+        .map(|&child| {
+            let name = graph[child].name().to_owned();
+            let counter = counters.entry(name.clone()).or_insert(0u32);
+            let segment = PathSegment { name, index: *counter };
+            *counter += 1;
+            (segment, child)
+        })
+        .collect()
+}
+
+/// See module docs.
+#[derive(Clone, Default)]
+pub struct SceneDiff {
+    pub entries: Vec<DiffEntry>,
+}
+
+impl SceneDiff {
+    /// Computes diff which turns graph of `old` scene into graph of `new` scene.
+    pub fn compute(old: &Scene, new: &Scene) -> Self {
+        Self::compute_hierarchy(&old.graph, old.graph.get_root(), &new.graph, new.graph.get_root())
+    }
+
+    /// Computes diff which turns hierarchy of `old_root` into hierarchy of `new_root`.
+    pub fn compute_hierarchy(old: &Graph, old_root: Handle<Node>, new: &Graph, new_root: Handle<Node>) -> Self {
+        let mut diff = Self::default();
+        diff.diff_node(old, old_root, new, new_root, &NodePath::default());
+        diff
+    }
+
+    /// Computes changes of instance of model resource relative to the resource, root of
+    /// instance is matched with root of scene of resource. Returns `None` if node is not
+    /// root of instance.
+    pub fn from_instance(graph: &Graph, root: Handle<Node>) -> Option<Self> {
+        if !graph[root].is_resource_instance() {
+            return None;
+        }
+        let model = graph[root].resource()?;
+        let model = model.lock().unwrap();
+        let resource_graph = &model.get_scene().graph;
+        Some(Self::compute_hierarchy(resource_graph, resource_graph.get_root(), graph, root))
+    }
+
+    /// Returns true if diff has no changes.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn diff_node(&mut self, old: &Graph, old_node: Handle<Node>, new: &Graph, new_node: Handle<Node>, path: &NodePath) {
+        let changes = node_changes(&old[old_node], &new[new_node]);
+        if !changes.is_empty() {
+            self.entries.push(DiffEntry::Modified { path: path.clone(), changes });
+        }
+
+        let old_children = child_segments(old, old_node);
+        let new_children = child_segments(new, new_node);
+
+        // Removed first and from the end, so indices of remaining siblings do not change.
+        for (segment, old_child) in old_children.iter().rev() {
+            let replaced = match new_children.iter().find(|(s, _)| s == segment) {
+                Some(&(_, new_child)) => old[*old_child].id() != new[new_child].id(),
+                None => true,
+            };
+            if replaced {
+                self.entries.push(DiffEntry::Removed { path: path.child(segment.clone()) });
+            }
+        }
+
+        for (segment, new_child) in new_children.iter() {
+            match old_children.iter().find(|(s, _)| s == segment) {
+                Some(&(_, old_child)) if old[old_child].id() == new[*new_child].id() => {
+                    self.diff_node(old, old_child, new, *new_child, &path.child(segment.clone()));
+                }
+                _ => self.add_subtree(new, *new_child, path),
+            }
+        }
+    }
+
+    fn add_subtree(&mut self, graph: &Graph, root: Handle<Node>, parent: &NodePath) {
+        let mut nodes = Vec::new();
+        let mut parents = Vec::new();
+        let mut handles = Vec::new();
+        let mut stack = vec![(root, std::u32::MAX)];
+        while let Some((handle, parent_index)) = stack.pop() {
+            let index = nodes.len() as u32;
+            // Clone does not copy links to parent and children.
+            nodes.push(graph[handle].clone());
+            parents.push(parent_index);
+            handles.push(handle);
+            for &child in graph[handle].children().iter().rev() {
+                stack.push((child, index));
+            }
+        }
+        self.entries.push(DiffEntry::Added { parent: parent.clone(), nodes, parents, handles });
+    }
+
+    /// Applies diff to graph of scene, nodes are removed with `Scene::remove_node` so
+    /// animations and other entities of removed nodes are removed too. Returns amount of
+    /// entries which were not applied because their nodes were not found.
+    pub fn apply(&self, scene: &mut Scene) -> usize {
+        let root = scene.graph.get_root();
+        self.apply_to_hierarchy(scene, root)
+    }
+
+    /// Applies diff to hierarchy of given node, for example diff from `from_instance` to
+    /// fresh instance of the same resource. Returns amount of entries which were not applied.
+    pub fn apply_to_hierarchy(&self, scene: &mut Scene, root: Handle<Node>) -> usize {
+        let mut missing = 0;
+
+        for entry in self.entries.iter() {
+            match entry {
+                DiffEntry::Modified { path, changes } => {
+                    let handle = path.resolve(&scene.graph, root);
+                    if handle.is_none() {
+                        missing += 1;
+                        continue;
+                    }
+                    for change in changes {
+                        change.apply(&mut scene.graph[handle]);
+                    }
+                }
+                DiffEntry::Added { parent, nodes, parents, handles } => {
+                    let parent = parent.resolve(&scene.graph, root);
+                    if parent.is_none() {
+                        missing += 1;
+                        continue;
+                    }
+                    let mut created: Vec<Handle<Node>> = Vec::with_capacity(nodes.len());
+                    for (node, &parent_index) in nodes.iter().zip(parents.iter()) {
+                        let handle = scene.graph.add_node(node.clone());
+                        let parent = created.get(parent_index as usize).cloned().unwrap_or(parent);
+                        scene.graph.link_nodes(handle, parent);
+                        created.push(handle);
+                    }
+                    let old_new_mapping: HashMap<_, _> = handles.iter().cloned().zip(created.into_iter()).collect();
+                    scene.graph.remap_handles(&old_new_mapping);
+                }
+                DiffEntry::Removed { path } => {
+                    let handle = path.resolve(&scene.graph, root);
+                    if handle.is_none() || handle == root {
+                        missing += 1;
+                        continue;
+                    }
+                    scene.remove_node(handle);
+                }
+            }
+        }
+
+        scene.graph.update_hierachical_data();
+
+        missing
+    }
+}
+
+impl Visit for SceneDiff {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.entries.visit("Entries", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{
+            Scene,
+            node::Node,
+            base::BaseBuilder,
+            diff::SceneDiff,
+        },
+    };
+
+    fn make_level() -> Scene {
+        let mut scene = Scene::new();
+        let room = scene.graph.add_node(Node::Base(BaseBuilder::new().with_name("Room").build()));
+        let lamp = scene.graph.add_node(Node::Base(BaseBuilder::new().with_name("Lamp").build()));
+        let chair = scene.graph.add_node(Node::Base(BaseBuilder::new().with_name("Chair").build()));
+        scene.graph.link_nodes(lamp, room);
+        scene.graph.link_nodes(chair, room);
+        scene
+    }
+
+    #[test]
+    fn test_scene_diff_round_trip() {
+        let source = make_level();
+
+        let mut edited = make_level();
+        let lamp = edited.graph.find_by_name_from_root("Lamp");
+        edited.graph[lamp].local_transform_mut().set_position(Vec3::new(1.0, 2.0, 3.0));
+        let chair = edited.graph.find_by_name_from_root("Chair");
+        edited.remove_node(chair);
+        let table = edited.graph.add_node(Node::Base(BaseBuilder::new().with_name("Table").build()));
+        let cup = edited.graph.add_node(Node::Base(BaseBuilder::new().with_name("Cup").build()));
+        edited.graph.link_nodes(cup, table);
+
+        let diff = SceneDiff::compute(&source, &edited);
+        assert_eq!(diff.entries.len(), 3);
+
+        let mut patched = make_level();
+        assert_eq!(diff.apply(&mut patched), 0);
+        assert!(SceneDiff::compute(&patched, &edited).is_empty());
+
+        let lamp = patched.graph.find_by_name_from_root("Lamp");
+        assert_eq!(patched.graph[lamp].local_transform().position(), Vec3::new(1.0, 2.0, 3.0));
+        assert!(patched.graph.find_by_name_from_root("Chair").is_none());
+        let cup = patched.graph.find_by_name_from_root("Cup");
+        assert_eq!(patched.graph[patched.graph[cup].parent()].name(), "Table");
+    }
+}
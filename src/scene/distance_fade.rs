@@ -0,0 +1,63 @@
+//! Distance-based fading of effects.
+//!
+//! Effects (particle systems, lights) which are far from camera give almost nothing to
+//! final picture, but still cost simulation and rendering time. Distance fade defines
+//! range of distances in which effect smoothly fades out: effect is at full strength
+//! closer than `begin` and is completely disabled farther than `end`. What exactly
+//! "strength" means depends on node, see `ParticleSystem::set_distance_fade` and
+//! `Light::set_distance_fade`.
+
+use crate::core::visitor::{
+    Visit,
+    Visitor,
+    VisitResult,
+};
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DistanceFade {
+    /// Distance at which effect starts to fade out.
+    pub begin: f32,
+    /// Distance at which effect is completely faded out.
+    pub end: f32,
+}
+
+impl Default for DistanceFade {
+    fn default() -> Self {
+        Self {
+            begin: 30.0,
+            end: 50.0,
+        }
+    }
+}
+
+impl DistanceFade {
+    /// Creates new fade range.
+    pub fn new(begin: f32, end: f32) -> Self {
+        Self { begin, end }
+    }
+
+    /// Returns strength of effect in [0; 1] range at given distance. Strength changes
+    /// smoothly (smoothstep), so there is no visible moment when fading starts.
+    pub fn factor(&self, distance: f32) -> f32 {
+        if distance <= self.begin {
+            1.0
+        } else if distance >= self.end {
+            0.0
+        } else {
+            let t = (distance - self.begin) / (self.end - self.begin);
+            1.0 - t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Visit for DistanceFade {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.begin.visit("Begin", visitor)?;
+        self.end.visit("End", visitor)?;
+
+        visitor.leave_region()
+    }
+}
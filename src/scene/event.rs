@@ -0,0 +1,193 @@
+//! Event bus is a typed message queue of scene, it allows subsystems and game code to
+//! exchange messages without knowing about each other.
+//!
+//! Any `'static` type can be a message, each type has its own queue. Messages live until
+//! the beginning of next `Scene::update`, so every reader can read messages of the frame
+//! in any order, and nobody has to clean queues:
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, event::TriggerMessage};
+//!
+//! struct Explosion {
+//!     power: f32
+//! }
+//!
+//! fn game_logic(scene: &mut Scene) {
+//!     scene.events.publish(Explosion { power: 10.0 });
+//!
+//!     for message in scene.events.read::<TriggerMessage>() {
+//!         println!("{:?} - {:?}", message.trigger, message.event);
+//!     }
+//! }
+//! ```
+//!
+//! Scene publishes events of its subsystems to the bus: `TriggerMessage` for each event of
+//! triggers, and `AnimationSignalMessage` for signals of animations if enabled by
+//! `EventBus::set_capture_animation_signals` (signals are moved from animations to the bus,
+//! so `Animation::pop_event` will not return them). Timelines publish
+//! `TimelineSignalMessage` for their signals and `SoundCueMessage` for their sound cues,
+//! game code can publish `SoundCueMessage` too. Projectiles publish `ProjectileHitMessage`
+//! (see `scene::projectile`) for each hit.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+use crate::{
+    core::pool::Handle,
+    scene::{
+        node::Node,
+        trigger::TriggerEvent,
+    },
+    animation::{
+        Animation,
+        timeline::Timeline,
+    },
+};
+
+/// Event of a trigger node.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TriggerMessage {
+    pub trigger: Handle<Node>,
+    pub event: TriggerEvent,
+}
+
+/// Signal of an animation, see `AnimationSignal`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AnimationSignalMessage {
+    pub animation: Handle<Animation>,
+    pub signal_id: u64,
+}
+
+/// Signal of a timeline, see `TimelineSignal`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimelineSignalMessage {
+    pub timeline: Handle<Timeline>,
+    pub signal_id: u64,
+}
+
+/// Request to play sound event of sound bank, at position of given node or as non-spatial
+/// sound if node is `Handle::NONE`. Played by engine after update of scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoundCueMessage {
+    pub event: String,
+    pub node: Handle<Node>,
+}
+
+trait Queue {
+    fn clear(&mut self);
+
+    fn len(&self) -> usize;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> Queue for Vec<T> {
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// See module docs.
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn Queue>>,
+    capture_animation_signals: bool,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds message to the queue of its type.
+    pub fn publish<T: 'static>(&mut self, message: T) {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Vec::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .unwrap()
+            .push(message);
+    }
+
+    /// Returns messages of given type published since last `Scene::update`.
+    pub fn read<T: 'static>(&self) -> &[T] {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .and_then(|queue| queue.as_any().downcast_ref::<Vec<T>>())
+            .map_or(&[][..], |queue| queue.as_slice())
+    }
+
+    /// Takes messages of given type out of the bus, so other readers won't see them.
+    pub fn drain<T: 'static>(&mut self) -> Vec<T> {
+        self.queues
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|queue| queue.as_any_mut().downcast_mut::<Vec<T>>())
+            .map_or_else(Vec::new, std::mem::take)
+    }
+
+    /// Returns total amount of messages of all types.
+    pub fn len(&self) -> usize {
+        self.queues.values().map(|queue| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes messages of all types, allocated memory is kept for next frames.
+    pub fn clear(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.clear();
+        }
+    }
+
+    /// Enables moving of animation signals to the bus as `AnimationSignalMessage`. Disabled
+    /// by default because `Animation::pop_event` will not return signals anymore.
+    pub fn set_capture_animation_signals(&mut self, capture: bool) {
+        self.capture_animation_signals = capture;
+    }
+
+    pub fn is_capture_animation_signals(&self) -> bool {
+        self.capture_animation_signals
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::event::EventBus;
+
+    #[test]
+    fn test_event_bus() {
+        let mut bus = EventBus::new();
+        bus.publish(1u32);
+        bus.publish(2u32);
+        bus.publish("message");
+
+        assert_eq!(bus.read::<u32>(), &[1, 2]);
+        assert_eq!(bus.read::<&str>(), &["message"]);
+        assert!(bus.read::<f32>().is_empty());
+        assert_eq!(bus.len(), 3);
+
+        assert_eq!(bus.drain::<u32>(), vec![1, 2]);
+        assert!(bus.read::<u32>().is_empty());
+
+        bus.clear();
+        assert!(bus.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! Flicker - built-in modulation of intensity of lights.
+//!
+//! Torches, candles, pulsing alarms and broken fluorescent lamps are in almost every game.
+//! Flicker of a light multiplies its intensity by a factor in [1 - amplitude; 1] range which
+//! changes over time, it is advanced during `Scene::update` and affects lighting, light
+//! scattering and lens flare of the light. Kinds of modulation:
+//!
+//! - `Sine` - smooth periodic pulse, `frequency` is amount of pulses per second.
+//! - `Noise` - smooth random wobble.
+//! - `Candle` - random wobble with fast small jitter on top.
+//! - `Fluorescent` - light is mostly steady, but sometimes stutters off and on.
+//!
+//! Noise-based kinds are deterministic: lights with the same seed flicker in sync, give
+//! lights different seeds to make them flicker independently.
+//!
+//! ```no_run
+//! use rg3d::scene::{
+//!     light::{LightBuilder, LightKind, PointLight},
+//!     base::BaseBuilder,
+//!     flicker::LightFlicker,
+//! };
+//!
+//! let torch = LightBuilder::new(LightKind::Point(PointLight::new(5.0)), BaseBuilder::new())
+//!     .with_flicker(LightFlicker::candle().with_seed(42))
+//!     .build();
+//! ```
+
+use crate::{
+    core::visitor::{
+        Visit,
+        Visitor,
+        VisitResult,
+    },
+    utils::noise,
+};
+
+/// Kind of modulation, see module docs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlickerKind {
+    Sine,
+    Noise,
+    Candle,
+    Fluorescent,
+}
+
+impl FlickerKind {
+    fn id(self) -> u32 {
+        match self {
+            FlickerKind::Sine => 0,
+            FlickerKind::Noise => 1,
+            FlickerKind::Candle => 2,
+            FlickerKind::Fluorescent => 3,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(FlickerKind::Sine),
+            1 => Ok(FlickerKind::Noise),
+            2 => Ok(FlickerKind::Candle),
+            3 => Ok(FlickerKind::Fluorescent),
+            _ => Err(format!("Invalid flicker kind {}", id))
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightFlicker {
+    /// Kind of modulation.
+    pub kind: FlickerKind,
+    /// Fraction of intensity which is modulated, in [0; 1] range.
+    pub amplitude: f32,
+    /// Speed of modulation, for `Sine` it is amount of pulses per second.
+    pub frequency: f32,
+    /// Seed of noise-based kinds.
+    pub seed: u32,
+    time: f32,
+}
+
+impl Default for LightFlicker {
+    fn default() -> Self {
+        Self::candle()
+    }
+}
+
+impl LightFlicker {
+    /// Creates new flicker of given kind.
+    pub fn new(kind: FlickerKind, amplitude: f32, frequency: f32) -> Self {
+        Self {
+            kind,
+            amplitude: amplitude.max(0.0).min(1.0),
+            frequency: frequency.max(0.0),
+            seed: 0,
+            time: 0.0,
+        }
+    }
+
+    /// Slow smooth pulse, for alarms and magic lights.
+    pub fn pulse() -> Self {
+        Self::new(FlickerKind::Sine, 0.5, 1.0)
+    }
+
+    /// Candle or torch flame.
+    pub fn candle() -> Self {
+        Self::new(FlickerKind::Candle, 0.3, 4.0)
+    }
+
+    /// Broken fluorescent lamp.
+    pub fn fluorescent() -> Self {
+        Self::new(FlickerKind::Fluorescent, 0.9, 1.5)
+    }
+
+    /// Sets seed of noise, see module docs.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub(in crate) fn update(&mut self, dt: f32) {
+        self.time += dt;
+        // Keep precision of time, noise is periodic enough for nobody to notice.
+        if self.time > 10000.0 {
+            self.time -= 10000.0;
+        }
+    }
+
+    /// Returns current multiplier of intensity in [1 - amplitude; 1] range.
+    pub fn factor(&self) -> f32 {
+        let t = self.time * self.frequency;
+        // Offset by half avoids zeros of noise at integer points.
+        let wobble = |t: f32, seed: u32| noise::perlin_1d(t + 0.5, seed) * 0.5 + 0.5;
+        let modulation = match self.kind {
+            FlickerKind::Sine => 0.5 - 0.5 * (t * 2.0 * std::f32::consts::PI).cos(),
+            FlickerKind::Noise => wobble(t, self.seed),
+            FlickerKind::Candle => 0.7 * wobble(t, self.seed) + 0.3 * wobble(t * 5.3, self.seed.wrapping_add(1)),
+            FlickerKind::Fluorescent => {
+                if noise::perlin_1d(t + 0.5, self.seed) > 0.3 {
+                    // Fast stutter while lamp is failing.
+                    if (t * 12.0).fract() < 0.5 { 1.0 } else { 0.0 }
+                } else {
+                    0.0
+                }
+            }
+        };
+        1.0 - self.amplitude * modulation.max(0.0).min(1.0)
+    }
+}
+
+impl Visit for LightFlicker {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut kind_id = self.kind.id();
+        kind_id.visit("Kind", visitor)?;
+        if visitor.is_reading() {
+            self.kind = FlickerKind::from_id(kind_id)?;
+        }
+        self.amplitude.visit("Amplitude", visitor)?;
+        self.frequency.visit("Frequency", visitor)?;
+        self.seed.visit("Seed", visitor)?;
+        self.time.visit("Time", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::flicker::{LightFlicker, FlickerKind};
+
+    #[test]
+    fn test_flicker_range() {
+        for &kind in [FlickerKind::Sine, FlickerKind::Noise, FlickerKind::Candle, FlickerKind::Fluorescent].iter() {
+            let mut flicker = LightFlicker::new(kind, 0.4, 3.0);
+            for _ in 0..1000 {
+                flicker.update(0.013);
+                let factor = flicker.factor();
+                assert!(factor >= 0.6 - 1.0e-5 && factor <= 1.0 + 1.0e-5);
+            }
+        }
+
+        let mut pulse = LightFlicker::new(FlickerKind::Sine, 0.5, 1.0);
+        assert_eq!(pulse.factor(), 1.0);
+        pulse.update(0.5);
+        assert!((pulse.factor() - 0.5).abs() < 1.0e-5);
+    }
+}
@@ -0,0 +1,410 @@
+//! Force field is an invisible volume which pushes rigid bodies and particles inside of it:
+//! radial push or pull (explosions, black holes), directional wind (fans, air vents),
+//! vortex (tornadoes, whirlpools) or buoyancy (water volumes). Fields are placed and tuned
+//! as nodes, so explosions and fans become data instead of code.
+//!
+//! Influence volume uses the same shapes as triggers, in local space of field node.
+//! Strength can fade out from center to border of volume with falloff.
+//!
+//! ```no_run
+//! use rg3d::scene::{
+//!     base::BaseBuilder,
+//!     force_field::{ForceFieldBuilder, ForceFieldKind},
+//!     node::Node,
+//!     trigger::TriggerShape,
+//!     Scene,
+//! };
+//!
+//! fn create_explosion(scene: &mut Scene) {
+//!     let explosion = ForceFieldBuilder::new(BaseBuilder::new().with_lifetime(0.1))
+//!         .with_kind(ForceFieldKind::Radial { strength: 300.0 })
+//!         .with_shape(TriggerShape::Sphere { radius: 5.0 })
+//!         .with_falloff(1.0)
+//!         .build();
+//!     scene.graph.add_node(Node::ForceField(explosion));
+//! }
+//! ```
+//!
+//! Fields are applied by scene to rigid bodies bound to nodes (see `PhysicsBinder`) before
+//! each physics step, and by graph to particle systems if `affects_particles` is set.
+//! Bodies are treated as points at their positions, mass is not taken into account, so
+//! strength is acceleration in units per second squared. Buoyancy field ignores size of
+//! bodies, use water volumes (see `scene::water`) for plausible floating objects.
+
+use std::ops::{Deref, DerefMut};
+use crate::{
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        trigger::TriggerShape,
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        pool::Handle,
+        math::{
+            vec3::Vec3,
+            mat4::Mat4,
+        },
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+/// How force field pushes things inside of it. Directions are in local space of field node.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ForceFieldKind {
+    /// Pushes away from center of field, negative strength pulls towards center.
+    Radial {
+        strength: f32,
+    },
+    /// Pushes along given direction, like wind of a fan.
+    Directional {
+        direction: Vec3,
+        strength: f32,
+    },
+    /// Spins around local Y axis of field and pulls towards the axis.
+    Vortex {
+        strength: f32,
+        pull: f32,
+    },
+    /// Pushes up against gravity, push is proportional to depth below top of volume and
+    /// reaches `strength` at depth of `depth`. `drag` slows down everything inside.
+    Buoyancy {
+        strength: f32,
+        depth: f32,
+        drag: f32,
+    },
+}
+
+impl Default for ForceFieldKind {
+    fn default() -> Self {
+        ForceFieldKind::Radial { strength: 10.0 }
+    }
+}
+
+impl ForceFieldKind {
+    fn id(&self) -> u8 {
+        match self {
+            ForceFieldKind::Radial { .. } => 0,
+            ForceFieldKind::Directional { .. } => 1,
+            ForceFieldKind::Vortex { .. } => 2,
+            ForceFieldKind::Buoyancy { .. } => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, String> {
+        match id {
+            0 => Ok(ForceFieldKind::Radial { strength: 0.0 }),
+            1 => Ok(ForceFieldKind::Directional { direction: Default::default(), strength: 0.0 }),
+            2 => Ok(ForceFieldKind::Vortex { strength: 0.0, pull: 0.0 }),
+            3 => Ok(ForceFieldKind::Buoyancy { strength: 0.0, depth: 0.0, drag: 0.0 }),
+            _ => Err(format!("Invalid force field kind {}", id))
+        }
+    }
+}
+
+impl Visit for ForceFieldKind {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = ForceFieldKind::from_id(id)?;
+        }
+        match self {
+            ForceFieldKind::Radial { strength } => strength.visit("Strength", visitor)?,
+            ForceFieldKind::Directional { direction, strength } => {
+                direction.visit("Direction", visitor)?;
+                strength.visit("Strength", visitor)?;
+            }
+            ForceFieldKind::Vortex { strength, pull } => {
+                strength.visit("Strength", visitor)?;
+                pull.visit("Pull", visitor)?;
+            }
+            ForceFieldKind::Buoyancy { strength, depth, drag } => {
+                strength.visit("Strength", visitor)?;
+                depth.visit("Depth", visitor)?;
+                drag.visit("Drag", visitor)?;
+            }
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct ForceField {
+    base: Base,
+    kind: ForceFieldKind,
+    shape: TriggerShape,
+    falloff: f32,
+    affects_bodies: bool,
+    affects_particles: bool,
+}
+
+impl Deref for ForceField {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for ForceField {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for ForceField {
+    fn default() -> Self {
+        ForceFieldBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl ForceField {
+    pub fn set_kind(&mut self, kind: ForceFieldKind) {
+        self.kind = kind;
+    }
+
+    pub fn kind(&self) -> ForceFieldKind {
+        self.kind
+    }
+
+    pub fn set_shape(&mut self, shape: TriggerShape) {
+        self.shape = shape;
+    }
+
+    pub fn shape(&self) -> TriggerShape {
+        self.shape
+    }
+
+    /// Sets how much strength fades from center to border of volume: 0 - constant
+    /// strength, 1 - strength fades linearly to zero at the border.
+    pub fn set_falloff(&mut self, falloff: f32) {
+        self.falloff = falloff.max(0.0).min(1.0);
+    }
+
+    pub fn falloff(&self) -> f32 {
+        self.falloff
+    }
+
+    pub fn set_affects_bodies(&mut self, affects_bodies: bool) {
+        self.affects_bodies = affects_bodies;
+    }
+
+    pub fn affects_bodies(&self) -> bool {
+        self.affects_bodies
+    }
+
+    pub fn set_affects_particles(&mut self, affects_particles: bool) {
+        self.affects_particles = affects_particles;
+    }
+
+    pub fn affects_particles(&self) -> bool {
+        self.affects_particles
+    }
+
+    /// Returns copy of field settings with its current global transform, which can compute
+    /// accelerations without access to graph. `None` if transform is degenerate.
+    pub fn sample(&self) -> Option<ForceFieldSample> {
+        let transform = self.global_transform();
+        transform.inverse().ok().map(|inverse_transform| ForceFieldSample {
+            kind: self.kind,
+            shape: self.shape,
+            falloff: self.falloff,
+            transform,
+            inverse_transform,
+        })
+    }
+
+    /// Returns acceleration (in units per second squared, world space) of an object at given
+    /// world position which moves with given velocity (in units per second).
+    pub fn acceleration_at(&self, position: Vec3, velocity: Vec3) -> Vec3 {
+        self.sample().map_or(Vec3::ZERO, |sample| sample.acceleration_at(position, velocity))
+    }
+}
+
+/// Snapshot of force field, see [`ForceField::sample`].
+#[derive(Copy, Clone, Debug)]
+pub struct ForceFieldSample {
+    kind: ForceFieldKind,
+    shape: TriggerShape,
+    falloff: f32,
+    transform: Mat4,
+    inverse_transform: Mat4,
+}
+
+impl ForceFieldSample {
+    /// Same as [`ForceField::acceleration_at`].
+    pub fn acceleration_at(&self, position: Vec3, velocity: Vec3) -> Vec3 {
+        let local = self.inverse_transform.transform_vector(position);
+
+        // Distance from center relative to size of volume, 1 is at the border.
+        let distance = match self.shape {
+            TriggerShape::Box { half_extents } => {
+                let axis = |p: f32, e: f32| if e > 0.0 { p.abs() / e } else { std::f32::MAX };
+                axis(local.x, half_extents.x)
+                    .max(axis(local.y, half_extents.y))
+                    .max(axis(local.z, half_extents.z))
+            }
+            TriggerShape::Sphere { radius } => if radius > 0.0 { local.len() / radius } else { std::f32::MAX },
+        };
+        if distance > 1.0 {
+            return Vec3::ZERO;
+        }
+        let k = 1.0 - self.falloff * distance;
+
+        let to_world = |direction: Vec3| self.transform.basis().transform_vector(direction).normalized().unwrap_or(Vec3::ZERO);
+        let center = self.transform.position();
+
+        match self.kind {
+            ForceFieldKind::Radial { strength } => {
+                (position - center).normalized().unwrap_or(Vec3::ZERO).scale(strength * k)
+            }
+            ForceFieldKind::Directional { direction, strength } => {
+                to_world(direction).scale(strength * k)
+            }
+            ForceFieldKind::Vortex { strength, pull } => {
+                let axis = to_world(Vec3::new(0.0, 1.0, 0.0));
+                let offset = position - center;
+                let radial = offset - axis.scale(offset.dot(&axis));
+                match radial.normalized() {
+                    Some(radial) => (axis.cross(&radial).scale(strength) - radial.scale(pull)).scale(k),
+                    None => Vec3::ZERO,
+                }
+            }
+            ForceFieldKind::Buoyancy { strength, depth, drag } => {
+                // Depth is measured from top of volume along world up axis.
+                let top = match self.shape {
+                    TriggerShape::Box { half_extents } => half_extents.y,
+                    TriggerShape::Sphere { radius } => radius,
+                };
+                let surface = self.transform.transform_vector(Vec3::new(0.0, top, 0.0)).y;
+                let submersion = if depth > 0.0 { ((surface - position.y) / depth).max(0.0).min(1.0) } else { 1.0 };
+                Vec3::new(0.0, strength * submersion * k, 0.0) - velocity.scale(drag)
+            }
+        }
+    }
+}
+
+impl Visit for ForceField {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.kind.visit("Kind", visitor)?;
+        self.shape.visit("Shape", visitor)?;
+        self.falloff.visit("Falloff", visitor)?;
+        self.affects_bodies.visit("AffectsBodies", visitor)?;
+        self.affects_particles.visit("AffectsParticles", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct ForceFieldBuilder {
+    base_builder: BaseBuilder,
+    kind: Option<ForceFieldKind>,
+    shape: Option<TriggerShape>,
+    falloff: Option<f32>,
+    affects_bodies: Option<bool>,
+    affects_particles: Option<bool>,
+}
+
+impl ForceFieldBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            kind: None,
+            shape: None,
+            falloff: None,
+            affects_bodies: None,
+            affects_particles: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: ForceFieldKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn with_shape(mut self, shape: TriggerShape) -> Self {
+        self.shape = Some(shape);
+        self
+    }
+
+    pub fn with_falloff(mut self, falloff: f32) -> Self {
+        self.falloff = Some(falloff);
+        self
+    }
+
+    /// Sets whether field pushes rigid bodies bound to nodes, true by default.
+    pub fn with_affects_bodies(mut self, affects_bodies: bool) -> Self {
+        self.affects_bodies = Some(affects_bodies);
+        self
+    }
+
+    /// Sets whether field pushes particles, false by default.
+    pub fn with_affects_particles(mut self, affects_particles: bool) -> Self {
+        self.affects_particles = Some(affects_particles);
+        self
+    }
+
+    pub fn build(self) -> ForceField {
+        ForceField {
+            base: self.base_builder.build(),
+            kind: self.kind.unwrap_or_default(),
+            shape: self.shape.unwrap_or_default(),
+            falloff: self.falloff.unwrap_or(0.0).max(0.0).min(1.0),
+            affects_bodies: self.affects_bodies.unwrap_or(true),
+            affects_particles: self.affects_particles.unwrap_or(false),
+        }
+    }
+
+    /// Creates new force field node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::ForceField(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{
+            base::BaseBuilder,
+            force_field::{ForceFieldBuilder, ForceFieldKind},
+            graph::Graph,
+            node::Node,
+            trigger::TriggerShape,
+        },
+    };
+
+    #[test]
+    fn test_force_field_acceleration() {
+        let mut graph = Graph::new();
+        let field = graph.add_node(Node::ForceField(ForceFieldBuilder::new(BaseBuilder::new())
+            .with_kind(ForceFieldKind::Radial { strength: 10.0 })
+            .with_shape(TriggerShape::Sphere { radius: 2.0 })
+            .with_falloff(1.0)
+            .build()));
+        graph[field].local_transform_mut().set_position(Vec3::new(1.0, 0.0, 0.0));
+        graph.update_hierachical_data();
+
+        let field = graph[field].as_force_field();
+        assert_eq!(field.acceleration_at(Vec3::new(2.0, 0.0, 0.0), Vec3::ZERO), Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(field.acceleration_at(Vec3::new(4.0, 0.0, 0.0), Vec3::ZERO), Vec3::ZERO);
+    }
+}
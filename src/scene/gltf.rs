@@ -0,0 +1,449 @@
+//! Export of scenes to glTF 2.0.
+//!
+//! Content which was assembled or generated in the engine (scattered props, CSG geometry,
+//! voxel terrain, etc.) can be exported to glTF and opened in DCC tools or other engines.
+//! Exporter writes `.gltf` file with description of scene and `.bin` file with geometry
+//! next to it:
+//!
+//! ```no_run
+//! use rg3d::scene::{
+//!     Scene,
+//!     gltf::{self, GltfExportOptions},
+//! };
+//!
+//! fn export_level(scene: &Scene) {
+//!     let options = GltfExportOptions {
+//!         baked_lighting: true,
+//!         ..Default::default()
+//!     };
+//!     gltf::export(scene, "level.gltf", &options).unwrap();
+//! }
+//! ```
+//!
+//! Every node of graph becomes glTF node with the same name and local transform, meshes
+//! get one primitive per surface. Surfaces which share data share accessors too, so
+//! instanced geometry is not duplicated. Materials are exported as metallic-roughness
+//! materials with color, diffuse and normal textures and alpha mode of surface; texture
+//! images are referenced by their paths as is, so textures must be reachable from location
+//! of exported file. Procedural textures without path are skipped.
+//!
+//! Baked lighting is exported as vertex colors (`COLOR_0`) and second texture coordinates
+//! (`TEXCOORD_1`), lightmaps are not part of scene and must be assigned by receiving tool.
+//! Skinned meshes are exported in bind pose without skin, lights, cameras and other nodes
+//! are exported as empty nodes.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use crate::{
+    core::{
+        color::Color,
+        pool::Handle,
+    },
+    scene::{
+        Scene,
+        node::Node,
+        graph::Graph,
+    },
+    renderer::surface::{Surface, SurfaceSharedData},
+    resource::{
+        texture::Texture,
+        material::BlendMode,
+    },
+    utils::json::JsonValue,
+};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Defines what is written to exported file.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GltfExportOptions {
+    /// Export vertex colors and second texture coordinates, which hold baked lighting.
+    pub baked_lighting: bool,
+    /// Skip invisible nodes together with their descendants.
+    pub skip_invisible: bool,
+}
+
+/// Writes scene to `.gltf` file at given path and its geometry to `.bin` file with the
+/// same name.
+pub fn export<P: AsRef<Path>>(scene: &Scene, path: P, options: &GltfExportOptions) -> io::Result<()> {
+    let path = path.as_ref();
+    let bin_path = path.with_extension("bin");
+    let bin_uri = bin_path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let (document, buffer) = build(&scene.graph, &bin_uri, options);
+
+    File::create(&bin_path)?.write_all(&buffer)?;
+    let mut file = io::BufWriter::new(File::create(path)?);
+    write!(file, "{}", document)?;
+    file.flush()
+}
+
+fn number(n: f32) -> JsonValue {
+    JsonValue::Number(f64::from(n))
+}
+
+fn index(i: usize) -> JsonValue {
+    JsonValue::Number(i as f64)
+}
+
+fn numbers(values: &[f32]) -> JsonValue {
+    JsonValue::Array(values.iter().map(|v| number(*v)).collect())
+}
+
+fn object(members: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(members.into_iter().map(|(name, value)| (name.to_owned(), value)).collect())
+}
+
+fn color_factor(color: Color) -> JsonValue {
+    numbers(&[
+        f32::from(color.r) / 255.0,
+        f32::from(color.g) / 255.0,
+        f32::from(color.b) / 255.0,
+        f32::from(color.a) / 255.0,
+    ])
+}
+
+/// Accessors of geometry of one surface data.
+#[derive(Clone)]
+struct GeometryAccessors {
+    attributes: Vec<(&'static str, usize)>,
+    indices: usize,
+}
+
+#[derive(Default)]
+struct Exporter {
+    options: GltfExportOptions,
+    buffer: Vec<u8>,
+    buffer_views: Vec<JsonValue>,
+    accessors: Vec<JsonValue>,
+    nodes: Vec<JsonValue>,
+    meshes: Vec<JsonValue>,
+    materials: Vec<JsonValue>,
+    textures: Vec<JsonValue>,
+    images: Vec<JsonValue>,
+    /// Surface data (by address) to its accessors.
+    geometry: HashMap<usize, GeometryAccessors>,
+    /// Text of material to its index, equal materials are written once.
+    material_indices: HashMap<String, usize>,
+    /// Path of image to index of texture.
+    texture_indices: HashMap<String, usize>,
+}
+
+impl Exporter {
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        // Accessors of floats and integers must be aligned to 4 bytes.
+        while self.buffer.len() % 4 != 0 {
+            self.buffer.push(0);
+        }
+        self.buffer_views.push(object(vec![
+            ("buffer", index(0)),
+            ("byteOffset", index(self.buffer.len())),
+            ("byteLength", index(bytes.len())),
+            ("target", JsonValue::Number(f64::from(target))),
+        ]));
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    fn push_floats(&mut self, values: &[f32], kind: &str, components: usize, bounds: bool) -> usize {
+        let mut bytes = Vec::with_capacity(values.len() * 4);
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let view = self.push_view(&bytes, ARRAY_BUFFER);
+        let mut accessor = vec![
+            ("bufferView", index(view)),
+            ("componentType", JsonValue::Number(f64::from(FLOAT))),
+            ("count", index(values.len() / components)),
+            ("type", JsonValue::String(kind.to_owned())),
+        ];
+        // Positions must have bounds.
+        if bounds {
+            let mut min = vec![std::f32::MAX; components];
+            let mut max = vec![-std::f32::MAX; components];
+            for element in values.chunks(components) {
+                for (i, value) in element.iter().enumerate() {
+                    min[i] = min[i].min(*value);
+                    max[i] = max[i].max(*value);
+                }
+            }
+            accessor.push(("min", numbers(&min)));
+            accessor.push(("max", numbers(&max)));
+        }
+        self.accessors.push(object(accessor));
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let mut bytes = Vec::with_capacity(indices.len() * 4);
+        for i in indices {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        let view = self.push_view(&bytes, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(object(vec![
+            ("bufferView", index(view)),
+            ("componentType", JsonValue::Number(f64::from(UNSIGNED_INT))),
+            ("count", index(indices.len())),
+            ("type", JsonValue::String("SCALAR".to_owned())),
+        ]));
+        self.accessors.len() - 1
+    }
+
+    fn geometry(&mut self, data: &Arc<Mutex<SurfaceSharedData>>) -> Option<GeometryAccessors> {
+        let key = &**data as *const Mutex<SurfaceSharedData> as usize;
+        if let Some(accessors) = self.geometry.get(&key) {
+            return Some(accessors.clone());
+        }
+
+        let data = data.lock().unwrap();
+        let vertices = data.get_vertices();
+        if vertices.is_empty() || data.triangles().is_empty() {
+            return None;
+        }
+
+        let mut positions = Vec::with_capacity(vertices.len() * 3);
+        let mut normals = Vec::with_capacity(vertices.len() * 3);
+        let mut tex_coords = Vec::with_capacity(vertices.len() * 2);
+        for v in vertices {
+            positions.extend_from_slice(&[v.position.x, v.position.y, v.position.z]);
+            normals.extend_from_slice(&[v.normal.x, v.normal.y, v.normal.z]);
+            // Texture coordinates of engine already have origin at top left corner, as in
+            // glTF (see FBX loader), so they're written as is.
+            tex_coords.extend_from_slice(&[v.tex_coord.x, v.tex_coord.y]);
+        }
+
+        let mut attributes = vec![
+            ("POSITION", self.push_floats(&positions, "VEC3", 3, true)),
+            ("NORMAL", self.push_floats(&normals, "VEC3", 3, false)),
+            ("TEXCOORD_0", self.push_floats(&tex_coords, "VEC2", 2, false)),
+        ];
+
+        if self.options.baked_lighting {
+            let mut second_tex_coords = Vec::with_capacity(vertices.len() * 2);
+            let mut colors = Vec::with_capacity(vertices.len() * 4);
+            for v in vertices {
+                second_tex_coords.extend_from_slice(&[v.second_tex_coord.x, v.second_tex_coord.y]);
+                colors.extend_from_slice(&[
+                    f32::from(v.color.r) / 255.0,
+                    f32::from(v.color.g) / 255.0,
+                    f32::from(v.color.b) / 255.0,
+                    f32::from(v.color.a) / 255.0,
+                ]);
+            }
+            attributes.push(("TEXCOORD_1", self.push_floats(&second_tex_coords, "VEC2", 2, false)));
+            attributes.push(("COLOR_0", self.push_floats(&colors, "VEC4", 4, false)));
+        }
+
+        let indices = data.triangles()
+            .iter()
+            .flat_map(|triangle| triangle.0.iter().cloned())
+            .collect::<Vec<_>>();
+        let indices = self.push_indices(&indices);
+
+        let accessors = GeometryAccessors { attributes, indices };
+        self.geometry.insert(key, accessors.clone());
+        Some(accessors)
+    }
+
+    fn texture(&mut self, texture: Option<Arc<Mutex<Texture>>>) -> Option<usize> {
+        let texture = texture?;
+        let uri = texture.lock().unwrap().path().to_string_lossy().replace('\\', "/");
+        if uri.is_empty() {
+            return None;
+        }
+        if let Some(index) = self.texture_indices.get(&uri) {
+            return Some(*index);
+        }
+        self.images.push(object(vec![("uri", JsonValue::String(uri.clone()))]));
+        self.textures.push(object(vec![("source", index(self.images.len() - 1))]));
+        let texture_index = self.textures.len() - 1;
+        self.texture_indices.insert(uri, texture_index);
+        Some(texture_index)
+    }
+
+    fn material(&mut self, surface: &Surface) -> usize {
+        let mut pbr = vec![
+            ("baseColorFactor", color_factor(surface.color())),
+            ("metallicFactor", number(0.0)),
+            ("roughnessFactor", number(1.0)),
+        ];
+        if let Some(texture) = self.texture(surface.get_diffuse_texture()) {
+            pbr.push(("baseColorTexture", object(vec![("index", index(texture))])));
+        }
+        let mut material = vec![("pbrMetallicRoughness", object(pbr))];
+        if let Some(texture) = self.texture(surface.get_normal_texture()) {
+            material.push(("normalTexture", object(vec![("index", index(texture))])));
+        }
+        match surface.blend_mode() {
+            BlendMode::Opaque => material.push(("alphaMode", JsonValue::String("OPAQUE".to_owned()))),
+            BlendMode::AlphaTest => {
+                material.push(("alphaMode", JsonValue::String("MASK".to_owned())));
+                material.push(("alphaCutoff", number(surface.alpha_cutoff())));
+            }
+        }
+        let material = object(material);
+
+        let key = material.to_string();
+        if let Some(index) = self.material_indices.get(&key) {
+            return *index;
+        }
+        self.materials.push(material);
+        let material_index = self.materials.len() - 1;
+        self.material_indices.insert(key, material_index);
+        material_index
+    }
+
+    fn mesh(&mut self, surfaces: &[Surface]) -> Option<usize> {
+        let mut primitives = Vec::new();
+        for surface in surfaces {
+            if let Some(geometry) = self.geometry(&surface.get_data()) {
+                let attributes = geometry.attributes
+                    .iter()
+                    .map(|(name, accessor)| (*name, index(*accessor)))
+                    .collect();
+                primitives.push(object(vec![
+                    ("attributes", object(attributes)),
+                    ("indices", index(geometry.indices)),
+                    ("material", index(self.material(surface))),
+                ]));
+            }
+        }
+        if primitives.is_empty() {
+            return None;
+        }
+        self.meshes.push(object(vec![("primitives", JsonValue::Array(primitives))]));
+        Some(self.meshes.len() - 1)
+    }
+
+    /// Writes node with its descendants and returns its index.
+    fn node(&mut self, graph: &Graph, handle: Handle<Node>) -> Option<usize> {
+        let node = &graph[handle];
+        if self.options.skip_invisible && !node.visibility() {
+            return None;
+        }
+
+        // Reserve index, so parent goes before its children.
+        let node_index = self.nodes.len();
+        self.nodes.push(JsonValue::Null);
+
+        let mut members = vec![
+            ("name", JsonValue::String(node.name().to_owned())),
+            // Matrix instead of TRS, because local transform of engine has pivots and offsets.
+            ("matrix", numbers(&node.local_transform().matrix().f)),
+        ];
+        if let Node::Mesh(mesh) = node {
+            if let Some(mesh) = self.mesh(mesh.surfaces()) {
+                members.push(("mesh", index(mesh)));
+            }
+        }
+        let children = node.children()
+            .iter()
+            .filter_map(|child| self.node(graph, *child))
+            .map(index)
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            members.push(("children", JsonValue::Array(children)));
+        }
+
+        self.nodes[node_index] = object(members);
+        Some(node_index)
+    }
+}
+
+/// Builds glTF document and contents of its binary buffer. Root of graph is not exported,
+/// its children become root nodes of glTF scene.
+fn build(graph: &Graph, bin_uri: &str, options: &GltfExportOptions) -> (JsonValue, Vec<u8>) {
+    let mut exporter = Exporter {
+        options: *options,
+        ..Default::default()
+    };
+
+    let roots = graph[graph.get_root()].children()
+        .iter()
+        .filter_map(|child| exporter.node(graph, *child))
+        .map(index)
+        .collect::<Vec<_>>();
+
+    let mut members = vec![
+        ("asset", object(vec![
+            ("version", JsonValue::String("2.0".to_owned())),
+            ("generator", JsonValue::String("rg3d".to_owned())),
+        ])),
+        ("scene", index(0)),
+        ("scenes", JsonValue::Array(vec![object(vec![("nodes", JsonValue::Array(roots))])])),
+        ("nodes", JsonValue::Array(exporter.nodes)),
+    ];
+    // glTF forbids empty arrays.
+    for (name, items) in vec![
+        ("meshes", exporter.meshes),
+        ("materials", exporter.materials),
+        ("textures", exporter.textures),
+        ("images", exporter.images),
+        ("accessors", exporter.accessors),
+        ("bufferViews", exporter.buffer_views),
+    ] {
+        if !items.is_empty() {
+            members.push((name, JsonValue::Array(items)));
+        }
+    }
+    if !exporter.buffer.is_empty() {
+        members.push(("buffers", JsonValue::Array(vec![object(vec![
+            ("uri", JsonValue::String(bin_uri.to_owned())),
+            ("byteLength", index(exporter.buffer.len())),
+        ])])));
+    }
+
+    (object(members), exporter.buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use crate::{
+        renderer::surface::{Surface, SurfaceSharedData},
+        scene::{
+            Scene,
+            node::Node,
+            base::BaseBuilder,
+            mesh::MeshBuilder,
+            gltf::{self, GltfExportOptions},
+        },
+        utils::json::JsonValue,
+    };
+
+    #[test]
+    fn test_gltf_export() {
+        let mut scene = Scene::new();
+        let data = Arc::new(Mutex::new(SurfaceSharedData::make_cube()));
+        let room = scene.graph.add_node(Node::Base(BaseBuilder::new().with_name("Room").build()));
+        for name in &["BoxA", "BoxB"] {
+            let mesh = scene.graph.add_node(Node::Mesh(MeshBuilder::new(BaseBuilder::new().with_name(name))
+                .with_surfaces(vec![Surface::new(data.clone())])
+                .build()));
+            scene.graph.link_nodes(mesh, room);
+        }
+
+        let options = GltfExportOptions { baked_lighting: true, ..Default::default() };
+        let (document, buffer) = gltf::build(&scene.graph, "level.bin", &options);
+
+        // Output must be valid JSON.
+        let document = JsonValue::parse(&document.to_string()).unwrap();
+        assert_eq!(document.get("nodes").unwrap().as_array().unwrap().len(), 3);
+        assert_eq!(document.get("meshes").unwrap().as_array().unwrap().len(), 2);
+        assert_eq!(document.get("materials").unwrap().as_array().unwrap().len(), 1);
+        // Shared data is written once: 5 attributes and indices.
+        assert_eq!(document.get("accessors").unwrap().as_array().unwrap().len(), 6);
+        let buffers = document.get("buffers").unwrap().as_array().unwrap();
+        assert_eq!(buffers[0].get("byteLength").unwrap().as_f64(), Some(buffer.len() as f64));
+    }
+}
@@ -21,16 +21,39 @@
 //! is global transform calculation - it allows you to produce complex movements
 //! just by linking nodes to each other. Good example of this is skeleton which
 //! is used in skinning (animating 3d model by set of bones).
+//!
+//! # Spatial queries
+//!
+//! Graph can find nodes in a sphere, box or camera frustum, see [`Graph::find_in_sphere`].
+//! Queries are answered by a spatial index if it is enabled, otherwise every node is tested.
 
 #![warn(missing_docs)]
 
 use std::{
+    cell::RefCell,
     collections::HashMap,
+    sync::{Arc, Mutex},
     ops::{Index, IndexMut},
+    fmt::{self, Display, Formatter, Write},
 };
 use crate::{
     utils::log::Log,
-    scene::node::Node,
+    resource::model::Model,
+    scene::{
+        node::Node,
+        mesh::Mesh,
+        light::Light,
+        sprite::Sprite,
+        particle_system::ParticleSystem,
+        wind::Wind,
+        base,
+        camera::Camera,
+        spatial_index::{
+            self,
+            SpatialIndex,
+            Entry,
+        },
+    },
     core::{
         pool::{
             Handle,
@@ -44,6 +67,9 @@ use crate::{
             mat4::Mat4,
             vec3::Vec3,
             vec2::Vec2,
+            vec4::Vec4,
+            aabb::AxisAlignedBoundingBox,
+            frustum::Frustum,
         },
         visitor::{
             Visit,
@@ -53,11 +79,95 @@ use crate::{
     },
 };
 
+/// Problem found by [`Graph::validate`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GraphIssue {
+    /// Node has handle to parent which is not valid.
+    DeadParent {
+        /// Handle of broken node.
+        node: Handle<Node>,
+        /// Invalid handle of parent.
+        parent: Handle<Node>,
+    },
+    /// Node has handle to child which is not valid.
+    DeadChild {
+        /// Handle of broken node.
+        node: Handle<Node>,
+        /// Invalid handle of child.
+        child: Handle<Node>,
+    },
+    /// Node is in list of children of other node, but its parent handle points elsewhere.
+    ParentMismatch {
+        /// Handle of node which lists child.
+        node: Handle<Node>,
+        /// Handle of child which has other parent.
+        child: Handle<Node>,
+    },
+    /// Node has no parent and it is not root, so it is unreachable from root and won't be
+    /// updated.
+    Orphan {
+        /// Handle of orphaned node.
+        node: Handle<Node>,
+    },
+    /// Node is its own ancestor.
+    Cycle {
+        /// Handle of node in cycle.
+        node: Handle<Node>,
+    },
+    /// Local or global transform of node contains NaN or infinity.
+    InvalidTransform {
+        /// Handle of broken node.
+        node: Handle<Node>,
+    },
+    /// Surface of mesh references bone which is not valid.
+    DeadBone {
+        /// Handle of mesh.
+        mesh: Handle<Node>,
+        /// Index of surface in mesh.
+        surface: usize,
+        /// Invalid handle of bone.
+        bone: Handle<Node>,
+    },
+}
+
+impl Display for GraphIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphIssue::DeadParent { node, parent } =>
+                write!(f, "node {:?} has dead parent {:?}", node, parent),
+            GraphIssue::DeadChild { node, child } =>
+                write!(f, "node {:?} has dead child {:?}", node, child),
+            GraphIssue::ParentMismatch { node, child } =>
+                write!(f, "node {:?} lists child {:?} which has other parent", node, child),
+            GraphIssue::Orphan { node } =>
+                write!(f, "node {:?} is not attached to graph", node),
+            GraphIssue::Cycle { node } =>
+                write!(f, "node {:?} is its own ancestor", node),
+            GraphIssue::InvalidTransform { node } =>
+                write!(f, "node {:?} has NaN or infinite transform", node),
+            GraphIssue::DeadBone { mesh, surface, bone } =>
+                write!(f, "surface {} of mesh {:?} references dead bone {:?}", surface, mesh, bone),
+        }
+    }
+}
+
+/// Cache of name-to-handle pairs, see [`Graph::set_name_index_enabled`].
+#[derive(Default)]
+struct NameIndex {
+    map: HashMap<String, Handle<Node>>,
+    valid: bool,
+    name_generation: usize,
+}
+
 /// See module docs.
 pub struct Graph {
     root: Handle<Node>,
     pool: Pool<Node>,
     stack: Vec<Handle<Node>>,
+    hierarchy_stack: Vec<(Handle<Node>, bool)>,
+    wind: Wind,
+    name_index: Option<RefCell<NameIndex>>,
+    spatial_index: Option<RefCell<SpatialIndex>>,
 }
 
 impl Default for Graph {
@@ -66,6 +176,30 @@ impl Default for Graph {
             root: Handle::NONE,
             pool: Pool::new(),
             stack: Vec::new(),
+            hierarchy_stack: Vec::new(),
+            wind: Default::default(),
+            name_index: None,
+            spatial_index: None,
+        }
+    }
+}
+
+/// Defines pair of iterators over nodes of one kind, which give (handle; node) pairs with
+/// node already cast to its actual type.
+macro_rules! define_typed_iter {
+    ($iter:ident, $iter_mut:ident, $kind:ident, $result:ty) => {
+        pub fn $iter(&self) -> impl Iterator<Item=(Handle<Node>, &$result)> {
+            self.pool.pair_iter().filter_map(|(handle, node)| match node {
+                Node::$kind(value) => Some((handle, value)),
+                _ => None
+            })
+        }
+
+        pub fn $iter_mut(&mut self) -> impl Iterator<Item=(Handle<Node>, &mut $result)> {
+            self.pool.pair_iter_mut().filter_map(|(handle, node)| match node {
+                Node::$kind(value) => Some((handle, value)),
+                _ => None
+            })
         }
     }
 }
@@ -79,18 +213,29 @@ impl Graph {
         let root = pool.spawn(root);
         Self {
             stack: Vec::new(),
+            hierarchy_stack: Vec::new(),
             root,
             pool,
+            wind: Default::default(),
+            name_index: None,
+            spatial_index: None,
         }
     }
 
     /// Adds new node to the graph. Node will be transferred into implementation-defined
     /// storage and you'll get a handle to the node. Node will be automatically attached
     /// to root node of graph, it is required because graph can contain only one root.
+    /// Children declared by `BaseBuilder::with_children` are linked to the node.
     #[inline]
-    pub fn add_node(&mut self, node: Node) -> Handle<Node> {
+    pub fn add_node(&mut self, mut node: Node) -> Handle<Node> {
+        // Declared children are still linked with their previous parents, so they must be
+        // re-linked to become real children of node.
+        let children = std::mem::take(&mut node.children);
         let handle = self.pool.spawn(node);
         self.link_nodes(handle, self.root);
+        for child in children {
+            self.link_nodes(child, handle);
+        }
         handle
     }
 
@@ -124,6 +269,8 @@ impl Graph {
     #[inline]
     pub fn remove_node(&mut self, node_handle: Handle<Node>) {
         self.unlink_internal(node_handle);
+        self.invalidate_name_index();
+        self.invalidate_spatial_index();
 
         self.stack.clear();
         self.stack.push(node_handle);
@@ -152,10 +299,26 @@ impl Graph {
     #[inline]
     pub fn link_nodes(&mut self, child: Handle<Node>, parent: Handle<Node>) {
         self.unlink_internal(child);
+        self.invalidate_name_index();
+        self.invalidate_spatial_index();
         self.pool[child].parent = parent;
+        self.pool[child].transform_changed = true;
         self.pool[parent].children.push(child);
     }
 
+    /// Links specified child with specified parent and recalculates local transform of child
+    /// so it stays at the same place in world space, for example when a character picks up
+    /// an item. Uses global transforms calculated on last update, so call
+    /// [`update_hierachical_data`](Graph::update_hierachical_data) first if nodes were moved
+    /// since then.
+    pub fn link_nodes_keep_world_transform(&mut self, child: Handle<Node>, parent: Handle<Node>) {
+        let child_global = self.pool[child].global_transform();
+        let parent_global = self.pool[parent].global_transform();
+        let local = parent_global.inverse().unwrap_or(Mat4::IDENTITY) * child_global;
+        self.link_nodes(child, parent);
+        self.pool[child].local_transform_mut().set_matrix(local);
+    }
+
     /// Unlinks specified node from its parent and attaches it to root graph node.
     #[inline]
     pub fn unlink_node(&mut self, node_handle: Handle<Node>) {
@@ -181,9 +344,174 @@ impl Graph {
         Handle::NONE
     }
 
+    /// Enables or disables name index. Searching a node by name requires traversal of whole
+    /// hierarchy, which is slow in large scenes if it is done every frame. Name index is a
+    /// hash map of names to nodes, it is used by [`find_by_name_from_root`](Graph::find_by_name_from_root)
+    /// and [`find_by_name`](Graph::find_by_name) when search starts from root. Index is rebuilt
+    /// lazily on first search after nodes were added, removed, linked or renamed. Index is
+    /// disabled by default.
+    pub fn set_name_index_enabled(&mut self, enabled: bool) {
+        self.name_index = if enabled { Some(Default::default()) } else { None };
+    }
+
+    /// Returns true if name index is enabled.
+    pub fn is_name_index_enabled(&self) -> bool {
+        self.name_index.is_some()
+    }
+
+    fn invalidate_name_index(&mut self) {
+        if let Some(index) = self.name_index.as_mut() {
+            index.get_mut().valid = false;
+        }
+    }
+
+    fn find_by_name_indexed(&self, index: &RefCell<NameIndex>, name: &str) -> Handle<Node> {
+        let mut index = index.borrow_mut();
+        let generation = base::name_generation();
+        if !index.valid || index.name_generation != generation {
+            index.map.clear();
+            // Depth-first traversal in same order as find_by_name, so first found node
+            // with a name wins - exactly as without index.
+            let mut stack = vec![self.root];
+            while let Some(handle) = stack.pop() {
+                let node = &self.pool[handle];
+                if !index.map.contains_key(node.name()) {
+                    index.map.insert(node.name().to_owned(), handle);
+                }
+                for &child in node.children().iter().rev() {
+                    stack.push(child);
+                }
+            }
+            index.valid = true;
+            index.name_generation = generation;
+        }
+        index.map.get(name).cloned().unwrap_or(Handle::NONE)
+    }
+
+    /// Enables spatial index with given size of cell or disables it if `None` is passed.
+    /// Size of cell should be close to typical radius of queries, for example radius of
+    /// perception of AI, [`spatial_index::DEFAULT_CELL_SIZE`] suits most scenes. Index is
+    /// rebuilt lazily on first query after nodes were moved, added or removed. Index is
+    /// disabled by default.
+    pub fn set_spatial_index(&mut self, cell_size: Option<f32>) {
+        self.spatial_index = cell_size.map(|cell_size| RefCell::new(SpatialIndex::new(cell_size)));
+    }
+
+    /// Returns size of cell of spatial index, or `None` if index is disabled.
+    pub fn spatial_index_cell_size(&self) -> Option<f32> {
+        self.spatial_index.as_ref().map(|index| index.borrow().cell_size())
+    }
+
+    fn invalidate_spatial_index(&mut self) {
+        if let Some(index) = self.spatial_index.as_mut() {
+            index.get_mut().valid = false;
+        }
+    }
+
+    /// Bounding sphere of node in world space. Meshes are bounded by their bounding box,
+    /// every other node is a point.
+    fn spatial_entry(handle: Handle<Node>, node: &Node) -> Entry {
+        if let Node::Mesh(mesh) = node {
+            let world = mesh.global_transform();
+            let bounding_box = mesh.bounding_box();
+            let max_scale = world.side().len().max(world.up().len()).max(world.look().len());
+            Entry {
+                handle,
+                center: world.transform_vector((bounding_box.min + bounding_box.max).scale(0.5)),
+                radius: (bounding_box.max - bounding_box.min).len() * 0.5 * max_scale,
+            }
+        } else {
+            Entry {
+                handle,
+                center: node.global_position(),
+                radius: 0.0,
+            }
+        }
+    }
+
+    /// Collects nodes which may intersect `bounds` (exactly or approximately) and pass `test`.
+    fn spatial_query<F>(&self, bounds: &AxisAlignedBoundingBox, mut test: F) -> Vec<Handle<Node>>
+        where F: FnMut(&Entry) -> bool {
+        let mut result = Vec::new();
+        if let Some(index) = self.spatial_index.as_ref() {
+            let mut index = index.borrow_mut();
+            if !index.valid {
+                let root = self.root;
+                index.rebuild(self.pool.pair_iter()
+                    .filter(|(handle, _)| *handle != root)
+                    .map(|(handle, node)| Self::spatial_entry(handle, node)));
+            }
+            index.query(bounds, |entry| {
+                if test(entry) {
+                    result.push(entry.handle);
+                }
+            });
+        } else {
+            for (handle, node) in self.pool.pair_iter() {
+                if handle != self.root {
+                    let entry = Self::spatial_entry(handle, node);
+                    if test(&entry) {
+                        result.push(handle);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns handles of nodes which intersect given sphere. Meshes are tested by their
+    /// bounding spheres, other nodes by position. Global transforms of last update are used.
+    pub fn find_in_sphere(&self, center: Vec3, radius: f32) -> Vec<Handle<Node>> {
+        let mut bounds = AxisAlignedBoundingBox::default();
+        let extent = Vec3::new(radius, radius, radius);
+        bounds.add_point(center - extent);
+        bounds.add_point(center + extent);
+        self.spatial_query(&bounds, |entry| {
+            let distance = radius + entry.radius;
+            entry.center.sqr_distance(&center) <= distance * distance
+        })
+    }
+
+    /// Returns handles of nodes which intersect given box, see [`find_in_sphere`](Graph::find_in_sphere).
+    pub fn find_in_aabb(&self, bounds: &AxisAlignedBoundingBox) -> Vec<Handle<Node>> {
+        self.spatial_query(bounds, |entry| {
+            spatial_index::sqr_distance_to_aabb(entry.center, bounds) <= entry.radius * entry.radius
+        })
+    }
+
+    /// Returns handles of nodes which are inside of frustum of given camera, see
+    /// [`find_in_sphere`](Graph::find_in_sphere). Camera matrices of last update are used.
+    pub fn find_in_frustum(&self, camera: &Camera) -> Vec<Handle<Node>> {
+        let view_projection = camera.view_projection_matrix();
+        let frustum = match Frustum::from(view_projection) {
+            Ok(frustum) => frustum,
+            Err(_) => return Vec::new(),
+        };
+
+        // Bounds of frustum are bounds of its corners.
+        let inv_view_projection = view_projection.inverse().unwrap_or_default();
+        let mut bounds = AxisAlignedBoundingBox::default();
+        for &x in &[-1.0, 1.0] {
+            for &y in &[-1.0, 1.0] {
+                for &z in &[-1.0, 1.0] {
+                    let corner = inv_view_projection.transform_vector4(Vec4::new(x, y, z, 1.0));
+                    bounds.add_point(corner.xyz().scale(1.0 / corner.w));
+                }
+            }
+        }
+
+        self.spatial_query(&bounds, |entry| frustum.is_intersects_sphere(entry.center, entry.radius))
+    }
+
     /// Searches node with specified name starting from specified node. If nothing was found,
     /// [`Handle::NONE`] is returned.
     pub fn find_by_name(&self, root_node: Handle<Node>, name: &str) -> Handle<Node> {
+        if root_node == self.root {
+            if let Some(index) = self.name_index.as_ref() {
+                return self.find_by_name_indexed(index, name);
+            }
+        }
+
         let root = &self.pool[root_node];
         if root.name() == name {
             root_node
@@ -236,20 +564,78 @@ impl Graph {
         let mut old_new_mapping = HashMap::new();
         let root_handle = self.copy_node_raw(node_handle, dest_graph, &mut old_new_mapping, filter);
 
-        // Iterate over instantiated nodes and remap bones handles.
+        dest_graph.remap_handles(&old_new_mapping);
+
+        (root_handle, old_new_mapping)
+    }
+
+    /// Creates deep copy of node with all children within same graph, copy is attached to
+    /// the same parent as the original. Use it to spawn multiple copies of some in-scene
+    /// template. Works the same as [`copy_node`](Graph::copy_node): handles inside of copied
+    /// nodes (bones of surfaces, cloth pins and colliders) which point to nodes of copied
+    /// hierarchy are remapped to copies, other handles are left as is.
+    ///
+    /// Returns tuple where first element is handle to copy of node, and second element -
+    /// old-to-new hash map. Use [`Scene::copy_node`](crate::scene::Scene::copy_node) if you
+    /// also need copies of animations and physics bindings.
+    pub fn copy_node_inplace<F>(&mut self,
+                                node_handle: Handle<Node>,
+                                filter: &mut F,
+    ) -> (Handle<Node>, HashMap<Handle<Node>, Handle<Node>>) where F: FnMut(&Node) -> bool {
+        let mut old_new_mapping = HashMap::new();
+        let root_handle = self.copy_node_inplace_raw(node_handle, &mut old_new_mapping, filter);
+
+        let parent = self.pool[node_handle].parent();
+        if parent.is_some() {
+            self.link_nodes(root_handle, parent);
+        }
+
+        self.remap_handles(&old_new_mapping);
+
+        (root_handle, old_new_mapping)
+    }
+
+    fn copy_node_inplace_raw<F>(&mut self,
+                                root_handle: Handle<Node>,
+                                old_new_mapping: &mut HashMap<Handle<Node>, Handle<Node>>,
+                                filter: &mut F,
+    ) -> Handle<Node> where F: FnMut(&Node) -> bool {
+        let src_node = &self.pool[root_handle];
+        let mut dest_node = src_node.clone();
+        // Copy is an instance of same resource node as original.
+        dest_node.original = src_node.original;
+        let children = src_node.children().to_vec();
+        let dest_copy_handle = self.pool.spawn(dest_node);
+        old_new_mapping.insert(root_handle, dest_copy_handle);
+        for src_child_handle in children {
+            if filter(&self.pool[src_child_handle]) {
+                let dest_child_handle = self.copy_node_inplace_raw(src_child_handle, old_new_mapping, filter);
+                self.link_nodes(dest_child_handle, dest_copy_handle);
+            }
+        }
+        dest_copy_handle
+    }
+
+    /// Remaps handles stored inside of copied nodes (bones, cloth pins, etc.) using
+    /// old-to-new mapping.
+    pub(in crate) fn remap_handles(&mut self, old_new_mapping: &HashMap<Handle<Node>, Handle<Node>>) {
         for (_, &new_node_handle) in old_new_mapping.iter() {
-            if let Node::Mesh(mesh) = &mut dest_graph.pool[new_node_handle] {
-                for surface in mesh.surfaces_mut() {
-                    for bone_handle in surface.bones.iter_mut() {
-                        if let Some(entry) = old_new_mapping.get(bone_handle) {
-                            *bone_handle = *entry;
+            match &mut self.pool[new_node_handle] {
+                Node::Mesh(mesh) => {
+                    for surface in mesh.surfaces_mut() {
+                        for bone_handle in surface.bones.iter_mut() {
+                            if let Some(entry) = old_new_mapping.get(bone_handle) {
+                                *bone_handle = *entry;
+                            }
                         }
                     }
                 }
+                Node::Cloth(cloth) => cloth.remap_handles(old_new_mapping),
+                Node::Trigger(trigger) => trigger.remap_handles(old_new_mapping),
+                Node::WaterVolume(volume) => volume.remap_handles(old_new_mapping),
+                _ => ()
             }
         }
-
-        (root_handle, old_new_mapping)
     }
 
     fn copy_node_raw<F>(&self,
@@ -274,9 +660,32 @@ impl Graph {
         dest_copy_handle
     }
 
+    /// Moves every node of other graph into this graph, children of root of other graph
+    /// become children of root of this graph. Handles inside of moved nodes (bones of
+    /// surfaces, cloth pins and colliders) are remapped, moved instances of model resources
+    /// stay linked with their resources.
+    ///
+    /// Returns old-to-new mapping, root of other graph is mapped to root of this graph.
+    pub fn append(&mut self, other: Graph) -> HashMap<Handle<Node>, Handle<Node>> {
+        let mut old_new_mapping = HashMap::new();
+        for &child_handle in other.pool[other.root].children() {
+            let copy = other.copy_node_raw(child_handle, self, &mut old_new_mapping, &mut |_| true);
+            self.link_nodes(copy, self.root);
+        }
+
+        for (&old_handle, &new_handle) in old_new_mapping.iter() {
+            self.pool[new_handle].original = other.pool[old_handle].original;
+        }
+
+        old_new_mapping.insert(other.root, self.root);
+        self.remap_handles(&old_new_mapping);
+
+        old_new_mapping
+    }
+
     /// Searches root node in given hierarchy starting from given node. This method is used
     /// when you need to find a root node of a model in complex graph.
-    fn find_model_root(&self, from: Handle<Node>) -> Handle<Node> {
+    pub(in crate) fn find_model_root(&self, from: Handle<Node>) -> Handle<Node> {
         let mut model_root_handle = from;
         while model_root_handle.is_some() {
             let model_node = &self.pool[model_root_handle];
@@ -354,6 +763,9 @@ impl Graph {
                                 *bone_handle = graph.find_copy_of(root_handle, *bone_handle);
                             }
                         }
+
+                        // Surfaces were replaced, instance-level changes must be applied again.
+                        apply_surface_overrides(mesh);
                     }
                 }
             }
@@ -362,36 +774,224 @@ impl Graph {
         Log::writeln("Graph resolved successfully!".to_owned());
     }
 
+    /// Applies surface overrides of given node (see `scene::instance`) to its surfaces. Call
+    /// it after changing overrides of a mesh to see the changes immediately.
+    pub fn apply_overrides(&mut self, node: Handle<Node>) {
+        if let Node::Mesh(mesh) = &mut self.pool[node] {
+            apply_surface_overrides(mesh);
+        }
+    }
+
+    /// Synchronizes instances of model resources with their resources. Call it after model
+    /// resources were reloaded, for example after re-import of a changed model: properties
+    /// of instance nodes are copied from corresponding nodes of resource, except overridden
+    /// ones (see `scene::instance`), and nodes added to resource are added to instances.
+    /// Nodes removed from resource are kept in instances. Nodes are matched by names, like
+    /// in [`resolve`](Graph::resolve). Root of each instance is left as is.
+    pub fn sync_instances(&mut self) {
+        let roots = self.pool.pair_iter()
+            .filter(|(_, node)| node.is_resource_instance() && node.resource().is_some())
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+
+        for root in roots {
+            self.sync_instance(root);
+        }
+
+        self.update_hierachical_data();
+    }
+
+    /// Returns nodes of instance which were instantiated from the same resource as its root,
+    /// nested instances of other resources are skipped.
+    fn instance_nodes(&self, root: Handle<Node>, model: &Arc<Mutex<Model>>) -> Vec<Handle<Node>> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![root];
+        while let Some(handle) = stack.pop() {
+            nodes.push(handle);
+            for &child_handle in self.pool[handle].children() {
+                let child = &self.pool[child_handle];
+                if !child.is_resource_instance() && child.resource().map_or(false, |r| Arc::ptr_eq(&r, model)) {
+                    stack.push(child_handle);
+                }
+            }
+        }
+        nodes
+    }
+
+    fn sync_instance(&mut self, root: Handle<Node>) {
+        let model = self.pool[root].resource().unwrap();
+        let model_ref = model.lock().unwrap();
+        let resource_graph = &model_ref.get_scene().graph;
+        let resource_root = resource_graph.get_root();
+
+        let names = self.instance_nodes(root, &model)
+            .into_iter()
+            .filter(|&handle| handle != root)
+            .map(|handle| (self.pool[handle].name().to_owned(), handle))
+            .collect::<HashMap<_, _>>();
+        let find_instance_node = |resource_handle: Handle<Node>| {
+            if resource_handle == resource_root {
+                root
+            } else {
+                names.get(resource_graph[resource_handle].name()).cloned().unwrap_or(Handle::NONE)
+            }
+        };
+
+        // Add nodes which appeared in resource, whole new subtree is copied at once.
+        let mut added = Vec::new();
+        for resource_handle in resource_graph.traverse_handle_iter(resource_root) {
+            let resource_parent = resource_graph[resource_handle].parent();
+            if resource_parent.is_some() && find_instance_node(resource_handle).is_none() {
+                let parent = find_instance_node(resource_parent);
+                if parent.is_some() {
+                    let (copy, mapping) = resource_graph.copy_node(resource_handle, self, &mut |_| true);
+                    self.link_nodes(copy, parent);
+                    added.extend(mapping.values().cloned());
+                }
+            }
+        }
+        for &handle in added.iter() {
+            self.pool[handle].resource = Some(model.clone());
+        }
+
+        // Resolve original handles first, they are used to remap bones.
+        let nodes = self.instance_nodes(root, &model);
+        for &handle in nodes.iter() {
+            let resource_handle = if handle == root {
+                resource_root
+            } else {
+                resource_graph.find_by_name_from_root(self.pool[handle].name())
+            };
+            if resource_handle.is_some() {
+                let node = &mut self.pool[handle];
+                node.original = resource_handle;
+                node.inv_bind_pose_transform = resource_graph[resource_handle].inv_bind_pose_transform();
+            }
+        }
+
+        for &handle in nodes.iter().filter(|&&handle| handle != root) {
+            let resource_handle = self.pool[handle].original;
+            if resource_handle.is_none() || resource_graph[resource_handle].name() != self.pool[handle].name() {
+                // Node was removed from resource.
+                continue;
+            }
+            let resource_node = &resource_graph[resource_handle];
+
+            let surfaces = if let Node::Mesh(resource_mesh) = resource_node {
+                resource_mesh.surfaces()
+                    .iter()
+                    .map(|resource_surface| {
+                        let mut surface = resource_surface.clone();
+                        for bone_handle in surface.bones.iter_mut() {
+                            let resource_bone = *bone_handle;
+                            *bone_handle = self.find_copy_of(root, resource_bone);
+                            if bone_handle.is_none() {
+                                Log::writeln(format!("Unable to find bone {} of mesh {} in instance {}, bone will not move vertices!",
+                                                     resource_graph[resource_bone].name(), resource_node.name(), self.pool[root].name()));
+                            }
+                        }
+                        surface
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            let node = &mut self.pool[handle];
+            let overrides = node.overrides().clone();
+            if !overrides.local_transform {
+                node.set_local_transform(resource_node.local_transform().clone());
+            }
+            if !overrides.visibility {
+                node.set_visibility(resource_node.visibility());
+            }
+            if !overrides.enabled {
+                node.set_enabled(resource_node.is_enabled());
+            }
+            if let Node::Mesh(mesh) = node {
+                mesh.clear_surfaces();
+                for surface in surfaces {
+                    mesh.add_surface(surface);
+                }
+                apply_surface_overrides(mesh);
+            }
+        }
+
+        if !added.is_empty() {
+            Log::writeln(format!("Instance {} synced, {} new node(s) added from resource.",
+                                 self.pool[root].name(), added.len()));
+        }
+    }
+
     /// Calculates local and global transform, global visibility for each node in graph.
     /// Normally you not need to call this method directly, it will be called automatically
     /// on each frame. However there is one use case - when you setup complex hierarchy and
     /// need to know global transform of nodes before entering update loop, then you can call
     /// this method.
     pub fn update_hierachical_data(&mut self) {
-        // Calculate transforms on nodes
-        self.stack.clear();
-        self.stack.push(self.root);
-        while let Some(node_handle) = self.stack.pop() {
+        // Calculate transforms on nodes. Global transform is recalculated only if local
+        // transform of node or of any of its ancestors was changed, so static parts of
+        // scene cost just a traversal.
+        let mut any_changed = false;
+        self.hierarchy_stack.clear();
+        self.hierarchy_stack.push((self.root, false));
+        while let Some((node_handle, parent_changed)) = self.hierarchy_stack.pop() {
             // Calculate local transform and get parent handle
             let parent_handle = self.pool[node_handle].parent();
 
-            let (parent_global_transform, parent_visibility) =
+            let (parent_global_transform, parent_visibility, parent_enabled) =
                 if parent_handle.is_some() {
                     let parent = &self.pool[parent_handle];
-                    (parent.global_transform(), parent.global_visibility())
+                    (parent.global_transform(), parent.global_visibility(), parent.is_globally_enabled())
                 } else {
-                    (Mat4::IDENTITY, true)
+                    (Mat4::IDENTITY, true, true)
                 };
 
             let node = &mut self.pool[node_handle];
-            node.global_transform = parent_global_transform * node.local_transform().matrix();
-            node.global_visibility = parent_visibility && node.visibility();
+            let changed = parent_changed || node.transform_changed;
+            if changed {
+                node.global_transform = parent_global_transform * node.local_transform().matrix();
+                node.transform_changed = false;
+                any_changed = true;
+            }
+            node.global_enabled = parent_enabled && node.is_enabled();
+            node.global_visibility = parent_visibility && node.visibility() && node.global_enabled;
 
             // Queue children and continue traversal on them
-            for child_handle in node.children() {
-                self.stack.push(child_handle.clone());
+            for &child_handle in node.children() {
+                self.hierarchy_stack.push((child_handle, changed));
+            }
+        }
+
+        if any_changed {
+            self.invalidate_spatial_index();
+        }
+    }
+
+    /// Moves every top-level node by `-offset` and everything what is simulated in world
+    /// space (trails, cloth), then recalculates global transforms. Use `Scene::shift_origin`
+    /// instead, it keeps the rest of scene in sync.
+    pub(in crate) fn shift_origin(&mut self, offset: Vec3) {
+        for i in 0..self.pool[self.root].children().len() {
+            let child = self.pool[self.root].children()[i];
+            self.pool[child].local_transform_mut().offset(offset.scale(-1.0));
+        }
+
+        for node in self.pool.iter_mut() {
+            match node {
+                Node::Trail(trail) => trail.shift_origin(offset),
+                Node::Cloth(cloth) => cloth.shift_origin(offset),
+                _ => ()
             }
         }
+
+        self.update_hierachical_data();
+    }
+
+    /// Returns total amount of slots in pool of nodes and amount of alive nodes. Large
+    /// difference means that pool was used to hold many more nodes than it has now.
+    pub fn pool_usage(&self) -> (usize, usize) {
+        (self.pool.get_capacity(), self.pool.alive_count())
     }
 
     /// Checks whether given node handle is valid or not.
@@ -403,18 +1003,88 @@ impl Graph {
     pub fn update_nodes(&mut self, frame_size: Vec2, dt: f32) {
         self.update_hierachical_data();
 
-        for node in self.pool.iter_mut() {
+        self.wind.update(dt);
+        let wind = &self.wind;
+
+        let force_fields = self.pool.iter()
+            .filter_map(|node| match node {
+                Node::ForceField(field) if field.affects_particles() && field.is_globally_enabled() => field.sample(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Effects fade by distance to nearest camera, transforms of cameras are already known.
+        let camera_positions = self.pool.iter()
+            .filter_map(|node| match node {
+                Node::Camera(camera) if camera.is_enabled() && camera.is_globally_enabled() => Some(camera.global_position()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let camera_distance = |position: Vec3| camera_positions.iter()
+            .map(|camera_position| (*camera_position - position).len())
+            .fold(None, |min: Option<f32>, distance| Some(min.map_or(distance, |min| min.min(distance))))
+            .unwrap_or(0.0);
+
+        for node in self.pool.iter_mut().filter(|node| node.is_globally_enabled()) {
             if let Some(lifetime) = node.lifetime() {
                 node.set_lifetime(lifetime - dt);
             }
 
             match node {
-                Node::Camera(camera) => camera.calculate_matrices(frame_size),
-                Node::ParticleSystem(particle_system) => particle_system.update(dt),
+                Node::Camera(camera) => {
+                    camera.shake_mut().update(dt);
+                    camera.calculate_matrices(frame_size);
+                }
+                Node::ParticleSystem(particle_system) => {
+                    let distance = camera_distance(particle_system.global_position());
+                    particle_system.update(dt, wind, &force_fields, distance)
+                }
+                Node::Scatter(scatter) => scatter.update_sway(wind),
+                Node::Trail(trail) => trail.update(dt),
+                Node::Crowd(crowd) => crowd.update(dt),
+                Node::Light(light) => light.update(dt),
+                Node::Projector(projector) => projector.update(dt),
                 _ => ()
             }
         }
 
+        // Clipping planes are fitted to meshes of whole graph, so cameras with auto-fit
+        // are updated separately when view matrices are known.
+        for i in 0..self.pool.get_capacity() {
+            let planes = if let Some(Node::Camera(camera)) = self.pool.at(i) {
+                if !camera.is_globally_enabled() || !camera.is_auto_fit_clip_planes() {
+                    continue;
+                }
+                camera.calculate_clip_planes(self)
+            } else {
+                continue;
+            };
+
+            let handle = self.pool.handle_from_index(i);
+            if let Node::Camera(camera) = &mut self.pool[handle] {
+                camera.set_fitted_clip_planes(planes);
+                camera.calculate_matrices(frame_size);
+            }
+        }
+
+        // Cloth needs transforms of other nodes (pins and colliders), so it is updated
+        // separately when all transforms are known.
+        for i in 0..self.pool.get_capacity() {
+            let environment = if let Some(Node::Cloth(cloth)) = self.pool.at(i) {
+                if !cloth.is_globally_enabled() {
+                    continue;
+                }
+                cloth.gather_environment(self)
+            } else {
+                continue;
+            };
+
+            let handle = self.pool.handle_from_index(i);
+            if let Node::Cloth(cloth) = &mut self.pool[handle] {
+                cloth.update(dt, &environment);
+            }
+        }
+
         for i in 0..self.pool.get_capacity() {
             let remove = if let Some(node) = self.pool.at(i) {
                 if let Some(lifetime) = node.lifetime() {
@@ -432,6 +1102,35 @@ impl Graph {
         }
     }
 
+    /// Updates sets of nodes inside of triggers, `bound_nodes` are nodes with rigid bodies
+    /// which are tracked by triggers without explicit list of nodes. Must be called after
+    /// `update_nodes`, when global transforms are known.
+    pub(in crate) fn update_triggers(&mut self, bound_nodes: &[Handle<Node>]) {
+        for i in 0..self.pool.get_capacity() {
+            let handle = self.pool.handle_from_index(i);
+            let inside = if let Some(Node::Trigger(trigger)) = self.pool.at(i) {
+                if trigger.is_globally_enabled() {
+                    trigger.tracked_nodes()
+                        .unwrap_or(bound_nodes)
+                        .iter()
+                        .filter(|&&node| node != handle && self.is_valid_handle(node)
+                            && trigger.contains_point(self.pool[node].global_position()))
+                        .cloned()
+                        .collect()
+                } else {
+                    // Disabled trigger keeps its contents, but does not emit events.
+                    trigger.inside().to_vec()
+                }
+            } else {
+                continue;
+            };
+
+            if let Node::Trigger(trigger) = &mut self.pool[handle] {
+                trigger.set_inside(inside);
+            }
+        }
+    }
+
     /// Creates an iterator that has linear iteration order over internal collection
     /// of nodes. It does *not* perform any tree traversal!
     pub fn linear_iter(&self) -> PoolIterator<Node> {
@@ -454,6 +1153,14 @@ impl Graph {
         self.pool.pair_iter_mut()
     }
 
+    // Typed iterators still visit every slot of pool, but systems which care about one kind
+    // of nodes get them without matching on each node themselves.
+    define_typed_iter!(meshes, meshes_mut, Mesh, Mesh);
+    define_typed_iter!(lights, lights_mut, Light, Light);
+    define_typed_iter!(cameras, cameras_mut, Camera, Camera);
+    define_typed_iter!(sprites, sprites_mut, Sprite, Sprite);
+    define_typed_iter!(particle_systems, particle_systems_mut, ParticleSystem, ParticleSystem);
+
     /// Create graph depth traversal iterator.
     ///
     /// # Notes
@@ -480,6 +1187,96 @@ impl Graph {
         }
     }
 
+    /// Returns human-readable indented tree of nodes starting from given node with names,
+    /// kinds and handles of nodes. Useful to check what was imported from a model file.
+    pub fn dump_hierarchy(&self, from: Handle<Node>) -> String {
+        let mut out = String::new();
+        let mut stack = vec![(from, 0)];
+        while let Some((handle, depth)) = stack.pop() {
+            for _ in 0..depth {
+                out.push_str("    ");
+            }
+            if !self.pool.is_valid_handle(handle) {
+                writeln!(out, "<dead> {:?}", handle).unwrap();
+                continue;
+            }
+            let node = &self.pool[handle];
+            writeln!(out, "{} ({}) {:?}", node.name(), node.kind_name(), handle).unwrap();
+            // Children are pushed in reverse order to print them in order.
+            for &child in node.children().iter().rev() {
+                stack.push((child, depth + 1));
+            }
+        }
+        out
+    }
+
+    /// Checks graph for broken links (dead or mismatched parent and child handles), orphaned
+    /// nodes, cycles, invalid transforms and surfaces that reference dead bones. Returns list
+    /// of found issues, empty list means graph is fine. This is relatively heavy method, it
+    /// is intended for debugging.
+    pub fn validate(&self) -> Vec<GraphIssue> {
+        let mut issues = Vec::new();
+        let alive = self.pool.alive_count();
+
+        for (handle, node) in self.pool.pair_iter() {
+            let parent = node.parent();
+            if parent.is_some() {
+                if !self.pool.is_valid_handle(parent) {
+                    issues.push(GraphIssue::DeadParent { node: handle, parent });
+                }
+            } else if handle != self.root {
+                issues.push(GraphIssue::Orphan { node: handle });
+            }
+
+            for &child in node.children() {
+                if !self.pool.is_valid_handle(child) {
+                    issues.push(GraphIssue::DeadChild { node: handle, child });
+                } else if self.pool[child].parent() != handle {
+                    issues.push(GraphIssue::ParentMismatch { node: handle, child });
+                }
+            }
+
+            // Walk up the hierarchy, in a tree we must reach top in at most `alive` steps.
+            let mut ancestor = parent;
+            let mut steps = 0;
+            while ancestor.is_some() && self.pool.is_valid_handle(ancestor) {
+                if ancestor == handle || steps > alive {
+                    issues.push(GraphIssue::Cycle { node: handle });
+                    break;
+                }
+                ancestor = self.pool[ancestor].parent();
+                steps += 1;
+            }
+
+            let local = node.local_transform().matrix();
+            if local.f.iter().chain(node.global_transform().f.iter()).any(|v| !v.is_finite()) {
+                issues.push(GraphIssue::InvalidTransform { node: handle });
+            }
+
+            if let Node::Mesh(mesh) = node {
+                for (i, surface) in mesh.surfaces().iter().enumerate() {
+                    for &bone in surface.bones.iter() {
+                        if !self.pool.is_valid_handle(bone) {
+                            issues.push(GraphIssue::DeadBone { mesh: handle, surface: i, bone });
+                        }
+                    }
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Returns wind settings of graph, wind affects particle systems, cloth and scatter nodes.
+    pub fn wind(&self) -> &Wind {
+        &self.wind
+    }
+
+    /// Returns mutable reference to wind settings of graph.
+    pub fn wind_mut(&mut self) -> &mut Wind {
+        &mut self.wind
+    }
+
     /// Creates deep copy of graph. Allows filtering while copying, returns copy and
     /// old-to-new node mapping.
     pub fn clone<F>(&self, filter: &mut F) -> (Self, HashMap<Handle<Node>, Handle<Node>>)
@@ -487,10 +1284,24 @@ impl Graph {
         let mut copy = Self::default();
         let (root, old_new_map) = self.copy_node(self.root, &mut copy, filter);
         copy.root = root;
+        copy.wind = self.wind;
+        if self.is_name_index_enabled() {
+            copy.set_name_index_enabled(true);
+        }
+        copy.set_spatial_index(self.spatial_index_cell_size());
         (copy, old_new_map)
     }
 }
 
+fn apply_surface_overrides(mesh: &mut Mesh) {
+    let overrides = mesh.overrides().clone();
+    for surface_override in overrides.surfaces() {
+        if let Some(surface) = mesh.surfaces_mut().get_mut(surface_override.surface as usize) {
+            surface_override.apply(surface);
+        }
+    }
+}
+
 impl Index<Handle<Node>> for Graph {
     type Output = Node;
 
@@ -559,8 +1370,12 @@ impl Visit for Graph {
             panic!("Graph pool must be empty on load!")
         }
 
+        self.invalidate_name_index();
+        self.invalidate_spatial_index();
+
         self.root.visit("Root", visitor)?;
         self.pool.visit("Pool", visitor)?;
+        self.wind.visit("Wind", visitor)?;
 
         visitor.leave_region()
     }
@@ -570,11 +1385,19 @@ impl Visit for Graph {
 mod test {
     use crate::{
         scene::{
-            graph::Graph,
+            graph::{Graph, GraphIssue},
             node::Node,
-            base::Base,
+            base::{Base, BaseBuilder},
+            mesh::Mesh,
+            camera::Camera,
+        },
+        core::{
+            pool::Handle,
+            math::{
+                vec3::Vec3,
+                quat::Quat,
+            },
         },
-        core::pool::Handle,
     };
 
     #[test]
@@ -592,4 +1415,180 @@ mod test {
         let c = graph.add_node(Node::Base(Base::default()));
         assert_eq!(graph.pool.alive_count(), 4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn graph_visibility_test() {
+        let mut graph = Graph::new();
+        let parent = graph.add_node(Node::Base(Base::default()));
+        let child = graph.add_node(Node::Base(Base::default()));
+        graph.link_nodes(child, parent);
+
+        graph[parent].set_visibility(false);
+        graph.update_hierachical_data();
+        assert!(!graph[child].global_visibility());
+        assert!(graph[child].visibility());
+
+        graph[parent].set_visibility(true);
+        graph[parent].set_enabled(false);
+        graph.update_hierachical_data();
+        assert!(!graph[child].is_globally_enabled());
+        assert!(!graph[child].global_visibility());
+
+        graph[parent].set_enabled(true);
+        graph.update_hierachical_data();
+        assert!(graph[child].global_visibility());
+    }
+
+    #[test]
+    fn graph_link_keep_world_transform_test() {
+        let mut graph = Graph::new();
+        let parent = graph.add_node(Node::Base(Base::default()));
+        let child = graph.add_node(Node::Base(Base::default()));
+        graph[parent].local_transform_mut()
+            .set_position(Vec3::new(1.0, 2.0, 3.0))
+            .set_rotation(Quat::from_axis_angle(Vec3::UP, 1.0))
+            .set_scale(Vec3::new(2.0, 2.0, 2.0));
+        graph[child].local_transform_mut().set_position(Vec3::new(5.0, 0.0, 0.0));
+        graph.update_hierachical_data();
+        let before = graph[child].global_transform();
+
+        graph.link_nodes_keep_world_transform(child, parent);
+        graph.update_hierachical_data();
+        let after = graph[child].global_transform();
+
+        assert_eq!(graph[child].parent(), parent);
+        for (a, b) in before.f.iter().zip(after.f.iter()) {
+            assert!((a - b).abs() < 1.0e-4);
+        }
+    }
+
+    #[test]
+    fn graph_validate_test() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(Node::Base(Base::default()));
+        let b = graph.add_node(Node::Base(Base::default()));
+        graph.link_nodes(b, a);
+        assert!(graph.validate().is_empty());
+        assert_eq!(graph.dump_hierarchy(graph.root).lines().count(), 3);
+
+        // Break link manually.
+        graph[b].parent = Handle::NONE;
+        let issues = graph.validate();
+        assert!(issues.contains(&GraphIssue::Orphan { node: b }));
+        assert!(issues.contains(&GraphIssue::ParentMismatch { node: a, child: b }));
+    }
+
+    #[test]
+    fn graph_name_index_test() {
+        let mut graph = Graph::new();
+        graph.set_name_index_enabled(true);
+        let a = graph.add_node(Node::Base(Base::default()));
+        graph[a].set_name("A");
+        assert_eq!(graph.find_by_name_from_root("A"), a);
+
+        graph[a].set_name("B");
+        assert_eq!(graph.find_by_name_from_root("A"), Handle::NONE);
+        assert_eq!(graph.find_by_name_from_root("B"), a);
+
+        graph.remove_node(a);
+        assert_eq!(graph.find_by_name_from_root("B"), Handle::NONE);
+    }
+
+    #[test]
+    fn graph_spatial_query_test() {
+        let mut graph = Graph::new();
+        let near = graph.add_node(Node::Base(Base::default()));
+        graph[near].local_transform_mut().set_position(Vec3::new(1.0, 0.0, 0.0));
+        let far = graph.add_node(Node::Base(Base::default()));
+        graph[far].local_transform_mut().set_position(Vec3::new(100.0, 0.0, 0.0));
+        graph.update_hierachical_data();
+
+        assert_eq!(graph.find_in_sphere(Vec3::ZERO, 5.0), vec![near]);
+
+        graph.set_spatial_index(Some(4.0));
+        assert_eq!(graph.find_in_sphere(Vec3::ZERO, 5.0), vec![near]);
+
+        // Index must follow moved nodes.
+        graph[far].local_transform_mut().set_position(Vec3::new(-2.0, 0.0, 0.0));
+        graph.update_hierachical_data();
+        let found = graph.find_in_sphere(Vec3::ZERO, 5.0);
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&near) && found.contains(&far));
+    }
+
+    #[test]
+    fn graph_copy_node_inplace_test() {
+        let mut graph = Graph::new();
+        let parent = graph.add_node(Node::Base(Base::default()));
+        let child = graph.add_node(Node::Base(Base::default()));
+        graph.link_nodes(child, parent);
+
+        let (copy, map) = graph.copy_node_inplace(parent, &mut |_| true);
+        assert_eq!(graph.pool.alive_count(), 5);
+        assert_eq!(map[&parent], copy);
+        assert_eq!(graph[copy].parent(), graph.root);
+        assert_eq!(graph[copy].children(), &[map[&child]]);
+        assert_eq!(graph[map[&child]].parent(), copy);
+    }
+
+    #[test]
+    fn graph_append_test() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::Base(Base::default()));
+
+        let mut other = Graph::new();
+        let parent = other.add_node(Node::Base(Base::default()));
+        let child = other.add_node(Node::Base(Base::default()));
+        other.link_nodes(child, parent);
+        let other_root = other.root;
+
+        let map = graph.append(other);
+        assert_eq!(graph.pool.alive_count(), 4);
+        assert_eq!(map[&other_root], graph.root);
+        assert_eq!(graph[map[&parent]].parent(), graph.root);
+        assert_eq!(graph[map[&child]].parent(), map[&parent]);
+    }
+
+    #[test]
+    fn test_typed_iterators() {
+        let mut graph = Graph::new();
+        let mesh = graph.add_node(Node::Mesh(Mesh::default()));
+        graph.add_node(Node::Base(Base::default()));
+        let camera = graph.add_node(Node::Camera(Camera::default()));
+
+        assert_eq!(graph.meshes().map(|(handle, _)| handle).collect::<Vec<_>>(), vec![mesh]);
+        assert_eq!(graph.cameras().map(|(handle, _)| handle).collect::<Vec<_>>(), vec![camera]);
+        assert_eq!(graph.lights().count(), 0);
+
+        for (_, camera) in graph.cameras_mut() {
+            camera.set_enabled(false);
+        }
+        assert!(!graph[camera].as_camera().is_enabled());
+    }
+
+    #[test]
+    fn test_build_on() {
+        let mut graph = Graph::new();
+        let root = graph.get_root();
+        let parent = BaseBuilder::new().build_on(&mut graph, root);
+        let child = BaseBuilder::new().build_on(&mut graph, parent);
+        assert_eq!(graph[parent].parent(), root);
+        assert_eq!(graph[child].parent(), parent);
+        assert!(graph[root].children().contains(&parent));
+        assert!(!graph[root].children().contains(&child));
+    }
+
+    #[test]
+    fn test_declared_children() {
+        let mut graph = Graph::new();
+        let root = graph.get_root();
+        let camera = graph.add_node(Node::Camera(Camera::default()));
+        let pitch = BaseBuilder::new().with_children(vec![camera]).build_on(&mut graph, root);
+        let yaw = BaseBuilder::new().with_children(vec![pitch]).build_on(&mut graph, root);
+        assert_eq!(graph[camera].parent(), pitch);
+        assert_eq!(graph[pitch].parent(), yaw);
+        assert_eq!(graph[yaw].parent(), root);
+        assert_eq!(graph[root].children().len(), 1);
+        assert!(graph.validate().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! Named groups of nodes - "all enemies", "all pickups" and similar collections.
+//!
+//! Node can be in any amount of groups, group keeps order in which nodes were added and
+//! contains each node once. Group is created when first node is added and removed when
+//! last node is removed from it. Groups are saved together with scene.
+//!
+//! Dead handles are pruned automatically: `Scene::remove_node` removes node and its
+//! descendants from all groups right away, nodes removed directly from graph are removed
+//! from groups at the beginning of next `Scene::update`.
+//!
+//! ```no_run
+//! use rg3d::scene::Scene;
+//!
+//! fn collect_pickups(scene: &mut Scene) {
+//!     let player_position = scene.graph[scene.groups.first("player")].global_position();
+//!     for &pickup in scene.groups.nodes("pickups") {
+//!         if (scene.graph[pickup].global_position() - player_position).len() < 1.0 {
+//!             scene.commands.remove(pickup);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use crate::{
+    core::{
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// See module docs.
+#[derive(Clone, Debug, Default)]
+pub struct NodeGroups {
+    groups: HashMap<String, Vec<Handle<Node>>>,
+}
+
+impl NodeGroups {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds node to group, returns false if node is already in group.
+    pub fn add(&mut self, group: &str, node: Handle<Node>) -> bool {
+        let nodes = self.groups.entry(group.to_owned()).or_default();
+        if nodes.contains(&node) {
+            false
+        } else {
+            nodes.push(node);
+            true
+        }
+    }
+
+    /// Removes node from group, returns false if node is not in group.
+    pub fn remove(&mut self, group: &str, node: Handle<Node>) -> bool {
+        let removed = match self.groups.get_mut(group) {
+            Some(nodes) => match nodes.iter().position(|&n| n == node) {
+                Some(index) => {
+                    nodes.remove(index);
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        };
+        if removed && self.groups[group].is_empty() {
+            self.groups.remove(group);
+        }
+        removed
+    }
+
+    /// Removes node from every group.
+    pub fn remove_node(&mut self, node: Handle<Node>) {
+        self.retain(|n| n != node);
+    }
+
+    /// Removes whole group, returns its nodes.
+    pub fn remove_group(&mut self, group: &str) -> Vec<Handle<Node>> {
+        self.groups.remove(group).unwrap_or_default()
+    }
+
+    /// Returns nodes of group in order of addition, empty slice if there is no such group.
+    pub fn nodes(&self, group: &str) -> &[Handle<Node>] {
+        self.groups.get(group).map_or(&[], |nodes| nodes.as_slice())
+    }
+
+    /// Returns first node of group or `Handle::NONE`, handy for groups of one node like
+    /// "player".
+    pub fn first(&self, group: &str) -> Handle<Node> {
+        self.nodes(group).first().cloned().unwrap_or(Handle::NONE)
+    }
+
+    pub fn contains(&self, group: &str, node: Handle<Node>) -> bool {
+        self.nodes(group).contains(&node)
+    }
+
+    /// Returns names of groups which contain given node.
+    pub fn groups_of(&self, node: Handle<Node>) -> impl Iterator<Item=&str> {
+        self.groups.iter()
+            .filter(move |(_, nodes)| nodes.contains(&node))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Returns names of all groups, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.groups.keys().map(|name| name.as_str())
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+
+    fn retain<F>(&mut self, mut func: F) where F: FnMut(Handle<Node>) -> bool {
+        for nodes in self.groups.values_mut() {
+            nodes.retain(|&node| func(node));
+        }
+        self.groups.retain(|_, nodes| !nodes.is_empty());
+    }
+
+    /// Removes handles of nodes which are not in graph anymore.
+    pub fn prune(&mut self, graph: &Graph) {
+        self.retain(|node| graph.is_valid_handle(node));
+    }
+
+    /// Adds nodes of other groups to these groups using old-to-new node mapping, nodes which
+    /// are not in mapping are skipped.
+    pub(in crate) fn append_remapped(&mut self, other: &NodeGroups, old_new_map: &HashMap<Handle<Node>, Handle<Node>>) {
+        for (name, nodes) in other.groups.iter() {
+            for node in nodes.iter() {
+                if let Some(&new_node) = old_new_map.get(node) {
+                    self.add(name, new_node);
+                }
+            }
+        }
+    }
+}
+
+impl Visit for NodeGroups {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.groups.visit("Groups", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{
+        graph::Graph,
+        node::Node,
+        base::Base,
+        group::NodeGroups,
+    };
+
+    #[test]
+    fn test_node_groups() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(Node::Base(Base::default()));
+        let b = graph.add_node(Node::Base(Base::default()));
+
+        let mut groups = NodeGroups::new();
+        assert!(groups.add("enemies", a));
+        assert!(groups.add("enemies", b));
+        assert!(!groups.add("enemies", a));
+        assert!(groups.add("bosses", b));
+        assert_eq!(groups.nodes("enemies"), &[a, b]);
+        assert_eq!(groups.groups_of(b).count(), 2);
+
+        assert!(groups.remove("bosses", b));
+        assert_eq!(groups.names().count(), 1);
+
+        graph.remove_node(a);
+        groups.prune(&graph);
+        assert_eq!(groups.nodes("enemies"), &[b]);
+        assert_eq!(groups.first("enemies"), b);
+        assert!(groups.nodes("pickups").is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+//! Impostors - billboard level of detail for complex objects.
+//!
+//! Impostor is a sprite which shows pre-rendered image of an object instead of the object
+//! itself. Images are rendered from a set of viewpoints around vertical axis of object and
+//! packed into an atlas, see `Renderer::bake_impostor`. When observer is farther than switch
+//! distance, detailed object is hidden and billboard shows a frame of atlas which was
+//! rendered from direction closest to direction to observer. This makes distant trees,
+//! rocks, buildings, etc. very cheap to render.
+//!
+//! Observer is the first enabled camera of graph. Atlas texture is created at runtime and
+//! it is not saved, so impostors must be baked again after scene is loaded.
+
+use std::f32::consts::PI;
+use crate::{
+    core::{
+        math::{
+            Rect,
+            vec3::Vec3,
+        },
+        visitor::{
+            Visit,
+            VisitResult,
+            Visitor,
+        },
+        pool::{
+            Pool,
+            Handle,
+            PoolIterator,
+            PoolIteratorMut,
+            PoolPairIterator,
+        },
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Parameters of impostor baking.
+#[derive(Copy, Clone, Debug)]
+pub struct ImpostorSettings {
+    /// Amount of viewpoints evenly distributed around vertical axis of object.
+    pub view_count: u32,
+    /// Size of one frame of atlas in pixels, frames are square.
+    pub frame_size: u32,
+    /// Distance from observer at which detailed object is replaced by billboard.
+    pub switch_distance: f32,
+}
+
+impl Default for ImpostorSettings {
+    fn default() -> Self {
+        Self {
+            view_count: 8,
+            frame_size: 128,
+            switch_distance: 50.0,
+        }
+    }
+}
+
+impl ImpostorSettings {
+    /// Returns amount of columns of atlas grid, atlas is as square as possible.
+    pub fn atlas_columns(&self) -> u32 {
+        (self.view_count as f32).sqrt().ceil().max(1.0) as u32
+    }
+
+    /// Returns amount of rows of atlas grid.
+    pub fn atlas_rows(&self) -> u32 {
+        let columns = self.atlas_columns();
+        (self.view_count.max(1) + columns - 1) / columns
+    }
+
+    /// Returns angle of viewpoint around vertical axis of object.
+    pub fn view_angle(&self, view: u32) -> f32 {
+        2.0 * PI * view as f32 / self.view_count.max(1) as f32
+    }
+}
+
+/// Switches between detailed object and its billboard, see module docs.
+#[derive(Clone, Debug)]
+pub struct ImpostorLod {
+    detailed: Handle<Node>,
+    billboard: Handle<Node>,
+    settings: ImpostorSettings,
+}
+
+impl Default for ImpostorLod {
+    fn default() -> Self {
+        Self {
+            detailed: Handle::NONE,
+            billboard: Handle::NONE,
+            settings: Default::default(),
+        }
+    }
+}
+
+impl ImpostorLod {
+    /// Creates new level of detail, `billboard` must be a sprite with atlas baked with the
+    /// same settings.
+    pub fn new(detailed: Handle<Node>, billboard: Handle<Node>, settings: ImpostorSettings) -> Self {
+        Self {
+            detailed,
+            billboard,
+            settings,
+        }
+    }
+
+    pub fn detailed(&self) -> Handle<Node> {
+        self.detailed
+    }
+
+    pub(in crate) fn set_detailed(&mut self, detailed: Handle<Node>) {
+        self.detailed = detailed;
+    }
+
+    pub fn billboard(&self) -> Handle<Node> {
+        self.billboard
+    }
+
+    pub(in crate) fn set_billboard(&mut self, billboard: Handle<Node>) {
+        self.billboard = billboard;
+    }
+
+    pub fn settings(&self) -> &ImpostorSettings {
+        &self.settings
+    }
+
+    pub fn set_switch_distance(&mut self, distance: f32) -> &mut Self {
+        self.settings.switch_distance = distance.max(0.0);
+        self
+    }
+
+    /// Returns texture coordinates of atlas frame.
+    pub fn frame_uv_rect(&self, view: u32) -> Rect<f32> {
+        let columns = self.settings.atlas_columns();
+        let rows = self.settings.atlas_rows();
+        Rect::new(
+            (view % columns) as f32 / columns as f32,
+            (view / columns) as f32 / rows as f32,
+            1.0 / columns as f32,
+            1.0 / rows as f32,
+        )
+    }
+
+    fn update(&self, graph: &mut Graph, observer: Vec3) {
+        let detailed_transform = graph[self.detailed].global_transform();
+        let center = graph[self.billboard].global_position();
+        let to_observer = observer - center;
+
+        let far = to_observer.len() > self.settings.switch_distance;
+        graph[self.detailed].set_visibility(!far);
+        graph[self.billboard].set_visibility(far);
+
+        if far {
+            // Direction to observer in horizontal plane of object defines frame.
+            let x = to_observer.dot(&detailed_transform.side());
+            let z = to_observer.dot(&detailed_transform.look());
+            let angle = x.atan2(z).rem_euclid(2.0 * PI);
+            let step = self.settings.view_angle(1);
+            let view = (angle / step).round() as u32 % self.settings.view_count.max(1);
+            let uv_rect = self.frame_uv_rect(view);
+            if let Node::Sprite(sprite) = &mut graph[self.billboard] {
+                sprite.set_uv_rect(uv_rect);
+            }
+        }
+    }
+}
+
+impl Visit for ImpostorLod {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.detailed.visit("Detailed", visitor)?;
+        self.billboard.visit("Billboard", visitor)?;
+        self.settings.view_count.visit("ViewCount", visitor)?;
+        self.settings.frame_size.visit("FrameSize", visitor)?;
+        self.settings.switch_distance.visit("SwitchDistance", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct ImpostorLodContainer {
+    pool: Pool<ImpostorLod>
+}
+
+impl Default for ImpostorLodContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImpostorLodContainer {
+    pub(in crate) fn new() -> Self {
+        Self {
+            pool: Pool::new()
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> PoolIterator<ImpostorLod> {
+        self.pool.iter()
+    }
+
+    #[inline]
+    pub fn pair_iter(&self) -> PoolPairIterator<ImpostorLod> {
+        self.pool.pair_iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<ImpostorLod> {
+        self.pool.iter_mut()
+    }
+
+    #[inline]
+    pub fn add(&mut self, lod: ImpostorLod) -> Handle<ImpostorLod> {
+        self.pool.spawn(lod)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, handle: Handle<ImpostorLod>) {
+        self.pool.free(handle);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.pool.clear()
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<ImpostorLod>) -> &ImpostorLod {
+        self.pool.borrow(handle)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<ImpostorLod>) -> &mut ImpostorLod {
+        self.pool.borrow_mut(handle)
+    }
+
+    #[inline]
+    pub fn retain<P>(&mut self, pred: P) where P: FnMut(&ImpostorLod) -> bool {
+        self.pool.retain(pred)
+    }
+
+    pub fn update(&mut self, graph: &mut Graph) {
+        let observer = graph.linear_iter().find_map(|node| {
+            if let Node::Camera(camera) = node {
+                if camera.is_enabled() && camera.is_globally_enabled() {
+                    return Some(camera.global_position());
+                }
+            }
+            None
+        });
+
+        if let Some(observer) = observer {
+            for lod in self.pool.iter() {
+                if graph.is_valid_handle(lod.detailed) && graph.is_valid_handle(lod.billboard) {
+                    lod.update(graph, observer);
+                }
+            }
+        }
+    }
+
+    pub fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone()
+        }
+    }
+}
+
+impl Visit for ImpostorLodContainer {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
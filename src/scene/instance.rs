@@ -0,0 +1,157 @@
+//! Per-instance overrides of nodes instantiated from model resources.
+//!
+//! Instance of a model (see `Model::instantiate`) is a copy of the nodes of its resource.
+//! Level designers often tweak instances: move a lamp on one table, swap texture of one
+//! barrel, disable a broken window of one house. Such changes must survive re-import of
+//! the model, otherwise every change of the source model wipes work done on the level.
+//!
+//! Overrides record which properties of a node were changed on the instance. When model
+//! resource is reloaded, `Graph::sync_instances` copies properties of resource nodes to
+//! their instances, except overridden ones (`Scene::sync_instances` also rebinds tracks of
+//! animations):
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, node::Node};
+//! use rg3d::core::pool::Handle;
+//! use rg3d::engine::resource_manager::SharedTexture;
+//!
+//! fn swap_texture(scene: &mut Scene, barrel: Handle<Node>, texture: SharedTexture) {
+//!     if let Node::Mesh(mesh) = &mut scene.graph[barrel] {
+//!         mesh.overrides_mut().surface_mut(0).diffuse_texture = Some(texture);
+//!     }
+//!     scene.graph.apply_overrides(barrel);
+//! }
+//! ```
+//!
+//! Overrides are stored together with the node when scene is saved. Root of an instance
+//! is never synced: its transform is a placement of the instance on the level.
+
+use crate::{
+    core::visitor::{Visit, VisitResult, Visitor},
+    engine::resource_manager::{SharedTexture, SharedMaterial},
+    renderer::surface::Surface,
+};
+
+/// Replaced textures and material of a surface, `None` means that value of resource is used.
+#[derive(Clone, Default)]
+pub struct SurfaceOverride {
+    /// Index of surface in mesh.
+    pub surface: u32,
+    pub diffuse_texture: Option<SharedTexture>,
+    pub normal_texture: Option<SharedTexture>,
+    pub material: Option<SharedMaterial>,
+}
+
+impl Visit for SurfaceOverride {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.surface.visit("Surface", visitor)?;
+        self.diffuse_texture.visit("DiffuseTexture", visitor)?;
+        self.normal_texture.visit("NormalTexture", visitor)?;
+        self.material.visit("Material", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl SurfaceOverride {
+    /// Applies overridden values to given surface.
+    pub fn apply(&self, surface: &mut Surface) {
+        if let Some(texture) = self.diffuse_texture.clone() {
+            surface.set_diffuse_texture(texture);
+        }
+        if let Some(texture) = self.normal_texture.clone() {
+            surface.set_normal_texture(texture);
+        }
+        if self.material.is_some() {
+            surface.set_material(self.material.clone());
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Default)]
+pub struct InstanceOverrides {
+    /// Local transform of node is not taken from resource.
+    pub local_transform: bool,
+    /// Visibility of node is not taken from resource.
+    pub visibility: bool,
+    /// Enabled state of node is not taken from resource, set it to keep a child of
+    /// instance disabled.
+    pub enabled: bool,
+    surfaces: Vec<SurfaceOverride>,
+}
+
+impl Visit for InstanceOverrides {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.local_transform.visit("LocalTransform", visitor)?;
+        self.visibility.visit("Visibility", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+        self.surfaces.visit("Surfaces", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+impl InstanceOverrides {
+    /// Returns true if nothing is overridden.
+    pub fn is_empty(&self) -> bool {
+        !self.local_transform && !self.visibility && !self.enabled && self.surfaces.is_empty()
+    }
+
+    /// Returns override of surface with given index, if any.
+    pub fn surface(&self, surface: u32) -> Option<&SurfaceOverride> {
+        self.surfaces.iter().find(|s| s.surface == surface)
+    }
+
+    /// Returns override of surface with given index, empty override is created if there
+    /// is none.
+    pub fn surface_mut(&mut self, surface: u32) -> &mut SurfaceOverride {
+        match self.surfaces.iter().position(|s| s.surface == surface) {
+            Some(index) => &mut self.surfaces[index],
+            None => {
+                self.surfaces.push(SurfaceOverride { surface, ..Default::default() });
+                self.surfaces.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Removes override of surface with given index, resource values will be used on
+    /// next sync.
+    pub fn remove_surface(&mut self, surface: u32) {
+        self.surfaces.retain(|s| s.surface != surface)
+    }
+
+    pub fn surfaces(&self) -> &[SurfaceOverride] {
+        &self.surfaces
+    }
+
+    /// Removes every override.
+    pub fn clear(&mut self) {
+        *self = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::instance::InstanceOverrides;
+
+    #[test]
+    fn test_surface_overrides() {
+        let mut overrides = InstanceOverrides::default();
+        assert!(overrides.is_empty());
+
+        overrides.surface_mut(2);
+        overrides.surface_mut(2);
+        assert_eq!(overrides.surfaces().len(), 1);
+        assert!(overrides.surface(2).is_some());
+        assert!(!overrides.is_empty());
+
+        overrides.remove_surface(2);
+        assert!(overrides.surface(2).is_none());
+        assert!(overrides.is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! Lens flare is a set of bright spots and rings (elements) which appear on screen when
+//! camera looks at bright light source, like the sun.
+//!
+//! Lens flare is attached to light node. Elements are placed on a line which goes from
+//! light position on screen through center of screen, so they move when camera rotates.
+//! Flare is faded out when light is occluded by something, occlusion is tested using
+//! depth buffer of frame around light position on screen.
+
+use crate::{
+    core::{
+        color::Color,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+    resource::texture::Texture,
+};
+use std::sync::{Arc, Mutex};
+
+/// Single sprite of lens flare.
+#[derive(Clone, Debug)]
+pub struct LensFlareElement {
+    /// Texture of element, `None` means white quad. Only first channel of texture is used.
+    pub texture: Option<Arc<Mutex<Texture>>>,
+    /// Position of element on line from light to center of screen, zero is light position,
+    /// 0.5 is center of screen, one is the point mirrored from light through center.
+    pub offset: f32,
+    /// Size of element relative to height of frame.
+    pub size: f32,
+    pub color: Color,
+}
+
+impl Default for LensFlareElement {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            offset: 0.0,
+            size: 0.1,
+            color: Color::WHITE,
+        }
+    }
+}
+
+impl Visit for LensFlareElement {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.texture.visit("Texture", visitor)?;
+        self.offset.visit("Offset", visitor)?;
+        self.size.visit("Size", visitor)?;
+        self.color.visit("Color", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LensFlare {
+    elements: Vec<LensFlareElement>,
+    intensity: f32,
+    occlusion_radius: f32,
+}
+
+impl Default for LensFlare {
+    fn default() -> Self {
+        Self {
+            elements: Vec::new(),
+            intensity: 1.0,
+            occlusion_radius: 0.01,
+        }
+    }
+}
+
+impl LensFlare {
+    pub fn new(elements: Vec<LensFlareElement>) -> Self {
+        Self {
+            elements,
+            ..Default::default()
+        }
+    }
+
+    pub fn elements(&self) -> &[LensFlareElement] {
+        &self.elements
+    }
+
+    pub fn elements_mut(&mut self) -> &mut Vec<LensFlareElement> {
+        &mut self.elements
+    }
+
+    /// Sets brightness multiplier for all elements.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Sets radius of area (relative to height of frame) around light position on screen
+    /// which is checked for occluders. Partially occluded area partially fades flare, so
+    /// larger radius gives smoother fade when light goes behind an object.
+    pub fn set_occlusion_radius(&mut self, radius: f32) {
+        self.occlusion_radius = radius.max(0.0);
+    }
+
+    pub fn occlusion_radius(&self) -> f32 {
+        self.occlusion_radius
+    }
+}
+
+impl Visit for LensFlare {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.elements.visit("Elements", visitor)?;
+        self.intensity.visit("Intensity", visitor)?;
+        self.occlusion_radius.visit("OcclusionRadius", visitor)?;
+
+        visitor.leave_region()
+    }
+}
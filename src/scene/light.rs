@@ -15,11 +15,16 @@
 //! Most of light sources supports shadows (via shadows maps) and light scattering,
 //! these are common effects for modern games but still can significantly impact
 //! performance.
+//!
+//! Small lights can be switched off smoothly when they're far from camera using distance
+//! fade, see `Light::set_distance_fade`. Intensity of torches, candles and broken lamps can
+//! be modulated over time by flicker, see `Light::set_flicker`.
 
 #![warn(missing_docs)]
 
 use crate::{
     core::{
+        pool::Handle,
         color::Color,
         visitor::{
             Visit,
@@ -28,17 +33,32 @@ use crate::{
         },
         math::vec3::Vec3,
     },
-    scene::base::{
-        BaseBuilder,
-        Base,
+    scene::{
+        base::{
+            BaseBuilder,
+            Base,
+        },
+        lens_flare::LensFlare,
+        distance_fade::DistanceFade,
+        flicker::LightFlicker,
+        graph::Graph,
+        node::Node,
     },
+    resource::texture::Texture,
+};
+use std::{
+    ops::{DerefMut, Deref},
+    sync::{Arc, Mutex},
 };
-use std::ops::{DerefMut, Deref};
 
 /// Default amount of light scattering, it is set to 3% which is fairly
 /// significant value and you'll clearly see light volume with such settings.
 pub const DEFAULT_SCATTER: Vec3 = Vec3::new(0.03, 0.03, 0.03);
 
+/// Default intensity of light, it gives reasonable brightness for lights with
+/// default radius.
+pub const DEFAULT_INTENSITY: f32 = 10.0;
+
 /// Spot light is can be imagined as flash light - it has direction and cone
 /// shape of light volume. It defined by two angles:
 /// 1) Hot spot inner angle - this is zone where intensity of light is max.
@@ -47,8 +67,9 @@ pub const DEFAULT_SCATTER: Vec3 = Vec3::new(0.03, 0.03, 0.03);
 /// two angles will have smooth transition.
 ///
 /// Same as point lights, spot lights have distance attenuation which defines
-/// how intensity of light changes over distance to point in world. Currently
-/// engine uses inverse square root law of distance attenuation.
+/// how intensity of light changes over distance to point in world. Engine uses
+/// inverse square law of distance attenuation with smooth cutoff at distance
+/// of light, so distance works as radius of light.
 ///
 /// # Light scattering
 ///
@@ -58,6 +79,12 @@ pub const DEFAULT_SCATTER: Vec3 = Vec3::new(0.03, 0.03, 0.03);
 /// should be used carefully with sane values of light scattering, otherwise you'll
 /// get bright glowing cone instead of slightly visible light volume.
 ///
+/// # Cookies
+///
+/// Spot light can have cookie texture - texture which is projected by light on
+/// surfaces and modulates color of light, it can be used to fake window frames,
+/// patterns of flash light, caustics and so on.
+///
 /// # Performance notes
 ///
 /// Light scattering feature may significantly impact performance on low-end
@@ -67,6 +94,7 @@ pub struct SpotLight {
     hotspot_cone_angle: f32,
     falloff_angle_delta: f32,
     distance: f32,
+    cookie_texture: Option<Arc<Mutex<Texture>>>,
 }
 
 impl Default for SpotLight {
@@ -75,6 +103,7 @@ impl Default for SpotLight {
             hotspot_cone_angle: 90.0f32.to_radians(),
             falloff_angle_delta: 5.0f32.to_radians(),
             distance: 10.0,
+            cookie_texture: None,
         }
     }
 }
@@ -87,6 +116,7 @@ impl SpotLight {
             hotspot_cone_angle: hotspot_cone_angle.abs(),
             falloff_angle_delta: falloff_angle_delta.abs(),
             distance,
+            cookie_texture: None,
         }
     }
 
@@ -123,7 +153,7 @@ impl SpotLight {
     }
 
     /// Sets maximum distance at which light intensity will be zero. Intensity
-    /// of light will be calculated using inverse square root law.
+    /// of light will be calculated using inverse square law.
     #[inline]
     pub fn set_distance(&mut self, distance: f32) -> &mut Self {
         self.distance = distance.abs();
@@ -135,6 +165,20 @@ impl SpotLight {
     pub fn distance(&self) -> f32 {
         self.distance
     }
+
+    /// Sets cookie texture of light. Texture is projected along direction of light
+    /// and covers whole cone of light. `None` removes cookie.
+    #[inline]
+    pub fn set_cookie_texture(&mut self, texture: Option<Arc<Mutex<Texture>>>) -> &mut Self {
+        self.cookie_texture = texture;
+        self
+    }
+
+    /// Returns cookie texture of light, if any.
+    #[inline]
+    pub fn cookie_texture(&self) -> Option<Arc<Mutex<Texture>>> {
+        self.cookie_texture.clone()
+    }
 }
 
 impl Visit for SpotLight {
@@ -144,6 +188,7 @@ impl Visit for SpotLight {
         self.hotspot_cone_angle.visit("HotspotConeAngle", visitor)?;
         self.falloff_angle_delta.visit("FalloffAngleDelta", visitor)?;
         self.distance.visit("Distance", visitor)?;
+        self.cookie_texture.visit("CookieTexture", visitor)?;
 
         visitor.leave_region()
     }
@@ -152,7 +197,11 @@ impl Visit for SpotLight {
 /// Point light can be represented as light bulb which hangs on wire - it is
 /// spherical light source which emits light in all directions. It has single
 /// parameter - radius at which intensity will be zero. Intensity of light will
-/// be calculated using inverse square root law.
+/// be calculated using inverse square law, which is smoothly windowed to reach
+/// zero at radius. Brightness of light is defined by intensity of light node.
+///
+/// Radius is also used by renderer to skip lights which does not touch any
+/// visible mesh, so keep it as small as possible.
 ///
 /// # Light scattering
 ///
@@ -269,9 +318,13 @@ pub struct Light {
     base: Base,
     kind: LightKind,
     color: Color,
+    intensity: f32,
     cast_shadows: bool,
     scatter: Vec3,
     scatter_enabled: bool,
+    lens_flare: Option<LensFlare>,
+    distance_fade: Option<DistanceFade>,
+    flicker: Option<LightFlicker>,
 }
 
 impl Deref for Light {
@@ -294,9 +347,13 @@ impl Default for Light {
             base: Default::default(),
             kind: LightKind::Point(Default::default()),
             color: Color::WHITE,
+            intensity: DEFAULT_INTENSITY,
             cast_shadows: true,
             scatter: DEFAULT_SCATTER,
             scatter_enabled: true,
+            lens_flare: None,
+            distance_fade: None,
+            flicker: None,
         }
     }
 }
@@ -316,6 +373,10 @@ impl Visit for Light {
         self.cast_shadows.visit("CastShadows", visitor)?;
         self.scatter.visit("ScatterFactor", visitor)?;
         self.scatter_enabled.visit("ScatterEnabled", visitor)?;
+        self.intensity.visit("Intensity", visitor)?;
+        self.lens_flare.visit("LensFlare", visitor)?;
+        self.distance_fade.visit("DistanceFade", visitor)?;
+        self.flicker.visit("Flicker", visitor)?;
 
         visitor.leave_region()
     }
@@ -356,6 +417,21 @@ impl Light {
         &mut self.kind
     }
 
+    /// Sets intensity of light. Intensity is a multiplier for color of light, it can be
+    /// greater than one. Since lights use inverse square falloff, intensity must be high
+    /// enough to light surfaces at far distance, for example intensity of light with
+    /// radius of 10 meters should be around 10-20.
+    #[inline]
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    /// Returns current intensity of light.
+    #[inline]
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
     /// Enables or disables shadows for light source.
     #[inline]
     pub fn set_cast_shadows(&mut self, value: bool) {
@@ -398,6 +474,77 @@ impl Light {
     pub fn is_scatter_enabled(&self) -> bool {
         self.scatter_enabled
     }
+
+    /// Sets lens flare of light, `None` removes flare. Flare of directional light is
+    /// placed infinitely far along direction to light, like the sun.
+    #[inline]
+    pub fn set_lens_flare(&mut self, lens_flare: Option<LensFlare>) {
+        self.lens_flare = lens_flare;
+    }
+
+    /// Returns lens flare of light, if any.
+    #[inline]
+    pub fn lens_flare(&self) -> Option<&LensFlare> {
+        self.lens_flare.as_ref()
+    }
+
+    /// Returns mutable reference to lens flare of light, if any.
+    #[inline]
+    pub fn lens_flare_mut(&mut self) -> Option<&mut LensFlare> {
+        self.lens_flare.as_mut()
+    }
+
+    /// Sets distance fade of light, `None` disables fading. Intensity of light (with its
+    /// scattering and lens flare) is scaled by fade factor of distance to camera, light is
+    /// not rendered at all when it is completely faded out.
+    #[inline]
+    pub fn set_distance_fade(&mut self, distance_fade: Option<DistanceFade>) {
+        self.distance_fade = distance_fade;
+    }
+
+    /// Returns distance fade of light, if any.
+    #[inline]
+    pub fn distance_fade(&self) -> Option<DistanceFade> {
+        self.distance_fade
+    }
+
+    /// Returns fade factor of light for camera at given position, one if light has no
+    /// distance fade.
+    #[inline]
+    pub fn fade_factor(&self, camera_position: Vec3) -> f32 {
+        self.distance_fade.map_or(1.0, |fade| fade.factor((self.global_position() - camera_position).len()))
+    }
+
+    /// Sets flicker of light, `None` disables flicker. Intensity of light (with its
+    /// scattering and lens flare) is scaled by current factor of flicker.
+    #[inline]
+    pub fn set_flicker(&mut self, flicker: Option<LightFlicker>) {
+        self.flicker = flicker;
+    }
+
+    /// Returns flicker of light, if any.
+    #[inline]
+    pub fn flicker(&self) -> Option<&LightFlicker> {
+        self.flicker.as_ref()
+    }
+
+    /// Returns mutable reference to flicker of light, if any.
+    #[inline]
+    pub fn flicker_mut(&mut self) -> Option<&mut LightFlicker> {
+        self.flicker.as_mut()
+    }
+
+    /// Returns current multiplier of intensity from flicker, one if light has no flicker.
+    #[inline]
+    pub fn flicker_factor(&self) -> f32 {
+        self.flicker.as_ref().map_or(1.0, |flicker| flicker.factor())
+    }
+
+    pub(in crate) fn update(&mut self, dt: f32) {
+        if let Some(flicker) = self.flicker.as_mut() {
+            flicker.update(dt);
+        }
+    }
 }
 
 /// Light scene node builder. Provides easy declarative way of creating light scene
@@ -406,9 +553,13 @@ pub struct LightBuilder {
     base_builder: BaseBuilder,
     kind: LightKind,
     color: Color,
+    intensity: f32,
     cast_shadows: bool,
     scatter_factor: Vec3,
     scatter_enabled: bool,
+    lens_flare: Option<LensFlare>,
+    distance_fade: Option<DistanceFade>,
+    flicker: Option<LightFlicker>,
 }
 
 impl LightBuilder {
@@ -421,9 +572,13 @@ impl LightBuilder {
             base_builder,
             kind,
             color: Color::WHITE,
+            intensity: DEFAULT_INTENSITY,
             cast_shadows: true,
             scatter_factor: DEFAULT_SCATTER,
             scatter_enabled: true,
+            lens_flare: None,
+            distance_fade: None,
+            flicker: None,
         }
     }
 
@@ -433,6 +588,12 @@ impl LightBuilder {
         self
     }
 
+    /// Sets light intensity.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
     /// Sets whether to casts shadows or not.
     pub fn cast_shadows(mut self, cast_shadows: bool) -> Self {
         self.cast_shadows = cast_shadows;
@@ -451,6 +612,24 @@ impl LightBuilder {
         self
     }
 
+    /// Sets lens flare of light.
+    pub fn with_lens_flare(mut self, lens_flare: LensFlare) -> Self {
+        self.lens_flare = Some(lens_flare);
+        self
+    }
+
+    /// Sets desired distance fade of light.
+    pub fn with_distance_fade(mut self, distance_fade: DistanceFade) -> Self {
+        self.distance_fade = Some(distance_fade);
+        self
+    }
+
+    /// Sets desired flicker of light.
+    pub fn with_flicker(mut self, flicker: LightFlicker) -> Self {
+        self.flicker = Some(flicker);
+        self
+    }
+
     /// Creates new instance of light scene node. Warning: each scene node
     /// must be added to scene, otherwise it won't have any effect and most
     /// likely will be dropped as soon as it go out of scope.
@@ -459,9 +638,21 @@ impl LightBuilder {
             base: self.base_builder.build(),
             kind: self.kind,
             color: self.color,
+            intensity: self.intensity.max(0.0),
             cast_shadows: self.cast_shadows,
             scatter: self.scatter_factor,
             scatter_enabled: self.scatter_enabled,
+            lens_flare: self.lens_flare,
+            distance_fade: self.distance_fade,
+            flicker: self.flicker,
         }
     }
+
+    /// Creates new light node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Light(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
 }
\ No newline at end of file
@@ -0,0 +1,212 @@
+//! Light probes - baked ambient lighting for dynamic objects.
+//!
+//! Light probe stores light which comes to a point of space from six directions (so called
+//! ambient cube). Probes are placed in a regular grid over some volume of scene and baked
+//! by rendering static geometry around each probe, see `Renderer::bake_light_probes`. Meshes
+//! with light probes enabled (see `Mesh::set_use_light_probes`) take ambient light from
+//! probes interpolated at their position instead of global ambient color, so a character
+//! that walks from sunlit street into dark tunnel gets darker too.
+//!
+//! Probes are saved with scene, so there is no need to bake them again after load.
+
+use crate::core::{
+    math::{
+        vec3::Vec3,
+        aabb::AxisAlignedBoundingBox,
+    },
+    visitor::{
+        Visit,
+        VisitResult,
+        Visitor,
+    },
+};
+
+/// Light coming from six directions, order of directions is +X, -X, +Y, -Y, +Z, -Z.
+/// Components of colors are in [0; 1] range.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AmbientCube {
+    pub colors: [Vec3; 6],
+}
+
+impl AmbientCube {
+    /// Directions of faces of cube in order of colors.
+    pub const DIRECTIONS: [Vec3; 6] = [
+        Vec3 { x: 1.0, y: 0.0, z: 0.0 },
+        Vec3 { x: -1.0, y: 0.0, z: 0.0 },
+        Vec3 { x: 0.0, y: 1.0, z: 0.0 },
+        Vec3 { x: 0.0, y: -1.0, z: 0.0 },
+        Vec3 { x: 0.0, y: 0.0, z: 1.0 },
+        Vec3 { x: 0.0, y: 0.0, z: -1.0 },
+    ];
+
+    /// Returns light which comes to surface with given normal.
+    pub fn evaluate(&self, normal: Vec3) -> Vec3 {
+        let pick = |value: f32, positive: usize| {
+            let color = if value >= 0.0 { self.colors[positive] } else { self.colors[positive + 1] };
+            color.scale(value * value)
+        };
+        pick(normal.x, 0) + pick(normal.y, 2) + pick(normal.z, 4)
+    }
+
+    fn lerp(&self, other: &AmbientCube, t: f32) -> AmbientCube {
+        let mut result = *self;
+        for (color, other) in result.colors.iter_mut().zip(other.colors.iter()) {
+            *color = *color + (*other - *color).scale(t);
+        }
+        result
+    }
+}
+
+impl Visit for AmbientCube {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.colors[0].visit("PositiveX", visitor)?;
+        self.colors[1].visit("NegativeX", visitor)?;
+        self.colors[2].visit("PositiveY", visitor)?;
+        self.colors[3].visit("NegativeY", visitor)?;
+        self.colors[4].visit("PositiveZ", visitor)?;
+        self.colors[5].visit("NegativeZ", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Regular grid of light probes. Empty grid means that scene has no probes and all meshes
+/// use global ambient color.
+#[derive(Clone, Debug, Default)]
+pub struct LightProbeGrid {
+    origin: Vec3,
+    spacing: f32,
+    size: [u32; 3],
+    probes: Vec<AmbientCube>,
+}
+
+impl LightProbeGrid {
+    /// Creates grid of black probes which covers given bounds, probes are placed every
+    /// `spacing` units starting from minimum of bounds.
+    pub fn new(bounds: &AxisAlignedBoundingBox, spacing: f32) -> Self {
+        let spacing = spacing.max(0.001);
+        let extent = bounds.max - bounds.min;
+        let count = |length: f32| (length.max(0.0) / spacing).floor() as u32 + 1;
+        let size = [count(extent.x), count(extent.y), count(extent.z)];
+        Self {
+            origin: bounds.min,
+            spacing,
+            size,
+            probes: vec![AmbientCube::default(); (size[0] * size[1] * size[2]) as usize],
+        }
+    }
+
+    pub(in crate) fn shift_origin(&mut self, offset: Vec3) {
+        self.origin -= offset;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+
+    /// Returns amount of probes along each axis.
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    pub fn len(&self) -> usize {
+        self.probes.len()
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + y * self.size[0] + z * self.size[0] * self.size[1]) as usize
+    }
+
+    /// Returns world position of probe with given index.
+    pub fn probe_position(&self, index: usize) -> Vec3 {
+        let index = index as u32;
+        let x = index % self.size[0];
+        let y = (index / self.size[0]) % self.size[1];
+        let z = index / (self.size[0] * self.size[1]);
+        self.origin + Vec3::new(x as f32, y as f32, z as f32).scale(self.spacing)
+    }
+
+    pub fn probes(&self) -> &[AmbientCube] {
+        &self.probes
+    }
+
+    pub fn probes_mut(&mut self) -> &mut [AmbientCube] {
+        &mut self.probes
+    }
+
+    /// Returns ambient cube at given position, it is trilinearly interpolated from eight
+    /// nearest probes. Positions out of grid are clamped to grid bounds. Returns `None`
+    /// if grid is empty.
+    pub fn sample(&self, position: Vec3) -> Option<AmbientCube> {
+        if self.probes.is_empty() {
+            return None;
+        }
+
+        let local = (position - self.origin).scale(1.0 / self.spacing);
+        let axis = |value: f32, size: u32| {
+            let max = (size - 1) as f32;
+            let value = value.max(0.0).min(max);
+            let i = (value.floor() as u32).min(size.saturating_sub(2));
+            (i, (i + 1).min(size - 1), value - i as f32)
+        };
+        let (x0, x1, tx) = axis(local.x, self.size[0]);
+        let (y0, y1, ty) = axis(local.y, self.size[1]);
+        let (z0, z1, tz) = axis(local.z, self.size[2]);
+
+        let probe = |x, y, z| &self.probes[self.index(x, y, z)];
+        let lerp_x = |y, z| probe(x0, y, z).lerp(probe(x1, y, z), tx);
+        let lerp_y = |z| lerp_x(y0, z).lerp(&lerp_x(y1, z), ty);
+
+        Some(lerp_y(z0).lerp(&lerp_y(z1), tz))
+    }
+}
+
+impl Visit for LightProbeGrid {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.origin.visit("Origin", visitor)?;
+        self.spacing.visit("Spacing", visitor)?;
+        self.size[0].visit("SizeX", visitor)?;
+        self.size[1].visit("SizeY", visitor)?;
+        self.size[2].visit("SizeZ", visitor)?;
+        self.probes.visit("Probes", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::{
+            vec3::Vec3,
+            aabb::AxisAlignedBoundingBox,
+        },
+        scene::light_probe::{AmbientCube, LightProbeGrid},
+    };
+
+    #[test]
+    fn light_probe_grid_interpolation() {
+        let mut bounds = AxisAlignedBoundingBox::default();
+        bounds.add_point(Vec3::ZERO);
+        bounds.add_point(Vec3::new(2.0, 0.0, 0.0));
+        let mut grid = LightProbeGrid::new(&bounds, 2.0);
+        assert_eq!(grid.size(), [2, 1, 1]);
+
+        grid.probes_mut()[1] = AmbientCube { colors: [Vec3::new(1.0, 1.0, 1.0); 6] };
+        let cube = grid.sample(Vec3::new(0.5, 0.0, 0.0)).unwrap();
+        assert_eq!(cube.colors[0], Vec3::new(0.25, 0.25, 0.25));
+        // Out of bounds is clamped.
+        assert_eq!(grid.sample(Vec3::new(10.0, 5.0, 0.0)).unwrap().colors[3], Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(cube.evaluate(Vec3::new(0.0, -1.0, 0.0)), Vec3::new(0.25, 0.25, 0.25));
+
+        assert!(LightProbeGrid::default().sample(Vec3::ZERO).is_none());
+    }
+}
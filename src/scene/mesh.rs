@@ -19,8 +19,10 @@ use crate::{
     scene::{
         base::Base,
         graph::Graph,
+        node::Node,
     },
     core::{
+        pool::Handle,
         visitor::{
             Visit,
             Visitor,
@@ -29,6 +31,8 @@ use crate::{
         math::{
             aabb::AxisAlignedBoundingBox,
             frustum::Frustum,
+            vec3::Vec3,
+            mat4::Mat4,
         },
     },
 };
@@ -41,6 +45,10 @@ pub struct Mesh {
     surfaces: Vec<Surface>,
     bounding_box: Cell<AxisAlignedBoundingBox>,
     bounding_box_dirty: Cell<bool>,
+    cast_shadows: bool,
+    receive_shadows: bool,
+    use_light_probes: bool,
+    is_static: bool,
 }
 
 impl Default for Mesh {
@@ -50,6 +58,10 @@ impl Default for Mesh {
             surfaces: Default::default(),
             bounding_box: Default::default(),
             bounding_box_dirty: Cell::new(true),
+            cast_shadows: true,
+            receive_shadows: true,
+            use_light_probes: false,
+            is_static: false,
         }
     }
 }
@@ -73,6 +85,10 @@ impl Visit for Mesh {
         visitor.enter_region(name)?;
 
         self.base.visit("Common", visitor)?;
+        self.cast_shadows.visit("CastShadows", visitor)?;
+        self.receive_shadows.visit("ReceiveShadows", visitor)?;
+        self.use_light_probes.visit("UseLightProbes", visitor)?;
+        self.is_static.visit("IsStatic", visitor)?;
 
         // No need to serialize surfaces, correct ones will be assigned on resolve stage.
         visitor.leave_region()
@@ -92,6 +108,66 @@ impl Mesh {
         &mut self.surfaces
     }
 
+    /// Enables or disables rendering of mesh into shadow maps. Small objects and
+    /// first-person arms usually should not cast shadows.
+    #[inline]
+    pub fn set_cast_shadows(&mut self, cast_shadows: bool) {
+        self.cast_shadows = cast_shadows;
+    }
+
+    /// Returns true if mesh is rendered into shadow maps.
+    #[inline]
+    pub fn is_cast_shadows(&self) -> bool {
+        self.cast_shadows
+    }
+
+    /// Defines whether shadows of other objects will be visible on mesh or not.
+    #[inline]
+    pub fn set_receive_shadows(&mut self, receive_shadows: bool) {
+        self.receive_shadows = receive_shadows;
+    }
+
+    /// Returns true if shadows of other objects are visible on mesh.
+    #[inline]
+    pub fn is_receive_shadows(&self) -> bool {
+        self.receive_shadows
+    }
+
+    /// Marks mesh as static - part of level which does not move. Shadows of static meshes
+    /// are cached by renderer and re-rendered only when some static mesh in range of light
+    /// moves or the light itself changes, so static mesh still can be moved occasionally.
+    /// Skinned meshes are never cached, because bones can deform them at any time.
+    #[inline]
+    pub fn set_static(&mut self, is_static: bool) {
+        self.is_static = is_static;
+    }
+
+    /// Returns true if mesh is static.
+    #[inline]
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
+    /// Returns true if any surface of mesh is deformed by bones.
+    #[inline]
+    pub fn is_skinned(&self) -> bool {
+        self.surfaces.iter().any(|surface| !surface.bones.is_empty())
+    }
+
+    /// Defines whether ambient lighting of mesh is taken from light probes of scene instead
+    /// of global ambient color. Should be enabled for dynamic objects, meshes which use
+    /// light probes are not rendered when probes are baked. See `scene::light_probe`.
+    #[inline]
+    pub fn set_use_light_probes(&mut self, use_light_probes: bool) {
+        self.use_light_probes = use_light_probes;
+    }
+
+    /// Returns true if ambient lighting of mesh is taken from light probes.
+    #[inline]
+    pub fn is_use_light_probes(&self) -> bool {
+        self.use_light_probes
+    }
+
     /// Removes all surfaces from mesh.
     #[inline]
     pub fn clear_surfaces(&mut self) {
@@ -156,12 +232,64 @@ impl Mesh {
 
         false
     }
+
+    /// Checks whether bounding box of mesh in world coordinates intersects given sphere.
+    /// Skinned meshes are always considered intersecting, because their bounding box does
+    /// not take bones into account.
+    pub fn is_intersect_sphere(&self, center: Vec3, radius: f32) -> bool {
+        if self.is_skinned() {
+            return true;
+        }
+
+        let local_box = self.bounding_box();
+        let mut world_box = AxisAlignedBoundingBox::default();
+        for &x in [local_box.min.x, local_box.max.x].iter() {
+            for &y in [local_box.min.y, local_box.max.y].iter() {
+                for &z in [local_box.min.z, local_box.max.z].iter() {
+                    world_box.add_point(self.global_transform.transform_vector(Vec3::new(x, y, z)));
+                }
+            }
+        }
+
+        // Find closest point of box to center of sphere.
+        let closest = Vec3::new(
+            center.x.max(world_box.min.x).min(world_box.max.x),
+            center.y.max(world_box.min.y).min(world_box.max.y),
+            center.z.max(world_box.min.z).min(world_box.max.z),
+        );
+
+        closest.sqr_distance(&center) <= radius * radius
+    }
+
+    /// Calculates world space positions of vertices of surface with given index, skinning
+    /// is performed on CPU using current transforms of bones. Positions of non-skinned
+    /// surfaces are just transformed by global transform of mesh. Returns `None` if there
+    /// is no surface with such index.
+    pub fn world_vertex_positions(&self, graph: &Graph, surface_index: usize) -> Option<Vec<Vec3>> {
+        let surface = self.surfaces.get(surface_index)?;
+        let mut positions = Vec::new();
+        if surface.bone_count() > 0 {
+            let mut bone_matrices: Vec<Mat4> = Vec::with_capacity(surface.bone_count());
+            surface.fill_bone_matrices(graph, &mut bone_matrices);
+            surface.skinned_vertex_positions(&bone_matrices, &mut positions);
+        } else {
+            let data = surface.get_data();
+            let data = data.lock().unwrap();
+            let global_transform = self.global_transform();
+            positions.extend(data.get_vertices().iter().map(|v| global_transform.transform_vector(v.position)));
+        }
+        Some(positions)
+    }
 }
 
 /// Mesh builder allows you to construct mesh in declarative manner.
 pub struct MeshBuilder {
     base_builder: BaseBuilder,
-    surfaces: Vec<Surface>
+    surfaces: Vec<Surface>,
+    cast_shadows: bool,
+    receive_shadows: bool,
+    use_light_probes: bool,
+    is_static: bool,
 }
 
 impl MeshBuilder {
@@ -169,7 +297,11 @@ impl MeshBuilder {
     pub fn new(base_builder: BaseBuilder) -> Self {
         Self {
             base_builder,
-            surfaces: Default::default()
+            surfaces: Default::default(),
+            cast_shadows: true,
+            receive_shadows: true,
+            use_light_probes: false,
+            is_static: false,
         }
     }
 
@@ -179,13 +311,49 @@ impl MeshBuilder {
         self
     }
 
+    /// Sets whether mesh should be rendered into shadow maps or not.
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    /// Sets whether shadows of other objects should be visible on mesh or not.
+    pub fn with_receive_shadows(mut self, receive_shadows: bool) -> Self {
+        self.receive_shadows = receive_shadows;
+        self
+    }
+
+    /// Sets whether ambient lighting of mesh should be taken from light probes or not.
+    pub fn with_use_light_probes(mut self, use_light_probes: bool) -> Self {
+        self.use_light_probes = use_light_probes;
+        self
+    }
+
+    /// Sets whether mesh is static or not, see `Mesh::set_static`.
+    pub fn with_static(mut self, is_static: bool) -> Self {
+        self.is_static = is_static;
+        self
+    }
+
     /// Creates new mesh.
     pub fn build(self) -> Mesh {
         Mesh {
             base: self.base_builder.build(),
             surfaces: self.surfaces,
             bounding_box: Default::default(),
-            bounding_box_dirty: Default::default()
+            bounding_box_dirty: Default::default(),
+            cast_shadows: self.cast_shadows,
+            receive_shadows: self.receive_shadows,
+            use_light_probes: self.use_light_probes,
+            is_static: self.is_static,
         }
     }
+
+    /// Creates new mesh node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Mesh(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
 }
\ No newline at end of file
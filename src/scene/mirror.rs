@@ -0,0 +1,168 @@
+//! Mirror is a flat rectangular node which shows reflection of scene.
+//!
+//! For each camera which sees a mirror, renderer draws the scene once more from camera
+//! reflected by plane of mirror and puts result on mirror using projective mapping. This
+//! gives exact sharp reflections of everything (unlike screen space reflections, which
+//! can reflect only what is visible on screen), but costs one more render of the scene
+//! per visible mirror, so keep amount of mirrors small.
+//!
+//! Mirror lies in local XY plane of the node, its reflective side faces local Z axis.
+//! Mirrors are not visible in reflections of other mirrors. Objects behind mirror are
+//! not clipped from reflection, so mirror should be placed against wall or any other
+//! opaque object.
+
+use std::ops::{Deref, DerefMut};
+use crate::{
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        pool::Handle,
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
+            mat4::Mat4,
+        },
+        color::Color,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+#[derive(Clone)]
+pub struct Mirror {
+    base: Base,
+    size: Vec2,
+    tint: Color,
+}
+
+impl Deref for Mirror {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Mirror {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Mirror {
+    fn default() -> Self {
+        MirrorBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl Mirror {
+    /// Sets width and height of mirror in local units.
+    pub fn set_size(&mut self, size: Vec2) {
+        self.size = size;
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    /// Sets color which is multiplied with reflection, dark colors make mirror look
+    /// like polished stone or dark glass.
+    pub fn set_tint(&mut self, tint: Color) {
+        self.tint = tint;
+    }
+
+    pub fn tint(&self) -> Color {
+        self.tint
+    }
+
+    /// Returns matrix which transforms unit quad in [0; 1] range to world space rectangle
+    /// of mirror.
+    pub fn quad_transform(&self) -> Mat4 {
+        self.global_transform() *
+            Mat4::scale(Vec3::new(self.size.x, self.size.y, 1.0)) *
+            Mat4::translate(Vec3::new(-0.5, -0.5, 0.0))
+    }
+
+    /// Returns matrix which reflects world space points by plane of mirror.
+    pub fn reflection_matrix(&self) -> Mat4 {
+        let transform = self.global_transform();
+        transform *
+            Mat4::scale(Vec3::new(1.0, 1.0, -1.0)) *
+            transform.inverse().unwrap_or_default()
+    }
+
+    /// Returns true if given point is in front of reflective side of mirror.
+    pub fn is_facing(&self, point: Vec3) -> bool {
+        (point - self.global_position()).dot(&self.look_vector()) > 0.0
+    }
+
+    /// Returns radius of sphere (centered at node position) which encloses mirror.
+    pub fn bounding_radius(&self) -> f32 {
+        let side = self.side_vector().len() * self.size.x;
+        let up = self.up_vector().len() * self.size.y;
+        0.5 * (side * side + up * up).sqrt()
+    }
+}
+
+impl Visit for Mirror {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.size.visit("Size", visitor)?;
+        self.tint.visit("Tint", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct MirrorBuilder {
+    base_builder: BaseBuilder,
+    size: Option<Vec2>,
+    tint: Option<Color>,
+}
+
+impl MirrorBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            size: None,
+            tint: None,
+        }
+    }
+
+    pub fn with_size(mut self, size: Vec2) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+
+    pub fn build(self) -> Mirror {
+        Mirror {
+            base: self.base_builder.build(),
+            size: self.size.unwrap_or_else(|| Vec2::new(1.0, 1.0)),
+            tint: self.tint.unwrap_or(Color::WHITE),
+        }
+    }
+
+    /// Creates new mirror node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Mirror(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
@@ -7,8 +7,44 @@ pub mod light;
 pub mod particle_system;
 pub mod transform;
 pub mod sprite;
+pub mod trail;
+pub mod text3d;
+pub mod scatter;
+pub mod cloth;
+pub mod wind;
+pub mod lens_flare;
+pub mod mirror;
+pub mod trigger;
+pub mod impostor;
+pub mod light_probe;
 pub mod graph;
 pub mod base;
+pub mod stats;
+pub mod origin;
+pub mod distance_fade;
+pub mod spatial_index;
+pub mod event;
+pub mod save;
+pub mod diff;
+pub mod gltf;
+pub mod instance;
+pub mod day_night;
+pub mod weather;
+pub mod acoustics;
+pub mod sound_binder;
+pub mod sound_bank;
+pub mod prefab_pool;
+pub mod crowd;
+pub mod force_field;
+pub mod destructible;
+pub mod projectile;
+pub mod water;
+pub mod command;
+pub mod component;
+pub mod group;
+pub mod behavior;
+pub mod flicker;
+pub mod projector;
 
 use crate::{
     core::{
@@ -18,8 +54,12 @@ use crate::{
             Pool,
             PoolIterator,
             PoolIteratorMut,
+            PoolPairIterator,
+        },
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
         },
-        math::vec2::Vec2,
     },
     physics::{
         Physics,
@@ -28,27 +68,81 @@ use crate::{
     scene::{
         graph::Graph,
         node::Node,
+        impostor::ImpostorLodContainer,
+        light_probe::LightProbeGrid,
+        stats::SceneStatistics,
+        event::{
+            EventBus,
+            TriggerMessage,
+            AnimationSignalMessage,
+        },
+        save::SceneState,
+        day_night::DayNightCycle,
+        weather::Weather,
+        acoustics::Acoustics,
+        sound_binder::SoundBinder,
+        sound_bank::SoundBank,
+        command::SceneCommandBuffer,
+        component::ComponentStore,
+        group::NodeGroups,
+        behavior::BehaviorContainer,
+        projectile::ProjectileContainer,
+        origin::{
+            AbsolutePosition,
+            OriginShiftListener,
+        },
+    },
+    animation::{
+        AnimationContainer,
+        spline::SplineFollowerContainer,
+        spring_bone::SpringBoneContainer,
+        timeline::TimelineContainer,
+        tween::TweenContainer,
     },
-    animation::AnimationContainer,
+    engine::determinism::StateHasher,
     utils::log::Log,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::{Index, IndexMut};
+use std::hash::{Hash, Hasher};
 
 #[derive(Clone)]
 pub struct PhysicsBinder {
-    node_rigid_body_map: HashMap<Handle<Node>, Handle<RigidBody>>
+    node_rigid_body_map: HashMap<Handle<Node>, Handle<RigidBody>>,
+    interpolation: bool,
+    /// Positions of bound bodies before last physics step.
+    previous_positions: HashMap<Handle<Node>, Vec3>,
 }
 
 impl Default for PhysicsBinder {
     fn default() -> Self {
         Self {
-            node_rigid_body_map: Default::default()
+            node_rigid_body_map: Default::default(),
+            interpolation: false,
+            previous_positions: Default::default(),
         }
     }
 }
 
 impl PhysicsBinder {
+    /// Enables interpolation of positions of bound nodes between two last physics steps.
+    /// When scenes are updated with fixed time step (see `engine::determinism`), physics
+    /// steps happen at fixed rate while frames are rendered at variable rate, so bodies
+    /// visibly stutter. With interpolation engine moves bound nodes to positions between
+    /// previous and current positions of bodies using part of step which was not simulated
+    /// yet, see [`Scene::interpolate_physics`]. Rendered state lags behind simulation by
+    /// at most one step. Disabled by default, it is not serialized.
+    pub fn set_interpolation_enabled(&mut self, enabled: bool) {
+        self.interpolation = enabled;
+        if !enabled {
+            self.previous_positions.clear();
+        }
+    }
+
+    pub fn is_interpolation_enabled(&self) -> bool {
+        self.interpolation
+    }
+
     pub fn bind(&mut self, node: Handle<Node>, rigid_body: Handle<RigidBody>) -> Option<Handle<RigidBody>> {
         self.node_rigid_body_map.insert(node, rigid_body)
     }
@@ -56,6 +150,18 @@ impl PhysicsBinder {
     pub fn unbind(&mut self, node: Handle<Node>) -> Option<Handle<RigidBody>> {
         self.node_rigid_body_map.remove(&node)
     }
+
+    /// Returns handle of rigid body bound to given node.
+    pub fn body_of(&self, node: Handle<Node>) -> Option<Handle<RigidBody>> {
+        self.node_rigid_body_map.get(&node).cloned()
+    }
+
+    /// Returns node bound to given rigid body or `Handle::NONE` if there is no such node.
+    pub fn node_of(&self, body: Handle<RigidBody>) -> Handle<Node> {
+        self.node_rigid_body_map.iter()
+            .find(|(_, &other)| other == body)
+            .map_or(Handle::NONE, |(&node, _)| node)
+    }
 }
 
 impl Visit for PhysicsBinder {
@@ -85,6 +191,77 @@ pub struct Scene {
     /// Physics binder is a bridge between physics world and scene graph. If a rigid body is linked
     /// to a graph node, then rigid body will control local transform of node.
     pub physics_binder: PhysicsBinder,
+
+    /// Spline followers move nodes along splines, they are updated after animations. See
+    /// `animation::spline` module docs for more info.
+    pub spline_followers: SplineFollowerContainer,
+
+    /// Tweens smoothly change properties of nodes, they are updated after spline followers.
+    /// See `animation::tween` module docs for more info.
+    pub tweens: TweenContainer,
+
+    /// Spring bones add secondary motion to bones, they are updated after tweens. See
+    /// `animation::spring_bone` module docs for more info.
+    pub spring_bones: SpringBoneContainer,
+
+    /// Timelines sequence animations, camera cuts, sounds and signals for cutscenes, they
+    /// are updated after animations. See `animation::timeline` module docs for more info.
+    pub timelines: TimelineContainer,
+
+    /// Impostors replace distant objects with billboards, they are updated after spring
+    /// bones.
+    /// See `scene::impostor` module docs for more info.
+    pub impostors: ImpostorLodContainer,
+
+    /// Lightweight ballistic objects which are swept against physics world, they are
+    /// updated right after physics. See `scene::projectile` module docs for more info.
+    pub projectiles: ProjectileContainer,
+
+    /// Baked ambient lighting for meshes which use light probes. See `scene::light_probe`
+    /// module docs for more info.
+    pub light_probes: LightProbeGrid,
+
+    /// Typed message queue, messages are removed at the beginning of each update. See
+    /// `scene::event` module docs for more info.
+    pub events: EventBus,
+
+    /// Time of day with sun and environment colors, `None` by default. See
+    /// `scene::day_night` module docs for more info.
+    pub day_night: Option<DayNightCycle>,
+
+    /// Precipitation and wetness, `None` by default. See `scene::weather` module docs for
+    /// more info.
+    pub weather: Option<Weather>,
+
+    /// Reverb zones and occlusion of sounds. See `scene::acoustics` module docs for more
+    /// info.
+    pub acoustics: Acoustics,
+
+    /// Sound binder moves sound sources and listener together with nodes and applies
+    /// Doppler effect. See `scene::sound_binder` module docs for more info.
+    pub sound_binder: SoundBinder,
+
+    /// Named sound events with random variations, can be triggered by animation signals.
+    /// See `scene::sound_bank` module docs for more info.
+    pub sound_bank: SoundBank,
+
+    /// Deferred changes of scene, applied at the beginning of each update. See
+    /// `scene::command` module docs for more info.
+    pub commands: SceneCommandBuffer,
+
+    /// Game components attached to nodes. See `scene::component` module docs for more
+    /// info.
+    pub components: ComponentStore,
+
+    /// Named collections of nodes. See `scene::group` module docs for more info.
+    pub groups: NodeGroups,
+
+    /// Closures attached to nodes and called each update. See `scene::behavior` module
+    /// docs for more info.
+    pub behaviors: BehaviorContainer,
+
+    origin: AbsolutePosition,
+    origin_shift_listeners: Vec<OriginShiftListener>,
 }
 
 impl Default for Scene {
@@ -94,10 +271,53 @@ impl Default for Scene {
             animations: Default::default(),
             physics: Default::default(),
             physics_binder: Default::default(),
+            spline_followers: Default::default(),
+            tweens: Default::default(),
+            spring_bones: Default::default(),
+            timelines: Default::default(),
+            impostors: Default::default(),
+            projectiles: Default::default(),
+            light_probes: Default::default(),
+            events: Default::default(),
+            day_night: None,
+            weather: None,
+            acoustics: Default::default(),
+            sound_binder: Default::default(),
+            sound_bank: Default::default(),
+            commands: Default::default(),
+            components: Default::default(),
+            groups: Default::default(),
+            behaviors: Default::default(),
+            origin: Default::default(),
+            origin_shift_listeners: Default::default(),
         }
     }
 }
 
+/// Returns lowest and highest world heights of meshes in hierarchy of given node.
+fn vertical_extent(graph: &Graph, node: Handle<Node>) -> Option<(f32, f32)> {
+    let mut extent: Option<(f32, f32)> = None;
+    for node in graph.traverse_iter(node) {
+        if let Node::Mesh(mesh) = node {
+            if mesh.surfaces().is_empty() {
+                continue;
+            }
+            let bounds = mesh.bounding_box();
+            let transform = mesh.global_transform();
+            for i in 0..8 {
+                let corner = Vec3::new(
+                    if i & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                    if i & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                    if i & 4 == 0 { bounds.min.z } else { bounds.max.z },
+                );
+                let y = transform.transform_vector(corner).y;
+                extent = Some(extent.map_or((y, y), |(bottom, top)| (bottom.min(y), top.max(y))));
+            }
+        }
+    }
+    extent
+}
+
 impl Scene {
     #[inline]
     pub fn new() -> Self {
@@ -107,10 +327,43 @@ impl Scene {
             physics: Default::default(),
             animations: Default::default(),
             physics_binder: Default::default(),
+            spline_followers: Default::default(),
+            tweens: Default::default(),
+            spring_bones: Default::default(),
+            timelines: Default::default(),
+            impostors: Default::default(),
+            projectiles: Default::default(),
+            light_probes: Default::default(),
+            events: Default::default(),
+            day_night: None,
+            weather: None,
+            acoustics: Default::default(),
+            sound_binder: Default::default(),
+            sound_bank: Default::default(),
+            commands: Default::default(),
+            components: Default::default(),
+            groups: Default::default(),
+            behaviors: Default::default(),
+            origin: Default::default(),
+            origin_shift_listeners: Default::default(),
         }
     }
 
     fn update_physics(&mut self, dt: f32) {
+        if self.physics_binder.interpolation {
+            let physics = &self.physics;
+            let previous_positions = &mut self.physics_binder.previous_positions;
+            previous_positions.clear();
+            for (&node, &body) in self.physics_binder.node_rigid_body_map.iter() {
+                if physics.is_valid_body_handle(body) {
+                    previous_positions.insert(node, physics.borrow_body(body).get_position());
+                }
+            }
+        }
+
+        self.apply_force_fields(dt);
+        self.apply_water_volumes(dt);
+
         self.physics.step(dt);
 
         // Keep pair when node and body are both alive.
@@ -122,11 +375,113 @@ impl Scene {
 
         // Sync node positions with assigned physics bodies
         for (node, body) in self.physics_binder.node_rigid_body_map.iter() {
-            let body = physics.borrow_body(*body);
-            self.graph[*node].local_transform_mut().set_position(body.get_position());
+            let node = &mut self.graph[*node];
+            if node.is_globally_enabled() {
+                let body = physics.borrow_body(*body);
+                node.local_transform_mut().set_position(body.get_position());
+            }
+        }
+    }
+
+    /// Pushes rigid bodies bound to nodes by force fields, see `scene::force_field`.
+    fn apply_force_fields(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let force_fields = self.graph.linear_iter()
+            .filter_map(|node| match node {
+                Node::ForceField(field) if field.affects_bodies() && field.is_globally_enabled() => field.sample(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if force_fields.is_empty() {
+            return;
+        }
+
+        for body in self.physics_binder.node_rigid_body_map.values() {
+            if self.physics.is_valid_body_handle(*body) {
+                let body = self.physics.borrow_body_mut(*body);
+                let position = body.get_position();
+                // Velocity of body is displacement per step.
+                let velocity = body.get_velocity();
+                let acceleration = force_fields.iter()
+                    .fold(Vec3::ZERO, |sum, field| sum + field.acceleration_at(position, velocity.scale(1.0 / dt)));
+                body.set_velocity(velocity + acceleration.scale(dt * dt));
+            }
         }
     }
 
+    /// Makes rigid bodies bound to nodes float in water volumes, see `scene::water`.
+    fn apply_water_volumes(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let graph = &self.graph;
+        let volumes = graph.linear_iter()
+            .filter_map(|node| match node {
+                Node::WaterVolume(volume) if volume.is_globally_enabled() => Some((volume, volume.water_level(graph))),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if volumes.is_empty() {
+            return;
+        }
+
+        for (&node, &body) in self.physics_binder.node_rigid_body_map.iter() {
+            if !graph.is_valid_handle(node) || !self.physics.is_valid_body_handle(body) {
+                continue;
+            }
+            let body = self.physics.borrow_body_mut(body);
+            let position = body.get_position();
+            let volume = volumes.iter().find(|(volume, _)| volume.is_inside(position));
+            if let Some((volume, water_level)) = volume {
+                // Bounds of meshes are taken relative to node, body may be offset from it.
+                let offset = position.y - graph[node].global_position().y;
+                let (bottom, top) = match vertical_extent(graph, node) {
+                    Some((bottom, top)) => (bottom + offset, top + offset),
+                    None => {
+                        let half_height = volume.default_body_height() * 0.5;
+                        (position.y - half_height, position.y + half_height)
+                    }
+                };
+                // Velocity of body is displacement per step.
+                let velocity = body.get_velocity();
+                let acceleration = volume.acceleration(*water_level, bottom, top, velocity.scale(1.0 / dt));
+                body.set_velocity(velocity + acceleration.scale(dt * dt));
+            }
+        }
+    }
+
+    /// Moves nodes bound to rigid bodies to positions between positions of bodies before
+    /// (`alpha` = 0) and after (`alpha` = 1) last physics step, then updates global
+    /// transforms. Does nothing if interpolation is disabled, see
+    /// [`PhysicsBinder::set_interpolation_enabled`]. Called by engine after update when fixed
+    /// time step is used. Next update moves nodes exactly to positions of bodies again.
+    pub fn interpolate_physics(&mut self, alpha: f32) {
+        if !self.physics_binder.interpolation {
+            return;
+        }
+
+        for (node, body) in self.physics_binder.node_rigid_body_map.iter() {
+            if !self.graph.is_valid_handle(*node) || !self.physics.is_valid_body_handle(*body) {
+                continue;
+            }
+            let node_ref = &mut self.graph[*node];
+            if node_ref.is_globally_enabled() {
+                let current = self.physics.borrow_body(*body).get_position();
+                let position = match self.physics_binder.previous_positions.get(node) {
+                    Some(previous) => previous.lerp(&current, alpha),
+                    None => current,
+                };
+                node_ref.local_transform_mut().set_position(position);
+            }
+        }
+
+        self.graph.update_hierachical_data();
+    }
+
     /// Removes node from scene with all associated entities, like animations etc.
     ///
     /// # Panics
@@ -143,11 +498,173 @@ impl Scene {
                 }
                 true
             });
+            for animation in self.animations.iter_mut() {
+                animation.retain_property_tracks(|track| track.node() != descendant);
+                if let Some(lod) = animation.lod_mut() {
+                    if lod.node == descendant {
+                        lod.node = Handle::NONE;
+                    }
+                }
+            }
+            self.spline_followers.retain(|follower| follower.node() != descendant);
+            self.tweens.stop_all(descendant);
+            self.spring_bones.retain(|chain| !chain.bones().contains(&descendant));
+            self.impostors.retain(|lod| lod.detailed() != descendant && lod.billboard() != descendant);
+            for projectile in self.projectiles.iter_mut() {
+                if projectile.node() == descendant {
+                    projectile.set_node(Handle::NONE);
+                }
+            }
+            self.components.remove_node(descendant);
+            self.groups.remove_node(descendant);
+            self.behaviors.remove(descendant);
         }
 
         self.graph.remove_node(handle)
     }
 
+    /// Creates deep copy of node hierarchy within the scene, see [`Graph::copy_node_inplace`].
+    /// If `copy_associated` is true, then associated entities are copied too: animations
+    /// (only tracks of copied nodes are kept), rigid bodies bound to copied nodes and
+    /// spline followers. Tweens are not copied.
+    ///
+    /// Returns handle to copy of node and old-to-new node mapping.
+    pub fn copy_node(&mut self, handle: Handle<Node>, copy_associated: bool)
+                     -> (Handle<Node>, HashMap<Handle<Node>, Handle<Node>>) {
+        let (copy, old_new_map) = self.graph.copy_node_inplace(handle, &mut |_| true);
+
+        if copy_associated {
+            let mut animations = Vec::new();
+            for animation in self.animations.iter() {
+                if animation.get_tracks().iter().any(|track| old_new_map.contains_key(&track.get_node())) {
+                    let mut animation = animation.clone();
+                    animation.retain_tracks(|track| old_new_map.contains_key(&track.get_node()));
+                    for track in animation.get_tracks_mut() {
+                        track.set_node(old_new_map[&track.get_node()]);
+                    }
+                    animation.remap_property_tracks(&old_new_map);
+                    animations.push(animation);
+                }
+            }
+            for animation in animations {
+                self.animations.add(animation);
+            }
+
+            let mut bindings = Vec::new();
+            for (node, body) in self.physics_binder.node_rigid_body_map.iter() {
+                if let Some(&new_node) = old_new_map.get(node) {
+                    if self.physics.is_valid_body_handle(*body) {
+                        bindings.push((new_node, self.physics.borrow_body(*body).clone()));
+                    }
+                }
+            }
+            for (node, body) in bindings {
+                let body = self.physics.add_body(body);
+                self.physics_binder.bind(node, body);
+            }
+
+            let mut followers = Vec::new();
+            for follower in self.spline_followers.iter() {
+                if let Some(&new_node) = old_new_map.get(&follower.node()) {
+                    let mut follower = follower.clone();
+                    follower.set_node(new_node);
+                    followers.push(follower);
+                }
+            }
+            for follower in followers {
+                self.spline_followers.add(follower);
+            }
+        }
+
+        (copy, old_new_map)
+    }
+
+    /// Moves content of other scene into this scene: nodes (see [`Graph::append`]),
+    /// animations, rigid bodies bound to nodes, spline followers, tweens, spring bones,
+    /// timelines, impostors and node groups, with handles remapped to moved nodes and animations. Use it to compose a level of multiple scene
+    /// files. Unbound physics objects, light probes and origin of other scene are dropped,
+    /// callbacks of tweens are not moved.
+    ///
+    /// Returns old-to-new node mapping.
+    pub fn append(&mut self, other: Scene) -> HashMap<Handle<Node>, Handle<Node>> {
+        let Scene { graph, animations, physics, physics_binder, spline_followers, tweens, spring_bones, timelines, impostors, groups, .. } = other;
+
+        let old_new_map = self.graph.append(graph);
+
+        let mut animation_map = HashMap::new();
+        for (handle, animation) in animations.pair_iter() {
+            let mut animation = animation.clone();
+            animation.retain_tracks(|track| old_new_map.contains_key(&track.get_node()));
+            for track in animation.get_tracks_mut() {
+                track.set_node(old_new_map[&track.get_node()]);
+            }
+            animation.remap_property_tracks(&old_new_map);
+            animation_map.insert(handle, self.animations.add(animation));
+        }
+
+        for (node, &body) in physics_binder.node_rigid_body_map.iter() {
+            if let Some(&new_node) = old_new_map.get(node) {
+                if physics.is_valid_body_handle(body) {
+                    let body = self.physics.add_body(physics.borrow_body(body).clone());
+                    self.physics_binder.bind(new_node, body);
+                }
+            }
+        }
+
+        for follower in spline_followers.iter() {
+            if let Some(&new_node) = old_new_map.get(&follower.node()) {
+                let mut follower = follower.clone();
+                follower.set_node(new_node);
+                self.spline_followers.add(follower);
+            }
+        }
+
+        for tween in tweens.iter() {
+            if let Some(&new_node) = old_new_map.get(&tween.node()) {
+                let mut tween = tween.clone();
+                tween.set_node(new_node);
+                self.tweens.add(tween);
+            }
+        }
+
+        for chain in spring_bones.iter() {
+            if chain.bones().iter().all(|bone| old_new_map.contains_key(bone)) {
+                let mut chain = chain.clone();
+                chain.remap_nodes(|node| old_new_map.get(&node).cloned().unwrap_or(Handle::NONE));
+                self.spring_bones.add(chain);
+            }
+        }
+
+        for timeline in timelines.iter() {
+            let mut timeline = timeline.clone();
+            timeline.remap_nodes(&old_new_map);
+            timeline.remap_animations(&animation_map);
+            self.timelines.add(timeline);
+        }
+
+        for lod in impostors.iter() {
+            if let (Some(&detailed), Some(&billboard)) = (old_new_map.get(&lod.detailed()), old_new_map.get(&lod.billboard())) {
+                let mut lod = lod.clone();
+                lod.set_detailed(detailed);
+                lod.set_billboard(billboard);
+                self.impostors.add(lod);
+            }
+        }
+
+        self.groups.append_remapped(&groups, &old_new_map);
+
+        old_new_map
+    }
+
+    /// Synchronizes instances of reloaded model resources (see [`Graph::sync_instances`]),
+    /// then binds tracks of animations to nodes again by names, so animations keep working
+    /// when bones were added or renamed in source model. Bones and tracks which could not be
+    /// matched are written to log.
+    pub fn sync_instances(&mut self) {
+        self.graph.sync_instances();
+        self.animations.rebind(&self.graph);
+    }
+
     pub fn resolve(&mut self) {
         Log::writeln("Starting resolve...".to_owned());
         self.graph.resolve();
@@ -156,9 +673,228 @@ impl Scene {
     }
 
     pub fn update(&mut self, frame_size: Vec2, dt: f32) {
+        self.events.clear();
+
+        let mut commands = std::mem::take(&mut self.commands);
+        commands.apply(self);
+        self.components.retain_alive(&self.graph);
+        self.groups.prune(&self.graph);
+
         self.update_physics(dt);
-        self.animations.update_animations(dt);
+        self.projectiles.update(&self.physics, &self.physics_binder, &mut self.graph, &mut self.events, dt);
+        self.animations.update_animations_with_lod(&self.graph, dt);
+        if self.events.is_capture_animation_signals() {
+            for (handle, animation) in self.animations.pair_iter_mut() {
+                while let Some(event) = animation.pop_event() {
+                    self.events.publish(AnimationSignalMessage {
+                        animation: handle,
+                        signal_id: event.signal_id,
+                    });
+                }
+            }
+        }
+        self.timelines.update(&mut self.graph, &mut self.animations, &mut self.events, dt);
+        self.spline_followers.update(&mut self.graph, dt);
+        self.tweens.update(&mut self.graph, dt);
+        self.spring_bones.update(&mut self.graph, dt);
+        self.impostors.update(&mut self.graph);
+        if let Some(day_night) = self.day_night.as_mut() {
+            day_night.update(&mut self.graph, dt);
+        }
+        if let Some(weather) = self.weather.as_mut() {
+            weather.update(&mut self.graph, dt);
+        }
+        self.behaviors.update(&mut self.graph, dt);
         self.graph.update_nodes(frame_size, dt);
+
+        let bound_nodes = self.physics_binder.node_rigid_body_map.keys().cloned().collect::<Vec<_>>();
+        self.graph.update_triggers(&bound_nodes);
+        for (handle, node) in self.graph.pair_iter() {
+            if let Node::Trigger(trigger) = node {
+                for &event in trigger.events() {
+                    self.events.publish(TriggerMessage { trigger: handle, event });
+                }
+            }
+        }
+    }
+
+    /// Moves origin of scene to given point, in other words moves whole world by `-offset`:
+    /// nodes, rigid bodies bound to nodes, projectiles, light probes and splines of followers which move
+    /// top-level nodes. Listeners are notified after everything else is moved. See
+    /// `scene::origin` module docs for more info.
+    pub fn shift_origin(&mut self, offset: Vec3) {
+        self.graph.shift_origin(offset);
+        self.projectiles.shift_origin(offset);
+
+        let bodies = self.physics_binder.node_rigid_body_map.values()
+            .filter(|&&body| self.physics.is_valid_body_handle(body))
+            .cloned()
+            .collect::<HashSet<_>>();
+        for body in bodies {
+            let body = self.physics.borrow_body_mut(body);
+            // Setting position resets velocity, so keep it.
+            let velocity = body.get_velocity();
+            let position = body.get_position();
+            body.set_position(position - offset);
+            body.set_velocity(velocity);
+        }
+
+        // Splines are in local space of parent of a node, only splines of top-level nodes
+        // are in world space.
+        let root = self.graph.get_root();
+        for follower in self.spline_followers.iter_mut() {
+            if self.graph.is_valid_handle(follower.node()) && self.graph[follower.node()].parent() == root {
+                let points = follower.spline().points().iter().map(|&point| point - offset).collect();
+                follower.spline_mut().set_points(points);
+            }
+        }
+
+        for position in self.physics_binder.previous_positions.values_mut() {
+            *position -= offset;
+        }
+
+        self.spring_bones.shift_origin(offset);
+        self.light_probes.shift_origin(offset);
+
+        self.origin = self.origin + offset;
+
+        for listener in self.origin_shift_listeners.iter_mut() {
+            listener(offset, &mut self.physics);
+        }
+    }
+
+    /// Shifts origin to `focus` if it is farther than `threshold` from current origin.
+    /// Typical usage is to call it every frame with position of player. Returns true if
+    /// origin was shifted.
+    pub fn rebase_origin(&mut self, focus: Vec3, threshold: f32) -> bool {
+        if focus.len() > threshold {
+            self.shift_origin(focus);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Saves or loads only dynamic state of scene, see `scene::save` module docs. On load
+    /// scene must be already created the same way as it was before saving, state is
+    /// applied on top of it.
+    pub fn visit_state(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut state = if visitor.is_reading() {
+            SceneState::default()
+        } else {
+            SceneState::capture(self)
+        };
+
+        state.visit(name, visitor)?;
+
+        if visitor.is_reading() {
+            let missing = state.apply(self);
+            if missing != 0 {
+                Log::writeln(format!("{} saved nodes were not found in scene!", missing));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns absolute position of origin of scene.
+    pub fn origin(&self) -> AbsolutePosition {
+        self.origin
+    }
+
+    /// Converts position relative to current origin to absolute position.
+    pub fn to_absolute(&self, position: Vec3) -> AbsolutePosition {
+        self.origin + position
+    }
+
+    /// Converts absolute position to position relative to current origin.
+    pub fn to_relative(&self, position: AbsolutePosition) -> Vec3 {
+        position - self.origin
+    }
+
+    /// Adds listener which will be called on each shift of origin, see `scene::origin`
+    /// module docs.
+    pub fn add_origin_shift_listener(&mut self, listener: OriginShiftListener) {
+        self.origin_shift_listeners.push(listener);
+    }
+
+    /// Gathers amount of nodes of each kind, triangles, animations and so on. See
+    /// `scene::stats` module docs for more info.
+    pub fn stats(&self) -> SceneStatistics {
+        SceneStatistics::gather(self)
+    }
+
+    /// Calculates hash of simulation state of the scene: global transforms and visibility of
+    /// nodes, animation time positions and positions and velocities of bound rigid bodies.
+    /// Two scenes that were simulated with same input in deterministic mode will have same
+    /// hash, so it can be used to detect desync in lockstep multiplayer.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+
+        for (handle, node) in self.graph.pair_iter() {
+            handle.hash(&mut hasher);
+            hasher.write_f32_slice(&node.global_transform().f);
+            hasher.write_u8(node.global_visibility() as u8);
+        }
+
+        for animation in self.animations.iter() {
+            hasher.write_f32(animation.get_time_position());
+            hasher.write_u8(animation.is_enabled() as u8);
+        }
+
+        hasher.write_u64(self.physics_state_hash());
+
+        hasher.finish()
+    }
+
+    /// Calculates hash of positions and velocities of rigid bodies bound to nodes. Use it to
+    /// check that physics state survives save and load exactly: hash of loaded scene must be
+    /// equal to hash of scene at the moment of saving.
+    pub fn physics_state_hash(&self) -> u64 {
+        // Binder is a hash map which has no defined iteration order, so combine hashes of
+        // pairs in order-independent way.
+        let mut bindings_hash = 0u64;
+        for (node, body) in self.physics_binder.node_rigid_body_map.iter() {
+            if self.physics.is_valid_body_handle(*body) {
+                let mut pair_hasher = StateHasher::new();
+                node.hash(&mut pair_hasher);
+                let body = self.physics.borrow_body(*body);
+                for vector in [body.get_position(), body.get_velocity()].iter() {
+                    pair_hasher.write_f32(vector.x);
+                    pair_hasher.write_f32(vector.y);
+                    pair_hasher.write_f32(vector.z);
+                }
+                bindings_hash = bindings_hash.wrapping_add(pair_hasher.finish());
+            }
+        }
+        bindings_hash
+    }
+
+    /// Velocities of bound bodies are stored explicitly, so moving and resting bodies keep
+    /// their exact velocities after load instead of getting velocity that is restored from
+    /// positions, which makes stacked objects jump on first step.
+    fn visit_body_velocities(&mut self, visitor: &mut Visitor) -> VisitResult {
+        let mut velocities = self.physics_binder.node_rigid_body_map.iter()
+            .filter(|(_, &body)| self.physics.is_valid_body_handle(body))
+            .map(|(&node, &body)| BodyVelocity {
+                node,
+                velocity: self.physics.borrow_body(body).get_velocity(),
+            })
+            .collect::<Vec<_>>();
+
+        velocities.visit("BodyVelocities", visitor)?;
+
+        if visitor.is_reading() {
+            for state in velocities {
+                if let Some(body) = self.physics_binder.body_of(state.node) {
+                    if self.physics.is_valid_body_handle(body) {
+                        self.physics.borrow_body_mut(body).set_velocity(state.velocity);
+                    }
+                }
+            }
+        }
+
+        Ok(())
     }
 
     pub fn clone<F>(&self, filter: &mut F) -> Self
@@ -172,9 +908,11 @@ impl Scene {
             for track in animation.get_tracks_mut() {
                 track.set_node(old_new_map[&track.get_node()]);
             }
+            animation.remap_property_tracks(&old_new_map);
         }
         let physics = self.physics.clone();
         let mut physics_binder = PhysicsBinder::default();
+        physics_binder.interpolation = self.physics_binder.interpolation;
         for (node, &body) in self.physics_binder.node_rigid_body_map.iter() {
             // Make sure we bind existing node with new physical body.
             if let Some(&new_node) = old_new_map.get(node) {
@@ -183,15 +921,96 @@ impl Scene {
                 physics_binder.bind(new_node, body);
             }
         }
+        let mut spline_followers = self.spline_followers.clone();
+        spline_followers.retain(|follower| old_new_map.contains_key(&follower.node()));
+        for follower in spline_followers.iter_mut() {
+            follower.set_node(old_new_map[&follower.node()]);
+        }
+        let mut tweens = self.tweens.clone();
+        tweens.retain(|tween| old_new_map.contains_key(&tween.node()));
+        for tween in tweens.iter_mut() {
+            tween.set_node(old_new_map[&tween.node()]);
+        }
+        let mut spring_bones = self.spring_bones.clone();
+        spring_bones.retain(|chain| chain.bones().iter().all(|bone| old_new_map.contains_key(bone)));
+        for chain in spring_bones.iter_mut() {
+            chain.remap_nodes(|node| old_new_map.get(&node).cloned().unwrap_or(Handle::NONE));
+        }
+        let mut timelines = self.timelines.clone();
+        for timeline in timelines.iter_mut() {
+            timeline.remap_nodes(&old_new_map);
+        }
+        let mut day_night = self.day_night.clone();
+        if let Some(day_night) = day_night.as_mut() {
+            day_night.set_sun(old_new_map.get(&day_night.sun()).cloned().unwrap_or(Handle::NONE));
+        }
+        // Particle system of precipitation is re-created if it was filtered out.
+        let mut weather = self.weather.clone();
+        if let Some(weather) = weather.as_mut() {
+            weather.set_camera(old_new_map.get(&weather.camera()).cloned().unwrap_or(Handle::NONE));
+            weather.particle_system = old_new_map.get(&weather.particle_system).cloned().unwrap_or(Handle::NONE);
+        }
+        let mut sound_bank = self.sound_bank.clone();
+        sound_bank.remap_nodes(&old_new_map);
+        let mut groups = NodeGroups::default();
+        groups.append_remapped(&self.groups, &old_new_map);
+        let mut impostors = self.impostors.clone();
+        impostors.retain(|lod| old_new_map.contains_key(&lod.detailed()) && old_new_map.contains_key(&lod.billboard()));
+        for lod in impostors.iter_mut() {
+            lod.set_detailed(old_new_map[&lod.detailed()]);
+            lod.set_billboard(old_new_map[&lod.billboard()]);
+        }
         Self {
             graph,
             animations,
             physics,
-            physics_binder
+            physics_binder,
+            spline_followers,
+            tweens,
+            spring_bones,
+            timelines,
+            impostors,
+            projectiles: self.projectiles.clone(),
+            light_probes: self.light_probes.clone(),
+            // Messages are addressed to readers of original scene.
+            events: Default::default(),
+            day_night,
+            weather,
+            acoustics: self.acoustics.clone(),
+            // Sound sources can't be shared between scenes.
+            sound_binder: Default::default(),
+            sound_bank,
+            // Commands refer to nodes of original scene.
+            commands: Default::default(),
+            // Components are runtime data of game systems and are not required to be cloneable.
+            components: Default::default(),
+            groups,
+            // Behaviors are closures which can't be copied.
+            behaviors: Default::default(),
+            origin: self.origin,
+            // Listeners are bound to particular scene, so they're not copied.
+            origin_shift_listeners: Default::default(),
         }
     }
 }
 
+#[derive(Default)]
+struct BodyVelocity {
+    node: Handle<Node>,
+    velocity: Vec3,
+}
+
+impl Visit for BodyVelocity {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.node.visit("Node", visitor)?;
+        self.velocity.visit("Velocity", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
 impl Visit for Scene {
     fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
         visitor.enter_region(name)?;
@@ -199,6 +1018,20 @@ impl Visit for Scene {
         self.graph.visit("Graph", visitor)?;
         self.animations.visit("Animations", visitor)?;
         self.physics.visit("Physics", visitor)?;
+        self.visit_body_velocities(visitor)?;
+        self.spline_followers.visit("SplineFollowers", visitor)?;
+        self.tweens.visit("Tweens", visitor)?;
+        self.spring_bones.visit("SpringBones", visitor)?;
+        self.timelines.visit("Timelines", visitor)?;
+        self.impostors.visit("Impostors", visitor)?;
+        self.projectiles.visit("Projectiles", visitor)?;
+        self.light_probes.visit("LightProbes", visitor)?;
+        self.day_night.visit("DayNight", visitor)?;
+        self.weather.visit("Weather", visitor)?;
+        self.acoustics.visit("Acoustics", visitor)?;
+        self.sound_binder.visit("SoundBinder", visitor)?;
+        self.origin.visit("Origin", visitor)?;
+        self.groups.visit("Groups", visitor)?;
         visitor.leave_region()
     }
 }
@@ -224,6 +1057,38 @@ impl SceneContainer {
         self.pool.iter_mut()
     }
 
+    /// Creates new iterator that iterates over scenes giving (handle; scene) pairs.
+    #[inline]
+    pub fn pair_iter(&self) -> PoolPairIterator<Scene> {
+        self.pool.pair_iter()
+    }
+
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Scene>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
+    /// Returns reference to scene or `None` if handle is invalid, for example if scene was
+    /// removed. Unlike indexing it never panics.
+    #[inline]
+    pub fn try_get(&self, handle: Handle<Scene>) -> Option<&Scene> {
+        if self.pool.is_valid_handle(handle) {
+            Some(&self.pool[handle])
+        } else {
+            None
+        }
+    }
+
+    /// Mutable version of `try_get`.
+    #[inline]
+    pub fn try_get_mut(&mut self, handle: Handle<Scene>) -> Option<&mut Scene> {
+        if self.pool.is_valid_handle(handle) {
+            Some(&mut self.pool[handle])
+        } else {
+            None
+        }
+    }
+
     #[inline]
     pub fn add(&mut self, animation: Scene) -> Handle<Scene> {
         self.pool.spawn(animation)
@@ -234,9 +1099,13 @@ impl SceneContainer {
         self.pool.clear()
     }
 
+    /// Removes scene, does nothing if scene was already removed. Scene can be removed at
+    /// any time, including hooks of `UpdateHandler` called in the middle of update.
     #[inline]
     pub fn remove(&mut self, handle: Handle<Scene>) {
-        self.pool.free(handle);
+        if self.pool.is_valid_handle(handle) {
+            self.pool.free(handle);
+        }
     }
 }
 
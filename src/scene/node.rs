@@ -8,6 +8,16 @@ use crate::{
         mesh::Mesh,
         sprite::Sprite,
         particle_system::ParticleSystem,
+        trail::Trail,
+        text3d::Text3D,
+        scatter::Scatter,
+        cloth::Cloth,
+        mirror::Mirror,
+        trigger::Trigger,
+        crowd::Crowd,
+        force_field::ForceField,
+        water::WaterVolume,
+        projector::Projector,
         base::Base
     }
 };
@@ -24,6 +34,16 @@ macro_rules! static_dispatch {
             Node::Light(v) => v.$func($($args),*),
             Node::ParticleSystem(v) => v.$func($($args),*),
             Node::Sprite(v) => v.$func($($args),*),
+            Node::Trail(v) => v.$func($($args),*),
+            Node::Text3D(v) => v.$func($($args),*),
+            Node::Scatter(v) => v.$func($($args),*),
+            Node::Cloth(v) => v.$func($($args),*),
+            Node::Mirror(v) => v.$func($($args),*),
+            Node::Trigger(v) => v.$func($($args),*),
+            Node::Crowd(v) => v.$func($($args),*),
+            Node::ForceField(v) => v.$func($($args),*),
+            Node::WaterVolume(v) => v.$func($($args),*),
+            Node::Projector(v) => v.$func($($args),*),
         }
     };
 }
@@ -48,6 +68,16 @@ pub enum Node {
     Mesh(Mesh),
     Sprite(Sprite),
     ParticleSystem(ParticleSystem),
+    Trail(Trail),
+    Text3D(Text3D),
+    Scatter(Scatter),
+    Cloth(Cloth),
+    Mirror(Mirror),
+    Trigger(Trigger),
+    Crowd(Crowd),
+    ForceField(ForceField),
+    WaterVolume(WaterVolume),
+    Projector(Projector),
 }
 
 macro_rules! static_dispatch_deref {
@@ -59,6 +89,16 @@ macro_rules! static_dispatch_deref {
             Node::Light(v) => v,
             Node::ParticleSystem(v) => v,
             Node::Sprite(v) => v,
+            Node::Trail(v) => v,
+            Node::Text3D(v) => v,
+            Node::Scatter(v) => v,
+            Node::Cloth(v) => v,
+            Node::Mirror(v) => v,
+            Node::Trigger(v) => v,
+            Node::Crowd(v) => v,
+            Node::ForceField(v) => v,
+            Node::WaterVolume(v) => v,
+            Node::Projector(v) => v,
         }
     };
 }
@@ -119,6 +159,16 @@ impl Node {
             3 => Ok(Node::Mesh(Default::default())),
             4 => Ok(Node::Sprite(Default::default())),
             5 => Ok(Node::ParticleSystem(Default::default())),
+            6 => Ok(Node::Trail(Default::default())),
+            7 => Ok(Node::Text3D(Default::default())),
+            8 => Ok(Node::Scatter(Default::default())),
+            9 => Ok(Node::Cloth(Default::default())),
+            10 => Ok(Node::Mirror(Default::default())),
+            11 => Ok(Node::Trigger(Default::default())),
+            12 => Ok(Node::Crowd(Default::default())),
+            13 => Ok(Node::ForceField(Default::default())),
+            14 => Ok(Node::WaterVolume(Default::default())),
+            15 => Ok(Node::Projector(Default::default())),
             _ => Err(format!("Invalid node kind {}", id))
         }
     }
@@ -132,6 +182,38 @@ impl Node {
             Node::Mesh(_) => 3,
             Node::Sprite(_) => 4,
             Node::ParticleSystem(_) => 5,
+            Node::Trail(_) => 6,
+            Node::Text3D(_) => 7,
+            Node::Scatter(_) => 8,
+            Node::Cloth(_) => 9,
+            Node::Mirror(_) => 10,
+            Node::Trigger(_) => 11,
+            Node::Crowd(_) => 12,
+            Node::ForceField(_) => 13,
+            Node::WaterVolume(_) => 14,
+            Node::Projector(_) => 15,
+        }
+    }
+
+    /// Returns name of variant, it is useful for debugging output.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Node::Base(_) => "Base",
+            Node::Light(_) => "Light",
+            Node::Camera(_) => "Camera",
+            Node::Mesh(_) => "Mesh",
+            Node::Sprite(_) => "Sprite",
+            Node::ParticleSystem(_) => "ParticleSystem",
+            Node::Trail(_) => "Trail",
+            Node::Text3D(_) => "Text3D",
+            Node::Scatter(_) => "Scatter",
+            Node::Cloth(_) => "Cloth",
+            Node::Mirror(_) => "Mirror",
+            Node::Trigger(_) => "Trigger",
+            Node::Crowd(_) => "Crowd",
+            Node::ForceField(_) => "ForceField",
+            Node::WaterVolume(_) => "WaterVolume",
+            Node::Projector(_) => "Projector",
         }
     }
 
@@ -140,4 +222,14 @@ impl Node {
     define_is_as!(is_light, as_light, as_light_mut, Light, Light);
     define_is_as!(is_particle_system, as_particle_system, as_particle_system_mut, ParticleSystem, ParticleSystem);
     define_is_as!(is_sprite, as_sprite, as_sprite_mut, Sprite, Sprite);
+    define_is_as!(is_trail, as_trail, as_trail_mut, Trail, Trail);
+    define_is_as!(is_text3d, as_text3d, as_text3d_mut, Text3D, Text3D);
+    define_is_as!(is_scatter, as_scatter, as_scatter_mut, Scatter, Scatter);
+    define_is_as!(is_cloth, as_cloth, as_cloth_mut, Cloth, Cloth);
+    define_is_as!(is_mirror, as_mirror, as_mirror_mut, Mirror, Mirror);
+    define_is_as!(is_trigger, as_trigger, as_trigger_mut, Trigger, Trigger);
+    define_is_as!(is_crowd, as_crowd, as_crowd_mut, Crowd, Crowd);
+    define_is_as!(is_force_field, as_force_field, as_force_field_mut, ForceField, ForceField);
+    define_is_as!(is_water_volume, as_water_volume, as_water_volume_mut, WaterVolume, WaterVolume);
+    define_is_as!(is_projector, as_projector, as_projector_mut, Projector, Projector);
 }
\ No newline at end of file
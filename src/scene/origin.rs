@@ -0,0 +1,99 @@
+//! Floating origin support for large worlds.
+//!
+//! Positions are stored in single precision, which gives about half a millimeter precision
+//! at 10 km from origin - far away from origin meshes start to jitter, physics becomes
+//! unstable and camera shakes. Floating origin keeps area of interest (usually player)
+//! near origin by moving whole world when player gets too far: `Scene::shift_origin`
+//! moves every node, bound rigid body, light probes, spline and so on by the same offset
+//! at once, so nothing moves relative to each other.
+//!
+//! Scene tracks absolute position of its origin in double precision, so absolute position
+//! of anything can still be calculated with `Scene::to_absolute`, for example to save the
+//! game or to sync it over network.
+//!
+//! # Notifications
+//!
+//! Some state can't be shifted by scene - static geometry and rigid bodies which are not
+//! bound to nodes, sound sources, positions stored in game code. Listeners added with
+//! `Scene::add_origin_shift_listener` are called on each shift, so they can move such
+//! state by the same offset.
+//!
+//! # Limitations
+//!
+//! Only top-level nodes are moved, children follow them. Animations and tweens which
+//! change position of top-level nodes will move them back, so such nodes should be
+//! attached to a pivot.
+
+use std::ops::{Add, Sub};
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+    physics::Physics,
+};
+
+/// Position in double precision.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AbsolutePosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl AbsolutePosition {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl Add<Vec3> for AbsolutePosition {
+    type Output = Self;
+
+    fn add(self, rhs: Vec3) -> Self::Output {
+        Self::new(self.x + rhs.x as f64, self.y + rhs.y as f64, self.z + rhs.z as f64)
+    }
+}
+
+impl Sub for AbsolutePosition {
+    type Output = Vec3;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vec3::new((self.x - rhs.x) as f32, (self.y - rhs.y) as f32, (self.z - rhs.z) as f32)
+    }
+}
+
+impl Visit for AbsolutePosition {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.x.visit("X", visitor)?;
+        self.y.visit("Y", visitor)?;
+        self.z.visit("Z", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Listener of origin shifts. It gets offset by which world was moved (it is subtracted
+/// from every position) and physics of scene, to move static geometry and unbound bodies.
+pub type OriginShiftListener = Box<dyn FnMut(Vec3, &mut Physics)>;
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::origin::AbsolutePosition,
+    };
+
+    #[test]
+    fn test_absolute_position_precision() {
+        let origin = AbsolutePosition::new(100_000_000.0, 0.0, -100_000_000.0);
+        let position = origin + Vec3::new(0.25, 1.0, -0.5);
+        assert_eq!(position - origin, Vec3::new(0.25, 1.0, -0.5));
+    }
+}
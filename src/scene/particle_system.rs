@@ -10,14 +10,22 @@ use std::{
     },
     ops::{DerefMut, Deref}
 };
-use rand::Rng;
 use crate::{
     resource::texture::Texture,
-    scene::base::{
-        BaseBuilder,
-        Base,
+    utils::random::{self, RandomGenerator},
+    scene::{
+        base::{
+            BaseBuilder,
+            Base,
+        },
+        wind::Wind,
+        force_field::ForceFieldSample,
+        distance_fade::DistanceFade,
+        graph::Graph,
+        node::Node,
     },
     core::{
+        pool::Handle,
         math::{
             vec3::Vec3,
             vec2::Vec2,
@@ -132,7 +140,9 @@ impl Visit for Particle {
 }
 
 pub trait Emit {
-    fn emit(&self, emitter: &Emitter, particle_system: &ParticleSystem, particle: &mut Particle);
+    /// Initializes position of given particle. Emitter must use given random number generator
+    /// instead of any other source of randomness to make particle system reproducible.
+    fn emit(&self, emitter: &Emitter, particle_system: &ParticleSystem, particle: &mut Particle, rng: &mut RandomGenerator);
 }
 
 pub struct BoxEmitter {
@@ -162,12 +172,11 @@ impl Default for BoxEmitter {
 }
 
 impl Emit for BoxEmitter {
-    fn emit(&self, emitter: &Emitter, _: &ParticleSystem, particle: &mut Particle) {
-        let mut rng = rand::thread_rng();
+    fn emit(&self, emitter: &Emitter, _: &ParticleSystem, particle: &mut Particle, rng: &mut RandomGenerator) {
         particle.position = Vec3::new(
-            emitter.position.x + rng.gen_range(-self.half_width, self.half_width),
-            emitter.position.y + rng.gen_range(-self.half_height, self.half_height),
-            emitter.position.z + rng.gen_range(-self.half_depth, self.half_depth),
+            emitter.position.x + rng.range(-self.half_width, self.half_width),
+            emitter.position.y + rng.range(-self.half_height, self.half_height),
+            emitter.position.z + rng.range(-self.half_depth, self.half_depth),
         )
     }
 }
@@ -225,11 +234,10 @@ impl Visit for SphereEmitter {
 }
 
 impl Emit for SphereEmitter {
-    fn emit(&self, _: &Emitter, _: &ParticleSystem, particle: &mut Particle) {
-        let mut rng = rand::thread_rng();
-        let phi = rng.gen_range(0.0, std::f32::consts::PI);
-        let theta = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
-        let radius = rng.gen_range(0.0, self.radius);
+    fn emit(&self, _: &Emitter, _: &ParticleSystem, particle: &mut Particle, rng: &mut RandomGenerator) {
+        let phi = rng.range(0.0, std::f32::consts::PI);
+        let theta = rng.range(0.0, 2.0 * std::f32::consts::PI);
+        let radius = rng.range(0.0, self.radius);
         let cos_theta = theta.cos();
         let sin_theta = theta.sin();
         let cos_phi = phi.cos();
@@ -335,12 +343,12 @@ impl EmitterKind {
 }
 
 impl Emit for EmitterKind {
-    fn emit(&self, emitter: &Emitter, particle_system: &ParticleSystem, particle: &mut Particle) {
+    fn emit(&self, emitter: &Emitter, particle_system: &ParticleSystem, particle: &mut Particle, rng: &mut RandomGenerator) {
         match self {
             EmitterKind::Unknown => panic!("Unknown emitter kind is not supported"),
-            EmitterKind::Box(box_emitter) => box_emitter.emit(emitter, particle_system, particle),
-            EmitterKind::Sphere(sphere_emitter) => sphere_emitter.emit(emitter, particle_system, particle),
-            EmitterKind::Custom(custom_emitter) => custom_emitter.emit(emitter, particle_system, particle)
+            EmitterKind::Box(box_emitter) => box_emitter.emit(emitter, particle_system, particle, rng),
+            EmitterKind::Sphere(sphere_emitter) => sphere_emitter.emit(emitter, particle_system, particle, rng),
+            EmitterKind::Custom(custom_emitter) => custom_emitter.emit(emitter, particle_system, particle, rng)
         }
     }
 }
@@ -548,6 +556,12 @@ impl EmitterBuilder {
 
 impl Emitter {
     pub fn tick(&mut self, dt: f32) {
+        if self.particle_spawn_rate == 0 {
+            // Emitter is paused, accumulated time must not become infinite.
+            self.time = 0.0;
+            self.particles_to_spawn = 0;
+            return;
+        }
         self.time += dt;
         let time_amount_per_particle = 1.0 / self.particle_spawn_rate as f32;
         let mut particle_count = (self.time / time_amount_per_particle) as u32;
@@ -566,20 +580,20 @@ impl Emitter {
         self.spawned_particles += self.particles_to_spawn as u64;
     }
 
-    pub fn emit(&self, particle_system: &ParticleSystem, particle: &mut Particle) {
+    pub fn emit(&self, particle_system: &ParticleSystem, particle: &mut Particle, rng: &mut RandomGenerator) {
         particle.lifetime = 0.0;
-        particle.initial_lifetime = self.lifetime.random();
+        particle.initial_lifetime = rng.numeric_range(&self.lifetime);
         particle.color = Color::WHITE;
-        particle.size = self.size.random();
-        particle.size_modifier = self.size_modifier.random();
+        particle.size = rng.numeric_range(&self.size);
+        particle.size_modifier = rng.numeric_range(&self.size_modifier);
         particle.velocity = Vec3::new(
-            self.x_velocity.random(),
-            self.y_velocity.random(),
-            self.z_velocity.random(),
+            rng.numeric_range(&self.x_velocity),
+            rng.numeric_range(&self.y_velocity),
+            rng.numeric_range(&self.z_velocity),
         );
-        particle.rotation = self.rotation.random();
-        particle.rotation_speed = self.rotation_speed.random();
-        self.kind.emit(self, particle_system, particle);
+        particle.rotation = rng.numeric_range(&self.rotation);
+        particle.rotation_speed = rng.numeric_range(&self.rotation_speed);
+        self.kind.emit(self, particle_system, particle, rng);
     }
 
     pub fn set_position(&mut self, position: Vec3) -> &mut Self {
@@ -782,7 +796,11 @@ pub struct ParticleSystem {
     emitters: Vec<Emitter>,
     texture: Option<Arc<Mutex<Texture>>>,
     acceleration: Vec3,
+    wind_influence: f32,
     color_over_lifetime: Option<ColorGradient>,
+    rng: RandomGenerator,
+    distance_fade: Option<DistanceFade>,
+    freeze_when_faded: bool,
 }
 
 impl Deref for ParticleSystem {
@@ -804,25 +822,108 @@ impl ParticleSystem {
         self.emitters.push(emitter)
     }
 
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    pub fn emitters_mut(&mut self) -> &mut [Emitter] {
+        &mut self.emitters
+    }
+
+    /// Removes every particle and resets emitters, so emission starts from scratch as if
+    /// particle system was just created.
+    pub fn clear_particles(&mut self) {
+        self.particles.clear();
+        self.free_particles.clear();
+        for emitter in self.emitters.iter_mut() {
+            emitter.alive_particles.set(0);
+            emitter.spawned_particles = 0;
+            emitter.particles_to_spawn = 0;
+            emitter.time = 0.0;
+        }
+    }
+
+    /// Moves every particle by given offset in local space. Particles live in local space of
+    /// particle system, so moving the node drags them along; offset them back to keep them
+    /// in place in the world.
+    pub fn offset_particles(&mut self, offset: Vec3) {
+        for particle in self.particles.iter_mut() {
+            particle.position += offset;
+        }
+    }
+
     pub fn acceleration(&mut self, accel: Vec3) {
         self.acceleration = accel;
     }
 
+    /// Sets how strongly particles are pushed by wind of scene, zero disables wind.
+    pub fn set_wind_influence(&mut self, wind_influence: f32) {
+        self.wind_influence = wind_influence;
+    }
+
+    pub fn wind_influence(&self) -> f32 {
+        self.wind_influence
+    }
+
+    /// Returns amount of particles which are alive at the moment.
+    pub fn alive_particle_count(&self) -> usize {
+        self.particles.iter().filter(|particle| particle.alive).count()
+    }
+
     pub fn color_over_lifetime_gradient(&mut self, gradient: ColorGradient) {
         self.color_over_lifetime = Some(gradient)
     }
 
-    pub fn update(&mut self, dt: f32) {
+    /// Sets distance fade of particle system, `None` disables fading. Emission rate is
+    /// scaled by fade factor of distance to nearest camera, so far systems emit fewer
+    /// particles and stop emitting at all when completely faded out. Particles which
+    /// are already emitted live their life as usual, unless system is frozen, see
+    /// `set_freeze_when_faded`.
+    pub fn set_distance_fade(&mut self, distance_fade: Option<DistanceFade>) {
+        self.distance_fade = distance_fade;
+    }
+
+    pub fn distance_fade(&self) -> Option<DistanceFade> {
+        self.distance_fade
+    }
+
+    /// Sets whether simulation of particle system should be stopped completely when it is
+    /// faded out by distance. Frozen system keeps its particles as is and continues from
+    /// the same state when camera comes closer, so it costs nothing but rendering.
+    pub fn set_freeze_when_faded(&mut self, freeze: bool) {
+        self.freeze_when_faded = freeze;
+    }
+
+    pub fn is_freeze_when_faded(&self) -> bool {
+        self.freeze_when_faded
+    }
+
+    /// Updates particle system, particles are pushed by given wind and force fields.
+    /// `camera_distance` is distance to nearest camera, it is used for distance fade. Called
+    /// automatically by graph.
+    pub fn update(&mut self, dt: f32, wind: &Wind, force_fields: &[ForceFieldSample], camera_distance: f32) {
+        let fade = self.distance_fade.map_or(1.0, |fade| fade.factor(camera_distance));
+        if fade <= 0.0 && self.freeze_when_faded {
+            return;
+        }
+
         for emitter in self.emitters.iter_mut() {
             emitter.tick(dt);
         }
 
+        // Emitters borrow particle system, so use a copy of generator and put it back
+        // when all particles are emitted.
+        let mut rng = self.rng;
         for (i, emitter) in self.emitters.iter().enumerate() {
             for _ in 0..emitter.particles_to_spawn {
+                // Thin out emission randomly, so rate decreases smoothly with distance.
+                if fade < 1.0 && rng.unit() >= fade {
+                    continue;
+                }
                 let mut particle = Particle::default();
                 particle.emitter_index = i as u32;
                 emitter.alive_particles.set(emitter.alive_particles.get() + 1);
-                emitter.emit(self, &mut particle);
+                emitter.emit(self, &mut particle, &mut rng);
                 if let Some(free_index) = self.free_particles.pop() {
                     self.particles[free_index as usize] = particle;
                 } else {
@@ -830,8 +931,12 @@ impl ParticleSystem {
                 }
             }
         }
+        self.rng = rng;
 
         let acceleration_offset = self.acceleration.scale(dt * dt);
+        // Wind is sampled in world space, particles are in local space of particle system.
+        let global_transform = self.global_transform();
+        let wind_scale = self.wind_influence * dt * dt;
 
         for (i, particle) in self.particles.iter_mut().enumerate() {
             if particle.alive {
@@ -845,6 +950,17 @@ impl ParticleSystem {
                     particle.lifetime = particle.initial_lifetime;
                 } else {
                     particle.velocity += acceleration_offset;
+                    if wind_scale != 0.0 || !force_fields.is_empty() {
+                        let world_position = global_transform.transform_vector(particle.position);
+                        if wind_scale != 0.0 {
+                            particle.velocity += wind.velocity_at(world_position).scale(wind_scale);
+                        }
+                        // Velocity of particle is displacement per update.
+                        let velocity = if dt > 0.0 { particle.velocity.scale(1.0 / dt) } else { Vec3::ZERO };
+                        for field in force_fields {
+                            particle.velocity += field.acceleration_at(world_position, velocity).scale(dt * dt);
+                        }
+                    }
                     particle.position += particle.velocity;
                     particle.size += particle.size_modifier * dt;
                     if particle.size < 0.0 {
@@ -939,6 +1055,23 @@ impl ParticleSystem {
     pub fn texture(&self) -> Option<Arc<Mutex<Texture>>> {
         self.texture.clone()
     }
+
+    /// Resets random number generator of particle system, so it will emit exactly
+    /// the same particles as before when updated with same time deltas.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng.reseed(seed)
+    }
+
+    /// Returns reference to random number generator of particle system.
+    pub fn rng(&self) -> &RandomGenerator {
+        &self.rng
+    }
+
+    /// Returns mutable reference to random number generator of particle system. Can be
+    /// used to get reproducible jitter in user code that is tied to this particle system.
+    pub fn rng_mut(&mut self) -> &mut RandomGenerator {
+        &mut self.rng
+    }
 }
 
 
@@ -951,7 +1084,11 @@ impl Visit for ParticleSystem {
         self.texture.visit("Texture", visitor)?;
         self.emitters.visit("Emitters", visitor)?;
         self.acceleration.visit("Acceleration", visitor)?;
+        self.wind_influence.visit("WindInfluence", visitor)?;
         self.color_over_lifetime.visit("ColorGradient", visitor)?;
+        self.rng.visit("Rng", visitor)?;
+        self.distance_fade.visit("DistanceFade", visitor)?;
+        self.freeze_when_faded.visit("FreezeWhenFaded", visitor)?;
         self.base.visit("Base", visitor)?;
 
         visitor.leave_region()
@@ -969,7 +1106,11 @@ pub struct ParticleSystemBuilder {
     emitters: Option<Vec<Emitter>>,
     texture: Option<Arc<Mutex<Texture>>>,
     acceleration: Option<Vec3>,
+    wind_influence: Option<f32>,
     color_over_lifetime: Option<ColorGradient>,
+    seed: Option<u64>,
+    distance_fade: Option<DistanceFade>,
+    freeze_when_faded: bool,
 }
 
 impl ParticleSystemBuilder {
@@ -979,10 +1120,21 @@ impl ParticleSystemBuilder {
             emitters: None,
             texture: None,
             acceleration: None,
+            wind_influence: None,
             color_over_lifetime: None,
+            seed: None,
+            distance_fade: None,
+            freeze_when_faded: false,
         }
     }
 
+    /// Sets seed for random number generator of particle system. If not set, seed
+    /// will be taken from global generator.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
     pub fn with_emitters(mut self, emitters: Vec<Emitter>) -> Self {
         self.emitters = Some(emitters);
         self
@@ -1003,11 +1155,29 @@ impl ParticleSystemBuilder {
         self
     }
 
+    /// Sets how strongly particles are pushed by wind, zero disables wind.
+    pub fn with_wind_influence(mut self, wind_influence: f32) -> Self {
+        self.wind_influence = Some(wind_influence);
+        self
+    }
+
     pub fn with_color_over_lifetime_gradient(mut self, color_over_lifetime: ColorGradient) -> Self {
         self.color_over_lifetime = Some(color_over_lifetime);
         self
     }
 
+    /// Sets distance fade of particle system, see `ParticleSystem::set_distance_fade`.
+    pub fn with_distance_fade(mut self, distance_fade: DistanceFade) -> Self {
+        self.distance_fade = Some(distance_fade);
+        self
+    }
+
+    /// Sets whether particle system should be frozen when it is faded out by distance.
+    pub fn with_freeze_when_faded(mut self, freeze: bool) -> Self {
+        self.freeze_when_faded = freeze;
+        self
+    }
+
     pub fn build(self) -> ParticleSystem {
         ParticleSystem {
             base: self.base_builder.build(),
@@ -1016,7 +1186,19 @@ impl ParticleSystemBuilder {
             emitters: self.emitters.unwrap_or_default(),
             texture: self.texture.clone(),
             acceleration: self.acceleration.unwrap_or_else(|| Vec3::new(0.0, -9.81, 0.0)),
+            wind_influence: self.wind_influence.unwrap_or(1.0),
             color_over_lifetime: self.color_over_lifetime,
+            rng: RandomGenerator::new(self.seed.unwrap_or_else(|| random::global().gen_u64())),
+            distance_fade: self.distance_fade,
+            freeze_when_faded: self.freeze_when_faded,
         }
     }
+
+    /// Creates new particle system node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::ParticleSystem(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
 }
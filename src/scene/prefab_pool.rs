@@ -0,0 +1,270 @@
+//! Pool of pre-instantiated prefabs.
+//!
+//! Games often spawn many short-living copies of the same model: bullets, shell casings,
+//! impact effects, etc. Instantiating a model copies its whole hierarchy and removing it
+//! frees it again, doing this each shot is wasteful. Prefab pool instantiates given
+//! amount of copies once, keeps them disabled in graph and hands them out on request.
+//! Acquired instance is reset to the state of the model (local transforms, visibility,
+//! particles and trail points) and enabled, released instance is disabled and returned
+//! back to the pool. Disabled nodes are excluded from updates and rendering, so idle
+//! instances cost almost nothing.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::math::vec3::Vec3,
+//!     engine::resource_manager::SharedModel,
+//!     scene::{Scene, prefab_pool::PrefabPool},
+//! };
+//!
+//! fn shoot(pool: &mut PrefabPool, scene: &mut Scene, position: Vec3) {
+//!     // Impact effect will be returned to the pool in two seconds.
+//!     if let Some(effect) = pool.acquire_with_lifetime(scene, 2.0) {
+//!         scene.graph[effect].local_transform_mut().set_position(position);
+//!     }
+//! }
+//!
+//! fn create_pool(model: SharedModel, scene: &mut Scene) -> PrefabPool {
+//!     PrefabPool::new(model, scene, 32).with_max_size(Some(64))
+//! }
+//! ```
+//!
+//! Pool does not own nodes in sense of graph, so if an instance is removed from graph
+//! manually, pool just skips it. Call `update` each frame if timed instances are used.
+
+use crate::{
+    core::pool::Handle,
+    engine::resource_manager::SharedModel,
+    scene::{
+        node::Node,
+        Scene,
+    },
+};
+
+struct TimedInstance {
+    handle: Handle<Node>,
+    time_left: f32,
+}
+
+/// See module docs.
+pub struct PrefabPool {
+    model: SharedModel,
+    free: Vec<Handle<Node>>,
+    active: Vec<Handle<Node>>,
+    timed: Vec<TimedInstance>,
+    max_size: Option<usize>,
+}
+
+impl PrefabPool {
+    /// Creates new pool and instantiates `count` disabled copies of given model in scene.
+    pub fn new(model: SharedModel, scene: &mut Scene, count: usize) -> Self {
+        let mut pool = Self {
+            model,
+            free: Vec::with_capacity(count),
+            active: Default::default(),
+            timed: Default::default(),
+            max_size: None,
+        };
+        pool.reserve(scene, count);
+        pool
+    }
+
+    /// Sets max amount of instances pool can hold. When every instance is in use and pool
+    /// has reached its max size, `acquire` returns `None`. `None` means that pool will grow
+    /// without limits, this is default behaviour.
+    pub fn with_max_size(mut self, max_size: Option<usize>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn set_max_size(&mut self, max_size: Option<usize>) {
+        self.max_size = max_size;
+    }
+
+    pub fn max_size(&self) -> Option<usize> {
+        self.max_size
+    }
+
+    pub fn model(&self) -> &SharedModel {
+        &self.model
+    }
+
+    /// Instantiates additional disabled copies so that pool has at least `count` free
+    /// instances, respecting max size.
+    pub fn reserve(&mut self, scene: &mut Scene, count: usize) {
+        while self.free.len() < count && self.can_grow() {
+            let instance = self.instantiate(scene);
+            self.free.push(instance);
+        }
+    }
+
+    fn can_grow(&self) -> bool {
+        self.max_size.map_or(true, |max_size| self.total_count() < max_size)
+    }
+
+    fn instantiate(&self, scene: &mut Scene) -> Handle<Node> {
+        let instance = self.model.lock().unwrap().instantiate_geometry(scene);
+        scene.graph[instance].set_enabled(false);
+        instance
+    }
+
+    /// Sets local transforms and visibility of every node of instance to the values of
+    /// its original node in the model, removes particles and trail points.
+    fn reset(&self, scene: &mut Scene, instance: Handle<Node>) {
+        let model = self.model.lock().unwrap();
+        let resource_graph = &model.get_scene().graph;
+        for handle in scene.graph.traverse_handle_iter(instance).collect::<Vec<_>>() {
+            let node = &mut scene.graph[handle];
+            let original = node.original_handle();
+            if resource_graph.is_valid_handle(original) {
+                let original = &resource_graph[original];
+                *node.local_transform_mut() = original.local_transform().clone();
+                node.set_visibility(original.visibility());
+            }
+            node.set_enabled(true);
+            match node {
+                Node::ParticleSystem(particle_system) => particle_system.clear_particles(),
+                Node::Trail(trail) => trail.clear_points(),
+                _ => ()
+            }
+        }
+    }
+
+    /// Returns reset and enabled instance of the model. New instance will be created if
+    /// there are no free instances and pool can grow. Instance is attached to root of the
+    /// graph and stays active until it is released.
+    pub fn acquire(&mut self, scene: &mut Scene) -> Option<Handle<Node>> {
+        let instance = loop {
+            match self.free.pop() {
+                Some(handle) => {
+                    // Instance could be removed from graph by user.
+                    if scene.graph.is_valid_handle(handle) {
+                        break handle;
+                    }
+                }
+                None => {
+                    if self.can_grow() {
+                        break self.instantiate(scene);
+                    } else {
+                        return None;
+                    }
+                }
+            }
+        };
+
+        self.reset(scene, instance);
+        self.active.push(instance);
+        Some(instance)
+    }
+
+    /// Same as `acquire`, but instance will be released automatically after given amount
+    /// of seconds by `update`.
+    pub fn acquire_with_lifetime(&mut self, scene: &mut Scene, time_seconds: f32) -> Option<Handle<Node>> {
+        let instance = self.acquire(scene)?;
+        self.timed.push(TimedInstance {
+            handle: instance,
+            time_left: time_seconds,
+        });
+        Some(instance)
+    }
+
+    /// Disables instance and returns it back to the pool. Returns false if instance was
+    /// not acquired from this pool or was already released.
+    pub fn release(&mut self, scene: &mut Scene, instance: Handle<Node>) -> bool {
+        if let Some(index) = self.active.iter().position(|h| *h == instance) {
+            self.active.swap_remove(index);
+            self.timed.retain(|timed| timed.handle != instance);
+            if scene.graph.is_valid_handle(instance) {
+                scene.graph[instance].set_enabled(false);
+                self.free.push(instance);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Releases every active instance.
+    pub fn release_all(&mut self, scene: &mut Scene) {
+        for instance in self.active.clone() {
+            self.release(scene, instance);
+        }
+    }
+
+    /// Releases instances which were acquired with lifetime and whose time is over.
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) {
+        let mut expired = Vec::new();
+        for timed in self.timed.iter_mut() {
+            timed.time_left -= dt;
+            if timed.time_left <= 0.0 {
+                expired.push(timed.handle);
+            }
+        }
+        for instance in expired {
+            self.release(scene, instance);
+        }
+    }
+
+    /// Returns true if given instance was acquired from this pool and not released yet.
+    pub fn is_active(&self, instance: Handle<Node>) -> bool {
+        self.active.contains(&instance)
+    }
+
+    pub fn active_instances(&self) -> &[Handle<Node>] {
+        &self.active
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.free.len() + self.active.len()
+    }
+
+    /// Removes every instance, both free and active, from the scene.
+    pub fn clear(&mut self, scene: &mut Scene) {
+        for instance in self.free.drain(..).chain(self.active.drain(..)) {
+            if scene.graph.is_valid_handle(instance) {
+                scene.graph.remove_node(instance);
+            }
+        }
+        self.timed.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use crate::{
+        resource::model::Model,
+        scene::{Scene, prefab_pool::PrefabPool},
+    };
+
+    #[test]
+    fn test_prefab_pool_reuse() {
+        let model = Arc::new(Mutex::new(Model::default()));
+        model.lock().unwrap().self_weak_ref = Some(Arc::downgrade(&model));
+
+        let mut scene = Scene::new();
+        let mut pool = PrefabPool::new(model, &mut scene, 2).with_max_size(Some(2));
+        assert_eq!(pool.free_count(), 2);
+
+        let a = pool.acquire(&mut scene).unwrap();
+        let b = pool.acquire_with_lifetime(&mut scene, 1.0).unwrap();
+        assert!(scene.graph[a].is_enabled() && scene.graph[b].is_enabled());
+        assert!(pool.acquire(&mut scene).is_none());
+
+        pool.update(&mut scene, 1.5);
+        assert!(!pool.is_active(b));
+        assert!(!scene.graph[b].is_enabled());
+        assert_eq!(pool.acquire(&mut scene), Some(b));
+
+        assert!(pool.release(&mut scene, a));
+        assert!(!pool.release(&mut scene, a));
+        assert_eq!(pool.total_count(), 2);
+    }
+}
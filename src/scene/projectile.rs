@@ -0,0 +1,470 @@
+//! Projectiles - lightweight ballistic objects (bullets, arrows, shells) which are moved by
+//! scene without creating a rigid body for each of them.
+//!
+//! Each update projectile is accelerated by gravity, slowed down by drag and moved along its
+//! velocity. Path passed during the update is swept by ray casts against physics world, so
+//! fast projectiles never tunnel through thin walls. Projectiles with radius are swept by a
+//! bundle of parallel rays around the path, which is good enough for small objects. When a
+//! projectile hits something, [`ProjectileHitMessage`] is published to event bus of scene
+//! and projectile is removed.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::{math::vec3::Vec3, pool::Handle},
+//!     physics::rigid_body::RigidBody,
+//!     scene::{
+//!         projectile::{ProjectileBuilder, ProjectileHitMessage},
+//!         Scene,
+//!     },
+//! };
+//!
+//! fn shoot(scene: &mut Scene, shooter: Handle<RigidBody>, muzzle: Vec3, direction: Vec3) {
+//!     scene.projectiles.add(ProjectileBuilder::new(muzzle, direction.scale(400.0))
+//!         .with_drag(0.1)
+//!         .with_ignored_body(shooter)
+//!         .with_user_data(25) // Damage.
+//!         .build());
+//! }
+//!
+//! fn apply_hits(scene: &Scene) {
+//!     for hit in scene.events.read::<ProjectileHitMessage>() {
+//!         println!("{:?} hit at {:?} with damage {}", hit.node, hit.position, hit.user_data);
+//!     }
+//! }
+//! ```
+//!
+//! Projectiles are updated right after physics step. Optional node (a tracer for example)
+//! is moved together with projectile and removed from graph when projectile ends.
+
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            ray::Ray,
+        },
+        pool::{
+            Handle,
+            Pool,
+            PoolIterator,
+            PoolIteratorMut,
+        },
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    physics::{
+        HitKind,
+        Physics,
+        RayCastOptions,
+        rigid_body::RigidBody,
+        static_geometry::StaticGeometry,
+    },
+    scene::{
+        event::EventBus,
+        graph::Graph,
+        node::Node,
+        PhysicsBinder,
+    },
+};
+
+/// Published to event bus of scene when projectile hits a rigid body or static geometry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProjectileHitMessage {
+    /// Handle of projectile, it is already removed from container.
+    pub projectile: Handle<Projectile>,
+    /// User data of projectile, see [`Projectile::user_data`].
+    pub user_data: u64,
+    /// Point of hit in world coordinates.
+    pub position: Vec3,
+    /// Normal of surface at point of hit.
+    pub normal: Vec3,
+    /// Velocity of projectile at the moment of hit in units per second.
+    pub velocity: Vec3,
+    /// Rigid body which was hit or `Handle::NONE` if static geometry was hit.
+    pub body: Handle<RigidBody>,
+    /// Node bound to hit rigid body, `Handle::NONE` if there is no such node.
+    pub node: Handle<Node>,
+    /// Static geometry which was hit or `Handle::NONE` if rigid body was hit.
+    pub static_geometry: Handle<StaticGeometry>,
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    position: Vec3,
+    velocity: Vec3,
+    gravity: Vec3,
+    drag: f32,
+    radius: f32,
+    time_left: f32,
+    ignored_body: Handle<RigidBody>,
+    node: Handle<Node>,
+    user_data: u64,
+}
+
+impl Default for Projectile {
+    fn default() -> Self {
+        ProjectileBuilder::new(Vec3::ZERO, Vec3::ZERO).build()
+    }
+}
+
+impl Projectile {
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Returns velocity in units per second.
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    pub fn set_velocity(&mut self, velocity: Vec3) {
+        self.velocity = velocity;
+    }
+
+    /// Returns time in seconds left before projectile is removed without a hit.
+    pub fn time_left(&self) -> f32 {
+        self.time_left
+    }
+
+    pub fn node(&self) -> Handle<Node> {
+        self.node
+    }
+
+    pub fn set_node(&mut self, node: Handle<Node>) {
+        self.node = node;
+    }
+
+    /// Returns arbitrary value passed to hit message, damage or kind of ammo for example.
+    pub fn user_data(&self) -> u64 {
+        self.user_data
+    }
+
+    /// Finds nearest hit of path from current position to given one.
+    fn sweep(&self, physics: &Physics, to: Vec3) -> Option<(Vec3, Vec3, HitKind)> {
+        let path = to - self.position;
+        let direction = path.normalized()?;
+
+        let mut offsets = vec![Vec3::ZERO];
+        if self.radius > 0.0 {
+            let up = if direction.y.abs() < 0.99 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+            let side = direction.cross(&up).normalized().unwrap_or(Vec3::new(1.0, 0.0, 0.0));
+            let up = side.cross(&direction);
+            offsets.extend_from_slice(&[
+                side.scale(self.radius),
+                side.scale(-self.radius),
+                up.scale(self.radius),
+                up.scale(-self.radius),
+            ]);
+        }
+
+        let options = RayCastOptions {
+            ignore_bodies: false,
+            ignore_static_geometries: false,
+            sort_results: true,
+        };
+
+        let mut nearest: Option<(f32, Vec3, Vec3, HitKind)> = None;
+        let mut results = Vec::new();
+        for offset in offsets {
+            let begin = self.position + offset;
+            let ray = match Ray::from_two_points(&begin, &(begin + path)) {
+                Some(ray) => ray,
+                None => continue,
+            };
+            results.clear();
+            physics.ray_cast(&ray, options, &mut results);
+            let hit = results.iter().find(|result| match result.kind {
+                HitKind::Body(body) => body != self.ignored_body,
+                HitKind::StaticTriangle { .. } => true,
+            });
+            if let Some(hit) = hit {
+                if nearest.as_ref().map_or(true, |(sqr_distance, ..)| hit.sqr_distance < *sqr_distance) {
+                    // Point of hit of side ray is moved back to center of projectile.
+                    nearest = Some((hit.sqr_distance, hit.position - offset, hit.normal, hit.kind.clone()));
+                }
+            }
+        }
+
+        nearest.map(|(_, position, normal, kind)| (position, normal, kind))
+    }
+}
+
+impl Visit for Projectile {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.position.visit("Position", visitor)?;
+        self.velocity.visit("Velocity", visitor)?;
+        self.gravity.visit("Gravity", visitor)?;
+        self.drag.visit("Drag", visitor)?;
+        self.radius.visit("Radius", visitor)?;
+        self.time_left.visit("TimeLeft", visitor)?;
+        self.ignored_body.visit("IgnoredBody", visitor)?;
+        self.node.visit("Node", visitor)?;
+        self.user_data.visit("UserData", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct ProjectileBuilder {
+    position: Vec3,
+    velocity: Vec3,
+    gravity: Option<Vec3>,
+    drag: Option<f32>,
+    radius: Option<f32>,
+    lifetime: Option<f32>,
+    ignored_body: Handle<RigidBody>,
+    node: Handle<Node>,
+    user_data: u64,
+}
+
+impl ProjectileBuilder {
+    /// Creates builder of projectile at given position in world coordinates with given
+    /// velocity in units per second.
+    pub fn new(position: Vec3, velocity: Vec3) -> Self {
+        Self {
+            position,
+            velocity,
+            gravity: None,
+            drag: None,
+            radius: None,
+            lifetime: None,
+            ignored_body: Handle::NONE,
+            node: Handle::NONE,
+            user_data: 0,
+        }
+    }
+
+    /// Sets acceleration of gravity. Default is (0, -9.81, 0).
+    pub fn with_gravity(mut self, gravity: Vec3) -> Self {
+        self.gravity = Some(gravity);
+        self
+    }
+
+    /// Sets drag coefficient - fraction of velocity lost per second. Default is zero.
+    pub fn with_drag(mut self, drag: f32) -> Self {
+        self.drag = Some(drag);
+        self
+    }
+
+    /// Sets radius of projectile, zero radius means that path is swept by single ray.
+    /// Default is zero.
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    /// Sets time in seconds after which projectile is removed if it did not hit anything.
+    /// Default is 10 seconds.
+    pub fn with_lifetime(mut self, lifetime: f32) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// Sets rigid body which can't be hit by projectile, usually body of shooter.
+    pub fn with_ignored_body(mut self, body: Handle<RigidBody>) -> Self {
+        self.ignored_body = body;
+        self
+    }
+
+    /// Sets node which is moved together with projectile and removed with it.
+    pub fn with_node(mut self, node: Handle<Node>) -> Self {
+        self.node = node;
+        self
+    }
+
+    pub fn with_user_data(mut self, user_data: u64) -> Self {
+        self.user_data = user_data;
+        self
+    }
+
+    pub fn build(self) -> Projectile {
+        Projectile {
+            position: self.position,
+            velocity: self.velocity,
+            gravity: self.gravity.unwrap_or(Vec3::new(0.0, -9.81, 0.0)),
+            drag: self.drag.unwrap_or(0.0).max(0.0),
+            radius: self.radius.unwrap_or(0.0).max(0.0),
+            time_left: self.lifetime.unwrap_or(10.0),
+            ignored_body: self.ignored_body,
+            node: self.node,
+            user_data: self.user_data,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProjectileContainer {
+    pool: Pool<Projectile>,
+}
+
+impl Default for ProjectileContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProjectileContainer {
+    pub(in crate) fn new() -> Self {
+        Self {
+            pool: Pool::new(),
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> PoolIterator<Projectile> {
+        self.pool.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<Projectile> {
+        self.pool.iter_mut()
+    }
+
+    #[inline]
+    pub fn add(&mut self, projectile: Projectile) -> Handle<Projectile> {
+        self.pool.spawn(projectile)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, handle: Handle<Projectile>) {
+        self.pool.free(handle);
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.pool.clear()
+    }
+
+    #[inline]
+    pub fn is_valid_handle(&self, handle: Handle<Projectile>) -> bool {
+        self.pool.is_valid_handle(handle)
+    }
+
+    #[inline]
+    pub fn get(&self, handle: Handle<Projectile>) -> &Projectile {
+        self.pool.borrow(handle)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, handle: Handle<Projectile>) -> &mut Projectile {
+        self.pool.borrow_mut(handle)
+    }
+
+    #[inline]
+    pub fn retain<P>(&mut self, pred: P) where P: FnMut(&Projectile) -> bool {
+        self.pool.retain(pred)
+    }
+
+    #[inline]
+    pub fn alive_count(&self) -> usize {
+        self.pool.alive_count()
+    }
+
+    /// Moves every projectile, publishes hits and removes projectiles which hit something
+    /// or lived out their time.
+    pub(in crate) fn update(&mut self,
+                            physics: &Physics,
+                            physics_binder: &PhysicsBinder,
+                            graph: &mut Graph,
+                            events: &mut EventBus,
+                            dt: f32,
+    ) {
+        let mut ended = Vec::new();
+        for (handle, projectile) in self.pool.pair_iter_mut() {
+            projectile.velocity += projectile.gravity.scale(dt);
+            projectile.velocity = projectile.velocity.scale((1.0 - projectile.drag * dt).max(0.0));
+            let next_position = projectile.position + projectile.velocity.scale(dt);
+
+            if let Some((position, normal, kind)) = projectile.sweep(physics, next_position) {
+                let (body, static_geometry) = match kind {
+                    HitKind::Body(body) => (body, Handle::NONE),
+                    HitKind::StaticTriangle { static_geometry, .. } => (Handle::NONE, static_geometry),
+                };
+                events.publish(ProjectileHitMessage {
+                    projectile: handle,
+                    user_data: projectile.user_data,
+                    position,
+                    normal,
+                    velocity: projectile.velocity,
+                    body,
+                    node: physics_binder.node_of(body),
+                    static_geometry,
+                });
+                projectile.position = position;
+                ended.push(handle);
+            } else {
+                projectile.position = next_position;
+                projectile.time_left -= dt;
+                if projectile.time_left <= 0.0 {
+                    ended.push(handle);
+                }
+            }
+
+            if graph.is_valid_handle(projectile.node) {
+                graph[projectile.node].local_transform_mut().set_position(projectile.position);
+            }
+        }
+
+        for handle in ended {
+            let node = self.pool.borrow(handle).node;
+            if graph.is_valid_handle(node) {
+                graph.remove_node(node);
+            }
+            self.pool.free(handle);
+        }
+    }
+
+    pub(in crate) fn shift_origin(&mut self, offset: Vec3) {
+        for projectile in self.pool.iter_mut() {
+            projectile.position = projectile.position - offset;
+        }
+    }
+}
+
+impl Visit for ProjectileContainer {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.pool.visit("Pool", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        physics::Physics,
+        scene::{
+            event::EventBus,
+            graph::Graph,
+            projectile::{ProjectileBuilder, ProjectileContainer},
+            PhysicsBinder,
+        },
+    };
+
+    #[test]
+    fn test_projectile_ballistics() {
+        let mut container = ProjectileContainer::new();
+        let projectile = container.add(ProjectileBuilder::new(Vec3::ZERO, Vec3::new(10.0, 0.0, 0.0))
+            .with_lifetime(1.0)
+            .build());
+
+        let physics = Physics::default();
+        let binder = PhysicsBinder::default();
+        let mut graph = Graph::new();
+        let mut events = EventBus::default();
+
+        container.update(&physics, &binder, &mut graph, &mut events, 0.5);
+        let position = container.get(projectile).position();
+        assert_eq!(position.x, 5.0);
+        // Gravity pulls down.
+        assert!(position.y < 0.0);
+
+        container.update(&physics, &binder, &mut graph, &mut events, 0.5);
+        assert!(!container.is_valid_handle(projectile));
+        assert_eq!(container.alive_count(), 0);
+    }
+}
@@ -0,0 +1,438 @@
+//! Projector is a node which projects texture onto geometry within its frustum, like a slide
+//! projector or a stage gobo.
+//!
+//! Unlike cookie of spot light, projection does not depend on lighting: it is applied to
+//! lit frame, so it is visible in darkness too and does not cost shadow maps. Mode defines
+//! how texture is combined with frame: `Additive` for stage effects and caustic patterns,
+//! `Multiply` for fake cloud shadows, `AlphaBlend` for slides. Texture can scroll, which
+//! is enough for moving clouds and water caustics. Projection is faded out on surfaces
+//! which are turned away from projector.
+//!
+//! Projector looks along its local Z axis. Every visible projector costs one full screen
+//! pass. Projection is applied to opaque geometry only, transparent objects (particles,
+//! sprites) are not affected.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     scene::{
+//!         Scene,
+//!         base::BaseBuilder,
+//!         projector::{ProjectorBuilder, ProjectorMode, ProjectorProjection},
+//!     },
+//!     core::math::vec2::Vec2,
+//!     engine::resource_manager::SharedTexture,
+//! };
+//!
+//! fn add_cloud_shadows(scene: &mut Scene, clouds: SharedTexture) {
+//!     let root = scene.graph.get_root();
+//!     ProjectorBuilder::new(BaseBuilder::new())
+//!         .with_texture(clouds)
+//!         .with_mode(ProjectorMode::Multiply)
+//!         .with_projection(ProjectorProjection::Orthographic { width: 200.0, height: 200.0 })
+//!         .with_far(500.0)
+//!         .with_scroll_speed(Vec2::new(0.01, 0.005))
+//!         .build_on(&mut scene.graph, root);
+//! }
+//! ```
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
+use crate::{
+    resource::texture::Texture,
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        pool::Handle,
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
+            mat4::Mat4,
+        },
+        color::Color,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+/// How projected texture is combined with frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProjectorMode {
+    /// Color of texture is added to frame.
+    Additive,
+    /// Frame is multiplied by color of texture.
+    Multiply,
+    /// Texture is blended over frame using its alpha.
+    AlphaBlend,
+}
+
+impl ProjectorMode {
+    fn id(self) -> u32 {
+        match self {
+            ProjectorMode::Additive => 0,
+            ProjectorMode::Multiply => 1,
+            ProjectorMode::AlphaBlend => 2,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(ProjectorMode::Additive),
+            1 => Ok(ProjectorMode::Multiply),
+            2 => Ok(ProjectorMode::AlphaBlend),
+            _ => Err(format!("Invalid projector mode {}", id))
+        }
+    }
+}
+
+/// Shape of frustum of projector.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProjectorProjection {
+    /// Projection from a point, field of view is vertical and in radians, width of image
+    /// is defined by aspect ratio of projector.
+    Perspective { fov: f32 },
+    /// Parallel projection onto rectangle of given size in local units.
+    Orthographic { width: f32, height: f32 },
+}
+
+#[derive(Clone)]
+pub struct Projector {
+    base: Base,
+    texture: Option<Arc<Mutex<Texture>>>,
+    mode: ProjectorMode,
+    projection: ProjectorProjection,
+    aspect: f32,
+    z_near: f32,
+    z_far: f32,
+    color: Color,
+    intensity: f32,
+    scroll_speed: Vec2,
+    scroll_offset: Vec2,
+}
+
+impl Deref for Projector {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Projector {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Projector {
+    fn default() -> Self {
+        ProjectorBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl Projector {
+    /// Sets projected texture, projector without texture projects its color.
+    pub fn set_texture(&mut self, texture: Option<Arc<Mutex<Texture>>>) {
+        self.texture = texture;
+    }
+
+    pub fn texture(&self) -> Option<Arc<Mutex<Texture>>> {
+        self.texture.clone()
+    }
+
+    pub fn set_mode(&mut self, mode: ProjectorMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> ProjectorMode {
+        self.mode
+    }
+
+    pub fn set_projection(&mut self, projection: ProjectorProjection) {
+        self.projection = projection;
+    }
+
+    pub fn projection(&self) -> ProjectorProjection {
+        self.projection
+    }
+
+    pub fn is_perspective(&self) -> bool {
+        match self.projection {
+            ProjectorProjection::Perspective { .. } => true,
+            ProjectorProjection::Orthographic { .. } => false,
+        }
+    }
+
+    /// Sets ratio of width to height of perspective projection.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect.max(0.001);
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    /// Sets distances of near and far planes of frustum, nothing is projected outside of
+    /// them.
+    pub fn set_range(&mut self, z_near: f32, z_far: f32) {
+        self.z_near = z_near.max(0.001);
+        self.z_far = z_far.max(self.z_near + 0.001);
+    }
+
+    pub fn z_near(&self) -> f32 {
+        self.z_near
+    }
+
+    pub fn z_far(&self) -> f32 {
+        self.z_far
+    }
+
+    /// Sets color which is multiplied with texture.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Sets strength of projection, in `Multiply` mode zero means no effect and one means
+    /// full effect.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Sets speed of scrolling of texture in texture coordinates per second.
+    pub fn set_scroll_speed(&mut self, speed: Vec2) {
+        self.scroll_speed = speed;
+    }
+
+    pub fn scroll_speed(&self) -> Vec2 {
+        self.scroll_speed
+    }
+
+    /// Returns current offset of texture coordinates.
+    pub fn scroll_offset(&self) -> Vec2 {
+        self.scroll_offset
+    }
+
+    pub(in crate) fn update(&mut self, dt: f32) {
+        let offset = self.scroll_offset + self.scroll_speed.scale(dt);
+        // Texture repeats, so only fractional part matters.
+        self.scroll_offset = Vec2::new(offset.x.fract(), offset.y.fract());
+    }
+
+    /// Returns matrix which transforms world space points into clip space of projector.
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        let position = self.global_position();
+        let look = self.look_vector().normalized().unwrap_or(Vec3::new(0.0, 0.0, 1.0));
+        let up = self.up_vector().normalized().unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+        let view = Mat4::look_at(position, position + look, up).unwrap_or_default();
+        let projection = match self.projection {
+            ProjectorProjection::Perspective { fov } => Mat4::perspective(fov, self.aspect, self.z_near, self.z_far),
+            ProjectorProjection::Orthographic { width, height } => {
+                Mat4::ortho(-width * 0.5, width * 0.5, -height * 0.5, height * 0.5, self.z_near, self.z_far)
+            }
+        };
+        projection * view
+    }
+
+    /// Returns center and radius of sphere which encloses frustum of projector.
+    pub fn bounding_sphere(&self) -> (Vec3, f32) {
+        let (half_width, half_height) = match self.projection {
+            ProjectorProjection::Perspective { fov } => {
+                let half_height = (fov * 0.5).tan() * self.z_far;
+                (half_height * self.aspect, half_height)
+            }
+            ProjectorProjection::Orthographic { width, height } => (width * 0.5, height * 0.5),
+        };
+        let half_depth = (self.z_far - self.z_near) * 0.5;
+        let look = self.look_vector().normalized().unwrap_or(Vec3::new(0.0, 0.0, 1.0));
+        let center = self.global_position() + look.scale(self.z_near + half_depth);
+        let radius = (half_width * half_width + half_height * half_height + half_depth * half_depth).sqrt();
+        (center, radius)
+    }
+}
+
+impl Visit for Projector {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.texture.visit("Texture", visitor)?;
+        let mut mode_id = self.mode.id();
+        mode_id.visit("Mode", visitor)?;
+        if visitor.is_reading() {
+            self.mode = ProjectorMode::from_id(mode_id)?;
+        }
+        let (mut kind, mut a, mut b) = match self.projection {
+            ProjectorProjection::Perspective { fov } => (0u32, fov, 0.0),
+            ProjectorProjection::Orthographic { width, height } => (1u32, width, height),
+        };
+        kind.visit("ProjectionKind", visitor)?;
+        a.visit("ProjectionA", visitor)?;
+        b.visit("ProjectionB", visitor)?;
+        if visitor.is_reading() {
+            self.projection = match kind {
+                0 => ProjectorProjection::Perspective { fov: a },
+                1 => ProjectorProjection::Orthographic { width: a, height: b },
+                _ => return Err(format!("Invalid projection kind {}", kind).into()),
+            };
+        }
+        self.aspect.visit("Aspect", visitor)?;
+        self.z_near.visit("ZNear", visitor)?;
+        self.z_far.visit("ZFar", visitor)?;
+        self.color.visit("Color", visitor)?;
+        self.intensity.visit("Intensity", visitor)?;
+        self.scroll_speed.visit("ScrollSpeed", visitor)?;
+        self.scroll_offset.visit("ScrollOffset", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct ProjectorBuilder {
+    base_builder: BaseBuilder,
+    texture: Option<Arc<Mutex<Texture>>>,
+    mode: ProjectorMode,
+    projection: ProjectorProjection,
+    aspect: f32,
+    z_near: f32,
+    z_far: f32,
+    color: Color,
+    intensity: f32,
+    scroll_speed: Vec2,
+}
+
+impl ProjectorBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            texture: None,
+            mode: ProjectorMode::Additive,
+            projection: ProjectorProjection::Perspective { fov: 45.0f32.to_radians() },
+            aspect: 1.0,
+            z_near: 0.1,
+            z_far: 10.0,
+            color: Color::WHITE,
+            intensity: 1.0,
+            scroll_speed: Vec2::ZERO,
+        }
+    }
+
+    pub fn with_texture(mut self, texture: Arc<Mutex<Texture>>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: ProjectorMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_projection(mut self, projection: ProjectorProjection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_aspect(mut self, aspect: f32) -> Self {
+        self.aspect = aspect;
+        self
+    }
+
+    pub fn with_near(mut self, z_near: f32) -> Self {
+        self.z_near = z_near;
+        self
+    }
+
+    pub fn with_far(mut self, z_far: f32) -> Self {
+        self.z_far = z_far;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_scroll_speed(mut self, speed: Vec2) -> Self {
+        self.scroll_speed = speed;
+        self
+    }
+
+    pub fn build(self) -> Projector {
+        let mut projector = Projector {
+            base: self.base_builder.build(),
+            texture: self.texture,
+            mode: self.mode,
+            projection: self.projection,
+            aspect: 1.0,
+            z_near: 0.1,
+            z_far: 10.0,
+            color: self.color,
+            intensity: self.intensity.max(0.0),
+            scroll_speed: self.scroll_speed,
+            scroll_offset: Vec2::ZERO,
+        };
+        projector.set_aspect(self.aspect);
+        projector.set_range(self.z_near, self.z_far);
+        projector
+    }
+
+    /// Creates new projector node, adds it to given graph and links it with given parent,
+    /// returns handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Projector(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec2::Vec2,
+        scene::{
+            base::BaseBuilder,
+            projector::ProjectorBuilder,
+        },
+    };
+
+    #[test]
+    fn test_projector_scroll() {
+        let mut projector = ProjectorBuilder::new(BaseBuilder::new())
+            .with_scroll_speed(Vec2::new(0.25, -0.5))
+            .with_near(5.0)
+            .with_far(1.0)
+            .build();
+        assert!(projector.z_far() > projector.z_near());
+
+        projector.update(1.0);
+        projector.update(4.0);
+        let offset = projector.scroll_offset();
+        assert!(offset.x.abs() < 1.0e-5);
+        assert!(offset.y.abs() < 1.0e-5);
+
+        projector.update(1.0);
+        assert!((projector.scroll_offset().x - 0.25).abs() < 1.0e-5);
+    }
+}
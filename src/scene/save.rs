@@ -0,0 +1,302 @@
+//! Save games which store only dynamic state of scene.
+//!
+//! `Scene` visits everything it has, so a save made with `Scene::visit` contains whole
+//! scene and it can't follow changes in assets. Usually it is better to store only the
+//! state that was changed by playing: scene is created in the same way as it was
+//! created at the start of the game (level is loaded and instantiated from resources), then
+//! dynamic state is applied on top of it.
+//!
+//! Dynamic state consists of:
+//!
+//! - local transform, visibility and enabled state of persistent nodes (see
+//! `Base::set_persistent`) together with position and velocity of rigid bodies bound to them;
+//! - time position, speed and enabled state of animations;
+//! - origin of scene (see `scene::origin`).
+//!
+//! Game data is stored in separate regions of the same visitor:
+//!
+//! ```no_run
+//! use rg3d::{
+//!     scene::Scene,
+//!     core::visitor::{Visitor, VisitResult, Visit},
+//! };
+//!
+//! fn save(scene: &mut Scene, score: &mut u32) -> VisitResult {
+//!     let mut visitor = Visitor::new();
+//!     scene.visit_state("Level", &mut visitor)?;
+//!     score.visit("Score", &mut visitor)?;
+//!     visitor.save_binary("save.bin")
+//! }
+//! ```
+//!
+//! Loading is the same, except that visitor is loaded from file and scene must be created
+//! the same way as before saving. Nodes are matched by handle and name, if node with
+//! saved handle has different name (content was changed) node is searched by name. Nodes
+//! created while playing (projectiles, spawned enemies) are not restored, game must
+//! store and re-create them itself.
+
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+    scene::{
+        Scene,
+        node::Node,
+        transform::Transform,
+        origin::AbsolutePosition,
+    },
+    animation::Animation,
+};
+
+/// Position and velocity of rigid body bound to node.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BodyState {
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+impl Visit for BodyState {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.position.visit("Position", visitor)?;
+        self.velocity.visit("Velocity", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Dynamic state of persistent node.
+#[derive(Clone, Default)]
+pub struct NodeState {
+    pub handle: Handle<Node>,
+    pub name: String,
+    pub transform: Transform,
+    pub visibility: bool,
+    pub enabled: bool,
+    pub body: Option<BodyState>,
+}
+
+impl Visit for NodeState {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.handle.visit("Handle", visitor)?;
+        self.name.visit("Name", visitor)?;
+        self.transform.visit("Transform", visitor)?;
+        self.visibility.visit("Visibility", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+        self.body.visit("Body", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Dynamic state of animation.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AnimationState {
+    pub handle: Handle<Animation>,
+    pub time_position: f32,
+    pub speed: f32,
+    pub enabled: bool,
+}
+
+impl Visit for AnimationState {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.handle.visit("Handle", visitor)?;
+        self.time_position.visit("TimePosition", visitor)?;
+        self.speed.visit("Speed", visitor)?;
+        self.enabled.visit("Enabled", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Default)]
+pub struct SceneState {
+    pub nodes: Vec<NodeState>,
+    pub animations: Vec<AnimationState>,
+    pub origin: AbsolutePosition,
+}
+
+impl SceneState {
+    /// Captures dynamic state of persistent nodes of scene.
+    pub fn capture(scene: &Scene) -> Self {
+        Self::capture_filtered(scene, |_, node| node.is_persistent())
+    }
+
+    /// Captures dynamic state of nodes for which `filter` returns true.
+    pub fn capture_filtered<F>(scene: &Scene, mut filter: F) -> Self
+        where F: FnMut(Handle<Node>, &Node) -> bool {
+        let nodes = scene.graph.pair_iter()
+            .filter(|&(handle, node)| filter(handle, node))
+            .map(|(handle, node)| NodeState {
+                handle,
+                name: node.name().to_owned(),
+                transform: node.local_transform().clone(),
+                visibility: node.visibility(),
+                enabled: node.is_enabled(),
+                body: scene.physics_binder.body_of(handle)
+                    .filter(|&body| scene.physics.is_valid_body_handle(body))
+                    .map(|body| {
+                        let body = scene.physics.borrow_body(body);
+                        BodyState {
+                            position: body.get_position(),
+                            velocity: body.get_velocity(),
+                        }
+                    }),
+            })
+            .collect();
+
+        let animations = scene.animations.pair_iter()
+            .map(|(handle, animation)| AnimationState {
+                handle,
+                time_position: animation.get_time_position(),
+                speed: animation.get_speed(),
+                enabled: animation.is_enabled(),
+            })
+            .collect();
+
+        Self {
+            nodes,
+            animations,
+            origin: scene.origin(),
+        }
+    }
+
+    fn find_node(scene: &Scene, state: &NodeState) -> Handle<Node> {
+        if scene.graph.is_valid_handle(state.handle) && scene.graph[state.handle].name() == state.name {
+            state.handle
+        } else {
+            scene.graph.find_by_name_from_root(&state.name)
+        }
+    }
+
+    /// Applies state to scene, states of nodes and animations which can't be found in
+    /// scene are ignored. Returns amount of node states which were not applied.
+    pub fn apply(&self, scene: &mut Scene) -> usize {
+        let mut missing = 0;
+
+        for state in self.nodes.iter() {
+            let handle = Self::find_node(scene, state);
+            if handle.is_none() {
+                missing += 1;
+                continue;
+            }
+
+            let node = &mut scene.graph[handle];
+            node.set_local_transform(state.transform.clone());
+            node.set_visibility(state.visibility);
+            node.set_enabled(state.enabled);
+
+            if let Some(body_state) = state.body {
+                if let Some(body) = scene.physics_binder.body_of(handle) {
+                    if scene.physics.is_valid_body_handle(body) {
+                        let body = scene.physics.borrow_body_mut(body);
+                        body.set_position(body_state.position);
+                        body.set_velocity(body_state.velocity);
+                    }
+                }
+            }
+        }
+
+        for state in self.animations.iter() {
+            if scene.animations.is_valid_handle(state.handle) {
+                let animation = scene.animations.get_mut(state.handle);
+                animation.set_time_position(state.time_position);
+                animation.set_speed(state.speed);
+                animation.set_enabled(state.enabled);
+            }
+        }
+
+        scene.origin = self.origin;
+        scene.graph.update_hierachical_data();
+
+        missing
+    }
+}
+
+impl Visit for SceneState {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.nodes.visit("Nodes", visitor)?;
+        self.animations.visit("Animations", visitor)?;
+        self.origin.visit("Origin", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        physics::{
+            rigid_body::RigidBody,
+            convex_shape::{ConvexShape, SphereShape},
+        },
+        scene::{
+            Scene,
+            node::Node,
+            base::BaseBuilder,
+            save::SceneState,
+        },
+    };
+
+    #[test]
+    fn test_scene_state_round_trip() {
+        let mut scene = Scene::new();
+        let persistent = scene.graph.add_node(Node::Base(BaseBuilder::new()
+            .with_name("Door")
+            .with_persistent(true)
+            .build()));
+        let other = scene.graph.add_node(Node::Base(BaseBuilder::new()
+            .with_name("Wall")
+            .build()));
+
+        scene.graph[persistent].local_transform_mut().set_position(Vec3::new(1.0, 2.0, 3.0));
+        scene.graph[other].local_transform_mut().set_position(Vec3::new(4.0, 5.0, 6.0));
+        let state = SceneState::capture(&scene);
+        assert_eq!(state.nodes.len(), 1);
+
+        scene.graph[persistent].local_transform_mut().set_position(Vec3::ZERO);
+        scene.graph[other].local_transform_mut().set_position(Vec3::ZERO);
+        assert_eq!(state.apply(&mut scene), 0);
+
+        assert_eq!(scene.graph[persistent].local_transform().position(), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(scene.graph[other].local_transform().position(), Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_body_state_round_trip() {
+        let mut scene = Scene::new();
+        let crate_node = scene.graph.add_node(Node::Base(BaseBuilder::new()
+            .with_name("Crate")
+            .with_persistent(true)
+            .build()));
+        let body = scene.physics.add_body(RigidBody::new(ConvexShape::Sphere(SphereShape::new(0.5))));
+        scene.physics_binder.bind(crate_node, body);
+        scene.physics.borrow_body_mut(body).set_position(Vec3::new(1.0, 2.0, 3.0));
+        scene.physics.borrow_body_mut(body).set_velocity(Vec3::new(0.0, -0.1, 0.0));
+
+        let state = SceneState::capture(&scene);
+        let hash = scene.physics_state_hash();
+
+        scene.physics.borrow_body_mut(body).set_position(Vec3::ZERO);
+        scene.physics.borrow_body_mut(body).set_velocity(Vec3::ZERO);
+        assert_ne!(scene.physics_state_hash(), hash);
+
+        state.apply(&mut scene);
+        assert_eq!(scene.physics_state_hash(), hash);
+    }
+}
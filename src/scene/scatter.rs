@@ -0,0 +1,392 @@
+//! Scatter is a node which renders many copies (instances) of the same mesh - grass, rocks,
+//! bushes, etc.
+//!
+//! Placing thousands of separate mesh nodes by hand is infeasible and rendering of them one
+//! by one is very slow, so scatter stores only position, rotation and scale of each instance
+//! and renders all visible instances of each surface using single instanced draw call.
+//! Instances can be generated over arbitrary surface (terrain for example) using density map.
+//!
+//! Instances which are farther from camera than cull distance are not rendered, instances
+//! between fade start and cull distance are gradually shrunk, so they do not pop out.
+//!
+//! Instances sway under wind of scene - each instance is tilted along wind direction
+//! proportionally to wind speed at its position, so gusts run over the field as waves.
+
+use std::{
+    cell::Cell,
+    ops::{Deref, DerefMut},
+};
+use crate::{
+    renderer::surface::{
+        Surface,
+        SurfaceSharedData,
+    },
+    resource::texture::Texture,
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        wind::Wind,
+        graph::Graph,
+        node::Node,
+    },
+    utils::random::RandomGenerator,
+    core::{
+        pool::Handle,
+        math::{
+            vec3::Vec3,
+            mat4::Mat4,
+            quat::{Quat, RotationOrder},
+            frustum::Frustum,
+        },
+        numeric_range::NumericRange,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+/// Placement of single instance, in local coordinates of scatter node.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScatterInstance {
+    pub position: Vec3,
+    /// Rotation around Y axis in radians.
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+impl Default for ScatterInstance {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl ScatterInstance {
+    fn matrix(&self, scale: f32) -> Mat4 {
+        let rotation = Quat::from_euler(Vec3::new(0.0, self.rotation, 0.0), RotationOrder::XYZ);
+        Mat4::translate(self.position) * Mat4::from_quat(rotation) * Mat4::scale(Vec3::new(scale, scale, scale))
+    }
+}
+
+impl Visit for ScatterInstance {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.position.visit("Position", visitor)?;
+        self.rotation.visit("Rotation", visitor)?;
+        self.scale.visit("Scale", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Clone)]
+pub struct Scatter {
+    base: Base,
+    surfaces: Vec<Surface>,
+    instances: Vec<ScatterInstance>,
+    fade_start: f32,
+    cull_distance: f32,
+    sway: f32,
+    wind: Wind,
+    bounding_radius: Cell<f32>,
+    bounding_radius_dirty: Cell<bool>,
+}
+
+impl Deref for Scatter {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Scatter {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Scatter {
+    fn default() -> Self {
+        ScatterBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+/// Maximum tilt of instance caused by wind, in radians.
+const MAX_SWAY_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
+
+fn sample_density(map: &Texture, u: f32, v: f32) -> f32 {
+    if !map.is_loaded() || map.width == 0 || map.height == 0 {
+        return 1.0;
+    }
+    let x = ((u.max(0.0).min(1.0) * (map.width - 1) as f32).round()) as usize;
+    let y = ((v.max(0.0).min(1.0) * (map.height - 1) as f32).round()) as usize;
+    map.first_channel(x, y)
+}
+
+impl Scatter {
+    /// Returns surfaces which are rendered for each instance.
+    pub fn surfaces(&self) -> &[Surface] {
+        &self.surfaces
+    }
+
+    /// Sets surfaces which are rendered for each instance, usually surfaces are taken from
+    /// some mesh. Skinned surfaces are not supported. Surfaces are not serialized, they must
+    /// be set again after load.
+    pub fn set_surfaces(&mut self, surfaces: Vec<Surface>) {
+        self.surfaces = surfaces;
+        self.bounding_radius_dirty.set(true);
+    }
+
+    pub fn instances(&self) -> &[ScatterInstance] {
+        &self.instances
+    }
+
+    pub fn add_instance(&mut self, instance: ScatterInstance) {
+        self.instances.push(instance);
+    }
+
+    pub fn set_instances(&mut self, instances: Vec<ScatterInstance>) {
+        self.instances = instances;
+    }
+
+    pub fn clear_instances(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Sets distance from camera at which instances start to shrink.
+    pub fn set_fade_start(&mut self, fade_start: f32) {
+        self.fade_start = fade_start;
+    }
+
+    pub fn fade_start(&self) -> f32 {
+        self.fade_start
+    }
+
+    /// Sets distance from camera after which instances are not rendered.
+    pub fn set_cull_distance(&mut self, cull_distance: f32) {
+        self.cull_distance = cull_distance;
+    }
+
+    pub fn cull_distance(&self) -> f32 {
+        self.cull_distance
+    }
+
+    /// Sets how much instances are tilted by wind, in radians per unit of wind speed. Zero
+    /// disables sway.
+    pub fn set_sway(&mut self, sway: f32) {
+        self.sway = sway.max(0.0);
+    }
+
+    pub fn sway(&self) -> f32 {
+        self.sway
+    }
+
+    /// Remembers wind which is used to sway instances, called automatically by graph.
+    pub fn update_sway(&mut self, wind: &Wind) {
+        self.wind = *wind;
+    }
+
+    /// Returns radius of sphere (centered at origin) which encloses all surfaces of
+    /// single instance with unit scale.
+    pub fn bounding_radius(&self) -> f32 {
+        if self.bounding_radius_dirty.get() {
+            let mut radius: f32 = 0.0;
+            for surface in self.surfaces.iter() {
+                let data = surface.get_data();
+                let data = data.lock().unwrap();
+                for vertex in data.get_vertices() {
+                    radius = radius.max(vertex.position.len());
+                }
+            }
+            self.bounding_radius.set(radius);
+            self.bounding_radius_dirty.set(false);
+        }
+        self.bounding_radius.get()
+    }
+
+    /// Generates instances over given surface data and adds them to scatter. `density` is
+    /// amount of instances per square unit, `density_map` (if any) is sampled using second
+    /// texture coordinates of surface and its first channel modulates density, so black areas
+    /// will be left empty. Surface data is taken in local coordinates, so scatter node must
+    /// have the same transform as mesh which uses this data. Each instance gets random
+    /// rotation around Y axis and random scale from given range.
+    pub fn generate_instances(&mut self,
+                              data: &SurfaceSharedData,
+                              density: f32,
+                              density_map: Option<&Texture>,
+                              scale: NumericRange<f32>,
+                              generator: &mut RandomGenerator) {
+        let vertices = data.get_vertices();
+        for triangle in data.triangles() {
+            let a = &vertices[triangle[0] as usize];
+            let b = &vertices[triangle[1] as usize];
+            let c = &vertices[triangle[2] as usize];
+
+            let area = 0.5 * (b.position - a.position).cross(&(c.position - a.position)).len();
+
+            let mut expected = area * density;
+            if let Some(map) = density_map {
+                let u = (a.second_tex_coord.x + b.second_tex_coord.x + c.second_tex_coord.x) / 3.0;
+                let v = (a.second_tex_coord.y + b.second_tex_coord.y + c.second_tex_coord.y) / 3.0;
+                expected *= sample_density(map, u, v);
+            }
+
+            // Fractional part is treated as probability of one more instance, so small
+            // triangles get instances too.
+            let mut count = expected.floor() as usize;
+            if generator.unit() < expected.fract() {
+                count += 1;
+            }
+
+            for _ in 0..count {
+                // Uniformly distributed random point on triangle.
+                let r1 = generator.unit().sqrt();
+                let r2 = generator.unit();
+                let position = a.position.scale(1.0 - r1) +
+                    b.position.scale(r1 * (1.0 - r2)) +
+                    c.position.scale(r1 * r2);
+
+                self.instances.push(ScatterInstance {
+                    position,
+                    rotation: generator.range(0.0, 2.0 * std::f32::consts::PI),
+                    scale: generator.numeric_range(&scale),
+                });
+            }
+        }
+    }
+
+    /// Collects world transforms of instances which are visible from given camera position
+    /// and inside given frustum. Fade and sway are baked into each matrix.
+    pub fn visible_instances(&self, camera_position: Vec3, frustum: &Frustum, matrices: &mut Vec<Mat4>) {
+        matrices.clear();
+
+        let global_transform = self.global_transform();
+        let radius = self.bounding_radius();
+        let fade_length = (self.cull_distance - self.fade_start).max(std::f32::EPSILON);
+        let sqr_cull_distance = self.cull_distance * self.cull_distance;
+        let sway_axis = if self.sway > 0.0 {
+            Vec3::new(0.0, 1.0, 0.0).cross(&self.wind.direction()).normalized()
+        } else {
+            None
+        };
+
+        for instance in self.instances.iter() {
+            let position = global_transform.transform_vector(instance.position);
+
+            let sqr_distance = position.sqr_distance(&camera_position);
+            if sqr_distance > sqr_cull_distance {
+                continue;
+            }
+
+            if !frustum.is_intersects_sphere(position, radius * instance.scale) {
+                continue;
+            }
+
+            let fade = ((self.cull_distance - sqr_distance.sqrt()) / fade_length).min(1.0).max(0.0);
+
+            let matrix = global_transform * instance.matrix(instance.scale * fade);
+
+            // Tilt instance around its base point, so top of it follows wind direction.
+            if let Some(axis) = sway_axis {
+                let angle = (self.sway * self.wind.velocity_at(position).len()).min(MAX_SWAY_ANGLE);
+                let tilt = Mat4::from_quat(Quat::from_axis_angle(axis, angle));
+                matrices.push(Mat4::translate(position) * tilt * Mat4::translate(position.scale(-1.0)) * matrix);
+            } else {
+                matrices.push(matrix);
+            }
+        }
+    }
+}
+
+impl Visit for Scatter {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        // Surfaces are not serialized, they must be set again after load.
+        self.instances.visit("Instances", visitor)?;
+        self.fade_start.visit("FadeStart", visitor)?;
+        self.cull_distance.visit("CullDistance", visitor)?;
+        self.sway.visit("Sway", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct ScatterBuilder {
+    base_builder: BaseBuilder,
+    surfaces: Option<Vec<Surface>>,
+    instances: Option<Vec<ScatterInstance>>,
+    fade_start: Option<f32>,
+    cull_distance: Option<f32>,
+    sway: Option<f32>,
+}
+
+impl ScatterBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            surfaces: None,
+            instances: None,
+            fade_start: None,
+            cull_distance: None,
+            sway: None,
+        }
+    }
+
+    pub fn with_surfaces(mut self, surfaces: Vec<Surface>) -> Self {
+        self.surfaces = Some(surfaces);
+        self
+    }
+
+    pub fn with_instances(mut self, instances: Vec<ScatterInstance>) -> Self {
+        self.instances = Some(instances);
+        self
+    }
+
+    pub fn with_fade_start(mut self, fade_start: f32) -> Self {
+        self.fade_start = Some(fade_start);
+        self
+    }
+
+    pub fn with_cull_distance(mut self, cull_distance: f32) -> Self {
+        self.cull_distance = Some(cull_distance);
+        self
+    }
+
+    pub fn with_sway(mut self, sway: f32) -> Self {
+        self.sway = Some(sway);
+        self
+    }
+
+    pub fn build(self) -> Scatter {
+        Scatter {
+            base: self.base_builder.build(),
+            surfaces: self.surfaces.unwrap_or_default(),
+            instances: self.instances.unwrap_or_default(),
+            fade_start: self.fade_start.unwrap_or(40.0),
+            cull_distance: self.cull_distance.unwrap_or(50.0),
+            sway: self.sway.unwrap_or(0.02).max(0.0),
+            wind: Default::default(),
+            bounding_radius: Cell::new(0.0),
+            bounding_radius_dirty: Cell::new(true),
+        }
+    }
+
+    /// Creates new scatter node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Scatter(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
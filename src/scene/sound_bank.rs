@@ -0,0 +1,305 @@
+//! Sound bank is a set of named sound events.
+//!
+//! Playing the same clip for every footstep or gunshot quickly becomes annoying. Sound
+//! event is a name which maps to a few variations of a sound: on each play one of the
+//! clips is picked at random (never the same clip twice in a row if there is a choice),
+//! and pitch and gain are randomized a bit. Cooldown limits how often event can be played,
+//! so ten bullets hitting a wall in the same frame do not produce ten impacts.
+//!
+//! Game code plays events by name, animation signals can be bound to events, so footsteps
+//! are played exactly when foot touches ground:
+//!
+//! ```no_run
+//! use rg3d::{
+//!     scene::{Scene, sound_bank::SoundEvent, node::Node},
+//!     engine::resource_manager::ResourceManager,
+//!     core::{pool::Handle, numeric_range::NumericRange},
+//!     animation::Animation,
+//! };
+//!
+//! fn setup(scene: &mut Scene, resource_manager: &mut ResourceManager, walk: Handle<Animation>, character: Handle<Node>) {
+//!     let clips = ["data/step1.wav", "data/step2.wav", "data/step3.wav"]
+//!         .iter()
+//!         .filter_map(|path| resource_manager.request_sound_buffer(path, false))
+//!         .collect();
+//!     scene.sound_bank.add_event("footstep", SoundEvent::new(clips)
+//!         .with_pitch_range(NumericRange::new(0.9, 1.1))
+//!         .with_cooldown(0.1));
+//!     // Signal 1 of walk animation plays footstep at position of character.
+//!     scene.sound_bank.bind_signal(walk, 1, "footstep", character);
+//!     scene.events.set_capture_animation_signals(true);
+//! }
+//! ```
+//!
+//! Signals are read from event bus of scene by engine, so capture of animation signals
+//! must be enabled, see `scene::event` module docs. Every `SoundCueMessage` on the bus is
+//! played too, this is how timelines play their sound cues.
+
+use std::collections::HashMap;
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        numeric_range::NumericRange,
+        pool::Handle,
+    },
+    sound::{
+        context::Context,
+        source::{
+            SoundSource,
+            Status,
+            generic::GenericSourceBuilder,
+            spatial::SpatialSourceBuilder,
+        },
+    },
+    engine::resource_manager::SharedSoundBuffer,
+    scene::{
+        node::Node,
+        graph::Graph,
+        event::{EventBus, AnimationSignalMessage, SoundCueMessage},
+    },
+    animation::Animation,
+    utils::{
+        log::Log,
+        random::{self, RandomGenerator},
+    },
+};
+
+/// See module docs.
+#[derive(Clone)]
+pub struct SoundEvent {
+    clips: Vec<SharedSoundBuffer>,
+    pitch: NumericRange<f32>,
+    gain: NumericRange<f32>,
+    cooldown: f32,
+    cooldown_left: f32,
+    last_clip: Option<usize>,
+}
+
+impl SoundEvent {
+    /// Creates event which plays one of given clips with unchanged pitch and gain.
+    pub fn new(clips: Vec<SharedSoundBuffer>) -> Self {
+        Self {
+            clips,
+            pitch: NumericRange::new(1.0, 1.0),
+            gain: NumericRange::new(1.0, 1.0),
+            cooldown: 0.0,
+            cooldown_left: 0.0,
+            last_clip: None,
+        }
+    }
+
+    /// Sets range from which pitch of each play is picked.
+    pub fn with_pitch_range(mut self, pitch: NumericRange<f32>) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    /// Sets range from which gain of each play is picked.
+    pub fn with_gain_range(mut self, gain: NumericRange<f32>) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Sets minimal time in seconds between two plays of event, plays during cooldown
+    /// are ignored.
+    pub fn with_cooldown(mut self, cooldown: f32) -> Self {
+        self.cooldown = cooldown.max(0.0);
+        self
+    }
+
+    pub fn clips(&self) -> &[SharedSoundBuffer] {
+        &self.clips
+    }
+
+    /// Returns true if event can be played now.
+    pub fn is_ready(&self) -> bool {
+        self.cooldown_left <= 0.0
+    }
+}
+
+/// Picks index of clip to play out of `count` clips, the same clip is not repeated if there
+/// is a choice.
+fn pick_clip(count: usize, last: Option<usize>, rng: &mut RandomGenerator) -> Option<usize> {
+    match (count, last) {
+        (0, _) => None,
+        (1, _) => Some(0),
+        (_, Some(last)) => {
+            // Pick from all clips except last one.
+            let index = rng.range_i32(0, count as i32 - 1) as usize;
+            Some(if index >= last { index + 1 } else { index })
+        }
+        (_, None) => Some(rng.range_i32(0, count as i32) as usize),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct SignalKey {
+    animation: Handle<Animation>,
+    signal_id: u64,
+}
+
+#[derive(Clone, Debug)]
+struct SignalBinding {
+    event: String,
+    node: Handle<Node>,
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct SoundBank {
+    events: HashMap<String, SoundEvent>,
+    signals: HashMap<SignalKey, SignalBinding>,
+    rng: RandomGenerator,
+}
+
+impl Default for SoundBank {
+    fn default() -> Self {
+        Self {
+            events: Default::default(),
+            signals: Default::default(),
+            rng: RandomGenerator::new(random::global().gen_u64()),
+        }
+    }
+}
+
+impl SoundBank {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds event with given name, returns previous event with the same name.
+    pub fn add_event(&mut self, name: &str, event: SoundEvent) -> Option<SoundEvent> {
+        self.events.insert(name.to_owned(), event)
+    }
+
+    pub fn remove_event(&mut self, name: &str) -> Option<SoundEvent> {
+        self.events.remove(name)
+    }
+
+    pub fn event(&self, name: &str) -> Option<&SoundEvent> {
+        self.events.get(name)
+    }
+
+    /// Binds signal of animation to event, event is played at position of given node or
+    /// as non-spatial sound if node is `Handle::NONE`.
+    pub fn bind_signal(&mut self, animation: Handle<Animation>, signal_id: u64, event: &str, node: Handle<Node>) {
+        self.signals.insert(SignalKey { animation, signal_id }, SignalBinding {
+            event: event.to_owned(),
+            node,
+        });
+    }
+
+    pub fn unbind_signal(&mut self, animation: Handle<Animation>, signal_id: u64) {
+        self.signals.remove(&SignalKey { animation, signal_id });
+    }
+
+    /// Remaps nodes of signal bindings, bindings to nodes which are not in map are
+    /// played as non-spatial sounds.
+    pub(in crate) fn remap_nodes(&mut self, old_new_mapping: &HashMap<Handle<Node>, Handle<Node>>) {
+        for binding in self.signals.values_mut() {
+            binding.node = old_new_mapping.get(&binding.node).cloned().unwrap_or(Handle::NONE);
+        }
+    }
+
+    /// Plays event with given name at given position, or as non-spatial sound if position
+    /// is `None`. Returns handle of new sound source, it is removed from context when it
+    /// stops. Returns `None` if there is no such event or it is on cooldown.
+    pub fn play(&mut self, context: &mut Context, name: &str, position: Option<Vec3>) -> Option<Handle<SoundSource>> {
+        let event = match self.events.get_mut(name) {
+            Some(event) => event,
+            None => {
+                Log::writeln(format!("Unable to play sound event {}: no such event!", name));
+                return None;
+            }
+        };
+
+        if !event.is_ready() {
+            return None;
+        }
+
+        let clip = pick_clip(event.clips.len(), event.last_clip, &mut self.rng)?;
+        event.last_clip = Some(clip);
+        let pitch = self.rng.numeric_range(&event.pitch);
+        let gain = self.rng.numeric_range(&event.gain);
+
+        let builder = GenericSourceBuilder::new(event.clips[clip].clone())
+            .with_status(Status::Playing)
+            .with_play_once(true)
+            .with_pitch(f64::from(pitch))
+            .with_gain(gain);
+
+        let source = match position {
+            Some(position) => builder.build()
+                .map(|generic| SpatialSourceBuilder::new(generic).with_position(position).build_source()),
+            None => builder.build_source(),
+        };
+
+        match source {
+            Ok(source) => {
+                event.cooldown_left = event.cooldown;
+                Some(context.add_source(source))
+            }
+            Err(e) => {
+                Log::writeln(format!("Unable to play sound event {}: {:?}", name, e));
+                None
+            }
+        }
+    }
+
+    /// Updates cooldowns, plays events bound to animation signals and events requested by
+    /// sound cue messages, called automatically by engine.
+    pub fn update(&mut self, graph: &Graph, events: &EventBus, context: &mut Context, dt: f32) {
+        for event in self.events.values_mut() {
+            event.cooldown_left = (event.cooldown_left - dt).max(0.0);
+        }
+
+        let position_of = |node: Handle<Node>| if graph.is_valid_handle(node) {
+            Some(graph[node].global_position())
+        } else {
+            None
+        };
+
+        for message in events.read::<SoundCueMessage>() {
+            self.play(context, &message.event, position_of(message.node));
+        }
+
+        if self.signals.is_empty() {
+            return;
+        }
+
+        for message in events.read::<AnimationSignalMessage>() {
+            let key = SignalKey {
+                animation: message.animation,
+                signal_id: message.signal_id,
+            };
+            if let Some(binding) = self.signals.get(&key) {
+                let position = position_of(binding.node);
+                let event = binding.event.clone();
+                self.play(context, &event, position);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        scene::sound_bank::pick_clip,
+        utils::random::RandomGenerator,
+    };
+
+    #[test]
+    fn test_clip_is_not_repeated() {
+        let mut rng = RandomGenerator::new(7);
+        assert!(pick_clip(0, None, &mut rng).is_none());
+        assert_eq!(pick_clip(1, Some(0), &mut rng), Some(0));
+
+        let mut last = pick_clip(3, None, &mut rng).unwrap();
+        for _ in 0..100 {
+            let next = pick_clip(3, Some(last), &mut rng).unwrap();
+            assert_ne!(next, last);
+            assert!(next < 3);
+            last = next;
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Sound binder is a bridge between scene graph and sound context, similar to physics binder.
+//!
+//! Sound sources live in sound context, which knows nothing about scene. Binder moves
+//! spatial sources together with nodes they're bound to, moves listener together with a
+//! node (usually a camera) and applies Doppler effect: pitch of a source rises while it
+//! approaches listener and falls when it moves away, which is what makes passing
+//! vehicles and flying projectiles sound right.
+//!
+//! Velocities are calculated from movement of nodes, so sources bound to nodes driven by
+//! rigid bodies, animations or game code are handled the same way. Binder is updated by
+//! engine after scenes, so it sees final positions of nodes for the frame:
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, node::Node};
+//! use rg3d::sound::source::SoundSource;
+//! use rg3d::core::pool::Handle;
+//!
+//! fn setup(scene: &mut Scene, camera: Handle<Node>, car: Handle<Node>, engine_sound: Handle<SoundSource>) {
+//!     scene.sound_binder.set_listener(camera);
+//!     scene.sound_binder.bind(car, engine_sound, 1.0);
+//!     // Exaggerate effect a bit.
+//!     scene.sound_binder.set_doppler_factor(1.5);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    sound::{
+        context::Context,
+        source::SoundSource,
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+    },
+};
+
+/// Speed of sound in air in units per second, assuming that one unit is one meter.
+pub const DEFAULT_SPEED_OF_SOUND: f32 = 343.0;
+
+/// Sources can't move faster than this fraction of speed of sound, otherwise Doppler
+/// formula gives negative or infinite pitch.
+const MAX_RELATIVE_SPEED: f32 = 0.9;
+
+#[derive(Clone, Debug)]
+struct SoundBinding {
+    source: Handle<SoundSource>,
+    /// Pitch of source without Doppler effect.
+    base_pitch: f64,
+    last_position: Option<Vec3>,
+    velocity: Vec3,
+}
+
+impl Default for SoundBinding {
+    fn default() -> Self {
+        Self {
+            source: Handle::NONE,
+            base_pitch: 1.0,
+            last_position: None,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+impl Visit for SoundBinding {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.source.visit("Source", visitor)?;
+        self.base_pitch.visit("BasePitch", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct SoundBinder {
+    bindings: HashMap<Handle<Node>, SoundBinding>,
+    listener: Handle<Node>,
+    listener_last_position: Option<Vec3>,
+    listener_velocity: Vec3,
+    doppler_factor: f32,
+    speed_of_sound: f32,
+}
+
+impl Default for SoundBinder {
+    fn default() -> Self {
+        Self {
+            bindings: Default::default(),
+            listener: Handle::NONE,
+            listener_last_position: None,
+            listener_velocity: Vec3::ZERO,
+            doppler_factor: 1.0,
+            speed_of_sound: DEFAULT_SPEED_OF_SOUND,
+        }
+    }
+}
+
+/// Calculates velocity from previous and current position and updates previous position.
+fn track_velocity(last_position: &mut Option<Vec3>, position: Vec3, dt: f32) -> Vec3 {
+    let velocity = match *last_position {
+        Some(last) if dt > 0.0 => (position - last).scale(1.0 / dt),
+        _ => Vec3::ZERO,
+    };
+    *last_position = Some(position);
+    velocity
+}
+
+/// Returns pitch multiplier of a source heard by listener, velocities are in units per second.
+pub fn doppler_shift(listener: Vec3, listener_velocity: Vec3, source: Vec3, source_velocity: Vec3,
+                     speed_of_sound: f32, doppler_factor: f32) -> f32 {
+    let direction = match (source - listener).normalized() {
+        Some(direction) => direction,
+        None => return 1.0,
+    };
+    let limit = speed_of_sound * MAX_RELATIVE_SPEED;
+    // Positive speed of listener means that it moves towards source, negative speed
+    // of source means that source moves towards listener.
+    let listener_speed = (listener_velocity.dot(&direction) * doppler_factor).max(-limit).min(limit);
+    let source_speed = (source_velocity.dot(&direction) * doppler_factor).max(-limit).min(limit);
+    (speed_of_sound + listener_speed) / (speed_of_sound + source_speed)
+}
+
+impl SoundBinder {
+    /// Binds sound source to node, `base_pitch` is pitch of source without Doppler effect.
+    /// Returns previously bound source, if any.
+    pub fn bind(&mut self, node: Handle<Node>, source: Handle<SoundSource>, base_pitch: f64) -> Option<Handle<SoundSource>> {
+        self.bindings
+            .insert(node, SoundBinding { source, base_pitch, ..Default::default() })
+            .map(|binding| binding.source)
+    }
+
+    pub fn unbind(&mut self, node: Handle<Node>) -> Option<Handle<SoundSource>> {
+        self.bindings.remove(&node).map(|binding| binding.source)
+    }
+
+    /// Returns handle of sound source bound to given node.
+    pub fn source_of(&self, node: Handle<Node>) -> Option<Handle<SoundSource>> {
+        self.bindings.get(&node).map(|binding| binding.source)
+    }
+
+    /// Returns velocity of node with bound source, calculated on last update.
+    pub fn velocity_of(&self, node: Handle<Node>) -> Option<Vec3> {
+        self.bindings.get(&node).map(|binding| binding.velocity)
+    }
+
+    /// Sets node which position and orientation is used for listener of sound context.
+    pub fn set_listener(&mut self, listener: Handle<Node>) {
+        self.listener = listener;
+        self.listener_last_position = None;
+    }
+
+    pub fn listener(&self) -> Handle<Node> {
+        self.listener
+    }
+
+    /// Sets strength of Doppler effect, zero disables it, one is physically correct.
+    pub fn set_doppler_factor(&mut self, factor: f32) {
+        self.doppler_factor = factor.max(0.0);
+    }
+
+    pub fn doppler_factor(&self) -> f32 {
+        self.doppler_factor
+    }
+
+    /// Sets speed of sound in units per second, change it if one unit is not one meter.
+    pub fn set_speed_of_sound(&mut self, speed: f32) {
+        self.speed_of_sound = speed.max(std::f32::EPSILON);
+    }
+
+    pub fn speed_of_sound(&self) -> f32 {
+        self.speed_of_sound
+    }
+
+    /// Moves listener and bound sources and applies Doppler effect, called automatically by
+    /// engine. Bindings of removed nodes are dropped.
+    pub fn update(&mut self, graph: &Graph, context: &mut Context, dt: f32) {
+        let listener_position = if graph.is_valid_handle(self.listener) {
+            let listener = &graph[self.listener];
+            let position = listener.global_position();
+            self.listener_velocity = track_velocity(&mut self.listener_last_position, position, dt);
+            let context_listener = context.listener_mut();
+            context_listener.set_position(&position);
+            context_listener.set_basis(listener.global_transform().basis());
+            Some(position)
+        } else {
+            None
+        };
+
+        self.bindings.retain(|node, _| graph.is_valid_handle(*node));
+
+        for (&node, binding) in self.bindings.iter_mut() {
+            let position = graph[node].global_position();
+            binding.velocity = track_velocity(&mut binding.last_position, position, dt);
+
+            let source = context.source_mut(binding.source);
+            if let SoundSource::Spatial(spatial) = &mut *source {
+                spatial.set_position(&position);
+            }
+
+            let shift = match listener_position {
+                Some(listener_position) => doppler_shift(listener_position, self.listener_velocity, position,
+                                                         binding.velocity, self.speed_of_sound, self.doppler_factor),
+                None => 1.0,
+            };
+            source.generic_mut().set_pitch(binding.base_pitch * f64::from(shift));
+        }
+    }
+}
+
+impl Visit for SoundBinder {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.bindings.visit("Bindings", visitor)?;
+        self.listener.visit("Listener", visitor)?;
+        self.doppler_factor.visit("DopplerFactor", visitor)?;
+        self.speed_of_sound.visit("SpeedOfSound", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::math::vec3::Vec3;
+    use crate::scene::sound_binder::{doppler_shift, DEFAULT_SPEED_OF_SOUND};
+
+    #[test]
+    fn test_doppler_shift() {
+        let listener = Vec3::ZERO;
+        let source = Vec3::new(100.0, 0.0, 0.0);
+        let approaching = doppler_shift(listener, Vec3::ZERO, source, Vec3::new(-30.0, 0.0, 0.0), DEFAULT_SPEED_OF_SOUND, 1.0);
+        let leaving = doppler_shift(listener, Vec3::ZERO, source, Vec3::new(30.0, 0.0, 0.0), DEFAULT_SPEED_OF_SOUND, 1.0);
+        assert!(approaching > 1.0);
+        assert!(leaving < 1.0);
+
+        // Sideways movement does not change pitch, disabled effect does nothing.
+        assert_eq!(doppler_shift(listener, Vec3::ZERO, source, Vec3::new(0.0, 0.0, 30.0), DEFAULT_SPEED_OF_SOUND, 1.0), 1.0);
+        assert_eq!(doppler_shift(listener, Vec3::ZERO, source, Vec3::new(-30.0, 0.0, 0.0), DEFAULT_SPEED_OF_SOUND, 0.0), 1.0);
+    }
+}
@@ -0,0 +1,154 @@
+//! Spatial index of graph - uniform hash grid of bounding spheres of nodes, used by
+//! spatial queries of graph (`Graph::find_in_sphere` and others).
+//!
+//! Grid is rebuilt lazily on first query after global transforms of nodes were changed,
+//! so it costs nothing for scenes which do not use queries, and a single rebuild per frame
+//! for dynamic scenes. Nodes which cover too many cells are stored in a separate list and
+//! are tested by each query.
+
+use std::collections::HashMap;
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            aabb::AxisAlignedBoundingBox,
+        },
+        pool::Handle,
+    },
+    scene::node::Node,
+};
+
+/// Default size of a cell of grid, suits typical scenes with human-scale objects.
+pub const DEFAULT_CELL_SIZE: f32 = 10.0;
+
+/// Nodes which cover more cells than this are not put into the grid.
+const MAX_CELLS_PER_ENTRY: i64 = 64;
+
+/// Bounding sphere of node in world space.
+#[derive(Copy, Clone, Debug)]
+pub(in crate) struct Entry {
+    pub handle: Handle<Node>,
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+type Cell = (i32, i32, i32);
+
+pub(in crate) struct SpatialIndex {
+    cell_size: f32,
+    pub(in crate) valid: bool,
+    entries: Vec<Entry>,
+    cells: HashMap<Cell, Vec<u32>>,
+    large: Vec<u32>,
+    // Query number at which entry was last visited, prevents duplicates in results because
+    // entry is put into every cell it touches.
+    visited: Vec<u32>,
+    query: u32,
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(0.001),
+            valid: false,
+            entries: Default::default(),
+            cells: Default::default(),
+            large: Default::default(),
+            visited: Default::default(),
+            query: 0,
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell_of(&self, point: Vec3) -> Cell {
+        ((point.x / self.cell_size).floor() as i32,
+         (point.y / self.cell_size).floor() as i32,
+         (point.z / self.cell_size).floor() as i32)
+    }
+
+    fn cell_range(&self, min: Vec3, max: Vec3) -> (Cell, Cell, i64) {
+        let a = self.cell_of(min);
+        let b = self.cell_of(max);
+        let count = (b.0 as i64 - a.0 as i64 + 1) * (b.1 as i64 - a.1 as i64 + 1) * (b.2 as i64 - a.2 as i64 + 1);
+        (a, b, count)
+    }
+
+    pub fn rebuild<I: Iterator<Item=Entry>>(&mut self, entries: I) {
+        self.entries.clear();
+        self.entries.extend(entries);
+        self.cells.clear();
+        self.large.clear();
+        self.visited.clear();
+        self.visited.resize(self.entries.len(), 0);
+        self.query = 0;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let extent = Vec3::new(entry.radius, entry.radius, entry.radius);
+            let (a, b, count) = self.cell_range(entry.center - extent, entry.center + extent);
+            if count > MAX_CELLS_PER_ENTRY {
+                self.large.push(i as u32);
+                continue;
+            }
+            for x in a.0..=b.0 {
+                for y in a.1..=b.1 {
+                    for z in a.2..=b.2 {
+                        self.cells.entry((x, y, z)).or_insert_with(Vec::new).push(i as u32);
+                    }
+                }
+            }
+        }
+
+        self.valid = true;
+    }
+
+    /// Calls `func` for every entry which may intersect given box, each entry is visited
+    /// once. If box covers more cells than there are entries, all entries are visited.
+    pub fn query<F: FnMut(&Entry)>(&mut self, bounds: &AxisAlignedBoundingBox, mut func: F) {
+        let (a, b, count) = self.cell_range(bounds.min, bounds.max);
+        if count > self.entries.len() as i64 {
+            for entry in self.entries.iter() {
+                func(entry);
+            }
+            return;
+        }
+
+        self.query = self.query.wrapping_add(1);
+        if self.query == 0 {
+            // Wrapped around, reset marks so old queries are not confused with new ones.
+            for mark in self.visited.iter_mut() {
+                *mark = 0;
+            }
+            self.query = 1;
+        }
+
+        for x in a.0..=b.0 {
+            for y in a.1..=b.1 {
+                for z in a.2..=b.2 {
+                    if let Some(cell) = self.cells.get(&(x, y, z)) {
+                        for &i in cell {
+                            if self.visited[i as usize] != self.query {
+                                self.visited[i as usize] = self.query;
+                                func(&self.entries[i as usize]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for &i in self.large.iter() {
+            func(&self.entries[i as usize]);
+        }
+    }
+}
+
+/// Returns squared distance from point to box, zero if point is inside.
+pub(in crate) fn sqr_distance_to_aabb(point: Vec3, bounds: &AxisAlignedBoundingBox) -> f32 {
+    let dx = (bounds.min.x - point.x).max(0.0).max(point.x - bounds.max.x);
+    let dy = (bounds.min.y - point.y).max(0.0).max(point.y - bounds.max.y);
+    let dz = (bounds.min.z - point.z).max(0.0).max(point.z - bounds.max.z);
+    dx * dx + dy * dy + dz * dz
+}
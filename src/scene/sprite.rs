@@ -6,18 +6,27 @@ use std::{
     ops::{Deref, DerefMut}
 };
 use crate::{
-    resource::texture::Texture,
-    scene::base::{
-        BaseBuilder,
-        Base,
+    resource::{
+        texture::Texture,
+        texture_atlas::TextureAtlas,
+    },
+    scene::{
+        base::{
+            BaseBuilder,
+            Base,
+        },
+        graph::Graph,
+        node::Node,
     },
     core::{
+        pool::Handle,
         visitor::{
             VisitResult,
             Visit,
             Visitor,
         },
         color::Color,
+        math::Rect,
     },
 };
 
@@ -28,6 +37,9 @@ pub struct Sprite {
     color: Color,
     size: f32,
     rotation: f32,
+    uv_rect: Rect<f32>,
+    depth_test: bool,
+    depth_bias: f32,
 }
 
 impl Deref for Sprite {
@@ -83,6 +95,46 @@ impl Sprite {
     pub fn texture(&self) -> Option<Arc<Mutex<Texture>>> {
         self.texture.clone()
     }
+
+    /// Sets rectangle of texture (in normalized coordinates) which will be shown on sprite.
+    /// By default whole texture is used.
+    pub fn set_uv_rect(&mut self, uv_rect: Rect<f32>) {
+        self.uv_rect = uv_rect;
+    }
+
+    pub fn uv_rect(&self) -> Rect<f32> {
+        self.uv_rect
+    }
+
+    /// Makes sprite to show given region of atlas. Sprites that use same atlas and are
+    /// next to each other in back-to-front order are batched together by renderer. Does
+    /// nothing if there is no region with given index.
+    pub fn set_atlas_region(&mut self, atlas: &TextureAtlas, index: usize) {
+        if let Some(region) = atlas.region(index) {
+            self.texture = Some(atlas.texture());
+            self.uv_rect = region.uv_rect;
+        }
+    }
+
+    /// Enables or disables depth test for sprite. Sprite without depth test is drawn on top
+    /// of scene geometry, this is useful for markers, waypoint icons, etc.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test;
+    }
+
+    pub fn is_depth_test_enabled(&self) -> bool {
+        self.depth_test
+    }
+
+    /// Sets distance (in world units) by which sprite will be moved towards camera before
+    /// depth test. Small bias prevents z-fighting with surface sprite sits on.
+    pub fn set_depth_bias(&mut self, depth_bias: f32) {
+        self.depth_bias = depth_bias;
+    }
+
+    pub fn depth_bias(&self) -> f32 {
+        self.depth_bias
+    }
 }
 
 impl Visit for Sprite {
@@ -93,6 +145,9 @@ impl Visit for Sprite {
         self.color.visit("Color", visitor)?;
         self.size.visit("Size", visitor)?;
         self.rotation.visit("Rotation", visitor)?;
+        self.uv_rect.visit("UvRect", visitor)?;
+        self.depth_test.visit("DepthTest", visitor)?;
+        self.depth_bias.visit("DepthBias", visitor)?;
         self.base.visit("Base", visitor)?;
 
         visitor.leave_region()
@@ -105,6 +160,9 @@ pub struct SpriteBuilder {
     color: Option<Color>,
     size: Option<f32>,
     rotation: Option<f32>,
+    uv_rect: Option<Rect<f32>>,
+    depth_test: Option<bool>,
+    depth_bias: Option<f32>,
 }
 
 impl SpriteBuilder {
@@ -115,6 +173,9 @@ impl SpriteBuilder {
             color: None,
             size: None,
             rotation: None,
+            uv_rect: None,
+            depth_test: None,
+            depth_bias: None,
         }
     }
 
@@ -143,6 +204,31 @@ impl SpriteBuilder {
         self
     }
 
+    pub fn with_uv_rect(mut self, uv_rect: Rect<f32>) -> Self {
+        self.uv_rect = Some(uv_rect);
+        self
+    }
+
+    /// Sets texture and texture coordinates from given atlas region. Region index is not
+    /// checked, invalid index leaves texture and texture coordinates untouched.
+    pub fn with_atlas_region(mut self, atlas: &TextureAtlas, index: usize) -> Self {
+        if let Some(region) = atlas.region(index) {
+            self.texture = Some(atlas.texture());
+            self.uv_rect = Some(region.uv_rect);
+        }
+        self
+    }
+
+    pub fn with_depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = Some(depth_test);
+        self
+    }
+
+    pub fn with_depth_bias(mut self, depth_bias: f32) -> Self {
+        self.depth_bias = Some(depth_bias);
+        self
+    }
+
     pub fn build(self) -> Sprite {
         Sprite {
             base: self.base_builder.build(),
@@ -150,6 +236,17 @@ impl SpriteBuilder {
             color: self.color.unwrap_or(Color::WHITE),
             size: self.size.unwrap_or(0.2),
             rotation: self.rotation.unwrap_or(0.0),
+            uv_rect: self.uv_rect.unwrap_or(Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 }),
+            depth_test: self.depth_test.unwrap_or(true),
+            depth_bias: self.depth_bias.unwrap_or(0.0),
         }
     }
+
+    /// Creates new sprite node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Sprite(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+//! Scene statistics - amount of objects of each kind in a scene. Could be used to display
+//! budgets of a level or to check in CI that assets do not bloat over time.
+//!
+//! Statistics are gathered by `Scene::stats`, which walks the whole scene, so it is not
+//! meant to be called every frame in release builds.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::AddAssign,
+};
+use crate::scene::{
+    Scene,
+    node::Node,
+};
+
+/// Amount of nodes of each kind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeStatistics {
+    pub base: usize,
+    pub lights: usize,
+    pub cameras: usize,
+    pub meshes: usize,
+    pub sprites: usize,
+    pub particle_systems: usize,
+    pub trails: usize,
+    pub text3d: usize,
+    pub scatters: usize,
+    pub cloths: usize,
+    pub mirrors: usize,
+    pub triggers: usize,
+    pub crowds: usize,
+    pub force_fields: usize,
+    pub water_volumes: usize,
+    pub projectors: usize,
+}
+
+impl NodeStatistics {
+    /// Returns total amount of nodes.
+    pub fn total(&self) -> usize {
+        self.base + self.lights + self.cameras + self.meshes + self.sprites +
+            self.particle_systems + self.trails + self.text3d + self.scatters +
+            self.cloths + self.mirrors + self.triggers + self.crowds + self.force_fields +
+            self.water_volumes + self.projectors
+    }
+}
+
+impl AddAssign for NodeStatistics {
+    fn add_assign(&mut self, rhs: Self) {
+        self.base += rhs.base;
+        self.lights += rhs.lights;
+        self.cameras += rhs.cameras;
+        self.meshes += rhs.meshes;
+        self.sprites += rhs.sprites;
+        self.particle_systems += rhs.particle_systems;
+        self.trails += rhs.trails;
+        self.text3d += rhs.text3d;
+        self.scatters += rhs.scatters;
+        self.cloths += rhs.cloths;
+        self.mirrors += rhs.mirrors;
+        self.triggers += rhs.triggers;
+        self.crowds += rhs.crowds;
+        self.force_fields += rhs.force_fields;
+        self.water_volumes += rhs.water_volumes;
+        self.projectors += rhs.projectors;
+    }
+}
+
+/// See module docs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SceneStatistics {
+    pub nodes: NodeStatistics,
+    /// Total amount of surfaces of meshes, scatters and cloths.
+    pub surfaces: usize,
+    /// Total amount of triangles of surfaces. Shared surface data is counted for every
+    /// surface which uses it, because it is drawn that many times. Instances of scatters
+    /// are not taken into account.
+    pub triangles: usize,
+    pub animations: usize,
+    /// Amount of rigid bodies bound to nodes.
+    pub rigid_bodies: usize,
+    /// Amount of particles which are alive at the moment.
+    pub active_particles: usize,
+}
+
+impl AddAssign for SceneStatistics {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nodes += rhs.nodes;
+        self.surfaces += rhs.surfaces;
+        self.triangles += rhs.triangles;
+        self.animations += rhs.animations;
+        self.rigid_bodies += rhs.rigid_bodies;
+        self.active_particles += rhs.active_particles;
+    }
+}
+
+impl Display for SceneStatistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let n = &self.nodes;
+        writeln!(f, "Nodes: {}", n.total())?;
+        writeln!(f, "\tBase: {}", n.base)?;
+        writeln!(f, "\tLights: {}", n.lights)?;
+        writeln!(f, "\tCameras: {}", n.cameras)?;
+        writeln!(f, "\tMeshes: {}", n.meshes)?;
+        writeln!(f, "\tSprites: {}", n.sprites)?;
+        writeln!(f, "\tParticle systems: {}", n.particle_systems)?;
+        writeln!(f, "\tTrails: {}", n.trails)?;
+        writeln!(f, "\tText3D: {}", n.text3d)?;
+        writeln!(f, "\tScatters: {}", n.scatters)?;
+        writeln!(f, "\tCloths: {}", n.cloths)?;
+        writeln!(f, "\tMirrors: {}", n.mirrors)?;
+        writeln!(f, "\tTriggers: {}", n.triggers)?;
+        writeln!(f, "\tCrowds: {}", n.crowds)?;
+        writeln!(f, "\tForce fields: {}", n.force_fields)?;
+        writeln!(f, "\tWater volumes: {}", n.water_volumes)?;
+        writeln!(f, "\tProjectors: {}", n.projectors)?;
+        writeln!(f, "Surfaces: {}", self.surfaces)?;
+        writeln!(f, "Triangles: {}", self.triangles)?;
+        writeln!(f, "Animations: {}", self.animations)?;
+        writeln!(f, "Rigid bodies: {}", self.rigid_bodies)?;
+        write!(f, "Active particles: {}", self.active_particles)
+    }
+}
+
+impl SceneStatistics {
+    pub(in crate) fn gather(scene: &Scene) -> Self {
+        let mut stats = SceneStatistics::default();
+
+        for node in scene.graph.linear_iter() {
+            let nodes = &mut stats.nodes;
+            match node {
+                Node::Base(_) => nodes.base += 1,
+                Node::Light(_) => nodes.lights += 1,
+                Node::Camera(_) => nodes.cameras += 1,
+                Node::Mesh(mesh) => {
+                    nodes.meshes += 1;
+                    for surface in mesh.surfaces() {
+                        stats.surfaces += 1;
+                        stats.triangles += surface.get_data().lock().unwrap().triangles().len();
+                    }
+                }
+                Node::Sprite(_) => nodes.sprites += 1,
+                Node::ParticleSystem(particle_system) => {
+                    nodes.particle_systems += 1;
+                    stats.active_particles += particle_system.alive_particle_count();
+                }
+                Node::Trail(_) => nodes.trails += 1,
+                Node::Text3D(_) => nodes.text3d += 1,
+                Node::Scatter(scatter) => {
+                    nodes.scatters += 1;
+                    for surface in scatter.surfaces() {
+                        stats.surfaces += 1;
+                        stats.triangles += surface.get_data().lock().unwrap().triangles().len();
+                    }
+                }
+                Node::Cloth(cloth) => {
+                    nodes.cloths += 1;
+                    stats.surfaces += 1;
+                    stats.triangles += cloth.surface().get_data().lock().unwrap().triangles().len();
+                }
+                Node::Mirror(_) => nodes.mirrors += 1,
+                Node::Trigger(_) => nodes.triggers += 1,
+                Node::Crowd(crowd) => {
+                    nodes.crowds += 1;
+                    for surface in crowd.surfaces() {
+                        stats.surfaces += 1;
+                        stats.triangles += surface.get_data().lock().unwrap().triangles().len();
+                    }
+                }
+                Node::ForceField(_) => nodes.force_fields += 1,
+                Node::WaterVolume(_) => nodes.water_volumes += 1,
+                Node::Projector(_) => nodes.projectors += 1,
+            }
+        }
+
+        stats.animations = scene.animations.iter().count();
+        stats.rigid_bodies = scene.physics_binder.node_rigid_body_map
+            .values()
+            .filter(|&&body| scene.physics.is_valid_body_handle(body))
+            .count();
+
+        stats
+    }
+}
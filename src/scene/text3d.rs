@@ -0,0 +1,348 @@
+//! Text3D is a text which is placed in the world and always faces camera.
+//!
+//! Text is rendered using atlas of font, the same font that is used by user interface
+//! can be used. Size of text is defined in scene units, not in pixels, so labels are
+//! getting smaller with distance as any other object in the world. Use cases are name
+//! tags, damage numbers, signs, etc.
+
+use std::{
+    sync::{Arc, Mutex},
+    ops::{Deref, DerefMut},
+};
+use crate::{
+    gui::ttf::Font,
+    scene::{
+        base::{
+            BaseBuilder,
+            Base,
+        },
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        pool::Handle,
+        math::{
+            vec3::Vec3,
+            vec2::Vec2,
+            TriangleDefinition,
+        },
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+        color::Color,
+    },
+};
+
+/// OpenGL expects this structure packed as in C.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Vertex {
+    /// Position of text origin in world space, same for every vertex of text.
+    position: Vec3,
+    /// Offset from origin in plane of the camera, in scene units.
+    offset: Vec2,
+    tex_coord: Vec2,
+    color: Color,
+}
+
+pub struct DrawData {
+    vertices: Vec<Vertex>,
+    triangles: Vec<TriangleDefinition>,
+}
+
+impl Default for DrawData {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+        }
+    }
+}
+
+impl DrawData {
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+    }
+
+    pub fn get_vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn get_triangles(&self) -> &[TriangleDefinition] {
+        &self.triangles
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TextAlignment {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+impl TextAlignment {
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(TextAlignment::Left),
+            1 => Ok(TextAlignment::Center),
+            2 => Ok(TextAlignment::Right),
+            _ => Err(format!("Invalid text alignment {}", id))
+        }
+    }
+}
+
+impl Visit for TextAlignment {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut id = *self as u32;
+        id.visit(name, visitor)?;
+        if visitor.is_reading() {
+            *self = Self::from_id(id)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Text3D {
+    base: Base,
+    text: String,
+    localization_key: Option<String>,
+    font: Option<Arc<Mutex<Font>>>,
+    size: f32,
+    color: Color,
+    alignment: TextAlignment,
+}
+
+impl Deref for Text3D {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Text3D {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Text3D {
+    fn default() -> Self {
+        Text3DBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl Text3D {
+    pub fn set_text<P: AsRef<str>>(&mut self, text: P) {
+        self.text = text.as_ref().to_owned();
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Sets key of text in string tables, text is replaced with translation each time
+    /// locale is changed. See `resource::string_table` module docs.
+    pub fn set_localization_key(&mut self, key: Option<String>) {
+        self.localization_key = key;
+    }
+
+    pub fn localization_key(&self) -> Option<&str> {
+        self.localization_key.as_deref()
+    }
+
+    pub fn set_font(&mut self, font: Arc<Mutex<Font>>) {
+        self.font = Some(font);
+    }
+
+    pub fn font(&self) -> Option<Arc<Mutex<Font>>> {
+        self.font.clone()
+    }
+
+    /// Sets height of line of text in scene units.
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size;
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Sets horizontal alignment of lines relative to node position.
+    pub fn set_alignment(&mut self, alignment: TextAlignment) {
+        self.alignment = alignment;
+    }
+
+    pub fn alignment(&self) -> TextAlignment {
+        self.alignment
+    }
+
+    /// Generates quad for each glyph of text. Text is vertically centered on node position,
+    /// lines go from top to bottom.
+    pub fn generate_draw_data(&self, font: &Font, draw_data: &mut DrawData) {
+        draw_data.clear();
+
+        if font.height() <= 0.0 {
+            return;
+        }
+
+        let scale = self.size / font.height();
+        let position = self.global_position();
+        let line_count = self.text.lines().count();
+        let mut baseline = 0.5 * (line_count as f32 * font.height()) - font.ascender();
+
+        for line in self.text.lines() {
+            let width = line.chars()
+                .filter_map(|c| font.glyph(c as u32))
+                .map(|glyph| glyph.advance)
+                .sum::<f32>();
+
+            let mut cursor = match self.alignment {
+                TextAlignment::Left => 0.0,
+                TextAlignment::Center => -0.5 * width,
+                TextAlignment::Right => -width,
+            };
+
+            for c in line.chars() {
+                let glyph = match font.glyph(c as u32) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+
+                if glyph.bitmap_width > 0 && glyph.bitmap_height > 0 {
+                    let left = (cursor + glyph.left) * scale;
+                    let right = left + glyph.bitmap_width as f32 * scale;
+                    let top = (baseline + glyph.top) * scale;
+                    let bottom = top - glyph.bitmap_height as f32 * scale;
+
+                    let base_index = draw_data.vertices.len() as u32;
+
+                    let corners = [
+                        Vec2::new(left, top),
+                        Vec2::new(right, top),
+                        Vec2::new(right, bottom),
+                        Vec2::new(left, bottom),
+                    ];
+                    for (offset, tex_coord) in corners.iter().zip(glyph.tex_coords.iter()) {
+                        draw_data.vertices.push(Vertex {
+                            position,
+                            offset: *offset,
+                            tex_coord: *tex_coord,
+                            color: self.color,
+                        });
+                    }
+
+                    draw_data.triangles.push(TriangleDefinition([base_index, base_index + 1, base_index + 2]));
+                    draw_data.triangles.push(TriangleDefinition([base_index, base_index + 2, base_index + 3]));
+                }
+
+                cursor += glyph.advance;
+            }
+
+            baseline -= font.height();
+        }
+    }
+}
+
+impl Visit for Text3D {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        // Font is not serialized, it must be set again after load.
+        self.text.visit("Text", visitor)?;
+        self.localization_key.visit("LocalizationKey", visitor)?;
+        self.size.visit("Size", visitor)?;
+        self.color.visit("Color", visitor)?;
+        self.alignment.visit("Alignment", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct Text3DBuilder {
+    base_builder: BaseBuilder,
+    text: Option<String>,
+    localization_key: Option<String>,
+    font: Option<Arc<Mutex<Font>>>,
+    size: Option<f32>,
+    color: Option<Color>,
+    alignment: Option<TextAlignment>,
+}
+
+impl Text3DBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            text: None,
+            localization_key: None,
+            font: None,
+            size: None,
+            color: None,
+            alignment: None,
+        }
+    }
+
+    pub fn with_text<P: AsRef<str>>(mut self, text: P) -> Self {
+        self.text = Some(text.as_ref().to_owned());
+        self
+    }
+
+    pub fn with_localization_key<P: AsRef<str>>(mut self, key: P) -> Self {
+        self.localization_key = Some(key.as_ref().to_owned());
+        self
+    }
+
+    pub fn with_font(mut self, font: Arc<Mutex<Font>>) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = Some(alignment);
+        self
+    }
+
+    pub fn build(self) -> Text3D {
+        Text3D {
+            base: self.base_builder.build(),
+            text: self.text.unwrap_or_default(),
+            localization_key: self.localization_key,
+            font: self.font,
+            size: self.size.unwrap_or(0.25),
+            color: self.color.unwrap_or(Color::WHITE),
+            alignment: self.alignment.unwrap_or(TextAlignment::Center),
+        }
+    }
+
+    /// Creates new 3D text node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Text3D(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
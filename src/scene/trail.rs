@@ -0,0 +1,437 @@
+//! Trail is a camera-facing ribbon built from list of points.
+//!
+//! Points can be generated automatically from motion of the node (sword trails, trails
+//! of projectiles, etc.) or set manually (debug paths for example). Each point has its
+//! own age, color and width of ribbon at the point are defined by age of point, so
+//! trail smoothly fades out.
+
+use std::{
+    sync::{Arc, Mutex},
+    ops::{Deref, DerefMut},
+};
+use crate::{
+    resource::texture::Texture,
+    scene::{
+        base::{
+            BaseBuilder,
+            Base,
+        },
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        pool::Handle,
+        math::{
+            vec3::Vec3,
+            vec2::Vec2,
+            TriangleDefinition,
+        },
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+        color_gradient::ColorGradient,
+        color::Color,
+    },
+};
+
+/// OpenGL expects this structure packed as in C.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Vertex {
+    position: Vec3,
+    tex_coord: Vec2,
+    color: Color,
+}
+
+pub struct DrawData {
+    vertices: Vec<Vertex>,
+    triangles: Vec<TriangleDefinition>,
+}
+
+impl Default for DrawData {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+        }
+    }
+}
+
+impl DrawData {
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+    }
+
+    pub fn get_vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn get_triangles(&self) -> &[TriangleDefinition] {
+        &self.triangles
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TrailPoint {
+    /// Position of point in world space.
+    pub position: Vec3,
+    /// Time in seconds since point was added.
+    pub age: f32,
+}
+
+impl Visit for TrailPoint {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.position.visit("Pos", visitor)?;
+        self.age.visit("Age", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[derive(Clone)]
+pub struct Trail {
+    base: Base,
+    points: Vec<TrailPoint>,
+    texture: Option<Arc<Mutex<Texture>>>,
+    width: f32,
+    color: Color,
+    color_over_lifetime: Option<ColorGradient>,
+    point_lifetime: Option<f32>,
+    emit_from_motion: bool,
+    min_segment_length: f32,
+    max_points: u32,
+}
+
+impl Deref for Trail {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Trail {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Trail {
+    fn default() -> Self {
+        TrailBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl Trail {
+    /// Adds new point to the end of trail. If trail already has maximum amount of points,
+    /// the oldest point will be removed.
+    pub fn add_point(&mut self, position: Vec3) {
+        if self.max_points > 0 && self.points.len() >= self.max_points as usize {
+            self.points.remove(0);
+        }
+        self.points.push(TrailPoint { position, age: 0.0 });
+    }
+
+    /// Replaces every point of trail with given positions.
+    pub fn set_points(&mut self, positions: &[Vec3]) {
+        self.points = positions.iter()
+            .map(|position| TrailPoint { position: *position, age: 0.0 })
+            .collect();
+    }
+
+    pub fn clear_points(&mut self) {
+        self.points.clear();
+    }
+
+    /// Returns points of trail from oldest to newest.
+    pub fn points(&self) -> &[TrailPoint] {
+        &self.points
+    }
+
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width;
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// Sets color of trail. Color is ignored if color over lifetime gradient is set.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn set_color_over_lifetime_gradient(&mut self, gradient: Option<ColorGradient>) {
+        self.color_over_lifetime = gradient;
+    }
+
+    /// Sets time in seconds after which point is removed from trail. `None` means that
+    /// points will live forever, this is useful for debug paths.
+    pub fn set_point_lifetime(&mut self, lifetime: Option<f32>) {
+        self.point_lifetime = lifetime;
+    }
+
+    pub fn point_lifetime(&self) -> Option<f32> {
+        self.point_lifetime
+    }
+
+    /// Enables or disables automatic generation of points from motion of node.
+    pub fn set_emit_from_motion(&mut self, state: bool) {
+        self.emit_from_motion = state;
+    }
+
+    pub fn is_emitting_from_motion(&self) -> bool {
+        self.emit_from_motion
+    }
+
+    /// Sets minimal distance which node should pass before new point will be added.
+    pub fn set_min_segment_length(&mut self, length: f32) {
+        self.min_segment_length = length;
+    }
+
+    pub fn min_segment_length(&self) -> f32 {
+        self.min_segment_length
+    }
+
+    /// Sets maximum amount of points, zero means no limit.
+    pub fn set_max_points(&mut self, max_points: u32) {
+        self.max_points = max_points;
+    }
+
+    pub fn max_points(&self) -> u32 {
+        self.max_points
+    }
+
+    pub fn set_texture(&mut self, texture: Option<Arc<Mutex<Texture>>>) {
+        self.texture = texture;
+    }
+
+    pub fn texture(&self) -> Option<Arc<Mutex<Texture>>> {
+        self.texture.clone()
+    }
+
+    pub(in crate) fn shift_origin(&mut self, offset: Vec3) {
+        for point in self.points.iter_mut() {
+            point.position -= offset;
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for point in self.points.iter_mut() {
+            point.age += dt;
+        }
+
+        if let Some(lifetime) = self.point_lifetime {
+            self.points.retain(|point| point.age < lifetime);
+        }
+
+        if self.emit_from_motion {
+            let position = self.base.global_position();
+            let need_point = match self.points.last() {
+                Some(last) => last.position.sqr_distance(&position) >= self.min_segment_length * self.min_segment_length,
+                None => true,
+            };
+            if need_point {
+                self.add_point(position);
+            }
+        }
+    }
+
+    fn point_color(&self, point: &TrailPoint) -> Color {
+        let k = match self.point_lifetime {
+            Some(lifetime) if lifetime > 0.0 => (point.age / lifetime).min(1.0),
+            _ => 0.0,
+        };
+        if let Some(gradient) = &self.color_over_lifetime {
+            gradient.get_color(k)
+        } else {
+            let mut color = self.color;
+            color.a = (f32::from(color.a) * (1.0 - k)) as u8;
+            color
+        }
+    }
+
+    /// Generates camera-facing ribbon. Width of ribbon is interpolated from full width at
+    /// the newest point to zero at the point which is about to die.
+    pub fn generate_draw_data(&self, draw_data: &mut DrawData, camera_pos: &Vec3) {
+        draw_data.clear();
+
+        if self.points.len() < 2 {
+            return;
+        }
+
+        let last = self.points.len() - 1;
+        for (i, point) in self.points.iter().enumerate() {
+            let prev = &self.points[if i > 0 { i - 1 } else { i }];
+            let next = &self.points[if i < last { i + 1 } else { i }];
+            let direction = next.position - prev.position;
+            let to_camera = *camera_pos - point.position;
+
+            let half_width = match self.point_lifetime {
+                Some(lifetime) if lifetime > 0.0 => 0.5 * self.width * (1.0 - (point.age / lifetime).min(1.0)),
+                _ => 0.5 * self.width,
+            };
+
+            let side = direction.cross(&to_camera)
+                .normalized()
+                .unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0))
+                .scale(half_width);
+
+            let color = self.point_color(point);
+            let u = i as f32 / last as f32;
+
+            draw_data.vertices.push(Vertex {
+                position: point.position - side,
+                tex_coord: Vec2::new(u, 0.0),
+                color,
+            });
+
+            draw_data.vertices.push(Vertex {
+                position: point.position + side,
+                tex_coord: Vec2::new(u, 1.0),
+                color,
+            });
+
+            if i > 0 {
+                let base_index = (i * 2) as u32;
+                draw_data.triangles.push(TriangleDefinition([base_index - 2, base_index - 1, base_index + 1]));
+                draw_data.triangles.push(TriangleDefinition([base_index - 2, base_index + 1, base_index]));
+            }
+        }
+    }
+}
+
+impl Visit for Trail {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.points.visit("Points", visitor)?;
+        self.texture.visit("Texture", visitor)?;
+        self.width.visit("Width", visitor)?;
+        self.color.visit("Color", visitor)?;
+        self.color_over_lifetime.visit("ColorGradient", visitor)?;
+        self.point_lifetime.visit("PointLifetime", visitor)?;
+        self.emit_from_motion.visit("EmitFromMotion", visitor)?;
+        self.min_segment_length.visit("MinSegmentLength", visitor)?;
+        self.max_points.visit("MaxPoints", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct TrailBuilder {
+    base_builder: BaseBuilder,
+    points: Option<Vec<Vec3>>,
+    texture: Option<Arc<Mutex<Texture>>>,
+    width: Option<f32>,
+    color: Option<Color>,
+    color_over_lifetime: Option<ColorGradient>,
+    point_lifetime: Option<f32>,
+    emit_from_motion: Option<bool>,
+    min_segment_length: Option<f32>,
+    max_points: Option<u32>,
+}
+
+impl TrailBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            points: None,
+            texture: None,
+            width: None,
+            color: None,
+            color_over_lifetime: None,
+            point_lifetime: None,
+            emit_from_motion: None,
+            min_segment_length: None,
+            max_points: None,
+        }
+    }
+
+    pub fn with_points(mut self, points: Vec<Vec3>) -> Self {
+        self.points = Some(points);
+        self
+    }
+
+    pub fn with_texture(mut self, texture: Arc<Mutex<Texture>>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn with_opt_texture(mut self, texture: Option<Arc<Mutex<Texture>>>) -> Self {
+        self.texture = texture;
+        self
+    }
+
+    pub fn with_width(mut self, width: f32) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_color_over_lifetime_gradient(mut self, color_over_lifetime: ColorGradient) -> Self {
+        self.color_over_lifetime = Some(color_over_lifetime);
+        self
+    }
+
+    pub fn with_point_lifetime(mut self, lifetime: f32) -> Self {
+        self.point_lifetime = Some(lifetime);
+        self
+    }
+
+    pub fn with_emit_from_motion(mut self, state: bool) -> Self {
+        self.emit_from_motion = Some(state);
+        self
+    }
+
+    pub fn with_min_segment_length(mut self, length: f32) -> Self {
+        self.min_segment_length = Some(length);
+        self
+    }
+
+    pub fn with_max_points(mut self, max_points: u32) -> Self {
+        self.max_points = Some(max_points);
+        self
+    }
+
+    pub fn build(self) -> Trail {
+        let mut trail = Trail {
+            base: self.base_builder.build(),
+            points: Vec::new(),
+            texture: self.texture,
+            width: self.width.unwrap_or(0.1),
+            color: self.color.unwrap_or(Color::WHITE),
+            color_over_lifetime: self.color_over_lifetime,
+            point_lifetime: self.point_lifetime,
+            emit_from_motion: self.emit_from_motion.unwrap_or(false),
+            min_segment_length: self.min_segment_length.unwrap_or(0.1),
+            max_points: self.max_points.unwrap_or(0),
+        };
+        if let Some(points) = self.points {
+            trail.set_points(&points);
+        }
+        trail
+    }
+
+    /// Creates new trail node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Trail(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
@@ -44,7 +44,8 @@
 //!
 //! Once transform baked into matrix, it is *almost* impossible to decompose it back into
 //! initial components, thats why engine does not provide any methods to get those
-//! properties back.
+//! properties back. The only exception is [`Transform::set_matrix`], which solves position,
+//! rotation and scale for a matrix without shear while keeping all other properties.
 
 #![warn(missing_docs)]
 
@@ -300,6 +301,49 @@ impl Transform {
             rotation_pivot_inv * scale_offset * scale_pivot * scale * scale_pivot_inv
     }
 
+    /// Adjusts position, rotation and scale so that [`matrix`](Transform::matrix) becomes
+    /// equal to given matrix, all other properties (pre- and post-rotation, offsets and
+    /// pivots) are kept as is. Matrix must not have shear, otherwise result will be
+    /// approximate. Negative scale is put on X axis.
+    pub fn set_matrix(&mut self, matrix: Mat4) -> &mut Self {
+        let mut side = matrix.side();
+        let up = matrix.up();
+        let look = matrix.look();
+
+        let mut scale = Vec3::new(side.len(), up.len(), look.len());
+        if side.dot(&up.cross(&look)) < 0.0 {
+            scale.x = -scale.x;
+            side = side.scale(-1.0);
+        }
+
+        let rotation = rotation_from_basis(
+            side.normalized().unwrap_or_else(|| Vec3::new(1.0, 0.0, 0.0)),
+            up.normalized().unwrap_or(Vec3::UP),
+            look.normalized().unwrap_or(Vec3::LOOK),
+        );
+
+        // Linear part of transform is Rpre * R * Rpost * S, so R = Rpre⁻¹ * Rd * Rpost⁻¹
+        let inv_pre = Mat4::from_quat(self.pre_rotation).inverse().unwrap_or(Mat4::IDENTITY);
+        let inv_post = Mat4::from_quat(self.post_rotation).inverse().unwrap_or(Mat4::IDENTITY);
+        let local_rotation = inv_pre * Mat4::from_quat(rotation) * inv_post;
+
+        self.local_rotation = rotation_from_basis(
+            local_rotation.side(),
+            local_rotation.up(),
+            local_rotation.look(),
+        );
+        self.local_scale = scale;
+
+        // Offsets and pivots only add translation, so find it with zero position and
+        // compensate.
+        self.local_position = Vec3::ZERO;
+        self.dirty.set(true);
+        self.local_position = matrix.position() - self.matrix().position();
+        self.dirty.set(true);
+
+        self
+    }
+
     /// Returns matrix which is final result of transform. Matrix then can be used to transform
     /// a vector, or combine with other matrix, to make transform hierarchy for example.
     pub fn matrix(&self) -> Mat4 {
@@ -311,6 +355,34 @@ impl Transform {
     }
 }
 
+/// Converts orthonormal right-handed basis (columns of rotation matrix) to quaternion
+/// using axis-angle representation.
+fn rotation_from_basis(side: Vec3, up: Vec3, look: Vec3) -> Quat {
+    let cos = ((side.x + up.y + look.z - 1.0) * 0.5).max(-1.0).min(1.0);
+    let angle = cos.acos();
+    let axis = Vec3::new(up.z - look.y, look.x - side.z, side.y - up.x);
+    if let Some(axis) = axis.normalized() {
+        if angle.sin() > 1.0e-4 {
+            return Quat::from_axis_angle(axis, angle);
+        }
+    }
+    if cos > 0.0 {
+        return Quat::IDENTITY;
+    }
+    // Rotation by 180 degrees, axis is taken from diagonal of matrix.
+    let x = ((side.x + 1.0) * 0.5).max(0.0).sqrt();
+    let y = ((up.y + 1.0) * 0.5).max(0.0).sqrt();
+    let z = ((look.z + 1.0) * 0.5).max(0.0).sqrt();
+    let axis = if x > 1.0e-4 {
+        Vec3::new(x, y.copysign(side.y + up.x), z.copysign(side.z + look.x))
+    } else if y > 1.0e-4 {
+        Vec3::new(x, y, z.copysign(up.z + look.y))
+    } else {
+        Vec3::new(0.0, 0.0, 1.0)
+    };
+    Quat::from_axis_angle(axis.normalized().unwrap_or(Vec3::UP), std::f32::consts::PI)
+}
+
 /// Transform builder allows you to construct transform in declarative manner.
 /// This is typical implementation of Builder pattern.
 pub struct TransformBuilder {
@@ -0,0 +1,317 @@
+//! Trigger is an invisible volume (box or sphere) which tracks which nodes are inside of
+//! it and emits events when nodes enter or leave it. It is the standard building block
+//! for checkpoints, doors, cutscene triggers and so on.
+//!
+//! By default trigger tracks every node which has a rigid body bound to it (see
+//! `PhysicsBinder`), but it can also track explicit list of nodes, for example only player.
+//! Nodes are tested by their global position, size of bodies is not taken into account.
+//!
+//! Triggers are updated by scene at the end of `Scene::update`, events of last update can
+//! be read by `Trigger::events`:
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, node::Node, trigger::TriggerEvent};
+//!
+//! fn check_triggers(scene: &Scene) {
+//!     for node in scene.graph.linear_iter() {
+//!         if let Node::Trigger(trigger) = node {
+//!             for event in trigger.events() {
+//!                 match event {
+//!                     TriggerEvent::Enter(node) => println!("{:?} entered {}", node, trigger.name()),
+//!                     TriggerEvent::Leave(node) => println!("{:?} left {}", node, trigger.name()),
+//!                 }
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
+use crate::{
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        node::Node,
+        graph::Graph,
+    },
+    core::{
+        math::vec3::Vec3,
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+/// Shape of trigger volume in local space of trigger node.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TriggerShape {
+    /// Box centered at node position.
+    Box {
+        half_extents: Vec3,
+    },
+    /// Sphere centered at node position.
+    Sphere {
+        radius: f32,
+    },
+}
+
+impl Default for TriggerShape {
+    fn default() -> Self {
+        TriggerShape::Box { half_extents: Vec3::new(0.5, 0.5, 0.5) }
+    }
+}
+
+impl TriggerShape {
+    fn id(&self) -> u8 {
+        match self {
+            TriggerShape::Box { .. } => 0,
+            TriggerShape::Sphere { .. } => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, String> {
+        match id {
+            0 => Ok(TriggerShape::Box { half_extents: Default::default() }),
+            1 => Ok(TriggerShape::Sphere { radius: 0.0 }),
+            _ => Err(format!("Invalid trigger shape {}", id))
+        }
+    }
+}
+
+impl Visit for TriggerShape {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut id = self.id();
+        id.visit("Id", visitor)?;
+        if visitor.is_reading() {
+            *self = TriggerShape::from_id(id)?;
+        }
+        match self {
+            TriggerShape::Box { half_extents } => half_extents.visit("HalfExtents", visitor)?,
+            TriggerShape::Sphere { radius } => radius.visit("Radius", visitor)?,
+        }
+
+        visitor.leave_region()
+    }
+}
+
+/// Event of trigger, contains handle of node which entered or left trigger volume.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TriggerEvent {
+    Enter(Handle<Node>),
+    /// Node left volume or was removed from graph while it was inside.
+    Leave(Handle<Node>),
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct Trigger {
+    base: Base,
+    shape: TriggerShape,
+    tracked: Option<Vec<Handle<Node>>>,
+    inside: Vec<Handle<Node>>,
+    events: Vec<TriggerEvent>,
+}
+
+impl Deref for Trigger {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Trigger {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        TriggerBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl Trigger {
+    pub fn set_shape(&mut self, shape: TriggerShape) {
+        self.shape = shape;
+    }
+
+    pub fn shape(&self) -> TriggerShape {
+        self.shape
+    }
+
+    /// Sets explicit list of nodes to track, `None` means every node with bound rigid body.
+    pub fn set_tracked_nodes(&mut self, tracked: Option<Vec<Handle<Node>>>) {
+        self.tracked = tracked;
+    }
+
+    pub fn tracked_nodes(&self) -> Option<&[Handle<Node>]> {
+        self.tracked.as_deref()
+    }
+
+    /// Returns nodes which were inside of volume on last update.
+    pub fn inside(&self) -> &[Handle<Node>] {
+        &self.inside
+    }
+
+    /// Returns true if given node was inside of volume on last update.
+    pub fn is_inside(&self, node: Handle<Node>) -> bool {
+        self.inside.contains(&node)
+    }
+
+    /// Returns events of last update.
+    pub fn events(&self) -> &[TriggerEvent] {
+        &self.events
+    }
+
+    /// Returns true if given world space point is inside of volume.
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        let local = match self.global_transform().inverse() {
+            Ok(inverse) => inverse.transform_vector(point),
+            Err(_) => return false,
+        };
+        match self.shape {
+            TriggerShape::Box { half_extents } => {
+                local.x.abs() <= half_extents.x && local.y.abs() <= half_extents.y && local.z.abs() <= half_extents.z
+            }
+            TriggerShape::Sphere { radius } => local.len() <= radius,
+        }
+    }
+
+    /// Replaces set of nodes inside of volume and emits events for the difference.
+    pub(in crate) fn set_inside(&mut self, inside: Vec<Handle<Node>>) {
+        self.events.clear();
+        for &node in inside.iter() {
+            if !self.inside.contains(&node) {
+                self.events.push(TriggerEvent::Enter(node));
+            }
+        }
+        for &node in self.inside.iter() {
+            if !inside.contains(&node) {
+                self.events.push(TriggerEvent::Leave(node));
+            }
+        }
+        self.inside = inside;
+    }
+
+    /// Remaps tracked nodes of copied trigger, contents are cleared because they belong
+    /// to original.
+    pub(in crate) fn remap_handles(&mut self, old_new_mapping: &HashMap<Handle<Node>, Handle<Node>>) {
+        if let Some(tracked) = self.tracked.as_mut() {
+            for node in tracked.iter_mut() {
+                if let Some(&new_node) = old_new_mapping.get(node) {
+                    *node = new_node;
+                }
+            }
+        }
+        self.inside.clear();
+        self.events.clear();
+    }
+}
+
+impl Visit for Trigger {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.shape.visit("Shape", visitor)?;
+        self.tracked.visit("Tracked", visitor)?;
+        self.inside.visit("Inside", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct TriggerBuilder {
+    base_builder: BaseBuilder,
+    shape: Option<TriggerShape>,
+    tracked: Option<Vec<Handle<Node>>>,
+}
+
+impl TriggerBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            shape: None,
+            tracked: None,
+        }
+    }
+
+    pub fn with_shape(mut self, shape: TriggerShape) -> Self {
+        self.shape = Some(shape);
+        self
+    }
+
+    pub fn with_tracked_nodes(mut self, tracked: Vec<Handle<Node>>) -> Self {
+        self.tracked = Some(tracked);
+        self
+    }
+
+    pub fn build(self) -> Trigger {
+        Trigger {
+            base: self.base_builder.build(),
+            shape: self.shape.unwrap_or_default(),
+            tracked: self.tracked,
+            inside: Default::default(),
+            events: Default::default(),
+        }
+    }
+
+    /// Creates new trigger node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::Trigger(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::{vec2::Vec2, vec3::Vec3},
+        scene::{
+            base::{Base, BaseBuilder},
+            graph::Graph,
+            node::Node,
+            trigger::{TriggerBuilder, TriggerEvent, TriggerShape},
+        },
+    };
+
+    #[test]
+    fn test_trigger_events() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(Node::Base(Base::default()));
+        let b = graph.add_node(Node::Base(Base::default()));
+        graph[b].local_transform_mut().set_position(Vec3::new(5.0, 0.0, 0.0));
+        let trigger = graph.add_node(Node::Trigger(TriggerBuilder::new(BaseBuilder::new())
+            .with_shape(TriggerShape::Sphere { radius: 1.0 })
+            .with_tracked_nodes(vec![a, b])
+            .build()));
+
+        graph.update_nodes(Vec2::new(1.0, 1.0), 0.0);
+        graph.update_triggers(&[]);
+        assert_eq!(graph[trigger].as_trigger().events(), &[TriggerEvent::Enter(a)]);
+
+        graph.update_nodes(Vec2::new(1.0, 1.0), 0.0);
+        graph.update_triggers(&[]);
+        assert!(graph[trigger].as_trigger().events().is_empty());
+
+        graph[a].local_transform_mut().set_position(Vec3::new(5.0, 0.0, 0.0));
+        graph[b].local_transform_mut().set_position(Vec3::new(0.5, 0.0, 0.0));
+        graph.update_nodes(Vec2::new(1.0, 1.0), 0.0);
+        graph.update_triggers(&[]);
+        assert_eq!(graph[trigger].as_trigger().events(), &[TriggerEvent::Enter(b), TriggerEvent::Leave(a)]);
+    }
+}
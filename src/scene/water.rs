@@ -0,0 +1,311 @@
+//! Water volume is an invisible box which makes rigid bodies inside of it float: bodies are
+//! pushed up proportionally to their submerged fraction and slowed down by drag of water.
+//!
+//! Level of water is top of the box, or, if water volume is associated with a surface node
+//! (usually mesh of water plane), height of that node. So when water surface is moved (tide,
+//! flooding room) floating objects follow it without any extra code.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::{math::vec3::Vec3, pool::Handle},
+//!     scene::{
+//!         base::BaseBuilder,
+//!         node::Node,
+//!         water::WaterVolumeBuilder,
+//!         Scene,
+//!     },
+//! };
+//!
+//! fn create_pool(scene: &mut Scene, water_plane: Handle<Node>) {
+//!     let volume = WaterVolumeBuilder::new(BaseBuilder::new())
+//!         .with_half_extents(Vec3::new(10.0, 2.0, 10.0))
+//!         .with_surface(water_plane)
+//!         .with_buoyancy(2.0 * 9.81)
+//!         .build();
+//!     scene.graph.add_node(Node::WaterVolume(volume));
+//! }
+//! ```
+//!
+//! Water volumes are applied by scene to rigid bodies bound to nodes (see `PhysicsBinder`)
+//! before each physics step. Vertical extent of body is taken from bounds of meshes of its
+//! node hierarchy, bodies without meshes use default body height of volume. Mass of bodies
+//! is not taken into account, so buoyancy is acceleration in units per second squared at
+//! full submersion: body floats when `buoyancy * fraction` equals gravity, for example
+//! buoyancy of twice the gravity keeps bodies half-submerged.
+
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
+use crate::{
+    scene::{
+        base::{
+            Base,
+            BaseBuilder,
+        },
+        graph::Graph,
+        node::Node,
+    },
+    core::{
+        math::vec3::Vec3,
+        pool::Handle,
+        visitor::{
+            Visit,
+            Visitor,
+            VisitResult,
+        },
+    },
+};
+
+/// Returns fraction of vertical span `[bottom; top]` which is below water level.
+pub fn submerged_fraction(water_level: f32, bottom: f32, top: f32) -> f32 {
+    if top > bottom {
+        ((water_level - bottom) / (top - bottom)).max(0.0).min(1.0)
+    } else if water_level >= bottom {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// See module docs.
+#[derive(Clone)]
+pub struct WaterVolume {
+    base: Base,
+    half_extents: Vec3,
+    surface: Handle<Node>,
+    buoyancy: f32,
+    drag: f32,
+    default_body_height: f32,
+}
+
+impl Deref for WaterVolume {
+    type Target = Base;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for WaterVolume {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl Default for WaterVolume {
+    fn default() -> Self {
+        WaterVolumeBuilder::new(BaseBuilder::new()).build()
+    }
+}
+
+impl WaterVolume {
+    /// Sets half size of box of volume in local space of node.
+    pub fn set_half_extents(&mut self, half_extents: Vec3) {
+        self.half_extents = half_extents;
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        self.half_extents
+    }
+
+    /// Sets node which defines level of water, `Handle::NONE` means top of the box.
+    pub fn set_surface(&mut self, surface: Handle<Node>) {
+        self.surface = surface;
+    }
+
+    pub fn surface(&self) -> Handle<Node> {
+        self.surface
+    }
+
+    /// Sets upward acceleration of fully submerged body in units per second squared.
+    pub fn set_buoyancy(&mut self, buoyancy: f32) {
+        self.buoyancy = buoyancy.max(0.0);
+    }
+
+    pub fn buoyancy(&self) -> f32 {
+        self.buoyancy
+    }
+
+    /// Sets fraction of velocity of fully submerged body lost per second.
+    pub fn set_drag(&mut self, drag: f32) {
+        self.drag = drag.max(0.0);
+    }
+
+    pub fn drag(&self) -> f32 {
+        self.drag
+    }
+
+    /// Sets height of bodies which have no meshes.
+    pub fn set_default_body_height(&mut self, height: f32) {
+        self.default_body_height = height.max(0.0);
+    }
+
+    pub fn default_body_height(&self) -> f32 {
+        self.default_body_height
+    }
+
+    /// Returns world height of water surface.
+    pub fn water_level(&self, graph: &Graph) -> f32 {
+        if graph.is_valid_handle(self.surface) {
+            graph[self.surface].global_position().y
+        } else {
+            self.global_transform().transform_vector(Vec3::new(0.0, self.half_extents.y, 0.0)).y
+        }
+    }
+
+    /// Returns true if given world point is inside horizontal bounds of volume and above
+    /// its bottom. Points above water level are inside too, so bodies which are partially
+    /// submerged are affected.
+    pub fn is_inside(&self, point: Vec3) -> bool {
+        match self.global_transform().inverse() {
+            Ok(inverse) => {
+                let local = inverse.transform_vector(point);
+                local.x.abs() <= self.half_extents.x
+                    && local.z.abs() <= self.half_extents.z
+                    && local.y >= -self.half_extents.y
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns acceleration (in units per second squared) of body which spans given world
+    /// heights and moves with given velocity (in units per second).
+    pub fn acceleration(&self, water_level: f32, bottom: f32, top: f32, velocity: Vec3) -> Vec3 {
+        let fraction = submerged_fraction(water_level, bottom, top);
+        if fraction > 0.0 {
+            Vec3::new(0.0, self.buoyancy * fraction, 0.0) - velocity.scale(self.drag * fraction)
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    pub(in crate) fn remap_handles(&mut self, old_new_mapping: &HashMap<Handle<Node>, Handle<Node>>) {
+        if let Some(&new_surface) = old_new_mapping.get(&self.surface) {
+            self.surface = new_surface;
+        }
+    }
+}
+
+impl Visit for WaterVolume {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.half_extents.visit("HalfExtents", visitor)?;
+        self.surface.visit("Surface", visitor)?;
+        self.buoyancy.visit("Buoyancy", visitor)?;
+        self.drag.visit("Drag", visitor)?;
+        self.default_body_height.visit("DefaultBodyHeight", visitor)?;
+        self.base.visit("Base", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+pub struct WaterVolumeBuilder {
+    base_builder: BaseBuilder,
+    half_extents: Option<Vec3>,
+    surface: Handle<Node>,
+    buoyancy: Option<f32>,
+    drag: Option<f32>,
+    default_body_height: Option<f32>,
+}
+
+impl WaterVolumeBuilder {
+    pub fn new(base_builder: BaseBuilder) -> Self {
+        Self {
+            base_builder,
+            half_extents: None,
+            surface: Handle::NONE,
+            buoyancy: None,
+            drag: None,
+            default_body_height: None,
+        }
+    }
+
+    pub fn with_half_extents(mut self, half_extents: Vec3) -> Self {
+        self.half_extents = Some(half_extents);
+        self
+    }
+
+    pub fn with_surface(mut self, surface: Handle<Node>) -> Self {
+        self.surface = surface;
+        self
+    }
+
+    /// Sets buoyancy, default is twice the standard gravity.
+    pub fn with_buoyancy(mut self, buoyancy: f32) -> Self {
+        self.buoyancy = Some(buoyancy);
+        self
+    }
+
+    /// Sets drag, default is 1.
+    pub fn with_drag(mut self, drag: f32) -> Self {
+        self.drag = Some(drag);
+        self
+    }
+
+    /// Sets height of bodies which have no meshes, default is 1.
+    pub fn with_default_body_height(mut self, height: f32) -> Self {
+        self.default_body_height = Some(height);
+        self
+    }
+
+    pub fn build(self) -> WaterVolume {
+        WaterVolume {
+            base: self.base_builder.build(),
+            half_extents: self.half_extents.unwrap_or(Vec3::new(0.5, 0.5, 0.5)),
+            surface: self.surface,
+            buoyancy: self.buoyancy.unwrap_or(2.0 * 9.81).max(0.0),
+            drag: self.drag.unwrap_or(1.0).max(0.0),
+            default_body_height: self.default_body_height.unwrap_or(1.0).max(0.0),
+        }
+    }
+
+    /// Creates new water volume node, adds it to given graph and links it with given parent, returns
+    /// handle of new node. Use `Graph::get_root` as parent for top-level nodes.
+    pub fn build_on(self, graph: &mut Graph, parent: Handle<Node>) -> Handle<Node> {
+        let handle = graph.add_node(Node::WaterVolume(self.build()));
+        graph.link_nodes(handle, parent);
+        handle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        scene::{
+            base::BaseBuilder,
+            graph::Graph,
+            node::Node,
+            water::{submerged_fraction, WaterVolumeBuilder},
+        },
+    };
+
+    #[test]
+    fn test_water_volume_buoyancy() {
+        assert_eq!(submerged_fraction(0.0, -1.0, 1.0), 0.5);
+        assert_eq!(submerged_fraction(5.0, -1.0, 1.0), 1.0);
+        assert_eq!(submerged_fraction(-5.0, -1.0, 1.0), 0.0);
+
+        let mut graph = Graph::new();
+        let surface = graph.add_node(Node::Base(BaseBuilder::new().build()));
+        graph[surface].local_transform_mut().set_position(Vec3::new(0.0, 0.5, 0.0));
+        let volume = graph.add_node(Node::WaterVolume(WaterVolumeBuilder::new(BaseBuilder::new())
+            .with_half_extents(Vec3::new(5.0, 2.0, 5.0))
+            .with_buoyancy(20.0)
+            .with_drag(0.0)
+            .build()));
+        graph.update_hierachical_data();
+
+        assert_eq!(graph[volume].as_water_volume().water_level(&graph), 2.0);
+        graph[volume].as_water_volume_mut().set_surface(surface);
+
+        let volume = graph[volume].as_water_volume();
+        assert_eq!(volume.water_level(&graph), 0.5);
+        assert!(volume.is_inside(Vec3::new(1.0, 3.0, 1.0)));
+        assert!(!volume.is_inside(Vec3::new(6.0, 0.0, 1.0)));
+        assert_eq!(volume.acceleration(0.0, -1.0, 1.0, Vec3::ZERO), Vec3::new(0.0, 10.0, 0.0));
+    }
+}
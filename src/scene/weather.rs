@@ -0,0 +1,345 @@
+//! Weather effects of a scene.
+//!
+//! Weather produces precipitation (rain or snow) around a camera and makes scene wet. Drops
+//! are particles of a particle system which is created by weather on first update and
+//! moved together with camera, particles are shifted back on each move, so precipitation
+//! stays in place in the world when camera moves instead of being dragged with it.
+//!
+//! Rain gradually wets the scene: wet surfaces are darker and more reflective, see
+//! `Material::set_wetness_factor` to exclude sheltered surfaces. When rain stops, scene
+//! slowly dries out. Intensity of precipitation can be changed at any time, `fade_to`
+//! changes it smoothly:
+//!
+//! ```no_run
+//! use rg3d::scene::{Scene, weather::{Weather, Precipitation}};
+//! use rg3d::core::pool::Handle;
+//! use rg3d::scene::node::Node;
+//!
+//! fn start_rain(scene: &mut Scene, camera: Handle<Node>) {
+//!     let mut weather = Weather::new(camera, Precipitation::Rain);
+//!     // Storm starts in 30 seconds.
+//!     weather.fade_to(1.0, 30.0);
+//!     scene.weather = Some(weather);
+//! }
+//! ```
+
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        numeric_range::NumericRange,
+        pool::Handle,
+        visitor::{Visit, VisitResult, Visitor},
+    },
+    scene::{
+        node::Node,
+        graph::Graph,
+        base::BaseBuilder,
+        particle_system::{
+            ParticleSystemBuilder,
+            EmitterBuilder,
+            EmitterKind,
+            BoxEmitter,
+        },
+    },
+};
+
+/// Kind of precipitation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Precipitation {
+    Rain,
+    Snow,
+}
+
+impl Precipitation {
+    fn id(self) -> u32 {
+        match self {
+            Precipitation::Rain => 0,
+            Precipitation::Snow => 1,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(Precipitation::Rain),
+            1 => Ok(Precipitation::Snow),
+            _ => Err(format!("Invalid precipitation id {}", id))
+        }
+    }
+
+    /// Amount of particles spawned per second at full intensity.
+    fn max_spawn_rate(self) -> u32 {
+        match self {
+            Precipitation::Rain => 4000,
+            Precipitation::Snow => 1500,
+        }
+    }
+}
+
+/// See module docs.
+#[derive(Clone, Debug)]
+pub struct Weather {
+    precipitation: Precipitation,
+    intensity: f32,
+    target_intensity: f32,
+    fade_speed: f32,
+    wetness: f32,
+    wetting_time: f32,
+    drying_time: f32,
+    area_size: f32,
+    camera: Handle<Node>,
+    pub(in crate) particle_system: Handle<Node>,
+    last_camera_position: Option<Vec3>,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self {
+            precipitation: Precipitation::Rain,
+            intensity: 0.0,
+            target_intensity: 0.0,
+            fade_speed: 0.0,
+            wetness: 0.0,
+            wetting_time: 60.0,
+            drying_time: 300.0,
+            area_size: 30.0,
+            camera: Handle::NONE,
+            particle_system: Handle::NONE,
+            last_camera_position: None,
+        }
+    }
+}
+
+/// Height above camera at which particles are spawned.
+const SPAWN_HEIGHT: f32 = 10.0;
+
+impl Weather {
+    /// Creates weather with zero intensity, precipitation follows given camera.
+    pub fn new(camera: Handle<Node>, precipitation: Precipitation) -> Self {
+        Self {
+            camera,
+            precipitation,
+            ..Default::default()
+        }
+    }
+
+    /// Sets kind of precipitation, particle system is re-created on next update.
+    pub fn set_precipitation(&mut self, graph: &mut Graph, precipitation: Precipitation) {
+        if self.precipitation != precipitation {
+            self.precipitation = precipitation;
+            self.remove_particle_system(graph);
+        }
+    }
+
+    pub fn precipitation(&self) -> Precipitation {
+        self.precipitation
+    }
+
+    /// Sets intensity of precipitation in [0; 1] range immediately.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0).min(1.0);
+        self.target_intensity = self.intensity;
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Smoothly changes intensity of precipitation to given value in given time in seconds.
+    pub fn fade_to(&mut self, intensity: f32, time: f32) {
+        self.target_intensity = intensity.max(0.0).min(1.0);
+        if time > 0.0 {
+            self.fade_speed = (self.target_intensity - self.intensity).abs() / time;
+        } else {
+            self.intensity = self.target_intensity;
+        }
+    }
+
+    /// Sets wetness of scene in [0; 1] range, for example to start a level after the rain.
+    pub fn set_wetness(&mut self, wetness: f32) {
+        self.wetness = wetness.max(0.0).min(1.0);
+    }
+
+    /// Returns wetness of scene in [0; 1] range, it is used by renderer to darken surfaces.
+    pub fn wetness(&self) -> f32 {
+        self.wetness
+    }
+
+    /// Sets time in seconds in which full intensity rain makes dry scene fully wet.
+    pub fn set_wetting_time(&mut self, time: f32) {
+        self.wetting_time = time.max(0.0);
+    }
+
+    pub fn wetting_time(&self) -> f32 {
+        self.wetting_time
+    }
+
+    /// Sets time in seconds in which fully wet scene dries out.
+    pub fn set_drying_time(&mut self, time: f32) {
+        self.drying_time = time.max(0.0);
+    }
+
+    pub fn drying_time(&self) -> f32 {
+        self.drying_time
+    }
+
+    /// Sets size of square area around camera covered by precipitation.
+    pub fn set_area_size(&mut self, graph: &mut Graph, size: f32) {
+        self.area_size = size.max(1.0);
+        self.remove_particle_system(graph);
+    }
+
+    pub fn area_size(&self) -> f32 {
+        self.area_size
+    }
+
+    pub fn set_camera(&mut self, camera: Handle<Node>) {
+        self.camera = camera;
+        self.last_camera_position = None;
+    }
+
+    pub fn camera(&self) -> Handle<Node> {
+        self.camera
+    }
+
+    /// Returns particle system node of precipitation, use it to set texture of drops.
+    /// It is `Handle::NONE` until first update.
+    pub fn particle_system(&self) -> Handle<Node> {
+        self.particle_system
+    }
+
+    fn remove_particle_system(&mut self, graph: &mut Graph) {
+        if graph.is_valid_handle(self.particle_system) {
+            graph.remove_node(self.particle_system);
+        }
+        self.particle_system = Handle::NONE;
+        self.last_camera_position = None;
+    }
+
+    fn create_particle_system(&self, graph: &mut Graph) -> Handle<Node> {
+        // Velocities of particles are in units per frame.
+        let emitter = EmitterBuilder::new(EmitterKind::Box(BoxEmitter::new(self.area_size, 1.0, self.area_size)))
+            .with_position(Vec3::new(0.0, SPAWN_HEIGHT, 0.0))
+            .with_spawn_rate(0)
+            .resurrect_particles(true);
+
+        let (emitter, wind_influence) = match self.precipitation {
+            Precipitation::Rain => (emitter
+                .with_max_particles(self.precipitation.max_spawn_rate())
+                .with_lifetime_range(NumericRange::new(0.8, 1.0))
+                .with_size_range(NumericRange::new(0.02, 0.03))
+                .with_size_modifier_range(NumericRange::new(0.0, 0.0))
+                .with_x_velocity_range(NumericRange::new(0.0, 0.0))
+                .with_y_velocity_range(NumericRange::new(-0.25, -0.2))
+                .with_z_velocity_range(NumericRange::new(0.0, 0.0)), 0.2),
+            Precipitation::Snow => (emitter
+                .with_max_particles(self.precipitation.max_spawn_rate() * 8)
+                .with_lifetime_range(NumericRange::new(6.0, 8.0))
+                .with_size_range(NumericRange::new(0.03, 0.06))
+                .with_size_modifier_range(NumericRange::new(0.0, 0.0))
+                .with_x_velocity_range(NumericRange::new(-0.005, 0.005))
+                .with_y_velocity_range(NumericRange::new(-0.03, -0.02))
+                .with_z_velocity_range(NumericRange::new(-0.005, 0.005))
+                .with_rotation_speed_range(NumericRange::new(-0.05, 0.05)), 1.0),
+        };
+
+        graph.add_node(Node::ParticleSystem(ParticleSystemBuilder::new(BaseBuilder::new()
+            .with_name("__Precipitation"))
+            .with_emitters(vec![emitter.build()])
+            .with_wind_influence(wind_influence)
+            .build()))
+    }
+
+    /// Updates intensity, wetness and particles, called automatically by scene.
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        if self.intensity < self.target_intensity {
+            self.intensity = (self.intensity + self.fade_speed * dt).min(self.target_intensity);
+        } else if self.intensity > self.target_intensity {
+            self.intensity = (self.intensity - self.fade_speed * dt).max(self.target_intensity);
+        }
+
+        // Snow does not wet the scene.
+        let raining = self.precipitation == Precipitation::Rain && self.intensity > 0.0;
+        if raining && self.wetting_time > 0.0 {
+            self.wetness = (self.wetness + self.intensity * dt / self.wetting_time).min(1.0);
+        } else if raining {
+            self.wetness = 1.0;
+        } else if self.drying_time > 0.0 {
+            self.wetness = (self.wetness - dt / self.drying_time).max(0.0);
+        } else {
+            self.wetness = 0.0;
+        }
+
+        if !graph.is_valid_handle(self.camera) {
+            return;
+        }
+        if !graph.is_valid_handle(self.particle_system) {
+            self.particle_system = self.create_particle_system(graph);
+            self.last_camera_position = None;
+        }
+
+        let camera_position = graph[self.camera].global_position();
+        let offset = self.last_camera_position.map_or(Vec3::ZERO, |last| last - camera_position);
+        self.last_camera_position = Some(camera_position);
+
+        let spawn_rate = (self.precipitation.max_spawn_rate() as f32 * self.intensity) as u32;
+        if let Node::ParticleSystem(particle_system) = &mut graph[self.particle_system] {
+            particle_system.local_transform_mut().set_position(camera_position);
+            particle_system.offset_particles(offset);
+            for emitter in particle_system.emitters_mut() {
+                emitter.set_spawn_rate(spawn_rate);
+            }
+        }
+    }
+}
+
+impl Visit for Weather {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        let mut precipitation = self.precipitation.id();
+        precipitation.visit("Precipitation", visitor)?;
+        if visitor.is_reading() {
+            self.precipitation = Precipitation::from_id(precipitation)?;
+        }
+        self.intensity.visit("Intensity", visitor)?;
+        self.target_intensity.visit("TargetIntensity", visitor)?;
+        self.fade_speed.visit("FadeSpeed", visitor)?;
+        self.wetness.visit("Wetness", visitor)?;
+        self.wetting_time.visit("WettingTime", visitor)?;
+        self.drying_time.visit("DryingTime", visitor)?;
+        self.area_size.visit("AreaSize", visitor)?;
+        self.camera.visit("Camera", visitor)?;
+        self.particle_system.visit("ParticleSystem", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{
+        graph::Graph,
+        weather::{Weather, Precipitation},
+    };
+    use crate::core::pool::Handle;
+
+    #[test]
+    fn test_weather_wetness() {
+        let mut graph = Graph::new();
+        let mut weather = Weather::new(Handle::NONE, Precipitation::Rain);
+        weather.set_wetting_time(10.0);
+        weather.set_drying_time(20.0);
+        weather.fade_to(1.0, 2.0);
+
+        weather.update(&mut graph, 1.0);
+        assert!((weather.intensity() - 0.5).abs() < 0.001);
+        weather.update(&mut graph, 1.0);
+        assert_eq!(weather.intensity(), 1.0);
+        assert!(weather.wetness() > 0.0);
+
+        weather.set_intensity(0.0);
+        weather.set_wetness(1.0);
+        weather.update(&mut graph, 10.0);
+        assert!((weather.wetness() - 0.5).abs() < 0.001);
+    }
+}
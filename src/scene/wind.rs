@@ -0,0 +1,122 @@
+//! Wind settings of a scene.
+//!
+//! Wind is shared by everything on scene which is moved by air - particles, cloth and
+//! vegetation (instances of scatter nodes sway), so environmental motion is consistent.
+//! Wind has constant direction and base strength plus gusts - smooth variations of strength
+//! which travel along wind direction, so neighbour objects are affected by the same gust
+//! with small delay, like grass field waves.
+
+use crate::core::{
+    math::vec3::Vec3,
+    visitor::{
+        Visit,
+        Visitor,
+        VisitResult,
+    },
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Wind {
+    direction: Vec3,
+    strength: f32,
+    gust_strength: f32,
+    gust_frequency: f32,
+    gust_length: f32,
+    time: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            strength: 0.0,
+            gust_strength: 0.0,
+            gust_frequency: 0.5,
+            gust_length: 10.0,
+            time: 0.0,
+        }
+    }
+}
+
+/// Smooth periodic noise in [0; 1] range, sum of sines with incommensurable frequencies
+/// does not repeat visibly.
+fn gust_noise(t: f32) -> f32 {
+    let sum = t.sin() + 0.5 * (2.31 * t + 1.7).sin() + 0.25 * (4.77 * t + 0.3).sin();
+    0.5 + 0.5 * sum / 1.75
+}
+
+impl Wind {
+    /// Sets direction of wind, it does not need to be normalized. Zero vector is ignored.
+    pub fn set_direction(&mut self, direction: Vec3) {
+        if let Some(direction) = direction.normalized() {
+            self.direction = direction;
+        }
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    /// Sets base speed of wind in units per second.
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.max(0.0);
+    }
+
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+
+    /// Sets maximum speed which is added to base speed by gusts.
+    pub fn set_gust_strength(&mut self, gust_strength: f32) {
+        self.gust_strength = gust_strength.max(0.0);
+    }
+
+    pub fn gust_strength(&self) -> f32 {
+        self.gust_strength
+    }
+
+    /// Sets how often gusts happen, in gusts per second (approximately).
+    pub fn set_gust_frequency(&mut self, gust_frequency: f32) {
+        self.gust_frequency = gust_frequency.max(0.0);
+    }
+
+    pub fn gust_frequency(&self) -> f32 {
+        self.gust_frequency
+    }
+
+    /// Sets distance between gusts along wind direction.
+    pub fn set_gust_length(&mut self, gust_length: f32) {
+        self.gust_length = gust_length.max(std::f32::EPSILON);
+    }
+
+    pub fn gust_length(&self) -> f32 {
+        self.gust_length
+    }
+
+    /// Advances gusts, called automatically by graph.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    /// Returns velocity of wind at given point of world.
+    pub fn velocity_at(&self, position: Vec3) -> Vec3 {
+        let phase = 2.0 * std::f32::consts::PI *
+            (self.gust_frequency * self.time - position.dot(&self.direction) / self.gust_length);
+        self.direction.scale(self.strength + self.gust_strength * gust_noise(phase))
+    }
+}
+
+impl Visit for Wind {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.direction.visit("Direction", visitor)?;
+        self.strength.visit("Strength", visitor)?;
+        self.gust_strength.visit("GustStrength", visitor)?;
+        self.gust_frequency.visit("GustFrequency", visitor)?;
+        self.gust_length.visit("GustLength", visitor)?;
+        self.time.visit("Time", visitor)?;
+
+        visitor.leave_region()
+    }
+}
@@ -0,0 +1,443 @@
+//! Crowd of navmesh agents which avoid each other.
+//!
+//! Agents which follow their own paths with steering behaviours push through each other in
+//! narrow corridors. Crowd manager moves agents along paths on navmesh and picks velocity of
+//! every agent with reciprocal velocity obstacles (RVO, by van den Berg et al.): each agent
+//! takes half of responsibility for avoiding each of its neighbours, so two agents which
+//! walk towards each other both step aside instead of dancing in front of each other.
+//!
+//! Avoidance is done in XZ plane. Manager does not move anything itself: each frame set
+//! positions of agents (for example from character controllers or rigid bodies), call
+//! `update` and apply velocities of agents to whatever moves characters.
+//!
+//! ```no_run
+//! use rg3d::{
+//!     core::{math::vec3::Vec3, pool::Handle},
+//!     utils::{
+//!         crowd::{CrowdManager, CrowdAgent},
+//!         navmesh::Navmesh,
+//!     },
+//! };
+//!
+//! fn spawn(crowd: &mut CrowdManager, navmesh: &mut Navmesh) -> Handle<CrowdAgent> {
+//!     let agent = crowd.add_agent(CrowdAgent::new(Vec3::ZERO, 0.4, 3.0));
+//!     crowd.set_target(agent, navmesh, Vec3::new(20.0, 0.0, 5.0));
+//!     agent
+//! }
+//!
+//! fn update(crowd: &mut CrowdManager, agent: Handle<CrowdAgent>, position: Vec3) -> Vec3 {
+//!     crowd.agent_mut(agent).set_position(position);
+//!     crowd.update();
+//!     crowd.agent(agent).velocity()
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+use crate::{
+    core::{
+        math::vec3::Vec3,
+        pool::{
+            Pool,
+            Handle,
+            PoolIterator,
+            PoolPairIterator,
+        },
+    },
+    utils::{
+        navmesh::Navmesh,
+        astar::PathKind,
+    },
+};
+
+/// Member of crowd, see module docs.
+#[derive(Clone, Debug)]
+pub struct CrowdAgent {
+    position: Vec3,
+    velocity: Vec3,
+    radius: f32,
+    max_speed: f32,
+    path: Vec<Vec3>,
+    current: usize,
+    waypoint_radius: f32,
+    slowing_radius: f32,
+    avoidance_weight: f32,
+}
+
+impl CrowdAgent {
+    /// Creates agent at given position with given radius and max speed.
+    pub fn new(position: Vec3, radius: f32, max_speed: f32) -> Self {
+        Self {
+            position,
+            velocity: Vec3::ZERO,
+            radius: radius.max(0.0),
+            max_speed: max_speed.max(0.0),
+            path: Default::default(),
+            current: 0,
+            waypoint_radius: 0.5,
+            slowing_radius: 1.0,
+            avoidance_weight: 1.0,
+        }
+    }
+
+    /// Sets distance to waypoint at which agent switches to next one.
+    pub fn with_waypoint_radius(mut self, waypoint_radius: f32) -> Self {
+        self.waypoint_radius = waypoint_radius.max(0.0);
+        self
+    }
+
+    /// Sets distance to end of path within which agent slows down.
+    pub fn with_slowing_radius(mut self, slowing_radius: f32) -> Self {
+        self.slowing_radius = slowing_radius.max(std::f32::EPSILON);
+        self
+    }
+
+    /// Sets how much agent prefers avoiding collisions over following its path, agents
+    /// with higher weight step aside earlier and wider.
+    pub fn with_avoidance_weight(mut self, avoidance_weight: f32) -> Self {
+        self.avoidance_weight = avoidance_weight.max(0.0);
+        self
+    }
+
+    /// Returns position of agent.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Sets position of agent, usually to position of character moved by velocity of agent.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+
+    /// Returns velocity chosen for agent by last `CrowdManager::update`.
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    /// Returns radius of agent.
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Returns max speed of agent.
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    /// Sets path which agent follows, points go from start to end.
+    pub fn set_path(&mut self, path: Vec<Vec3>) {
+        self.path = path;
+        self.current = 0;
+    }
+
+    /// Returns path of agent.
+    pub fn path(&self) -> &[Vec3] {
+        &self.path
+    }
+
+    /// Stops agent, it still steps aside when others pass by.
+    pub fn stop(&mut self) {
+        self.path.clear();
+        self.current = 0;
+    }
+
+    /// Returns true if agent has no path or it is close enough to end of its path.
+    pub fn is_arrived(&self) -> bool {
+        match self.path.last() {
+            Some(end) => self.current + 1 >= self.path.len() && planar(*end - self.position).len() <= self.waypoint_radius,
+            None => true,
+        }
+    }
+
+    /// Returns velocity with which agent would move along its path if it were alone.
+    fn preferred_velocity(&mut self) -> Vec3 {
+        if self.path.is_empty() {
+            return Vec3::ZERO;
+        }
+        while self.current + 1 < self.path.len()
+            && planar(self.path[self.current] - self.position).len() < self.waypoint_radius {
+            self.current += 1;
+        }
+        let offset = planar(self.path[self.current] - self.position);
+        let distance = offset.len();
+        if distance <= std::f32::EPSILON {
+            return Vec3::ZERO;
+        }
+        let speed = if self.current + 1 == self.path.len() && distance < self.slowing_radius {
+            self.max_speed * distance / self.slowing_radius
+        } else {
+            self.max_speed
+        };
+        offset.scale(speed / distance)
+    }
+}
+
+/// Projects vector onto XZ plane.
+fn planar(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, 0.0, v.z)
+}
+
+/// Returns time after which two discs collide if distance between them is `offset` (from
+/// first to second) and first moves relative to second with `velocity`. Overlapping discs
+/// which are moving towards each other are colliding right now, moving apart - never.
+fn time_to_collision(offset: Vec3, velocity: Vec3, radius: f32) -> f32 {
+    let distance_sqr = offset.dot(&offset);
+    let radius_sqr = radius * radius;
+    let approach = offset.dot(&velocity);
+    if distance_sqr < radius_sqr {
+        return if approach > 0.0 { 0.0 } else { std::f32::MAX };
+    }
+    // |offset - velocity * t| = radius
+    let a = velocity.dot(&velocity);
+    if a <= std::f32::EPSILON || approach <= 0.0 {
+        return std::f32::MAX;
+    }
+    let discriminant = approach * approach - a * (distance_sqr - radius_sqr);
+    if discriminant < 0.0 {
+        std::f32::MAX
+    } else {
+        (approach - discriminant.sqrt()) / a
+    }
+}
+
+/// Manages agents of crowd, see module docs.
+pub struct CrowdManager {
+    agents: Pool<CrowdAgent>,
+    neighbour_distance: f32,
+    max_neighbours: usize,
+    time_horizon: f32,
+    samples: usize,
+    neighbours: Vec<(f32, Handle<CrowdAgent>)>,
+}
+
+impl Default for CrowdManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CrowdManager {
+    /// Creates empty crowd.
+    pub fn new() -> Self {
+        Self {
+            agents: Pool::new(),
+            neighbour_distance: 10.0,
+            max_neighbours: 10,
+            time_horizon: 2.0,
+            samples: 32,
+            neighbours: Default::default(),
+        }
+    }
+
+    /// Sets distance within which agents take each other into account.
+    pub fn with_neighbour_distance(mut self, neighbour_distance: f32) -> Self {
+        self.neighbour_distance = neighbour_distance.max(0.0);
+        self
+    }
+
+    /// Sets max amount of closest neighbours which agent avoids.
+    pub fn with_max_neighbours(mut self, max_neighbours: usize) -> Self {
+        self.max_neighbours = max_neighbours;
+        self
+    }
+
+    /// Sets time in seconds within which collisions are predicted, collisions which would
+    /// happen later are ignored.
+    pub fn with_time_horizon(mut self, time_horizon: f32) -> Self {
+        self.time_horizon = time_horizon.max(std::f32::EPSILON);
+        self
+    }
+
+    /// Sets amount of candidate velocities tested for each agent, more samples give smoother
+    /// avoidance but cost more.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Adds agent to crowd.
+    pub fn add_agent(&mut self, agent: CrowdAgent) -> Handle<CrowdAgent> {
+        self.agents.spawn(agent)
+    }
+
+    /// Removes agent from crowd.
+    pub fn remove_agent(&mut self, handle: Handle<CrowdAgent>) {
+        self.agents.free(handle);
+    }
+
+    /// Returns true if handle points to an agent of crowd.
+    pub fn is_valid_handle(&self, handle: Handle<CrowdAgent>) -> bool {
+        self.agents.is_valid_handle(handle)
+    }
+
+    /// Borrows agent.
+    pub fn agent(&self, handle: Handle<CrowdAgent>) -> &CrowdAgent {
+        self.agents.borrow(handle)
+    }
+
+    /// Borrows agent mutably.
+    pub fn agent_mut(&mut self, handle: Handle<CrowdAgent>) -> &mut CrowdAgent {
+        self.agents.borrow_mut(handle)
+    }
+
+    /// Returns iterator over agents.
+    pub fn iter(&self) -> PoolIterator<CrowdAgent> {
+        self.agents.iter()
+    }
+
+    /// Returns iterator over agents with their handles.
+    pub fn pair_iter(&self) -> PoolPairIterator<CrowdAgent> {
+        self.agents.pair_iter()
+    }
+
+    /// Builds path on navmesh from agent to target and makes agent follow it. Returns kind
+    /// of path, agent is stopped if there is no path.
+    pub fn set_target(&mut self, handle: Handle<CrowdAgent>, navmesh: &mut Navmesh, target: Vec3) -> PathKind {
+        let agent = self.agents.borrow_mut(handle);
+        let mut path = Vec::new();
+        let kind = match (navmesh.query_closest(agent.position), navmesh.query_closest(target)) {
+            (Some(from), Some(to)) => navmesh.build_path(from, to, &mut path).unwrap_or(PathKind::Empty),
+            _ => PathKind::Empty,
+        };
+        if kind == PathKind::Empty {
+            agent.stop();
+        } else {
+            // Path finder gives points from end to start.
+            path.reverse();
+            if kind == PathKind::Full {
+                path.push(target);
+            }
+            agent.set_path(path);
+        }
+        kind
+    }
+
+    /// Chooses velocities of all agents, see module docs.
+    pub fn update(&mut self) {
+        let handles = self.agents.pair_iter().map(|(handle, _)| handle).collect::<Vec<_>>();
+        let preferred = handles.iter()
+            .map(|&handle| self.agents.borrow_mut(handle).preferred_velocity())
+            .collect::<Vec<_>>();
+
+        let mut velocities = Vec::with_capacity(handles.len());
+        for (&handle, &preferred_velocity) in handles.iter().zip(preferred.iter()) {
+            self.collect_neighbours(handle);
+            velocities.push(self.choose_velocity(handle, preferred_velocity));
+        }
+
+        // Velocities are applied after all agents made their choice, so choice does not
+        // depend on order of agents.
+        for (&handle, velocity) in handles.iter().zip(velocities) {
+            self.agents.borrow_mut(handle).velocity = velocity;
+        }
+    }
+
+    fn collect_neighbours(&mut self, handle: Handle<CrowdAgent>) {
+        self.neighbours.clear();
+        let agent = self.agents.borrow(handle);
+        for (other_handle, other) in self.agents.pair_iter() {
+            if other_handle == handle {
+                continue;
+            }
+            let distance = planar(other.position - agent.position).len() - other.radius;
+            if distance < self.neighbour_distance {
+                self.neighbours.push((distance, other_handle));
+            }
+        }
+        self.neighbours.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        self.neighbours.truncate(self.max_neighbours);
+    }
+
+    /// Picks velocity among candidates which minimizes sum of deviation from preferred
+    /// velocity and penalty for upcoming collisions.
+    fn choose_velocity(&self, handle: Handle<CrowdAgent>, preferred_velocity: Vec3) -> Vec3 {
+        let agent = self.agents.borrow(handle);
+        if self.neighbours.is_empty() {
+            return preferred_velocity;
+        }
+
+        let penalty = |candidate: Vec3| {
+            let mut min_time = std::f32::MAX;
+            for &(_, neighbour) in self.neighbours.iter() {
+                let other = self.agents.borrow(neighbour);
+                // Reciprocal velocity obstacle: agent takes half of avoidance, so it is tested
+                // with velocity which is reflected around its current velocity.
+                let relative_velocity = planar(candidate.scale(2.0) - agent.velocity - other.velocity);
+                let time = time_to_collision(
+                    planar(other.position - agent.position),
+                    relative_velocity,
+                    agent.radius + other.radius);
+                min_time = min_time.min(time);
+            }
+            let collision = if min_time > self.time_horizon {
+                0.0
+            } else {
+                agent.avoidance_weight / min_time.max(0.001)
+            };
+            // Small preference of right side, so agents which walk towards each other
+            // choose opposite sides instead of stepping aside in the same direction.
+            let right = Vec3::new(-preferred_velocity.z, 0.0, preferred_velocity.x);
+            collision + (preferred_velocity - candidate).len() - 0.01 * candidate.dot(&right)
+        };
+
+        let mut best = preferred_velocity;
+        let mut best_penalty = penalty(preferred_velocity);
+        // Nothing to avoid on the way.
+        if best_penalty > 0.0 {
+            // Candidates are spread evenly over disc of max speed: rings of directions with
+            // several speeds, so result is deterministic.
+            let rings = 3;
+            let per_ring = (self.samples / rings).max(1);
+            for ring in 1..=rings {
+                let speed = agent.max_speed * ring as f32 / rings as f32;
+                for i in 0..per_ring {
+                    let angle = 2.0 * std::f32::consts::PI * (i as f32 + 0.5 * ring as f32) / per_ring as f32;
+                    let candidate = Vec3::new(angle.cos() * speed, 0.0, angle.sin() * speed);
+                    let candidate_penalty = penalty(candidate);
+                    if candidate_penalty < best_penalty {
+                        best = candidate;
+                        best_penalty = candidate_penalty;
+                    }
+                }
+            }
+            let candidate_penalty = penalty(Vec3::ZERO);
+            if candidate_penalty < best_penalty {
+                best = Vec3::ZERO;
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        utils::crowd::{CrowdManager, CrowdAgent},
+    };
+
+    #[test]
+    fn test_crowd_avoidance() {
+        let mut crowd = CrowdManager::new();
+        let a = crowd.add_agent(CrowdAgent::new(Vec3::new(-5.0, 0.0, 0.0), 0.5, 2.0));
+        let b = crowd.add_agent(CrowdAgent::new(Vec3::new(5.0, 0.0, 0.0), 0.5, 2.0));
+        crowd.agent_mut(a).set_path(vec![Vec3::new(5.0, 0.0, 0.0)]);
+        crowd.agent_mut(b).set_path(vec![Vec3::new(-5.0, 0.0, 0.0)]);
+
+        // Agents walk towards each other along the same line, they must pass each other
+        // without overlapping.
+        let dt = 1.0 / 30.0;
+        for _ in 0..300 {
+            crowd.update();
+            for &handle in [a, b].iter() {
+                let agent = crowd.agent_mut(handle);
+                let position = agent.position() + agent.velocity().scale(dt);
+                agent.set_position(position);
+            }
+            let distance = crowd.agent(a).position().distance(&crowd.agent(b).position());
+            assert!(distance > 0.9);
+        }
+        assert!(crowd.agent(a).is_arrived());
+        assert!(crowd.agent(b).is_arrived());
+    }
+}
@@ -0,0 +1,384 @@
+//! Constructive solid geometry (CSG) - boolean operations on meshes.
+//!
+//! Implementation is based on BSP trees (the same approach as in csg.js by Evan Wallace).
+//! Both operands must be closed (watertight) meshes, otherwise result is undefined. Cut
+//! faces are split into convex polygons which are triangulated, all vertex attributes
+//! (texture coordinates, normals, etc.) are interpolated at cut points, so texturing of
+//! operands is preserved.
+//!
+//! Operands are taken in their local coordinates, use
+//! [`SurfaceSharedData::transform_geometry`] to place them relative to each other.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rg3d::{
+//!     renderer::surface::SurfaceSharedData,
+//!     utils::csg,
+//!     core::math::{mat4::Mat4, vec3::Vec3},
+//! };
+//!
+//! let cube = SurfaceSharedData::make_cube();
+//! let mut sphere = SurfaceSharedData::make_sphere(16, 16, 0.65);
+//! sphere.transform_geometry(&Mat4::translate(Vec3::new(0.25, 0.25, 0.25)));
+//! let cube_with_hole = csg::subtract(&cube, &sphere);
+//! ```
+
+use crate::{
+    renderer::surface::{
+        SurfaceSharedData,
+        Vertex,
+    },
+    core::{
+        color::Color,
+        math::{
+            vec2::Vec2,
+            vec3::Vec3,
+            vec4::Vec4,
+            TriangleDefinition,
+        },
+    },
+    utils::raw_mesh::RawMeshBuilder,
+};
+
+/// Tolerance used to decide whether point is on plane.
+const EPSILON: f32 = 1.0e-5;
+
+fn lerp_vec2(a: Vec2, b: Vec2, t: f32) -> Vec2 {
+    Vec2::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t)
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let lerp = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t) as u8;
+    Color::from_rgba(lerp(a.r, b.r), lerp(a.g, b.g), lerp(a.b, b.b), lerp(a.a, b.a))
+}
+
+fn lerp_vec3(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    a + (b - a).scale(t)
+}
+
+fn interpolate(a: &Vertex, b: &Vertex, t: f32) -> Vertex {
+    Vertex {
+        position: lerp_vec3(a.position, b.position, t),
+        tex_coord: lerp_vec2(a.tex_coord, b.tex_coord, t),
+        normal: lerp_vec3(a.normal, b.normal, t).normalized().unwrap_or(a.normal),
+        tangent: Vec4 {
+            x: a.tangent.x + (b.tangent.x - a.tangent.x) * t,
+            y: a.tangent.y + (b.tangent.y - a.tangent.y) * t,
+            z: a.tangent.z + (b.tangent.z - a.tangent.z) * t,
+            // Handedness can't be interpolated.
+            w: a.tangent.w,
+        },
+        bone_weights: a.bone_weights,
+        bone_indices: a.bone_indices,
+        second_tex_coord: lerp_vec2(a.second_tex_coord, b.second_tex_coord, t),
+        color: lerp_color(a.color, b.color, t),
+    }
+}
+
+fn flip_vertex(vertex: &mut Vertex) {
+    vertex.normal = vertex.normal.scale(-1.0);
+}
+
+#[derive(Copy, Clone)]
+struct Plane {
+    normal: Vec3,
+    w: f32,
+}
+
+impl Plane {
+    fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Option<Self> {
+        let normal = (b - a).cross(&(c - a)).normalized()?;
+        Some(Self {
+            normal,
+            w: normal.dot(&a),
+        })
+    }
+
+    fn flip(&mut self) {
+        self.normal = self.normal.scale(-1.0);
+        self.w = -self.w;
+    }
+}
+
+#[derive(Clone)]
+struct Polygon {
+    vertices: Vec<Vertex>,
+    plane: Plane,
+}
+
+impl Polygon {
+    fn new(vertices: Vec<Vertex>) -> Option<Self> {
+        let plane = Plane::from_points(vertices[0].position, vertices[1].position, vertices[2].position)?;
+        Some(Self {
+            vertices,
+            plane,
+        })
+    }
+
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        for vertex in self.vertices.iter_mut() {
+            flip_vertex(vertex);
+        }
+        self.plane.flip();
+    }
+}
+
+const COPLANAR: u32 = 0;
+const FRONT: u32 = 1;
+const BACK: u32 = 2;
+const SPANNING: u32 = 3;
+
+/// Splits polygon by plane and puts pieces in appropriate lists. Coplanar polygons go into
+/// either `coplanar_front` or `coplanar_back` depending on their orientation.
+fn split_polygon(plane: &Plane,
+                 polygon: Polygon,
+                 coplanar_front: &mut Vec<Polygon>,
+                 coplanar_back: &mut Vec<Polygon>,
+                 front: &mut Vec<Polygon>,
+                 back: &mut Vec<Polygon>) {
+    let mut polygon_type = 0;
+    let types = polygon.vertices.iter()
+        .map(|vertex| {
+            let t = plane.normal.dot(&vertex.position) - plane.w;
+            let vertex_type = if t < -EPSILON {
+                BACK
+            } else if t > EPSILON {
+                FRONT
+            } else {
+                COPLANAR
+            };
+            polygon_type |= vertex_type;
+            vertex_type
+        })
+        .collect::<Vec<_>>();
+
+    match polygon_type {
+        COPLANAR => {
+            if plane.normal.dot(&polygon.plane.normal) > 0.0 {
+                coplanar_front.push(polygon)
+            } else {
+                coplanar_back.push(polygon)
+            }
+        }
+        FRONT => front.push(polygon),
+        BACK => back.push(polygon),
+        _ => {
+            let mut f = Vec::new();
+            let mut b = Vec::new();
+            let count = polygon.vertices.len();
+            for i in 0..count {
+                let j = (i + 1) % count;
+                let ti = types[i];
+                let tj = types[j];
+                let vi = &polygon.vertices[i];
+                let vj = &polygon.vertices[j];
+                if ti != BACK {
+                    f.push(*vi);
+                }
+                if ti != FRONT {
+                    b.push(*vi);
+                }
+                if (ti | tj) == SPANNING {
+                    let t = (plane.w - plane.normal.dot(&vi.position)) / plane.normal.dot(&(vj.position - vi.position));
+                    let v = interpolate(vi, vj, t);
+                    f.push(v);
+                    b.push(v);
+                }
+            }
+            if f.len() >= 3 {
+                front.push(Polygon { vertices: f, plane: polygon.plane });
+            }
+            if b.len() >= 3 {
+                back.push(Polygon { vertices: b, plane: polygon.plane });
+            }
+        }
+    }
+}
+
+/// Node of BSP tree, holds polygons which lie on plane of node.
+#[derive(Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<Box<Node>>,
+    back: Option<Box<Node>>,
+    polygons: Vec<Polygon>,
+}
+
+impl Node {
+    fn new(polygons: Vec<Polygon>) -> Self {
+        let mut node = Node::default();
+        node.build(polygons);
+        node
+    }
+
+    /// Converts solid space to empty space and vice versa.
+    fn invert(&mut self) {
+        for polygon in self.polygons.iter_mut() {
+            polygon.flip();
+        }
+        if let Some(plane) = self.plane.as_mut() {
+            plane.flip();
+        }
+        if let Some(front) = self.front.as_mut() {
+            front.invert();
+        }
+        if let Some(back) = self.back.as_mut() {
+            back.invert();
+        }
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
+
+    /// Removes all polygons in given list that are inside this BSP tree.
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        let plane = match self.plane.as_ref() {
+            Some(plane) => plane,
+            None => return polygons,
+        };
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for polygon in polygons {
+            let mut coplanar_front = Vec::new();
+            let mut coplanar_back = Vec::new();
+            split_polygon(plane, polygon, &mut coplanar_front, &mut coplanar_back, &mut front, &mut back);
+            front.extend(coplanar_front);
+            back.extend(coplanar_back);
+        }
+        let front = match self.front.as_ref() {
+            Some(node) => node.clip_polygons(front),
+            None => front,
+        };
+        let back = match self.back.as_ref() {
+            Some(node) => node.clip_polygons(back),
+            // There is nothing behind leaf, so polygons are inside of solid.
+            None => Vec::new(),
+        };
+        let mut result = front;
+        result.extend(back);
+        result
+    }
+
+    /// Removes all polygons in this BSP tree that are inside other tree.
+    fn clip_to(&mut self, other: &Node) {
+        self.polygons = other.clip_polygons(std::mem::replace(&mut self.polygons, Vec::new()));
+        if let Some(front) = self.front.as_mut() {
+            front.clip_to(other);
+        }
+        if let Some(back) = self.back.as_mut() {
+            back.clip_to(other);
+        }
+    }
+
+    fn all_polygons(&self, result: &mut Vec<Polygon>) {
+        result.extend(self.polygons.iter().cloned());
+        if let Some(front) = self.front.as_ref() {
+            front.all_polygons(result);
+        }
+        if let Some(back) = self.back.as_ref() {
+            back.all_polygons(result);
+        }
+    }
+
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+        let plane = *self.plane.get_or_insert(polygons[0].plane);
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for polygon in polygons {
+            let mut coplanar_front = Vec::new();
+            let mut coplanar_back = Vec::new();
+            split_polygon(&plane, polygon, &mut coplanar_front, &mut coplanar_back, &mut front, &mut back);
+            self.polygons.extend(coplanar_front);
+            self.polygons.extend(coplanar_back);
+        }
+        if !front.is_empty() {
+            self.front.get_or_insert_with(Default::default).build(front);
+        }
+        if !back.is_empty() {
+            self.back.get_or_insert_with(Default::default).build(back);
+        }
+    }
+
+    fn into_polygons(self) -> Vec<Polygon> {
+        let mut polygons = Vec::new();
+        self.all_polygons(&mut polygons);
+        polygons
+    }
+}
+
+fn to_polygons(data: &SurfaceSharedData) -> Vec<Polygon> {
+    data.triangles
+        .iter()
+        .filter_map(|triangle| {
+            Polygon::new(vec![
+                data.vertices[triangle[0] as usize],
+                data.vertices[triangle[1] as usize],
+                data.vertices[triangle[2] as usize]
+            ])
+        })
+        .collect()
+}
+
+fn from_polygons(polygons: Vec<Polygon>) -> SurfaceSharedData {
+    let mut builder = RawMeshBuilder::<Vertex>::new(polygons.len() * 3, polygons.len() * 3);
+    for polygon in polygons {
+        // Polygons produced by splitting are always convex, so simple fan triangulation
+        // can be used.
+        for i in 1..polygon.vertices.len() - 1 {
+            builder.insert(polygon.vertices[0]);
+            builder.insert(polygon.vertices[i]);
+            builder.insert(polygon.vertices[i + 1]);
+        }
+    }
+    let mut data = SurfaceSharedData::from(builder.build());
+    // Remove triangles which have collapsed to a line after deduplication of vertices.
+    data.triangles.retain(|triangle: &TriangleDefinition| {
+        triangle[0] != triangle[1] && triangle[1] != triangle[2] && triangle[2] != triangle[0]
+    });
+    data
+}
+
+/// Returns geometry which contains space of both `a` and `b`.
+pub fn union(a: &SurfaceSharedData, b: &SurfaceSharedData) -> SurfaceSharedData {
+    let mut a = Node::new(to_polygons(a));
+    let mut b = Node::new(to_polygons(b));
+    a.clip_to(&b);
+    b.clip_to(&a);
+    b.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.build(b.into_polygons());
+    from_polygons(a.into_polygons())
+}
+
+/// Returns geometry which contains space of `a` without space of `b`.
+pub fn subtract(a: &SurfaceSharedData, b: &SurfaceSharedData) -> SurfaceSharedData {
+    let mut a = Node::new(to_polygons(a));
+    let mut b = Node::new(to_polygons(b));
+    a.invert();
+    a.clip_to(&b);
+    b.clip_to(&a);
+    b.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.build(b.into_polygons());
+    a.invert();
+    from_polygons(a.into_polygons())
+}
+
+/// Returns geometry which contains space that is common for both `a` and `b`.
+pub fn intersect(a: &SurfaceSharedData, b: &SurfaceSharedData) -> SurfaceSharedData {
+    let mut a = Node::new(to_polygons(a));
+    let mut b = Node::new(to_polygons(b));
+    a.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.clip_to(&b);
+    b.clip_to(&a);
+    a.build(b.into_polygons());
+    a.invert();
+    from_polygons(a.into_polygons())
+}
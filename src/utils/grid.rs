@@ -0,0 +1,427 @@
+//! Path finding on 2D grids: A* paths and flow fields over cost maps.
+//!
+//! Navmeshes are good for characters walking through levels, but RTS-style games and
+//! games on terrains often describe the world as a grid of cells: each cell has cost of
+//! moving through it (road is cheap, swamp is expensive, cliffs and buildings are
+//! impassable). Cost map holds such costs, and there are two ways to move over it:
+//!
+//! - `find_path` builds path for single unit with A*;
+//! - `FlowField` is built once for a target and gives direction to the target from any
+//! cell, so hundreds of units which go to the same place just follow the field instead of
+//! building hundreds of paths.
+//!
+//! ```
+//! use rg3d::utils::{grid::{CostMap, FlowField, Neighbourhood, find_path}, astar::PathKind};
+//!
+//! let mut costs = CostMap::new(16, 16);
+//! // Wall with a gap at the top.
+//! for y in 1..16 {
+//!     costs.set_cost(8, y, CostMap::BLOCKED);
+//! }
+//!
+//! let mut path = Vec::new();
+//! assert_eq!(find_path(&costs, (0, 15), (15, 15), Neighbourhood::Eight, &mut path), PathKind::Full);
+//!
+//! let field = FlowField::build(&costs, &[(15, 15)], Neighbourhood::Eight);
+//! let direction = field.direction(0, 15).unwrap();
+//! ```
+
+#![warn(missing_docs)]
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+};
+use crate::{
+    core::math::vec2::Vec2,
+    utils::astar::PathKind,
+};
+
+/// Grid of costs of moving through cells.
+#[derive(Clone, Debug)]
+pub struct CostMap {
+    width: usize,
+    height: usize,
+    costs: Vec<f32>,
+}
+
+impl CostMap {
+    /// Cost of impassable cell.
+    pub const BLOCKED: f32 = std::f32::INFINITY;
+
+    /// Creates map where every cell has cost 1.0.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            costs: vec![1.0; width * height],
+        }
+    }
+
+    /// Creates map with costs calculated by given function of cell coordinates.
+    pub fn from_fn<F: FnMut(usize, usize) -> f32>(width: usize, height: usize, mut func: F) -> Self {
+        let mut costs = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                costs.push(func(x, y));
+            }
+        }
+        Self { width, height, costs }
+    }
+
+    /// Creates map from heights of grid points (for example heightmap of terrain), `heights`
+    /// contains `width * height` values row by row. Cost grows with slope, cells with slope
+    /// steeper than `max_slope` (in radians) are blocked.
+    pub fn from_heights(width: usize, height: usize, heights: &[f32], cell_size: f32, max_slope: f32) -> Self {
+        let max_gradient = max_slope.tan();
+        let height_at = |x: usize, y: usize| heights.get(y * width + x).cloned().unwrap_or(0.0);
+        Self::from_fn(width, height, |x, y| {
+            let h = height_at(x, y);
+            let mut gradient = 0.0f32;
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for &(nx, ny) in neighbours.iter() {
+                if nx < width && ny < height {
+                    gradient = gradient.max((height_at(nx, ny) - h).abs() / cell_size);
+                }
+            }
+            if gradient > max_gradient {
+                Self::BLOCKED
+            } else {
+                1.0 + gradient
+            }
+        })
+    }
+
+    /// Returns width of map in cells.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns height of map in cells.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Sets cost of cell, does nothing if cell is out of map. Costs must be positive, use
+    /// `CostMap::BLOCKED` for impassable cells.
+    pub fn set_cost(&mut self, x: usize, y: usize, cost: f32) {
+        if let Some(index) = self.index(x, y) {
+            self.costs[index] = cost.max(std::f32::EPSILON);
+        }
+    }
+
+    /// Returns cost of cell, `None` if cell is out of map.
+    pub fn cost(&self, x: usize, y: usize) -> Option<f32> {
+        self.index(x, y).map(|index| self.costs[index])
+    }
+
+    /// Returns true if cell is in map and is not blocked.
+    pub fn is_walkable(&self, x: usize, y: usize) -> bool {
+        self.cost(x, y).map_or(false, |cost| cost.is_finite())
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    fn coords(&self, index: usize) -> (usize, usize) {
+        (index % self.width, index / self.width)
+    }
+
+    /// Calls given function for each walkable neighbour of cell with index of neighbour and
+    /// cost of moving into it. Diagonal moves can't cut corners of blocked cells.
+    fn for_each_neighbour<F: FnMut(usize, f32)>(&self, index: usize, neighbourhood: Neighbourhood, mut func: F) {
+        let (x, y) = self.coords(index);
+        let offsets: &[(i32, i32)] = match neighbourhood {
+            Neighbourhood::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+            Neighbourhood::Eight => &[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)],
+        };
+        for &(dx, dy) in offsets {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if nx < 0 || ny < 0 || !self.is_walkable(nx as usize, ny as usize) {
+                continue;
+            }
+            let diagonal = dx != 0 && dy != 0;
+            if diagonal && (!self.is_walkable(nx as usize, y) || !self.is_walkable(x, ny as usize)) {
+                continue;
+            }
+            let neighbour = ny as usize * self.width + nx as usize;
+            let distance = if diagonal { std::f32::consts::SQRT_2 } else { 1.0 };
+            // Cost of edge is average of costs of both cells.
+            func(neighbour, distance * 0.5 * (self.costs[index] + self.costs[neighbour]));
+        }
+    }
+}
+
+/// Defines to which cells unit can move from a cell.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// Only horizontal and vertical moves.
+    Four,
+    /// Diagonal moves are allowed too.
+    Eight,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct OpenEntry {
+    priority: f32,
+    index: usize,
+}
+
+impl Eq for OpenEntry {}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so binary heap pops entry with lowest priority first.
+        other.priority.partial_cmp(&self.priority).unwrap_or(Ordering::Equal)
+    }
+}
+
+fn heuristic(map: &CostMap, a: usize, b: usize, neighbourhood: Neighbourhood) -> f32 {
+    let (ax, ay) = map.coords(a);
+    let (bx, by) = map.coords(b);
+    let dx = (ax as f32 - bx as f32).abs();
+    let dy = (ay as f32 - by as f32).abs();
+    // Cost of cell is at least cost of empty cell, so heuristic never overestimates for
+    // maps with costs >= 1.0.
+    match neighbourhood {
+        Neighbourhood::Four => dx + dy,
+        Neighbourhood::Eight => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy),
+    }
+}
+
+/// Builds path with A* from cell `from` to cell `to`, `path` is filled with cells from
+/// start to end including both. If `to` can't be reached, path leads to reachable cell
+/// closest to `to` and `PathKind::Partial` is returned. `PathKind::Empty` is returned if
+/// start cell is out of map or blocked.
+pub fn find_path(map: &CostMap, from: (usize, usize), to: (usize, usize), neighbourhood: Neighbourhood,
+                 path: &mut Vec<(usize, usize)>) -> PathKind {
+    path.clear();
+
+    let start = match map.index(from.0, from.1) {
+        Some(start) if map.is_walkable(from.0, from.1) => start,
+        _ => return PathKind::Empty,
+    };
+    let goal = map.index(to.0.min(map.width - 1), to.1.min(map.height - 1)).unwrap_or(start);
+
+    let mut g_scores = vec![std::f32::INFINITY; map.costs.len()];
+    let mut parents = vec![usize::max_value(); map.costs.len()];
+    let mut open = BinaryHeap::new();
+    g_scores[start] = 0.0;
+    open.push(OpenEntry { priority: heuristic(map, start, goal, neighbourhood), index: start });
+
+    let mut closest = start;
+    let mut closest_distance = heuristic(map, start, goal, neighbourhood);
+    let mut reached = false;
+
+    while let Some(OpenEntry { priority, index }) = open.pop() {
+        let g_score = g_scores[index];
+        if priority > g_score + heuristic(map, index, goal, neighbourhood) {
+            // Outdated entry, cell was reached by cheaper path later.
+            continue;
+        }
+        if index == goal {
+            reached = true;
+            break;
+        }
+        let distance = heuristic(map, index, goal, neighbourhood);
+        if distance < closest_distance {
+            closest = index;
+            closest_distance = distance;
+        }
+        map.for_each_neighbour(index, neighbourhood, |neighbour, cost| {
+            let new_score = g_score + cost;
+            if new_score < g_scores[neighbour] {
+                g_scores[neighbour] = new_score;
+                parents[neighbour] = index;
+                open.push(OpenEntry {
+                    priority: new_score + heuristic(map, neighbour, goal, neighbourhood),
+                    index: neighbour,
+                });
+            }
+        });
+    }
+
+    let mut current = if reached { goal } else { closest };
+    loop {
+        path.push(map.coords(current));
+        if current == start {
+            break;
+        }
+        current = parents[current];
+    }
+    path.reverse();
+
+    if reached && goal == map.index(to.0, to.1).unwrap_or(usize::max_value()) {
+        PathKind::Full
+    } else {
+        PathKind::Partial
+    }
+}
+
+/// Field of directions to nearest target from every cell of cost map.
+#[derive(Clone, Debug)]
+pub struct FlowField {
+    width: usize,
+    height: usize,
+    /// Cost of path from cell to nearest target, infinity for unreachable cells.
+    distances: Vec<f32>,
+    /// Index of next cell on the way to target.
+    next: Vec<Option<usize>>,
+}
+
+impl FlowField {
+    /// Builds field for given targets, every cell leads to the target which is cheapest to
+    /// reach. Targets which are out of map or blocked are ignored.
+    pub fn build(map: &CostMap, targets: &[(usize, usize)], neighbourhood: Neighbourhood) -> Self {
+        let mut distances = vec![std::f32::INFINITY; map.costs.len()];
+        let mut open = BinaryHeap::new();
+        for &(x, y) in targets {
+            if let Some(index) = map.index(x, y) {
+                if map.is_walkable(x, y) {
+                    distances[index] = 0.0;
+                    open.push(OpenEntry { priority: 0.0, index });
+                }
+            }
+        }
+
+        // Dijkstra from targets, moves are symmetric so distance from target to cell is the
+        // same as distance from cell to target.
+        while let Some(OpenEntry { priority, index }) = open.pop() {
+            if priority > distances[index] {
+                continue;
+            }
+            map.for_each_neighbour(index, neighbourhood, |neighbour, cost| {
+                let distance = priority + cost;
+                if distance < distances[neighbour] {
+                    distances[neighbour] = distance;
+                    open.push(OpenEntry { priority: distance, index: neighbour });
+                }
+            });
+        }
+
+        let mut next = vec![None; map.costs.len()];
+        for (index, next) in next.iter_mut().enumerate() {
+            if distances[index] == 0.0 || !distances[index].is_finite() {
+                continue;
+            }
+            let mut best = distances[index];
+            map.for_each_neighbour(index, neighbourhood, |neighbour, _| {
+                if distances[neighbour] < best {
+                    best = distances[neighbour];
+                    *next = Some(neighbour);
+                }
+            });
+        }
+
+        Self {
+            width: map.width,
+            height: map.height,
+            distances,
+            next,
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns cost of path from cell to nearest target, `None` if target can't be reached
+    /// from cell.
+    pub fn distance(&self, x: usize, y: usize) -> Option<f32> {
+        self.index(x, y)
+            .map(|index| self.distances[index])
+            .filter(|distance| distance.is_finite())
+    }
+
+    /// Returns next cell on the way to nearest target, `None` if cell is a target or target
+    /// can't be reached from it.
+    pub fn next_cell(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        self.index(x, y)
+            .and_then(|index| self.next[index])
+            .map(|next| (next % self.width, next / self.width))
+    }
+
+    /// Returns normalized direction of movement from cell, x and y axes of direction are
+    /// axes of grid.
+    pub fn direction(&self, x: usize, y: usize) -> Option<Vec2> {
+        self.next_cell(x, y)
+            .map(|(nx, ny)| {
+                let dx = nx as f32 - x as f32;
+                let dy = ny as f32 - y as f32;
+                let length = (dx * dx + dy * dy).sqrt();
+                Vec2::new(dx / length, dy / length)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::{
+        grid::{CostMap, FlowField, Neighbourhood, find_path},
+        astar::PathKind,
+    };
+
+    #[test]
+    fn test_grid_path_and_flow_field() {
+        let mut map = CostMap::new(10, 10);
+        // Wall with a gap at the top.
+        for y in 1..10 {
+            map.set_cost(5, y, CostMap::BLOCKED);
+        }
+
+        let mut path = Vec::new();
+        assert_eq!(find_path(&map, (0, 9), (9, 9), Neighbourhood::Four, &mut path), PathKind::Full);
+        assert_eq!(path.first(), Some(&(0, 9)));
+        assert_eq!(path.last(), Some(&(9, 9)));
+        assert!(path.contains(&(5, 0)));
+        // Every step moves by one cell.
+        for pair in path.windows(2) {
+            let dx = (pair[0].0 as i32 - pair[1].0 as i32).abs();
+            let dy = (pair[0].1 as i32 - pair[1].1 as i32).abs();
+            assert_eq!(dx + dy, 1);
+        }
+
+        // Close the gap, target is unreachable now.
+        map.set_cost(5, 0, CostMap::BLOCKED);
+        assert_eq!(find_path(&map, (0, 9), (9, 9), Neighbourhood::Eight, &mut path), PathKind::Partial);
+        assert_eq!(path.last().map(|cell| cell.0), Some(4));
+
+        map.set_cost(5, 0, 1.0);
+        let field = FlowField::build(&map, &[(9, 9)], Neighbourhood::Eight);
+        assert_eq!(field.distance(9, 9), Some(0.0));
+        assert!(field.direction(9, 9).is_none());
+        // Cell left of the wall leads up to the gap.
+        let direction = field.direction(4, 9).unwrap();
+        assert!(direction.y < 0.0);
+        // Following the field reaches the target.
+        let mut cell = (0, 9);
+        for _ in 0..100 {
+            match field.next_cell(cell.0, cell.1) {
+                Some(next) => cell = next,
+                None => break,
+            }
+        }
+        assert_eq!(cell, (9, 9));
+    }
+}
@@ -1,12 +1,17 @@
 use std::{
     sync::Mutex, fs::File,
     io::{Write, self},
+    collections::VecDeque,
 };
 
+/// Amount of recent lines kept in memory for diagnostic reports.
+const RECENT_CAPACITY: usize = 256;
+
 lazy_static! {
     static ref LOG_FILE: Mutex<File> = {
          Mutex::new(File::create("rg3d.log").unwrap())
     };
+    static ref RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::with_capacity(RECENT_CAPACITY));
 }
 
 pub struct Log {}
@@ -14,11 +19,40 @@ pub struct Log {}
 impl Log {
     pub fn write(msg: String) {
         let _ = io::stdout().write_all(msg.as_bytes());
-        let _ = LOG_FILE.lock().unwrap().write_all(msg.as_bytes());
+        // Log must keep working after panic of a thread which was writing to it.
+        if let Ok(mut file) = LOG_FILE.lock() {
+            let _ = file.write_all(msg.as_bytes());
+        }
+        if let Ok(mut recent) = RECENT.lock() {
+            for line in msg.lines().filter(|line| !line.is_empty()) {
+                if recent.len() == RECENT_CAPACITY {
+                    recent.pop_front();
+                }
+                recent.push_back(line.to_owned());
+            }
+        }
     }
 
     pub fn writeln(mut msg: String) {
         msg.push('\n');
         Self::write(msg)
     }
-}
\ No newline at end of file
+
+    /// Returns up to `count` last lines of log, oldest first.
+    pub fn recent(count: usize) -> Vec<String> {
+        match RECENT.lock() {
+            Ok(recent) => recent.iter().skip(recent.len().saturating_sub(count)).cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Makes sure that everything written so far is in log file, called before process
+    /// is aborted.
+    pub fn flush() {
+        let _ = io::stdout().flush();
+        if let Ok(mut file) = LOG_FILE.lock() {
+            let _ = file.flush();
+            let _ = file.sync_all();
+        }
+    }
+}
@@ -1,7 +1,16 @@
 pub mod astar;
+pub mod crowd;
+pub mod csg;
+pub mod grid;
 pub mod log;
+pub mod json;
 pub mod navmesh;
+pub mod noise;
 pub mod raw_mesh;
+pub mod random;
+pub mod steering;
+pub mod uvgen;
+pub mod voxel;
 
 use crate::{
     scene::{mesh::Mesh},
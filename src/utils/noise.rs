@@ -0,0 +1,121 @@
+//! Contains gradient (Perlin) noise functions.
+//!
+//! Noise is smooth pseudo-random function, neighbour points have close values, so it is
+//! suitable for natural looking procedural motion (camera shake, flickering lights) and
+//! procedural content (height maps, clouds). Noise is fully defined by seed and uses
+//! integer hashing only, so it gives same results on every platform.
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+fn hash2(x: i32, y: i32, seed: u32) -> u32 {
+    hash((x as u32) ^ hash((y as u32) ^ hash(seed)))
+}
+
+/// Smooth interpolation curve 6t^5 - 15t^4 + 10t^3, its first and second derivatives
+/// are zero at ends, so noise does not have visible grid artifacts.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn gradient_1d(x: i32, seed: u32) -> f32 {
+    hash2(x, 0, seed) as f32 / std::u32::MAX as f32 * 2.0 - 1.0
+}
+
+fn gradient_2d(x: i32, y: i32, seed: u32) -> (f32, f32) {
+    let angle = hash2(x, y, seed) as f32 / std::u32::MAX as f32 * 2.0 * std::f32::consts::PI;
+    (angle.cos(), angle.sin())
+}
+
+/// Returns value of one-dimensional Perlin noise at given point, result is in [-1; 1] range.
+/// Noise is zero at integer points, so sample it at non-integer coordinates or offset
+/// coordinates to avoid regular zeros.
+pub fn perlin_1d(x: f32, seed: u32) -> f32 {
+    let x0 = x.floor();
+    let t = x - x0;
+    let i = x0 as i32;
+
+    let v0 = gradient_1d(i, seed) * t;
+    let v1 = gradient_1d(i.wrapping_add(1), seed) * (t - 1.0);
+
+    // Maximum magnitude of one-dimensional noise is 0.5.
+    (2.0 * (v0 + (v1 - v0) * fade(t))).max(-1.0).min(1.0)
+}
+
+/// Returns value of two-dimensional Perlin noise at given point, result is in [-1; 1] range.
+pub fn perlin_2d(x: f32, y: f32, seed: u32) -> f32 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let tx = x - x0;
+    let ty = y - y0;
+    let ix = x0 as i32;
+    let iy = y0 as i32;
+
+    let dot = |cx: i32, cy: i32, dx: f32, dy: f32| {
+        let (gx, gy) = gradient_2d(ix.wrapping_add(cx), iy.wrapping_add(cy), seed);
+        gx * dx + gy * dy
+    };
+
+    let v00 = dot(0, 0, tx, ty);
+    let v10 = dot(1, 0, tx - 1.0, ty);
+    let v01 = dot(0, 1, tx, ty - 1.0);
+    let v11 = dot(1, 1, tx - 1.0, ty - 1.0);
+
+    let fx = fade(tx);
+    let fy = fade(ty);
+    let a = v00 + (v10 - v00) * fx;
+    let b = v01 + (v11 - v01) * fx;
+
+    // Maximum magnitude of two-dimensional noise is sqrt(0.5).
+    ((a + (b - a) * fy) * std::f32::consts::SQRT_2).max(-1.0).min(1.0)
+}
+
+/// Returns sum of several octaves of two-dimensional noise (fractal Brownian motion), each
+/// next octave has twice higher frequency and `persistence` times lower amplitude. Result
+/// is normalized to [-1; 1] range.
+pub fn fbm_2d(x: f32, y: f32, seed: u32, octaves: usize, persistence: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut total_amplitude = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for octave in 0..octaves {
+        sum += amplitude * perlin_2d(x * frequency, y * frequency, seed.wrapping_add(octave as u32));
+        total_amplitude += amplitude;
+        amplitude *= persistence;
+        frequency *= 2.0;
+    }
+    if total_amplitude > 0.0 {
+        sum / total_amplitude
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::noise::{perlin_1d, perlin_2d};
+
+    #[test]
+    fn perlin_noise_is_bounded_and_continuous() {
+        let mut previous = perlin_1d(0.0, 7);
+        for i in 1..10_000 {
+            let x = i as f32 * 0.01;
+            let value = perlin_1d(x, 7);
+            assert!(value >= -1.0 && value <= 1.0);
+            assert!((value - previous).abs() < 0.1);
+            previous = value;
+
+            let value = perlin_2d(x, x * 0.37, 7);
+            assert!(value >= -1.0 && value <= 1.0);
+        }
+
+        assert_eq!(perlin_2d(1.5, 2.5, 3).to_bits(), perlin_2d(1.5, 2.5, 3).to_bits());
+        assert_ne!(perlin_1d(0.5, 1).to_bits(), perlin_1d(0.5, 2).to_bits());
+    }
+}
@@ -0,0 +1,194 @@
+//! Contains portable seedable pseudo-random number generator.
+//!
+//! Generator uses PCG32 algorithm which operates on integers only, so for the same seed
+//! it produces exactly the same sequence of numbers on every platform. This is required
+//! for lockstep multiplayer and replays where each peer must get identical results.
+//!
+//! Engine has one global generator, it can be re-seeded at any time by `random::seed`. Each
+//! particle system has its own generator which is seeded from the global one on creation
+//! (unless explicit seed was specified), so re-seeding global generator before creating
+//! a scene makes whole scene reproducible. Use separate generators for procedural content
+//! to make it independent of the amount of particle systems on scene.
+
+use std::sync::{Mutex, MutexGuard};
+use rand::{RngCore, Error};
+use crate::core::{
+    numeric_range::NumericRange,
+    visitor::{Visit, VisitResult, Visitor},
+};
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+const DEFAULT_STREAM: u64 = 1_442_695_040_888_963_407;
+const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+lazy_static! {
+    static ref GLOBAL_GENERATOR: Mutex<RandomGenerator> = {
+        Mutex::new(RandomGenerator::new(DEFAULT_SEED))
+    };
+}
+
+/// Seedable pseudo-random number generator with fully defined output.
+///
+/// Implements `rand::RngCore` so every method of `rand::Rng` is available as well, but keep
+/// in mind that methods of `rand::Rng` for floating point numbers are not guaranteed to be
+/// portable, use methods of generator itself when determinism is important.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RandomGenerator {
+    state: u64,
+    increment: u64,
+}
+
+impl Default for RandomGenerator {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl RandomGenerator {
+    /// Creates new generator with given seed.
+    pub fn new(seed: u64) -> Self {
+        let mut generator = Self {
+            state: 0,
+            increment: DEFAULT_STREAM,
+        };
+        generator.reseed(seed);
+        generator
+    }
+
+    /// Resets state of generator, so it will produce same sequence as newly created
+    /// generator with same seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.state = 0;
+        self.increment = DEFAULT_STREAM;
+        self.step();
+        self.state = self.state.wrapping_add(seed);
+        self.step();
+    }
+
+    fn step(&mut self) {
+        self.state = self.state
+            .wrapping_mul(MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+
+    /// Returns next random 32-bit number.
+    pub fn gen_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.step();
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+
+    /// Returns next random 64-bit number.
+    pub fn gen_u64(&mut self) -> u64 {
+        let lo = u64::from(self.gen_u32());
+        let hi = u64::from(self.gen_u32());
+        (hi << 32) | lo
+    }
+
+    /// Returns random number in [0; 1) range.
+    pub fn unit(&mut self) -> f32 {
+        // Take 24 bits which fits exactly into f32 mantissa.
+        (self.gen_u32() >> 8) as f32 * (1.0 / 16_777_216.0)
+    }
+
+    /// Returns random number in [min; max) range. Unlike `rand::Rng::gen_range` it does not
+    /// panic if range is empty, in this case `min` is returned.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        if max > min {
+            min + (max - min) * self.unit()
+        } else {
+            min
+        }
+    }
+
+    /// Returns random number from given numeric range.
+    pub fn numeric_range(&mut self, range: &NumericRange<f32>) -> f32 {
+        self.range(range.min, range.max)
+    }
+
+    /// Returns random integer in [min; max) range. Returns `min` if range is empty.
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        if max > min {
+            let span = (i64::from(max) - i64::from(min)) as u64;
+            (i64::from(min) + (u64::from(self.gen_u32()) % span) as i64) as i32
+        } else {
+            min
+        }
+    }
+
+    /// Returns internal state of generator, it can be used for desync detection.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+}
+
+impl RngCore for RandomGenerator {
+    fn next_u32(&mut self) -> u32 {
+        self.gen_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.gen_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.gen_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl Visit for RandomGenerator {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        visitor.enter_region(name)?;
+
+        self.state.visit("State", visitor)?;
+        self.increment.visit("Increment", visitor)?;
+
+        visitor.leave_region()
+    }
+}
+
+/// Returns locked global generator. Do not hold the guard for long time, it is shared
+/// between all threads.
+pub fn global() -> MutexGuard<'static, RandomGenerator> {
+    GLOBAL_GENERATOR.lock().unwrap()
+}
+
+/// Re-seeds global generator. Every peer of lockstep game must call this with same seed
+/// before first update.
+pub fn seed(seed: u64) {
+    global().reseed(seed)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::random::RandomGenerator;
+
+    #[test]
+    fn random_generator_is_reproducible() {
+        let mut a = RandomGenerator::new(42);
+        let mut b = RandomGenerator::new(42);
+        for _ in 0..1000 {
+            assert_eq!(a.gen_u32(), b.gen_u32());
+        }
+
+        b.reseed(42);
+        let mut c = RandomGenerator::new(42);
+        for _ in 0..1000 {
+            let value = b.range(-2.0, 3.0);
+            assert!(value >= -2.0 && value < 3.0);
+            assert_eq!(value.to_bits(), c.range(-2.0, 3.0).to_bits());
+        }
+
+        assert_ne!(RandomGenerator::new(1).gen_u32(), RandomGenerator::new(2).gen_u32());
+    }
+}
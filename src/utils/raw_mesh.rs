@@ -1,8 +1,17 @@
+//! Raw mesh is a simple indexed mesh with arbitrary vertex type. Builder removes exact
+//! duplicates of vertices, raw mesh itself has a set of post-processing routines which
+//! improve mesh for rendering: epsilon welding of vertices, triangle reordering for
+//! better usage of post-transform vertex cache and for less overdraw, vertex reordering
+//! for better memory access pattern.
+
 use std::{
-    collections::HashSet,
+    collections::{HashSet, HashMap},
     hash::{Hash, Hasher},
 };
-use crate::core::math::TriangleDefinition;
+use crate::core::math::{
+    TriangleDefinition,
+    vec3::Vec3,
+};
 
 #[derive(Copy, Clone)]
 struct IndexedStorage<T> {
@@ -44,6 +53,359 @@ pub struct RawMesh<T> {
     pub triangles: Vec<TriangleDefinition>,
 }
 
+/// Vertex which can be used in post-processing routines that need to know spatial
+/// layout of mesh.
+pub trait RawVertex {
+    fn position(&self) -> Vec3;
+
+    /// Returns true if vertices are equal within given tolerance, so they can be merged
+    /// into one.
+    fn is_approx_eq(&self, other: &Self, epsilon: f32) -> bool;
+}
+
+impl RawVertex for Vec3 {
+    fn position(&self) -> Vec3 {
+        *self
+    }
+
+    fn is_approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon &&
+            (self.y - other.y).abs() <= epsilon &&
+            (self.z - other.z).abs() <= epsilon
+    }
+}
+
+/// Size of simulated post-transform vertex cache. Modern GPUs have no fixed size FIFO cache,
+/// but 32 entries is good approximation which works well on most of hardware.
+const CACHE_SIZE: usize = 32;
+
+/// Tom Forsyth's vertex score function, see "Linear-Speed Vertex Cache Optimisation".
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: u32) -> f32 {
+    const CACHE_DECAY_POWER: f32 = 1.5;
+    const LAST_TRIANGLE_SCORE: f32 = 0.75;
+    const VALENCE_BOOST_SCALE: f32 = 2.0;
+    const VALENCE_BOOST_POWER: f32 = 0.5;
+
+    if remaining_triangles == 0 {
+        // Vertex is not used by any triangle anymore.
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // Vertices of last added triangle get fixed score, otherwise algorithm will
+        // prefer to add triangles which share edge with last triangle which is bad for
+        // strips-like order.
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let k = 1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32;
+            k.powf(CACHE_DECAY_POWER)
+        }
+        None => 0.0,
+    };
+
+    // Boost vertices with few remaining triangles so they will be removed from
+    // "to do" list faster.
+    cache_score + VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+fn triangle_area_normal<T: RawVertex>(vertices: &[T], triangle: &TriangleDefinition) -> (Vec3, Vec3) {
+    let a = vertices[triangle.0[0] as usize].position();
+    let b = vertices[triangle.0[1] as usize].position();
+    let c = vertices[triangle.0[2] as usize].position();
+    let centroid = (a + b + c).scale(1.0 / 3.0);
+    // Length of cross product is twice area of triangle, this is fine for weighting.
+    (centroid, (b - a).cross(&(c - a)))
+}
+
+impl<T> RawMesh<T> {
+    /// Reorders triangles so vertices will be reused in post-transform vertex cache as
+    /// much as possible. Uses Tom Forsyth's algorithm, vertices are not changed.
+    pub fn optimize_vertex_cache(&mut self) {
+        let vertex_count = self.vertices.len();
+        let triangle_count = self.triangles.len();
+        if triangle_count == 0 {
+            return;
+        }
+
+        let mut remaining = vec![0u32; vertex_count];
+        for triangle in self.triangles.iter() {
+            for &index in triangle.0.iter() {
+                remaining[index as usize] += 1;
+            }
+        }
+
+        // Triangles adjacent to each vertex in compact form: triangles of vertex `i` are
+        // stored in `adjacency[offsets[i]..offsets[i + 1]]`.
+        let mut offsets = Vec::with_capacity(vertex_count + 1);
+        let mut total = 0;
+        for &count in remaining.iter() {
+            offsets.push(total);
+            total += count as usize;
+        }
+        offsets.push(total);
+        let mut adjacency = vec![0u32; total];
+        let mut fill = offsets.clone();
+        for (n, triangle) in self.triangles.iter().enumerate() {
+            for &index in triangle.0.iter() {
+                adjacency[fill[index as usize]] = n as u32;
+                fill[index as usize] += 1;
+            }
+        }
+
+        let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+        let mut vertex_scores = remaining.iter()
+            .map(|&count| vertex_score(None, count))
+            .collect::<Vec<_>>();
+        let mut triangle_scores = self.triangles.iter()
+            .map(|triangle| triangle.0.iter().map(|&i| vertex_scores[i as usize]).sum::<f32>())
+            .collect::<Vec<_>>();
+        let mut added = vec![false; triangle_count];
+
+        let mut output = Vec::with_capacity(triangle_count);
+        let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        let mut new_cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+
+        let mut best = (0..triangle_count)
+            .max_by(|&a, &b| triangle_scores[a].partial_cmp(&triangle_scores[b]).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or(0);
+        // Fallback position for cases when there is no triangles adjacent to vertices in cache.
+        let mut cursor = 0;
+
+        loop {
+            let triangle = self.triangles[best].0;
+            added[best] = true;
+            output.push(TriangleDefinition(triangle));
+
+            for &index in triangle.iter() {
+                remaining[index as usize] -= 1;
+            }
+
+            new_cache.clear();
+            new_cache.extend_from_slice(&triangle);
+            new_cache.extend(cache.iter().filter(|index| !triangle.contains(index)));
+
+            for (position, &index) in new_cache.iter().enumerate() {
+                let index = index as usize;
+                cache_position[index] = if position < CACHE_SIZE { Some(position) } else { None };
+                vertex_scores[index] = vertex_score(cache_position[index], remaining[index]);
+            }
+
+            // Update scores of triangles affected by changes in cache and find best one.
+            let mut best_score = -1.0;
+            let mut found = false;
+            for &index in new_cache.iter() {
+                let index = index as usize;
+                for &n in adjacency[offsets[index]..offsets[index + 1]].iter() {
+                    let n = n as usize;
+                    if !added[n] {
+                        let score = self.triangles[n].0.iter().map(|&i| vertex_scores[i as usize]).sum::<f32>();
+                        triangle_scores[n] = score;
+                        if score > best_score {
+                            best_score = score;
+                            best = n;
+                            found = true;
+                        }
+                    }
+                }
+            }
+
+            new_cache.truncate(CACHE_SIZE);
+            std::mem::swap(&mut cache, &mut new_cache);
+
+            if !found {
+                while cursor < triangle_count && added[cursor] {
+                    cursor += 1;
+                }
+                if cursor == triangle_count {
+                    break;
+                }
+                best = cursor;
+            }
+        }
+
+        self.triangles = output;
+    }
+
+    /// Reorders vertices in order of their first use by triangles, so GPU will fetch
+    /// vertices almost sequentially. Should be called after triangle reordering. Vertices
+    /// which are not used by any triangle are removed.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![u32::max_value(); self.vertices.len()];
+        let mut order = Vec::with_capacity(self.vertices.len());
+        for triangle in self.triangles.iter_mut() {
+            for index in triangle.0.iter_mut() {
+                let new_index = &mut remap[*index as usize];
+                if *new_index == u32::max_value() {
+                    *new_index = order.len() as u32;
+                    order.push(*index);
+                }
+                *index = *new_index;
+            }
+        }
+
+        let mut vertices = std::mem::replace(&mut self.vertices, Vec::new())
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        self.vertices = order.into_iter()
+            .filter_map(|index| vertices[index as usize].take())
+            .collect();
+    }
+}
+
+impl<T> RawMesh<T> where T: RawVertex {
+    /// Merges vertices which are equal within given tolerance, this removes duplicates
+    /// which builder can't find because of tiny differences in floating point values.
+    /// Triangles which become degenerated after welding are removed, as well as vertices
+    /// which are not used anymore.
+    pub fn weld_vertices(&mut self, epsilon: f32) {
+        let epsilon = epsilon.max(0.0);
+        // Vertices are put in uniform grid, so we have to check only neighbour cells
+        // to find close vertices.
+        let cell_size = epsilon.max(std::f32::EPSILON) * 2.0;
+        let cell = |p: Vec3| -> (i32, i32, i32) {
+            ((p.x / cell_size).floor() as i32, (p.y / cell_size).floor() as i32, (p.z / cell_size).floor() as i32)
+        };
+
+        let mut grid: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let (x, y, z) = cell(vertex.position());
+            let mut existing = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = grid.get(&(x + dx, y + dy, z + dz)) {
+                            for &candidate in candidates.iter() {
+                                if self.vertices[candidate as usize].is_approx_eq(vertex, epsilon) {
+                                    existing = Some(candidate);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            match existing {
+                Some(existing) => remap.push(existing),
+                None => {
+                    grid.entry((x, y, z)).or_insert_with(Vec::new).push(i as u32);
+                    remap.push(i as u32);
+                }
+            }
+        }
+
+        for triangle in self.triangles.iter_mut() {
+            for index in triangle.0.iter_mut() {
+                *index = remap[*index as usize];
+            }
+        }
+        self.triangles.retain(|triangle| {
+            let [a, b, c] = triangle.0;
+            a != b && b != c && c != a
+        });
+
+        self.optimize_vertex_fetch();
+    }
+
+    /// Reorders triangles to reduce overdraw, vertex cache efficiency is kept almost the
+    /// same. Should be called after [`optimize_vertex_cache`](Self::optimize_vertex_cache).
+    ///
+    /// Triangles are split into clusters, and clusters are sorted so ones that face outwards
+    /// of mesh will be drawn first, this way they will occlude rest of mesh in most cases.
+    /// `threshold` defines how much vertex cache efficiency can be sacrificed, 1.05 means
+    /// that cache miss ratio can be 5% worse than before. Larger values produce more clusters.
+    pub fn optimize_overdraw(&mut self, threshold: f32) {
+        let triangle_count = self.triangles.len();
+        if triangle_count < 2 {
+            return;
+        }
+
+        // Simulate FIFO cache to find cache miss ratio of each triangle.
+        let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE);
+        let mut misses = Vec::with_capacity(triangle_count);
+        for triangle in self.triangles.iter() {
+            let mut count = 0;
+            for &index in triangle.0.iter() {
+                if !cache.contains(&index) {
+                    if cache.len() == CACHE_SIZE {
+                        cache.remove(0);
+                    }
+                    cache.push(index);
+                    count += 1;
+                }
+            }
+            misses.push(count);
+        }
+        let total_miss_ratio = misses.iter().sum::<u32>() as f32 / triangle_count as f32;
+
+        // Split triangles into clusters. New cluster starts when current cluster has enough
+        // triangles and its cache miss ratio is good enough, so reordering of clusters will
+        // not affect cache efficiency much.
+        const MIN_CLUSTER_SIZE: usize = 16;
+        let mut clusters = Vec::new();
+        let mut start = 0;
+        let mut cluster_misses = 0;
+        for (n, &count) in misses.iter().enumerate() {
+            cluster_misses += count;
+            let size = n + 1 - start;
+            if size >= MIN_CLUSTER_SIZE && cluster_misses as f32 / size as f32 <= total_miss_ratio * threshold {
+                clusters.push(start..n + 1);
+                start = n + 1;
+                cluster_misses = 0;
+            }
+        }
+        if start < triangle_count {
+            clusters.push(start..triangle_count);
+        }
+
+        if clusters.len() < 2 {
+            return;
+        }
+
+        let mut mesh_centroid = Vec3::ZERO;
+        let mut total_area = 0.0;
+        for triangle in self.triangles.iter() {
+            let (centroid, normal) = triangle_area_normal(&self.vertices, triangle);
+            let area = normal.len();
+            mesh_centroid += centroid.scale(area);
+            total_area += area;
+        }
+        if total_area > 0.0 {
+            mesh_centroid = mesh_centroid.scale(1.0 / total_area);
+        }
+
+        let mut sort_keys = clusters.iter()
+            .map(|cluster| {
+                let mut centroid = Vec3::ZERO;
+                let mut normal = Vec3::ZERO;
+                let mut area = 0.0;
+                for triangle in self.triangles[cluster.clone()].iter() {
+                    let (triangle_centroid, triangle_normal) = triangle_area_normal(&self.vertices, triangle);
+                    let triangle_area = triangle_normal.len();
+                    centroid += triangle_centroid.scale(triangle_area);
+                    normal += triangle_normal;
+                    area += triangle_area;
+                }
+                if area > 0.0 {
+                    centroid = centroid.scale(1.0 / area);
+                }
+                let normal = normal.normalized().unwrap_or(Vec3::ZERO);
+                (cluster.clone(), (centroid - mesh_centroid).dot(&normal))
+            })
+            .collect::<Vec<_>>();
+
+        // Clusters that face outwards of mesh go first.
+        sort_keys.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut triangles = Vec::with_capacity(triangle_count);
+        for (cluster, _) in sort_keys {
+            triangles.extend(self.triangles[cluster].iter().map(|triangle| TriangleDefinition(triangle.0)));
+        }
+        self.triangles = triangles;
+    }
+}
+
 impl<T> RawMeshBuilder<T> where T: Hash + PartialEq {
     /// Creates new builder with given start values of capacity for internal
     /// buffers. These values doesn't need to be precise.
@@ -0,0 +1,365 @@
+//! Steering behaviours for AI agents.
+//!
+//! Path finding gives a list of points, but moving a character from point to point with
+//! constant speed looks robotic: it turns instantly and stops dead at the end. Steering
+//! behaviours (by Craig Reynolds) produce forces which are applied to velocity of agent,
+//! so agent accelerates, turns and brakes smoothly:
+//!
+//! - `seek` and `flee` - move towards or away from a point at full speed;
+//! - `arrive` - like seek, but slows down near the target and stops at it;
+//! - `follow_path` - seeks waypoints one by one and arrives at the last one;
+//! - `separation`, `alignment` and `cohesion` - flocking, agents keep distance from each
+//! other, move in the same direction and keep together.
+//!
+//! Behaviours are plain functions of positions and velocities, so they can be used with any
+//! movement code. `SteeringAgent` keeps velocity of a node, sums forces and moves the node:
+//!
+//! ```no_run
+//! use rg3d::utils::steering::{SteeringAgent, PathFollower};
+//! use rg3d::scene::graph::Graph;
+//!
+//! fn update(graph: &mut Graph, agent: &mut SteeringAgent, path: &mut PathFollower, dt: f32) {
+//!     let force = agent.follow_path(graph, path, 1.0, 2.0);
+//!     agent.apply_force(force, dt);
+//!     agent.move_node(graph, dt);
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+use crate::{
+    core::{
+        math::{
+            vec3::Vec3,
+            quat::Quat,
+        },
+        pool::Handle,
+    },
+    scene::{
+        graph::Graph,
+        node::Node,
+    },
+};
+
+/// Truncates vector to given length.
+fn truncate(v: Vec3, max_length: f32) -> Vec3 {
+    let length = v.len();
+    if length > max_length && length > 0.0 {
+        v.scale(max_length / length)
+    } else {
+        v
+    }
+}
+
+/// Returns force which turns velocity towards target at full speed.
+pub fn seek(position: Vec3, velocity: Vec3, target: Vec3, max_speed: f32) -> Vec3 {
+    match (target - position).normalized() {
+        Some(direction) => direction.scale(max_speed) - velocity,
+        None => velocity.scale(-1.0),
+    }
+}
+
+/// Returns force which turns velocity away from threat at full speed, threats further than
+/// `panic_distance` are ignored.
+pub fn flee(position: Vec3, velocity: Vec3, threat: Vec3, max_speed: f32, panic_distance: f32) -> Vec3 {
+    if position.sqr_distance(&threat) > panic_distance * panic_distance {
+        return Vec3::ZERO;
+    }
+    match (position - threat).normalized() {
+        Some(direction) => direction.scale(max_speed) - velocity,
+        None => Vec3::ZERO,
+    }
+}
+
+/// Returns force which moves towards target and slows down linearly inside of
+/// `slowing_radius`, so agent stops at target.
+pub fn arrive(position: Vec3, velocity: Vec3, target: Vec3, max_speed: f32, slowing_radius: f32) -> Vec3 {
+    let offset = target - position;
+    let distance = offset.len();
+    if distance <= std::f32::EPSILON {
+        return velocity.scale(-1.0);
+    }
+    let speed = if distance < slowing_radius {
+        max_speed * distance / slowing_radius
+    } else {
+        max_speed
+    };
+    offset.scale(speed / distance) - velocity
+}
+
+/// Returns force which pushes agent away from neighbours closer than `radius`, closer
+/// neighbours push stronger.
+pub fn separation(position: Vec3, neighbours: &[Vec3], radius: f32) -> Vec3 {
+    let mut force = Vec3::ZERO;
+    for &neighbour in neighbours {
+        let offset = position - neighbour;
+        let distance = offset.len();
+        if distance > 0.0 && distance < radius {
+            // Direction divided by distance.
+            force += offset.scale(1.0 / (distance * distance));
+        }
+    }
+    force
+}
+
+/// Returns force which turns velocity to average velocity of neighbours.
+pub fn alignment(velocity: Vec3, neighbour_velocities: &[Vec3]) -> Vec3 {
+    if neighbour_velocities.is_empty() {
+        return Vec3::ZERO;
+    }
+    let mut average = Vec3::ZERO;
+    for &neighbour_velocity in neighbour_velocities {
+        average += neighbour_velocity;
+    }
+    average.scale(1.0 / neighbour_velocities.len() as f32) - velocity
+}
+
+/// Returns force which moves agent towards center of neighbours.
+pub fn cohesion(position: Vec3, velocity: Vec3, neighbours: &[Vec3], max_speed: f32) -> Vec3 {
+    if neighbours.is_empty() {
+        return Vec3::ZERO;
+    }
+    let mut center = Vec3::ZERO;
+    for &neighbour in neighbours {
+        center += neighbour;
+    }
+    seek(position, velocity, center.scale(1.0 / neighbours.len() as f32), max_speed)
+}
+
+/// Weights of flocking behaviours, see `SteeringAgent::flock`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlockWeights {
+    /// Weight of separation force.
+    pub separation: f32,
+    /// Weight of alignment force.
+    pub alignment: f32,
+    /// Weight of cohesion force.
+    pub cohesion: f32,
+    /// Only agents closer than this distance are neighbours.
+    pub neighbour_radius: f32,
+    /// Agents try to stay at least at this distance from each other.
+    pub separation_radius: f32,
+}
+
+impl Default for FlockWeights {
+    fn default() -> Self {
+        Self {
+            separation: 1.5,
+            alignment: 1.0,
+            cohesion: 1.0,
+            neighbour_radius: 5.0,
+            separation_radius: 1.5,
+        }
+    }
+}
+
+/// Path with index of current waypoint, see `SteeringAgent::follow_path`.
+#[derive(Clone, Debug, Default)]
+pub struct PathFollower {
+    points: Vec<Vec3>,
+    current: usize,
+}
+
+impl PathFollower {
+    /// Creates follower of path which goes through given points in order.
+    pub fn new(points: Vec<Vec3>) -> Self {
+        Self {
+            points,
+            current: 0,
+        }
+    }
+
+    /// Replaces path and starts it from first point.
+    pub fn set_points(&mut self, points: Vec<Vec3>) {
+        self.points = points;
+        self.current = 0;
+    }
+
+    /// Returns points of path.
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// Returns index of waypoint to which agent currently moves.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Returns true if current waypoint is the last one.
+    pub fn is_last(&self) -> bool {
+        self.current + 1 >= self.points.len()
+    }
+
+    /// Returns force which moves agent along the path. Agent switches to next waypoint when
+    /// it is closer than `waypoint_radius` to current one and arrives at the last point.
+    pub fn steer(&mut self, position: Vec3, velocity: Vec3, max_speed: f32, waypoint_radius: f32, slowing_radius: f32) -> Vec3 {
+        if self.points.is_empty() {
+            return arrive(position, velocity, position, max_speed, slowing_radius);
+        }
+        while !self.is_last() && position.sqr_distance(&self.points[self.current]) < waypoint_radius * waypoint_radius {
+            self.current += 1;
+        }
+        let target = self.points[self.current];
+        if self.is_last() {
+            arrive(position, velocity, target, max_speed, slowing_radius)
+        } else {
+            seek(position, velocity, target, max_speed)
+        }
+    }
+}
+
+/// Velocity of a node driven by steering forces.
+#[derive(Clone, Debug)]
+pub struct SteeringAgent {
+    node: Handle<Node>,
+    velocity: Vec3,
+    max_speed: f32,
+    max_force: f32,
+    mass: f32,
+    face_movement: bool,
+}
+
+impl SteeringAgent {
+    /// Creates agent which moves given node.
+    pub fn new(node: Handle<Node>, max_speed: f32, max_force: f32) -> Self {
+        Self {
+            node,
+            velocity: Vec3::ZERO,
+            max_speed,
+            max_force,
+            mass: 1.0,
+            face_movement: true,
+        }
+    }
+
+    /// Sets mass of agent, heavier agents respond to forces slower.
+    pub fn with_mass(mut self, mass: f32) -> Self {
+        self.mass = mass.max(std::f32::EPSILON);
+        self
+    }
+
+    /// Sets whether agent rotates node around Y axis to face direction of movement.
+    pub fn with_face_movement(mut self, face_movement: bool) -> Self {
+        self.face_movement = face_movement;
+        self
+    }
+
+    /// Returns handle of node moved by agent.
+    pub fn node(&self) -> Handle<Node> {
+        self.node
+    }
+
+    /// Returns current velocity of agent.
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    /// Sets velocity, it is truncated to max speed.
+    pub fn set_velocity(&mut self, velocity: Vec3) {
+        self.velocity = truncate(velocity, self.max_speed);
+    }
+
+    /// Returns max speed of agent.
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    /// Returns global position of node of agent.
+    pub fn position(&self, graph: &Graph) -> Vec3 {
+        if graph.is_valid_handle(self.node) {
+            graph[self.node].global_position()
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    /// Returns force of `seek` for this agent.
+    pub fn seek(&self, graph: &Graph, target: Vec3) -> Vec3 {
+        seek(self.position(graph), self.velocity, target, self.max_speed)
+    }
+
+    /// Returns force of `flee` for this agent.
+    pub fn flee(&self, graph: &Graph, threat: Vec3, panic_distance: f32) -> Vec3 {
+        flee(self.position(graph), self.velocity, threat, self.max_speed, panic_distance)
+    }
+
+    /// Returns force of `arrive` for this agent.
+    pub fn arrive(&self, graph: &Graph, target: Vec3, slowing_radius: f32) -> Vec3 {
+        arrive(self.position(graph), self.velocity, target, self.max_speed, slowing_radius)
+    }
+
+    /// Returns force which moves agent along path, see `PathFollower::steer`.
+    pub fn follow_path(&self, graph: &Graph, path: &mut PathFollower, waypoint_radius: f32, slowing_radius: f32) -> Vec3 {
+        path.steer(self.position(graph), self.velocity, self.max_speed, waypoint_radius, slowing_radius)
+    }
+
+    /// Returns weighted sum of flocking forces, `flock` contains every agent of flock and
+    /// may contain this agent too.
+    pub fn flock(&self, graph: &Graph, flock: &[SteeringAgent], weights: &FlockWeights) -> Vec3 {
+        let position = self.position(graph);
+        let mut positions = Vec::new();
+        let mut velocities = Vec::new();
+        for other in flock.iter().filter(|other| other.node != self.node) {
+            let other_position = other.position(graph);
+            if position.sqr_distance(&other_position) < weights.neighbour_radius * weights.neighbour_radius {
+                positions.push(other_position);
+                velocities.push(other.velocity);
+            }
+        }
+        separation(position, &positions, weights.separation_radius).scale(weights.separation)
+            + alignment(self.velocity, &velocities).scale(weights.alignment)
+            + cohesion(position, self.velocity, &positions, self.max_speed).scale(weights.cohesion)
+    }
+
+    /// Applies force to velocity, force is truncated to max force and velocity to max speed.
+    pub fn apply_force(&mut self, force: Vec3, dt: f32) {
+        let acceleration = truncate(force, self.max_force).scale(1.0 / self.mass);
+        self.velocity = truncate(self.velocity + acceleration.scale(dt), self.max_speed);
+    }
+
+    /// Moves node by velocity and turns it to direction of movement if enabled. Node is moved
+    /// in its local space, so agents should not be attached to moving parents.
+    pub fn move_node(&self, graph: &mut Graph, dt: f32) {
+        if !graph.is_valid_handle(self.node) {
+            return;
+        }
+        let transform = graph[self.node].local_transform_mut();
+        transform.offset(self.velocity.scale(dt));
+        if self.face_movement && (self.velocity.x != 0.0 || self.velocity.z != 0.0) {
+            let yaw = self.velocity.x.atan2(self.velocity.z);
+            transform.set_rotation(Quat::from_axis_angle(Vec3::UP, yaw));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::math::vec3::Vec3,
+        utils::steering::{arrive, separation, PathFollower},
+    };
+
+    #[test]
+    fn test_steering() {
+        // Far from target arrive works like seek, near target it slows down.
+        let far = arrive(Vec3::ZERO, Vec3::ZERO, Vec3::new(10.0, 0.0, 0.0), 2.0, 1.0);
+        assert!((far.x - 2.0).abs() < 0.001);
+        let near = arrive(Vec3::ZERO, Vec3::ZERO, Vec3::new(0.5, 0.0, 0.0), 2.0, 1.0);
+        assert!((near.x - 1.0).abs() < 0.001);
+
+        let push = separation(Vec3::ZERO, &[Vec3::new(1.0, 0.0, 0.0), Vec3::new(10.0, 0.0, 0.0)], 2.0);
+        assert!(push.x < 0.0);
+
+        // Simple integration along path reaches its end.
+        let mut path = PathFollower::new(vec![Vec3::new(5.0, 0.0, 0.0), Vec3::new(5.0, 0.0, 5.0)]);
+        let mut position = Vec3::ZERO;
+        let mut velocity = Vec3::ZERO;
+        for _ in 0..1000 {
+            let force = path.steer(position, velocity, 2.0, 0.5, 1.0);
+            velocity += force.scale(0.05);
+            position += velocity.scale(0.05);
+        }
+        assert!(path.is_last());
+        assert!(position.distance(&Vec3::new(5.0, 0.0, 5.0)) < 0.1);
+    }
+}
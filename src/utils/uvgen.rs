@@ -0,0 +1,213 @@
+//! UV generator creates second texture coordinates of surfaces, which are used for lightmaps.
+//!
+//! Generator splits mesh into charts - connected groups of triangles which face roughly the
+//! same direction, each chart is projected on plane, then all charts are packed into unit
+//! square. Vertices on borders of charts are duplicated, so generator changes vertex and
+//! index buffers of surface, but not visual appearance.
+
+use std::collections::HashMap;
+use crate::{
+    renderer::surface::SurfaceSharedData,
+    scene::mesh::Mesh,
+    core::math::{
+        vec2::Vec2,
+        vec3::Vec3,
+    },
+};
+
+type PositionKey = (u32, u32, u32);
+
+fn position_key(p: Vec3) -> PositionKey {
+    (p.x.to_bits(), p.y.to_bits(), p.z.to_bits())
+}
+
+fn edge_key(a: PositionKey, b: PositionKey) -> (PositionKey, PositionKey) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Returns index of projection plane for given normal. There are six planes - two per
+/// each axis.
+fn projection_class(normal: Vec3) -> usize {
+    let abs = [normal.x.abs(), normal.y.abs(), normal.z.abs()];
+    let axis = if abs[0] >= abs[1] && abs[0] >= abs[2] {
+        0
+    } else if abs[1] >= abs[2] {
+        1
+    } else {
+        2
+    };
+    let component = [normal.x, normal.y, normal.z][axis];
+    axis * 2 + if component >= 0.0 { 0 } else { 1 }
+}
+
+fn project(p: Vec3, class: usize) -> Vec2 {
+    match class / 2 {
+        0 => Vec2::new(p.z, p.y),
+        1 => Vec2::new(p.x, p.z),
+        _ => Vec2::new(p.x, p.y),
+    }
+}
+
+struct Chart {
+    triangles: Vec<usize>,
+    class: usize,
+    min: Vec2,
+    size: Vec2,
+    offset: Vec2,
+}
+
+/// Generates second texture coordinates for given surface data. `spacing` defines gap between
+/// charts in texture space, it should be large enough to prevent bleeding of lightmap texels
+/// between charts, for example `2.0 / lightmap_size`.
+pub fn generate_uvs(data: &mut SurfaceSharedData, spacing: f32) {
+    let triangle_count = data.triangles.len();
+    if triangle_count == 0 {
+        return;
+    }
+
+    let classes = data.triangles.iter()
+        .map(|triangle| {
+            let a = data.vertices[triangle[0] as usize].position;
+            let b = data.vertices[triangle[1] as usize].position;
+            let c = data.vertices[triangle[2] as usize].position;
+            projection_class((b - a).cross(&(c - a)))
+        })
+        .collect::<Vec<_>>();
+
+    // Triangles are adjacent if they share edge, positions are compared instead of indices,
+    // because vertices can be already split by other attributes.
+    let mut edges: HashMap<(PositionKey, PositionKey), Vec<usize>> = HashMap::new();
+    for (n, triangle) in data.triangles.iter().enumerate() {
+        for k in 0..3 {
+            let a = position_key(data.vertices[triangle[k] as usize].position);
+            let b = position_key(data.vertices[triangle[(k + 1) % 3] as usize].position);
+            edges.entry(edge_key(a, b)).or_insert_with(Vec::new).push(n);
+        }
+    }
+
+    // Segment mesh into charts using flood fill.
+    let mut chart_of = vec![usize::max_value(); triangle_count];
+    let mut charts = Vec::new();
+    let mut stack = Vec::new();
+    for seed in 0..triangle_count {
+        if chart_of[seed] != usize::max_value() {
+            continue;
+        }
+        let chart_index = charts.len();
+        let class = classes[seed];
+        let mut chart = Chart {
+            triangles: Vec::new(),
+            class,
+            min: Vec2::ZERO,
+            size: Vec2::ZERO,
+            offset: Vec2::ZERO,
+        };
+        chart_of[seed] = chart_index;
+        stack.push(seed);
+        while let Some(n) = stack.pop() {
+            chart.triangles.push(n);
+            let triangle = &data.triangles[n];
+            for k in 0..3 {
+                let a = position_key(data.vertices[triangle[k] as usize].position);
+                let b = position_key(data.vertices[triangle[(k + 1) % 3] as usize].position);
+                if let Some(neighbours) = edges.get(&edge_key(a, b)) {
+                    for &neighbour in neighbours.iter() {
+                        if chart_of[neighbour] == usize::max_value() && classes[neighbour] == class {
+                            chart_of[neighbour] = chart_index;
+                            stack.push(neighbour);
+                        }
+                    }
+                }
+            }
+        }
+        charts.push(chart);
+    }
+
+    // Split vertices which are shared between charts - each chart must have its own copy
+    // of vertex with its own texture coordinates.
+    let mut owner = vec![usize::max_value(); data.vertices.len()];
+    for (chart_index, chart) in charts.iter().enumerate() {
+        let mut copies: HashMap<u32, u32> = HashMap::new();
+        for &n in chart.triangles.iter() {
+            for k in 0..3 {
+                let index = data.triangles[n][k];
+                if owner[index as usize] == usize::max_value() {
+                    owner[index as usize] = chart_index;
+                } else if owner[index as usize] != chart_index {
+                    let vertices = &mut data.vertices;
+                    let copy = *copies.entry(index).or_insert_with(|| {
+                        let vertex = vertices[index as usize];
+                        vertices.push(vertex);
+                        (vertices.len() - 1) as u32
+                    });
+                    data.triangles[n].0[k] = copy;
+                }
+            }
+        }
+    }
+
+    // Find bounds of each chart in its projection plane.
+    let mut total_area = 0.0;
+    let mut max_width: f32 = 0.0;
+    for chart in charts.iter_mut() {
+        let mut min = Vec2::new(std::f32::MAX, std::f32::MAX);
+        let mut max = Vec2::new(-std::f32::MAX, -std::f32::MAX);
+        for &n in chart.triangles.iter() {
+            for k in 0..3 {
+                let uv = project(data.vertices[data.triangles[n][k] as usize].position, chart.class);
+                min = Vec2::new(min.x.min(uv.x), min.y.min(uv.y));
+                max = Vec2::new(max.x.max(uv.x), max.y.max(uv.y));
+            }
+        }
+        chart.min = min;
+        chart.size = max - min;
+        total_area += chart.size.x * chart.size.y;
+        max_width = max_width.max(chart.size.x);
+    }
+
+    // Pack charts using simple shelf algorithm: charts are sorted by height and placed in
+    // rows of fixed width.
+    let side = total_area.sqrt().max(max_width).max(std::f32::EPSILON);
+    let padding = spacing * side;
+    let mut order = (0..charts.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| charts[b].size.y.partial_cmp(&charts[a].size.y).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut x = padding;
+    let mut y = padding;
+    let mut row_height = 0.0;
+    let mut width: f32 = 0.0;
+    for &index in order.iter() {
+        let chart = &mut charts[index];
+        if x + chart.size.x + padding > side + 2.0 * padding && x > padding {
+            x = padding;
+            y += row_height + padding;
+            row_height = 0.0;
+        }
+        chart.offset = Vec2::new(x, y);
+        x += chart.size.x + padding;
+        width = width.max(x);
+        row_height = chart.size.y.max(row_height);
+    }
+    let height = y + row_height + padding;
+
+    let scale = 1.0 / width.max(height).max(std::f32::EPSILON);
+    for chart in charts.iter() {
+        for &n in chart.triangles.iter() {
+            for k in 0..3 {
+                let vertex = &mut data.vertices[data.triangles[n][k] as usize];
+                let uv = project(vertex.position, chart.class) - chart.min + chart.offset;
+                vertex.second_tex_coord = Vec2::new(uv.x * scale, uv.y * scale);
+            }
+        }
+    }
+}
+
+/// Generates second texture coordinates for each surface of given mesh. Surface data is
+/// shared between mesh instances, so every instance will get new coordinates too.
+pub fn generate_uvs_mesh(mesh: &Mesh, spacing: f32) {
+    for surface in mesh.surfaces() {
+        let data = surface.get_data();
+        let mut data = data.lock().unwrap();
+        generate_uvs(&mut data, spacing);
+    }
+}
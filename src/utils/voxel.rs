@@ -0,0 +1,272 @@
+//! Voxel chunks and greedy meshing.
+//!
+//! Voxel world is usually split into chunks of fixed size, each chunk is converted into one
+//! mesh. Naive approach (one cube per voxel) produces enormous amount of triangles, most of
+//! which are hidden, so mesher does two things: faces between two solid voxels are not
+//! generated at all, and adjacent coplanar faces of same material are merged into larger
+//! quads ("greedy meshing").
+//!
+//! Each face of each kind of voxel can have its own region in texture atlas. Hardware can't
+//! repeat sub-region of texture, so faces which use sub-region of atlas are never merged, only
+//! faces which use whole texture are merged and texture is repeated over merged quad (texture
+//! must use repeat wrapping mode in this case).
+
+use crate::{
+    renderer::surface::{
+        SurfaceSharedData,
+        TangentMethod,
+        Vertex,
+    },
+    core::{
+        color::Color,
+        math::{
+            Rect,
+            vec2::Vec2,
+            vec3::Vec3,
+            vec4::Vec4,
+            TriangleDefinition,
+        },
+    },
+};
+
+/// Kind of voxel, zero is empty space.
+pub type Voxel = u16;
+
+pub const EMPTY_VOXEL: Voxel = 0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VoxelFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl VoxelFace {
+    fn from_axis(axis: usize, positive: bool) -> Self {
+        match (axis, positive) {
+            (0, true) => VoxelFace::PositiveX,
+            (0, false) => VoxelFace::NegativeX,
+            (1, true) => VoxelFace::PositiveY,
+            (1, false) => VoxelFace::NegativeY,
+            (_, true) => VoxelFace::PositiveZ,
+            (_, false) => VoxelFace::NegativeZ,
+        }
+    }
+}
+
+/// Dense 3D array of voxels.
+#[derive(Clone)]
+pub struct VoxelChunk {
+    size: [usize; 3],
+    voxels: Vec<Voxel>,
+}
+
+fn is_whole_texture(rect: &Rect<f32>) -> bool {
+    rect.x == 0.0 && rect.y == 0.0 && rect.w == 1.0 && rect.h == 1.0
+}
+
+impl VoxelChunk {
+    /// Creates new chunk filled with empty space.
+    pub fn new(size_x: usize, size_y: usize, size_z: usize) -> Self {
+        Self {
+            size: [size_x, size_y, size_z],
+            voxels: vec![EMPTY_VOXEL; size_x * size_y * size_z],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize, usize) {
+        (self.size[0], self.size[1], self.size[2])
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+
+    /// Returns voxel at given position, positions outside of chunk are empty.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Voxel {
+        if x < self.size[0] && y < self.size[1] && z < self.size[2] {
+            self.voxels[self.index(x, y, z)]
+        } else {
+            EMPTY_VOXEL
+        }
+    }
+
+    /// Sets voxel at given position, positions outside of chunk are ignored.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, voxel: Voxel) {
+        if x < self.size[0] && y < self.size[1] && z < self.size[2] {
+            let index = self.index(x, y, z);
+            self.voxels[index] = voxel;
+        }
+    }
+
+    /// Builds mesh for chunk, everything outside chunk is considered empty space.
+    /// See [`build_mesh_with_neighbours`](Self::build_mesh_with_neighbours).
+    pub fn build_mesh<F>(&self, voxel_size: f32, face_region: F) -> SurfaceSharedData
+        where F: Fn(Voxel, VoxelFace) -> Rect<f32> {
+        self.build_mesh_with_neighbours(voxel_size, face_region, |_, _, _| false)
+    }
+
+    /// Builds mesh for chunk. `face_region` must return region of texture (in normalized
+    /// coordinates) for given face of given kind of voxel. `is_solid_outside` must tell
+    /// whether voxel outside of chunk (coordinates are relative to chunk) is solid, this
+    /// is used to remove faces on borders between chunks.
+    ///
+    /// Mesh is in local coordinates of chunk, its origin is at corner of first voxel.
+    pub fn build_mesh_with_neighbours<F, S>(&self, voxel_size: f32, face_region: F, is_solid_outside: S) -> SurfaceSharedData
+        where F: Fn(Voxel, VoxelFace) -> Rect<f32>,
+              S: Fn(i32, i32, i32) -> bool {
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+
+        let is_solid = |p: [i32; 3]| -> bool {
+            if p.iter().zip(self.size.iter()).all(|(&c, &s)| c >= 0 && (c as usize) < s) {
+                self.get(p[0] as usize, p[1] as usize, p[2] as usize) != EMPTY_VOXEL
+            } else {
+                is_solid_outside(p[0], p[1], p[2])
+            }
+        };
+
+        for axis in 0..3 {
+            let u = (axis + 1) % 3;
+            let v = (axis + 2) % 3;
+            let size_u = self.size[u];
+            let size_v = self.size[v];
+
+            let mut mask: Vec<Option<(Voxel, Rect<f32>)>> = vec![None; size_u * size_v];
+
+            for &positive in [true, false].iter() {
+                let face = VoxelFace::from_axis(axis, positive);
+
+                for slice in 0..self.size[axis] {
+                    // Collect visible faces of slice.
+                    for j in 0..size_v {
+                        for i in 0..size_u {
+                            let mut p = [0i32; 3];
+                            p[axis] = slice as i32;
+                            p[u] = i as i32;
+                            p[v] = j as i32;
+                            let voxel = self.get(p[0] as usize, p[1] as usize, p[2] as usize);
+                            let mut neighbour = p;
+                            neighbour[axis] += if positive { 1 } else { -1 };
+                            mask[j * size_u + i] = if voxel != EMPTY_VOXEL && !is_solid(neighbour) {
+                                Some((voxel, face_region(voxel, face)))
+                            } else {
+                                None
+                            };
+                        }
+                    }
+
+                    // Merge faces into quads.
+                    for j in 0..size_v {
+                        let mut i = 0;
+                        while i < size_u {
+                            let (voxel, region) = match mask[j * size_u + i] {
+                                Some(entry) => entry,
+                                None => {
+                                    i += 1;
+                                    continue;
+                                }
+                            };
+
+                            let same = |entry: &Option<(Voxel, Rect<f32>)>| {
+                                match entry {
+                                    Some((other_voxel, other_region)) => *other_voxel == voxel && *other_region == region,
+                                    None => false,
+                                }
+                            };
+
+                            let (width, height) = if is_whole_texture(&region) {
+                                let mut width = 1;
+                                while i + width < size_u && same(&mask[j * size_u + i + width]) {
+                                    width += 1;
+                                }
+                                let mut height = 1;
+                                'grow: while j + height < size_v {
+                                    for k in 0..width {
+                                        if !same(&mask[(j + height) * size_u + i + k]) {
+                                            break 'grow;
+                                        }
+                                    }
+                                    height += 1;
+                                }
+                                (width, height)
+                            } else {
+                                (1, 1)
+                            };
+
+                            for dj in 0..height {
+                                for di in 0..width {
+                                    mask[(j + dj) * size_u + i + di] = None;
+                                }
+                            }
+
+                            let mut origin = [0.0f32; 3];
+                            origin[axis] = (slice + if positive { 1 } else { 0 }) as f32;
+                            origin[u] = i as f32;
+                            origin[v] = j as f32;
+
+                            let mut du = [0.0f32; 3];
+                            du[u] = width as f32;
+                            let mut dv = [0.0f32; 3];
+                            dv[v] = height as f32;
+
+                            let mut normal = [0.0f32; 3];
+                            normal[axis] = if positive { 1.0 } else { -1.0 };
+
+                            let corners = [
+                                origin,
+                                [origin[0] + du[0], origin[1] + du[1], origin[2] + du[2]],
+                                [origin[0] + du[0] + dv[0], origin[1] + du[1] + dv[1], origin[2] + du[2] + dv[2]],
+                                [origin[0] + dv[0], origin[1] + dv[1], origin[2] + dv[2]],
+                            ];
+
+                            // Texture is mapped so its vertical axis goes along world Y axis on
+                            // side faces.
+                            let (s_axis, t_axis) = match axis {
+                                0 => (2, 1),
+                                1 => (0, 2),
+                                _ => (0, 1),
+                            };
+                            let t_max = origin[t_axis] + du[t_axis] + dv[t_axis];
+
+                            let base = vertices.len() as u32;
+                            for corner in corners.iter() {
+                                let s = corner[s_axis] - origin[s_axis];
+                                let t = t_max - corner[t_axis];
+                                vertices.push(Vertex {
+                                    position: Vec3::new(corner[0], corner[1], corner[2]).scale(voxel_size),
+                                    tex_coord: Vec2::new(region.x + region.w * s, region.y + region.h * t),
+                                    normal: Vec3::new(normal[0], normal[1], normal[2]),
+                                    tangent: Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 },
+                                    bone_weights: [0.0, 0.0, 0.0, 0.0],
+                                    bone_indices: [0, 0, 0, 0],
+                                    second_tex_coord: Vec2::ZERO,
+                                    color: Color::WHITE,
+                                });
+                            }
+
+                            // Cross product of u and v axes points along positive direction of
+                            // face axis, so winding must be flipped for negative faces.
+                            if positive {
+                                triangles.push(TriangleDefinition([base, base + 1, base + 2]));
+                                triangles.push(TriangleDefinition([base, base + 2, base + 3]));
+                            } else {
+                                triangles.push(TriangleDefinition([base, base + 2, base + 1]));
+                                triangles.push(TriangleDefinition([base, base + 3, base + 2]));
+                            }
+
+                            i += width;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut data = SurfaceSharedData::new(vertices, triangles);
+        data.calculate_tangents(TangentMethod::Fast);
+        data
+    }
+}